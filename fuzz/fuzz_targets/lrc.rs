@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/player/lrc.rs"]
+mod lrc;
+
+fuzz_target!(|data: &[u8]| {
+    let content = String::from_utf8_lossy(data);
+    let lines = lrc::parse_lrc(&content);
+    assert!(lines.windows(2).all(|w| w[0].time <= w[1].time));
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/webdav/propfind.rs"]
+mod propfind;
+
+fuzz_target!(|data: &[u8]| {
+    let xml = String::from_utf8_lossy(data);
+    for entry in propfind::parse_multistatus(&xml) {
+        assert!(!entry.href.is_empty());
+    }
+});
@@ -0,0 +1,140 @@
+// Tags and duration for AIFF / AIFF-C files. iTunes and most taggers write an
+// "ID3 " chunk, which the id3 crate reads; older tools only write the native
+// NAME / AUTH text chunks, so those are read as a fallback.
+
+use crate::metadata::{id3_synced_lyrics, parse_flag, EmbeddedTags};
+use id3::TagLike;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+// Text chunks are short; anything larger is skipped rather than read
+const MAX_TEXT_CHUNK: u32 = 64 * 1024;
+
+/// `Ok(None)` if the file isn't an AIFF or AIFF-C file.
+pub fn read_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    let mut file = File::open(path)?;
+    let mut form = [0u8; 12];
+    if file.read_exact(&mut form).is_err() || &form[..4] != b"FORM" || !matches!(&form[8..12], b"AIFF" | b"AIFC") {
+        return Ok(None);
+    }
+    let len = file.seek(SeekFrom::End(0))?;
+
+    let mut tags = EmbeddedTags::default();
+    let mut pos = 12u64;
+    while pos + 8 <= len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let size = u32::from_be_bytes(header[4..8].try_into().unwrap());
+
+        match &header[..4] {
+            b"COMM" => {
+                let mut comm = [0u8; 18];
+                if size >= 18 && file.read_exact(&mut comm).is_ok() {
+                    tags.duration = comm_duration(&comm);
+                }
+            }
+            b"NAME" | b"AUTH" if size <= MAX_TEXT_CHUNK => {
+                let mut text = vec![0u8; size as usize];
+                file.read_exact(&mut text)?;
+                let text = String::from_utf8_lossy(&text).trim_end_matches('\0').trim().to_string();
+                if !text.is_empty() {
+                    if &header[..4] == b"NAME" {
+                        tags.title = Some(text);
+                    } else {
+                        tags.artist = Some(text);
+                    }
+                }
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        pos += 8 + size as u64 + (size as u64 & 1);
+    }
+
+    if let Ok(id3) = id3::Tag::read_from_path(path) {
+        tags.title = id3.title().map(str::to_string).or(tags.title);
+        tags.artist = id3.artist().or(id3.album_artist()).map(str::to_string).or(tags.artist);
+        tags.album = id3.album().map(str::to_string);
+        tags.album_artist = id3.album_artist().map(str::to_string);
+        tags.track_number = id3.track();
+        tags.year = id3.year().or_else(|| id3.date_recorded().map(|date| date.year));
+        tags.genre = id3.genre_parsed().map(|genre| genre.into_owned());
+        tags.compilation = id3.get("TCMP").and_then(|frame| frame.content().text()).is_some_and(parse_flag);
+        tags.lyrics = id3_synced_lyrics(&id3).or_else(|| id3.lyrics().next().map(|lyrics| lyrics.text.clone()));
+        for picture in id3.pictures() {
+            tags.add_picture(picture.picture_type.into(), picture.data.clone());
+        }
+    }
+
+    Ok(Some(tags))
+}
+
+// COMM: channels, sample frames, sample size, then the rate as an 80-bit float
+fn comm_duration(comm: &[u8; 18]) -> Option<Duration> {
+    let frames = u32::from_be_bytes(comm[2..6].try_into().unwrap());
+    let rate = extended_to_f64(comm[8..18].try_into().unwrap());
+    (rate > 0.0 && rate.is_finite()).then(|| Duration::from_secs_f64(frames as f64 / rate))
+}
+
+// IEEE 754 80-bit extended precision: sign + 15-bit exponent, explicit 64-bit mantissa
+fn extended_to_f64(bytes: [u8; 10]) -> f64 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16_383 - 63);
+    if bytes[0] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    #[test]
+    fn test_read_native_aiff_chunks() {
+        // 44100 Hz as an 80-bit float
+        let rate = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+        let mut comm = 2u16.to_be_bytes().to_vec();
+        comm.extend_from_slice(&(44_100u32 * 3).to_be_bytes());
+        comm.extend_from_slice(&16u16.to_be_bytes());
+        comm.extend_from_slice(&rate);
+
+        let chunks = [chunk(b"COMM", &comm), chunk(b"NAME", b"Old Title"), chunk(b"AUTH", b"Someone"), chunk(b"SSND", &[0; 9])]
+            .concat();
+        let mut file = b"FORM".to_vec();
+        file.extend_from_slice(&((chunks.len() + 4) as u32).to_be_bytes());
+        file.extend_from_slice(b"AIFF");
+        file.extend(chunks);
+
+        let path = std::env::temp_dir().join(format!("{}-tags.aiff", std::process::id()));
+        std::fs::write(&path, &file).unwrap();
+        let tags = read_from_path(&path).unwrap().unwrap();
+
+        std::fs::write(&path, b"RIFF\0\0\0\0WAVE").unwrap();
+        assert!(read_from_path(&path).unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(tags.title.as_deref(), Some("Old Title"));
+        assert_eq!(tags.artist.as_deref(), Some("Someone"));
+        assert_eq!(tags.album, None);
+        assert_eq!(tags.duration, Some(Duration::from_secs(3)));
+    }
+}
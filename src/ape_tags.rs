@@ -0,0 +1,319 @@
+// APEv2 tags, cover art and duration for WavPack (.wv) and Monkey's Audio (.ape)
+// files. Both formats carry APEv2 tags at the end of the file, which id3 and
+// metaflac don't read; the duration comes from the stream's own header.
+
+use crate::metadata::{parse_flag, parse_track_number, parse_year, prefer_synced, EmbeddedTags, FRONT_COVER};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+const FOOTER_LEN: u64 = 32;
+const ID3V1_LEN: u64 = 128;
+// Tags hold covers, but nothing sensible is bigger than this
+const MAX_TAG_BYTES: u64 = 16 * 1024 * 1024;
+
+// WavPack sample rates by the 4-bit index in the block flags; 15 means custom
+const WAVPACK_RATES: [u32; 15] = [
+    6_000, 8_000, 9_600, 11_025, 12_000, 16_000, 22_050, 24_000, 32_000, 44_100, 48_000, 64_000, 88_200, 96_000,
+    192_000,
+];
+
+/// `Ok(None)` if the file isn't a WavPack or Monkey's Audio stream.
+pub fn read_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    let mut file = File::open(path)?;
+    let start = skip_id3v2(&mut file)?;
+    let mut header = vec![0u8; 96];
+    file.seek(SeekFrom::Start(start))?;
+    let read = read_up_to(&mut file, &mut header)?;
+    header.truncate(read);
+
+    let duration = if header.starts_with(b"wvpk") {
+        wavpack_duration(&header)
+    } else if header.starts_with(b"MAC ") {
+        ape_duration(&header)
+    } else {
+        return Ok(None);
+    };
+
+    let mut tags = read_apev2(&mut file)?.unwrap_or_default();
+    tags.duration = duration;
+    Ok(Some(tags))
+}
+
+/// Only the APEv2 tag at the end of the file, whatever the stream is; MP3s
+/// tagged by foobar2000 or mp3gain carry one in place of (or besides) ID3.
+pub fn read_apev2_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    read_apev2(&mut File::open(path)?)
+}
+
+// Files are occasionally prefixed with an ID3v2 tag; returns where the stream starts
+fn skip_id3v2(file: &mut File) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    file.seek(SeekFrom::Start(0))?;
+    if read_up_to(file, &mut header)? < 10 || &header[..3] != b"ID3" {
+        return Ok(0);
+    }
+    // Syncsafe size: 7 bits per byte
+    let size = header[6..10].iter().fold(0u64, |acc, b| (acc << 7) | (*b & 0x7f) as u64);
+    Ok(10 + size)
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+// First WavPack block header: total sample count and the sample rate index
+fn wavpack_duration(header: &[u8]) -> Option<Duration> {
+    let total_samples = u32_le(header.get(12..16)?);
+    let flags = u32_le(header.get(24..28)?);
+    // All ones: the encoder didn't know the length (piped input)
+    if total_samples == u32::MAX {
+        return None;
+    }
+    let total = ((*header.get(11)? as u64) << 32) | total_samples as u64;
+    let rate = *WAVPACK_RATES.get(((flags >> 23) & 0xf) as usize)?;
+    Some(Duration::from_secs_f64(total as f64 / rate as f64))
+}
+
+// Monkey's Audio: a descriptor plus header since 3.98, a single older header before
+fn ape_duration(header: &[u8]) -> Option<Duration> {
+    let version = u16_le(header.get(4..6)?);
+    let (blocks_per_frame, final_frame_blocks, total_frames, sample_rate) = if version >= 3980 {
+        let descriptor_len = u32_le(header.get(8..12)?) as usize;
+        let h = header.get(descriptor_len..)?;
+        (u32_le(h.get(4..8)?), u32_le(h.get(8..12)?), u32_le(h.get(12..16)?), u32_le(h.get(20..24)?))
+    } else {
+        let compression = u16_le(header.get(6..8)?);
+        let blocks_per_frame = if version >= 3950 {
+            73_728 * 4
+        } else if version >= 3900 || (version >= 3800 && compression == 4000) {
+            73_728
+        } else {
+            9_216
+        };
+        (blocks_per_frame, u32_le(header.get(28..32)?), u32_le(header.get(24..28)?), u32_le(header.get(12..16)?))
+    };
+    if total_frames == 0 || sample_rate == 0 {
+        return None;
+    }
+    let samples = (total_frames as u64 - 1) * blocks_per_frame as u64 + final_frame_blocks as u64;
+    Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+// APEv2 tag at the end of the file, before an ID3v1 tag if there is one
+fn read_apev2(file: &mut File) -> io::Result<Option<EmbeddedTags>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    for trailer in [0, ID3V1_LEN] {
+        let Some(footer_pos) = len.checked_sub(trailer + FOOTER_LEN) else { continue };
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        file.seek(SeekFrom::Start(footer_pos))?;
+        file.read_exact(&mut footer)?;
+        if &footer[..8] != b"APETAGEX" {
+            continue;
+        }
+
+        // Size covers the items and the footer, not the optional header
+        let size = u32_le(&footer[12..16]) as u64;
+        let count = u32_le(&footer[16..20]);
+        if !(FOOTER_LEN..=MAX_TAG_BYTES).contains(&size) || size > footer_pos + FOOTER_LEN {
+            return Ok(None);
+        }
+        let mut items = vec![0u8; (size - FOOTER_LEN) as usize];
+        file.seek(SeekFrom::Start(footer_pos + FOOTER_LEN - size))?;
+        file.read_exact(&mut items)?;
+        return Ok(Some(parse_items(&items, count)));
+    }
+    Ok(None)
+}
+
+// Item: value size, flags, NUL-terminated key, value. Keys are case-insensitive.
+fn parse_items(data: &[u8], count: u32) -> EmbeddedTags {
+    let mut tags = EmbeddedTags::default();
+    let mut pos = 0usize;
+
+    for _ in 0..count {
+        let Some(header) = data.get(pos..pos + 8) else { break };
+        let value_len = u32_le(&header[..4]) as usize;
+        let flags = u32_le(&header[4..8]);
+        let Some(key_len) = data.get(pos + 8..).and_then(|rest| rest.iter().position(|b| *b == 0)) else { break };
+        let key = String::from_utf8_lossy(&data[pos + 8..pos + 8 + key_len]).to_ascii_lowercase();
+        let value_start = pos + 8 + key_len + 1;
+        let Some(value) = value_start.checked_add(value_len).and_then(|end| data.get(value_start..end)) else { break };
+        pos = value_start + value_len;
+
+        // Bits 1-2: 0 text, 1 binary, 2 external reference
+        let is_text = (flags >> 1) & 0x3 == 0;
+        // Multiple text values are NUL-separated; the first is enough here
+        let text = || {
+            let first = value.split(|b| *b == 0).next().unwrap_or_default();
+            let text = String::from_utf8_lossy(first).trim().to_string();
+            (!text.is_empty()).then_some(text)
+        };
+        match key.as_str() {
+            "title" if is_text => tags.title = tags.title.take().or_else(text),
+            "artist" if is_text => tags.artist = tags.artist.take().or_else(text),
+            "album artist" | "albumartist" if is_text => tags.album_artist = tags.album_artist.take().or_else(text),
+            "album" if is_text => tags.album = tags.album.take().or_else(text),
+            "track" if is_text => tags.track_number = tags.track_number.or_else(|| text().as_deref().and_then(parse_track_number)),
+            "year" if is_text => tags.year = tags.year.or_else(|| text().as_deref().and_then(parse_year)),
+            "genre" if is_text => tags.genre = tags.genre.take().or_else(text),
+            "compilation" if is_text => tags.compilation |= text().is_some_and(|flag| parse_flag(&flag)),
+            "lyrics" | "unsyncedlyrics" if is_text => {
+                if let Some(lyrics) = text() {
+                    tags.lyrics = prefer_synced(tags.lyrics.take(), &lyrics);
+                }
+            }
+            // Binary cover items are "filename\0image data"
+            _ if !is_text && key.starts_with("cover art (") => {
+                let image = value.iter().position(|b| *b == 0).map(|nul| &value[nul + 1..]);
+                if let Some(image) = image {
+                    tags.add_picture(picture_type(&key), image.to_vec());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tags.fill_artist_from_album_artist();
+    tags
+}
+
+fn u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+// "Cover Art (Back)" and so on, named after the ID3 picture types
+fn picture_type(key: &str) -> u8 {
+    match key.trim_start_matches("cover art (").trim_end_matches(')') {
+        "icon" => 1,
+        "other icon" => 2,
+        "front" => FRONT_COVER,
+        "back" => 4,
+        "leaflet" => 5,
+        "media" => 6,
+        "lead artist" => 7,
+        "artist" => 8,
+        "conductor" => 9,
+        "band" => 10,
+        "composer" => 11,
+        "lyricist" => 12,
+        "recording location" => 13,
+        "during recording" => 14,
+        "during performance" => 15,
+        "video capture" => 16,
+        "illustration" => 18,
+        "band logotype" => 19,
+        "publisher logotype" => 20,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(key: &str, flags: u32, value: &[u8]) -> Vec<u8> {
+        let mut out = (value.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+        out.extend_from_slice(value);
+        out
+    }
+
+    fn apev2(items: &[Vec<u8>]) -> Vec<u8> {
+        let body = items.concat();
+        let mut out = body.clone();
+        out.extend_from_slice(b"APETAGEX");
+        out.extend_from_slice(&2000u32.to_le_bytes());
+        out.extend_from_slice(&((body.len() as u64 + FOOTER_LEN) as u32).to_le_bytes());
+        out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 12]);
+        out
+    }
+
+    fn write_temp(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_wavpack_with_apev2_and_id3v1() {
+        let mut header = b"wvpk".to_vec();
+        header.extend_from_slice(&100u32.to_le_bytes());
+        header.extend_from_slice(&0x410u16.to_le_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(&(44_100u32 * 5).to_le_bytes());
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&(9u32 << 23).to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+
+        let mut cover = b"cover.jpg\0".to_vec();
+        cover.extend_from_slice(b"\xff\xd8jpeg");
+        let mut file = header;
+        file.extend_from_slice(&[0x11; 200]);
+        file.extend(apev2(&[
+            item("Title", 0, "Ünïcode".as_bytes()),
+            item("ARTIST", 0, b"First\0Second"),
+            item("Album", 0, b"  "),
+            item("Track", 0, b"4/10"),
+            item("Cover Art (Back)", 1 << 1, b"back.jpg\0\xff\xd8back"),
+            item("Cover Art (Front)", 1 << 1, &cover),
+        ]));
+        file.extend_from_slice(b"TAG");
+        file.extend_from_slice(&[0; 125]);
+        let path = write_temp("tags.wv", &file);
+
+        let tags = read_from_path(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(tags.title.as_deref(), Some("Ünïcode"));
+        assert_eq!(tags.artist.as_deref(), Some("First"));
+        assert_eq!(tags.album, None);
+        assert_eq!(tags.track_number, Some(4));
+        assert_eq!(tags.cover(), Some(&b"\xff\xd8jpeg"[..]));
+        let labels: Vec<_> = tags.pictures.iter().map(|picture| picture.label()).collect();
+        assert_eq!(labels, ["Front cover", "Back cover"]);
+        assert_eq!(tags.duration, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_read_monkeys_audio_header() {
+        // 3.99 descriptor (52 bytes) followed by the header
+        let mut file = b"MAC ".to_vec();
+        file.extend_from_slice(&3990u16.to_le_bytes());
+        file.extend_from_slice(&[0, 0]);
+        file.extend_from_slice(&52u32.to_le_bytes());
+        file.extend_from_slice(&[0; 40]);
+        file.extend_from_slice(&2000u16.to_le_bytes());
+        file.extend_from_slice(&0u16.to_le_bytes());
+        file.extend_from_slice(&73_728u32.to_le_bytes());
+        file.extend_from_slice(&14_400u32.to_le_bytes());
+        file.extend_from_slice(&3u32.to_le_bytes());
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&48_000u32.to_le_bytes());
+        let path = write_temp("header.ape", &file);
+
+        let tags = read_from_path(&path).unwrap().unwrap();
+        assert_eq!(tags.duration, Some(Duration::from_secs_f64(161_856.0 / 48_000.0)));
+        assert_eq!(tags.title, None);
+
+        // An MP3 with an APEv2 tag is left to the ID3 reader
+        std::fs::write(&path, [b"ID3\x04\0\0\0\0\0\0\xff\xfb".to_vec(), apev2(&[item("Title", 0, b"x")])].concat()).unwrap();
+        assert!(read_from_path(&path).unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
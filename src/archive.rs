@@ -0,0 +1,270 @@
+// Read-only access to .zip albums: listing what's inside, and inflating single
+// entries so their tracks can play without unpacking the whole archive. Entries
+// are addressed as "<archive>.zip!/<entry>". Only stored and deflated entries
+// are read, which is what album downloads use; ZIP64 and encrypted ones aren't.
+
+use flate2::read::DeflateDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+const SEPARATOR: &str = "!/";
+const END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+const CENTRAL_DIR_HEADER: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+// The end record is 22 bytes plus a comment of up to 64K
+const MAX_END_RECORD: u64 = 22 + 0xFFFF;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveEntry {
+    // Path inside the archive, '/'-separated
+    pub name: String,
+    pub size: u64,
+    compressed_size: u64,
+    method: u16,
+    encrypted: bool,
+    header_offset: u64,
+}
+
+impl ArchiveEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.name.trim_end_matches('/').rsplit('/').next().unwrap_or(&self.name)
+    }
+}
+
+/// The track path for an entry of `archive`.
+pub fn entry_path(archive: &Path, entry: &str) -> String {
+    format!("{}{}{}", archive.display(), SEPARATOR, entry)
+}
+
+/// Splits a track path made by `entry_path` back into the archive and entry.
+pub fn split_path(path: &str) -> Option<(&Path, &str)> {
+    let at = path.to_ascii_lowercase().find(".zip!/")? + ".zip".len();
+    Some((Path::new(&path[..at]), &path[at + SEPARATOR.len()..]))
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Every entry in the archive, in the order the central directory lists them.
+pub fn list(archive: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let mut file = File::open(archive)?;
+    let len = file.metadata()?.len();
+    let tail_len = len.min(MAX_END_RECORD);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_OF_CENTRAL_DIR)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let count = u16_at(&tail, end + 10) as usize;
+    let dir_size = u32_at(&tail, end + 12) as u64;
+    let dir_offset = u32_at(&tail, end + 16) as u64;
+    if dir_offset == 0xFFFF_FFFF || count == 0xFFFF {
+        return Err(invalid("ZIP64 archives aren't supported"));
+    }
+
+    file.seek(SeekFrom::Start(dir_offset))?;
+    let mut dir = vec![0; dir_size as usize];
+    file.read_exact(&mut dir)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut at = 0;
+    while at + 46 <= dir.len() && u32_at(&dir, at) == CENTRAL_DIR_HEADER {
+        let name_len = u16_at(&dir, at + 28) as usize;
+        let extra_len = u16_at(&dir, at + 30) as usize;
+        let comment_len = u16_at(&dir, at + 32) as usize;
+        let name = dir.get(at + 46..at + 46 + name_len).ok_or_else(|| invalid("truncated central directory"))?;
+        entries.push(ArchiveEntry {
+            // Names not flagged UTF-8 are meant to be CP437, but in practice they're
+            // mostly ASCII or UTF-8 anyway
+            name: String::from_utf8_lossy(name).into_owned(),
+            size: u32_at(&dir, at + 24) as u64,
+            compressed_size: u32_at(&dir, at + 20) as u64,
+            method: u16_at(&dir, at + 10),
+            encrypted: u16_at(&dir, at + 8) & 1 != 0,
+            header_offset: u32_at(&dir, at + 42) as u64,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// The named entry's contents, inflated as they're read.
+pub fn open_entry(archive: &Path, name: &str) -> io::Result<(ArchiveEntry, Box<dyn Read + Send>)> {
+    let entry = list(archive)?
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("'{}' isn't in the archive", name)))?;
+    if entry.encrypted {
+        return Err(invalid("encrypted entries aren't supported"));
+    }
+
+    let mut file = File::open(archive)?;
+    file.seek(SeekFrom::Start(entry.header_offset))?;
+    let mut header = [0; 30];
+    file.read_exact(&mut header)?;
+    if u32_at(&header, 0) != LOCAL_HEADER {
+        return Err(invalid("bad local header"));
+    }
+    let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+    file.seek(SeekFrom::Current(skip))?;
+
+    let data = BufReader::new(file).take(entry.compressed_size);
+    let reader: Box<dyn Read + Send> = match entry.method {
+        0 => Box::new(data),
+        8 => Box::new(DeflateDecoder::new(data)),
+        method => return Err(invalid(&format!("compression method {} isn't supported", method))),
+    };
+    Ok((entry, reader))
+}
+
+/// Inflate one entry to `dest`, reporting bytes written and the entry's full
+/// size as it goes. Stops with `Interrupted` once `cancelled` turns true.
+pub fn extract_entry(
+    archive: &Path,
+    name: &str,
+    dest: &Path,
+    cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(u64, u64),
+) -> io::Result<()> {
+    let (entry, mut reader) = open_entry(archive, name)?;
+    let mut out = File::create(dest)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut written = 0;
+    loop {
+        if cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "extraction cancelled"));
+        }
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        out.write_all(&buffer[..n])?;
+        written += n as u64;
+        on_progress(written, entry.size);
+    }
+    out.flush()
+}
+
+/// What `extract_all` unpacked, and the entries it left alone.
+#[derive(Debug, Default)]
+pub struct Extracted {
+    pub files: Vec<PathBuf>,
+    // Names of wanted entries that would have landed outside the destination
+    pub skipped: Vec<String>,
+}
+
+/// Unpack every file whose name passes `wanted` into `dest`, keeping the
+/// archive's folders. Entries that would land outside `dest` are skipped.
+pub fn extract_all(archive: &Path, dest: &Path, wanted: &dyn Fn(&str) -> bool) -> io::Result<Extracted> {
+    let mut extracted = Extracted::default();
+    for entry in list(archive)? {
+        if entry.is_dir() || !wanted(entry.file_name()) {
+            continue;
+        }
+        let relative = Path::new(&entry.name);
+        if !relative.components().all(|part| matches!(part, Component::Normal(_))) {
+            extracted.skipped.push(entry.name);
+            continue;
+        }
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        extract_entry(archive, &entry.name, &target, &|| false, &mut |_, _| {})?;
+        extracted.files.push(target);
+    }
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minimal zip with one stored and one deflated entry; CRCs are left at zero
+    // since nothing here checks them
+    fn write_test_archive(path: &Path) {
+        let deflated = {
+            let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&b"la".repeat(500)).unwrap();
+            encoder.finish().unwrap()
+        };
+        let entries: [(&str, u16, &[u8], u32); 2] =
+            [("Album/cover.jpg", 0, b"\xFF\xD8\xFFjpeg", 7), ("Album/01 Song.flac", 8, &deflated, 1000)];
+
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+        for (name, method, content, size) in entries {
+            let offset = data.len() as u32;
+            for part in [&LOCAL_HEADER.to_le_bytes()[..], &[20, 0, 0, 0], &method.to_le_bytes(), &[0; 8]] {
+                data.extend_from_slice(part);
+            }
+            data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(content);
+
+            for part in [&CENTRAL_DIR_HEADER.to_le_bytes()[..], &[20, 0, 20, 0, 0, 0], &method.to_le_bytes(), &[0; 8]] {
+                central.extend_from_slice(part);
+            }
+            central.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            central.extend_from_slice(&size.to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let dir_offset = data.len() as u32;
+        data.extend_from_slice(&central);
+        data.extend_from_slice(&END_OF_CENTRAL_DIR.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 2, 0, 2, 0]);
+        data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        data.extend_from_slice(&dir_offset.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_reads_stored_and_deflated_entries() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_archive_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("Album.ZIP");
+        write_test_archive(&archive);
+
+        let entries = list(&archive).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].file_name(), "01 Song.flac");
+
+        let path = entry_path(&archive, &entries[1].name);
+        assert_eq!(split_path(&path), Some((archive.as_path(), "Album/01 Song.flac")));
+        assert_eq!(split_path("/music/01 Song.flac"), None);
+
+        let mut song = Vec::new();
+        open_entry(&archive, "Album/01 Song.flac").unwrap().1.read_to_end(&mut song).unwrap();
+        assert_eq!(song, b"la".repeat(500));
+
+        let extracted = extract_all(&archive, &dir.join("out"), &|name| name.ends_with(".jpg")).unwrap();
+        assert_eq!(extracted.files, vec![dir.join("out").join("Album").join("cover.jpg")]);
+        assert!(extracted.skipped.is_empty());
+        assert_eq!(std::fs::read(&extracted.files[0]).unwrap(), b"\xFF\xD8\xFFjpeg");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
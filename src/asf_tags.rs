@@ -0,0 +1,249 @@
+// Tags, cover art and duration for WMA files. WMA lives in an ASF container whose
+// header object carries the metadata: the content description (title, author),
+// the extended content description (WM/* attributes) and the file properties.
+
+use crate::metadata::{parse_flag, parse_track_number, parse_year, EmbeddedTags};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Duration;
+
+// GUIDs as stored on disk (first three fields little-endian)
+const HEADER_OBJECT: [u8; 16] = guid(0x75B2_2630, 0x668E, 0x11CF, [0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C]);
+const FILE_PROPERTIES: [u8; 16] = guid(0x8CAB_DCA1, 0xA947, 0x11CF, [0x8E, 0xE4, 0x00, 0xC0, 0x0C, 0x20, 0x53, 0x65]);
+const CONTENT_DESCRIPTION: [u8; 16] =
+    guid(0x75B2_2633, 0x668E, 0x11CF, [0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C]);
+const EXTENDED_CONTENT_DESCRIPTION: [u8; 16] =
+    guid(0xD2D0_A440, 0xE307, 0x11D2, [0x97, 0xF0, 0x00, 0xA0, 0xC9, 0x5E, 0xA8, 0x50]);
+
+// The header holds embedded pictures, but nothing sensible is bigger than this
+const MAX_HEADER_BYTES: u64 = 16 * 1024 * 1024;
+const OBJECT_HEADER_LEN: usize = 24;
+
+// Extended content descriptor value types
+const VALUE_UNICODE: u16 = 0;
+const VALUE_BYTES: u16 = 1;
+const VALUE_DWORD: u16 = 3;
+
+const fn guid(a: u32, b: u16, c: u16, d: [u8; 8]) -> [u8; 16] {
+    let a = a.to_le_bytes();
+    let b = b.to_le_bytes();
+    let c = c.to_le_bytes();
+    [a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]]
+}
+
+/// `Ok(None)` if the file isn't an ASF (WMA / WMV) container.
+pub fn read_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 30];
+    if file.read_exact(&mut header).is_err() || header[..16] != HEADER_OBJECT {
+        return Ok(None);
+    }
+    let size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+    if !(30..=MAX_HEADER_BYTES).contains(&size) {
+        return Ok(None);
+    }
+
+    let mut objects = Vec::new();
+    (&mut file).take(size - 30).read_to_end(&mut objects)?;
+    Ok(Some(parse_header_objects(&objects)))
+}
+
+fn parse_header_objects(data: &[u8]) -> EmbeddedTags {
+    let mut tags = EmbeddedTags::default();
+    let mut pos = 0usize;
+
+    while let Some(header) = data.get(pos..pos + OBJECT_HEADER_LEN) {
+        let size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let Some(end) = usize::try_from(size).ok().and_then(|size| pos.checked_add(size)) else { break };
+        // Also stops on sizes smaller than the object header
+        let Some(body) = data.get(pos + OBJECT_HEADER_LEN..end) else { break };
+
+        let id = &header[..16];
+        if id == FILE_PROPERTIES {
+            tags.duration = parse_file_properties(body);
+        } else if id == CONTENT_DESCRIPTION {
+            let (title, author) = parse_content_description(body);
+            tags.title = tags.title.take().or(title);
+            tags.artist = tags.artist.take().or(author);
+        } else if id == EXTENDED_CONTENT_DESCRIPTION {
+            parse_extended_content(body, &mut tags);
+        }
+        pos = end;
+    }
+
+    tags.fill_artist_from_album_artist();
+    tags
+}
+
+// Play duration (100ns units) includes the preroll (ms)
+fn parse_file_properties(body: &[u8]) -> Option<Duration> {
+    let play_duration = u64::from_le_bytes(body.get(40..48)?.try_into().ok()?);
+    let preroll = u64::from_le_bytes(body.get(56..64)?.try_into().ok()?);
+    let duration = Duration::from_nanos(play_duration.saturating_mul(100)).saturating_sub(Duration::from_millis(preroll));
+    (!duration.is_zero()).then_some(duration)
+}
+
+// Five lengths (title, author, copyright, description, rating), then the strings
+fn parse_content_description(body: &[u8]) -> (Option<String>, Option<String>) {
+    let Some(lengths) = body.get(..10) else { return (None, None) };
+    let title_len = u16_le(&lengths[0..2]) as usize;
+    let author_len = u16_le(&lengths[2..4]) as usize;
+    let title = body.get(10..10 + title_len).and_then(utf16_text);
+    let author = body.get(10 + title_len..10 + title_len + author_len).and_then(utf16_text);
+    (title, author)
+}
+
+fn parse_extended_content(body: &[u8], tags: &mut EmbeddedTags) {
+    let Some(count) = body.get(..2).map(u16_le) else { return };
+    let mut pos = 2usize;
+    for _ in 0..count {
+        let Some(name_len) = body.get(pos..pos + 2).map(u16_le) else { return };
+        let name_end = pos + 2 + name_len as usize;
+        let Some(name) = body.get(pos + 2..name_end).and_then(utf16_text) else { return };
+        let Some(kind_and_len) = body.get(name_end..name_end + 4) else { return };
+        let value_type = u16_le(&kind_and_len[..2]);
+        let value_len = u16_le(&kind_and_len[2..]) as usize;
+        let Some(value) = body.get(name_end + 4..name_end + 4 + value_len) else { return };
+        pos = name_end + 4 + value_len;
+
+        let text = || (value_type == VALUE_UNICODE).then(|| utf16_text(value)).flatten();
+        match name.as_str() {
+            "WM/AlbumTitle" => tags.album = tags.album.take().or_else(text),
+            "WM/AlbumArtist" => tags.album_artist = tags.album_artist.take().or_else(text),
+            "WM/Year" => tags.year = tags.year.or_else(|| text().as_deref().and_then(parse_year)),
+            "WM/Genre" => tags.genre = tags.genre.take().or_else(text),
+            // Usually a BOOL, four bytes of which any set means true
+            "WM/IsCompilation" => {
+                tags.compilation |= match value_type {
+                    VALUE_UNICODE => text().is_some_and(|flag| parse_flag(&flag)),
+                    _ => value.iter().any(|byte| *byte != 0),
+                }
+            }
+            "WM/TrackNumber" => {
+                let number = match value_type {
+                    VALUE_DWORD => value.try_into().ok().map(u32::from_le_bytes).filter(|n| *n > 0),
+                    _ => text().as_deref().and_then(parse_track_number),
+                };
+                tags.track_number = tags.track_number.or(number);
+            }
+            "WM/Lyrics" => tags.lyrics = tags.lyrics.take().or_else(text),
+            "WM/Picture" if value_type == VALUE_BYTES => {
+                if let Some((picture_type, data)) = parse_picture(value) {
+                    tags.add_picture(picture_type, data);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// WM/Picture: type, data length, NUL-terminated UTF-16 mime and description, data
+fn parse_picture(value: &[u8]) -> Option<(u8, Vec<u8>)> {
+    let picture_type = *value.first()?;
+    let data_len = u32::from_le_bytes(value.get(1..5)?.try_into().ok()?) as usize;
+    let mut pos = 5;
+    for _ in 0..2 {
+        let nul = value.get(pos..)?.chunks_exact(2).position(|c| c == [0, 0])?;
+        pos += nul * 2 + 2;
+    }
+    let data = value.get(pos..pos.checked_add(data_len)?)?;
+    (!data.is_empty()).then(|| (picture_type, data.to_vec()))
+}
+
+fn utf16_text(bytes: &[u8]) -> Option<String> {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let text = String::from_utf16_lossy(&units);
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16z(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    fn object(id: [u8; 16], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&((body.len() + OBJECT_HEADER_LEN) as u64).to_le_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn descriptor(name: &str, value_type: u16, value: &[u8]) -> Vec<u8> {
+        let name = utf16z(name);
+        let mut out = (name.len() as u16).to_le_bytes().to_vec();
+        out.extend_from_slice(&name);
+        out.extend_from_slice(&value_type.to_le_bytes());
+        out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        out.extend_from_slice(value);
+        out
+    }
+
+    #[test]
+    fn test_read_wma_header() {
+        let mut properties = vec![0u8; 80];
+        properties[40..48].copy_from_slice(&(65_000_000u64).to_le_bytes());
+        properties[56..64].copy_from_slice(&1_500u64.to_le_bytes());
+
+        let (title, author) = (utf16z("Tïtle"), utf16z(""));
+        let mut content = Vec::new();
+        for len in [title.len(), author.len(), 0, 0, 0] {
+            content.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        content.extend_from_slice(&title);
+        content.extend_from_slice(&author);
+
+        let mut picture = vec![3u8];
+        picture.extend_from_slice(&4u32.to_le_bytes());
+        picture.extend(utf16z("image/jpeg"));
+        picture.extend(utf16z(""));
+        picture.extend_from_slice(b"\xff\xd8\xff\xe0");
+        let descriptors = [
+            descriptor("WM/AlbumTitle", VALUE_UNICODE, &utf16z("Album")),
+            descriptor("WM/AlbumArtist", VALUE_UNICODE, &utf16z("Band")),
+            descriptor("WM/TrackNumber", VALUE_DWORD, &7u32.to_le_bytes()),
+            descriptor("WM/Year", VALUE_UNICODE, &utf16z("1999")),
+            descriptor("WM/Genre", VALUE_UNICODE, &utf16z("Trance")),
+            descriptor("WM/Picture", VALUE_BYTES, &picture),
+        ];
+        let mut extended = (descriptors.len() as u16).to_le_bytes().to_vec();
+        extended.extend(descriptors.concat());
+
+        let objects = [
+            object(FILE_PROPERTIES, &properties),
+            object(CONTENT_DESCRIPTION, &content),
+            object(EXTENDED_CONTENT_DESCRIPTION, &extended),
+        ]
+        .concat();
+        let mut file = HEADER_OBJECT.to_vec();
+        file.extend_from_slice(&((objects.len() + 30) as u64).to_le_bytes());
+        file.extend_from_slice(&3u32.to_le_bytes());
+        file.extend_from_slice(&[1, 2]);
+        file.extend(objects);
+        file.extend_from_slice(&[0x55; 64]);
+
+        let path = std::env::temp_dir().join(format!("{}-tags.wma", std::process::id()));
+        std::fs::write(&path, &file).unwrap();
+        let tags = read_from_path(&path).unwrap().unwrap();
+
+        std::fs::write(&path, b"RIFF....WAVE").unwrap();
+        assert!(read_from_path(&path).unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(tags.title.as_deref(), Some("Tïtle"));
+        assert_eq!(tags.artist.as_deref(), Some("Band"));
+        assert_eq!(tags.album.as_deref(), Some("Album"));
+        assert_eq!(tags.track_number, Some(7));
+        assert_eq!((tags.year, tags.genre.as_deref()), (Some(1999), Some("Trance")));
+        assert_eq!(tags.cover(), Some(&b"\xff\xd8\xff\xe0"[..]));
+        assert_eq!(tags.duration, Some(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,339 @@
+// A Bandcamp fan's purchased collection, read with the same endpoints the
+// website uses once logged in (the "identity" cookie stands in for the login).
+// Owned tracks stream from Bandcamp's MP3 links; albums can also be downloaded
+// as FLAC and unpacked into a library folder, tagged as Bandcamp ships them.
+
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_TYPE, COOKIE};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SUMMARY_URL: &str = "https://bandcamp.com/api/fan/2/collection_summary";
+const ITEMS_URL: &str = "https://bandcamp.com/api/fancollection/1/collection_items";
+const PAGE_SIZE: u32 = 100;
+// What gets unpacked out of a downloaded album
+const KEEP_EXTENSIONS: [&str; 4] = [".flac", ".jpg", ".jpeg", ".png"];
+
+// Login and where downloads go, saved in bandcamp.json
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BandcampAccount {
+    #[serde(default)]
+    pub encrypted_identity: String,
+    #[serde(default)]
+    pub download_dir: Option<String>,
+}
+
+impl BandcampAccount {
+    pub fn identity(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if self.encrypted_identity.is_empty() {
+            return Ok(String::new());
+        }
+        crate::crypto::decrypt_password(&self.encrypted_identity, &crate::crypto::get_master_password()?)
+    }
+
+    pub fn set_identity(&mut self, identity: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.encrypted_identity = if identity.trim().is_empty() {
+            String::new()
+        } else {
+            crate::crypto::encrypt_password(identity.trim(), &crate::crypto::get_master_password()?)?
+        };
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollectionTrack {
+    pub title: String,
+    pub artist: String,
+    pub duration: Duration,
+    pub stream_url: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollectionItem {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub art_url: Option<String>,
+    pub tracks: Vec<CollectionTrack>,
+    // The item's "download" page, where the FLAC link is found
+    pub download_page: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Summary {
+    fan_id: u64,
+}
+
+#[derive(Deserialize)]
+struct ItemsPage {
+    #[serde(default)]
+    items: Vec<RawItem>,
+    #[serde(default)]
+    more_available: bool,
+    last_token: Option<String>,
+    #[serde(default)]
+    tracklists: HashMap<String, Vec<RawTrack>>,
+    #[serde(default)]
+    redownload_urls: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RawItem {
+    tralbum_type: String,
+    tralbum_id: u64,
+    item_title: String,
+    band_name: String,
+    item_art_id: Option<u64>,
+    sale_item_type: Option<String>,
+    sale_item_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawTrack {
+    title: String,
+    artist: Option<String>,
+    #[serde(default)]
+    duration: f64,
+    #[serde(default)]
+    file: HashMap<String, String>,
+}
+
+pub struct BandcampClient {
+    client: Client,
+    identity: String,
+}
+
+impl BandcampClient {
+    pub fn new(identity: &str) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(120))
+            .build()
+            .map_err(|e| format!("创建HTTP客户端失败: {}", e))?;
+        Ok(BandcampClient { client, identity: identity.trim().to_string() })
+    }
+
+    fn cookie(&self) -> String {
+        format!("identity={}", self.identity)
+    }
+
+    /// Everything the fan has bought, newest purchase first.
+    pub fn collection(&self) -> Result<Vec<CollectionItem>, String> {
+        let summary: Summary = serde_json::from_str(&self.get_text(SUMMARY_URL)?)
+            .map_err(|_| "Bandcamp didn't accept the identity cookie".to_string())?;
+
+        // Paging starts from "now" and walks back through older purchases
+        let mut token = format!("{}::a::", unix_now());
+        let mut items = Vec::new();
+        loop {
+            let body = serde_json::json!({ "fan_id": summary.fan_id, "older_than_token": token, "count": PAGE_SIZE });
+            let page = self
+                .client
+                .post(ITEMS_URL)
+                .header(COOKIE, self.cookie())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .map_err(|e| format!("Couldn't list the Bandcamp collection: {}", e))?;
+            let (page_items, next) = parse_items_page(&page)?;
+            items.extend(page_items);
+            match next {
+                Some(next) => token = next,
+                None => break,
+            }
+        }
+        eprintln!("[Bandcamp] 收藏共 {} 项", items.len());
+        Ok(items)
+    }
+
+    /// Download `item` as FLAC into its `album_dir` under `library`, unpacking
+    /// albums, and return the files written. `on_progress` gets bytes so far
+    /// and the total (0 if unknown).
+    pub fn download_flac(
+        &self,
+        item: &CollectionItem,
+        library: &Path,
+        on_progress: &mut dyn FnMut(u64, u64),
+    ) -> Result<Vec<PathBuf>, String> {
+        let dest = &album_dir(library, item);
+        let page_url = item.download_page.as_deref().ok_or("This item has no download")?;
+        let page = self.get_text(page_url)?;
+        let flac_url = flac_url_from_download_page(&page).ok_or("Bandcamp doesn't offer FLAC for this item")?;
+        let file_url = self.resolve_download(&flac_url)?;
+
+        std::fs::create_dir_all(dest).map_err(|e| format!("Couldn't create {}: {}", dest.display(), e))?;
+        let partial = dest.join(".bandcamp-download.part");
+        let mut response = self
+            .client
+            .get(&file_url)
+            .header(COOKIE, self.cookie())
+            .timeout(Duration::from_secs(3600))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Download failed: {}", e))?;
+        let total = response.content_length().unwrap_or(0);
+        let mut out = std::fs::File::create(&partial).map_err(|e| e.to_string())?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut written = 0;
+        loop {
+            let n = response.read(&mut buffer).map_err(|e| format!("Download failed: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            out.write_all(&buffer[..n]).map_err(|e| e.to_string())?;
+            written += n as u64;
+            on_progress(written, total);
+        }
+        drop(out);
+
+        let mut magic = [0; 2];
+        let is_zip = std::fs::File::open(&partial).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == *b"PK";
+        let files = if is_zip {
+            let zip = partial.with_extension("zip");
+            std::fs::rename(&partial, &zip).map_err(|e| e.to_string())?;
+            let wanted = |name: &str| {
+                let name = name.to_lowercase();
+                KEEP_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+            };
+            let extracted = crate::archive::extract_all(&zip, dest, &wanted).map_err(|e| format!("Couldn't unpack the album: {}", e));
+            let _ = std::fs::remove_file(&zip);
+            let extracted = extracted?;
+            for name in &extracted.skipped {
+                eprintln!("[Bandcamp] 跳过不安全的路径: {}", name);
+            }
+            extracted.files
+        } else {
+            let target = dest.join(sanitize_file_name(&format!("{} - {}.flac", item.artist, item.title)));
+            std::fs::rename(&partial, &target).map_err(|e| e.to_string())?;
+            vec![target]
+        };
+        eprintln!("[Bandcamp] 已下载 {}: {} 个文件", item.title, files.len());
+        Ok(files)
+    }
+
+    fn get_text(&self, url: &str) -> Result<String, String> {
+        self.client
+            .get(url)
+            .header(COOKIE, self.cookie())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .map_err(|e| format!("Couldn't reach Bandcamp: {}", e))
+    }
+
+    // The FLAC link points at a page that prepares the file; its "statdownload"
+    // twin answers with the actual file URL once it's ready
+    fn resolve_download(&self, url: &str) -> Result<String, String> {
+        let stat_url = url.replacen("/download/", "/statdownload/", 1);
+        let stat = self.get_text(&format!("{}&.vrs=1", stat_url))?;
+        let json = stat.find('{').zip(stat.rfind('}')).map(|(start, end)| &stat[start..=end]).unwrap_or(&stat);
+        let download_url = serde_json::from_str::<serde_json::Value>(json)
+            .ok()
+            .and_then(|stat| stat.get("download_url").and_then(|url| url.as_str()).map(str::to_string));
+        Ok(download_url.unwrap_or_else(|| url.to_string()))
+    }
+}
+
+/// Where a downloaded item goes: "<library>/<artist>/<title>".
+pub fn album_dir(library: &Path, item: &CollectionItem) -> PathBuf {
+    library.join(sanitize_file_name(&item.artist)).join(sanitize_file_name(&item.title))
+}
+
+// Bandcamp's paging tokens are "<unix time>::<type>::"
+fn unix_now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn parse_items_page(json: &str) -> Result<(Vec<CollectionItem>, Option<String>), String> {
+    let page: ItemsPage = serde_json::from_str(json).map_err(|e| format!("Unexpected reply from Bandcamp: {}", e))?;
+    let mut tracklists = page.tracklists;
+    let items = page
+        .items
+        .into_iter()
+        .map(|raw| {
+            let key = format!("{}{}", raw.tralbum_type, raw.tralbum_id);
+            let tracks = tracklists
+                .remove(&key)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|track| {
+                    Some(CollectionTrack {
+                        stream_url: track.file.get("mp3-v0").or_else(|| track.file.values().next())?.clone(),
+                        artist: track.artist.unwrap_or_else(|| raw.band_name.clone()),
+                        duration: Duration::try_from_secs_f64(track.duration).unwrap_or_default(),
+                        title: track.title,
+                    })
+                })
+                .collect();
+            let sale_key = raw.sale_item_type.zip(raw.sale_item_id).map(|(kind, id)| format!("{}{}", kind, id));
+            CollectionItem {
+                download_page: sale_key.and_then(|key| page.redownload_urls.get(&key).cloned()),
+                art_url: raw.item_art_id.map(|art| format!("https://f4.bcbits.com/img/a{}_10.jpg", art)),
+                id: key,
+                title: raw.item_title,
+                artist: raw.band_name,
+                tracks,
+            }
+        })
+        .collect();
+    let next = if page.more_available { page.last_token } else { None };
+    Ok((items, next))
+}
+
+// The download page carries its data as HTML-escaped JSON in a data-blob attribute
+fn flac_url_from_download_page(html: &str) -> Option<String> {
+    let start = html.find("data-blob=\"")? + "data-blob=\"".len();
+    let end = start + html[start..].find('"')?;
+    let blob = html[start..end]
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    let data: serde_json::Value = serde_json::from_str(&blob).ok()?;
+    data.get("digital_items")?
+        .as_array()?
+        .iter()
+        .find_map(|item| item.pointer("/downloads/flac/url")?.as_str().map(str::to_string))
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars().map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_collection_and_download_page() {
+        let page = r#"{
+            "items": [{"tralbum_type": "a", "tralbum_id": 42, "item_title": "Album", "band_name": "Band",
+                       "item_art_id": 7, "sale_item_type": "p", "sale_item_id": 9}],
+            "more_available": true,
+            "last_token": "1700000000:42:a::",
+            "tracklists": {"a42": [{"title": "One", "duration": 61.5, "file": {"mp3-v0": "https://t4.bcbits.com/stream/1"}},
+                                   {"title": "Two", "artist": "Guest", "duration": 90.0, "file": {}}]},
+            "redownload_urls": {"p9": "https://bandcamp.com/download?sitem_id=9"}
+        }"#;
+        let (items, next) = parse_items_page(page).unwrap();
+        assert_eq!(next.as_deref(), Some("1700000000:42:a::"));
+        assert_eq!(items[0].download_page.as_deref(), Some("https://bandcamp.com/download?sitem_id=9"));
+        assert_eq!(items[0].art_url.as_deref(), Some("https://f4.bcbits.com/img/a7_10.jpg"));
+        // Tracks without a stream are left out
+        assert_eq!(items[0].tracks.len(), 1);
+        assert_eq!(items[0].tracks[0].artist, "Band");
+        assert_eq!(items[0].tracks[0].duration, Duration::from_millis(61_500));
+
+        let html = r#"<div id="pagedata" data-blob="{&quot;digital_items&quot;:[{&quot;downloads&quot;:{&quot;flac&quot;:{&quot;url&quot;:&quot;https://bandcamp.com/download/album?enc=flac&amp;id=42&quot;}}}]}"></div>"#;
+        assert_eq!(flac_url_from_download_page(html).as_deref(), Some("https://bandcamp.com/download/album?enc=flac&id=42"));
+        assert_eq!(flac_url_from_download_page("<html></html>"), None);
+
+        assert_eq!(sanitize_file_name("AC/DC - Who? Me.flac"), "AC_DC - Who_ Me.flac");
+    }
+}
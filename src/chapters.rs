@@ -0,0 +1,190 @@
+// Chapter markers for long single-file recordings (DJ mixes, live sets, radio
+// shows), read from a CUE sheet or a pasted tracklist with timestamps.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Within this far into a chapter, "previous" goes to the chapter before it
+const RESTART_WINDOW: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+/// Parse a CUE sheet (recognised by its `INDEX 01` lines) or a tracklist with
+/// one timestamped line per chapter. Sorted by start; untitled chapters are
+/// numbered.
+pub fn parse(text: &str) -> Vec<Chapter> {
+    let text = text.trim_start_matches('\u{feff}');
+    let is_cue = text.lines().any(|line| line.trim_start().to_uppercase().starts_with("INDEX 01"));
+    let mut chapters = if is_cue { parse_cue(text) } else { parse_tracklist(text) };
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters.dedup_by_key(|chapter| chapter.start);
+    for (i, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = format!("Chapter {}", i + 1);
+        }
+    }
+    chapters
+}
+
+// TRACK blocks with TITLE, PERFORMER and `INDEX 01 mm:ss:ff` (75 frames a second)
+fn parse_cue(text: &str) -> Vec<Chapter> {
+    #[derive(Default)]
+    struct CueTrack {
+        title: Option<String>,
+        performer: Option<String>,
+        start: Option<Duration>,
+    }
+
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let command = command.to_uppercase();
+        if command == "TRACK" {
+            tracks.push(CueTrack::default());
+            continue;
+        }
+        // TITLE and PERFORMER before the first TRACK describe the whole file
+        let Some(track) = tracks.last_mut() else { continue };
+        match command.as_str() {
+            "TITLE" => track.title = Some(unquote(rest)),
+            "PERFORMER" => track.performer = Some(unquote(rest)),
+            "INDEX" => {
+                if let Some(("01", time)) = rest.split_once(char::is_whitespace) {
+                    track.start = cue_time(time.trim());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tracks
+        .into_iter()
+        .filter_map(|track| {
+            let title = match (track.performer.filter(|p| !p.is_empty()), track.title) {
+                (Some(performer), Some(title)) => format!("{} - {}", performer, title),
+                (performer, title) => title.or(performer).unwrap_or_default(),
+            };
+            Some(Chapter { start: track.start?, title })
+        })
+        .collect()
+}
+
+fn unquote(text: &str) -> String {
+    text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text).to_string()
+}
+
+fn cue_time(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= 75 {
+        return None;
+    }
+    Some(Duration::from_secs(minutes * 60 + seconds) + Duration::from_millis(frames * 1000 / 75))
+}
+
+// "00:00 Artist - Title", "01. [1:02:03] Title", "Title (45:10)": the first word
+// that reads as a timestamp is the start, the rest of the line the title
+fn parse_tracklist(text: &str) -> Vec<Chapter> {
+    text.lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (at, start) = words.iter().enumerate().find_map(|(i, word)| {
+                let word = word.trim_matches(|c| matches!(c, '[' | ']' | '(' | ')'));
+                Some((i, timestamp(word)?))
+            })?;
+            // Leading numbering ("01.", "1)", "#3") isn't part of the title
+            let before = words[..at].iter().filter(|word| !is_numbering(word));
+            let title = before.chain(&words[at + 1..]).copied().collect::<Vec<_>>().join(" ");
+            let title = title.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | '|' | ':' | '.'));
+            Some(Chapter { start, title: title.to_string() })
+        })
+        .collect()
+}
+
+// m:ss or h:mm:ss
+fn timestamp(word: &str) -> Option<Duration> {
+    let parts: Vec<u64> = word.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let secs = match parts[..] {
+        [minutes, seconds] if seconds < 60 => minutes * 60 + seconds,
+        [hours, minutes, seconds] if minutes < 60 && seconds < 60 => hours * 3600 + minutes * 60 + seconds,
+        _ => return None,
+    };
+    Some(Duration::from_secs(secs))
+}
+
+fn is_numbering(word: &str) -> bool {
+    let digits = word.trim_start_matches('#').trim_end_matches(['.', ')']);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The chapter playing at `time`.
+pub fn chapter_at(chapters: &[Chapter], time: Duration) -> Option<usize> {
+    chapters.iter().rposition(|chapter| chapter.start <= time)
+}
+
+pub fn next_start(chapters: &[Chapter], time: Duration) -> Option<Duration> {
+    chapters.iter().map(|chapter| chapter.start).find(|start| *start > time)
+}
+
+/// Back to the start of the current chapter, or to the one before if that's
+/// where playback just was.
+pub fn previous_start(chapters: &[Chapter], time: Duration) -> Option<Duration> {
+    let current = chapter_at(chapters, time)?;
+    let start = chapters[current].start;
+    if time.saturating_sub(start) > RESTART_WINDOW || current == 0 {
+        Some(start)
+    } else {
+        Some(chapters[current - 1].start)
+    }
+}
+
+/// Chapters as tracklist text that `parse` reads back.
+pub fn to_tracklist(chapters: &[Chapter]) -> String {
+    chapters
+        .iter()
+        .map(|chapter| format!("{} {}", crate::format::format_duration(chapter.start), chapter.title))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(chapters: &[Chapter]) -> Vec<(u64, &str)> {
+        chapters.iter().map(|c| (c.start.as_millis() as u64, c.title.as_str())).collect()
+    }
+
+    #[test]
+    fn test_parses_cue_and_tracklists() {
+        let cue = "\u{feff}PERFORMER \"DJ Mix\"\r\nTITLE \"Summer Set\"\r\nFILE \"mix.mp3\" MP3\r\n  TRACK 01 AUDIO\r\n    TITLE \"Intro\"\r\n    INDEX 01 00:00:00\r\n  TRACK 02 AUDIO\r\n    TITLE \"Second\"\r\n    PERFORMER \"Artist B\"\r\n    INDEX 00 03:58:00\r\n    INDEX 01 04:00:15\r\n";
+        assert_eq!(summary(&parse(cue)), vec![(0, "Intro"), (240_200, "Artist B - Second")]);
+
+        let tracklist = "Summer Set tracklist\n01. [00:00] Intro\n02. 4:00 Artist B - Second\nThird (1:02:03)\n\n#4 1:10:00\n";
+        let chapters = parse(tracklist);
+        assert_eq!(
+            summary(&chapters),
+            vec![(0, "Intro"), (240_000, "Artist B - Second"), (3_723_000, "Third"), (4_200_000, "Chapter 4")]
+        );
+        assert_eq!(parse(&to_tracklist(&chapters)), chapters);
+    }
+
+    #[test]
+    fn test_chapter_navigation() {
+        let chapters = parse("0:00 A\n4:00 B\n8:00 C");
+        let at = Duration::from_secs;
+        assert_eq!(chapter_at(&chapters, at(300)), Some(1));
+        assert_eq!(next_start(&chapters, at(300)), Some(at(480)));
+        assert_eq!(next_start(&chapters, at(500)), None);
+        // Well into B goes back to its start; right after it starts, back to A
+        assert_eq!(previous_start(&chapters, at(300)), Some(at(240)));
+        assert_eq!(previous_start(&chapters, at(241)), Some(at(0)));
+        assert_eq!(previous_start(&chapters, at(1)), Some(at(0)));
+    }
+}
@@ -0,0 +1,285 @@
+// Indexing a whole WebDAV server into a playlist. The tree is listed first (in one
+// PROPFIND where the server allows it), then each audio file's tags and length are
+// read from its head over Range requests, a few files at a time, so nothing is
+// downloaded in full. Tracks are handed back in listing order as soon as the ones
+// before them are read.
+
+use crate::player::{TrackMetadata, WebDAVStreamSource};
+use crate::webdav::{Credentials, WebDAVClient, WebDAVItem};
+use crate::{cover_cache, is_audio_file, resolve_webdav_url, Track, TrackSource, WebDAVConfig};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// Files read at once; each is a couple of small requests, mostly spent waiting on the server
+const WORKERS: usize = 4;
+
+pub enum CloudScanEvent {
+    // How many audio files the server has that aren't in the playlist yet, once it's listed
+    Found(usize),
+    // The next tracks in listing order (possibly none), and how many files have been read
+    Tracks { tracks: Vec<Track>, done: usize },
+    // A file that couldn't be read: its path, name and why
+    Failed { path: String, name: String, reason: String },
+    // The server couldn't be listed; only Finished follows
+    Error(String),
+    Finished,
+}
+
+/// A server scan running in the background; dropping it cancels the scan.
+pub struct CloudScanner {
+    config_id: String,
+    name: String,
+    cancelled: Arc<AtomicBool>,
+    event_receiver: Mutex<Option<UnboundedReceiver<CloudScanEvent>>>,
+}
+
+impl CloudScanner {
+    /// Index everything on `config`'s server, leaving out the files whose paths
+    /// are in `known` without reading them.
+    pub fn start(config: WebDAVConfig, credentials: Option<Credentials>, known: HashSet<String>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (events, event_receiver) = unbounded_channel();
+        let (config_id, name) = (config.id.clone(), config.name.clone());
+
+        let stop = cancelled.clone();
+        std::thread::spawn(move || {
+            let client = WebDAVClient::new(config.url.clone()).with_credentials(credentials.clone());
+            // The client is async and this thread has no runtime of its own
+            let listed = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())
+                .and_then(|runtime| {
+                    runtime.block_on(client.list_tree("", |_| !stop.load(Ordering::Relaxed))).map_err(|e| e.to_string())
+                });
+            let files: Vec<WebDAVItem> = match listed {
+                Ok(files) => files.into_iter().filter(|f| is_audio_file(&f.name) && !known.contains(&f.path)).collect(),
+                Err(e) => {
+                    eprintln!("[CloudScan] 无法列出 {}: {}", config.name, e);
+                    let _ = events.send(CloudScanEvent::Error(e));
+                    let _ = events.send(CloudScanEvent::Finished);
+                    return;
+                }
+            };
+            if events.send(CloudScanEvent::Found(files.len())).is_err() {
+                return;
+            }
+
+            let failed = AtomicUsize::new(0);
+            read_tracks(
+                &files,
+                &|item| read_track(&config, &credentials, item),
+                &stop,
+                |tracks, done| {
+                    let _ = events.send(CloudScanEvent::Tracks { tracks, done });
+                },
+                |item, reason| {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("[CloudScan] 读取失败 {}: {}", item.path, reason);
+                    let _ = events.send(CloudScanEvent::Failed { path: item.path.clone(), name: item.name.clone(), reason });
+                },
+            );
+            if stop.load(Ordering::Relaxed) {
+                eprintln!("[CloudScan] 已取消扫描 {}", config.name);
+            } else {
+                eprintln!("[CloudScan] 扫描 {}: {} 个新文件，{} 个读取失败", config.name, files.len(), failed.into_inner());
+            }
+            let _ = events.send(CloudScanEvent::Finished);
+        });
+
+        CloudScanner { config_id, name, cancelled, event_receiver: Mutex::new(Some(event_receiver)) }
+    }
+
+    pub fn config_id(&self) -> &str {
+        &self.config_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Tracks already handed out stay; the rest of the server isn't read
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    // Found, the tracks and failures as they're read, then Finished; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<CloudScanEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+impl Drop for CloudScanner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// Read `files` on a few threads with `read`. `ready` is called as each file is
+// read with whichever tracks are now complete in order and the count read so
+// far; `failed` with each file that couldn't be read, as it's reached.
+fn read_tracks(
+    files: &[WebDAVItem],
+    read: &(dyn Fn(&WebDAVItem) -> Result<Track, String> + Sync),
+    cancelled: &AtomicBool,
+    mut ready: impl FnMut(Vec<Track>, usize),
+    mut failed: impl FnMut(&WebDAVItem, String),
+) {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..WORKERS.min(files.len()) {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = files.get(index) else { break };
+                    if sender.send((index, read(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Files read ahead of one still being read wait here for it
+        let mut waiting = BTreeMap::new();
+        let (mut handed_out, mut done) = (0, 0);
+        for (index, result) in receiver {
+            waiting.insert(index, result);
+            done += 1;
+            let mut tracks = Vec::new();
+            while let Some(result) = waiting.remove(&handed_out) {
+                match result {
+                    Ok(track) => tracks.push(track),
+                    Err(reason) => failed(&files[handed_out], reason),
+                }
+                handed_out += 1;
+            }
+            ready(tracks, done);
+        }
+    });
+}
+
+// A track for a remote file from the tags and length at its head. A server that
+// only sends whole files isn't worth downloading everything from; the file's
+// name stands in for its tags there.
+fn read_track(config: &WebDAVConfig, auth: &Option<Credentials>, item: &WebDAVItem) -> Result<Track, String> {
+    let url = resolve_webdav_url(config, &item.path).map_err(|e| format!("Bad path: {}", e))?;
+    let metadata = match WebDAVStreamSource::open(&url, auth.clone()) {
+        Ok(source) => TrackMetadata::from_stream(source),
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => TrackMetadata::default(),
+        Err(e) => return Err(format!("Couldn't read the file: {}", e)),
+    };
+    let mut duration = metadata.duration;
+    if duration.is_zero() {
+        // Symphonia can't size some streams without reading them through; their headers can
+        if let Ok(mut source) = WebDAVStreamSource::open(&url, auth.clone()) {
+            duration = crate::duration::read(&mut source).unwrap_or_default();
+        }
+    }
+
+    let title = metadata.title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| {
+        Path::new(&item.name).file_stem().and_then(|s| s.to_str()).unwrap_or(&item.name).to_string()
+    });
+    let artist = metadata.artist.filter(|a| !a.trim().is_empty()).unwrap_or_else(|| "Cloud Stream".to_string());
+    let album = metadata.album.filter(|a| !a.trim().is_empty());
+    let cover = metadata.pictures.first().and_then(|picture| match &album {
+        Some(album) => cover_cache::store_album(&artist, album, || Some(picture.data.clone())),
+        None => cover_cache::store(&picture.data),
+    });
+    Ok(Track {
+        id: uuid::Uuid::new_v4().to_string(),
+        path: item.path.clone(),
+        title,
+        artist,
+        album: album.unwrap_or_else(|| "WebDAV".to_string()),
+        album_artist: None,
+        track_number: metadata.track_number,
+        year: None,
+        genre: None,
+        duration,
+        cover,
+        source: TrackSource::WebDAV { config_id: config.id.clone() },
+        gain_db: 0.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{wav_bytes, MockWebDAV};
+    use crate::WebDAVAuth;
+    use std::time::Duration;
+
+    async fn scan(scanner: CloudScanner) -> (Vec<Track>, Vec<String>, Option<usize>) {
+        let mut events = scanner.take_events().unwrap();
+        let (mut tracks, mut failed, mut found) = (Vec::new(), Vec::new(), None);
+        while let Some(event) = events.recv().await {
+            match event {
+                CloudScanEvent::Found(total) => found = Some(total),
+                CloudScanEvent::Tracks { tracks: ready, .. } => tracks.extend(ready),
+                CloudScanEvent::Failed { path, .. } => failed.push(path),
+                CloudScanEvent::Error(e) => panic!("listing failed: {}", e),
+                CloudScanEvent::Finished => break,
+            }
+        }
+        (tracks, failed, found)
+    }
+
+    #[tokio::test]
+    async fn test_scans_the_whole_server() {
+        let server = MockWebDAV::start(
+            &[
+                ("music/A/01 One.wav", wav_bytes(1000, 8000)),
+                ("music/A/B/02 Two.wav", wav_bytes(2000, 8000)),
+                ("music/C/cover.jpg", vec![0xFF, 0xD8]),
+                ("music/C/03 Three.wav", wav_bytes(500, 8000)),
+                ("music/Known.wav", wav_bytes(500, 8000)),
+            ],
+            Some(("alice", "secret")),
+        );
+        let config = WebDAVConfig {
+            id: "nas".to_string(),
+            name: "NAS".to_string(),
+            url: server.url("music"),
+            username: "alice".to_string(),
+            encrypted_password: String::new(),
+            enabled: true,
+            password: None,
+            synced_folders: Vec::new(),
+            download_limit_kbps: 0,
+            auth: WebDAVAuth::Basic,
+        };
+        let known: HashSet<String> = ["Known.wav".to_string()].into();
+
+        let (tracks, failed, found) = scan(CloudScanner::start(config.clone(), Some(Credentials::basic("alice", "secret")), known.clone())).await;
+        assert_eq!(found, Some(3));
+        assert!(failed.is_empty());
+        let mut titles: Vec<(&str, &str, Duration)> =
+            tracks.iter().map(|t| (t.path.as_str(), t.title.as_str(), t.duration)).collect();
+        titles.sort();
+        assert_eq!(
+            titles,
+            vec![
+                ("A/01%20One.wav", "01 One", Duration::from_secs(1)),
+                ("A/B/02%20Two.wav", "02 Two", Duration::from_secs(2)),
+                ("C/03%20Three.wav", "03 Three", Duration::from_millis(500)),
+            ]
+        );
+        assert!(tracks.iter().all(|t| t.source == TrackSource::WebDAV { config_id: "nas".to_string() }));
+        // The whole tree came back from one PROPFIND
+        assert_eq!(server.requests().iter().filter(|r| r.starts_with("PROPFIND")).count(), 1);
+
+        // A server that won't list its whole tree at once is walked a folder at a time
+        server.refuse_infinite_depth();
+        let (tracks, _, found) = scan(CloudScanner::start(config, Some(Credentials::basic("alice", "secret")), known)).await;
+        assert_eq!((found, tracks.len()), (Some(3), 3));
+        // The refused one, then the root, A, A/B and C
+        assert_eq!(server.requests().iter().filter(|r| r.starts_with("PROPFIND")).count(), 6);
+    }
+}
@@ -0,0 +1,215 @@
+// Cover art kept on disk under the config dir, one file per distinct image named
+// by its SHA-256. Tracks only carry that name, so an album's worth of tracks
+// shares one copy and playlists stay small. Every user profile shares the cache.
+// Art embedded in tagged files is named by the album instead and shrunk to a
+// thumbnail, so the rest of an album's files never need their pictures decoded.
+// Lists and grids show smaller copies still, made on first use and kept under
+// `small`, so only the Now Playing card ever carries a full image.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Longest side of the thumbnails kept for embedded art
+const THUMBNAIL_SIZE: u32 = 512;
+// Longest side of the copies shown in lists and grids
+const SMALL_SIZE: u32 = 128;
+
+static COVER_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    let dir = crate::base_config_dir().ok()?.join("covers");
+    match std::fs::create_dir_all(&dir) {
+        Ok(()) => Some(dir),
+        Err(e) => {
+            eprintln!("[Cover] 无法创建封面缓存目录 {}: {}", dir.display(), e);
+            None
+        }
+    }
+});
+
+// Small copies already encoded this session, by id; None when there's no image
+static SMALL_URLS: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(Default::default);
+
+/// Put image data in the cache and return the id tracks refer to it by.
+pub fn store(data: &[u8]) -> Option<String> {
+    store_in(COVER_DIR.as_ref()?, data)
+}
+
+/// The album's cover, calling `picture` for the art only when the album has none
+/// cached yet. Returns the id tracks refer to it by.
+pub fn store_album(artist: &str, album: &str, picture: impl FnOnce() -> Option<Vec<u8>>) -> Option<String> {
+    store_album_in(COVER_DIR.as_ref()?, artist, album, picture)
+}
+
+/// The image stored under `id`, if it's still there.
+pub fn load(id: &str) -> Option<Vec<u8>> {
+    load_from(COVER_DIR.as_ref()?, id)
+}
+
+/// A small copy of the image stored under `id` as a data URL, for lists and
+/// grids. Each is shrunk and encoded once, however often it's shown.
+pub fn small_data_url(id: &str) -> Option<String> {
+    if let Some(url) = SMALL_URLS.lock().unwrap().get(id) {
+        return url.clone();
+    }
+    let url = load_small_from(COVER_DIR.as_ref()?, id).map(|data| data_url(&data));
+    SMALL_URLS.lock().unwrap().insert(id.to_string(), url.clone());
+    url
+}
+
+pub fn data_url(data: &[u8]) -> String {
+    format!("data:image/jpeg;base64,{}", STANDARD.encode(data))
+}
+
+fn cover_id(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Tag spellings differ in case and stray spaces between an album's files
+fn album_id(artist: &str, album: &str) -> String {
+    let key = format!("album\0{}\0{}", artist.trim().to_lowercase(), album.trim().to_lowercase());
+    cover_id(key.as_bytes())
+}
+
+fn store_in(dir: &Path, data: &[u8]) -> Option<String> {
+    if data.is_empty() {
+        return None;
+    }
+    let id = cover_id(data);
+    if !dir.join(&id).exists() {
+        write_in(dir, &id, data)?;
+    }
+    Some(id)
+}
+
+fn store_album_in(dir: &Path, artist: &str, album: &str, picture: impl FnOnce() -> Option<Vec<u8>>) -> Option<String> {
+    let id = album_id(artist, album);
+    if !dir.join(&id).exists() {
+        let data = picture().filter(|data| !data.is_empty())?;
+        write_in(dir, &id, &thumbnail(&data, THUMBNAIL_SIZE))?;
+    }
+    Some(id)
+}
+
+fn write_in(dir: &Path, id: &str, data: &[u8]) -> Option<()> {
+    // Written aside and renamed, so a reader never sees half an image
+    let partial = dir.join(format!("{}.part", id));
+    if let Err(e) = std::fs::write(&partial, data).and_then(|()| std::fs::rename(&partial, dir.join(id))) {
+        eprintln!("[Cover] 保存封面失败: {}", e);
+        return None;
+    }
+    Some(())
+}
+
+// Big scans get scaled down to a JPEG; small or undecodable images are kept as they are
+fn thumbnail(data: &[u8], size: u32) -> Vec<u8> {
+    let Ok(image) = image::load_from_memory(data) else {
+        return data.to_vec();
+    };
+    if image.width() <= size && image.height() <= size {
+        return data.to_vec();
+    }
+    let mut encoded = Cursor::new(Vec::new());
+    match image
+        .thumbnail(size, size)
+        .to_rgb8()
+        .write_to(&mut encoded, image::ImageFormat::Jpeg)
+    {
+        Ok(()) => encoded.into_inner(),
+        Err(e) => {
+            eprintln!("[Cover] 缩小封面失败: {}", e);
+            data.to_vec()
+        }
+    }
+}
+
+fn load_from(dir: &Path, id: &str) -> Option<Vec<u8>> {
+    // Ids are hex digests; anything else isn't a file of ours
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    std::fs::read(dir.join(id)).ok()
+}
+
+fn load_small_from(dir: &Path, id: &str) -> Option<Vec<u8>> {
+    let small_dir = dir.join("small");
+    if let Some(data) = load_from(&small_dir, id) {
+        return Some(data);
+    }
+    let small = thumbnail(&load_from(dir, id)?, SMALL_SIZE);
+    match std::fs::create_dir_all(&small_dir) {
+        Ok(()) => {
+            write_in(&small_dir, id, &small);
+        }
+        Err(e) => eprintln!("[Cover] 无法创建缩略图目录 {}: {}", small_dir.display(), e),
+    }
+    Some(small)
+}
+
+/// Reads a track's `cover`, moving the raw bytes older playlists stored inline
+/// into the cache.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredCover {
+        Cached(String),
+        Inline(Vec<u8>),
+    }
+
+    Ok(match Option::<StoredCover>::deserialize(deserializer)? {
+        Some(StoredCover::Cached(id)) => Some(id),
+        Some(StoredCover::Inline(data)) => store(&data),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stores_each_image_once() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_covers_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let id = store_in(&dir, b"\xFF\xD8\xFFfake jpeg").unwrap();
+        assert_eq!(id.len(), 64);
+        assert_eq!(store_in(&dir, b"\xFF\xD8\xFFfake jpeg"), Some(id.clone()));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(load_from(&dir, &id).as_deref(), Some(&b"\xFF\xD8\xFFfake jpeg"[..]));
+
+        assert_eq!(store_in(&dir, b""), None);
+        assert_eq!(load_from(&dir, "../secrets"), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_album_art_is_shrunk_and_extracted_once() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_covers_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(1200, 600).write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let id = store_album_in(&dir, "Artist", "Album", || Some(png.into_inner())).unwrap();
+        let stored = image::load_from_memory(&load_from(&dir, &id).unwrap()).unwrap();
+        assert_eq!((stored.width(), stored.height()), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2));
+
+        // The rest of the album reuses it without handing over a picture
+        let again = store_album_in(&dir, " artist", "ALBUM ", || panic!("album art extracted twice"));
+        assert_eq!(again, Some(id));
+        assert_eq!(store_album_in(&dir, "Artist", "Other Album", || None), None);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Lists get a smaller copy still, made once and read back after that
+        let small = image::load_from_memory(&load_small_from(&dir, &id).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (SMALL_SIZE, SMALL_SIZE / 2));
+        std::fs::remove_file(dir.join(&id)).unwrap();
+        assert!(load_small_from(&dir, &id).is_some());
+        assert_eq!(load_small_from(&dir, "abc"), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
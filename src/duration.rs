@@ -0,0 +1,260 @@
+// Track length from the headers at the start of a file, without decoding any
+// audio: an MP3's Xing / Info or VBRI frame (else its bitrate and size), FLAC's
+// STREAMINFO block, or the movie header (mvhd) of an MP4 / M4A as mp4_tags
+// reads it. Used where opening a decoder per file would be too slow, or where
+// symphonia couldn't size the stream.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+// How far past the ID3 tag to look for the first MPEG frame
+const MAX_SYNC_SEARCH: u64 = 64 * 1024;
+
+pub fn from_path(path: &Path) -> Option<Duration> {
+    read(&mut BufReader::new(File::open(path).ok()?))
+}
+
+/// The length `reader`'s headers give, for a whole file held in memory too.
+pub fn read<R: Read + Seek>(reader: &mut R) -> Option<Duration> {
+    let len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).ok()?;
+    let duration = if &magic[..4] == b"fLaC" {
+        flac(reader)
+    } else if &magic[4..8] == b"ftyp" {
+        crate::mp4_tags::read_duration(reader)
+    } else {
+        mp3(reader, len)
+    };
+    duration.filter(|duration| !duration.is_zero())
+}
+
+// STREAMINFO is always the first metadata block: after the block sizes come 20
+// bits of sample rate, 3 of channels, 5 of sample size and 36 of total samples
+fn flac<R: Read + Seek>(reader: &mut R) -> Option<Duration> {
+    let mut block = [0u8; 4 + 18];
+    reader.seek(SeekFrom::Start(4)).ok()?;
+    reader.read_exact(&mut block).ok()?;
+    if block[0] & 0x7f != 0 {
+        return None;
+    }
+    let packed = u64::from_be_bytes(block[14..22].try_into().unwrap());
+    let sample_rate = packed >> 44;
+    let samples = packed & 0xf_ffff_ffff;
+    (sample_rate > 0).then(|| Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FrameHeader {
+    mpeg1: bool,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(bytes: [u8; 4]) -> Option<Self> {
+        if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = (bytes[1] >> 3) & 3;
+        let layer = 4 - ((bytes[1] >> 1) & 3);
+        let bitrate_index = usize::from(bytes[2] >> 4);
+        let rate_index = usize::from((bytes[2] >> 2) & 3);
+        if version == 1 || layer == 4 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        const KBPS: [[u16; 15]; 5] = [
+            [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+            [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+            [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+            [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        ];
+        let table = match (mpeg1, layer) {
+            (true, layer) => usize::from(layer - 1),
+            (false, 1) => 3,
+            (false, _) => 4,
+        };
+        // MPEG 2 halves the MPEG 1 rates and MPEG 2.5 quarters them
+        let sample_rate = [44_100, 48_000, 32_000][rate_index] >> (3 - version.max(1)).min(2);
+        Some(FrameHeader {
+            mpeg1,
+            layer,
+            bitrate: u32::from(KBPS[table][bitrate_index]) * 1000,
+            sample_rate,
+            padding: bytes[2] & 0x02 != 0,
+            mono: bytes[3] >> 6 == 3,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        match (self.layer, self.mpeg1) {
+            (1, _) => 384,
+            (3, false) => 576,
+            _ => 1152,
+        }
+    }
+
+    fn frame_len(&self) -> u64 {
+        let padding = u64::from(self.padding);
+        if self.layer == 1 {
+            (12 * u64::from(self.bitrate) / u64::from(self.sample_rate) + padding) * 4
+        } else {
+            u64::from(self.samples()) / 8 * u64::from(self.bitrate) / u64::from(self.sample_rate) + padding
+        }
+    }
+
+    // Where a Xing / Info tag starts, after the side information
+    fn xing_offset(&self) -> usize {
+        4 + match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        }
+    }
+}
+
+fn mp3<R: Read + Seek>(reader: &mut R, len: u64) -> Option<Duration> {
+    // Skip an ID3v2 tag: "ID3", version, flags, then a syncsafe size
+    let mut id3 = [0u8; 10];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut id3).ok()?;
+    let mut start = 0;
+    if &id3[..3] == b"ID3" {
+        let size = id3[6..10].iter().fold(0u64, |size, byte| (size << 7) | u64::from(byte & 0x7f));
+        let footer = if id3[5] & 0x10 != 0 { 10 } else { 0 };
+        start = 10 + size + footer;
+    }
+
+    let (offset, header) = find_frame(reader, start, len)?;
+    let mut frame = vec![0u8; header.frame_len().min(len - offset) as usize];
+    reader.seek(SeekFrom::Start(offset)).ok()?;
+    reader.read_exact(&mut frame).ok()?;
+    let frames_in = |at: usize, flag_check: bool| -> Option<u32> {
+        let field = |at: usize| frame.get(at..at + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+        (!flag_check || field(at)? & 1 != 0).then(|| field(at + 4)).flatten()
+    };
+    let xing = header.xing_offset();
+    let vbr_frames = match frame.get(xing..xing + 4) {
+        Some(tag) if tag == b"Xing" || tag == b"Info" => frames_in(xing + 4, true),
+        // VBRI sits 32 bytes after the header whatever the side information's size
+        _ if frame.get(36..40) == Some(b"VBRI".as_slice()) => frames_in(36 + 10, false),
+        _ => None,
+    };
+    if let Some(frames) = vbr_frames {
+        let samples = u64::from(frames) * u64::from(header.samples());
+        return Some(Duration::from_secs_f64(samples as f64 / f64::from(header.sample_rate)));
+    }
+
+    // Constant bitrate: the audio's size over its rate, less an ID3v1 tag at the end
+    let mut tail = [0u8; 3];
+    let has_id3v1 = len >= 128
+        && reader.seek(SeekFrom::Start(len - 128)).is_ok()
+        && reader.read_exact(&mut tail).is_ok()
+        && &tail == b"TAG";
+    let audio_bytes = len.saturating_sub(offset).saturating_sub(if has_id3v1 { 128 } else { 0 });
+    Some(Duration::from_secs_f64(audio_bytes as f64 * 8.0 / f64::from(header.bitrate)))
+}
+
+// The first frame header from `start` that's followed by another frame (or the
+// end of the file), so stray 0xFF bytes in leftover tag data aren't taken for one
+fn find_frame<R: Read + Seek>(reader: &mut R, start: u64, len: u64) -> Option<(u64, FrameHeader)> {
+    let end = len.min(start + MAX_SYNC_SEARCH);
+    let mut window = vec![0u8; end.saturating_sub(start) as usize];
+    reader.seek(SeekFrom::Start(start)).ok()?;
+    reader.read_exact(&mut window).ok()?;
+    let header_at = |reader: &mut R, at: u64| -> Option<FrameHeader> {
+        let mut bytes = [0u8; 4];
+        reader.seek(SeekFrom::Start(at)).ok()?;
+        reader.read_exact(&mut bytes).ok()?;
+        FrameHeader::parse(bytes)
+    };
+    for i in 0..window.len().saturating_sub(3) {
+        let Some(header) = FrameHeader::parse(window[i..i + 4].try_into().unwrap()) else {
+            continue;
+        };
+        let offset = start + i as u64;
+        let next = offset + header.frame_len();
+        if next + 4 > len || header_at(reader, next).is_some() {
+            return Some((offset, header));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // MPEG 1 Layer III, 128 kbps, 44.1 kHz, stereo: 417-byte frames
+    const HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x00];
+
+    fn frames(count: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..count {
+            let mut frame = vec![0u8; 417];
+            frame[..4].copy_from_slice(&HEADER);
+            data.extend(frame);
+        }
+        data
+    }
+
+    fn secs(data: Vec<u8>) -> Option<f64> {
+        read(&mut Cursor::new(data)).map(|duration| duration.as_secs_f64())
+    }
+
+    #[test]
+    fn test_mp3_durations() {
+        // Constant bitrate, behind an ID3v2 tag and ahead of an ID3v1 one
+        let mut cbr = b"ID3\x04\x00\x00\x00\x00\x00\x05hello".to_vec();
+        cbr.extend(frames(100));
+        cbr.extend(b"TAG".iter().chain([0u8; 125].iter()));
+        let expected = 100.0 * 417.0 * 8.0 / 128_000.0;
+        assert!((secs(cbr).unwrap() - expected).abs() < 0.001);
+
+        // A Xing frame counting 1000 frames
+        let mut vbr = frames(3);
+        vbr[36..40].copy_from_slice(b"Xing");
+        vbr[40..44].copy_from_slice(&1u32.to_be_bytes());
+        vbr[44..48].copy_from_slice(&1000u32.to_be_bytes());
+        assert!((secs(vbr).unwrap() - 1000.0 * 1152.0 / 44_100.0).abs() < 0.001);
+
+        assert_eq!(secs(b"not audio at all".to_vec()), None);
+    }
+
+    #[test]
+    fn test_flac_and_mp4_durations() {
+        // 44.1 kHz, stereo, 16-bit, 441000 samples
+        let mut flac = b"fLaC\x00\x00\x00\x22".to_vec();
+        flac.extend([0u8; 10]);
+        let packed: u64 = (44_100 << 44) | (1 << 41) | (15 << 36) | 441_000;
+        flac.extend(packed.to_be_bytes());
+        flac.extend([0u8; 16]);
+        assert_eq!(secs(flac), Some(10.0));
+
+        let atom = |name: &[u8; 4], body: &[u8]| [&(8 + body.len() as u32).to_be_bytes()[..], name.as_slice(), body].concat();
+        let mut mvhd = vec![0u8; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&215_500u32.to_be_bytes());
+        let mp4 = [
+            atom(b"ftyp", b"M4A \x00\x00\x00\x00"),
+            atom(b"free", &[0u8; 20]),
+            atom(b"moov", &[atom(b"udta", &[0u8; 12]), atom(b"mvhd", &mvhd)].concat()),
+            atom(b"mdat", &[0u8; 64]),
+        ]
+        .concat();
+        assert_eq!(secs(mp4), Some(215.5));
+
+        // A 64-bit atom size big enough to wrap the position around
+        let wrapping = [atom(b"ftyp", b"M4A \x00\x00\x00\x00"), [&1u32.to_be_bytes()[..], b"free", &u64::MAX.to_be_bytes()].concat()].concat();
+        assert_eq!(secs(wrapping), None);
+    }
+}
@@ -0,0 +1,201 @@
+// Watches the folders of imported local tracks for lyric and cover files that
+// appear or change next to them, so lyrics or art downloaded while the app is
+// open show up without re-importing or restarting the track. Playlists can also
+// watch whole music folders, following the audio files added, renamed or
+// deleted anywhere beneath them.
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// The names the importer looks for when a track has no embedded art
+pub const COVER_FILENAMES: [&str; 6] = ["cover.jpg", "cover.jpeg", "cover.png", "folder.jpg", "folder.jpeg", "folder.png"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SidecarEvent {
+    // A .lrc or .txt file was written
+    Lyrics(PathBuf),
+    // The folder whose cover image was added, replaced or removed
+    Cover(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FolderEvent {
+    // An audio file was added or written, or a folder that may hold some appeared
+    Changed(PathBuf),
+    // Something went away; any tracks at or beneath it are gone
+    Removed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+// A notify watcher and the folders it's on
+struct Watched {
+    watcher: RecommendedWatcher,
+    dirs: HashSet<PathBuf>,
+    mode: RecursiveMode,
+}
+
+impl Watched {
+    fn start(mode: RecursiveMode, on_event: impl Fn(Event) + Send + 'static) -> notify::Result<Self> {
+        let watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_) | EventKind::Other | EventKind::Any) => on_event(event),
+            Ok(_) => {}
+            Err(e) => eprintln!("[Watcher] 文件监视出错: {}", e),
+        })?;
+        Ok(Watched { watcher, dirs: HashSet::new(), mode })
+    }
+
+    fn set_dirs(&mut self, dirs: HashSet<PathBuf>) {
+        for dir in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            if let Err(e) = self.watcher.watch(dir, self.mode) {
+                eprintln!("[Watcher] 无法监视目录 {}: {}", dir.display(), e);
+            }
+        }
+        self.dirs = dirs;
+    }
+}
+
+pub struct SidecarWatcher {
+    watched: Mutex<Watched>,
+    event_receiver: Mutex<Option<UnboundedReceiver<SidecarEvent>>>,
+}
+
+impl SidecarWatcher {
+    pub fn start() -> notify::Result<Self> {
+        let (events, event_receiver) = unbounded_channel();
+        let watched = Watched::start(RecursiveMode::NonRecursive, move |event| {
+            for path in &event.paths {
+                if let Some(sidecar) = classify(path) {
+                    let _ = events.send(sidecar);
+                }
+            }
+        })?;
+
+        Ok(SidecarWatcher {
+            watched: Mutex::new(watched),
+            event_receiver: Mutex::new(Some(event_receiver)),
+        })
+    }
+
+    /// Watch exactly these folders, starting on new ones and dropping the rest.
+    pub fn watch_dirs(&self, dirs: HashSet<PathBuf>) {
+        self.watched.lock().unwrap().set_dirs(dirs);
+    }
+
+    // Lyric and cover changes as they happen; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<SidecarEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+pub struct FolderWatcher {
+    watched: Mutex<Watched>,
+    event_receiver: Mutex<Option<UnboundedReceiver<FolderEvent>>>,
+}
+
+impl FolderWatcher {
+    pub fn start() -> notify::Result<Self> {
+        let (events, event_receiver) = unbounded_channel();
+        let watched = Watched::start(RecursiveMode::Recursive, move |event| {
+            for folder_event in classify_folder_event(&event) {
+                let _ = events.send(folder_event);
+            }
+        })?;
+
+        Ok(FolderWatcher {
+            watched: Mutex::new(watched),
+            event_receiver: Mutex::new(Some(event_receiver)),
+        })
+    }
+
+    /// Watch exactly these folders and everything beneath them.
+    pub fn watch_dirs(&self, dirs: HashSet<PathBuf>) {
+        self.watched.lock().unwrap().set_dirs(dirs);
+    }
+
+    // Library changes as they happen; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<FolderEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+fn classify(path: &Path) -> Option<SidecarEvent> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if COVER_FILENAMES.contains(&name.as_str()) {
+        return Some(SidecarEvent::Cover(path.parent()?.to_path_buf()));
+    }
+    (name.ends_with(".lrc") || name.ends_with(".txt")).then(|| SidecarEvent::Lyrics(path.to_path_buf()))
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| crate::is_audio_file(&name.to_string_lossy()))
+}
+
+// Folders can't be told apart once they're gone, so removals are passed on
+// whatever they were; additions only for audio files and folders
+fn classify_folder_event(event: &Event) -> Vec<FolderEvent> {
+    let appeared = |path: &PathBuf| (is_audio(path) || path.is_dir()).then(|| FolderEvent::Changed(path.clone()));
+    match (&event.kind, event.paths.as_slice()) {
+        (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+            if is_audio(to) || to.is_dir() {
+                vec![FolderEvent::Renamed { from: from.clone(), to: to.clone() }]
+            } else {
+                vec![FolderEvent::Removed(from.clone())]
+            }
+        }
+        (EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_), paths) => {
+            paths.iter().map(|path| FolderEvent::Removed(path.clone())).collect()
+        }
+        (EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)), paths) => paths.iter().filter_map(appeared).collect(),
+        (EventKind::Modify(_), paths) => {
+            paths.iter().filter(|path| is_audio(path)).map(|path| FolderEvent::Changed(path.clone())).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    #[test]
+    fn test_classifies_sidecar_files() {
+        let dir = Path::new("/music/album");
+        assert_eq!(classify(&dir.join("Folder.JPG")), Some(SidecarEvent::Cover(dir.to_path_buf())));
+        assert_eq!(classify(&dir.join("01 Song.lrc")), Some(SidecarEvent::Lyrics(dir.join("01 Song.lrc"))));
+        assert_eq!(classify(&dir.join("01 Song.flac")), None);
+        assert_eq!(classify(&dir.join("back.jpg")), None);
+    }
+
+    #[test]
+    fn test_classifies_library_changes() {
+        let song = PathBuf::from("/music/album/01 Song.flac");
+        let renamed = PathBuf::from("/music/album/01 Song (Live).flac");
+        let event = |kind: EventKind, paths: &[&PathBuf]| {
+            classify_folder_event(&paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.to_path_buf())))
+        };
+
+        assert_eq!(event(EventKind::Create(CreateKind::File), &[&song]), [FolderEvent::Changed(song.clone())]);
+        assert_eq!(event(EventKind::Create(CreateKind::File), &[&PathBuf::from("/music/album/notes.txt")]), []);
+        assert_eq!(
+            event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &[&song]),
+            [FolderEvent::Changed(song.clone())]
+        );
+        assert_eq!(event(EventKind::Remove(RemoveKind::Any), &[&song]), [FolderEvent::Removed(song.clone())]);
+        assert_eq!(
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&song, &renamed]),
+            [FolderEvent::Renamed { from: song.clone(), to: renamed.clone() }]
+        );
+        assert_eq!(
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&song, &PathBuf::from("/music/album/song.bak")]),
+            [FolderEvent::Removed(song.clone())]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Display formatting for durations, sizes, counts and dates. Number separators
+// follow the user's locale (LC_ALL / LC_NUMERIC / LANG); wording stays English
+// like the rest of the UI.
+
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub group: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat { decimal: '.', group: ',' }
+    }
+}
+
+impl NumberFormat {
+    /// Separators for a POSIX locale name such as `de_DE.UTF-8` or `fr_FR`.
+    pub fn for_locale(locale: &str) -> Self {
+        let language = locale
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr" | "el" => NumberFormat { decimal: ',', group: '.' },
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" => {
+                NumberFormat { decimal: ',', group: '\u{a0}' }
+            }
+            _ => NumberFormat::default(),
+        }
+    }
+
+    /// The process locale, read once.
+    pub fn current() -> Self {
+        static CURRENT: OnceLock<NumberFormat> = OnceLock::new();
+        *CURRENT.get_or_init(|| {
+            let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|key| std::env::var(key).ok())
+                .find(|value| !value.is_empty())
+                .unwrap_or_default();
+            NumberFormat::for_locale(&locale)
+        })
+    }
+
+    /// 1234567 -> "1,234,567"
+    pub fn integer(&self, value: u64) -> String {
+        let digits = value.to_string();
+        let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, ch) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                result.push(self.group);
+            }
+            result.push(ch);
+        }
+        result
+    }
+
+    /// One decimal place: 1234.56 -> "1,234.6"
+    pub fn decimal(&self, value: f64) -> String {
+        let tenths = (value * 10.0).round().max(0.0) as u64;
+        format!("{}{}{}", self.integer(tenths / 10), self.decimal, tenths % 10)
+    }
+}
+
+/// "3:07", or "1:02:03" from an hour up.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, mins, secs)
+    } else {
+        format!("{}:{:02}", mins, secs)
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    format_bytes_with(bytes, NumberFormat::current())
+}
+
+/// "512 B", "1.5 KB", "3.2 MB", "1,024.0 GB" (binary units)
+pub fn format_bytes_with(bytes: u64, format: NumberFormat) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", format.integer(bytes), UNITS[0])
+    } else {
+        format!("{} {}", format.decimal(size), UNITS[unit])
+    }
+}
+
+/// "1 file", "1,200 files"
+pub fn format_count(count: usize, singular: &str, plural: &str) -> String {
+    let noun = if count == 1 { singular } else { plural };
+    format!("{} {}", NumberFormat::current().integer(count as u64), noun)
+}
+
+pub fn format_time_ago(time: SystemTime) -> String {
+    format_time_ago_at(time, SystemTime::now())
+}
+
+/// "just now", "1 minute ago", "2 days ago", "3 years ago". Times in the future
+/// (clock skew between client and server) read as "just now".
+pub fn format_time_ago_at(time: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(time).map(|d| d.as_secs()).unwrap_or(0);
+    let (count, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3_599 => (secs / 60, "minute"),
+        3_600..=86_399 => (secs / 3_600, "hour"),
+        86_400..=604_799 => (secs / 86_400, "day"),
+        604_800..=2_591_999 => (secs / 604_800, "week"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+/// Relative time for a WebDAV `getlastmodified` value; falls back to the raw
+/// text if the server sent something other than an HTTP date.
+pub fn format_modified(modified: &str) -> String {
+    match parse_http_date(modified) {
+        Some(time) => format_time_ago(time),
+        None => modified.trim().to_string(),
+    }
+}
+
+/// RFC 1123 dates as used by HTTP and WebDAV: "Mon, 01 Jan 2024 00:00:00 GMT".
+/// The weekday is optional and not checked.
+pub fn parse_http_date(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    let text = text.split_once(',').map_or(text, |(_, rest)| rest);
+    let mut parts = text.split_whitespace();
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?.to_ascii_lowercase();
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|p| p.parse::<u64>().ok());
+    let (hour, min, sec) = (clock.next()??, clock.next()??, clock.next()??);
+    if !matches!(parts.next(), None | Some("GMT") | Some("UTC") | Some("+0000")) {
+        return None;
+    }
+
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    if !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 || year < 1970 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86_400 + hour * 3_600 + min * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration_and_bytes() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0:00");
+        assert_eq!(format_duration(Duration::from_secs(187)), "3:07");
+        assert_eq!(format_duration(Duration::from_secs(3_723)), "1:02:03");
+        // Playlist and library totals run past a day
+        assert_eq!(format_duration(Duration::from_secs(360_130)), "100:02:10");
+
+        let en = NumberFormat::for_locale("en_US.UTF-8");
+        let de = NumberFormat::for_locale("de_DE.UTF-8");
+        let fr = NumberFormat::for_locale("fr_FR");
+        assert_eq!(format_bytes_with(512, en), "512 B");
+        assert_eq!(format_bytes_with(1_536, en), "1.5 KB");
+        assert_eq!(format_bytes_with(1_536, de), "1,5 KB");
+        assert_eq!(format_bytes_with(5 * 1024 * 1024 * 1024 * 1024, en), "5,120.0 GB");
+        assert_eq!(format_bytes_with(5 * 1024 * 1024 * 1024 * 1024, de), "5.120,0 GB");
+        assert_eq!(fr.integer(1_234_567), "1\u{a0}234\u{a0}567");
+        assert_eq!(NumberFormat::for_locale(""), NumberFormat::default());
+    }
+
+    #[test]
+    fn test_format_time_ago_pluralizes() {
+        let now = UNIX_EPOCH + Duration::from_secs(100 * 86_400);
+        let ago = |secs: u64| format_time_ago_at(now - Duration::from_secs(secs), now);
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(150), "2 minutes ago");
+        assert_eq!(ago(3_600), "1 hour ago");
+        assert_eq!(ago(2 * 86_400 + 10), "2 days ago");
+        assert_eq!(ago(15 * 86_400), "2 weeks ago");
+        assert_eq!(ago(95 * 86_400), "3 months ago");
+        assert_eq!(format_time_ago_at(now + Duration::from_secs(30), now), "just now");
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        let parsed = parse_http_date("Mon, 01 Jan 2024 00:00:00 GMT").unwrap();
+        assert_eq!(parsed.duration_since(UNIX_EPOCH).unwrap().as_secs(), 1_704_067_200);
+        let parsed = parse_http_date("Thu, 29 Feb 2024 13:45:10 GMT").unwrap();
+        assert_eq!(parsed.duration_since(UNIX_EPOCH).unwrap().as_secs(), 1_709_214_310);
+        assert_eq!(parse_http_date("2024-01-01T00:00:00Z"), None);
+        assert_eq!(parse_http_date("Mon, 01 Foo 2024 00:00:00 GMT"), None);
+        assert_eq!(format_modified("yesterday-ish"), "yesterday-ish");
+    }
+}
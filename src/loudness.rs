@@ -0,0 +1,245 @@
+// EBU R128 loudness scanning. A background thread decodes tracks and measures
+// their integrated loudness (ITU-R BS.1770: K-weighted, gated), so normalization
+// can level tracks that carry no loudness tags of their own.
+
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// Normalization brings every track to this: ReplayGain 2.0's reference level.
+// R128's -23 is meant for broadcast and would leave most music far quieter.
+pub const TARGET_LUFS: f64 = -18.0;
+// Gating blocks are 400ms long and start every 100ms, so each is four steps
+const STEPS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+pub enum LoudnessEvent {
+    Measured { path: String, lufs: f64 },
+    Failed { path: String },
+    Finished,
+}
+
+// One second-order section, transposed direct form II
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// BS.1770's K-weighting (a high shelf modelling the head, then a high-pass),
+// worked out for any sample rate rather than only the 48kHz coefficients
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+/// Integrated loudness of interleaved samples, fed in any number of pieces.
+pub struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    step_frames: usize,
+    // The 100ms step being filled: weighted energy so far, and frames in it
+    step_energy: f64,
+    frames_in_step: usize,
+    channel: usize,
+    // Mean weighted energy of every finished step
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        // 5.1 in the usual order: the LFE doesn't count and the surrounds count extra
+        let weights = (0..channels)
+            .map(|i| match (channels, i) {
+                (6, 3) => 0.0,
+                (6, 4 | 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        LoudnessMeter {
+            filters: vec![k_weighting(sample_rate as f64); channels],
+            weights,
+            step_frames: (sample_rate as usize / 10).max(1),
+            step_energy: 0.0,
+            frames_in_step: 0,
+            channel: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        let [shelf, high_pass] = &mut self.filters[self.channel];
+        let weighted = high_pass.process(shelf.process(sample as f64));
+        self.step_energy += self.weights[self.channel] * weighted * weighted;
+
+        self.channel += 1;
+        if self.channel == self.filters.len() {
+            self.channel = 0;
+            self.frames_in_step += 1;
+            if self.frames_in_step == self.step_frames {
+                self.steps.push(self.step_energy / self.step_frames as f64);
+                self.step_energy = 0.0;
+                self.frames_in_step = 0;
+            }
+        }
+    }
+
+    /// LUFS over everything pushed so far, or None if it's all silence or
+    /// shorter than one 400ms block.
+    pub fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(STEPS_PER_BLOCK)
+            .map(|steps| steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+            .filter(|energy| loudness(*energy) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        let threshold = loudness(mean(&blocks)?) - RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks.into_iter().filter(|energy| loudness(*energy) > threshold).collect();
+        Some(loudness(mean(&gated)?))
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Decode a whole file and measure it. None if `cancelled` turned true first.
+pub fn measure_file(path: &Path, cancelled: &dyn Fn() -> bool) -> Option<Result<f64, String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Some(Err(format!("无法打开文件: {}", e))),
+    };
+    let decoder = match Decoder::try_from(file) {
+        Ok(decoder) => decoder,
+        Err(e) => return Some(Err(format!("无法解码: {}", e))),
+    };
+    let mut meter = LoudnessMeter::new(decoder.channels(), decoder.sample_rate());
+    for (i, sample) in decoder.enumerate() {
+        if i % 65536 == 0 && cancelled() {
+            return None;
+        }
+        meter.push(sample);
+    }
+    Some(meter.integrated().ok_or_else(|| "没有可测量的声音".to_string()))
+}
+
+/// Measures a list of local files one after another on its own thread,
+/// reporting each as it's done. Dropping the scanner stops it.
+pub struct LoudnessScanner {
+    total: usize,
+    cancelled: Arc<AtomicBool>,
+    event_receiver: Mutex<Option<UnboundedReceiver<LoudnessEvent>>>,
+}
+
+impl LoudnessScanner {
+    pub fn start(paths: Vec<String>) -> Self {
+        let total = paths.len();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (events, event_receiver) = unbounded_channel();
+
+        let stop = cancelled.clone();
+        std::thread::spawn(move || {
+            let is_cancelled = || stop.load(Ordering::SeqCst);
+            for path in paths {
+                let event = match measure_file(Path::new(&path), &is_cancelled) {
+                    None => return,
+                    Some(Ok(lufs)) => LoudnessEvent::Measured { path, lufs },
+                    Some(Err(error)) => {
+                        eprintln!("[Loudness] 测量失败 {}: {}", path, error);
+                        LoudnessEvent::Failed { path }
+                    }
+                };
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+            eprintln!("[Loudness] 响度扫描完成，共 {} 首", total);
+            let _ = events.send(LoudnessEvent::Finished);
+        });
+
+        LoudnessScanner { total, cancelled, event_receiver: Mutex::new(Some(event_receiver)) }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    // Every track's result, then Finished; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<LoudnessEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+impl Drop for LoudnessScanner {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(meter: &mut LoudnessMeter, amplitude: f32, seconds: usize) {
+        for i in 0..48000 * seconds {
+            let sample = amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin();
+            meter.push(sample);
+            meter.push(sample);
+        }
+    }
+
+    #[test]
+    fn test_measures_reference_tone() {
+        // EBU Tech 3341 case 1: a 1kHz sine at -23 dBFS in both channels is -23 LUFS
+        let mut meter = LoudnessMeter::new(2, 48000);
+        sine(&mut meter, 10f32.powf(-23.0 / 20.0), 10);
+        let lufs = meter.integrated().unwrap();
+        assert!((lufs + 23.0).abs() < 0.1, "{}", lufs);
+
+        // Silence and a much quieter passage are gated out
+        for _ in 0..48000 * 2 * 10 {
+            meter.push(0.0);
+        }
+        sine(&mut meter, 10f32.powf(-60.0 / 20.0), 10);
+        assert!((meter.integrated().unwrap() - lufs).abs() < 0.1);
+
+        assert_eq!(LoudnessMeter::new(2, 48000).integrated(), None);
+    }
+}
@@ -1,22 +1,52 @@
 mod player;
 mod playlist;
 mod metadata;
+mod metadata_cache;
 mod webdav;
+mod aiff_tags;
+mod archive;
+mod bandcamp;
+mod ape_tags;
+mod asf_tags;
+mod chapters;
+mod cloud_scanner;
+mod cover_cache;
 mod crypto;
+mod diagnostics;
+mod duration;
+mod file_watcher;
+mod format;
+mod loudness;
+mod mp4_tags;
+mod musicbrainz;
+mod organize;
+mod ogg_tags;
+mod party;
+mod profiles;
+mod resume;
+mod scanner;
+mod sleep_inhibit;
+mod throttle;
+mod webdav_sync;
+#[cfg(feature = "ytdlp")]
+mod ytdlp;
+#[cfg(test)]
+mod test_support;
 
 use dioxus::prelude::*;
-use player::{MusicPlayer, PlayerState};
-use playlist::Playlist;
+use player::{MusicPlayer, PlayerEvent, PlayerState, MAX_TRACK_GAIN_DB};
+use playlist::{ContentType, ContentTypeSettings, PlayBehavior, Playlist, SortKey, SortOrder};
+use format::{format_bytes, format_count, format_duration, format_modified, format_time_ago};
 use metadata::TrackMetadata;
 use std::time::Duration;
 use std::path::Path;
 use serde::{Deserialize, Serialize};
-use walkdir::WalkDir;
 use uuid::Uuid;
 use std::sync::{Arc, Mutex};
 use once_cell::sync::Lazy;
 
-static WEBDAV_COVER_CACHE: Lazy<std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>> =
+// Cover cache id of each WebDAV folder's cover, by folder URL
+static WEBDAV_COVER_CACHE: Lazy<std::sync::Mutex<std::collections::HashMap<String, String>>> =
     Lazy::new(|| std::sync::Mutex::new(std::collections::HashMap::new()));
 
 fn load_header_icon() -> Option<String> {
@@ -65,7 +95,52 @@ fn get_global_state() -> &'static GlobalPlayerState {
     GLOBAL_STATE.get_or_init(GlobalPlayerState::new)
 }
 
-const AUDIO_FORMATS: &[&str] = &["mp3", "wav", "flac", "ogg", "m4a"];
+const AUDIO_FORMATS: &[&str] = &["mp3", "wav", "flac", "ogg", "oga", "opus", "m4a", "wv", "ape", "aiff", "aif", "wma"];
+
+// Where a track comes from; drives playback routing and the badge shown in lists
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TrackSource {
+    #[default]
+    Local,
+    #[serde(rename = "webdav")]
+    WebDAV { config_id: String },
+    Radio,
+    Podcast,
+    // A YouTube / SoundCloud page, played through yt-dlp
+    Online,
+    // Streamed from a purchase in the user's Bandcamp collection
+    Bandcamp,
+}
+
+impl TrackSource {
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, TrackSource::Local)
+    }
+
+    pub fn label(&self, configs: &[WebDAVConfig]) -> String {
+        match self {
+            TrackSource::Local => "Local".to_string(),
+            TrackSource::WebDAV { config_id } => configs
+                .iter()
+                .find(|c| &c.id == config_id)
+                .map(|c| c.name.clone())
+                .unwrap_or_else(|| "WebDAV".to_string()),
+            TrackSource::Radio => "Radio".to_string(),
+            TrackSource::Podcast => "Podcast".to_string(),
+            TrackSource::Online => "Online".to_string(),
+            TrackSource::Bandcamp => "Bandcamp".to_string(),
+        }
+    }
+
+    pub fn badge_class(&self) -> &'static str {
+        match self {
+            TrackSource::Local => "badge badge-green",
+            TrackSource::WebDAV { .. } | TrackSource::Bandcamp => "badge badge-blue",
+            TrackSource::Radio | TrackSource::Podcast | TrackSource::Online => "badge badge-yellow",
+        }
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Track {
@@ -74,10 +149,138 @@ pub struct Track {
     pub title: String,
     pub artist: String,
     pub album: String,
+    // Only set when the file's tags say; entries saved before these were kept don't know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
     pub duration: Duration,
-    pub cover: Option<Vec<u8>>,
+    // Id of the artwork in the cover cache
+    #[serde(default, deserialize_with = "cover_cache::deserialize")]
+    pub cover: Option<String>,
+    #[serde(default)]
+    pub source: TrackSource,
+    // Played this much louder (or quieter, if negative) than the master volume
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+// How a track entered playback, kept so surprise plays can be traced
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+enum PlayReason {
+    PlaylistClick,
+    PlayButton,
+    SkipNext,
+    SkipPrevious,
+    AutoAdvance,
+    ShuffleAdvance,
+    // Next up in the play queue when the previous track ended
+    Queue,
+    CloudBrowser,
+    FolderTree,
+    RecentlyPlayed,
+    Favorites,
+    Search,
+}
+
+impl PlayReason {
+    fn describe(&self) -> &'static str {
+        match self {
+            PlayReason::PlaylistClick => "Selected in the playlist",
+            PlayReason::PlayButton => "Play button restarted the current track",
+            PlayReason::SkipNext => "Skipped forward with Next",
+            PlayReason::SkipPrevious => "Skipped back with Previous",
+            PlayReason::AutoAdvance => "Auto-advanced after the previous track ended",
+            PlayReason::ShuffleAdvance => "Shuffle picked it after the previous track ended",
+            PlayReason::Queue => "Next in the queue after the previous track ended",
+            PlayReason::CloudBrowser => "Opened from the cloud browser",
+            PlayReason::FolderTree => "Opened from the folder tree",
+            PlayReason::RecentlyPlayed => "Picked again from Recently Played",
+            PlayReason::Favorites => "Picked from Favorites",
+            PlayReason::Search => "Picked from the search results",
+        }
+    }
+}
+
+// Saved across sessions so shuffle can steer away from recent plays
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct PlayHistoryEntry {
+    track_id: String,
+    // Playlist entry ids differ between playlists, so shuffle matches on path
+    #[serde(default)]
+    path: String,
+    // None in entries logged before it was kept, which count by path alone
+    #[serde(default)]
+    source: Option<TrackSource>,
+    title: String,
+    artist: String,
+    reason: PlayReason,
+    played_at: std::time::SystemTime,
+}
+
+impl PlayHistoryEntry {
+    fn file_key(&self) -> String {
+        self.source.as_ref().map_or_else(|| self.path.clone(), |source| file_key(source, &self.path))
+    }
+}
+
+const PLAY_HISTORY_LIMIT: usize = 1000;
+
+// The sidebar's Recently Played list: each track once, at its latest play,
+// kept whole so it can play again even after leaving every playlist
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct RecentPlay {
+    track: TrackStub,
+    played_at: std::time::SystemTime,
+}
+
+const RECENTLY_PLAYED_LIMIT: usize = 100;
+
+// Built-in lists the sidebar shows above the user's playlists
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SpecialList {
+    RecentlyPlayed,
+    Favorites,
+}
+
+impl SpecialList {
+    fn title(self) -> &'static str {
+        match self {
+            SpecialList::RecentlyPlayed => "🕘 Recently Played",
+            SpecialList::Favorites => "♥ Favorites",
+        }
+    }
+}
+
+// One playlist's tracks matching the header search
+#[derive(Clone, Debug, PartialEq)]
+struct SearchGroup {
+    playlist_index: usize,
+    name: String,
+    tracks: Vec<TrackStub>,
 }
 
+// Rows shown per playlist in the search results; the rest are only counted
+const SEARCH_GROUP_LIMIT: usize = 50;
+
+// Beyond this the top-rated track all but always wins
+const MAX_SMART_SHUFFLE_EXPONENT: f64 = 5.0;
+
+// How close to the end of a track the next one starts downloading / decoding
+const PREFETCH_LEAD: Duration = Duration::from_secs(30);
+const MAX_CROSSFADE_SECS: u32 = 12;
+const MAX_READ_AHEAD_MB: u32 = 64;
+
+// The visualizer redraws about 30 times a second; bars keep this much of their
+// height each frame as they fall
+const VISUALIZER_FRAME_MS: u64 = 33;
+const VISUALIZER_FALLOFF: f32 = 0.85;
+const VISUALIZER_POINTS: usize = 128;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TrackStub {
     pub id: String,
@@ -85,8 +288,45 @@ pub struct TrackStub {
     pub title: String,
     pub artist: String,
     pub album: String,
+    // Only set when the file's tags say; entries saved before these were kept don't know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
     pub duration: Duration,
-    pub cover: Option<Vec<u8>>,
+    // Id of the artwork in the cover cache
+    #[serde(default, deserialize_with = "cover_cache::deserialize")]
+    pub cover: Option<String>,
+    // No default: saves from before every track had one get it from their path
+    // in playlist::schema, rather than all passing for local files
+    pub source: TrackSource,
+    // Played this much louder (or quieter, if negative) than the master volume
+    #[serde(default)]
+    pub gain_db: f32,
+    // When it first went into a playlist; entries saved before this was kept don't know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<std::time::SystemTime>,
+    // The local file as it was when its tags were read, so a rescan can skip it
+    // if it hasn't changed since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp: Option<FileStamp>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: std::time::SystemTime,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(FileStamp { size: metadata.len(), modified: metadata.modified().ok()? })
+    }
 }
 
 impl From<Track> for TrackStub {
@@ -97,12 +337,426 @@ impl From<Track> for TrackStub {
             title: track.title,
             artist: track.artist,
             album: track.album,
+            album_artist: track.album_artist,
+            track_number: track.track_number,
+            year: track.year,
+            genre: track.genre,
             duration: track.duration,
             cover: track.cover,
+            source: track.source,
+            gain_db: track.gain_db,
+            added_at: None,
+            stamp: None,
+        }
+    }
+}
+
+/// What tells a file apart from every other: its path, and for WebDAV tracks
+/// the server too, as their paths are relative to it. Ratings, favorites and
+/// play counts are kept by this; for everything but WebDAV it's the path itself.
+pub fn file_key(source: &TrackSource, path: &str) -> String {
+    match source {
+        TrackSource::WebDAV { config_id } => format!("webdav://{}/{}", config_id, path),
+        _ => path.to_string(),
+    }
+}
+
+impl TrackStub {
+    pub fn file_key(&self) -> String {
+        file_key(&self.source, &self.path)
+    }
+
+    /// The artist the track's album is filed under: the album artist where the
+    /// tags name one (Various Artists for compilations), else the track's own.
+    pub fn album_artist_or_artist(&self) -> &str {
+        self.album_artist.as_deref().filter(|artist| !artist.trim().is_empty()).unwrap_or(&self.artist)
+    }
+
+    /// Take the tags from a fresh read of the same file, keeping this entry's id,
+    /// gain and when it was added. Returns whether anything changed.
+    pub fn update_from(&mut self, scanned: &TrackStub) -> bool {
+        let updated = TrackStub {
+            id: self.id.clone(),
+            gain_db: self.gain_db,
+            added_at: self.added_at,
+            ..scanned.clone()
+        };
+        let changed = updated != *self;
+        *self = updated;
+        changed
+    }
+
+    /// Why the track can't be played any more, if it can't: its local file (or
+    /// the archive holding it) is gone, or its WebDAV server was removed. Streams
+    /// aren't checked, since that would mean going online.
+    pub fn missing_reason(&self, configs: &[WebDAVConfig]) -> Option<&'static str> {
+        match &self.source {
+            TrackSource::Local => {
+                let file = archive::split_path(&self.path).map_or(Path::new(&self.path), |(archive, _)| archive);
+                (!file.exists()).then_some("File not found")
+            }
+            TrackSource::WebDAV { config_id } => {
+                (!configs.iter().any(|c| c.id == *config_id)).then_some("Its WebDAV server was removed")
+            }
+            _ => None,
+        }
+    }
+}
+
+// Playback settings captured by a named profile
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlaybackSettings {
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    #[serde(default)]
+    pub balance: f32,
+    #[serde(default)]
+    pub mono_downmix: bool,
+    #[serde(default)]
+    pub crossfade_secs: u32,
+    #[serde(default)]
+    pub status_palette: StatusPalette,
+    // None for the system default
+    #[serde(default)]
+    pub output_device: Option<String>,
+}
+
+fn default_volume() -> f32 {
+    0.7
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        PlaybackSettings {
+            volume: default_volume(),
+            balance: 0.0,
+            mono_downmix: false,
+            crossfade_secs: 0,
+            status_palette: StatusPalette::default(),
+            output_device: None,
+        }
+    }
+}
+
+// How list rows (playlist, cloud browser) respond to clicks
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickMode {
+    #[default]
+    SingleClick,
+    /// Click selects, double-click plays
+    DoubleClick,
+    /// Tap plays, with larger rows
+    Touch,
+}
+
+impl ClickMode {
+    fn label(self) -> &'static str {
+        match self {
+            ClickMode::SingleClick => "🖱 Single-click",
+            ClickMode::DoubleClick => "🖱 Double-click",
+            ClickMode::Touch => "👆 Touch",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ClickMode::SingleClick => ClickMode::DoubleClick,
+            ClickMode::DoubleClick => ClickMode::Touch,
+            ClickMode::Touch => ClickMode::SingleClick,
+        }
+    }
+
+    fn plays_on_click(self) -> bool {
+        self != ClickMode::DoubleClick
+    }
+
+    // Extra class for list rows
+    fn row_class(self) -> &'static str {
+        match self {
+            ClickMode::Touch => "touch-target",
+            _ => "",
+        }
+    }
+}
+
+// Colors for ok/error indicators. Every indicator also carries an icon or pattern,
+// and the color-blind palette swaps red/green for orange/blue.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusPalette {
+    #[default]
+    Standard,
+    ColorBlind,
+}
+
+impl StatusPalette {
+    fn label(self) -> &'static str {
+        match self {
+            StatusPalette::Standard => "🎨 Standard colors",
+            StatusPalette::ColorBlind => "🎨 Color-blind colors",
+        }
+    }
+
+    fn toggled(self) -> Self {
+        match self {
+            StatusPalette::Standard => StatusPalette::ColorBlind,
+            StatusPalette::ColorBlind => StatusPalette::Standard,
+        }
+    }
+
+    // Class on the app root that the palette's CSS overrides hang off
+    fn root_class(self) -> &'static str {
+        match self {
+            StatusPalette::Standard => "",
+            StatusPalette::ColorBlind => "palette-colorblind",
+        }
+    }
+}
+
+// Main window arrangement. Browse-and-queue keeps a browser on the left and the
+// current playlist (the queue) on the right, with the player above both.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutMode {
+    #[default]
+    ThreeColumn,
+    BrowseAndQueue,
+}
+
+impl LayoutMode {
+    fn label(self) -> &'static str {
+        match self {
+            LayoutMode::ThreeColumn => "▥ Three columns",
+            LayoutMode::BrowseAndQueue => "◫ Browse + queue",
+        }
+    }
+
+    fn toggled(self) -> Self {
+        match self {
+            LayoutMode::ThreeColumn => LayoutMode::BrowseAndQueue,
+            LayoutMode::BrowseAndQueue => LayoutMode::ThreeColumn,
+        }
+    }
+
+    // Classes on the main grid; the player pane and queue pane hang their CSS off these
+    fn grid_class(self) -> &'static str {
+        match self {
+            LayoutMode::ThreeColumn => "grid grid-cols-3 gap-6",
+            LayoutMode::BrowseAndQueue => "grid grid-cols-2 gap-6 layout-browse-queue",
+        }
+    }
+}
+
+// What the Now Playing card draws from the audio being played
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VisualizerMode {
+    #[default]
+    Off,
+    Bars,
+    Waveform,
+}
+
+impl VisualizerMode {
+    fn label(self) -> &'static str {
+        match self {
+            VisualizerMode::Off => "📊 Visualizer",
+            VisualizerMode::Bars => "📊 Bars",
+            VisualizerMode::Waveform => "〰 Waveform",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            VisualizerMode::Off => VisualizerMode::Bars,
+            VisualizerMode::Bars => VisualizerMode::Waveform,
+            VisualizerMode::Waveform => VisualizerMode::Off,
+        }
+    }
+}
+
+// App-wide UI preferences
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct UiSettings {
+    #[serde(default)]
+    pub click_mode: ClickMode,
+    #[serde(default)]
+    pub status_palette: StatusPalette,
+    #[serde(default)]
+    pub layout: LayoutMode,
+    // Grab the keyboard's volume keys system-wide so they drive the player volume
+    #[serde(default)]
+    pub capture_volume_keys: bool,
+    #[serde(default)]
+    pub shuffle: bool,
+    // Shuffle avoids tracks among this many most recent plays
+    #[serde(default = "default_shuffle_history_window")]
+    pub shuffle_history_window: usize,
+    // Shuffle picks by rating and recent play count instead of skipping recent plays
+    #[serde(default)]
+    pub smart_shuffle: bool,
+    #[serde(default = "default_smart_shuffle_exponent")]
+    pub smart_shuffle_exponent: f64,
+    // Overlap between unrelated tracks; 0 plays them back to back
+    #[serde(default)]
+    pub crossfade_secs: u32,
+    // Name of the output device to play through; None for the system default
+    #[serde(default)]
+    pub output_device: Option<String>,
+    // Whether each kind of playlist moves on by itself and resumes tracks
+    #[serde(default)]
+    pub content_types: ContentTypeSettings,
+    // Level tracks to loudness::TARGET_LUFS using their measured loudness
+    #[serde(default)]
+    pub normalize_loudness: bool,
+    #[serde(default)]
+    pub visualizer: VisualizerMode,
+    // Lock mode: playback only, editing hidden until the PIN is entered. Kept
+    // across restarts so a kiosk comes back up locked.
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub lock_pin_hash: Option<String>,
+    // yt-dlp to run for online tracks; None looks for it on the PATH
+    #[serde(default)]
+    pub ytdlp_binary: Option<String>,
+    // Zoom on top of the monitor's own scaling, in percent
+    #[serde(default = "default_ui_scale_percent")]
+    pub ui_scale_percent: u32,
+    // Folders browsable from the sidebar's folder tree, alongside the ones playlists watch
+    #[serde(default)]
+    pub music_folders: Vec<String>,
+    // Where the organizer files tracks, and the folder / file name pattern it uses
+    #[serde(default)]
+    pub organize_root: Option<String>,
+    #[serde(default = "default_organize_pattern")]
+    pub organize_pattern: String,
+    // Save ratings and play counts into the files' own tags, for other players
+    #[serde(default)]
+    pub write_stats_to_tags: bool,
+    // Room for remote tracks kept for replaying and offline playback, in MB
+    #[serde(default = "default_track_cache_mb")]
+    pub track_cache_mb: u32,
+    // Connections a large remote file is downloaded over at once
+    #[serde(default = "default_download_connections")]
+    pub download_connections: usize,
+    // Local files are read this far ahead of playback, in MB; 0 reads them directly
+    #[serde(default = "default_read_ahead_mb")]
+    pub read_ahead_mb: u32,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        UiSettings {
+            click_mode: ClickMode::default(),
+            status_palette: StatusPalette::default(),
+            layout: LayoutMode::default(),
+            capture_volume_keys: false,
+            shuffle: false,
+            shuffle_history_window: default_shuffle_history_window(),
+            smart_shuffle: false,
+            smart_shuffle_exponent: default_smart_shuffle_exponent(),
+            crossfade_secs: 0,
+            output_device: None,
+            content_types: ContentTypeSettings::default(),
+            normalize_loudness: false,
+            visualizer: VisualizerMode::default(),
+            locked: false,
+            lock_pin_hash: None,
+            ytdlp_binary: None,
+            ui_scale_percent: default_ui_scale_percent(),
+            music_folders: Vec::new(),
+            organize_root: None,
+            organize_pattern: default_organize_pattern(),
+            write_stats_to_tags: false,
+            track_cache_mb: default_track_cache_mb(),
+            download_connections: default_download_connections(),
+            read_ahead_mb: default_read_ahead_mb(),
+        }
+    }
+}
+
+// PINs are short, so this only keeps them out of plain sight in the settings file
+fn hash_lock_pin(pin: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(format!("dioxus-music-lock:{}", pin)).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+const LOCK_PIN_MIN_DIGITS: usize = 4;
+
+fn default_shuffle_history_window() -> usize {
+    200
+}
+
+fn default_smart_shuffle_exponent() -> f64 {
+    1.0
+}
+
+fn default_ui_scale_percent() -> u32 {
+    100
+}
+
+fn default_track_cache_mb() -> u32 {
+    player::DEFAULT_CACHE_LIMIT_MB
+}
+
+fn default_download_connections() -> usize {
+    player::DEFAULT_DOWNLOAD_CONNECTIONS
+}
+
+fn default_read_ahead_mb() -> u32 {
+    player::DEFAULT_READ_AHEAD_MB
+}
+
+fn default_organize_pattern() -> String {
+    organize::DEFAULT_PATTERN.to_string()
+}
+
+const UI_SCALE_MIN: u32 = 80;
+const UI_SCALE_MAX: u32 = 150;
+const UI_SCALE_STEP: u32 = 10;
+
+const VOLUME_KEY_STEP: f32 = 0.05;
+// How long the volume overlay stays up after the last key press
+const VOLUME_OSD_DURATION: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VolumeKey {
+    Up,
+    Down,
+    Mute,
+}
+
+// Where playback was when the app last ran, restored on launch
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlaybackSession {
+    pub playlist_index: usize,
+    pub track_id: Option<String>,
+    pub position: Duration,
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+impl Default for PlaybackSession {
+    fn default() -> Self {
+        PlaybackSession {
+            playlist_index: 0,
+            track_id: None,
+            position: Duration::ZERO,
+            volume: default_volume(),
         }
     }
 }
 
+// A named snapshot of playback settings, e.g. "Headphones at work" vs "Living room DAC"
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SettingsProfile {
+    pub id: String,
+    pub name: String,
+    pub settings: PlaybackSettings,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct WebDAVConfig {
     pub id: String,
@@ -113,9 +767,76 @@ pub struct WebDAVConfig {
     pub enabled: bool,
     #[serde(skip)]
     pub password: Option<String>,
+    // Folders (item paths) kept on disk by the background sync
+    #[serde(default)]
+    pub synced_folders: Vec<String>,
+    // KB/s; 0 for no limit
+    #[serde(default)]
+    pub download_limit_kbps: u32,
+    #[serde(default)]
+    pub auth: WebDAVAuth,
+}
+
+// How a server wants its requests signed. The secret that goes with each (the
+// password, the token, or the OAuth2 refresh token) is kept in `encrypted_password`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum WebDAVAuth {
+    #[default]
+    Basic,
+    Bearer,
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        // Empty for public clients
+        #[serde(default)]
+        encrypted_client_secret: String,
+    },
+}
+
+impl WebDAVAuth {
+    pub fn oauth2(token_url: &str, client_id: &str, client_secret: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let encrypted_client_secret = if client_secret.is_empty() {
+            String::new()
+        } else {
+            crypto::encrypt_password(client_secret, &crypto::get_master_password()?)?
+        };
+        Ok(WebDAVAuth::OAuth2 { token_url: token_url.trim().to_string(), client_id: client_id.trim().to_string(), encrypted_client_secret })
+    }
+
+    pub fn client_secret(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            WebDAVAuth::OAuth2 { encrypted_client_secret, .. } if !encrypted_client_secret.is_empty() => {
+                crypto::decrypt_password(encrypted_client_secret, &crypto::get_master_password()?)
+            }
+            _ => Ok(String::new()),
+        }
+    }
 }
 
 impl WebDAVConfig {
+    /// What requests to this server are signed with; None for a server that takes them as they are.
+    pub fn credentials(&self) -> Result<Option<webdav::Credentials>, Box<dyn std::error::Error>> {
+        let secret = self.get_password()?;
+        Ok(match &self.auth {
+            WebDAVAuth::Basic if self.username.is_empty() && secret.is_empty() => None,
+            WebDAVAuth::Basic => Some(webdav::Credentials::basic(&self.username, secret)),
+            WebDAVAuth::Bearer if secret.is_empty() => None,
+            WebDAVAuth::Bearer => Some(webdav::Credentials::Bearer(secret)),
+            WebDAVAuth::OAuth2 { .. } if secret.is_empty() => return Err("No refresh token entered for this server".into()),
+            WebDAVAuth::OAuth2 { token_url, client_id, .. } => {
+                let settings = webdav::OAuth2Settings {
+                    token_url: token_url.clone(),
+                    client_id: client_id.clone(),
+                    client_secret: self.auth.client_secret()?,
+                    refresh_token: secret,
+                };
+                // Shared by everything talking to this server, so tokens are refreshed once for all
+                Some(webdav::Credentials::OAuth2(webdav::oauth2_session(&self.id, settings)))
+            }
+        })
+    }
+
     pub fn get_password(&self) -> Result<String, Box<dyn std::error::Error>> {
         // 优先使用内存中已缓存的明文密码
         if let Some(ref pwd) = self.password {
@@ -156,6 +877,11 @@ fn main() {
     use dioxus::prelude::VirtualDom;
     use dioxus_desktop::{Config, WindowBuilder};
 
+    diagnostics::startup_began();
+    if let Ok(config_dir) = base_config_dir() {
+        diagnostics::install_panic_hook(&config_dir);
+    }
+
     let icon_data: &[u8] = include_bytes!("../assets/rmusic.ico");
 
     let icon = image::load_from_memory_with_format(icon_data, image::ImageFormat::Ico)
@@ -183,6 +909,9 @@ fn main() {
 
     let cfg = Config::default()
         .with_window(window)
+        // Lets the webview handle HTML drag and drop (browser rows onto the queue);
+        // on Windows the native file drop handler would swallow those events
+        .with_disable_drag_drop_handler(true)
         .with_custom_head(String::from(r#"
             <style>
                 * { margin: 0; padding: 0; box-sizing: border-box; }
@@ -204,6 +933,23 @@ fn main() {
                 }
                 
                 .min-h-screen { min-height: 100vh; }
+                .touch-target { min-height: 48px; padding-top: 0.75rem; padding-bottom: 0.75rem; font-size: 1rem; }
+                .row-selected { outline: 2px solid #60a5fa; outline-offset: -2px; }
+                .status-ok { color: #4ade80; }
+                .status-error { color: #f87171; }
+                .status-off { color: #9ca3af; }
+                .meter-ok { background-color: #22c55e; }
+                .meter-clip { background: repeating-linear-gradient(45deg, #ef4444 0 4px, #b91c1c 4px 8px); }
+                .skeleton { border-radius: 0.375rem; background: linear-gradient(90deg, #374151 25%, #4b5563 50%, #374151 75%); background-size: 200% 100%; animation: skeleton-shimmer 1.2s ease-in-out infinite; }
+                @keyframes skeleton-shimmer { from { background-position: 200% 0; } to { background-position: -200% 0; } }
+                .visualizer { height: 4rem; }
+                .visualizer-bars { display: flex; align-items: flex-end; gap: 2px; height: 100%; padding: 0 2px; }
+                .visualizer-line { stroke: #22c55e; }
+                .palette-colorblind .visualizer-line { stroke: #56b4e9; }
+                .palette-colorblind .status-ok { color: #56b4e9; }
+                .palette-colorblind .status-error { color: #e69f00; }
+                .palette-colorblind .meter-ok { background-color: #56b4e9; }
+                .palette-colorblind .meter-clip { background: repeating-linear-gradient(45deg, #e69f00 0 4px, #a36f00 4px 8px); }
                 .bg-gray-800 { background-color: #1f2937; }
                 .bg-gray-900 { background-color: #111827; }
                 .bg-gray-700 { background-color: #374151; }
@@ -218,6 +964,7 @@ fn main() {
                 .text-blue-400 { color: #60a5fa; }
                 .text-green-400 { color: #4ade80; }
                 .text-red-400 { color: #f87171; }
+                .text-red-500 { color: #ef4444; }
                 .text-yellow-400 { color: #fbbf24; }
                 .p-6 { padding: 1.5rem; }
                 .p-4 { padding: 1rem; }
@@ -245,9 +992,23 @@ fn main() {
                 .gap-4 { gap: 1rem; }
                 .gap-6 { gap: 1.5rem; }
                 .grid { display: grid; }
+                .grid-cols-2 { grid-template-columns: repeat(2, minmax(0, 1fr)); }
                 .grid-cols-3 { grid-template-columns: repeat(3, minmax(0, 1fr)); }
                 .col-span-1 { grid-column: span 1 / span 1; }
                 .col-span-2 { grid-column: span 2 / span 2; }
+                /* Browse-and-queue layout: player across the top, browser | queue below */
+                .layout-browse-queue > .player-pane { grid-column: 1 / -1; order: -1; display: grid; grid-template-columns: repeat(3, minmax(0, 1fr)); gap: 1.5rem; align-items: start; }
+                [draggable=true] { cursor: grab; }
+                /* Volume overlay shown for media-key changes; never takes clicks */
+                .volume-osd { position: fixed; top: 50%; left: 50%; transform: translate(-50%, -50%); z-index: 1100; pointer-events: none; width: 14rem; padding: 1.25rem 1.5rem; border-radius: 1rem; background: rgba(17, 24, 39, 0.92); box-shadow: 0 10px 25px rgba(0, 0, 0, 0.5); text-align: center; }
+                /* Chapter starts over the seek bar */
+                .chapter-marker { position: absolute; top: 0; width: 2px; height: 0.5rem; margin-left: -1px; background: #fbbf24; pointer-events: none; }
+                /* Time (and lyric) under the pointer on the seek bar */
+                .seek-tooltip { position: absolute; bottom: 1.25rem; transform: translateX(-50%); pointer-events: none; white-space: nowrap; max-width: 20rem; overflow: hidden; text-overflow: ellipsis; padding: 0.25rem 0.5rem; border-radius: 0.375rem; background: rgba(17, 24, 39, 0.92); box-shadow: 0 4px 6px rgba(0, 0, 0, 0.4); font-size: 0.75rem; text-align: center; z-index: 20; }
+                .drop-target { outline: 2px dashed #3b82f6; outline-offset: -2px; border-radius: 0.5rem; }
+                /* Track reordering: the grip, and the row a dragged track would take the place of */
+                .drag-handle { cursor: grab; user-select: none; }
+                .drop-before { box-shadow: inset 0 2px 0 #3b82f6; }
                 .rounded { border-radius: 0.25rem; }
                 .rounded-full { border-radius: 9999px; }
                 .rounded-lg { border-radius: 0.5rem; }
@@ -265,6 +1026,7 @@ fn main() {
                 .bg-red-500 { background-color: #ef4444; }
                 .bg-red-600 { background-color: #dc2626; }
                 .bg-yellow-500 { background-color: #eab308; }
+                .bg-yellow-600 { background-color: #ca8a04; }
                 .bg-purple-500 { background-color: #a855f7; }
                 .bg-purple-600 { background-color: #9333ea; }
                 .bg-indigo-500 { background-color: #6366f1; }
@@ -277,6 +1039,7 @@ fn main() {
                 .hover\:bg-gray-600:hover { background-color: #4b5563; }
                 .hover\:bg-gray-700:hover { background-color: #374151; }
                 .hover\:bg-purple-700:hover { background-color: #7c3aed; }
+                .hover\:bg-yellow-700:hover { background-color: #a16207; }
                 .hover\:text-white:hover { color: #ffffff; }
                 .text-sm { font-size: 0.875rem; }
                 .text-xs { font-size: 0.75rem; }
@@ -291,20 +1054,27 @@ fn main() {
                 .font-semibold { font-weight: 600; }
                 .font-medium { font-weight: 500; }
                 .w-full { width: 100%; }
+                .h-full { height: 100%; }
                 .w-48 { width: 12rem; }
                 .w-40 { width: 10rem; }
                 .w-32 { width: 8rem; }
                 .w-20 { width: 5rem; }
+                .w-16 { width: 4rem; }
+                .w-8 { width: 2rem; }
+                .w-4 { width: 1rem; }
                 .h-48 { height: 12rem; }
                 .h-12 { height: 3rem; }
                 .h-10 { height: 2.5rem; }
                 .h-8 { height: 2rem; }
+                .h-2 { height: 0.5rem; }
                 .mx-auto { margin-left: auto; margin-right: auto; }
                 .max-w-7xl { max-width: 80rem; }
                 .max-w-4xl { max-width: 56rem; }
                 .max-w-2xl { max-width: 42rem; }
                 .max-w-md { max-width: 28rem; }
                 .truncate { overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+                .min-w-0 { min-width: 0; }
+                .space-y-1 > * + * { margin-top: 0.25rem; }
                 .space-y-2 > * + * { margin-top: 0.5rem; }
                 .space-y-3 > * + * { margin-top: 0.75rem; }
                 .space-y-4 > * + * { margin-top: 1rem; }
@@ -325,6 +1095,7 @@ fn main() {
                 .border { border-width: 1px; }
                 .border-2 { border-width: 2px; }
                 .border-gray-600 { border-color: #4b5563; }
+                .border-red-500 { border-color: #ef4444; }
                 .border-gray-500 { border-color: #6b7280; }
                 .border-gray-700 { border-color: #374151; }
                 .border-blue-500 { border-color: #3b82f6; }
@@ -679,6 +1450,14 @@ fn main() {
                     color: #fbbf24;
                 }
                 
+                /* Cloud folder grid */
+                .folder-cover {
+                    aspect-ratio: 1 / 1;
+                    display: flex;
+                    align-items: center;
+                    justify-content: center;
+                }
+                
                 /* Status indicator */
                 .status-dot {
                     width: 8px;
@@ -791,136 +1570,1495 @@ fn main() {
             </style>
         "#));
 
-    dioxus_desktop::launch::launch_virtual_dom(VirtualDom::new(App), cfg);
+    dioxus_desktop::launch::launch_virtual_dom(VirtualDom::new(Root), cfg);
+}
+
+// The user profile App was loaded for. App is keyed on it, so switching
+// profiles drops everything and loads the other profile from scratch.
+#[derive(Clone, Copy)]
+struct ActiveUserProfile(Signal<Option<profiles::UserProfile>>);
+
+// Point get_config_dir at the profile's folder and remember it for next launch
+fn activate_user_profile(profile: &profiles::UserProfile) {
+    let config_dir = match base_config_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("[Config] 无法获取配置目录: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = profiles::activate(&config_dir, profile) {
+        eprintln!("[Config] 无法创建用户目录 {}: {}", profile.name, e);
+        return;
+    }
+    let mut registry = profiles::ProfileRegistry::load(&config_dir);
+    registry.last_used = Some(profile.id.clone());
+    if let Err(e) = registry.save(&config_dir) {
+        eprintln!("[Config] 保存用户列表失败: {}", e);
+    }
+}
+
+#[component]
+fn Root() -> Element {
+    // None until a profile is picked, when asked to offer the choice at startup
+    let mut user_profile = use_signal(|| {
+        let registry = base_config_dir().map(|dir| profiles::ProfileRegistry::load(&dir)).unwrap_or_default();
+        if registry.ask_at_startup && registry.profiles.len() > 1 {
+            return None;
+        }
+        let profile = registry.last_used();
+        activate_user_profile(&profile);
+        Some(profile)
+    });
+    use_context_provider(|| ActiveUserProfile(user_profile));
+
+    match user_profile() {
+        Some(profile) => rsx! {
+            App { key: "{profile.id}" }
+        },
+        None => rsx! {
+            div { class: "h-screen bg-gradient-to-b from-gray-900 to-black text-white",
+                UserProfilesModal {
+                    current: None,
+                    on_switch: move |profile: profiles::UserProfile| {
+                        activate_user_profile(&profile);
+                        user_profile.set(Some(profile));
+                    },
+                    // Closing without picking opens the last one used
+                    on_close: move |_| {
+                        let registry = base_config_dir().map(|dir| profiles::ProfileRegistry::load(&dir)).unwrap_or_default();
+                        let profile = registry.last_used();
+                        activate_user_profile(&profile);
+                        user_profile.set(Some(profile));
+                    },
+                }
+            }
+        },
+    }
 }
 
 #[component]
 fn App() -> Element {
-    let mut player_state = use_signal(|| PlayerState::Stopped);
+    let session = use_hook(|| load_playback_session().unwrap_or_default());
+    // Playlists and the rest of the library are read off the UI thread after the
+    // window is up; until then a placeholder playlist stands in for them
+    let mut library_loaded = use_signal(|| false);
+    let mut playlists = use_signal(|| vec![Playlist::new("My Playlist".to_string())]);
+    let mut current_playlist = use_signal(|| 0usize);
     let mut current_track = use_signal(|| None::<TrackStub>);
-    let mut current_time = use_signal(|| Duration::from_secs(0));
+    let mut player_state = use_signal(|| PlayerState::Stopped);
+    let mut current_time = use_signal(|| Duration::ZERO);
     let mut current_duration = use_signal(|| Duration::from_secs(0));
-    let mut volume = use_signal(|| 0.7);
-    let mut playlists = use_signal(|| vec![Playlist::new("My Playlist".to_string())]);
-    let mut current_playlist = use_signal(|| 0);
+    let mut volume = use_signal(|| session.volume);
     let mut show_playlist_manager = use_signal(|| false);
     let mut show_directory_browser = use_signal(|| false);
     let mut show_webdav_config = use_signal(|| false);
     let mut show_webdav_config_list = use_signal(|| false);
+    // Size of the remote track cache, measured as the server list opens
+    let mut track_cache_used = use_signal(|| 0u64);
+    use_effect(move || {
+        if show_webdav_config_list() {
+            track_cache_used.set(player::cache_size());
+        }
+    });
     let mut show_webdav_browser = use_signal(|| false);
-    let mut webdav_configs = use_signal(|| load_webdav_configs().unwrap_or_default());
+    let mut webdav_configs = use_signal(Vec::<WebDAVConfig>::new);
     let mut current_webdav_config = use_signal(|| None::<usize>);
     let mut editing_webdav_config = use_signal(|| None::<usize>);
     let mut current_directory = use_signal(|| String::from(std::env::var("HOME").unwrap_or_else(|_| "/".to_string())));
     let mut error_msg = use_signal(|| None::<String>);
 
+    let mut ui_settings = use_signal(|| load_ui_settings().unwrap_or_default());
+    let click_mode = use_memo(move || ui_settings.read().click_mode);
+    let locked = use_memo(move || ui_settings.read().locked);
+    let mut show_lock = use_signal(|| false);
+    // Close the cloud browser on the way in; it adds whatever it plays to the playlist
+    let lock_ui = use_callback(move |_: ()| {
+        show_webdav_browser.set(false);
+        current_webdav_config.set(None);
+        ui_settings.write().locked = true;
+        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+            eprintln!("[Config] 保存界面设置失败: {}", e);
+        }
+    });
+
     // Provide current_time and duration as context for child components
     provide_context(current_time);
     provide_context(current_duration);
+    provide_context(click_mode);
+    provide_context(ScrollPositions(use_signal(std::collections::HashMap::new)));
+    let mut drag_state = use_signal(|| None::<DragPayload>);
+    provide_context(DragState(drag_state));
 
     // WebDAV Browser State
     let mut webdav_current_path = use_signal(|| "/".to_string());
+    // Last folder opened on each server, so re-expanding a source goes back there
+    let mut webdav_last_folder = use_signal(std::collections::HashMap::<String, String>::new);
     let mut webdav_items = use_signal(|| Vec::<webdav::WebDAVItem>::new());
     let mut webdav_is_loading = use_signal(|| false);
     let mut webdav_error = use_signal(|| Option::<String>::None);
     let mut current_lyric = use_signal(|| None::<player::Lyric>);
-    let _show_lyrics = use_signal(|| false);
-
-    // Auto-play trigger - atomic counter for thread-safe triggering
-    let _track_check_trigger: &'static Arc<std::sync::atomic::AtomicUsize> = {
-        static TRIGGER: std::sync::OnceLock<Arc<std::sync::atomic::AtomicUsize>> = std::sync::OnceLock::new();
-        TRIGGER.get_or_init(|| Arc::new(std::sync::atomic::AtomicUsize::new(0)))
+    let mut play_history = use_signal(Vec::<PlayHistoryEntry>::new);
+    let mut recently_played = use_signal(Vec::<RecentPlay>::new);
+    // A built-in list the main pane shows instead of the current playlist
+    let mut special_list = use_signal(|| None::<SpecialList>);
+    let mut track_ratings = use_signal(std::collections::HashMap::<String, u8>::new);
+    let mut favorite_tracks = use_signal(std::collections::HashSet::<String>::new);
+    // Party mode's guest request server while it's running, and the requests waiting on it
+    let mut party_server = use_signal(|| None::<party::PartyServer>);
+    let mut party_queue = use_signal(Vec::<party::SongRequest>::new);
+    let mut refresh_party_queue = move || {
+        party_queue.set(party_server.peek().as_ref().map(|server| server.queue()).unwrap_or_default());
     };
-
-    // Create a static-like player reference stored in component state
-    // This will be created once and persist for the lifetime of the app
-    let player_ref = use_signal(|| MusicPlayer::new().ok());
-
-    // Auto-play: periodically check if track ended and update current time
-    let global_state = get_global_state().clone();
-    let player_ref_clone = player_ref.clone();
-
-    let _time_update_future = use_future(move || {
-        let global_state = global_state.clone();
-        let player_ref_clone = player_ref_clone.clone();
-
-        async move {
-            loop {
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-
-                let player_guard = player_ref_clone.read();
-                if let Some(player) = player_guard.as_ref() {
-                    // Update current time
-                    let elapsed = player.get_elapsed();
-                    *current_time.write() = elapsed;
-
-                    // Sync duration from player
-                    let duration = player.get_duration();
-                    *current_duration.write() = duration;
-
-                    // Sync lyrics from player
-                    if let Some(lyric) = player.get_lyric() {
-                        *current_lyric.write() = Some(lyric);
-                    }
-
-                    // Check for track end
-                    let is_ended = *player.track_ended.lock().unwrap();
-                    let was_stopped_by_user = *player.stopped_by_user.lock().unwrap();
-                    if is_ended {
-                        eprintln!("[UI] 检测到曲目结束, stopped_by_user={}", was_stopped_by_user);
-                        
-                        // Reset the flags
-                        *player.track_ended.lock().unwrap() = false;
-                        *player.stopped_by_user.lock().unwrap() = false;
-                        
-                        if !was_stopped_by_user {
-                            eprintln!("[UI] 检测到曲目自然结束");
-                            
-                            let last_track_id = player.get_last_track_id();
-                            if let Some(id) = last_track_id {
-                                // Clone for the global state and keep original for closure
-                                global_state.set_last_track(id.clone());
-                                let track_id_for_search = id.clone();
-                                
-                                let all_playlists = playlists();
-                                let current_playlist_idx = current_playlist();
-                                
-                                if all_playlists.len() > current_playlist_idx {
-                                    let playlist = &all_playlists[current_playlist_idx];
-                                    if let Some(pos) = playlist.tracks.iter().position(|t| t.id == track_id_for_search) {
-                                        if pos < playlist.tracks.len() - 1 {
-                                            let next_track = playlist.tracks[pos + 1].clone();
-                                            eprintln!("[UI] 自动播放下一首: {}", next_track.title);
-                                            
-                                            let path = std::path::Path::new(&next_track.path);
-                                            player.play(path, Some(next_track.id.clone()));
-                                            player.set_stopped_by_user(false);
-                                            let vol = *volume.read();
-                                            let _ = player.set_volume(vol);
-                                            
-                                            *current_track.write() = Some(TrackStub::from(next_track.clone()));
-                                            *player_state.write() = PlayerState::Playing;
-                                        } else {
-                                            eprintln!("[UI] 播放列表已结束");
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    // Guests can search every track in every playlist
+    use_effect(move || {
+        let lists = playlists.read();
+        if let Some(server) = party_server.read().as_ref() {
+            server.set_library(party_library(&lists));
         }
     });
-
-    // We'll access it directly in the closures since Signal is Copy
-
-    let header_icon = use_signal(|| load_header_icon());
-
-    rsx! {
-        div { class: "h-screen bg-gradient-to-b from-gray-900 to-black text-white overflow-y-auto flex flex-col",
-
-            header { class: "bg-gray-800 shadow-lg p-6",
+    // Smart playlists follow the library, and the play counts, ratings and favorites their rules can ask about
+    use_effect(move || {
+        let updates = {
+            let play_counts = play_counts(&play_history.read());
+            let ratings = track_ratings.read();
+            let favorites = favorite_tracks.read();
+            let stats = playlist::smart::TrackStats {
+                play_counts: &play_counts,
+                ratings: &ratings,
+                favorites: &favorites,
+                now: std::time::SystemTime::now(),
+            };
+            playlist::smart::refreshed(&playlists.read(), &stats)
+        };
+        if updates.is_empty() {
+            return;
+        }
+        let mut lists = playlists.write();
+        for (idx, tracks) in updates {
+            lists[idx].tracks = tracks;
+        }
+    });
+    // Tracks of the shown playlist that can't be played, by id, with why; checked
+    // again whenever the playlist, the one shown or the servers change
+    let missing_tracks = use_memo(move || {
+        let configs = webdav_configs.read();
+        playlists
+            .read()
+            .get(current_playlist())
+            .map(|playlist| {
+                playlist
+                    .tracks
+                    .iter()
+                    .filter_map(|track| Some((track.id.clone(), track.missing_reason(&configs)?.to_string())))
+                    .collect::<std::collections::HashMap<_, _>>()
+            })
+            .unwrap_or_default()
+    });
+    // Some(None) while a new smart playlist is being set up, Some(id) while that one's rules are edited
+    let mut editing_smart_rules = use_signal(|| None::<Option<String>>);
+    let mut show_play_history = use_signal(|| false);
+    // The duplicate finder, None while it's still comparing files
+    let mut show_duplicates = use_signal(|| false);
+    let mut duplicate_groups = use_signal(|| None::<Vec<Vec<TrackStub>>>);
+    // The statistics dashboard, None while it's still adding up
+    let mut show_library_stats = use_signal(|| false);
+    let mut library_stats = use_signal(|| None::<playlist::stats::LibraryStats>);
+    let mut pending_merge = use_signal(|| None::<PendingMerge>);
+    let mut pending_import = use_signal(|| None::<PendingImport>);
+    let mut track_chapters = use_signal(std::collections::HashMap::<String, Vec<chapters::Chapter>>::new);
+    let mut editing_chapters = use_signal(|| None::<TrackStub>);
+    let mut editing_tags = use_signal(|| None::<TrackStub>);
+
+    // Tracks lined up with Play Next / Add to Queue, played before the playlist
+    // carries on. Entries get ids of their own, so the same track can be queued twice.
+    let mut play_queue = use_signal(Vec::<TrackStub>::new);
+    // The queued entry playing now and the playlist track to carry on after
+    let mut queue_resume = use_signal(|| None::<(String, String)>);
+
+    // What follows a track: the front of the queue, else the next one in the
+    // current playlist. Shuffle's pick is kept per track so the prefetch and the
+    // actual advance agree on it.
+    let mut shuffle_pick = use_signal(|| None::<(String, TrackStub)>);
+    let upcoming_track = use_callback(move |current_id: String| -> Option<TrackStub> {
+        if let Some(queued) = play_queue.peek().first() {
+            return Some(queued.clone());
+        }
+        let current_id = match queue_resume.peek().as_ref() {
+            Some((queued, resume)) if *queued == current_id => resume.clone(),
+            _ => current_id,
+        };
+        let lists = playlists.peek();
+        let playlist = lists.get(*current_playlist.peek())?;
+        let settings = ui_settings.peek();
+        if !settings.shuffle {
+            return playlist.next_after(&current_id).cloned();
+        }
+        if let Some((after, next)) = shuffle_pick.peek().as_ref() {
+            if *after == current_id && playlist.tracks.iter().any(|t| t.id == next.id) {
+                return Some(next.clone());
+            }
+        }
+        let recent: Vec<String> = play_history
+            .peek()
+            .iter()
+            .take(settings.shuffle_history_window)
+            .map(|entry| entry.file_key())
+            .collect();
+        let next = if settings.smart_shuffle {
+            playlist.smart_shuffled_next(
+                &current_id,
+                &track_ratings.peek(),
+                &recent,
+                settings.smart_shuffle_exponent,
+                &mut rand::thread_rng(),
+            )
+        } else {
+            playlist.shuffled_next(&current_id, &recent, &mut rand::thread_rng())
+        }
+        .cloned()?;
+        shuffle_pick.set(Some((current_id, next.clone())));
+        Some(next)
+    });
+    // Whether the end of `id` moves on by itself: queued entries always do,
+    // playlist tracks when the playlist auto-advances, tracks played straight
+    // from the browser never
+    let advances_after = use_callback(move |id: String| -> bool {
+        if !play_queue.peek().is_empty() {
+            return true;
+        }
+        let lists = playlists.peek();
+        let Some(playlist) = lists.get(*current_playlist.peek()) else {
+            return false;
+        };
+        let from_queue = queue_resume.peek().as_ref().is_some_and(|(queued, _)| *queued == id);
+        (from_queue || playlist.tracks.iter().any(|t| t.id == id)) && playlist.auto_advances(&ui_settings.peek().content_types)
+    });
+    // Call as `next` starts after the current track; takes it off the queue if
+    // it came from there, remembering where the playlist left off. True if it did.
+    let take_from_queue = use_callback(move |next: TrackStub| -> bool {
+        if play_queue.peek().first().is_none_or(|queued| queued.id != next.id) {
+            return false;
+        }
+        play_queue.write().remove(0);
+        let Some(previous) = current_track.peek().as_ref().map(|t| t.id.clone()) else { return true };
+        let resume = match queue_resume.peek().as_ref() {
+            Some((queued, resume)) if *queued == previous => resume.clone(),
+            _ => previous,
+        };
+        queue_resume.set(Some((next.id, resume)));
+        true
+    });
+    // Why a track that followed on by itself is playing
+    let advance_reason = move |from_queue: bool| {
+        if from_queue {
+            PlayReason::Queue
+        } else if ui_settings.peek().shuffle {
+            PlayReason::ShuffleAdvance
+        } else {
+            PlayReason::AutoAdvance
+        }
+    };
+    // Ratings and plays waiting to go into file tags, by path. A file is only
+    // rewritten once it isn't playing, as playback reads it from disk as it goes.
+    let mut pending_tag_stats = use_signal(std::collections::HashMap::<String, metadata::StatsUpdate>::new);
+    let save_tag_stats = use_callback(move |()| {
+        let playing = current_track.peek().as_ref().map(|t| t.path.clone());
+        let ready: Vec<(String, metadata::StatsUpdate)> = {
+            let mut pending = pending_tag_stats.write();
+            let paths: Vec<String> = pending.keys().filter(|path| Some(*path) != playing.as_ref()).cloned().collect();
+            paths.into_iter().filter_map(|path| pending.remove_entry(&path)).collect()
+        };
+        if ready.is_empty() {
+            return;
+        }
+        spawn(async move {
+            let _ = tokio::task::spawn_blocking(move || {
+                for (path, update) in ready {
+                    if let Err(e) = metadata::write_stats(Path::new(&path), update) {
+                        eprintln!("[Tags] 写入评分和播放次数失败 {}: {}", path, e);
+                    }
+                }
+            })
+            .await;
+        });
+    });
+    let queue_tag_stats = use_callback(move |(path, update): (String, metadata::StatsUpdate)| {
+        if !ui_settings.peek().write_stats_to_tags || !metadata::can_write_stats(Path::new(&path)) || !Path::new(&path).is_file() {
+            return;
+        }
+        pending_tag_stats.write().entry(path).or_default().merge(update);
+        save_tag_stats.call(());
+    });
+    // Whatever was waiting on the track that just stopped playing
+    use_effect(move || {
+        let _ = current_track.read();
+        save_tag_stats.call(());
+    });
+    // Plays logged from now on count towards the files' play counts
+    let mut plays_counted_until = use_signal(std::time::SystemTime::now);
+    use_effect(move || {
+        let since = *plays_counted_until.peek();
+        let new_plays: Vec<(String, std::time::SystemTime)> = play_history
+            .read()
+            .iter()
+            .take_while(|entry| entry.played_at > since)
+            .map(|entry| (entry.path.clone(), entry.played_at))
+            .collect();
+        let Some((_, newest)) = new_plays.first() else { return };
+        plays_counted_until.set(*newest);
+        for (path, _) in new_plays {
+            queue_tag_stats.call((path, metadata::StatsUpdate { plays: 1, ..Default::default() }));
+        }
+    });
+
+    // Ratings and favorites are kept by file_key, for every playlist the track is
+    // in; for local files that's their path, which is where the tags go
+    let set_rating = use_callback(move |(key, rating): (String, Option<u8>)| {
+        queue_tag_stats.call((key.clone(), metadata::StatsUpdate { rating: Some(rating), plays: 0 }));
+        match rating {
+            Some(stars) => track_ratings.write().insert(key, stars),
+            None => track_ratings.write().remove(&key),
+        };
+        // A pending pick was weighted with the old rating
+        shuffle_pick.set(None);
+        if let Err(e) = save_track_ratings(&track_ratings.peek()) {
+            eprintln!("[Config] 保存评分失败: {}", e);
+        }
+    });
+    let set_favorite = use_callback(move |(key, favorite): (String, bool)| {
+        if favorite {
+            favorite_tracks.write().insert(key);
+        } else {
+            favorite_tracks.write().remove(&key);
+        }
+        if let Err(e) = save_favorite_tracks(&favorite_tracks.peek()) {
+            eprintln!("[Config] 保存收藏失败: {}", e);
+        }
+    });
+    let find_duplicates = use_callback(move |_: ()| {
+        show_duplicates.set(true);
+        duplicate_groups.set(None);
+        spawn(async move {
+            let tracks: Vec<TrackStub> = playlists
+                .peek()
+                .iter()
+                .filter(|playlist| playlist.smart.is_none())
+                .flat_map(|playlist| playlist.tracks.iter().cloned())
+                .filter(|track| track.source == TrackSource::Local)
+                .collect();
+            let fingerprints = tokio::task::spawn_blocking(move || playlist::duplicates::fingerprints(&tracks))
+                .await
+                .unwrap_or_default();
+            let groups = playlist::duplicates::find(&playlists.peek(), &fingerprints);
+            eprintln!("[Library] 找到 {} 组重复曲目", groups.len());
+            duplicate_groups.set(Some(groups));
+        });
+    });
+    let open_library_stats = use_callback(move |_: ()| {
+        show_library_stats.set(true);
+        library_stats.set(None);
+        spawn(async move {
+            let lists = playlists.peek().clone();
+            let play_counts = play_counts(&play_history.peek());
+            // Local files saved before their size was kept get looked up on disk
+            let stats = tokio::task::spawn_blocking(move || playlist::stats::compute(&lists, &play_counts))
+                .await
+                .unwrap_or_default();
+            library_stats.set(Some(stats));
+        });
+    });
+    // Re-read the tags of local files changed since they were read, across every
+    // playlist; unchanged files aren't opened
+    let mut refreshing_library = use_signal(|| false);
+    let refresh_library = use_callback(move |_: ()| {
+        if refreshing_library() {
+            return;
+        }
+        refreshing_library.set(true);
+        spawn(async move {
+            let mut seen = std::collections::HashSet::new();
+            let tracks: Vec<TrackStub> = playlists
+                .peek()
+                .iter()
+                .filter(|playlist| playlist.smart.is_none())
+                .flat_map(|playlist| playlist.tracks.iter())
+                .filter(|track| seen.insert((track.source.clone(), track.path.clone())))
+                .cloned()
+                .collect();
+            let checked = tracks.len();
+            let fresh = tokio::task::spawn_blocking(move || reread_changed_tracks(&tracks)).await.unwrap_or_default();
+            if !fresh.is_empty() {
+                for playlist in playlists.write().iter_mut() {
+                    for track in playlist.tracks.iter_mut() {
+                        if let Some(scanned) = fresh.get(&track.path) {
+                            track.update_from(scanned);
+                        }
+                    }
+                }
+                if let Some(track) = current_track.write().as_mut() {
+                    if let Some(scanned) = fresh.get(&track.path) {
+                        track.update_from(scanned);
+                    }
+                }
+            }
+            eprintln!("[Library] 刷新完成: 检查了 {} 首曲目，重新读取了 {} 个文件", checked, fresh.len());
+            refreshing_library.set(false);
+        });
+    });
+    // The header search, across every playlist; while it has text the results
+    // take the place of the track list
+    let mut global_search = use_signal(String::new);
+    let search_results = use_memo(move || {
+        let query = global_search.read();
+        if query.trim().is_empty() {
+            return Vec::new();
+        }
+        playlists
+            .read()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, playlist)| {
+                let tracks: Vec<TrackStub> = playlist.tracks.iter().filter(|t| playlist::matches_query(t, &query)).cloned().collect();
+                (!tracks.is_empty()).then(|| SearchGroup { playlist_index: idx, name: playlist.name.clone(), tracks })
+            })
+            .collect::<Vec<_>>()
+    });
+    // What the Favorites list shows: each favorite once, in playlist order, then
+    // those only Recently Played still knows
+    let favorite_list = use_memo(move || {
+        let favorites = favorite_tracks.read();
+        let mut seen = std::collections::HashSet::new();
+        let lists = playlists.read();
+        let recent = recently_played.read();
+        lists
+            .iter()
+            .filter(|playlist| playlist.smart.is_none())
+            .flat_map(|playlist| playlist.tracks.iter())
+            .chain(recent.iter().map(|play| &play.track))
+            .filter(|track| favorites.contains(&track.file_key()) && seen.insert((track.source.clone(), track.path.clone())))
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+    let mut now_playing_metadata = use_signal(|| None::<player::TrackMetadata>);
+    let mut peak_levels = use_signal(|| (0.0f32, 0.0f32));
+    let mut balance = use_signal(|| 0.0f32);
+    let mut mono_downmix = use_signal(|| false);
+    let mut settings_profiles = use_signal(|| load_settings_profiles().unwrap_or_default());
+    let mut active_profile = use_signal(|| None::<String>);
+    let mut show_settings_profiles = use_signal(|| false);
+    let output_devices = use_signal(player::output_devices);
+    let ActiveUserProfile(mut user_profile) = use_context();
+    let mut show_user_profiles = use_signal(|| false);
+    let mut show_diagnostics = use_signal(|| false);
+    let mut show_online_track = use_signal(|| false);
+    let mut show_bandcamp = use_signal(|| false);
+    #[cfg(feature = "ytdlp")]
+    use_effect(move || ytdlp::set_binary(ui_settings.read().ytdlp_binary.clone()));
+    // Outcome of the last cloud import, shown until dismissed
+    let mut import_report = use_signal(|| None::<ImportReport>);
+    let mut retrying_import = use_signal(|| false);
+    // Add imported cloud tracks to the current playlist, skipping ones already in
+    // it. A retry (the paths retried) folds into the report it came from.
+    let add_cloud_import = use_callback(
+        move |(config_id, tracks, failures, retried): (String, Vec<Track>, Vec<ImportFailure>, Vec<String>)| {
+            let mut report = import_report
+                .peek()
+                .clone()
+                .filter(|report| !retried.is_empty() && report.config_id == config_id)
+                .unwrap_or_else(|| ImportReport { config_id, ..Default::default() });
+            report.failed.retain(|failure| !retried.contains(&failure.path));
+            report.failed.extend(failures);
+
+            let index = *current_playlist.peek();
+            if let Some(playlist) = playlists.write().get_mut(index) {
+                for track in tracks {
+                    if playlist.tracks.iter().any(|t| t.path == track.path) {
+                        report.duplicates.push(track.title);
+                    } else {
+                        playlist.add_track(track.into());
+                        report.imported += 1;
+                    }
+                }
+            }
+            eprintln!(
+                "[WebDAV] 导入完成: {} 首成功, {} 首重复, {} 首失败",
+                report.imported,
+                report.duplicates.len(),
+                report.failed.len()
+            );
+            import_report.set(Some(report));
+        },
+    );
+    let _show_lyrics = use_signal(|| false);
+
+    // Auto-play trigger - atomic counter for thread-safe triggering
+    let _track_check_trigger: &'static Arc<std::sync::atomic::AtomicUsize> = {
+        static TRIGGER: std::sync::OnceLock<Arc<std::sync::atomic::AtomicUsize>> = std::sync::OnceLock::new();
+        TRIGGER.get_or_init(|| Arc::new(std::sync::atomic::AtomicUsize::new(0)))
+    };
+
+    // Create a static-like player reference stored in component state
+    // This will be created once and persist for the lifetime of the app
+    let player_ref = use_signal(|| {
+        let player = MusicPlayer::new().ok();
+        diagnostics::startup_mark("audio output opened");
+        player
+    });
+    // The entry lined up in the player to follow the current one: (current id, next id)
+    let mut queued_next = use_signal(|| None::<(String, String)>);
+
+    use_hook(move || {
+        if let Some(player) = player_ref.peek().as_ref() {
+            player.set_crossfade(Duration::from_secs(ui_settings.peek().crossfade_secs.into()));
+            if let Err(e) = player.set_output_device(ui_settings.peek().output_device.clone()) {
+                eprintln!("[Player] 无法打开输出设备: {}", e);
+            }
+            player.set_read_ahead_size(ui_settings.peek().read_ahead_mb as usize * 1024 * 1024);
+        }
+        player::set_cache_limit(u64::from(ui_settings.peek().track_cache_mb) * 1024 * 1024);
+        player::set_download_connections(ui_settings.peek().download_connections);
+    });
+    // Switch everything a settings profile keeps, from the profiles dialog or the tray
+    let apply_profile = use_callback(move |profile: SettingsProfile| {
+        let settings = profile.settings;
+        if let Some(player) = player_ref.peek().as_ref() {
+            player.set_volume(settings.volume);
+            player.set_balance(settings.balance);
+            player.set_mono_downmix(settings.mono_downmix);
+            player.set_crossfade(Duration::from_secs(settings.crossfade_secs.into()));
+            if let Err(e) = player.set_output_device(settings.output_device.clone()) {
+                eprintln!("[Player] 无法切换输出设备: {}", e);
+                *error_msg.write() = Some(format!("Couldn't switch the output device: {}", e));
+            }
+        }
+        volume.set(settings.volume);
+        balance.set(settings.balance);
+        mono_downmix.set(settings.mono_downmix);
+        {
+            let mut ui = ui_settings.write();
+            ui.crossfade_secs = settings.crossfade_secs;
+            ui.status_palette = settings.status_palette;
+            ui.output_device = settings.output_device;
+        }
+        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+            eprintln!("[Config] 保存界面设置失败: {}", e);
+        }
+        active_profile.set(Some(profile.id));
+    });
+    // The profiles are also in the tray menu, so they switch without bringing up
+    // the window; greyed out while locked, like the rest of the settings
+    let tray = use_hook(build_tray_icon);
+    use_effect(move || {
+        let menu = profiles_tray_menu(&settings_profiles.read(), active_profile.read().as_deref(), locked());
+        if let Some(tray) = tray.as_ref() {
+            tray.set_menu(Some(Box::new(menu)));
+        }
+    });
+    dioxus_desktop::use_tray_menu_event_handler(move |event| {
+        let Some(id) = event.id.as_ref().strip_prefix(TRAY_PROFILE_PREFIX) else { return };
+        if ui_settings.peek().locked {
+            return;
+        }
+        let profile = settings_profiles.peek().iter().find(|p| p.id == id).cloned();
+        if let Some(profile) = profile {
+            eprintln!("[Tray] 切换设置配置: {}", profile.name);
+            apply_profile.call(profile);
+        }
+    });
+    // Integrated loudness (LUFS) by track path, measured in the background
+    let mut track_loudness = use_signal(std::collections::HashMap::<String, f64>::new);
+    let mut loudness_scan = use_signal(|| None::<loudness::LoudnessScanner>);
+    let mut loudness_scanned = use_signal(|| 0usize);
+    // Couldn't be measured this session; not retried until restart
+    let mut loudness_failed = use_signal(std::collections::HashSet::<String>::new);
+
+    // Whatever starts a track, or changes its gain, goes through current_track
+    use_effect(move || {
+        let Some(track) = current_track.read().clone() else { return };
+        let normalization = if ui_settings.read().normalize_loudness {
+            track_loudness.read().get(&track.path).map(|lufs| (loudness::TARGET_LUFS - lufs) as f32)
+        } else {
+            None
+        };
+        if let Some(player) = player_ref.peek().as_ref() {
+            player.set_track_gain(track.gain_db + normalization.unwrap_or(0.0));
+        }
+    });
+
+    // Local tracks in any playlist that haven't been measured yet
+    let unmeasured_tracks = move || -> Vec<String> {
+        let measured = track_loudness.read();
+        let failed = loudness_failed.read();
+        let mut seen = std::collections::HashSet::new();
+        playlists
+            .read()
+            .iter()
+            .flat_map(|playlist| playlist.tracks.iter())
+            .filter(|track| track.source == TrackSource::Local && archive::split_path(&track.path).is_none())
+            .map(|track| track.path.clone())
+            .filter(|path| !measured.contains_key(path) && !failed.contains(path) && seen.insert(path.clone()))
+            .collect()
+    };
+    let start_loudness_scan = use_callback(move |()| {
+        if loudness_scan.peek().is_some() {
+            return;
+        }
+        let paths = unmeasured_tracks();
+        if paths.is_empty() {
+            return;
+        }
+        eprintln!("[Loudness] 开始测量 {} 首曲目的响度", paths.len());
+        let scanner = loudness::LoudnessScanner::start(paths);
+        let Some(mut events) = scanner.take_events() else { return };
+        loudness_scanned.set(0);
+        loudness_scan.set(Some(scanner));
+        spawn(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    loudness::LoudnessEvent::Measured { path, lufs } => {
+                        track_loudness.write().insert(path, lufs);
+                        loudness_scanned += 1;
+                    }
+                    loudness::LoudnessEvent::Failed { path } => {
+                        loudness_failed.write().insert(path);
+                        loudness_scanned += 1;
+                    }
+                    loudness::LoudnessEvent::Finished => break,
+                }
+                // Keep what's measured so far if the app closes mid-scan
+                if *loudness_scanned.peek() % 20 == 0 {
+                    if let Err(e) = save_track_loudness(&track_loudness.peek()) {
+                        eprintln!("[Config] 保存响度数据失败: {}", e);
+                    }
+                }
+            }
+            if let Err(e) = save_track_loudness(&track_loudness.peek()) {
+                eprintln!("[Config] 保存响度数据失败: {}", e);
+            }
+            loudness_scan.set(None);
+        });
+    });
+    // With normalization on, new tracks are measured as they're added
+    use_effect(move || {
+        let enabled = ui_settings.read().normalize_loudness;
+        if enabled && !unmeasured_tracks().is_empty() {
+            start_loudness_scan.call(());
+        }
+    });
+
+    // Adding a folder reads it in the background, filling the playlist in as it goes
+    let mut library_scan = use_signal(|| None::<scanner::LibraryScanner>);
+    // Files read so far, and how many the folder has once it's been walked
+    let mut library_scan_progress = use_signal(|| (0usize, None::<usize>));
+    let start_library_scan = use_callback(move |dir: String| {
+        if let Some(scan) = library_scan.peek().as_ref() {
+            *error_msg.write() = Some(format!("Still adding {}; wait for it to finish or cancel it first", scan.dir()));
+            return;
+        }
+        let idx = *current_playlist.peek();
+        let Some(playlist_id) = playlists.peek().get(idx).map(|p| p.id.clone()) else { return };
+        // Adding a folder again only reads the files that are new or changed
+        let known = known_tracks(&playlists.peek(), idx);
+        let scan = scanner::LibraryScanner::start(dir, known);
+        let Some(mut events) = scan.take_events() else { return };
+        library_scan_progress.set((0, None));
+        library_scan.set(Some(scan));
+        spawn(async move {
+            let (mut added, mut changed) = (0, 0);
+            let mut finished = false;
+            while !finished {
+                let Some(event) = events.recv().await else { break };
+                // Whatever else has arrived goes into the playlist in the same write
+                let mut tracks = Vec::new();
+                let mut progress = *library_scan_progress.peek();
+                for event in std::iter::once(event).chain(std::iter::from_fn(|| events.try_recv().ok())) {
+                    match event {
+                        scanner::ScanEvent::Found(total) => progress.1 = Some(total),
+                        scanner::ScanEvent::Tracks { tracks: ready, done } => {
+                            tracks.extend(ready);
+                            progress.0 = done;
+                        }
+                        scanner::ScanEvent::Finished => finished = true,
+                    }
+                }
+                library_scan_progress.set(progress);
+                if !tracks.is_empty() {
+                    let mut lists = playlists.write();
+                    // The playlist was deleted; dropping the scan below stops it
+                    let Some(playlist) = lists.iter_mut().find(|p| p.id == playlist_id) else { break };
+                    let (a, c) = playlist.add_scanned(tracks);
+                    added += a;
+                    changed += c;
+                }
+                // A few updates a second is plenty for the list and the progress bar
+                if !finished {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+            }
+            eprintln!("[Library] 添加文件夹: 新增 {} 首，更新 {} 首", added, changed);
+            library_scan.set(None);
+        });
+    });
+
+    // Scanning a whole WebDAV server adds every audio file on it to the current
+    // playlist, reading tags over Range requests as it goes
+    let mut cloud_scan = use_signal(|| None::<cloud_scanner::CloudScanner>);
+    let mut cloud_scan_progress = use_signal(|| (0usize, None::<usize>));
+    let start_cloud_scan = use_callback(move |config: WebDAVConfig| {
+        if let Some(scan) = cloud_scan.peek().as_ref() {
+            *error_msg.write() = Some(format!("Still scanning {}; wait for it to finish or cancel it first", scan.name()));
+            return;
+        }
+        let credentials = match config.credentials() {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                *error_msg.write() = Some(format!("Couldn't scan {}: {}", config.name, e));
+                return;
+            }
+        };
+        let idx = *current_playlist.peek();
+        let Some(playlist_id) = playlists.peek().get(idx).map(|p| p.id.clone()) else { return };
+        // Scanning again only reads the files that aren't in the playlist yet
+        let source = TrackSource::WebDAV { config_id: config.id.clone() };
+        let known = playlists.peek()[idx].tracks.iter().filter(|t| t.source == source).map(|t| t.path.clone()).collect();
+        let scan = cloud_scanner::CloudScanner::start(config, credentials, known);
+        let Some(mut events) = scan.take_events() else { return };
+        let config_id = scan.config_id().to_string();
+        cloud_scan_progress.set((0, None));
+        cloud_scan.set(Some(scan));
+        spawn(async move {
+            let mut report = ImportReport { config_id, ..Default::default() };
+            let mut finished = false;
+            while !finished {
+                let Some(event) = events.recv().await else { break };
+                let mut tracks = Vec::new();
+                let mut progress = *cloud_scan_progress.peek();
+                for event in std::iter::once(event).chain(std::iter::from_fn(|| events.try_recv().ok())) {
+                    match event {
+                        cloud_scanner::CloudScanEvent::Found(total) => progress.1 = Some(total),
+                        cloud_scanner::CloudScanEvent::Tracks { tracks: ready, done } => {
+                            tracks.extend(ready.into_iter().map(TrackStub::from));
+                            progress.0 = done;
+                        }
+                        cloud_scanner::CloudScanEvent::Failed { path, name, reason } => {
+                            report.failed.push(ImportFailure { path, name, reason });
+                        }
+                        cloud_scanner::CloudScanEvent::Error(e) => *error_msg.write() = Some(format!("Couldn't scan the server: {}", e)),
+                        cloud_scanner::CloudScanEvent::Finished => finished = true,
+                    }
+                }
+                cloud_scan_progress.set(progress);
+                if !tracks.is_empty() {
+                    let mut lists = playlists.write();
+                    // The playlist was deleted; dropping the scan below stops it
+                    let Some(playlist) = lists.iter_mut().find(|p| p.id == playlist_id) else { break };
+                    report.imported += playlist.add_scanned(tracks).0;
+                }
+                if !finished {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+            }
+            // Only once the server was listed; a failed listing has said why already
+            if cloud_scan_progress.peek().1.is_some() {
+                eprintln!("[WebDAV] 扫描完成: 新增 {} 首, {} 首失败", report.imported, report.failed.len());
+                import_report.set(Some(report));
+            }
+            cloud_scan.set(None);
+        });
+    });
+
+    // Folders marked for sync on each server are kept on disk by a background
+    // job, which starts over whenever the servers or their folders change
+    let folder_sync = use_signal(webdav_sync::WebDAVSync::start);
+    // By (server id, folder)
+    let mut sync_status = use_signal(std::collections::HashMap::<(String, String), webdav_sync::FolderStatus>::new);
+    use_hook(move || {
+        let Some(mut events) = folder_sync.peek().take_events() else { return };
+        spawn(async move {
+            while let Some(event) = events.recv().await {
+                let mut statuses = sync_status.write();
+                for event in std::iter::once(event).chain(std::iter::from_fn(|| events.try_recv().ok())) {
+                    statuses.insert((event.config_id, event.folder), event.status);
+                }
+                drop(statuses);
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        });
+    });
+    use_effect(move || {
+        let configs = webdav_configs();
+        // Playback downloads and prefetching keep to these limits as well as the sync
+        for config in &configs {
+            throttle::set_limit(&config.url, config.download_limit_kbps);
+        }
+        let mut targets = Vec::new();
+        for config in configs.into_iter().filter(|c| c.enabled) {
+            match config.credentials() {
+                Ok(credentials) => targets.push(webdav_sync::SyncTarget { config, credentials }),
+                Err(e) => eprintln!("[Sync] 无法同步 {}: {}", config.name, e),
+            }
+        }
+        folder_sync.peek().set_targets(targets);
+    });
+
+    // The organizer moves library files into a tag-based layout, then points
+    // everything that remembers a track by path at where it went
+    let mut show_organizer = use_signal(|| false);
+    let mut organizing = use_signal(|| false);
+    let organize_library = use_callback(move |(root, pattern): (String, String)| {
+        if organizing() {
+            return;
+        }
+        {
+            let mut settings = ui_settings.write();
+            settings.organize_root = Some(root.clone());
+            settings.organize_pattern = pattern.clone();
+        }
+        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+            eprintln!("[Config] 保存界面设置失败: {}", e);
+        }
+        organizing.set(true);
+        spawn(async move {
+            let tracks: Vec<TrackStub> = playlists
+                .peek()
+                .iter()
+                .filter(|playlist| playlist.smart.is_none())
+                .flat_map(|playlist| playlist.tracks.iter().cloned())
+                .collect();
+            let result = tokio::task::spawn_blocking(move || {
+                let root = std::path::PathBuf::from(root);
+                let moves = organize::plan(&root, &pattern, &tracks);
+                organize::apply(&root, &moves).map(|()| moves)
+            })
+            .await;
+            organizing.set(false);
+            let moves = match result {
+                Ok(Ok(moves)) => moves,
+                Ok(Err(e)) => {
+                    eprintln!("[Organize] 整理失败，已全部移回: {}", e);
+                    error_msg.set(Some(format!("Couldn't organize the library, nothing was moved: {}", e)));
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("[Organize] 整理任务异常: {}", e);
+                    return;
+                }
+            };
+            let moved: std::collections::HashMap<String, String> = moves
+                .iter()
+                .map(|m| (m.from.to_string_lossy().into_owned(), m.to.to_string_lossy().into_owned()))
+                .collect();
+            let repoint = |path: &mut String| {
+                if let Some(to) = moved.get(path.as_str()) {
+                    *path = to.clone();
+                }
+            };
+            for playlist in playlists.write().iter_mut() {
+                playlist.tracks.iter_mut().for_each(|track| repoint(&mut track.path));
+            }
+            if let Some(track) = current_track.write().as_mut() {
+                repoint(&mut track.path);
+            }
+            play_queue.write().iter_mut().for_each(|track| repoint(&mut track.path));
+            recently_played.write().iter_mut().for_each(|recent| repoint(&mut recent.track.path));
+            play_history.write().iter_mut().for_each(|entry| repoint(&mut entry.path));
+            rekey_moved_paths(&mut track_ratings.write(), &moved);
+            rekey_moved_paths(&mut track_loudness.write(), &moved);
+            rekey_moved_paths(&mut track_chapters.write(), &moved);
+            resume::rekey(&moved);
+            {
+                let mut favorites = favorite_tracks.write();
+                for (from, to) in &moved {
+                    if favorites.remove(from) {
+                        favorites.insert(to.clone());
+                    }
+                }
+            }
+            let saved = [
+                save_recently_played(&recently_played.peek()),
+                save_play_history(&play_history.peek()),
+                save_track_ratings(&track_ratings.peek()),
+                save_favorite_tracks(&favorite_tracks.peek()),
+                save_track_loudness(&track_loudness.peek()),
+                save_track_chapters(&track_chapters.peek()),
+                get_config_dir().and_then(|dir| resume::save(&dir)),
+            ];
+            for e in saved.into_iter().filter_map(Result::err) {
+                eprintln!("[Config] 保存整理后的路径失败: {}", e);
+            }
+            eprintln!("[Organize] 已移动 {} 个文件", moves.len());
+            show_organizer.set(false);
+        });
+    });
+
+    // Lyric and cover files dropped next to local tracks while the app is open
+    let sidecar_watcher = use_hook(|| match file_watcher::SidecarWatcher::start() {
+        Ok(watcher) => Some(Arc::new(watcher)),
+        Err(e) => {
+            eprintln!("[Watcher] 无法启动文件监视: {}", e);
+            None
+        }
+    });
+    let watcher_for_dirs = sidecar_watcher.clone();
+    use_effect(move || {
+        let dirs = playlists
+            .read()
+            .iter()
+            .flat_map(|playlist| playlist.tracks.iter())
+            .filter(|track| track.source == TrackSource::Local && archive::split_path(&track.path).is_none())
+            .filter_map(|track| Path::new(&track.path).parent().map(Path::to_path_buf))
+            .collect();
+        if let Some(watcher) = &watcher_for_dirs {
+            watcher.watch_dirs(dirs);
+        }
+    });
+    let _sidecar_future = use_future(move || {
+        let receiver = sidecar_watcher.as_ref().and_then(|watcher| watcher.take_events());
+        async move {
+            let Some(mut events) = receiver else { return };
+            while let Some(first) = events.recv().await {
+                // A download arrives as several writes; handle them once it settles
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                let mut changed = vec![first];
+                while let Ok(event) = events.try_recv() {
+                    changed.push(event);
+                }
+
+                let mut cover_dirs = std::collections::HashSet::new();
+                for event in changed {
+                    match event {
+                        file_watcher::SidecarEvent::Lyrics(lyric_path) => {
+                            if let Some(player) = player_ref.peek().as_ref() {
+                                player.lyric_file_changed(&lyric_path);
+                            }
+                        }
+                        file_watcher::SidecarEvent::Cover(dir) => {
+                            cover_dirs.insert(dir);
+                        }
+                    }
+                }
+
+                for dir in cover_dirs {
+                    let paths: std::collections::HashSet<String> = playlists
+                        .peek()
+                        .iter()
+                        .flat_map(|playlist| playlist.tracks.iter())
+                        .filter(|track| track.source == TrackSource::Local && Path::new(&track.path).parent() == Some(dir.as_path()))
+                        .map(|track| track.path.clone())
+                        .collect();
+                    if paths.is_empty() {
+                        continue;
+                    }
+                    // Tracks with art of their own keep it, as on import
+                    let refreshed = tokio::task::spawn_blocking(move || {
+                        let cover = find_cover_image_in_dir(&dir);
+                        let uses_folder: std::collections::HashSet<String> = paths
+                            .into_iter()
+                            .filter(|path| TrackMetadata::from_file(Path::new(path)).map(|t| t.cover.is_none()).unwrap_or(true))
+                            .collect();
+                        (cover, uses_folder)
+                    })
+                    .await;
+                    let Ok((cover, uses_folder)) = refreshed else { continue };
+                    eprintln!("[Watcher] 封面已更新，涉及 {} 首曲目", uses_folder.len());
+
+                    for playlist in playlists.write().iter_mut() {
+                        for track in playlist.tracks.iter_mut().filter(|t| uses_folder.contains(&t.path)) {
+                            track.cover = cover.clone();
+                        }
+                    }
+                    if let Some(track) = current_track.write().as_mut().filter(|t| uses_folder.contains(&t.path)) {
+                        track.cover = cover.clone();
+                    }
+                }
+            }
+        }
+    });
+
+    // A playing track with no art, embedded or beside it, gets its album's front
+    // cover from the Cover Art Archive; every track of the album without art
+    // takes it too. Each album is only asked about once a session.
+    let mut cover_lookups = use_signal(std::collections::HashSet::<(String, String)>::new);
+    use_effect(move || {
+        let Some(track) = current_track() else { return };
+        let known = |name: &str, unknown: &str| !name.trim().is_empty() && name != unknown;
+        // Compilations are looked up as Various Artists, like MusicBrainz files them
+        let album_artist = track.album_artist_or_artist().to_string();
+        if track.cover.is_some() || !known(&album_artist, "Unknown Artist") || !known(&track.album, "Unknown Album") {
+            return;
+        }
+        if !cover_lookups.write().insert((album_artist.clone(), track.album.clone())) {
+            return;
+        }
+        spawn(async move {
+            let (artist, album) = (album_artist.clone(), track.album.clone());
+            let found = tokio::task::spawn_blocking(move || {
+                cover_cache::store_album(&artist, &album, || match musicbrainz::front_cover(&artist, &album) {
+                    Ok(cover) => cover,
+                    Err(e) => {
+                        eprintln!("[Cover] 在线封面查找失败: {}", e);
+                        None
+                    }
+                })
+            })
+            .await;
+            let Ok(Some(cover)) = found else { return };
+            let same_album = |t: &TrackStub| t.cover.is_none() && t.album_artist_or_artist() == album_artist && t.album == track.album;
+            for playlist in playlists.write().iter_mut() {
+                for entry in playlist.tracks.iter_mut().filter(|t| same_album(t)) {
+                    entry.cover = Some(cover.clone());
+                }
+            }
+            if let Some(entry) = current_track.write().as_mut().filter(|t| same_album(t)) {
+                entry.cover = Some(cover);
+            }
+        });
+    });
+
+    // Music folders playlists watch: files added, renamed or deleted there show up
+    // as it happens, and what changed while the app was closed once it loads
+    let folder_watcher = use_hook(|| match file_watcher::FolderWatcher::start() {
+        Ok(watcher) => Some(Arc::new(watcher)),
+        Err(e) => {
+            eprintln!("[Watcher] 无法启动文件夹监视: {}", e);
+            None
+        }
+    });
+    let watcher_for_folders = folder_watcher.clone();
+    use_effect(move || {
+        let dirs = playlists
+            .read()
+            .iter()
+            .flat_map(|playlist| playlist.watched_folders.iter())
+            .map(std::path::PathBuf::from)
+            .collect();
+        if let Some(watcher) = &watcher_for_folders {
+            watcher.watch_dirs(dirs);
+        }
+    });
+    // Bring a playlist in line with what's on disk at or beneath `path`, in one of
+    // its watched folders: new and changed files are read, ones that are gone
+    // removed. Nothing is removed while the watched folder itself is missing,
+    // since that's more likely an unplugged drive than a deleted library.
+    let sync_watched_path = use_callback(move |(playlist_id, path): (String, std::path::PathBuf)| {
+        let Some((roots, known)) = playlists.peek().iter().find(|p| p.id == playlist_id).map(|playlist| {
+            let roots: Vec<String> = playlist.watched_folders.iter().filter(|f| path.starts_with(f)).cloned().collect();
+            let known: std::collections::HashMap<String, TrackStub> = playlist
+                .tracks
+                .iter()
+                .filter(|t| Path::new(&t.path).starts_with(&path))
+                .map(|t| (t.path.clone(), t.clone()))
+                .collect();
+            (roots, known)
+        }) else {
+            return;
+        };
+        spawn(async move {
+            let scan = tokio::task::spawn_blocking(move || {
+                if !roots.iter().any(|root| Path::new(root).is_dir()) {
+                    return None;
+                }
+                let gone: Vec<String> = known.keys().filter(|p| !Path::new(p).exists()).cloned().collect();
+                let tracks = if path.exists() {
+                    scan_music_directory(&path.to_string_lossy(), &known).unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                let changed = !gone.is_empty() || tracks.iter().any(|t| known.get(&t.path) != Some(t));
+                changed.then_some((tracks, gone))
+            })
+            .await;
+            let Ok(Some((tracks, gone))) = scan else { return };
+            let mut lists = playlists.write();
+            let Some(playlist) = lists.iter_mut().find(|p| p.id == playlist_id) else { return };
+            let removed: usize = gone.iter().map(|p| playlist.remove_under(Path::new(p))).sum();
+            let (added, changed) = playlist.add_scanned(tracks);
+            eprintln!("[Library] {}: 新增 {} 首，更新 {} 首，移除 {} 首", playlist.name, added, changed, removed);
+        });
+    });
+    let _folder_future = use_future(move || {
+        let receiver = folder_watcher.as_ref().and_then(|watcher| watcher.take_events());
+        async move {
+            let Some(mut events) = receiver else { return };
+            while let Some(first) = events.recv().await {
+                // Copying an album in arrives as many writes; go over them once it settles
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                let mut changes = vec![first];
+                while let Ok(event) = events.try_recv() {
+                    changes.push(event);
+                }
+
+                let mut to_sync: Vec<(String, std::path::PathBuf)> = Vec::new();
+                for change in changes {
+                    let (from, to) = match change {
+                        file_watcher::FolderEvent::Changed(path) | file_watcher::FolderEvent::Removed(path) => (None, path),
+                        file_watcher::FolderEvent::Renamed { from, to } => (Some(from), to),
+                    };
+                    // Moved within a watched folder: the tracks keep their entries
+                    if let Some(from) = &from {
+                        let moves = playlists
+                            .peek()
+                            .iter()
+                            .any(|p| p.watches(&to) && p.tracks.iter().any(|t| Path::new(&t.path).starts_with(from)));
+                        if moves {
+                            for playlist in playlists.write().iter_mut().filter(|p| p.watches(&to)) {
+                                playlist.rename_under(from, &to);
+                            }
+                        }
+                    }
+                    for path in from.into_iter().chain([to]) {
+                        for playlist in playlists.peek().iter().filter(|p| p.watches(&path)) {
+                            let key = (playlist.id.clone(), path.clone());
+                            if !to_sync.contains(&key) {
+                                to_sync.push(key);
+                            }
+                        }
+                    }
+                }
+                for key in to_sync {
+                    sync_watched_path.call(key);
+                }
+            }
+        }
+    });
+
+    use_effect(|| diagnostics::startup_mark("first frame"));
+    let _library_load_future = use_future(move || {
+        let session = session.clone();
+        async move {
+            let Ok(library) = tokio::task::spawn_blocking(load_library).await else { return };
+            let index = session.playlist_index.min(library.playlists.len() - 1);
+            let track = session
+                .track_id
+                .as_ref()
+                .and_then(|id| library.playlists[index].tracks.iter().find(|t| &t.id == id).cloned());
+            playlists.set(library.playlists);
+            current_playlist.set(index);
+            webdav_configs.set(library.webdav_configs);
+            play_history.set(library.play_history);
+            recently_played.set(library.recently_played);
+            track_ratings.set(library.track_ratings);
+            favorite_tracks.set(library.favorite_tracks);
+            track_chapters.set(library.track_chapters);
+            track_loudness.set(library.track_loudness);
+
+            // Reload the restored track, paused at its saved position
+            if let Some(track) = track {
+                if let Some(player) = player_ref.peek().as_ref() {
+                    player.set_start_position(session.position);
+                    play_track(player, &track, &webdav_configs.peek());
+                    player.set_volume(*volume.peek());
+                }
+                current_time.set(session.position);
+                player_state.set(PlayerState::Paused);
+                current_track.set(Some(track));
+            }
+            library_loaded.set(true);
+            diagnostics::startup_mark("library loaded");
+
+            let watched: Vec<(String, std::path::PathBuf)> = playlists
+                .peek()
+                .iter()
+                .flat_map(|p| p.watched_folders.iter().map(|folder| (p.id.clone(), std::path::PathBuf::from(folder))))
+                .collect();
+            for key in watched {
+                sync_watched_path.call(key);
+            }
+        }
+    });
+
+    // Held only while playing, so the system can't suspend on idle mid-track
+    let mut sleep_inhibitor = use_signal(|| None::<sleep_inhibit::SleepInhibitor>);
+    use_effect(move || {
+        let playing = player_state() == PlayerState::Playing;
+        if playing == sleep_inhibitor.peek().is_some() {
+            return;
+        }
+        if !playing {
+            sleep_inhibitor.set(None);
+            return;
+        }
+        match sleep_inhibit::SleepInhibitor::acquire() {
+            Ok(inhibitor) => sleep_inhibitor.set(Some(inhibitor)),
+            Err(e) => eprintln!("[Player] 无法阻止系统休眠: {}", e),
+        }
+    });
+
+    // Save the session now and then, and once more when the window closes
+    let session_snapshot = move || PlaybackSession {
+        playlist_index: *current_playlist.peek(),
+        track_id: current_track.peek().as_ref().map(|t| t.id.clone()),
+        position: Duration::from_secs(current_time.peek().as_secs()),
+        volume: *volume.peek(),
+    };
+    let mut saved_session = use_signal(|| None::<PlaybackSession>);
+    let mut saved_playlists = use_signal(|| None::<Vec<Playlist>>);
+    let mut persist_session = move || {
+        // The placeholder playlist must never overwrite the saved ones
+        if !*library_loaded.peek() {
+            return;
+        }
+        let snapshot = session_snapshot();
+        if saved_session.peek().as_ref() != Some(&snapshot) {
+            match save_playback_session(&snapshot) {
+                Ok(()) => saved_session.set(Some(snapshot)),
+                Err(e) => eprintln!("[Session] 保存播放状态失败: {}", e),
+            }
+        }
+        if saved_playlists.peek().as_ref() != Some(&*playlists.peek()) {
+            let lists = playlists.peek().clone();
+            match save_session_playlists(&lists) {
+                Ok(()) => saved_playlists.set(Some(lists)),
+                Err(e) => eprintln!("[Session] 保存播放列表失败: {}", e),
+            }
+        }
+        if let Err(e) = get_config_dir().and_then(|dir| resume::save(&dir)) {
+            eprintln!("[Session] 保存续播位置失败: {}", e);
+        }
+    };
+    let _session_save_future = use_future(move || async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            persist_session();
+        }
+    });
+    // The webview's own zoom, so text and icons are re-rendered at the new size
+    // rather than stretched
+    let desktop = dioxus_desktop::window();
+    use_effect({
+        let desktop = desktop.clone();
+        move || desktop.set_zoom_level(ui_settings.read().ui_scale_percent as f64 / 100.0)
+    });
+    dioxus_desktop::use_wry_event_handler(move |event, _| {
+        use dioxus_desktop::tao::event::{Event, WindowEvent};
+        match event {
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => persist_session(),
+            // Moved to a monitor with different scaling. Some webviews drop their zoom
+            // when that happens, so it's put back on top of the new scale factor.
+            Event::WindowEvent { event: WindowEvent::ScaleFactorChanged { scale_factor, .. }, .. } => {
+                eprintln!("[UI] 显示缩放变为 {:.2}", scale_factor);
+                desktop.set_zoom_level(ui_settings.peek().ui_scale_percent as f64 / 100.0);
+            }
+            _ => {}
+        }
+    });
+    let set_ui_scale = use_callback(move |percent: u32| {
+        ui_settings.write().ui_scale_percent = percent.clamp(UI_SCALE_MIN, UI_SCALE_MAX);
+        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+            eprintln!("[Config] 保存界面设置失败: {}", e);
+        }
+    });
+
+    let global_state = get_global_state().clone();
+    let player_ref_clone = player_ref.clone();
+
+    // Device recovery, level meters and lyrics still need a steady refresh
+    let _time_update_future = use_future(move || {
+        let player_ref_clone = player_ref_clone.clone();
+
+        async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+                let player_guard = player_ref_clone.read();
+                if let Some(player) = player_guard.as_ref() {
+                    // Reopen the output if the audio device was unplugged
+                    if player.is_device_lost() {
+                        if let Err(e) = player.recover_output_device() {
+                            eprintln!("[UI] 音频设备恢复失败: {}", e);
+                        }
+                    }
+
+                    // Peak meter
+                    *peak_levels.write() = player.take_peak_levels();
+
+                    // Sync lyrics from player
+                    if let Some(lyric) = player.get_lyric() {
+                        *current_lyric.write() = Some(lyric);
+                    }
+                }
+            }
+        }
+    });
+
+    // Position, metadata, track-end and error notifications pushed by the player
+    let _player_events_future = use_future(move || {
+        let global_state = global_state.clone();
+        async move {
+            let receiver = player_ref.read().as_ref().and_then(|player| player.take_events());
+            let Some(mut events) = receiver else {
+                return;
+            };
+
+            while let Some(event) = events.recv().await {
+                match event {
+                    PlayerEvent::Progress { position } => {
+                        *current_time.write() = position;
+                        if let Some(current) = current_track.peek().as_ref() {
+                            let resumes = playlists.peek().get(*current_playlist.peek()).is_some_and(|playlist| {
+                                playlist.resumes(&ui_settings.peek().content_types) && playlist.tracks.iter().any(|t| t.id == current.id)
+                            });
+                            if resumes {
+                                resume::remember(&current.path, position);
+                            }
+                        }
+
+                        let duration = *current_duration.peek();
+                        if duration.is_zero() || duration.saturating_sub(position) > PREFETCH_LEAD {
+                            continue;
+                        }
+                        let Some(current_id) = current_track.peek().as_ref().map(|current| current.id.clone()) else {
+                            continue;
+                        };
+                        let next = upcoming_track.call(current_id.clone());
+                        let Some(player) = player_ref.peek().clone() else {
+                            continue;
+                        };
+                        let wanted = next.as_ref().map(|next| (current_id.clone(), next.id.clone()));
+                        if *queued_next.peek() == wanted {
+                            continue;
+                        }
+                        if queued_next.peek().is_some() {
+                            player.clear_next();
+                            queued_next.set(None);
+                        }
+                        if let Some(next) = next {
+                            prefetch_track(&player, &next, &webdav_configs.peek());
+                            // Hand over without going through TrackEnded, once the prefetch is ready
+                            if advances_after.call(current_id.clone()) && queue_track(&player, &next, &webdav_configs.peek()) {
+                                queued_next.set(Some((current_id, next.id)));
+                            }
+                        }
+                    }
+                    PlayerEvent::MetadataLoaded { metadata, duration } => {
+                        *current_duration.write() = duration;
+                        *now_playing_metadata.write() = Some(metadata);
+                    }
+                    PlayerEvent::Error { track_id, error } => {
+                        eprintln!("[UI] 播放错误: {}", error);
+                        let current = current_track.peek().clone();
+                        // A track that was replaced before it failed isn't worth reporting
+                        if current.as_ref().is_some_and(|track| track_id.as_ref().is_some_and(|id| *id != track.id)) {
+                            continue;
+                        }
+                        *player_state.write() = PlayerState::Stopped;
+                        *error_msg.write() = Some(match current {
+                            Some(track) => format!("Couldn't play \"{}\": {}", track.title, error),
+                            None => format!("Couldn't play: {}", error),
+                        });
+                    }
+                    PlayerEvent::QueuedTrackStarted { track_id } => {
+                        queued_next.set(None);
+                        let Some(id) = track_id else {
+                            continue;
+                        };
+                        if let Some(previous) = current_track.peek().as_ref() {
+                            global_state.set_last_track(previous.id.clone());
+                        }
+                        let next_track = play_queue.peek().first().filter(|t| t.id == id).cloned().or_else(|| {
+                            playlists
+                                .peek()
+                                .get(*current_playlist.peek())
+                                .and_then(|playlist| playlist.tracks.iter().find(|t| t.id == id).cloned())
+                        });
+                        let Some(next_track) = next_track else {
+                            continue;
+                        };
+                        eprintln!("[UI] 已衔接下一首: {}", next_track.title);
+                        let reason = advance_reason(take_from_queue.call(next_track.clone()));
+                        record_play(play_history, recently_played, &next_track, reason);
+                        *current_track.write() = Some(next_track);
+                        *player_state.write() = PlayerState::Playing;
+                    }
+                    PlayerEvent::TrackEnded { track_id } => {
+                        eprintln!("[UI] 检测到曲目自然结束");
+                        let Some(id) = track_id else {
+                            continue;
+                        };
+                        global_state.set_last_track(id.clone());
+                        if let Some(ended) = current_track.peek().as_ref().filter(|t| t.id == id) {
+                            resume::forget(&ended.path);
+                        }
+
+                        if !advances_after(id.clone()) {
+                            *player_state.write() = PlayerState::Stopped;
+                            continue;
+                        }
+
+                        match upcoming_track(id) {
+                            Some(next_track) => {
+                                eprintln!("[UI] 自动播放下一首: {}", next_track.title);
+                                let reason = advance_reason(take_from_queue.call(next_track.clone()));
+                                if let Some(ref player) = *player_ref.read() {
+                                    play_track(player, &next_track, &webdav_configs());
+                                    record_play(play_history, recently_played, &next_track, reason);
+                                    player.set_volume(volume());
+                                }
+                                *current_track.write() = Some(next_track);
+                                *player_state.write() = PlayerState::Playing;
+                            }
+                            None => {
+                                eprintln!("[UI] 播放列表已结束");
+                                *player_state.write() = PlayerState::Stopped;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // Volume changes that don't come from the slider (media keys) flash an overlay,
+    // since the slider may be scrolled away or the window in the background
+    let mut volume_osd_visible = use_signal(|| false);
+    let mut volume_osd_generation = use_signal(|| 0u64);
+    let mut volume_before_mute = use_signal(|| None::<f32>);
+    let on_volume_key = use_callback(move |key: VolumeKey| {
+        let current = *volume.peek();
+        let muted_from = volume_before_mute.write().take();
+        let new_volume = match key {
+            VolumeKey::Up => (current + VOLUME_KEY_STEP).min(1.0),
+            VolumeKey::Down => (current - VOLUME_KEY_STEP).max(0.0),
+            VolumeKey::Mute => match muted_from {
+                Some(previous) if current == 0.0 => previous,
+                _ => {
+                    volume_before_mute.set(Some(current));
+                    0.0
+                }
+            },
+        };
+        if let Some(ref player) = *player_ref.peek() {
+            player.set_volume(new_volume);
+        }
+        volume.set(new_volume);
+
+        volume_osd_visible.set(true);
+        let generation = *volume_osd_generation.peek() + 1;
+        volume_osd_generation.set(generation);
+        spawn(async move {
+            tokio::time::sleep(VOLUME_OSD_DURATION).await;
+            if *volume_osd_generation.peek() == generation {
+                volume_osd_visible.set(false);
+            }
+        });
+    });
+
+    // We'll access it directly in the closures since Signal is Copy
+
+    let header_icon = use_signal(|| load_header_icon());
+
+    rsx! {
+        div { class: "h-screen bg-gradient-to-b from-gray-900 to-black text-white overflow-y-auto flex flex-col {ui_settings.read().status_palette.root_class()}",
+
+            if ui_settings.read().capture_volume_keys {
+                VolumeKeyShortcuts { on_key: on_volume_key }
+            }
+            if volume_osd_visible() {
+                VolumeOsd { volume: volume() }
+            }
+
+            header { class: "bg-gray-800 shadow-lg p-6",
                 div { class: "max-w-7xl mx-auto",
                     h1 { class: "text-4xl font-bold mb-2 flex items-center gap-3",
                         if let Some(icon_url) = header_icon.read().as_ref() {
@@ -929,590 +3067,4750 @@ fn App() -> Element {
                                 alt: "Music Player Icon",
                                 class: "w-8 h-8",
                             }
-                        } else {
-                            span { "🎵" }
-                        }
-                        "Dioxus Music Player"
+                        } else {
+                            span { "🎵" }
+                        }
+                        "Dioxus Music Player"
+                    }
+                    input {
+                        class: "w-full mt-4 px-3 py-2 rounded bg-gray-700 border border-gray-600 text-sm text-white",
+                        placeholder: "🔍 Search every playlist by title, artist or album...",
+                        value: "{global_search}",
+                        oninput: move |e: FormEvent| global_search.set(e.value()),
+                        onkeydown: move |e: KeyboardEvent| {
+                            if e.key() == Key::Escape {
+                                global_search.set(String::new());
+                            }
+                        },
+                    }
+                    // p { class: "text-gray-400",
+                    // "Control your music with play, pause, seek, and playlist management"
+                    // }
+                    div { class: "mt-4 flex gap-2",
+                        // Locked, only playback is left: everything up here edits something
+                        if !locked() {
+                            button {
+                                class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded text-sm",
+                                onclick: move |_| *show_directory_browser.write() = true,
+                                "📁 Add Music"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Re-read the tags of local files that changed since they were added",
+                                disabled: refreshing_library(),
+                                onclick: move |_| refresh_library.call(()),
+                                if refreshing_library() { "🔄 Refreshing…" } else { "🔄 Refresh Library" }
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Find the same song more than once across your playlists and keep one copy",
+                                onclick: move |_| find_duplicates.call(()),
+                                "🧬 Duplicates"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Track, album and artist counts, most played artists and albums, and disk use per format",
+                                onclick: move |_| open_library_stats.call(()),
+                                "📊 Stats"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Move library files into Artist/Album folders named from their tags",
+                                onclick: move |_| show_organizer.set(true),
+                                "🗂 Organize"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-purple-600 hover:bg-purple-700 rounded text-sm",
+                                onclick: move |_| *show_webdav_config_list.write() = true,
+                                "☁️ WebDAV Config"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Stream your Bandcamp purchases, or download them as FLAC into a library folder",
+                                onclick: move |_| show_bandcamp.set(true),
+                                "🎧 Bandcamp"
+                            }
+                            if cfg!(feature = "ytdlp") {
+                                button {
+                                    class: "px-4 py-2 bg-red-600 hover:bg-red-700 rounded text-sm",
+                                    title: "Add a YouTube or SoundCloud page to the current playlist, played through yt-dlp",
+                                    onclick: move |_| show_online_track.set(true),
+                                    "▶️ Add from URL"
+                                }
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                onclick: move |_| *show_play_history.write() = true,
+                                "🕘 History"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                onclick: move |_| *show_settings_profiles.write() = true,
+                                "🎛 Profiles"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Switch user: each has its own playlists, cloud servers, history and settings",
+                                onclick: move |_| show_user_profiles.set(true),
+                                "👤 {user_profile.read().as_ref().map(|p| p.name.clone()).unwrap_or_default()}"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "How clicks on playlist and cloud rows behave: single-click plays, double-click plays (click selects), or touch with larger rows",
+                                onclick: move |_| {
+                                    ui_settings.write().click_mode = click_mode().next();
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                "{click_mode().label()}"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Switch status indicators to an orange/blue palette that stays distinguishable with red-green color blindness",
+                                onclick: move |_| {
+                                    let palette = ui_settings.peek().status_palette.toggled();
+                                    ui_settings.write().status_palette = palette;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                "{ui_settings.read().status_palette.label()}"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Switch between three columns and a browse-and-queue layout: browser on the left, queue on the right, drag rows across to enqueue",
+                                onclick: move |_| {
+                                    let layout = ui_settings.peek().layout.toggled();
+                                    ui_settings.write().layout = layout;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                "{ui_settings.read().layout.label()}"
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Let the keyboard's volume up / down / mute keys control the player instead of the system volume, even while the window is in the background",
+                                onclick: move |_| {
+                                    let capture = !ui_settings.peek().capture_volume_keys;
+                                    ui_settings.write().capture_volume_keys = capture;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                if ui_settings.read().capture_volume_keys {
+                                    "🔊 Volume keys: player"
+                                } else {
+                                    "🔈 Volume keys: system"
+                                }
+                            }
+                            button {
+                                class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Startup timings, and a zip of platform info, redacted settings and crash reports for bug reports",
+                                onclick: move |_| show_diagnostics.set(true),
+                                "🩺 Diagnostics"
+                            }
+                            button {
+                                class: if party_server.read().is_some() { "px-4 py-2 bg-pink-600 hover:bg-pink-700 rounded text-sm" } else { "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm" },
+                                title: "Let guests on the local network search the library and request songs from their phones; requests wait for your approval",
+                                onclick: move |_| {
+                                    if party_server.peek().is_some() {
+                                        party_server.set(None);
+                                        party_queue.set(Vec::new());
+                                        return;
+                                    }
+                                    match party::PartyServer::start(party::DEFAULT_PORT) {
+                                        Ok(server) => {
+                                            if let Some(mut requests) = server.take_events() {
+                                                spawn(async move {
+                                                    while requests.recv().await.is_some() {
+                                                        refresh_party_queue();
+                                                    }
+                                                });
+                                            }
+                                            party_server.set(Some(server));
+                                        }
+                                        Err(e) => {
+                                            *error_msg.write() = Some(format!("Couldn't start party mode on port {}: {}", party::DEFAULT_PORT, e));
+                                        }
+                                    }
+                                },
+                                "🎉 Party mode"
+                            }
+                            if current_webdav_config().is_some()
+                                && webdav_configs().len() > current_webdav_config().unwrap_or(0)
+                            {
+                                button {
+                                    class: "px-4 py-2 bg-teal-600 hover:bg-teal-700 rounded text-sm",
+                                    onclick: move |_| {
+                                        *show_webdav_browser.write() = true;
+                                        // Initial load if empty and config exists
+                                        if webdav_items.read().is_empty() {
+                                            if let Some(idx) = current_webdav_config() {
+                                                if idx < webdav_configs.read().len() {
+                                                    let cfg = webdav_configs.read()[idx].clone();
+                                                    let path = webdav_current_path();
+                                                    *webdav_is_loading.write() = true;
+                                                    spawn(async move {
+                                                        match load_webdav_folder(&cfg, &path).await {
+                                                            Ok(items) => {
+                                                                *webdav_items.write() = items;
+                                                                *webdav_error.write() = None;
+                                                            }
+                                                            Err(e) => {
+                                                                *webdav_error.write() = Some(format!("Error: {}", e));
+                                                            }
+                                                        }
+                                                        *webdav_is_loading.write() = false;
+                                                    });
+                                                }
+                                            }
+                                        }
+                                    },
+                                    "🌐 Browse Cloud"
+                                }
+                            }
+                        }
+                        div {
+                            class: "flex items-center bg-gray-700 rounded text-sm",
+                            title: "Interface size, on top of the display's own scaling",
+                            button {
+                                class: "px-3 py-2 hover:bg-gray-600 rounded disabled:opacity-50",
+                                disabled: ui_settings.read().ui_scale_percent <= UI_SCALE_MIN,
+                                onclick: move |_| set_ui_scale.call(ui_settings.peek().ui_scale_percent.saturating_sub(UI_SCALE_STEP)),
+                                "−"
+                            }
+                            button {
+                                class: "px-2 py-2 hover:bg-gray-600 rounded w-16",
+                                title: "Reset to 100%",
+                                onclick: move |_| set_ui_scale.call(default_ui_scale_percent()),
+                                "{ui_settings.read().ui_scale_percent}%"
+                            }
+                            button {
+                                class: "px-3 py-2 hover:bg-gray-600 rounded disabled:opacity-50",
+                                disabled: ui_settings.read().ui_scale_percent >= UI_SCALE_MAX,
+                                onclick: move |_| set_ui_scale.call(ui_settings.peek().ui_scale_percent + UI_SCALE_STEP),
+                                "+"
+                            }
+                        }
+                        button {
+                            class: if locked() { "px-4 py-2 bg-yellow-600 hover:bg-yellow-700 rounded text-sm" } else { "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded text-sm" },
+                            title: "Lock mode: leave only playback controls available, for kiosks, kids or a shop's background music. Unlocking takes a PIN.",
+                            onclick: move |_| {
+                                if !locked() && ui_settings.peek().lock_pin_hash.is_some() {
+                                    lock_ui.call(());
+                                } else {
+                                    show_lock.set(true);
+                                }
+                            },
+                            if locked() { "🔓 Unlock" } else { "🔒 Lock" }
+                        }
+                    }
+                }
+            }
+
+            main { class: "flex-1 max-w-7xl mx-auto p-6 overflow-y-auto",
+
+                div { class: "{ui_settings.read().layout.grid_class()}",
+
+                    aside { class: "col-span-1 h-[calc(100vh-12rem)] overflow-y-auto",
+                        if show_webdav_browser() {
+                            if let Some(config_idx) = current_webdav_config() {
+                                if config_idx < webdav_configs().len() {
+                                    WebDAVSidebar {
+                                        config: webdav_configs()[config_idx].clone(),
+                                        current_path: webdav_current_path(),
+                                        items: webdav_items(),
+                                        is_loading: webdav_is_loading(),
+                                        error_msg: webdav_error(),
+                                        scanning: cloud_scan.read().is_some(),
+                                        on_scan: move |_| start_cloud_scan.call(webdav_configs.peek()[config_idx].clone()),
+                                        sync_status: {
+                                            let config_id = webdav_configs.read()[config_idx].id.clone();
+                                            sync_status
+                                                .read()
+                                                .iter()
+                                                .filter(|((id, _), _)| *id == config_id)
+                                                .map(|((_, folder), status)| (folder.clone(), status.clone()))
+                                                .collect()
+                                        },
+                                        on_sync_folder: move |(folder, sync): (String, bool)| {
+                                            let mut configs = webdav_configs.write();
+                                            let folders = &mut configs[config_idx].synced_folders;
+                                            folders.retain(|f| *f != folder);
+                                            if sync {
+                                                folders.push(folder);
+                                            }
+                                            let configs_to_save = configs.clone();
+                                            drop(configs);
+                                            if let Err(e) = save_webdav_configs(&configs_to_save) {
+                                                eprintln!("保存WebDAV配置失败: {}", e);
+                                            }
+                                        },
+                                        on_close: move |_| *show_webdav_browser.write() = false,
+                                        on_navigate: move |path: String| {
+                                            *webdav_current_path.write() = path.clone();
+                                            *webdav_is_loading.write() = true;
+                                            let cfg = webdav_configs()[config_idx].clone();
+                                            webdav_last_folder.write().insert(cfg.id.clone(), path.clone());
+                                            spawn(async move {
+                                                match load_webdav_folder(&cfg, &path).await {
+                                                    Ok(items) => {
+                                                        *webdav_items.write() = items;
+                                                        *webdav_error.write() = None;
+                                                    }
+                                                    Err(e) => {
+                                                        *webdav_error.write() = Some(format!("Error: {}", e));
+                                                    }
+                                                }
+                                                *webdav_is_loading.write() = false;
+                                            });
+                                        },
+                                        on_play_track: move |item: webdav::WebDAVItem| {
+                                            let cfg = webdav_configs()[config_idx].clone();
+                                            let current_items = webdav_items();
+                                            let audio_files: Vec<String> = current_items
+
+                                                .iter()
+                                                .filter(|i| !i.is_dir && is_audio_file(&i.name))
+                                                .map(|i| i.path.clone())
+                                                .collect();
+                                            spawn(async move {
+                                                // Create placeholder tracks without downloading
+                                                if let Ok(tracks) = create_webdav_placeholder_tracks(&cfg, &audio_files)
+                                                    .await
+                                                {
+                                                    if !tracks.is_empty() {
+                                                        if playlists().len() > current_playlist() {
+                                                            let mut plist = playlists()[current_playlist()].clone();
+                                                            let mut target_track_id = None;
+                                                            let target_path = item.path.clone();
+                                                            for track in tracks {
+                                                                if track.path == target_path {
+                                                                    target_track_id = Some(track.id.clone());
+                                                                }
+                                                                plist.add_track(track.into());
+                                                            }
+                                                            let mut lists = playlists.write();
+                                                            lists[current_playlist()] = plist;
+                                                            if let Some(id) = target_track_id {
+                                                                if let Some(track) = lists[current_playlist()]
+                                                                    .tracks
+                                                                    .iter()
+                                                                    .find(|t| t.id == id)
+                                                                    .cloned()
+                                                                {
+                                                                    if let Some(ref player) = *player_ref.read() {
+                                                                        play_track(player, &track, &webdav_configs());
+                                                                        record_play(play_history, recently_played, &track, PlayReason::CloudBrowser);
+                                                                        let _ = player.set_volume(volume());
+                                                                    }
+                                                                    *current_track.write() = Some(track);
+                                                                    *player_state.write() = PlayerState::Playing;
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            });
+                                        },
+                                    }
+                                } else {
+                                    div { "Invalid Config" }
+                                }
+                            } else {
+                                div { "No Config Selected" }
+                            }
+                        } else if !library_loaded() {
+                            LibrarySkeleton { rows: 4 }
+                        } else {
+                            PlaylistSidebar {
+                                locked: locked(),
+                                playlists: playlists(),
+                                current_playlist: current_playlist(),
+                                webdav_configs: webdav_configs(),
+                                expanded_webdav_index: current_webdav_config(),
+                                webdav_items: webdav_items(),
+                                webdav_current_path: webdav_current_path(),
+                                webdav_loading: webdav_is_loading(),
+                                recent_count: recently_played.read().len(),
+                                favorite_count: favorite_list.read().len(),
+                                special_list: special_list(),
+                                on_show_special: move |list: SpecialList| special_list.set(Some(list)),
+                                on_select: move |idx| {
+                                    *current_playlist.write() = idx;
+                                    special_list.set(None);
+                                },
+                                on_add_playlist: move |_| {
+                                    *show_playlist_manager.write() = true;
+                                },
+                                on_add_smart_playlist: move |_| editing_smart_rules.set(Some(None)),
+                                on_rename_playlist: move |(idx, name): (usize, String)| {
+                                    if let Some(playlist) = playlists.write().get_mut(idx) {
+                                        playlist.name = name;
+                                    }
+                                },
+                                on_duplicate_playlist: move |idx: usize| {
+                                    let mut lists = playlists.write();
+                                    let Some(copy) = lists.get(idx).map(Playlist::duplicate) else { return };
+                                    lists.insert(idx + 1, copy);
+                                    current_playlist.set(idx + 1);
+                                },
+                                on_delete_playlist: move |idx: usize| {
+                                    let mut lists = playlists.write();
+                                    if idx >= lists.len() || lists.len() == 1 {
+                                        return;
+                                    }
+                                    let removed = lists.remove(idx);
+                                    let current = current_playlist();
+                                    if current > idx || current >= lists.len() {
+                                        current_playlist.set(current.saturating_sub(1));
+                                    }
+                                    if let Err(e) = forget_merge_base(&removed.id) {
+                                        eprintln!("[Config] 删除合并基准失败: {}", e);
+                                    }
+                                },
+                                folder_roots: {
+                                    let configured = ui_settings.read().music_folders.clone();
+                                    let lists = playlists.read();
+                                    let watched = lists.iter().flat_map(|p| p.watched_folders.iter()).filter(|f| !configured.contains(f));
+                                    let mut roots: Vec<(String, bool)> = configured.iter().map(|f| (f.clone(), true)).collect();
+                                    for folder in watched {
+                                        if !roots.iter().any(|(root, _)| root == folder) {
+                                            roots.push((folder.clone(), false));
+                                        }
+                                    }
+                                    roots
+                                },
+                                on_add_music_folder: move |_| {
+                                    spawn(async move {
+                                        let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await else { return };
+                                        let dir = dir.path().to_string_lossy().to_string();
+                                        let mut settings = ui_settings.write();
+                                        if !settings.music_folders.contains(&dir) {
+                                            settings.music_folders.push(dir);
+                                        }
+                                        drop(settings);
+                                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                                        }
+                                    });
+                                },
+                                on_remove_music_folder: move |folder: String| {
+                                    ui_settings.write().music_folders.retain(|f| *f != folder);
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                on_play_file: move |path: String| {
+                                    spawn(async move {
+                                        let read = tokio::task::spawn_blocking(move || {
+                                            read_local_track(Path::new(&path), &mut std::collections::HashMap::new())
+                                        })
+                                        .await;
+                                        let Ok(track) = read else { return };
+                                        if let Some(ref player) = *player_ref.read() {
+                                            play_track(player, &track, &webdav_configs());
+                                            record_play(play_history, recently_played, &track, PlayReason::FolderTree);
+                                            player.set_volume(volume());
+                                        }
+                                        *current_track.write() = Some(track);
+                                        *player_state.write() = PlayerState::Playing;
+                                    });
+                                },
+                                on_import_playlist: move |_| {
+                                    spawn(async move {
+                                        let Some(file) = rfd::AsyncFileDialog::new()
+                                            .set_title("Playlist exported from another service")
+                                            .add_filter("Exported playlist", &["csv", "json"])
+                                            .pick_file()
+                                            .await
+                                        else {
+                                            return;
+                                        };
+                                        let library = playlists.peek().clone();
+                                        let matched = tokio::task::spawn_blocking({
+                                            let path = file.path().to_path_buf();
+                                            move || {
+                                                let text = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+                                                let entries = playlist::import::parse(&text)?;
+                                                Ok::<_, String>((entries.len(), playlist::import::match_library(&entries, &library)))
+                                            }
+                                        })
+                                        .await
+                                        .map_err(|e| e.to_string())
+                                        .and_then(|result| result);
+                                        match matched {
+                                            Ok((total, matched)) => pending_import.set(Some(PendingImport {
+                                                name: file.path().file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| file.file_name()),
+                                                source: file.file_name(),
+                                                total,
+                                                matched,
+                                            })),
+                                            Err(e) => *error_msg.write() = Some(format!("Couldn't import {}: {}", file.file_name(), e)),
+                                        }
+                                    });
+                                },
+                                on_merge_playlist: move |_| {
+                                    let Some(ours) = playlists.peek().get(current_playlist()).cloned() else { return };
+                                    spawn(async move {
+                                        let Some(file) = rfd::AsyncFileDialog::new()
+                                            .set_title(format!("Other copy of \"{}\"", ours.name))
+                                            .add_filter("Playlist", &["json"])
+                                            .pick_file()
+                                            .await
+                                        else {
+                                            return;
+                                        };
+                                        let theirs = match read_playlist_copy(file.path(), &ours, &webdav_configs.peek()) {
+                                            Ok(theirs) => theirs,
+                                            Err(e) => {
+                                                *error_msg.write() = Some(format!("Couldn't merge from {}: {}", file.file_name(), e));
+                                                return;
+                                            }
+                                        };
+                                        let base = load_merge_bases().unwrap_or_default().remove(&ours.id);
+                                        let has_base = base.is_some();
+                                        // Never merged before: treat what both have as the common version
+                                        let base = base.unwrap_or_else(|| {
+                                            ours.tracks
+                                                .iter()
+                                                .filter(|t| theirs.tracks.iter().any(|other| other.path == t.path))
+                                                .cloned()
+                                                .collect()
+                                        });
+                                        pending_merge.set(Some(PendingMerge {
+                                            playlist_id: ours.id.clone(),
+                                            playlist_name: ours.name.clone(),
+                                            source: file.file_name(),
+                                            has_base,
+                                            result: playlist::merge::merge(&base, &ours.tracks, &theirs.tracks),
+                                        }));
+                                    });
+                                },
+                                on_toggle_webdav: move |idx| {
+                                    // If clicking the same one, collapse it
+                                    if current_webdav_config() == Some(idx) {
+                                        *current_webdav_config.write() = None;
+                                    } else {
+                                        // Expand new one, back in the folder it was last showing
+                                        *current_webdav_config.write() = Some(idx);
+
+                                        // Trigger initial load
+                                        if idx < webdav_configs().len() {
+                                            let cfg = webdav_configs()[idx].clone();
+                                            let path = webdav_last_folder
+                                                .read()
+                                                .get(&cfg.id)
+                                                .cloned()
+                                                .unwrap_or_else(|| "/".to_string());
+                                            *webdav_current_path.write() = path.clone();
+                                            *webdav_is_loading.write() = true;
+                                            spawn(async move {
+                                                match load_webdav_folder(&cfg, &path).await {
+                                                    Ok(items) => {
+                                                        *webdav_items.write() = items;
+                                                        *webdav_error.write() = None;
+                                                    }
+                                                    Err(e) => {
+                                                        *webdav_error.write() = Some(format!("Error: {}", e));
+                                                    }
+                                                }
+                                                *webdav_is_loading.write() = false;
+                                            });
+                                        }
+                                    }
+                                },
+                                on_webdav_navigate: move |path: String| {
+                                    *webdav_current_path.write() = path.clone();
+                                    *webdav_is_loading.write() = true;
+
+                                    if let Some(config_idx) = current_webdav_config() {
+                                        if config_idx < webdav_configs().len() {
+                                            let cfg = webdav_configs()[config_idx].clone();
+                                            webdav_last_folder.write().insert(cfg.id.clone(), path.clone());
+                                            spawn(async move {
+                                                match load_webdav_folder(&cfg, &path).await {
+                                                    Ok(items) => {
+                                                        *webdav_items.write() = items;
+                                                        *webdav_error.write() = None;
+                                                    }
+                                                    Err(e) => {
+                                                        *webdav_error.write() = Some(format!("Error: {}", e));
+                                                    }
+                                                }
+                                                *webdav_is_loading.write() = false;
+                                            });
+                                        }
+                                    }
+                                },
+                                on_webdav_play: move |item: webdav::WebDAVItem| {
+                                    if let Some(config_idx) = current_webdav_config() {
+                                        if config_idx < webdav_configs().len() {
+                                            let cfg = webdav_configs()[config_idx].clone();
+                                            let current_items = webdav_items();
+
+                                            // Get all audio files in current directory
+                                            let audio_files: Vec<String> = current_items
+                                                .iter()
+                                                .filter(|i| !i.is_dir && is_audio_file(&i.name))
+                                                .map(|i| i.path.clone())
+                                                .collect();
+                                            spawn(async move {
+                                                // Create placeholder tracks without downloading
+                                                if let Ok(tracks) = create_webdav_placeholder_tracks(
+                                                        &cfg,
+                                                        &audio_files,
+                                                    )
+                                                    .await
+                                                {
+                                                    if !tracks.is_empty() {
+                                                        if playlists().len() > current_playlist() {
+                                                            let mut plist = playlists()[current_playlist()].clone();
+                                                            let mut target_track_id = None;
+                                                            let target_path = item.path.clone();
+                                                            for track in tracks {
+                                                                if track.path == target_path {
+                                                                    target_track_id = Some(track.id.clone());
+                                                                }
+                                                                plist.add_track(track.into());
+                                                            }
+                                                            let mut lists = playlists.write();
+                                                            lists[current_playlist()] = plist;
+                                                            if let Some(id) = target_track_id {
+                                                                if let Some(track) = lists[current_playlist()]
+                                                                    .tracks
+                                                                    .iter()
+                                                                    .find(|t| t.id == id)
+                                                                    .cloned()
+                                                                {
+                                                                    if let Some(ref player) = *player_ref.read() {
+                                                                        play_track(player, &track, &webdav_configs());
+                                                                        record_play(play_history, recently_played, &track, PlayReason::CloudBrowser);
+                                                                        let _ = player.set_volume(volume());
+                                                                    }
+                                                                    *current_track.write() = Some(track);
+                                                                    *player_state.write() = PlayerState::Playing;
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            });
+                                        }
+                                    }
+                                },
+                            }
+                        }
+                    }
+
+                    section { class: "col-span-1 player-pane",
+
+                        PlayerControls {
+                            locked: locked(),
+                            state: player_state(),
+                            duration: Some(current_duration()),
+                            volume: volume(),
+                            levels: peak_levels(),
+                            current_time,
+                            lyric: current_lyric(),
+                            chapters: current_track().and_then(|track| track_chapters.read().get(&track.path).cloned()).unwrap_or_default(),
+                            on_edit_chapters: move |_| {
+                                if let Some(track) = current_track() {
+                                    editing_chapters.set(Some(track));
+                                }
+                            },
+                            on_play: move |_| {
+                                if let Some(ref player) = *player_ref.read() {
+
+                                    if player_state() == PlayerState::Paused && player.is_paused() {
+                                        let _ = player.resume();
+                                    } else if let Some(track_stub) = current_track() {
+                                        play_track(player, &track_stub, &webdav_configs());
+                                        record_play(play_history, recently_played, &track_stub, PlayReason::PlayButton);
+                                        let _ = player.set_volume(volume());
+                                    }
+                                }
+                                *player_state.write() = PlayerState::Playing;
+                            },
+                            on_pause: move |_| {
+                                if let Some(ref player) = *player_ref.read() {
+                                    let _ = player.pause();
+                                }
+                                *player_state.write() = PlayerState::Paused;
+                            },
+                            on_stop: move |_| {
+                                if let Some(ref player) = *player_ref.read() {
+                                    let _ = player.stop();
+                                    player.clear_prefetch();
+                                }
+                                *player_state.write() = PlayerState::Stopped;
+                            },
+                            on_seek: move |time| {
+                                if let Some(ref player) = *player_ref.read() {
+                                    let _ = player.seek(time);
+                                }
+                                *current_time.write() = time;
+                            },
+                            on_volume_change: move |vol| {
+                                if let Some(ref player) = *player_ref.read() {
+                                    let _ = player.set_volume(vol);
+                                }
+                                *volume.write() = vol;
+                            },
+                            on_previous: move |_| {
+                                if playlists().len() > current_playlist() {
+                                    let playlist = &playlists()[current_playlist()];
+                                    if let Some(current) = current_track() {
+                                        // Find current track index
+                                        if let Some(pos) = playlist
+                                            .tracks
+                                            .iter()
+                                            .position(|t| t.id == current.id)
+                                        {
+                                            if pos > 0 {
+                                                let prev_track = playlist.tracks[pos - 1].clone();
+                                                if let Some(ref player) = *player_ref.read() {
+                                                    player.stop();
+                                                    play_track(player, &prev_track, &webdav_configs());
+                                                    record_play(play_history, recently_played, &prev_track, PlayReason::SkipPrevious);
+                                                    let _ = player.set_volume(volume());
+                                                }
+                                                *current_track.write() = Some(prev_track);
+                                                *player_state.write() = PlayerState::Playing;
+                                            }
+                                        }
+                                    }
+                                }
+                            },
+                            on_next: move |_| {
+                                let Some(current) = current_track() else { return };
+                                if let Some(next_track) = upcoming_track(current.id) {
+                                    take_from_queue.call(next_track.clone());
+                                    if let Some(ref player) = *player_ref.read() {
+                                        player.stop();
+                                        play_track(player, &next_track, &webdav_configs());
+                                        record_play(play_history, recently_played, &next_track, PlayReason::SkipNext);
+                                        let _ = player.set_volume(volume());
+                                    }
+                                    *current_track.write() = Some(next_track);
+                                    *player_state.write() = PlayerState::Playing;
+                                }
+                            },
+                            shuffle: ui_settings.read().shuffle,
+                            on_toggle_shuffle: move |_| {
+                                let shuffle = !ui_settings.peek().shuffle;
+                                ui_settings.write().shuffle = shuffle;
+                                shuffle_pick.set(None);
+                                if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                    eprintln!("[Config] 保存界面设置失败: {}", e);
+                                }
+                            },
+                        }
+
+                        if !locked() {
+                            AudioSettings {
+                                balance: balance(),
+                                mono_downmix: mono_downmix(),
+                                crossfade_secs: ui_settings.read().crossfade_secs,
+                                output_device: ui_settings.read().output_device.clone(),
+                                output_devices: output_devices(),
+                                on_output_device_change: move |device: Option<String>| {
+                                    if let Some(ref player) = *player_ref.read() {
+                                        if let Err(e) = player.set_output_device(device.clone()) {
+                                            eprintln!("[Player] 无法切换输出设备: {}", e);
+                                            *error_msg.write() = Some(format!("Couldn't switch the output device: {}", e));
+                                        }
+                                    }
+                                    ui_settings.write().output_device = device;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                read_ahead_mb: ui_settings.read().read_ahead_mb,
+                                on_read_ahead_change: move |mb: u32| {
+                                    if let Some(ref player) = *player_ref.read() {
+                                        player.set_read_ahead_size(mb as usize * 1024 * 1024);
+                                    }
+                                    ui_settings.write().read_ahead_mb = mb;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                on_crossfade_change: move |secs: u32| {
+                                    if let Some(ref player) = *player_ref.read() {
+                                        player.set_crossfade(Duration::from_secs(secs.into()));
+                                    }
+                                    ui_settings.write().crossfade_secs = secs;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                on_balance_change: move |value: f32| {
+                                    if let Some(ref player) = *player_ref.read() {
+                                        player.set_balance(value);
+                                    }
+                                    *balance.write() = value;
+                                },
+                                normalize_loudness: ui_settings.read().normalize_loudness,
+                                loudness_progress: loudness_scan.read().as_ref().map(|scan| (loudness_scanned(), scan.total())),
+                                unmeasured: if loudness_scan.read().is_none() { unmeasured_tracks().len() } else { 0 },
+                                on_normalize_change: move |enabled: bool| {
+                                    ui_settings.write().normalize_loudness = enabled;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                                on_measure_loudness: move |_| start_loudness_scan.call(()),
+                                on_mono_change: move |mono: bool| {
+                                    if let Some(ref player) = *player_ref.read() {
+                                        player.set_mono_downmix(mono);
+                                    }
+                                    *mono_downmix.write() = mono;
+                                },
+                                content_types: ui_settings.read().content_types,
+                                on_content_types_change: move |types: ContentTypeSettings| {
+                                    ui_settings.write().content_types = types;
+                                    if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                        eprintln!("[Config] 保存界面设置失败: {}", e);
+                                    }
+                                },
+                            }
+                        }
+
+                        NowPlayingCard {
+                            current_track: current_track(),
+                            player_metadata: now_playing_metadata,
+                            player_ref: player_ref.clone(),
+                            rating: current_track().and_then(|track| track_ratings.read().get(&track.file_key()).copied()),
+                            on_rate: move |rating: Option<u8>| {
+                                if let Some(track) = current_track() {
+                                    set_rating.call((track.file_key(), rating));
+                                }
+                            },
+                            favorite: current_track().is_some_and(|track| favorite_tracks.read().contains(&track.file_key())),
+                            on_favorite: move |favorite: bool| {
+                                if let Some(track) = current_track() {
+                                    set_favorite.call((track.file_key(), favorite));
+                                }
+                            },
+                            visualizer: ui_settings.read().visualizer,
+                            on_visualizer_change: move |mode: VisualizerMode| {
+                                ui_settings.write().visualizer = mode;
+                                if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                                    eprintln!("[Config] 保存界面设置失败: {}", e);
+                                }
+                            },
+                        }
+
+                        if let Some(lyric) = current_lyric() {
+                            LyricsDisplay { current_time, lyric: Some(lyric) }
+                        }
+
+                        if !play_queue.read().is_empty() {
+                            PlayQueuePanel {
+                                queue: play_queue(),
+                                on_remove: move |id: String| play_queue.write().retain(|t| t.id != id),
+                                on_clear: move |_| play_queue.write().clear(),
+                            }
+                        }
+
+                        if let Some(server) = party_server.read().as_ref() {
+                            PartyQueuePanel {
+                                guest_url: server.guest_url().to_string(),
+                                requests: party_queue(),
+                                on_approve: move |id: u64| {
+                                    let Some(request) = party_server.peek().as_ref().and_then(|server| server.take_request(id)) else { return };
+                                    refresh_party_queue();
+                                    let track = playlists.peek().iter().flat_map(|p| p.tracks.iter()).find(|t| t.id == request.track_id).cloned();
+                                    match track {
+                                        Some(track) => {
+                                            eprintln!("[Party] 已接受点歌: {}", request.title);
+                                            play_queue.write().push(TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                        }
+                                        None => *error_msg.write() = Some(format!("Couldn't queue \"{}\": it's no longer in the library", request.title)),
+                                    }
+                                },
+                                on_reject: move |id: u64| {
+                                    if party_server.peek().as_ref().and_then(|server| server.take_request(id)).is_some() {
+                                        refresh_party_queue();
+                                    }
+                                },
+                            }
+                        }
+
+                        if let Some(dir) = library_scan.read().as_ref().map(|scan| scan.dir().to_string()) {
+                            LibraryScanProgress {
+                                dir,
+                                done: library_scan_progress().0,
+                                total: library_scan_progress().1,
+                                on_cancel: move |_| {
+                                    if let Some(scan) = library_scan.peek().as_ref() {
+                                        scan.cancel();
+                                    }
+                                },
+                            }
+                        }
+                        if let Some(name) = cloud_scan.read().as_ref().map(|scan| scan.name().to_string()) {
+                            LibraryScanProgress {
+                                dir: name,
+                                done: cloud_scan_progress().0,
+                                total: cloud_scan_progress().1,
+                                on_cancel: move |_| {
+                                    if let Some(scan) = cloud_scan.peek().as_ref() {
+                                        scan.cancel();
+                                    }
+                                },
+                            }
+                        }
+
+                        // Error message display
+                        if let Some(err) = error_msg() {
+                            div { class: "mb-4 p-4 bg-red-100 border border-red-400 text-red-700 rounded",
+                                "❌ {err}"
+                                button {
+                                    class: "ml-2 text-red-500 hover:text-red-700",
+                                    onclick: move |_| *error_msg.write() = None,
+                                    "✕"
+                                }
+                            }
+                        }
+                    }
+
+                    // Right: Playlist tracks. Rows dragged from the browser are appended here.
+                    aside {
+                        class: if drag_state.read().is_some() { "col-span-1 h-[calc(100vh-12rem)] overflow-y-auto drop-target" } else { "col-span-1 h-[calc(100vh-12rem)] overflow-y-auto" },
+                        ondragover: move |e: DragEvent| e.prevent_default(),
+                        ondrop: move |e: DragEvent| {
+                            e.prevent_default();
+                            let Some(payload) = drag_state.write().take() else { return };
+                            if locked() || special_list().is_some() {
+                                return;
+                            }
+                            let queue_idx = current_playlist();
+                            match payload {
+                                DragPayload::Playlist { playlist_id } => {
+                                    let mut lists = playlists.write();
+                                    let Some(source) = lists.iter().find(|p| p.id == playlist_id).cloned() else { return };
+                                    if let Some(queue) = lists.get_mut(queue_idx).filter(|queue| queue.id != source.id) {
+                                        // Fresh ids so dropping the same playlist twice doesn't repeat ids
+                                        for track in source.tracks {
+                                            queue.add_track(TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                        }
+                                    }
+                                }
+                                DragPayload::CloudFile { config_id, item } => {
+                                    let Some(cfg) = webdav_configs().into_iter().find(|c| c.id == config_id) else { return };
+                                    spawn(async move {
+                                        match create_webdav_placeholder_tracks(&cfg, std::slice::from_ref(&item.path)).await {
+                                            Ok(tracks) => {
+                                                if let Some(queue) = playlists.write().get_mut(queue_idx) {
+                                                    for track in tracks {
+                                                        queue.add_track(track.into());
+                                                    }
+                                                }
+                                            }
+                                            Err(e) => *error_msg.write() = Some(format!("Failed to add {}: {}", item.name, e)),
+                                        }
+                                    });
+                                }
+                            }
+                        },
+                        if !library_loaded() {
+                            LibrarySkeleton { rows: 8 }
+                        } else if !global_search.read().trim().is_empty() {
+                            SearchResults {
+                                query: global_search(),
+                                groups: search_results(),
+                                current_track: current_track(),
+                                webdav_configs: webdav_configs(),
+                                on_play: move |(idx, track): (usize, TrackStub)| {
+                                    if let Some(reason) = track.missing_reason(&webdav_configs.peek()) {
+                                        *error_msg.write() = Some(format!("Couldn't play \"{}\": {}", track.title, reason));
+                                        return;
+                                    }
+                                    // Carry on through the playlist it was found in
+                                    current_playlist.set(idx);
+                                    special_list.set(None);
+                                    if let Some(ref player) = *player_ref.read() {
+                                        play_track(player, &track, &webdav_configs());
+                                        record_play(play_history, recently_played, &track, PlayReason::Search);
+                                        player.set_volume(volume());
+                                    }
+                                    *current_track.write() = Some(track);
+                                    *player_state.write() = PlayerState::Playing;
+                                },
+                                on_add_to_queue: move |track: TrackStub| {
+                                    play_queue.write().push(TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                },
+                                on_close: move |_| global_search.set(String::new()),
+                            }
+                        } else if let Some(list) = special_list() {
+                            SpecialListTracks {
+                                list,
+                                entries: match list {
+                                    SpecialList::RecentlyPlayed => recently_played.read().iter().map(|play| (play.track.clone(), Some(play.played_at))).collect::<Vec<_>>(),
+                                    SpecialList::Favorites => favorite_list.read().iter().map(|track| (track.clone(), None)).collect::<Vec<_>>(),
+                                },
+                                current_track: current_track(),
+                                webdav_configs: webdav_configs(),
+                                favorites: favorite_tracks(),
+                                on_favorite: move |change: (String, bool)| set_favorite.call(change),
+                                on_play: move |track: TrackStub| {
+                                    if let Some(reason) = track.missing_reason(&webdav_configs.peek()) {
+                                        *error_msg.write() = Some(format!("Couldn't play \"{}\": {}", track.title, reason));
+                                        return;
+                                    }
+                                    if let Some(ref player) = *player_ref.read() {
+                                        play_track(player, &track, &webdav_configs());
+                                        let reason = match list {
+                                            SpecialList::RecentlyPlayed => PlayReason::RecentlyPlayed,
+                                            SpecialList::Favorites => PlayReason::Favorites,
+                                        };
+                                        record_play(play_history, recently_played, &track, reason);
+                                        player.set_volume(volume());
+                                    }
+                                    *current_track.write() = Some(track);
+                                    *player_state.write() = PlayerState::Playing;
+                                },
+                                on_add_to_queue: move |track: TrackStub| {
+                                    play_queue.write().push(TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                },
+                            }
+                        } else if playlists().len() > current_playlist() {
+                            PlaylistTracks {
+                                locked: locked(),
+                                playlist: playlists()[current_playlist()].clone(),
+                                auto_advances: playlists()[current_playlist()].auto_advances(&ui_settings.read().content_types),
+                                current_track: current_track(),
+                                webdav_configs: webdav_configs(),
+                                on_track_select: move |track_stub: TrackStub| {
+                                    if let Some(reason) = track_stub.missing_reason(&webdav_configs.peek()) {
+                                        *error_msg.write() = Some(format!("Couldn't play \"{}\": {}", track_stub.title, reason));
+                                        return;
+                                    }
+                                    if let Some(ref player) = *player_ref.read() {
+                                        play_track(player, &track_stub, &webdav_configs());
+                                        record_play(play_history, recently_played, &track_stub, PlayReason::PlaylistClick);
+                                        let _ = player.set_volume(volume());
+                                    }
+                                    *current_track.write() = Some(track_stub);
+                                    *player_state.write() = PlayerState::Playing;
+                                },
+                                on_play_next: move |track: TrackStub| {
+                                    play_queue.write().insert(0, TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                },
+                                on_add_to_queue: move |track: TrackStub| {
+                                    play_queue.write().push(TrackStub { id: Uuid::new_v4().to_string(), ..track });
+                                },
+                                on_gain_change: move |(id, gain_db): (String, f32)| {
+                                    if let Some(track) = playlists.write()[current_playlist()].tracks.iter_mut().find(|t| t.id == id) {
+                                        track.gain_db = gain_db;
+                                    }
+                                    if let Some(track) = current_track.write().as_mut().filter(|t| t.id == id) {
+                                        track.gain_db = gain_db;
+                                    }
+                                },
+                                on_move: move |(from, to): (usize, usize)| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.move_track(from, to);
+                                    }
+                                },
+                                missing: missing_tracks(),
+                                ratings: track_ratings(),
+                                favorites: favorite_tracks(),
+                                on_rate: move |change: (String, Option<u8>)| set_rating.call(change),
+                                on_favorite: move |change: (String, bool)| set_favorite.call(change),
+                                on_edit_tags: move |track: TrackStub| editing_tags.set(Some(track)),
+                                on_dedup: move |_| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        let removed = playlist.dedup();
+                                        eprintln!("[UI] 已从 {} 移除 {} 首重复曲目", playlist.name, removed);
+                                    }
+                                },
+                                on_sort: move |(key, order): (SortKey, SortOrder)| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.sort_by(key, order);
+                                    }
+                                },
+                                on_remove: move |ids: Vec<String>| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.remove_tracks(&ids);
+                                    }
+                                },
+                                other_playlists: playlists
+                                    .read()
+                                    .iter()
+                                    .enumerate()
+                                    .filter(|(idx, p)| *idx != current_playlist() && p.smart.is_none())
+                                    .map(|(_, p)| (p.id.clone(), p.name.clone()))
+                                    .collect::<Vec<_>>(),
+                                on_move_to_playlist: move |(ids, target_id): (Vec<String>, String)| {
+                                    let mut lists = playlists.write();
+                                    let Some(target) = lists.iter().position(|p| p.id == target_id) else { return };
+                                    let Some(moved) = lists.get_mut(current_playlist()).map(|p| p.take_tracks(&ids)) else { return };
+                                    for track in moved {
+                                        lists[target].add_track(track);
+                                    }
+                                },
+                                on_content_type_change: move |content_type: ContentType| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.set_content_type(content_type);
+                                    }
+                                },
+                                on_auto_advance_change: move |auto_advance: bool| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.set_auto_advance(auto_advance, &ui_settings.peek().content_types);
+                                    }
+                                },
+                                on_edit_rules: move |_| {
+                                    editing_smart_rules.set(playlists.peek().get(current_playlist()).map(|p| Some(p.id.clone())));
+                                },
+                                on_unwatch: move |folder: String| {
+                                    if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                                        playlist.watched_folders.retain(|f| *f != folder);
+                                    }
+                                },
+                                on_clear: move |_| {
+                                    let mut playlists_guard = playlists.write();
+                                    if playlists_guard.len() > current_playlist() {
+                                        playlists_guard[current_playlist()].tracks.clear();
+                                    }
+                                },
+                            }
+                        }
+                    }
+                }
+            }
+
+            if show_playlist_manager() {
+                PlaylistManagerModal {
+                    on_close: move |_| {
+                        *show_playlist_manager.write() = false;
+                    },
+                    on_add_playlist: move |name| {
+                        let new_playlist = Playlist::new(name);
+                        playlists.write().push(new_playlist);
+                        *show_playlist_manager.write() = false;
+                    },
+                    on_load_files: move |_| {},
+                }
+            }
+
+            if let Some(editing) = editing_smart_rules() {
+                SmartPlaylistModal {
+                    existing: editing
+                        .as_ref()
+                        .and_then(|id| playlists.read().iter().find(|p| p.id == *id).map(|p| (p.name.clone(), p.smart.clone().unwrap_or_default()))),
+                    on_save: move |(name, rules): (String, playlist::smart::SmartRules)| {
+                        let mut lists = playlists.write();
+                        match editing.as_ref().and_then(|id| lists.iter_mut().find(|p| p.id == *id)) {
+                            Some(playlist) => {
+                                playlist.name = name;
+                                playlist.smart = Some(rules);
+                            }
+                            None => {
+                                let mut playlist = Playlist::new(name);
+                                playlist.smart = Some(rules);
+                                lists.push(playlist);
+                                current_playlist.set(lists.len() - 1);
+                            }
+                        }
+                        editing_smart_rules.set(None);
+                    },
+                    on_close: move |_| editing_smart_rules.set(None),
+                }
+            }
+
+            if let Some(merge) = pending_merge() {
+                PlaylistMergeModal {
+                    merge: merge.clone(),
+                    on_apply: move |_| {
+                        if let Some(playlist) = playlists.write().iter_mut().find(|p| p.id == merge.playlist_id) {
+                            playlist.tracks = merge.result.tracks.clone();
+                        }
+                        if let Err(e) = save_merge_base(&merge.playlist_id, &merge.result.tracks) {
+                            eprintln!("[Config] 保存合并基准失败: {}", e);
+                        }
+                        pending_merge.set(None);
+                    },
+                    on_close: move |_| pending_merge.set(None),
+                }
+            }
+
+            if let Some(import) = pending_import() {
+                PlaylistImportModal {
+                    import: import.clone(),
+                    on_apply: move |_| {
+                        let mut playlist = Playlist::new(import.name.clone());
+                        for track in import.matched.tracks.iter().cloned() {
+                            playlist.add_track(track);
+                        }
+                        let mut lists = playlists.write();
+                        lists.push(playlist);
+                        current_playlist.set(lists.len() - 1);
+                        special_list.set(None);
+                        pending_import.set(None);
+                    },
+                    on_close: move |_| pending_import.set(None),
+                }
+            }
+
+            if let Some(track) = editing_chapters() {
+                ChaptersModal {
+                    track_title: track.title.clone(),
+                    text: track_chapters.read().get(&track.path).map(|chapters| chapters::to_tracklist(chapters)).unwrap_or_default(),
+                    on_save: move |parsed: Vec<chapters::Chapter>| {
+                        if parsed.is_empty() {
+                            track_chapters.write().remove(&track.path);
+                        } else {
+                            track_chapters.write().insert(track.path.clone(), parsed);
+                        }
+                        if let Err(e) = save_track_chapters(&track_chapters.peek()) {
+                            eprintln!("[Config] 保存章节失败: {}", e);
+                        }
+                        editing_chapters.set(None);
+                    },
+                    on_close: move |_| editing_chapters.set(None),
+                }
+            }
+
+            if let Some(track) = editing_tags() {
+                TagEditorModal {
+                    track: track.clone(),
+                    on_save: move |edit: metadata::TagEdit| {
+                        editing_tags.set(None);
+                        let path = track.path.clone();
+                        spawn(async move {
+                            // Write, then read the file back so every copy of it shows what's now on disk
+                            let written = tokio::task::spawn_blocking({
+                                let path = path.clone();
+                                move || {
+                                    metadata::write_tags(Path::new(&path), &edit).map_err(|e| e.to_string())?;
+                                    Ok::<_, String>(read_local_track(Path::new(&path), &mut std::collections::HashMap::new()))
+                                }
+                            })
+                            .await
+                            .map_err(|e| e.to_string())
+                            .and_then(|result| result);
+                            let scanned = match written {
+                                Ok(scanned) => scanned,
+                                Err(e) => {
+                                    *error_msg.write() = Some(format!("Couldn't save the tags of {}: {}", path, e));
+                                    return;
+                                }
+                            };
+                            for playlist in playlists.write().iter_mut() {
+                                for entry in playlist.tracks.iter_mut().filter(|t| t.path == path) {
+                                    entry.update_from(&scanned);
+                                }
+                            }
+                            if let Some(current) = current_track.write().as_mut().filter(|t| t.path == path) {
+                                current.update_from(&scanned);
+                            }
+                            if recently_played.peek().iter().any(|play| play.track.path == path) {
+                                for play in recently_played.write().iter_mut().filter(|play| play.track.path == path) {
+                                    play.track.update_from(&scanned);
+                                }
+                                if let Err(e) = save_recently_played(&recently_played.peek()) {
+                                    eprintln!("[History] 保存最近播放失败: {}", e);
+                                }
+                            }
+                        });
+                    },
+                    on_close: move |_| editing_tags.set(None),
+                }
+            }
+
+            if let Some(report) = import_report() {
+                ImportReportModal {
+                    report,
+                    retrying: retrying_import(),
+                    on_retry: move |paths: Vec<String>| {
+                        let Some(config_id) = import_report.peek().as_ref().map(|r| r.config_id.clone()) else { return };
+                        let Some(cfg) = webdav_configs.peek().iter().find(|c| c.id == config_id).cloned() else {
+                            *error_msg.write() = Some("That WebDAV server is no longer configured".to_string());
+                            return;
+                        };
+                        retrying_import.set(true);
+                        spawn(async move {
+                            match download_and_import_webdav_files(&cfg, &paths).await {
+                                Ok((tracks, failures)) => add_cloud_import.call((cfg.id.clone(), tracks, failures, paths)),
+                                Err(e) => *error_msg.write() = Some(format!("Retry failed: {}", e)),
+                            }
+                            retrying_import.set(false);
+                        });
+                    },
+                    on_close: move |_| import_report.set(None),
+                }
+            }
+
+            if show_lock() {
+                LockModal {
+                    pin_hash: ui_settings.read().lock_pin_hash.clone(),
+                    on_lock: move |hash: String| {
+                        ui_settings.write().lock_pin_hash = Some(hash);
+                        show_lock.set(false);
+                        lock_ui.call(());
+                    },
+                    on_unlock: move |forget_pin: bool| {
+                        {
+                            let mut settings = ui_settings.write();
+                            settings.locked = false;
+                            if forget_pin {
+                                settings.lock_pin_hash = None;
+                            }
+                        }
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                        show_lock.set(false);
+                    },
+                    on_close: move |_| show_lock.set(false),
+                }
+            }
+
+            if show_user_profiles() {
+                UserProfilesModal {
+                    current: user_profile.read().as_ref().map(|p| p.id.clone()),
+                    on_switch: move |profile: profiles::UserProfile| {
+                        show_user_profiles.set(false);
+                        if user_profile.peek().as_ref().is_some_and(|p| p.id == profile.id) {
+                            return;
+                        }
+                        // Save this profile's session before its folder stops being the active one
+                        persist_session();
+                        if let Some(player) = player_ref.peek().as_ref() {
+                            player.stop();
+                            player.clear_prefetch();
+                        }
+                        activate_user_profile(&profile);
+                        user_profile.set(Some(profile));
+                    },
+                    on_close: move |_| show_user_profiles.set(false),
+                }
+            }
+
+            if show_online_track() {
+                OnlineTrackModal {
+                    binary: ui_settings.read().ytdlp_binary.clone(),
+                    on_binary_change: move |binary: Option<String>| {
+                        ui_settings.write().ytdlp_binary = binary;
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    on_add: move |track: TrackStub| {
+                        if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                            playlist.add_track(track);
+                        }
+                        show_online_track.set(false);
+                    },
+                    on_close: move |_| show_online_track.set(false),
+                }
+            }
+
+            if show_bandcamp() {
+                BandcampModal {
+                    on_add_tracks: move |tracks: Vec<TrackStub>| {
+                        if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                            for track in tracks {
+                                playlist.add_track(track);
+                            }
+                        }
+                    },
+                    on_downloaded: move |dir: String| {
+                        let Some(playlist_id) = playlists.peek().get(current_playlist()).map(|p| p.id.clone()) else { return };
+                        let known = known_tracks(&playlists.peek(), current_playlist());
+                        spawn(async move {
+                            let folder = dir.clone();
+                            let scan = tokio::task::spawn_blocking(move || scan_music_directory(&folder, &known).map_err(|e| e.to_string())).await;
+                            match scan {
+                                Ok(Ok(tracks)) => {
+                                    if let Some(playlist) = playlists.write().iter_mut().find(|p| p.id == playlist_id) {
+                                        playlist.add_scanned(tracks);
+                                    }
+                                }
+                                Ok(Err(e)) => *error_msg.write() = Some(format!("Couldn't read {}: {}", dir, e)),
+                                Err(e) => eprintln!("[Library] 扫描任务失败: {}", e),
+                            }
+                        });
+                    },
+                    on_close: move |_| show_bandcamp.set(false),
+                }
+            }
+
+            if show_diagnostics() {
+                DiagnosticsModal {
+                    library_loaded: library_loaded(),
+                    on_error: move |message: String| *error_msg.write() = Some(message),
+                    on_close: move |_| show_diagnostics.set(false),
+                }
+            }
+
+            if show_duplicates() {
+                DuplicatesModal {
+                    groups: duplicate_groups(),
+                    on_keep: move |(keep, group): (TrackStub, Vec<TrackStub>)| {
+                        let changed = playlist::duplicates::keep_one(&mut playlists.write(), &keep, &group);
+                        eprintln!("[Library] 保留 {}，替换或移除了 {} 个重复条目", keep.path, changed);
+                        // The copy kept takes over the best rating and any favorite mark of the others
+                        let best = group.iter().filter_map(|t| track_ratings.peek().get(&t.file_key()).copied()).max();
+                        if let Some(stars) = best.filter(|_| !track_ratings.peek().contains_key(&keep.file_key())) {
+                            set_rating.call((keep.file_key(), Some(stars)));
+                        }
+                        let favorite = group.iter().any(|t| favorite_tracks.peek().contains(&t.file_key()));
+                        if favorite && !favorite_tracks.peek().contains(&keep.file_key()) {
+                            set_favorite.call((keep.file_key(), true));
+                        }
+                        if let Some(groups) = duplicate_groups.write().as_mut() {
+                            groups.retain(|other| other[0].path != group[0].path);
+                        }
+                    },
+                    on_close: move |_| show_duplicates.set(false),
+                }
+            }
+
+            if show_library_stats() {
+                LibraryStatsModal {
+                    stats: library_stats(),
+                    on_close: move |_| show_library_stats.set(false),
+                }
+            }
+
+            if show_organizer() {
+                OrganizeModal {
+                    tracks: playlists
+                        .read()
+                        .iter()
+                        .filter(|playlist| playlist.smart.is_none())
+                        .flat_map(|playlist| playlist.tracks.iter().cloned())
+                        .collect::<Vec<_>>(),
+                    root: ui_settings.read().organize_root.clone(),
+                    pattern: ui_settings.read().organize_pattern.clone(),
+                    busy: organizing(),
+                    on_apply: move |(root, pattern): (String, String)| organize_library.call((root, pattern)),
+                    on_close: move |_| show_organizer.set(false),
+                }
+            }
+
+            if show_play_history() {
+                PlayHistoryModal {
+                    entries: play_history(),
+                    write_stats_to_tags: ui_settings.read().write_stats_to_tags,
+                    on_write_stats_to_tags_change: move |enabled: bool| {
+                        ui_settings.write().write_stats_to_tags = enabled;
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    shuffle_history_window: ui_settings.read().shuffle_history_window,
+                    on_shuffle_history_window_change: move |window: usize| {
+                        ui_settings.write().shuffle_history_window = window;
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    smart_shuffle: ui_settings.read().smart_shuffle,
+                    smart_shuffle_exponent: ui_settings.read().smart_shuffle_exponent,
+                    on_smart_shuffle_change: move |(enabled, exponent): (bool, f64)| {
+                        {
+                            let mut settings = ui_settings.write();
+                            settings.smart_shuffle = enabled;
+                            settings.smart_shuffle_exponent = exponent;
+                        }
+                        shuffle_pick.set(None);
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    on_clear: move |_| {
+                        play_history.write().clear();
+                        if let Err(e) = save_play_history(&[]) {
+                            eprintln!("[History] 保存播放历史失败: {}", e);
+                        }
+                        recently_played.write().clear();
+                        if let Err(e) = save_recently_played(&[]) {
+                            eprintln!("[History] 保存最近播放失败: {}", e);
+                        }
+                    },
+                    on_close: move |_| *show_play_history.write() = false,
+                }
+            }
+
+            if show_settings_profiles() {
+                SettingsProfilesModal {
+                    profiles: settings_profiles(),
+                    active_profile: active_profile(),
+                    on_close: move |_| *show_settings_profiles.write() = false,
+                    on_save: move |name: String| {
+                        let settings = PlaybackSettings {
+                            volume: volume(),
+                            balance: balance(),
+                            mono_downmix: mono_downmix(),
+                            crossfade_secs: ui_settings.peek().crossfade_secs,
+                            status_palette: ui_settings.peek().status_palette,
+                            output_device: ui_settings.peek().output_device.clone(),
+                        };
+                        let mut profiles = settings_profiles.write();
+                        let id = match profiles.iter_mut().find(|p| p.name == name) {
+                            Some(existing) => {
+                                existing.settings = settings;
+                                existing.id.clone()
+                            }
+                            None => {
+                                let id = Uuid::new_v4().to_string();
+                                profiles.push(SettingsProfile { id: id.clone(), name, settings });
+                                id
+                            }
+                        };
+                        if let Err(e) = save_settings_profiles(&profiles) {
+                            eprintln!("保存设置配置失败: {}", e);
+                        }
+                        *active_profile.write() = Some(id);
+                    },
+                    on_apply: move |idx: usize| {
+                        if let Some(profile) = settings_profiles().get(idx).cloned() {
+                            apply_profile.call(profile);
+                        }
+                    },
+                    on_delete: move |idx: usize| {
+                        let mut profiles = settings_profiles.write();
+                        if idx < profiles.len() {
+                            let removed = profiles.remove(idx);
+                            if active_profile() == Some(removed.id) {
+                                *active_profile.write() = None;
+                            }
+                        }
+                        if let Err(e) = save_settings_profiles(&profiles) {
+                            eprintln!("保存设置配置失败: {}", e);
+                        }
+                    },
+                }
+            }
+
+            if show_directory_browser() {
+                DirectoryBrowserModal {
+                    current_directory: current_directory(),
+                    on_close: move |_| {
+                        *show_directory_browser.write() = false;
+                    },
+                    on_load_directory: move |dir: String| {
+                        *current_directory.write() = dir.clone();
+                        start_library_scan.call(dir);
+                        *show_directory_browser.write() = false;
+                    },
+                    on_watch_directory: move |dir: String| {
+                        *current_directory.write() = dir.clone();
+                        let mut lists = playlists.write();
+                        if let Some(playlist) = lists.get_mut(current_playlist()).filter(|p| p.smart.is_none()) {
+                            if !playlist.watched_folders.contains(&dir) {
+                                playlist.watched_folders.push(dir.clone());
+                            }
+                            sync_watched_path.call((playlist.id.clone(), dir.into()));
+                        }
+                        *show_directory_browser.write() = false;
+                    },
+                    on_add_tracks: move |tracks: Vec<TrackStub>| {
+                        if let Some(playlist) = playlists.write().get_mut(current_playlist()) {
+                            for track in tracks {
+                                playlist.add_track(track);
+                            }
+                        }
+                        *show_directory_browser.write() = false;
+                    },
+                }
+            }
+
+            if show_webdav_config_list() {
+                WebDAVConfigListModal {
+                    configs: webdav_configs(),
+                    current_config: current_webdav_config(),
+                    track_cache_mb: ui_settings.read().track_cache_mb,
+                    track_cache_used: track_cache_used(),
+                    on_track_cache_change: move |mb: u32| {
+                        player::set_cache_limit(u64::from(mb) * 1024 * 1024);
+                        ui_settings.write().track_cache_mb = mb;
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    download_connections: ui_settings.read().download_connections,
+                    on_download_connections_change: move |connections: usize| {
+                        player::set_download_connections(connections);
+                        ui_settings.write().download_connections = connections;
+                        if let Err(e) = save_ui_settings(&ui_settings.peek()) {
+                            eprintln!("[Config] 保存界面设置失败: {}", e);
+                        }
+                    },
+                    on_close: move |_| {
+                        *show_webdav_config_list.write() = false;
+                    },
+                    on_add_config: move |_| {
+                        *editing_webdav_config.write() = None;
+                        *show_webdav_config.write() = true;
+                    },
+                    on_edit_config: move |idx| {
+                        *editing_webdav_config.write() = Some(idx);
+                        *show_webdav_config.write() = true;
+                    },
+                    on_delete_config: move |idx| {
+                        let mut configs = webdav_configs.write();
+                        if idx < configs.len() {
+                            configs.remove(idx);
+                        }
+                        if let Some(current) = current_webdav_config() {
+                            if current >= configs.len() && !configs.is_empty() {
+                                *current_webdav_config.write() = Some(configs.len() - 1);
+                            }
+                        }
+
+                        // 保存到磁盘
+                        let configs_to_save = configs.clone();
+                        drop(configs);
+                        if let Err(e) = save_webdav_configs(&configs_to_save) {
+                            eprintln!("保存WebDAV配置失败: {}", e);
+                        }
+                    },
+                    on_select_config: move |idx| {
+                        *current_webdav_config.write() = Some(idx);
+                    },
+                }
+            }
+
+            if show_webdav_config() {
+                WebDAVConfigModal {
+                    config: {
+                        let editing_idx = editing_webdav_config();
+                        if let Some(idx) = editing_idx {
+                            if idx < webdav_configs().len() {
+                                webdav_configs()[idx].clone()
+                            } else {
+                                WebDAVConfig {
+                                    id: uuid::Uuid::new_v4().to_string(),
+                                    name: String::new(),
+                                    url: String::new(),
+                                    username: String::new(),
+                                    encrypted_password: String::new(),
+                                    enabled: false,
+                                    password: None,
+                                    synced_folders: Vec::new(),
+                                    download_limit_kbps: 0,
+                                    auth: WebDAVAuth::Basic,
+                                }
+                            }
+                        } else {
+                            WebDAVConfig {
+                                id: uuid::Uuid::new_v4().to_string(),
+                                name: String::new(),
+                                url: String::new(),
+                                username: String::new(),
+                                encrypted_password: String::new(),
+                                enabled: false,
+                                password: None,
+                                synced_folders: Vec::new(),
+                                download_limit_kbps: 0,
+                                auth: WebDAVAuth::Basic,
+                            }
+                        }
+                    },
+                    on_close: move |_| {
+                        *show_webdav_config.write() = false;
+                        *editing_webdav_config.write() = None;
+                    },
+                    on_save_config: move |new_config: WebDAVConfig| {
+                        let editing_idx = editing_webdav_config();
+                        let mut configs = webdav_configs.write();
+                        if let Some(idx) = editing_idx {
+                            if idx < configs.len() {
+                                configs[idx] = new_config.clone();
+                            }
+                        } else {
+                            configs.push(new_config);
+                        }
+                        let configs_to_save = configs.clone();
+                        drop(configs);
+                        if let Err(e) = save_webdav_configs(&configs_to_save) {
+                            eprintln!("保存WebDAV配置失败: {}", e);
+                        }
+                        *show_webdav_config.write() = false;
+                        *editing_webdav_config.write() = None;
+                        *show_webdav_config_list.write() = true;
+                    },
+                }
+            }
+
+            if show_webdav_browser() {
+                if let Some(config_idx) = current_webdav_config() {
+                    if config_idx < webdav_configs().len() {
+                        {
+                            rsx! {
+                                WebDAVBrowserModal {
+                                    config: webdav_configs()[config_idx].clone(),
+                                    on_close: move |_| {
+                                        *show_webdav_browser.write() = false;
+                                    },
+                                    on_import_folder: move |(tracks, failures): (Vec<Track>, Vec<ImportFailure>)| {
+                                        let config_id = webdav_configs.peek()[config_idx].id.clone();
+                                        add_cloud_import.call((config_id, tracks, failures, Vec::new()));
+                                        *show_webdav_browser.write() = false;
+                                    },
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn NowPlayingCard(
+    current_track: Option<TrackStub>,
+    player_metadata: Signal<Option<player::TrackMetadata>>,
+    player_ref: Signal<Option<player::MusicPlayer>>,
+    rating: Option<u8>,
+    on_rate: EventHandler<Option<u8>>,
+    favorite: bool,
+    on_favorite: EventHandler<bool>,
+    visualizer: VisualizerMode,
+    on_visualizer_change: EventHandler<VisualizerMode>,
+) -> Element {
+    let full_track: Option<Track> = current_track.as_ref().map(|stub| {
+        Track {
+            id: stub.id.clone(),
+            path: stub.path.clone(),
+            title: stub.title.clone(),
+            artist: stub.artist.clone(),
+            album: stub.album.clone(),
+            album_artist: stub.album_artist.clone(),
+            track_number: stub.track_number,
+            year: stub.year,
+            genre: stub.genre.clone(),
+            duration: stub.duration,
+            cover: stub.cover.clone(),
+            source: stub.source.clone(),
+            gain_db: stub.gain_db,
+        }
+    });
+
+    // Track last fetched lyrics to avoid duplicates
+    let mut last_lyric_track_info = use_signal(|| String::new());
+
+    // Effect to fetch lyrics when metadata changes
+    let player_ref_for_lyrics = player_ref.clone();
+    use_effect(move || {
+        let metadata = player_metadata();
+        let player_option = player_ref_for_lyrics.read().clone();
+
+        if let Some(ref p) = player_option {
+            if let Some(m) = metadata.as_ref() {
+                if let Some(title) = m.title.clone() {
+                    if !title.is_empty() {
+                        let artist = m.artist.clone().unwrap_or_default();
+                        let track_info = format!("{}|{}", artist, title);
+                        if *last_lyric_track_info.read() != track_info {
+                            eprintln!("[Lyrics] 检测到新曲目: {} - {}", artist, title);
+
+                            let player_for_task = p.clone();
+                            let artist_for_search = artist.clone();
+                            spawn(async move {
+                                eprintln!("[Lyrics] 开始搜索歌词...");
+                                player_for_task.fetch_lyrics_for_current_track(&title, &artist_for_search).await;
+                                eprintln!("[Lyrics] 歌词搜索完成");
+                            });
+
+                            *last_lyric_track_info.write() = track_info;
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // A file can embed several pictures (front, back, booklet...); the one
+    // picked is remembered for the track it was picked on
+    let track_path = full_track.as_ref().map(|t| t.path.clone()).unwrap_or_default();
+    let mut picture_choice = use_signal(|| (String::new(), 0usize));
+    let picture_labels: Vec<&'static str> = player_metadata
+        .read()
+        .as_ref()
+        .map(|m| m.pictures.iter().map(|picture| picture.label()).collect())
+        .unwrap_or_default();
+    let picture_idx = match &*picture_choice.read() {
+        (path, idx) if *path == track_path && *idx < picture_labels.len() => *idx,
+        _ => 0,
+    };
+
+    // The chosen embedded picture, else the library's cached art. Only this
+    // card shows art at full size, encoded once per picture rather than on
+    // every position update.
+    let cover_id = full_track.as_ref().and_then(|t| t.cover.clone());
+    let cover_img = use_memo(use_reactive!(|(cover_id, picture_idx)| {
+        player_metadata
+            .read()
+            .as_ref()
+            .and_then(|m| m.pictures.get(picture_idx))
+            .map(|picture| cover_cache::data_url(&picture.data))
+            .or_else(|| cover_id.as_deref().and_then(cover_cache::load).map(|data| cover_cache::data_url(&data)))
+    }))();
+
+    let display_title = player_metadata.read().as_ref()
+        .and_then(|m| m.title.clone())
+        .or_else(|| full_track.as_ref().map(|t| t.title.clone()))
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let display_artist = player_metadata.read().as_ref()
+        .and_then(|m| m.artist.clone())
+        .or_else(|| full_track.as_ref().map(|t| t.artist.clone()))
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+
+    let display_album = player_metadata.read().as_ref()
+        .and_then(|m| m.album.clone())
+        .or_else(|| full_track.as_ref().map(|t| t.album.clone()))
+        .unwrap_or_else(|| "Unknown Album".to_string());
+    let year_genre = full_track.as_ref().map(|t| year_and_genre(t.year, t.genre.as_deref())).unwrap_or_default();
+
+    // Codec, bitrate and so on, once the player has opened the file
+    let stream_summary = player_metadata.read().as_ref()
+        .map(|m| m.stream.summary())
+        .filter(|summary| !summary.is_empty());
+
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-6 mb-6 flex items-center gap-6",
+
+            if let Some(img_src) = cover_img {
+                div { class: "flex-shrink-0",
+                    div { class: "w-40 h-40 rounded-lg shadow-lg overflow-hidden",
+                        img {
+                            src: img_src,
+                            alt: "Album cover",
+                            class: "w-full h-full object-cover",
+                        }
+                    }
+                    if picture_labels.len() > 1 {
+                        div { class: "flex items-center justify-between mt-1 text-xs text-gray-400",
+                            button {
+                                class: "px-2 hover:text-white",
+                                title: "Previous picture",
+                                onclick: {
+                                    let (path, count) = (track_path.clone(), picture_labels.len());
+                                    move |_| picture_choice.set((path.clone(), (picture_idx + count - 1) % count))
+                                },
+                                "‹"
+                            }
+                            span { "{picture_labels[picture_idx]} {picture_idx + 1}/{picture_labels.len()}" }
+                            button {
+                                class: "px-2 hover:text-white",
+                                title: "Next picture",
+                                onclick: {
+                                    let (path, count) = (track_path.clone(), picture_labels.len());
+                                    move |_| picture_choice.set((path.clone(), (picture_idx + 1) % count))
+                                },
+                                "›"
+                            }
+                        }
+                    }
+                }
+            } else {
+                div { class: "w-40 h-40 flex-shrink-0 rounded-lg shadow-lg bg-gray-700 flex items-center justify-center text-4xl",
+                    "🎵"
+                }
+            }
+
+            div { class: "flex-1 text-left",
+                h2 { class: "text-2xl font-bold mb-2", "{display_title}" }
+                p { class: "text-gray-400 mb-1", "{display_artist}" }
+                p { class: "text-gray-500 text-sm", "{display_album}" }
+                if !year_genre.is_empty() {
+                    p { class: "text-gray-500 text-xs", "{year_genre}" }
+                }
+                if let Some(summary) = stream_summary {
+                    p { class: "text-gray-500 text-xs", "{summary}" }
+                }
+                if current_track.is_some() {
+                    // Clicking the current rating again clears it
+                    div { class: "flex gap-1 mt-2 text-xl",
+                        button {
+                            class: if favorite { "text-red-500 hover:text-red-400 mr-2" } else { "text-gray-600 hover:text-gray-400 mr-2" },
+                            title: if favorite { "Remove from Favorites" } else { "Add to Favorites" },
+                            onclick: move |_| on_favorite.call(!favorite),
+                            if favorite {
+                                "♥"
+                            } else {
+                                "♡"
+                            }
+                        }
+                        for stars in 1..=5u8 {
+                            button {
+                                key: "{stars}",
+                                class: if rating.is_some_and(|r| r >= stars) { "text-yellow-400 hover:text-yellow-300" } else { "text-gray-600 hover:text-gray-400" },
+                                title: "Rate {stars}/5",
+                                onclick: move |_| on_rate.call(if rating == Some(stars) { None } else { Some(stars) }),
+                                "★"
+                            }
+                        }
+                    }
+                }
+                if visualizer != VisualizerMode::Off {
+                    Visualizer { player_ref, mode: visualizer }
+                }
+                button {
+                    class: "px-2 py-1 mt-2 bg-gray-700 hover:bg-gray-600 rounded text-xs",
+                    title: "Switch between spectrum bars, waveform and off",
+                    onclick: move |_| on_visualizer_change.call(visualizer.next()),
+                    "{visualizer.label()}"
+                }
+            }
+        }
+    }
+}
+
+// Spectrum bars or an oscilloscope line drawn from the samples the player is
+// outputting. It polls the player itself, so nothing is sampled while hidden.
+#[component]
+fn Visualizer(player_ref: Signal<Option<player::MusicPlayer>>, mode: VisualizerMode) -> Element {
+    let mut bars = use_signal(Vec::<f32>::new);
+    let mut waveform = use_signal(Vec::<f32>::new);
+    let mut mode_now = use_signal(|| mode);
+    if *mode_now.peek() != mode {
+        mode_now.set(mode);
+    }
+
+    let _poll = use_future(move || async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(VISUALIZER_FRAME_MS)).await;
+            let Some(player) = player_ref.peek().clone() else { continue };
+            match *mode_now.peek() {
+                VisualizerMode::Bars => {
+                    // Bars jump up at once and fall back gently, like a hardware analyser
+                    let fresh = player.get_spectrum();
+                    let falling: Vec<f32> = {
+                        let previous = bars.peek();
+                        fresh
+                            .iter()
+                            .enumerate()
+                            .map(|(i, level)| level.max(previous.get(i).copied().unwrap_or(0.0) * VISUALIZER_FALLOFF))
+                            .collect()
+                    };
+                    bars.set(falling);
+                }
+                VisualizerMode::Waveform => waveform.set(player.get_waveform(VISUALIZER_POINTS)),
+                VisualizerMode::Off => {}
+            }
+        }
+    });
+
+    // The line runs over a 0..100 by -1..1 box, stretched to fill the element
+    let points = waveform
+        .read()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("{:.2},{:.3}", i as f32 * 100.0 / (VISUALIZER_POINTS - 1) as f32, -v))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    rsx! {
+        div { class: "visualizer mt-2 bg-gray-900 rounded overflow-hidden",
+            if mode == VisualizerMode::Bars {
+                div { class: "visualizer-bars",
+                    for (i , level) in bars.read().iter().enumerate() {
+                        div {
+                            key: "{i}",
+                            class: "flex-1 meter-ok",
+                            style: "height: {(level * 100.0).max(1.0)}%;",
+                        }
+                    }
+                }
+            } else {
+                svg {
+                    class: "w-full h-full",
+                    view_box: "0 -1 100 2",
+                    preserve_aspect_ratio: "none",
+                    polyline {
+                        class: "visualizer-line",
+                        points: "{points}",
+                        fill: "none",
+                        stroke_width: "1.5",
+                        vector_effect: "non-scaling-stroke",
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Pending guest requests in party mode; approving one adds it to the current playlist
+#[component]
+fn PartyQueuePanel(
+    guest_url: String,
+    requests: Vec<party::SongRequest>,
+    on_approve: EventHandler<u64>,
+    on_reject: EventHandler<u64>,
+) -> Element {
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4 mb-6",
+            div { class: "flex justify-between items-center gap-2 mb-2",
+                h3 { class: "font-bold", "🎉 Song requests" }
+                span { class: "text-xs text-gray-400 truncate", "Guests open {guest_url}" }
+            }
+            if requests.is_empty() {
+                div { class: "text-sm text-gray-500", "No requests yet" }
+            }
+            for request in requests {
+                div {
+                    key: "{request.id}",
+                    class: "flex items-center gap-2 p-2 bg-gray-700 rounded mb-1",
+                    div { class: "flex-1 min-w-0",
+                        div { class: "truncate text-sm font-semibold", "{request.title}" }
+                        div { class: "text-xs text-gray-400 truncate",
+                            "{request.artist} · {request.guest}, {format_time_ago(request.requested_at)}"
+                        }
+                    }
+                    button {
+                        class: "px-2 py-1 bg-green-600 hover:bg-green-700 rounded text-sm",
+                        title: "Add to the end of the current playlist",
+                        onclick: move |_| on_approve.call(request.id),
+                        "✓"
+                    }
+                    button {
+                        class: "px-2 py-1 bg-red-600 hover:bg-red-700 rounded text-sm",
+                        title: "Reject",
+                        onclick: move |_| on_reject.call(request.id),
+                        "✕"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn PlayQueuePanel(queue: Vec<TrackStub>, on_remove: EventHandler<String>, on_clear: EventHandler<()>) -> Element {
+    let mut expanded = use_signal(|| true);
+    let count = queue.len();
+
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4 mb-6",
+            div { class: "flex justify-between items-center gap-2",
+                button {
+                    class: "font-bold text-left flex-1",
+                    title: "Played next, before the playlist carries on",
+                    onclick: move |_| expanded.toggle(),
+                    if expanded() { "▾ " } else { "▸ " }
+                    "📋 Up next ({count})"
+                }
+                button {
+                    class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                    onclick: move |_| on_clear.call(()),
+                    "Clear"
+                }
+            }
+            if expanded() {
+                div { class: "mt-2 max-h-48 overflow-y-auto",
+                    for (idx , thumbnail , track) in queue
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, track)| (idx, track.cover.as_deref().and_then(cover_cache::small_data_url), track))
+                    {
+                        div {
+                            key: "{track.id}",
+                            class: "flex items-center gap-2 p-2 bg-gray-700 rounded mb-1",
+                            span { class: "text-xs text-gray-400 w-5", "{idx + 1}" }
+                            if let Some(src) = thumbnail {
+                                img { class: "w-8 h-8 rounded object-cover flex-shrink-0", src: "{src}" }
+                            } else {
+                                div { class: "w-8 h-8 rounded bg-gray-800 flex-shrink-0 flex items-center justify-center text-xs", "🎵" }
+                            }
+                            div { class: "flex-1 min-w-0",
+                                div { class: "truncate text-sm font-semibold", "{track.title}" }
+                                div { class: "text-xs text-gray-400 truncate", "{track.artist}" }
+                            }
+                            button {
+                                class: "px-2 py-1 bg-red-600 hover:bg-red-700 rounded text-sm",
+                                title: "Remove from the queue",
+                                onclick: move |_| on_remove.call(track.id.clone()),
+                                "✕"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn LyricsDisplay(
+    current_time: Signal<Duration>,
+    lyric: Option<player::Lyric>,
+) -> Element {
+    let (visible_lines, current_line_idx) = if let Some(ref lyric) = lyric {
+        let current_idx = lyric.get_current_line(*current_time.read()).unwrap_or(0);
+        let start = current_idx.saturating_sub(2);
+        let end = (current_idx + 4).min(lyric.lines.len());
+        let lines = lyric.lines[start..end].to_vec();
+        let relative_current_idx = current_idx.saturating_sub(start);
+        (lines, Some(relative_current_idx))
+    } else {
+        (vec![], None)
+    };
+
+    rsx! {
+        if !visible_lines.is_empty() {
+            div { class: "bg-gray-800 rounded-lg p-6 mb-6 text-center",
+                div { class: "space-y-3 max-h-48 overflow-y-auto",
+                    for (idx , line) in visible_lines.iter().enumerate() {
+                        if Some(idx) == current_line_idx {
+                            div { class: "text-xl font-bold text-white transition-colors scale-105",
+                                "{line.text}"
+                            }
+                        } else {
+                            div { class: "text-sm text-gray-400 transition-colors",
+                                "{line.text}"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn AudioSettings(
+    balance: f32,
+    mono_downmix: bool,
+    crossfade_secs: u32,
+    // None for the system default
+    output_device: Option<String>,
+    output_devices: Vec<String>,
+    read_ahead_mb: u32,
+    normalize_loudness: bool,
+    // Tracks measured so far and how many the running scan has
+    loudness_progress: Option<(usize, usize)>,
+    unmeasured: usize,
+    on_balance_change: EventHandler<f32>,
+    on_mono_change: EventHandler<bool>,
+    on_crossfade_change: EventHandler<u32>,
+    on_output_device_change: EventHandler<Option<String>>,
+    on_read_ahead_change: EventHandler<u32>,
+    on_normalize_change: EventHandler<bool>,
+    on_measure_loudness: EventHandler<()>,
+    content_types: ContentTypeSettings,
+    on_content_types_change: EventHandler<ContentTypeSettings>,
+) -> Element {
+    let set_behavior = move |content_type: ContentType, behavior: PlayBehavior| {
+        let mut types = content_types;
+        types.set(content_type, behavior);
+        on_content_types_change.call(types);
+    };
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-6 mb-6",
+            h3 { class: "text-lg font-bold mb-4", "🎚 Audio Settings" }
+
+            div { class: "flex items-center gap-4 mb-4",
+                span { class: "text-sm w-16", "Balance" }
+                input {
+                    r#type: "range",
+                    min: "-100",
+                    max: "100",
+                    value: (balance * 100.0).round() as i32,
+                    class: "flex-1",
+                    oninput: move |e| {
+                        let val = e.value().parse::<f32>().unwrap_or(0.0) / 100.0;
+                        on_balance_change.call(val);
+                    },
+                    ondoubleclick: move |_| on_balance_change.call(0.0),
+                }
+                span { class: "text-sm w-16", "{format_balance(balance)}" }
+            }
+
+            div {
+                class: "flex items-center gap-4 mb-4",
+                title: "Overlap between tracks as one hands over to the next. The next track of the same album always follows without a gap, for live and mixed albums.",
+                span { class: "text-sm w-16", "Crossfade" }
+                input {
+                    r#type: "range",
+                    min: "0",
+                    max: "{MAX_CROSSFADE_SECS}",
+                    value: crossfade_secs,
+                    class: "flex-1",
+                    oninput: move |e| {
+                        if let Ok(secs) = e.value().parse::<u32>() {
+                            on_crossfade_change.call(secs.min(MAX_CROSSFADE_SECS));
+                        }
+                    },
+                }
+                span { class: "text-sm w-16",
+                    if crossfade_secs == 0 { "Off" } else { "{crossfade_secs}s" }
+                }
+            }
+
+            div { class: "flex items-center gap-4 mb-4",
+                span { class: "text-sm w-16", "Output" }
+                select {
+                    class: "flex-1 px-2 py-1 rounded bg-gray-700 border border-gray-600 text-sm",
+                    value: output_device.clone().unwrap_or_default(),
+                    onchange: move |e| {
+                        let name = e.value();
+                        on_output_device_change.call((!name.is_empty()).then_some(name));
+                    },
+                    option { value: "", "System default" }
+                    for name in output_devices.iter() {
+                        option { key: "{name}", value: "{name}", "{name}" }
+                    }
+                    // Chosen before, but not plugged in now
+                    if let Some(missing) = output_device.as_ref().filter(|name| !output_devices.contains(name)) {
+                        option { value: "{missing}", "{missing} (not connected)" }
+                    }
+                }
+            }
+
+            label {
+                class: "flex items-center gap-2 text-sm mb-4",
+                title: "Local files are read this far ahead of playback, which smooths over slow disks and network mounts; 0 reads them as they play. Takes effect from the next track.",
+                "Read ahead"
+                input {
+                    r#type: "number",
+                    min: "0",
+                    max: "{MAX_READ_AHEAD_MB}",
+                    class: "w-16 px-2 py-1 bg-gray-700 rounded",
+                    value: "{read_ahead_mb}",
+                    onchange: move |e| {
+                        if let Ok(mb) = e.value().parse::<u32>() {
+                            on_read_ahead_change.call(mb.min(MAX_READ_AHEAD_MB));
+                        }
+                    },
+                }
+                "MB of local files"
+            }
+
+            label { class: "flex items-center gap-2 text-sm cursor-pointer",
+                input {
+                    r#type: "checkbox",
+                    checked: mono_downmix,
+                    onchange: move |e| on_mono_change.call(e.checked()),
+                }
+                "Mono downmix"
+            }
+
+            label {
+                class: "flex items-center gap-2 text-sm cursor-pointer mt-2",
+                title: "Play every track at about the same loudness, using each track's measured EBU R128 loudness; the track's own volume offset still applies on top",
+                input {
+                    r#type: "checkbox",
+                    checked: normalize_loudness,
+                    onchange: move |e| on_normalize_change.call(e.checked()),
+                }
+                "Normalize loudness"
+            }
+            if let Some((done, total)) = loudness_progress {
+                p { class: "text-xs text-gray-400 mt-2", "Measuring loudness… {done} / {total}" }
+            } else if unmeasured > 0 {
+                div { class: "flex items-center gap-2 mt-2 text-xs text-gray-400",
+                    span { "{format_count(unmeasured, \"track\", \"tracks\")} not measured yet" }
+                    button {
+                        class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                        onclick: move |_| on_measure_loudness.call(()),
+                        "Measure"
+                    }
+                }
+            }
+
+            div {
+                class: "mt-4 space-y-1 text-sm",
+                title: "How playlists of each content type play, unless a playlist's own Auto-advance says otherwise",
+                for content_type in ContentType::ALL {
+                    div { key: "{content_type.label()}", class: "flex items-center gap-4",
+                        span { class: "w-32", "{content_type.label()}" }
+                        label { class: "flex items-center gap-1 text-gray-300 cursor-pointer",
+                            input {
+                                r#type: "checkbox",
+                                checked: content_types.get(content_type).auto_advance,
+                                onchange: move |e| {
+                                    let behavior = content_types.get(content_type);
+                                    set_behavior(content_type, PlayBehavior { auto_advance: e.checked(), ..behavior });
+                                },
+                            }
+                            "Auto-advance"
+                        }
+                        label { class: "flex items-center gap-1 text-gray-300 cursor-pointer",
+                            input {
+                                r#type: "checkbox",
+                                checked: content_types.get(content_type).resume,
+                                onchange: move |e| {
+                                    let behavior = content_types.get(content_type);
+                                    set_behavior(content_type, PlayBehavior { resume: e.checked(), ..behavior });
+                                },
+                            }
+                            "Resume where left"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn LibraryScanProgress(dir: String, done: usize, total: Option<usize>, on_cancel: EventHandler<()>) -> Element {
+    let percent = match total {
+        Some(total) if total > 0 => done * 100 / total,
+        _ => 0,
+    };
+    rsx! {
+        div { class: "mb-4 p-4 bg-gray-800 rounded-lg",
+            div { class: "flex items-center justify-between gap-2 mb-2 text-sm",
+                if let Some(total) = total {
+                    span { class: "truncate", title: "{dir}", "Adding {dir}… {done} / {total}" }
+                } else {
+                    span { class: "truncate", title: "{dir}", "Looking for music in {dir}…" }
+                }
+                button {
+                    class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-xs",
+                    onclick: move |_| on_cancel.call(()),
+                    "Cancel"
+                }
+            }
+            div { class: "progress-bar",
+                div { class: "progress-bar-fill", style: "width: {percent}%;" }
+            }
+        }
+    }
+}
+
+// Peak level to meter fill: -60 dBFS and below is empty, 0 dBFS is full
+fn level_to_percent(level: f32) -> f32 {
+    if level <= 0.0 {
+        return 0.0;
+    }
+    let db = 20.0 * level.log10();
+    ((db + 60.0) / 60.0 * 100.0).clamp(0.0, 100.0)
+}
+
+#[component]
+fn LevelMeter(left: f32, right: f32) -> Element {
+    rsx! {
+        div { class: "mt-4 space-y-1",
+            for (label , level) in [("L", left), ("R", right)] {
+                div { key: "{label}", class: "flex items-center gap-2",
+                    span { class: "text-xs text-gray-400 w-4", "{label}" }
+                    div { class: "flex-1 h-2 bg-gray-700 rounded overflow-hidden",
+                        div {
+                            class: if level >= 0.99 { "h-full meter-clip" } else { "h-full meter-ok" },
+                            style: "width: {level_to_percent(level)}%; transition: width 0.1s linear;",
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn ImportReportModal(
+    report: ImportReport,
+    retrying: bool,
+    on_retry: EventHandler<Vec<String>>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let all_failed: Vec<String> = report.failed.iter().map(|failure| failure.path.clone()).collect();
+    let duplicate_titles = report.duplicates.join("\n");
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "☁ Import finished" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                div { class: "space-y-1 mb-4",
+                    div { class: "status-ok", "✓ {report.imported} imported" }
+                    if !report.duplicates.is_empty() {
+                        div {
+                            class: "text-gray-400",
+                            title: "{duplicate_titles}",
+                            "⟲ {report.duplicates.len()} skipped, already in the playlist"
+                        }
+                    }
+                    if !report.failed.is_empty() {
+                        div { class: "status-error", "✕ {report.failed.len()} failed" }
+                    }
+                }
+
+                if !report.failed.is_empty() {
+                    div { class: "h-48 overflow-y-auto space-y-1 mb-4",
+                        for failure in report.failed.iter().cloned() {
+                            div { key: "{failure.path}", class: "flex items-center gap-2 p-2 bg-gray-700 rounded",
+                                div { class: "flex-1 min-w-0",
+                                    div { class: "truncate text-sm", "{failure.name}" }
+                                    div { class: "text-xs text-gray-400 truncate", title: "{failure.reason}", "{failure.reason}" }
+                                }
+                                button {
+                                    class: "text-blue-400 hover:text-blue-300 text-sm disabled:opacity-50",
+                                    disabled: retrying,
+                                    onclick: move |_| on_retry.call(vec![failure.path.clone()]),
+                                    "Retry"
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { class: "flex justify-end gap-2",
+                    if !all_failed.is_empty() {
+                        button {
+                            class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded disabled:opacity-50",
+                            disabled: retrying,
+                            onclick: move |_| on_retry.call(all_failed.clone()),
+                            if retrying {
+                                "🔄 Retrying..."
+                            } else {
+                                "↻ Retry all failed"
+                            }
+                        }
+                    }
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Close"
+                    }
+                }
+            }
+        }
+    }
+}
+
+// How long this launch took to reach each stage, and the bug report bundle
+#[component]
+fn DiagnosticsModal(library_loaded: bool, on_error: EventHandler<String>, on_close: EventHandler<()>) -> Element {
+    let timings = diagnostics::startup_timings();
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-md shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "🩺 Diagnostics" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                h3 { class: "font-bold mb-2", "Startup" }
+                div { class: "space-y-1 mb-4",
+                    for (stage , elapsed) in timings {
+                        div { key: "{stage}", class: "flex justify-between text-sm",
+                            span { class: "text-gray-400", "{stage}" }
+                            span { "{elapsed.as_millis()} ms" }
+                        }
+                    }
+                    if !library_loaded {
+                        div { class: "text-sm text-gray-400", "Library still loading…" }
+                    }
+                }
+                p { class: "text-xs text-gray-400 mb-4", "Time since launch; the bundle includes these too." }
+
+                button {
+                    class: "w-full px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded text-sm",
+                    title: "Save platform info, startup timings, redacted settings and crash reports to a zip for bug reports",
+                    onclick: move |_| {
+                        spawn(async move {
+                            let bundle = match base_config_dir().and_then(|dir| diagnostics::create_bundle(&dir, &get_config_dir()?)) {
+                                Ok(bundle) => bundle,
+                                Err(e) => {
+                                    on_error.call(format!("Failed to create diagnostic bundle: {}", e));
+                                    return;
+                                }
+                            };
+                            if let Some(file) = rfd::AsyncFileDialog::new()
+                                .set_file_name(diagnostics::bundle_file_name())
+                                .save_file()
+                                .await
+                            {
+                                if let Err(e) = std::fs::write(file.path(), bundle) {
+                                    on_error.call(format!("Failed to save diagnostic bundle: {}", e));
+                                }
+                            }
+                        });
+                    },
+                    "💾 Save diagnostic bundle"
+                }
+            }
+        }
+    }
+}
+
+// Grey stand-in rows shown while the library is still loading
+#[component]
+fn LibrarySkeleton(rows: usize) -> Element {
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4 space-y-2",
+            div { class: "skeleton h-8 w-48 mb-4" }
+            for i in 0..rows {
+                div { key: "{i}", class: "skeleton h-10" }
+            }
+        }
+    }
+}
+
+#[component]
+fn VolumeOsd(volume: f32) -> Element {
+    let percent = (volume * 100.0).round() as i32;
+    rsx! {
+        div { class: "volume-osd",
+            div { class: "text-3xl mb-2",
+                if percent == 0 {
+                    "🔇"
+                } else {
+                    "🔊"
+                }
+            }
+            div { class: "h-2 bg-gray-700 rounded overflow-hidden mb-2",
+                div { class: "h-full bg-blue-500", style: "width: {percent}%;" }
+            }
+            div { class: "text-sm", "{percent}%" }
+        }
+    }
+}
+
+// Holds the volume keys as global shortcuts for as long as it's mounted. While held
+// they no longer change the system volume, so the App only mounts this when enabled.
+#[component]
+fn VolumeKeyShortcuts(on_key: EventHandler<VolumeKey>) -> Element {
+    use dioxus_desktop::{use_global_shortcut, HotKeyState};
+
+    let up = use_global_shortcut("AudioVolumeUp", move |state| {
+        if state == HotKeyState::Pressed {
+            on_key.call(VolumeKey::Up);
+        }
+    });
+    let down = use_global_shortcut("AudioVolumeDown", move |state| {
+        if state == HotKeyState::Pressed {
+            on_key.call(VolumeKey::Down);
+        }
+    });
+    let mute = use_global_shortcut("AudioVolumeMute", move |state| {
+        if state == HotKeyState::Pressed {
+            on_key.call(VolumeKey::Mute);
+        }
+    });
+    let registered = [("up", up.is_ok()), ("down", down.is_ok()), ("mute", mute.is_ok())];
+    use_hook(move || {
+        for (name, ok) in registered {
+            if !ok {
+                eprintln!("[Shortcut] 注册音量{}快捷键失败，可能已被其他程序占用", name);
+            }
+        }
+    });
+
+    rsx! {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    Ok,
+    Error,
+    Off,
+}
+
+// Icon plus text so status never depends on color alone
+#[component]
+fn StatusBadge(status: Status, label: String) -> Element {
+    let (icon, class) = match status {
+        Status::Ok => ("✓", "status-ok"),
+        Status::Error => ("✕", "status-error"),
+        Status::Off => ("○", "status-off"),
+    };
+    rsx! {
+        span { class: "{class}", "{icon} {label}" }
+    }
+}
+
+// Something picked up in the browser pane that can be dropped on the queue
+#[derive(Clone, Debug, PartialEq)]
+enum DragPayload {
+    CloudFile { config_id: String, item: webdav::WebDAVItem },
+    Playlist { playlist_id: String },
+}
+
+// What is being dragged right now, shared between the browser rows and the queue
+#[derive(Clone, Copy)]
+struct DragState(Signal<Option<DragPayload>>);
+
+// Scroll offsets of list views, keyed per view ("playlist:<id>",
+// "webdav:<config id>:<path>"), so going back to a folder or playlist returns
+// to where the user left it. Kept for the lifetime of the window.
+#[derive(Clone, Copy)]
+struct ScrollPositions(Signal<std::collections::HashMap<String, f64>>);
+
+// Handlers for one scrollable list. `ready` is false while the view's content
+// is still loading; the offset is restored once it turns true.
+#[derive(Clone, Copy)]
+struct RememberedScroll {
+    positions: ScrollPositions,
+    element: Signal<Option<std::rc::Rc<MountedData>>>,
+    key: Signal<String>,
+    ready: Signal<bool>,
+}
+
+fn use_remembered_scroll(key: String, ready: bool) -> RememberedScroll {
+    let positions = use_context::<ScrollPositions>();
+    let element = use_signal(|| None::<std::rc::Rc<MountedData>>);
+    let mut current_key = use_signal(|| key.clone());
+    let mut current_ready = use_signal(|| ready);
+    let scroll = RememberedScroll { positions, element, key: current_key, ready: current_ready };
+
+    use_effect(use_reactive!(|(key, ready)| {
+        current_key.set(key);
+        current_ready.set(ready);
+        if ready {
+            scroll.restore();
+        }
+    }));
+    scroll
+}
+
+impl RememberedScroll {
+    fn onmounted(mut self, event: MountedEvent) {
+        self.element.set(Some(event.data()));
+        if *self.ready.peek() {
+            self.restore();
+        }
+    }
+
+    fn onscroll(mut self, event: ScrollEvent) {
+        // Content swapping in for a new key scrolls too; don't record that
+        if !*self.ready.peek() {
+            return;
+        }
+        let key = self.key.peek().clone();
+        self.positions.0.write().insert(key, event.data().scroll_top());
+    }
+
+    fn restore(self) {
+        let Some(element) = self.element.peek().clone() else { return };
+        let top = self.positions.0.peek().get(&*self.key.peek()).copied().unwrap_or(0.0);
+        spawn(async move {
+            let _ = element.scroll(dioxus::html::geometry::PixelsVector2D::new(0.0, top), ScrollBehavior::Instant).await;
+        });
+    }
+}
+
+#[component]
+fn PlayerControls(
+    // Lock mode hides the chapters editor
+    locked: bool,
+    state: PlayerState,
+    duration: Option<Duration>,
+    volume: f32,
+    levels: (f32, f32),
+    current_time: Signal<Duration>,
+    lyric: Option<player::Lyric>,
+    chapters: Vec<chapters::Chapter>,
+    on_edit_chapters: EventHandler<()>,
+    on_play: EventHandler<()>,
+    on_pause: EventHandler<()>,
+    on_stop: EventHandler<()>,
+    on_seek: EventHandler<Duration>,
+    on_volume_change: EventHandler<f32>,
+    on_previous: EventHandler<()>,
+    on_next: EventHandler<()>,
+    shuffle: bool,
+    on_toggle_shuffle: EventHandler<()>,
+) -> Element {
+    let progress_percent = if let Some(d) = duration {
+        if d.as_secs() > 0 {
+            let ct = current_time();
+            (ct.as_secs_f64() / d.as_secs_f64() * 100.0).clamp(0.0, 100.0) as i32
+        } else {
+            0
+        }
+    } else {
+        0
+    };
+
+    let formatted_time = format_duration(current_time());
+    let formatted_duration = duration.map(format_duration).unwrap_or_else(|| "0:00".to_string());
+
+    // Pointer position over the seek bar in px, and the bar's width, measured on entry
+    let mut seek_bar = use_signal(|| None::<std::rc::Rc<MountedData>>);
+    let mut seek_bar_width = use_signal(|| 0.0f64);
+    let mut hover_x = use_signal(|| None::<f64>);
+    let tooltip = hover_x().zip(duration.filter(|d| !d.is_zero())).and_then(|(x, d)| {
+        let width = seek_bar_width();
+        if width <= 0.0 {
+            return None;
+        }
+        let target = Duration::from_secs_f64(d.as_secs_f64() * (x / width).clamp(0.0, 1.0));
+        let line = lyric.as_ref().and_then(|lyric| lyric.line_at(target)).map(|line| line.text.clone());
+        let chapter = chapters::chapter_at(&chapters, target).map(|i| chapters[i].title.clone());
+        Some((x.clamp(0.0, width), format_duration(target), chapter, line))
+    });
+    let markers: Vec<(f64, String)> = match duration.filter(|d| !d.is_zero()) {
+        Some(d) => chapters
+            .iter()
+            .filter(|chapter| !chapter.start.is_zero() && chapter.start < d)
+            .map(|chapter| (chapter.start.as_secs_f64() / d.as_secs_f64() * 100.0, chapter.title.clone()))
+            .collect(),
+        None => Vec::new(),
+    };
+    let current_chapter = chapters::chapter_at(&chapters, current_time()).map(|i| chapters[i].title.clone());
+    let previous_chapters = chapters.clone();
+    let next_chapters = chapters.clone();
+
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-6 mb-6",
+
+            div { class: "mb-4 relative",
+                if let Some((x, time, chapter, line)) = tooltip {
+                    div { class: "seek-tooltip", style: "left: {x}px;",
+                        div { class: "font-semibold", "{time}" }
+                        if let Some(chapter) = chapter {
+                            div { class: "text-yellow-400 truncate", "{chapter}" }
+                        }
+                        if let Some(line) = line.filter(|line| !line.trim().is_empty()) {
+                            div { class: "text-gray-300 truncate", "{line}" }
+                        }
+                    }
+                }
+                input {
+                    onmounted: move |e| seek_bar.set(Some(e.data())),
+                    onmouseenter: move |_| {
+                        let Some(element) = seek_bar.peek().clone() else { return };
+                        spawn(async move {
+                            if let Ok(rect) = element.get_client_rect().await {
+                                seek_bar_width.set(rect.width());
+                            }
+                        });
+                    },
+                    onmousemove: move |e| hover_x.set(Some(e.element_coordinates().x)),
+                    onmouseleave: move |_| hover_x.set(None),
+                    r#type: "range",
+                    min: "0",
+                    max: "100",
+                    value: "{progress_percent}",
+                    class: "w-full h-2 appearance-none cursor-pointer bg-gray-700 rounded-full",
+                    style: "accent-color: #3b82f6;",
+                    oninput: move |e| {
+                        if let Some(d) = duration {
+                            let percent = e.value().parse::<f64>().unwrap_or(0.0) / 100.0;
+                            let seek_time = Duration::from_secs_f64(d.as_secs_f64() * percent);
+                            on_seek.call(seek_time);
+                        }
+                    },
+                }
+                for (idx , (left , title)) in markers.into_iter().enumerate() {
+                    div { key: "{idx}", class: "chapter-marker", style: "left: {left}%;", title: "{title}" }
+                }
+                div { class: "flex justify-between items-center gap-2 mt-2 text-xs text-gray-400",
+                    span { "{formatted_time}" }
+                    if let Some(chapter) = current_chapter {
+                        div { class: "flex items-center gap-2 min-w-0",
+                            button {
+                                class: "hover:text-white",
+                                title: "Previous chapter",
+                                onclick: move |_| {
+                                    if let Some(start) = chapters::previous_start(&previous_chapters, *current_time.peek()) {
+                                        on_seek.call(start);
+                                    }
+                                },
+                                "⏮"
+                            }
+                            span { class: "text-yellow-400 truncate", "{chapter}" }
+                            button {
+                                class: "hover:text-white",
+                                title: "Next chapter",
+                                onclick: move |_| {
+                                    if let Some(start) = chapters::next_start(&next_chapters, *current_time.peek()) {
+                                        on_seek.call(start);
+                                    }
+                                },
+                                "⏭"
+                            }
+                        }
+                    }
+                    span { "{formatted_duration}" }
+                }
+            }
+
+            div { class: "flex justify-center items-center gap-4 mb-6",
+
+                button {
+                    class: "px-6 py-2 bg-blue-500 hover:bg-blue-600 rounded-lg font-semibold",
+                    onclick: move |_| on_previous.call(()),
+                    "⏮ Previous"
+                }
+
+                button {
+                    class: "px-6 py-2 bg-red-500 hover:bg-red-600 rounded-lg font-semibold",
+                    onclick: move |_| on_stop.call(()),
+                    "⏹ Stop"
+                }
+
+                if state == PlayerState::Playing {
+                    button {
+                        class: "px-6 py-2 bg-yellow-500 hover:bg-yellow-600 rounded-lg font-semibold text-black",
+                        onclick: move |_| on_pause.call(()),
+                        "⏸ Pause"
+                    }
+                } else {
+                    button {
+                        class: "px-6 py-2 bg-green-500 hover:bg-green-600 rounded-lg font-semibold text-black",
+                        onclick: move |_| on_play.call(()),
+                        "▶ Play"
+                    }
+                }
+
+                button {
+                    class: "px-6 py-2 bg-blue-500 hover:bg-blue-600 rounded-lg font-semibold",
+                    onclick: move |_| on_next.call(()),
+                    "⏭ Next"
+                }
+
+                button {
+                    class: if shuffle { "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded-lg font-semibold" } else { "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded-lg font-semibold" },
+                    title: "Shuffle: pick the next track at random, skipping tracks played recently (the window and smart shuffle are set in History)",
+                    onclick: move |_| on_toggle_shuffle.call(()),
+                    "🔀"
+                }
+
+                if !locked {
+                    button {
+                        class: if chapters.is_empty() { "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded-lg font-semibold" } else { "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded-lg font-semibold" },
+                        title: "Chapters: attach a tracklist or .cue sheet to split a long mix into chapters",
+                        onclick: move |_| on_edit_chapters.call(()),
+                        "📑"
+                    }
+                }
+            }
+
+            div { class: "flex items-center gap-4",
+                span { class: "text-sm", "🔊" }
+                input {
+                    r#type: "range",
+                    min: "0",
+                    max: "100",
+                    value: (volume * 100.0) as i32,
+                    class: "flex-1",
+                    oninput: move |e| {
+                        let val = e.value().parse::<f32>().unwrap_or(1.0) / 100.0;
+                        on_volume_change.call(val);
+                    },
+                }
+                span { class: "text-sm w-8", "{(volume * 100.0) as i32}%" }
+            }
+
+            LevelMeter { left: levels.0, right: levels.1 }
+        }
+    }
+}
+
+#[component]
+fn PlaylistSidebar(
+    // Lock mode: no new or merged playlists, and no cloud sources to add from
+    locked: bool,
+    playlists: Vec<Playlist>,
+    current_playlist: usize,
+    webdav_configs: Vec<WebDAVConfig>,
+    expanded_webdav_index: Option<usize>,
+    webdav_items: Vec<webdav::WebDAVItem>,
+    webdav_current_path: String,
+    webdav_loading: bool,
+    recent_count: usize,
+    favorite_count: usize,
+    special_list: Option<SpecialList>,
+    on_show_special: EventHandler<SpecialList>,
+    on_select: EventHandler<usize>,
+    on_add_playlist: EventHandler<()>,
+    on_add_smart_playlist: EventHandler<()>,
+    on_rename_playlist: EventHandler<(usize, String)>,
+    on_duplicate_playlist: EventHandler<usize>,
+    on_delete_playlist: EventHandler<usize>,
+    on_import_playlist: EventHandler<()>,
+    on_merge_playlist: EventHandler<()>,
+    // Roots of the folder tree, each with whether it was added there (and so can
+    // be taken out) rather than watched by a playlist
+    folder_roots: Vec<(String, bool)>,
+    on_add_music_folder: EventHandler<()>,
+    on_remove_music_folder: EventHandler<String>,
+    on_play_file: EventHandler<String>,
+    on_toggle_webdav: EventHandler<usize>,
+    on_webdav_navigate: EventHandler<String>,
+    on_webdav_play: EventHandler<webdav::WebDAVItem>,
+) -> Element {
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    let mut selected_item = use_signal(|| None::<String>);
+    let expanded_id = expanded_webdav_index
+        .and_then(|idx| webdav_configs.get(idx))
+        .map(|config| config.id.clone())
+        .unwrap_or_default();
+    let scroll = use_remembered_scroll(format!("webdav:{}:{}", expanded_id, webdav_current_path), !webdav_loading);
+    let DragState(mut drag_state) = use_context();
+    // The playlist whose name is being edited in place, and the one waiting on a delete confirmation
+    let mut renaming = use_signal(|| None::<(usize, String)>);
+    let mut confirm_delete = use_signal(|| None::<usize>);
+    let mut finish_rename = move || {
+        if let Some((idx, name)) = renaming.write().take() {
+            if !name.trim().is_empty() {
+                on_rename_playlist.call((idx, name.trim().to_string()));
+            }
+        }
+    };
+
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4 h-full flex flex-col",
+
+            div { class: "flex-1 overflow-y-auto mb-4",
+                div { class: "flex justify-between items-center mb-4",
+                    h3 { class: "text-lg font-bold", "📋 Playlists" }
+                    if !locked {
+                        div { class: "flex gap-2",
+                            button {
+                                class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Make a playlist from one exported as CSV or JSON, e.g. from Spotify with Exportify, out of the matching tracks in your library",
+                                onclick: move |_| on_import_playlist.call(()),
+                                "📥 Import"
+                            }
+                            button {
+                                class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Merge in the selected playlist's copy from another machine, keeping changes from both sides",
+                                onclick: move |_| on_merge_playlist.call(()),
+                                "⇄ Merge"
+                            }
+                            button {
+                                class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "A playlist that fills itself with the tracks matching a set of rules",
+                                onclick: move |_| on_add_smart_playlist.call(()),
+                                "🧠 Smart"
+                            }
+                            button {
+                                class: "px-3 py-1 bg-blue-500 hover:bg-blue-600 rounded text-sm",
+                                onclick: move |_| on_add_playlist.call(()),
+                                "+ New"
+                            }
+                        }
+                    }
+                }
+
+                div { class: "space-y-2",
+                    for (list , count) in [(SpecialList::RecentlyPlayed, recent_count), (SpecialList::Favorites, favorite_count)] {
+                        if count > 0 {
+                            button {
+                                key: "{list.title()}",
+                                class: if special_list == Some(list) { "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm" } else { "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm" },
+                                onclick: move |_| on_show_special.call(list),
+                                div { class: "font-semibold", "{list.title()}" }
+                                p { class: "text-xs text-gray-300", "{format_count(count, \"track\", \"tracks\")}" }
+                            }
+                        }
+                    }
+                    for (idx , playlist) in playlists.iter().enumerate() {
+                        if let Some((_, name)) = renaming().filter(|(renamed, _)| *renamed == idx) {
+                            input {
+                                class: "w-full px-3 py-2 rounded bg-gray-700 border border-blue-500 text-sm text-white",
+                                value: "{name}",
+                                onmounted: move |e| async move {
+                                    let _ = e.set_focus(true).await;
+                                },
+                                oninput: move |e| renaming.set(Some((idx, e.value()))),
+                                onkeydown: move |e: KeyboardEvent| match e.key() {
+                                    Key::Enter => finish_rename(),
+                                    Key::Escape => renaming.set(None),
+                                    _ => {}
+                                },
+                                onblur: move |_| finish_rename(),
+                            }
+                        } else {
+                            button {
+                                class: if idx == current_playlist && special_list.is_none() { "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm" } else { "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm" },
+                                onclick: move |_| on_select.call(idx),
+                                draggable: if locked { "false" } else { "true" },
+                                ondragstart: {
+                                    let playlist_id = playlist.id.clone();
+                                    move |_| drag_state.set(Some(DragPayload::Playlist { playlist_id: playlist_id.clone() }))
+                                },
+                                ondragend: move |_| drag_state.set(None),
+                                div { class: "flex items-center gap-2",
+                                    div { class: "font-semibold truncate flex-1",
+                                        if playlist.smart.is_some() {
+                                            "🧠 "
+                                        }
+                                        "{playlist.name}"
+                                    }
+                                    if idx == current_playlist && special_list.is_none() && !locked {
+                                        span {
+                                            class: "text-gray-300 hover:text-white",
+                                            title: "Rename",
+                                            onclick: {
+                                                let name = playlist.name.clone();
+                                                move |e: MouseEvent| {
+                                                    e.stop_propagation();
+                                                    renaming.set(Some((idx, name.clone())));
+                                                }
+                                            },
+                                            "✏️"
+                                        }
+                                        span {
+                                            class: "text-gray-300 hover:text-white",
+                                            title: "Duplicate",
+                                            onclick: move |e: MouseEvent| {
+                                                e.stop_propagation();
+                                                on_duplicate_playlist.call(idx);
+                                            },
+                                            "⧉"
+                                        }
+                                        if playlists.len() > 1 {
+                                            span {
+                                                class: "text-gray-300 hover:text-white",
+                                                title: "Delete",
+                                                onclick: move |e: MouseEvent| {
+                                                    e.stop_propagation();
+                                                    confirm_delete.set(Some(idx));
+                                                },
+                                                "🗑️"
+                                            }
+                                        }
+                                    }
+                                }
+                                p { class: "text-xs text-gray-300", "{playlist_summary(playlist)}" }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !locked {
+                div { class: "border-t border-gray-700 pt-4 mb-4",
+                    div { class: "flex justify-between items-center mb-2",
+                        h3 { class: "text-lg font-bold", "📁 Folders" }
+                        button {
+                            class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                            title: "Browse a music folder here and play its files without adding them to a playlist",
+                            onclick: move |_| on_add_music_folder.call(()),
+                            "+ Add"
+                        }
+                    }
+                    if folder_roots.is_empty() {
+                        p { class: "text-xs text-gray-400", "Add a music folder to browse and play it by directory." }
+                    }
+                    div { class: "max-h-96 overflow-y-auto",
+                        for (root , removable) in folder_roots.iter() {
+                            FolderNode {
+                                key: "{root}",
+                                path: root.clone(),
+                                label: root.clone(),
+                                on_play_file,
+                                on_remove: removable.then_some(on_remove_music_folder),
+                            }
+                        }
+                    }
+                }
+            }
+
+            // WebDAV Servers Section
+            if !locked && !webdav_configs.is_empty() {
+                div { class: "border-t border-gray-700 pt-4",
+                    h3 { class: "text-lg font-bold mb-2", "☁️ Cloud Sources" }
+                    div {
+                        class: "max-h-96 overflow-y-auto space-y-2 webdav-file-list",
+                        onmounted: move |e| scroll.onmounted(e),
+                        onscroll: move |e| scroll.onscroll(e),
+                        for (idx , config) in webdav_configs.iter().enumerate() {
+                            if config.enabled {
+                                div { class: "mb-2",
+                                    button {
+                                        class: "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-teal-700 text-sm flex items-center gap-2 mb-1",
+                                        onclick: move |_| on_toggle_webdav.call(idx),
+                                        span { "☁️" }
+                                        div {
+                                            div { class: "font-semibold truncate", "{config.name}" }
+                                            div { class: "text-xs text-gray-400 truncate",
+                                                "{config.url}"
+                                            }
+                                        }
+                                    }
+
+                                    if expanded_webdav_index == Some(idx) {
+                                        div { class: "ml-4 border-l-2 border-gray-600 pl-2 space-y-1",
+                                            if webdav_loading {
+                                                div { class: "text-xs text-gray-400 p-2",
+                                                    "🔄 Loading..."
+                                                }
+                                            } else {
+                                                // Breadcrumb / Up Navigation
+                                                {
+                                                    if webdav_current_path != "/" {
+                                                        let nav_path = webdav_current_path.clone();
+                                                        Some(rsx! {
+                                                            button {
+                                                                class: "w-full text-left px-2 py-1 text-xs bg-gray-600 hover:bg-gray-500 rounded mb-1",
+                                                                onclick: move |_| {
+                                                                    let mut path = nav_path.clone();
+                                                                    if path.ends_with('/') {
+                                                                        path.pop();
+                                                                    }
+                                                                    if let Some(pos) = path.rfind('/') {
+                                                                        path.truncate(pos + 1);
+                                                                    } else {
+                                                                        path = "/".to_string();
+                                                                    }
+                                                                    on_webdav_navigate.call(path);
+                                                                },
+                                                                "⬆ .."
+                                                            }
+                                                        })
+                                                    } else {
+                                                        None
+                                                    }
+                                                }
+
+                                                if webdav_items.is_empty() {
+                                                    div { class: "text-xs text-gray-400 p-2",
+                                                        "Empty folder"
+                                                    }
+                                                } else {
+                                                    {
+
+                                                        webdav_items
+                                                            .iter()
+                                                            .map(|item| {
+                                                                let item_clone = item.clone();
+                                                                let is_dir = item.is_dir;
+                                                                let item_name = item.name.clone();
+                                                                let current_p = webdav_current_path.clone();
+                                                                let nav_click = on_webdav_navigate.clone();
+                                                                let play_click = on_webdav_play.clone();
+                                                                let double_click_item = item.clone();
+                                                                let selected_class = if selected_item.read().as_deref() == Some(item.path.as_str()) { "row-selected" } else { "" };
+                                                                let drag_payload = DragPayload::CloudFile {
+                                                                    config_id: expanded_id.clone(),
+                                                                    item: item.clone(),
+                                                                };
+                                                                rsx! {
+                                                                    div {
+                                                                        class: "flex items-center p-1 rounded hover:bg-gray-600 cursor-pointer text-sm {click_mode.row_class()} {selected_class}",
+                                                                        draggable: if is_dir { "false" } else { "true" },
+                                                                        ondragstart: move |_| drag_state.set(Some(drag_payload.clone())),
+                                                                        ondragend: move |_| drag_state.set(None),
+                                                                        onclick: move |_| {
+                                                                            if is_dir {
+                                                                                let mut path = current_p.clone();
+                                                                                if !path.ends_with('/') {
+                                                                                    path.push('/');
+                                                                                }
+                                                                                path.push_str(&item_name);
+                                                                                nav_click.call(path);
+                                                                            } else if click_mode.plays_on_click() {
+                                                                                play_click.call(item_clone.clone());
+                                                                            } else {
+                                                                                selected_item.set(Some(item_clone.path.clone()));
+                                                                            }
+                                                                        },
+                                                                        ondoubleclick: move |_| {
+                                                                            if !double_click_item.is_dir && !click_mode.plays_on_click() {
+                                                                                play_click.call(double_click_item.clone());
+                                                                            }
+                                                                        },
+                                                                        span { class: "mr-2 text-xs",
+                                                                            if is_dir {
+                                                                                "📁"
+                                                                            } else {
+                                                                                "🎵"
+                                                                            }
+                                                                        }
+                                                                        span { class: "truncate flex-1", "{item.name}" }
+                                                                    }
+                                                                }
+                                                            })
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(idx) = confirm_delete() {
+            div {
+                class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+                onclick: move |_| confirm_delete.set(None),
+
+                div {
+                    class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl",
+                    onclick: move |e| e.stop_propagation(),
+
+                    h2 { class: "text-2xl font-bold mb-4", "Delete Playlist" }
+                    p { class: "text-gray-300 mb-4",
+                        "Delete \"{playlists.get(idx).map(|p| p.name.as_str()).unwrap_or_default()}\"? Its tracks stay on disk."
+                    }
+                    div { class: "flex gap-4 justify-end",
+                        button {
+                            class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                            onclick: move |_| confirm_delete.set(None),
+                            "Cancel"
+                        }
+                        button {
+                            class: "px-4 py-2 bg-red-600 hover:bg-red-700 rounded",
+                            onclick: move |_| {
+                                confirm_delete.set(None);
+                                on_delete_playlist.call(idx);
+                            },
+                            "Delete"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A folder in the sidebar's folder tree. Its contents are read each time it's
+// opened, so files added since show up.
+#[component]
+fn FolderNode(path: String, label: String, on_play_file: EventHandler<String>, on_remove: Option<EventHandler<String>>) -> Element {
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    let mut open = use_signal(|| false);
+    // None until first opened
+    let mut entries = use_signal(|| None::<Result<Vec<FolderEntry>, String>>);
+    let mut selected_file = use_signal(|| None::<String>);
+    let toggle = {
+        let path = path.clone();
+        move |_: MouseEvent| {
+            let opening = !open();
+            open.set(opening);
+            if !opening {
+                return;
+            }
+            let path = path.clone();
+            spawn(async move {
+                let listed = tokio::task::spawn_blocking(move || list_folder(Path::new(&path)).map_err(|e| e.to_string()))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|result| result);
+                entries.set(Some(listed));
+            });
+        }
+    };
+
+    rsx! {
+        div {
+            div {
+                class: "flex items-center gap-1 p-1 rounded hover:bg-gray-600 cursor-pointer text-sm",
+                title: "{path}",
+                onclick: toggle,
+                span { class: "text-xs w-3", if open() { "▾" } else { "▸" } }
+                span { class: "text-xs", "📁" }
+                span { class: "truncate flex-1", "{label}" }
+                if let Some(on_remove) = on_remove {
+                    span {
+                        class: "text-gray-400 hover:text-white",
+                        title: "Remove from the folder tree; the files stay",
+                        onclick: {
+                            let path = path.clone();
+                            move |e: MouseEvent| {
+                                e.stop_propagation();
+                                on_remove.call(path.clone());
+                            }
+                        },
+                        "✕"
+                    }
+                }
+            }
+            if open() {
+                div { class: "ml-3 border-l-2 border-gray-600 pl-2",
+                    match entries() {
+                        None => rsx! {
+                            div { class: "text-xs text-gray-400 p-1", "🔄 Loading..." }
+                        },
+                        Some(Err(e)) => rsx! {
+                            div { class: "text-xs text-red-400 p-1", "{e}" }
+                        },
+                        Some(Ok(listed)) if listed.is_empty() => rsx! {
+                            div { class: "text-xs text-gray-400 p-1", "No music here" }
+                        },
+                        Some(Ok(listed)) => rsx! {
+                            {
+                                listed.into_iter().map(|entry| {
+                                    if entry.is_dir {
+                                        return rsx! {
+                                            FolderNode {
+                                                key: "{entry.path}",
+                                                path: entry.path.clone(),
+                                                label: entry.name.clone(),
+                                                on_play_file,
+                                                on_remove: None,
+                                            }
+                                        };
+                                    }
+                                    let selected_class = if selected_file().as_deref() == Some(entry.path.as_str()) { "row-selected" } else { "" };
+                                    let click_path = entry.path.clone();
+                                    let double_click_path = entry.path.clone();
+                                    rsx! {
+                                        div {
+                                            key: "{entry.path}",
+                                            class: "flex items-center p-1 rounded hover:bg-gray-600 cursor-pointer text-sm {click_mode.row_class()} {selected_class}",
+                                            onclick: move |_| {
+                                                if click_mode.plays_on_click() {
+                                                    on_play_file.call(click_path.clone());
+                                                } else {
+                                                    selected_file.set(Some(click_path.clone()));
+                                                }
+                                            },
+                                            ondoubleclick: move |_| {
+                                                if !click_mode.plays_on_click() {
+                                                    on_play_file.call(double_click_path.clone());
+                                                }
+                                            },
+                                            span { class: "mr-2 text-xs", "🎵" }
+                                            span { class: "truncate flex-1", "{entry.name}" }
+                                        }
+                                    }
+                                })
+                            }
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn PlaylistTracks(
+    // Lock mode: tracks can be played and queued but not cleared or adjusted
+    locked: bool,
+    playlist: Playlist,
+    // Per the playlist, or else the settings for its content type
+    auto_advances: bool,
+    current_track: Option<TrackStub>,
+    webdav_configs: Vec<WebDAVConfig>,
+    on_track_select: EventHandler<TrackStub>,
+    on_play_next: EventHandler<TrackStub>,
+    on_add_to_queue: EventHandler<TrackStub>,
+    on_gain_change: EventHandler<(String, f32)>,
+    // A track dragged by its handle: (from, to) positions
+    on_move: EventHandler<(usize, usize)>,
+    on_sort: EventHandler<(SortKey, SortOrder)>,
+    on_edit_tags: EventHandler<TrackStub>,
+    on_dedup: EventHandler<()>,
+    // Tracks that can't be played, by id, with why
+    missing: std::collections::HashMap<String, String>,
+    // Star ratings and favorites, by file_key
+    ratings: std::collections::HashMap<String, u8>,
+    favorites: std::collections::HashSet<String>,
+    on_rate: EventHandler<(String, Option<u8>)>,
+    on_favorite: EventHandler<(String, bool)>,
+    on_remove: EventHandler<Vec<String>>,
+    // The playlists selected tracks can be moved to, as (id, name)
+    other_playlists: Vec<(String, String)>,
+    on_move_to_playlist: EventHandler<(Vec<String>, String)>,
+    on_content_type_change: EventHandler<ContentType>,
+    on_auto_advance_change: EventHandler<bool>,
+    on_edit_rules: EventHandler<()>,
+    on_unwatch: EventHandler<String>,
+    on_clear: EventHandler<()>,
+) -> Element {
+    let has_tracks = !playlist.tracks.is_empty();
+    // A smart playlist's tracks come from its rules, so they can't be changed by hand
+    let editable = !locked && playlist.smart.is_none();
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    // Selected track ids, and the row a shift-click selects from
+    let mut selected = use_signal(Vec::<String>::new);
+    let mut anchor = use_signal(|| None::<usize>);
+    // Narrows the rows shown without touching the playlist; set per playlist id
+    let mut filter = use_signal(|| (String::new(), String::new()));
+    let filter_text = Some(filter()).filter(|(id, _)| *id == playlist.id).map(|(_, text)| text).unwrap_or_default();
+    let query = filter_text.trim().to_lowercase();
+    let shown = |track: &TrackStub| playlist::matches_query(track, &query);
+    // Every track's id, and whether the filter shows it
+    let track_ids: std::rc::Rc<Vec<(String, bool)>> =
+        std::rc::Rc::new(playlist.tracks.iter().map(|t| (t.id.clone(), shown(t))).collect());
+    let shown_count = track_ids.iter().filter(|(_, shown)| *shown).count();
+    // Ids left over from another playlist or removed tracks don't count
+    let selection: Vec<String> = selected.read().iter().filter(|id| track_ids.iter().any(|(track, _)| track == *id)).cloned().collect();
+    // Right-clicked entry and where its menu opens
+    let mut menu = use_signal(|| None::<(String, f64, f64)>);
+    let menu_track = menu().and_then(|(id, x, y)| Some((playlist.tracks.iter().find(|t| t.id == id)?.clone(), x, y)));
+    // Position of the row being reordered, and the row it would land on
+    let mut dragging = use_signal(|| None::<usize>);
+    let mut drop_at = use_signal(|| None::<usize>);
+    // The last sort applied, and to which playlist, for the arrow on its column
+    let mut sorted = use_signal(|| None::<(String, SortKey, SortOrder)>);
+    let sorted_by = sorted().filter(|(id, _, _)| *id == playlist.id).map(|(_, key, order)| (key, order));
+    let scroll = use_remembered_scroll(format!("playlist:{}", playlist.id), true);
+
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4",
+
+            div { class: "flex items-center justify-between mb-4",
+                div {
+                    h3 { class: "text-lg font-bold", "🎶 Tracks" }
+                    if has_tracks {
+                        p { class: "text-xs text-gray-400", "{playlist_summary(&playlist)}" }
+                    }
+                }
+                if !locked {
+                    div { class: "flex items-center gap-2 text-sm",
+                        select {
+                            class: "px-2 py-1 bg-gray-700 rounded",
+                            title: "What this playlist holds, which decides whether it moves on by itself and remembers where each track was left (set per type in Audio Settings)",
+                            value: "{ContentType::ALL.iter().position(|ct| *ct == playlist.content_type).unwrap_or(0)}",
+                            onchange: move |e: FormEvent| {
+                                if let Some(content_type) = e.value().parse::<usize>().ok().and_then(|i| ContentType::ALL.get(i)) {
+                                    on_content_type_change.call(*content_type);
+                                }
+                            },
+                            for (i , content_type) in ContentType::ALL.iter().enumerate() {
+                                option { key: "{i}", value: "{i}", "{content_type.label()}" }
+                            }
+                        }
+                        label { class: "flex items-center gap-1 text-gray-300",
+                            input {
+                                r#type: "checkbox",
+                                checked: auto_advances,
+                                onchange: move |e| on_auto_advance_change.call(e.checked()),
+                            }
+                            "Auto-advance"
+                        }
+                        if editable && !missing.is_empty() {
+                            button {
+                                class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                title: "Remove the tracks whose file or server is gone",
+                                onclick: {
+                                    let ids: Vec<String> = missing.keys().cloned().collect();
+                                    move |_| on_remove.call(ids.clone())
+                                },
+                                "🧹 Clean up missing ({missing.len()})"
+                            }
+                        }
+                        if playlist.smart.is_some() {
+                            button {
+                                class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                onclick: move |_| on_edit_rules.call(()),
+                                "🧠 Rules"
+                            }
+                        } else if has_tracks {
+                            button {
+                                class: "px-3 py-1 bg-red-600 hover:bg-red-700 rounded text-sm text-white transition-colors",
+                                onclick: move |_| on_clear.call(()),
+                                "🗑️ Clear"
+                            }
+                        }
+                    }
+                }
+            }
+
+            for folder in playlist.watched_folders.iter() {
+                div { key: "{folder}", class: "flex items-center gap-2 mb-2 text-xs text-gray-400",
+                    span { class: "truncate", title: "New, renamed and deleted files here are picked up as they happen", "👁 Watching {folder}" }
+                    if !locked {
+                        button {
+                            class: "text-gray-400 hover:text-white",
+                            title: "Stop watching; the tracks stay",
+                            onclick: {
+                                let folder = folder.clone();
+                                move |_| on_unwatch.call(folder.clone())
+                            },
+                            "✕"
+                        }
+                    }
+                }
+            }
+
+            if has_tracks {
+                input {
+                    class: "w-full px-3 py-2 mb-2 rounded bg-gray-700 border border-gray-600 text-sm text-white",
+                    placeholder: "Filter by title, artist, album or genre...",
+                    value: "{filter_text}",
+                    oninput: {
+                        let playlist_id = playlist.id.clone();
+                        move |e: FormEvent| filter.set((playlist_id.clone(), e.value()))
+                    },
+                }
+            }
+
+            if editable && playlist.tracks.len() > 1 {
+                div { class: "flex items-center gap-1 mb-2 text-xs text-gray-400",
+                    span { "Sort:" }
+                    for key in SortKey::ALL {
+                        button {
+                            key: "{key.label()}",
+                            class: if sorted_by.is_some_and(|(by, _)| by == key) { "px-2 py-1 bg-gray-600 rounded text-white" } else { "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded" },
+                            onclick: {
+                                let playlist_id = playlist.id.clone();
+                                move |_| {
+                                    // Same column again flips the direction
+                                    let order = match sorted_by {
+                                        Some((by, SortOrder::Ascending)) if by == key => SortOrder::Descending,
+                                        _ => SortOrder::Ascending,
+                                    };
+                                    sorted.set(Some((playlist_id.clone(), key, order)));
+                                    on_sort.call((key, order));
+                                }
+                            },
+                            "{key.label()}"
+                            match sorted_by {
+                                Some((by, SortOrder::Ascending)) if by == key => " ▲",
+                                Some((by, SortOrder::Descending)) if by == key => " ▼",
+                                _ => "",
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !selection.is_empty() && editable {
+                div { class: "flex items-center gap-2 mb-2 text-sm",
+                    span { class: "flex-1 text-gray-300", "{format_count(selection.len(), \"track\", \"tracks\")} selected" }
+                    button {
+                        class: "px-2 py-1 bg-red-600 hover:bg-red-700 rounded",
+                        onclick: {
+                            let selection = selection.clone();
+                            move |_| {
+                                on_remove.call(selection.clone());
+                                selected.set(Vec::new());
+                            }
+                        },
+                        "Remove"
+                    }
+                    if !other_playlists.is_empty() {
+                        select {
+                            class: "px-2 py-1 bg-gray-700 rounded",
+                            value: "",
+                            onchange: {
+                                let selection = selection.clone();
+                                move |e: FormEvent| {
+                                    let target = e.value();
+                                    if !target.is_empty() {
+                                        on_move_to_playlist.call((selection.clone(), target));
+                                        selected.set(Vec::new());
+                                    }
+                                }
+                            },
+                            option { value: "", "Move to…" }
+                            for (id , name) in other_playlists.iter() {
+                                option { key: "{id}", value: "{id}", "{name}" }
+                            }
+                        }
+                    }
+                    button {
+                        class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                        title: "Clear the selection",
+                        onclick: move |_| selected.set(Vec::new()),
+                        "✕"
+                    }
+                }
+            }
+
+            if playlist.tracks.is_empty() {
+                div { class: "text-center py-8 text-gray-500", "No tracks in playlist" }
+            } else if shown_count == 0 {
+                div { class: "text-center py-8 text-gray-500", "No tracks match \"{filter_text.trim()}\"" }
+            } else {
+                div {
+                    class: "space-y-2 max-h-96 overflow-y-auto",
+                    onmounted: move |e| scroll.onmounted(e),
+                    onscroll: move |e| scroll.onscroll(e),
+                    {
+
+                        playlist
+                            .tracks
+                            .iter()
+                            .enumerate()
+                            .filter(|(idx, _)| track_ids[*idx].1)
+                            .map(|(idx, track)| {
+                                let track_clone = track.clone();
+                                let source_label = track.source.label(&webdav_configs);
+                                let source_class = track.source.badge_class();
+                                let is_current = current_track
+                                    .as_ref()
+                                    .map(|t| t.id == track.id)
+                                    .unwrap_or(false);
+                                let selected_class = if selection.contains(&track.id) { "row-selected" } else { "" };
+                                let class_str = if is_current {
+                                    "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm"
+                                } else {
+                                    "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm"
+                                };
+                                let select_id = track.id.clone();
+                                let range_ids = track_ids.clone();
+                                let double_click_track = track.clone();
+                                let menu_id = track.id.clone();
+                                let remove_id = track.id.clone();
+                                let drop_class = if drop_at() == Some(idx) && dragging().is_some_and(|from| from != idx) { "drop-before" } else { "" };
+                                let year_genre = year_and_genre(track.year, track.genre.as_deref());
+                                rsx! {
+                                    button {
+                                        key: "{idx}",
+                                        class: "{class_str} {click_mode.row_class()} {selected_class} {drop_class}",
+                                        ondragover: move |e: DragEvent| {
+                                            if dragging().is_some() {
+                                                e.prevent_default();
+                                                drop_at.set(Some(idx));
+                                            }
+                                        },
+                                        ondrop: move |e: DragEvent| {
+                                            let Some(from) = dragging.write().take() else { return };
+                                            // Keeps the playlist pane's drop handler out of it
+                                            e.prevent_default();
+                                            e.stop_propagation();
+                                            drop_at.set(None);
+                                            if from != idx {
+                                                sorted.set(None);
+                                                on_move.call((from, idx));
+                                            }
+                                        },
+                                        onclick: move |e: MouseEvent| {
+                                            let modifiers = e.modifiers();
+                                            if modifiers.contains(Modifiers::SHIFT) {
+                                                let from = anchor().unwrap_or(idx);
+                                                // Rows the filter hides stay out of the range
+                                                let range = &range_ids[from.min(idx)..=from.max(idx).min(range_ids.len() - 1)];
+                                                selected.set(range.iter().filter(|(_, shown)| *shown).map(|(id, _)| id.clone()).collect());
+                                                return;
+                                            }
+                                            anchor.set(Some(idx));
+                                            if modifiers.contains(Modifiers::CONTROL) || modifiers.contains(Modifiers::META) {
+                                                let mut selected = selected.write();
+                                                match selected.iter().position(|id| *id == select_id) {
+                                                    Some(pos) => {
+                                                        selected.remove(pos);
+                                                    }
+                                                    None => selected.push(select_id.clone()),
+                                                }
+                                            } else if click_mode.plays_on_click() {
+                                                selected.set(Vec::new());
+                                                on_track_select.call(track_clone.clone());
+                                            } else {
+                                                selected.set(vec![select_id.clone()]);
+                                            }
+                                        },
+                                        ondoubleclick: move |_| {
+                                            if !click_mode.plays_on_click() {
+                                                on_track_select.call(double_click_track.clone());
+                                            }
+                                        },
+                                        oncontextmenu: move |e: MouseEvent| {
+                                            e.prevent_default();
+                                            let at = e.client_coordinates();
+                                            menu.set(Some((menu_id.clone(), at.x, at.y)));
+                                        },
+
+                
+                                        div { class: "flex items-center justify-between gap-2",
+                                            // Positions only make sense with every row showing
+                                            if editable && query.is_empty() {
+                                                span {
+                                                    class: "drag-handle text-gray-400",
+                                                    title: "Drag to reorder",
+                                                    draggable: "true",
+                                                    ondragstart: move |_| dragging.set(Some(idx)),
+                                                    ondragend: move |_| {
+                                                        dragging.set(None);
+                                                        drop_at.set(None);
+                                                    },
+                                                    "⠿"
+                                                }
+                                            }
+                                            if let Some(reason) = missing.get(&track.id) {
+                                                span { title: "{reason}", "⚠️" }
+                                            }
+                                            FavoriteToggle {
+                                                favorite: favorites.contains(&track.file_key()),
+                                                file_key: track.file_key(),
+                                                on_favorite,
+                                            }
+                                            if let Some(number) = track.track_number {
+                                                span { class: "text-xs text-gray-400", title: "Track number", "{number}." }
+                                            }
+                                            div { class: "font-semibold truncate flex-1", "{track.title}" }
+                                            if let Some(stars) = ratings.get(&track.file_key()) {
+                                                span { class: "text-xs text-yellow-400", title: "Rated {stars}/5", "★{stars}" }
+                                            }
+                                            if track.gain_db != 0.0 {
+                                                span { class: "text-xs text-gray-300", title: "Volume offset", "{format_gain(track.gain_db)}" }
+                                            }
+                                            span { class: source_class, "{source_label}" }
+                                            if editable {
+                                                span {
+                                                    class: "text-gray-400 hover:text-white",
+                                                    title: "Remove from the playlist",
+                                                    onclick: move |e: MouseEvent| {
+                                                        e.stop_propagation();
+                                                        on_remove.call(vec![remove_id.clone()]);
+                                                    },
+                                                    "✕"
+                                                }
+                                            }
+                                        }
+                                        if track.artist != "Cloud Stream" {
+                                            p { class: "text-xs text-gray-300 truncate", "{track.artist}" }
+                                        }
+                                        if track.duration.as_secs() > 0 || !year_genre.is_empty() {
+                                            div { class: "flex justify-between gap-2 text-xs text-gray-400",
+                                                if track.duration.as_secs() > 0 {
+                                                    span { "{format_duration(track.duration)}" }
+                                                }
+                                                span { class: "truncate", "{year_genre}" }
+                                            }
+                                        }
+                                    }
+                                }
+                            })
+                    }
+                }
+            }
+
+            if let Some((track, x, y)) = menu_track {
+                // Any click outside the menu closes it
+                div {
+                    class: "fixed inset-0 z-50",
+                    onclick: move |_| menu.set(None),
+                    oncontextmenu: move |e: MouseEvent| {
+                        e.prevent_default();
+                        menu.set(None);
+                    },
+                }
+                div {
+                    class: "fixed z-50 w-48 bg-gray-900 rounded-lg shadow-lg p-3 text-sm",
+                    style: "left: {x}px; top: {y}px;",
+                    div { class: "font-semibold truncate mb-2", "{track.title}" }
+                    // Clicking the current rating again clears it
+                    div { class: "flex gap-1 mb-2 text-lg",
+                        for stars in 1..=5u8 {
+                            {
+                                let rating = ratings.get(&track.file_key()).copied();
+                                let file_key = track.file_key();
+                                rsx! {
+                                    button {
+                                        key: "{stars}",
+                                        class: if rating.is_some_and(|r| r >= stars) { "text-yellow-400 hover:text-yellow-300" } else { "text-gray-600 hover:text-gray-400" },
+                                        title: "Rate {stars}/5",
+                                        onclick: move |_| on_rate.call((file_key.clone(), if rating == Some(stars) { None } else { Some(stars) })),
+                                        "★"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    button {
+                        class: "w-full mb-1 px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-left",
+                        onclick: {
+                            let track = track.clone();
+                            move |_| {
+                                on_play_next.call(track.clone());
+                                menu.set(None);
+                            }
+                        },
+                        "⏭ Play Next"
+                    }
+                    button {
+                        class: "w-full mb-2 px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-left",
+                        onclick: {
+                            let track = track.clone();
+                            move |_| {
+                                on_add_to_queue.call(track.clone());
+                                menu.set(None);
+                            }
+                        },
+                        "➕ Add to Queue"
                     }
-                    // p { class: "text-gray-400",
-                    // "Control your music with play, pause, seek, and playlist management"
-                    // }
-                    div { class: "mt-4 flex gap-2",
+                    if !locked && track.source == TrackSource::Local && archive::split_path(&track.path).is_none()
+                        && metadata::can_edit_tags(Path::new(&track.path))
+                    {
                         button {
-                            class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded text-sm",
-                            onclick: move |_| *show_directory_browser.write() = true,
-                            "📁 Add Music"
+                            class: "w-full mb-2 px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-left",
+                            title: "Change the title, artist, album, track number, year and genre saved in the file",
+                            onclick: {
+                                let track = track.clone();
+                                move |_| {
+                                    on_edit_tags.call(track.clone());
+                                    menu.set(None);
+                                }
+                            },
+                            "🏷 Edit Tags…"
                         }
+                    }
+                    if editable {
                         button {
-                            class: "px-4 py-2 bg-purple-600 hover:bg-purple-700 rounded text-sm",
-                            onclick: move |_| *show_webdav_config_list.write() = true,
-                            "☁️ WebDAV Config"
+                            class: "w-full mb-2 px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-left",
+                            title: "Remove tracks that appear more than once, by file or by title, artist and length",
+                            onclick: move |_| {
+                                on_dedup.call(());
+                                menu.set(None);
+                            },
+                            "🧹 Remove Duplicates"
                         }
-                        if current_webdav_config().is_some()
-                            && webdav_configs().len() > current_webdav_config().unwrap_or(0)
-                        {
+                        div {
+                            class: "flex items-center justify-between gap-2",
+                            title: "Played this much louder or quieter than the master volume, for tracks mastered unusually quiet or loud",
+                            span { class: "text-gray-300", "Volume" }
                             button {
-                                class: "px-4 py-2 bg-teal-600 hover:bg-teal-700 rounded text-sm",
-                                onclick: move |_| {
-                                    *show_webdav_browser.write() = true;
-                                    // Initial load if empty and config exists
-                                    if webdav_items.read().is_empty() {
-                                        if let Some(idx) = current_webdav_config() {
-                                            if idx < webdav_configs.read().len() {
-                                                let cfg = webdav_configs.read()[idx].clone();
-                                                let path = webdav_current_path();
-                                                *webdav_is_loading.write() = true;
-                                                spawn(async move {
-                                                    match load_webdav_folder(&cfg, &path).await {
-                                                        Ok(items) => {
-                                                            *webdav_items.write() = items;
-                                                            *webdav_error.write() = None;
-                                                        }
-                                                        Err(e) => {
-                                                            *webdav_error.write() = Some(format!("Error: {}", e));
-                                                        }
-                                                    }
-                                                    *webdav_is_loading.write() = false;
-                                                });
-                                            }
-                                        }
-                                    }
+                                class: "px-2 bg-gray-700 hover:bg-gray-600 rounded",
+                                disabled: track.gain_db <= -MAX_TRACK_GAIN_DB,
+                                onclick: {
+                                    let id = track.id.clone();
+                                    move |_| on_gain_change.call((id.clone(), (track.gain_db - 1.0).max(-MAX_TRACK_GAIN_DB)))
+                                },
+                                "−"
+                            }
+                            span { class: "w-16 text-center", "{format_gain(track.gain_db)}" }
+                            button {
+                                class: "px-2 bg-gray-700 hover:bg-gray-600 rounded",
+                                disabled: track.gain_db >= MAX_TRACK_GAIN_DB,
+                                onclick: {
+                                    let id = track.id.clone();
+                                    move |_| on_gain_change.call((id.clone(), (track.gain_db + 1.0).min(MAX_TRACK_GAIN_DB)))
+                                },
+                                "+"
+                            }
+                        }
+                        if track.gain_db != 0.0 {
+                            button {
+                                class: "w-full mt-2 px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-xs",
+                                onclick: {
+                                    let id = track.id.clone();
+                                    move |_| on_gain_change.call((id.clone(), 0.0))
                                 },
-                                "🌐 Browse Cloud"
+                                "Reset"
                             }
                         }
                     }
                 }
             }
+        }
+    }
+}
 
-            main { class: "flex-1 max-w-7xl mx-auto p-6 overflow-y-auto",
-
-                div { class: "grid grid-cols-3 gap-6",
-
-                    aside { class: "col-span-1 h-[calc(100vh-12rem)] overflow-y-auto",
-                        if show_webdav_browser() {
-                            if let Some(config_idx) = current_webdav_config() {
-                                if config_idx < webdav_configs().len() {
-                                    WebDAVSidebar {
-                                        config: webdav_configs()[config_idx].clone(),
-                                        current_path: webdav_current_path(),
-                                        items: webdav_items(),
-                                        is_loading: webdav_is_loading(),
-                                        error_msg: webdav_error(),
-                                        on_close: move |_| *show_webdav_browser.write() = false,
-                                        on_navigate: move |path: String| {
-                                            *webdav_current_path.write() = path.clone();
-                                            *webdav_is_loading.write() = true;
-                                            let cfg = webdav_configs()[config_idx].clone();
-                                            spawn(async move {
-                                                match load_webdav_folder(&cfg, &path).await {
-                                                    Ok(items) => {
-                                                        *webdav_items.write() = items;
-                                                        *webdav_error.write() = None;
-                                                    }
-                                                    Err(e) => {
-                                                        *webdav_error.write() = Some(format!("Error: {}", e));
-                                                    }
-                                                }
-                                                *webdav_is_loading.write() = false;
-                                            });
-                                        },
-                                        on_play_track: move |item: webdav::WebDAVItem| {
-                                            let cfg = webdav_configs()[config_idx].clone();
-                                            let current_items = webdav_items();
-                                            let audio_files: Vec<String> = current_items
+// A built-in list in the main pane: Recently Played newest first, with when each
+// track last played, or Favorites
+#[component]
+fn SpecialListTracks(
+    list: SpecialList,
+    entries: Vec<(TrackStub, Option<std::time::SystemTime>)>,
+    current_track: Option<TrackStub>,
+    webdav_configs: Vec<WebDAVConfig>,
+    // Favorite paths
+    favorites: std::collections::HashSet<String>,
+    on_favorite: EventHandler<(String, bool)>,
+    on_play: EventHandler<TrackStub>,
+    on_add_to_queue: EventHandler<TrackStub>,
+) -> Element {
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    let scroll = use_remembered_scroll(format!("special:{:?}", list), true);
 
-                                                .iter()
-                                                .filter(|i| !i.is_dir && is_audio_file(&i.name))
-                                                .map(|i| i.path.clone())
-                                                .collect();
-                                            spawn(async move {
-                                                // Create placeholder tracks without downloading
-                                                if let Ok(tracks) = create_webdav_placeholder_tracks(&cfg, &audio_files)
-                                                    .await
-                                                {
-                                                    if !tracks.is_empty() {
-                                                        if playlists().len() > current_playlist() {
-                                                            let mut plist = playlists()[current_playlist()].clone();
-                                                            let mut target_track_id = None;
-                                                            let target_path = item.path.clone();
-                                                            for track in tracks {
-                                                                if track.path == target_path {
-                                                                    target_track_id = Some(track.id.clone());
-                                                                }
-                                                                plist.add_track(track.into());
-                                                            }
-                                                            let mut lists = playlists.write();
-                                                            lists[current_playlist()] = plist;
-                                                            if let Some(id) = target_track_id {
-                                                                if let Some(track) = lists[current_playlist()].get_track(&id)
-                                                                {
-                                                                    let stub = TrackStub::from(track.clone());
-                                                                    if let Some(ref player) = *player_ref.read() {
-                                                                        player
-                                                                            .play(
-                                                                                std::path::Path::new(&track.path),
-                                                                                Some(track.id.clone()),
-                                                                            );
-                                                                        let _ = player.set_volume(volume());
-                                                                    }
-                                                                    *current_track.write() = Some(stub);
-                                                                    *player_state.write() = PlayerState::Playing;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            });
-                                        },
-                                    }
-                                } else {
-                                    div { "Invalid Config" }
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4",
+            h3 { class: "text-lg font-bold mb-4", "{list.title()}" }
+            if entries.is_empty() {
+                div { class: "text-center py-8 text-gray-500", "No tracks yet" }
+            }
+            div {
+                class: "space-y-2 max-h-96 overflow-y-auto",
+                onmounted: move |e| scroll.onmounted(e),
+                onscroll: move |e| scroll.onscroll(e),
+                for (track , played_at) in entries.iter() {
+                    button {
+                        key: "{track.path}",
+                        class: if current_track.as_ref().is_some_and(|t| t.path == track.path) { "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm {click_mode.row_class()}" } else { "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm {click_mode.row_class()}" },
+                        onclick: {
+                            let track = track.clone();
+                            move |_| {
+                                if click_mode.plays_on_click() {
+                                    on_play.call(track.clone());
                                 }
-                            } else {
-                                div { "No Config Selected" }
                             }
-                        } else {
-                            PlaylistSidebar {
-                                playlists: playlists(),
-                                current_playlist: current_playlist(),
-                                webdav_configs: webdav_configs(),
-                                expanded_webdav_index: current_webdav_config(),
-                                webdav_items: webdav_items(),
-                                webdav_current_path: webdav_current_path(),
-                                webdav_loading: webdav_is_loading(),
-                                on_select: move |idx| {
-                                    *current_playlist.write() = idx;
-                                },
-                                on_add_playlist: move |_| {
-                                    *show_playlist_manager.write() = true;
-                                },
-                                on_toggle_webdav: move |idx| {
-                                    // If clicking the same one, collapse it
-                                    if current_webdav_config() == Some(idx) {
-                                        *current_webdav_config.write() = None;
-                                    } else {
-                                        // Expand new one
-                                        *current_webdav_config.write() = Some(idx);
-                                        *webdav_current_path.write() = "/".to_string();
-
-                                        // Trigger initial load
-                                        if idx < webdav_configs().len() {
-                                            let cfg = webdav_configs()[idx].clone();
-                                            *webdav_is_loading.write() = true;
-                                            spawn(async move {
-                                                match load_webdav_folder(&cfg, "/").await {
-                                                    Ok(items) => {
-                                                        *webdav_items.write() = items;
-                                                        *webdav_error.write() = None;
-                                                    }
-                                                    Err(e) => {
-                                                        *webdav_error.write() = Some(format!("Error: {}", e));
-                                                    }
-                                                }
-                                                *webdav_is_loading.write() = false;
-                                            });
-                                        }
+                        },
+                        ondoubleclick: {
+                            let track = track.clone();
+                            move |_| {
+                                if !click_mode.plays_on_click() {
+                                    on_play.call(track.clone());
+                                }
+                            }
+                        },
+                        div { class: "flex items-center justify-between gap-2",
+                            FavoriteToggle {
+                                favorite: favorites.contains(&track.file_key()),
+                                file_key: track.file_key(),
+                                on_favorite,
+                            }
+                            div { class: "font-semibold truncate flex-1", "{track.title}" }
+                            if let Some(played_at) = played_at {
+                                span { class: "text-xs text-gray-300 flex-shrink-0", "{format_time_ago(*played_at)}" }
+                            }
+                            span { class: track.source.badge_class(), "{track.source.label(&webdav_configs)}" }
+                            span {
+                                class: "text-gray-400 hover:text-white",
+                                title: "Add to Queue",
+                                onclick: {
+                                    let track = track.clone();
+                                    move |e: MouseEvent| {
+                                        e.stop_propagation();
+                                        on_add_to_queue.call(track.clone());
                                     }
                                 },
-                                on_webdav_navigate: move |path: String| {
-                                    *webdav_current_path.write() = path.clone();
-                                    *webdav_is_loading.write() = true;
+                                "➕"
+                            }
+                        }
+                        p { class: "text-xs text-gray-300 truncate", "{track.artist}" }
+                    }
+                }
+            }
+        }
+    }
+}
 
-                                    if let Some(config_idx) = current_webdav_config() {
-                                        if config_idx < webdav_configs().len() {
-                                            let cfg = webdav_configs()[config_idx].clone();
-                                            spawn(async move {
-                                                match load_webdav_folder(&cfg, &path).await {
-                                                    Ok(items) => {
-                                                        *webdav_items.write() = items;
-                                                        *webdav_error.write() = None;
-                                                    }
-                                                    Err(e) => {
-                                                        *webdav_error.write() = Some(format!("Error: {}", e));
-                                                    }
-                                                }
-                                                *webdav_is_loading.write() = false;
-                                            });
-                                        }
-                                    }
-                                },
-                                on_webdav_play: move |item: webdav::WebDAVItem| {
-                                    if let Some(config_idx) = current_webdav_config() {
-                                        if config_idx < webdav_configs().len() {
-                                            let cfg = webdav_configs()[config_idx].clone();
-                                            let current_items = webdav_items();
+// What the header search found, grouped by playlist
+#[component]
+fn SearchResults(
+    query: String,
+    groups: Vec<SearchGroup>,
+    current_track: Option<TrackStub>,
+    webdav_configs: Vec<WebDAVConfig>,
+    // The playlist the track was found in, by index, and the track
+    on_play: EventHandler<(usize, TrackStub)>,
+    on_add_to_queue: EventHandler<TrackStub>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    let total: usize = groups.iter().map(|group| group.tracks.len()).sum();
 
-                                            // Get all audio files in current directory
-                                            let audio_files: Vec<String> = current_items
-                                                .iter()
-                                                .filter(|i| !i.is_dir && is_audio_file(&i.name))
-                                                .map(|i| i.path.clone())
-                                                .collect();
-                                            spawn(async move {
-                                                // Create placeholder tracks without downloading
-                                                if let Ok(tracks) = create_webdav_placeholder_tracks(
-                                                        &cfg,
-                                                        &audio_files,
-                                                    )
-                                                    .await
-                                                {
-                                                    if !tracks.is_empty() {
-                                                        if playlists().len() > current_playlist() {
-                                                            let mut plist = playlists()[current_playlist()].clone();
-                                                            let mut target_track_id = None;
-                                                            let target_path = item.path.clone();
-                                                            for track in tracks {
-                                                                if track.path == target_path {
-                                                                    target_track_id = Some(track.id.clone());
-                                                                }
-                                                                plist.add_track(track.into());
-                                                            }
-                                                            let mut lists = playlists.write();
-                                                            lists[current_playlist()] = plist;
-                                                            if let Some(id) = target_track_id {
-                                                                if let Some(track) = lists[current_playlist()]
-                                                                    .get_track(&id)
-                                                                {
-                                                                    let stub = TrackStub::from(track.clone());
-                                                                    if let Some(ref player) = *player_ref.read() {
-                                                                        player
-                                                                            .play(
-                                                                                std::path::Path::new(&track.path),
-                                                                                Some(track.id.clone()),
-                                                                            );
-                                                                        let _ = player.set_volume(volume());
-                                                                    }
-                                                                    *current_track.write() = Some(stub);
-                                                                    *player_state.write() = PlayerState::Playing;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
+    rsx! {
+        div { class: "bg-gray-800 rounded-lg p-4",
+            div { class: "flex justify-between items-center mb-4",
+                div {
+                    h3 { class: "text-lg font-bold", "🔍 Search" }
+                    p { class: "text-xs text-gray-400", "{format_count(total, \"match\", \"matches\")} for \"{query.trim()}\"" }
+                }
+                button {
+                    class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                    onclick: move |_| on_close.call(()),
+                    "✕ Close"
+                }
+            }
+            if groups.is_empty() {
+                div { class: "text-center py-8 text-gray-500", "Nothing matches" }
+            }
+            div { class: "space-y-4 max-h-96 overflow-y-auto",
+                for group in groups.iter() {
+                    div { key: "{group.playlist_index}",
+                        h4 { class: "text-sm font-semibold text-gray-300 mb-2",
+                            "{group.name} · {format_count(group.tracks.len(), \"track\", \"tracks\")}"
+                        }
+                        div { class: "space-y-2",
+                            for track in group.tracks.iter().take(SEARCH_GROUP_LIMIT) {
+                                button {
+                                    key: "{track.id}",
+                                    class: if current_track.as_ref().is_some_and(|t| t.id == track.id) { "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm {click_mode.row_class()}" } else { "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm {click_mode.row_class()}" },
+                                    onclick: {
+                                        let track = track.clone();
+                                        let idx = group.playlist_index;
+                                        move |_| {
+                                            if click_mode.plays_on_click() {
+                                                on_play.call((idx, track.clone()));
+                                            }
+                                        }
+                                    },
+                                    ondoubleclick: {
+                                        let track = track.clone();
+                                        let idx = group.playlist_index;
+                                        move |_| {
+                                            if !click_mode.plays_on_click() {
+                                                on_play.call((idx, track.clone()));
+                                            }
+                                        }
+                                    },
+                                    div { class: "flex items-center justify-between gap-2",
+                                        div { class: "font-semibold truncate flex-1", "{track.title}" }
+                                        span { class: track.source.badge_class(), "{track.source.label(&webdav_configs)}" }
+                                        span {
+                                            class: "text-gray-400 hover:text-white",
+                                            title: "Add to Queue",
+                                            onclick: {
+                                                let track = track.clone();
+                                                move |e: MouseEvent| {
+                                                    e.stop_propagation();
+                                                    on_add_to_queue.call(track.clone());
                                                 }
-                                            });
+                                            },
+                                            "➕"
                                         }
                                     }
-                                },
+                                    p { class: "text-xs text-gray-300 truncate",
+                                        if track.album.is_empty() {
+                                            "{track.artist}"
+                                        } else {
+                                            "{track.artist} · {track.album}"
+                                        }
+                                    }
+                                }
+                            }
+                            if group.tracks.len() > SEARCH_GROUP_LIMIT {
+                                p { class: "text-xs text-gray-500", "…and {group.tracks.len() - SEARCH_GROUP_LIMIT} more; narrow the search to see them" }
                             }
                         }
                     }
+                }
+            }
+        }
+    }
+}
 
-                    section { class: "col-span-1",
+// The heart on a track row; clicks stay off the row itself
+#[component]
+fn FavoriteToggle(favorite: bool, file_key: String, on_favorite: EventHandler<(String, bool)>) -> Element {
+    rsx! {
+        span {
+            class: if favorite { "text-red-500 hover:text-red-400" } else { "text-gray-400 hover:text-white" },
+            title: if favorite { "Remove from Favorites" } else { "Add to Favorites" },
+            onclick: move |e: MouseEvent| {
+                e.stop_propagation();
+                on_favorite.call((file_key.clone(), !favorite));
+            },
+            if favorite {
+                "♥"
+            } else {
+                "♡"
+            }
+        }
+    }
+}
 
-                        PlayerControls {
-                            state: player_state(),
-                            duration: Some(current_duration()),
-                            volume: volume(),
-                            current_time,
-                            on_play: move |_| {
-                                if let Some(ref player) = *player_ref.read() {
-                                    player.set_stopped_by_user(false);
+#[component]
+fn PlayHistoryModal(
+    entries: Vec<PlayHistoryEntry>,
+    write_stats_to_tags: bool,
+    on_write_stats_to_tags_change: EventHandler<bool>,
+    shuffle_history_window: usize,
+    on_shuffle_history_window_change: EventHandler<usize>,
+    smart_shuffle: bool,
+    smart_shuffle_exponent: f64,
+    on_smart_shuffle_change: EventHandler<(bool, f64)>,
+    on_clear: EventHandler<()>,
+    on_close: EventHandler<()>,
+) -> Element {
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
 
-                                    if player_state() == PlayerState::Paused && player.is_paused() {
-                                        let _ = player.resume();
-                                    } else if let Some(track_stub) = current_track() {
-                                        player
-                                            .play(
-                                                std::path::Path::new(&track_stub.path),
-                                                Some(track_stub.id.clone()),
-                                            );
-                                        let _ = player.set_volume(volume());
-                                    }
-                                }
-                                *player_state.write() = PlayerState::Playing;
-                            },
-                            on_pause: move |_| {
-                                if let Some(ref player) = *player_ref.read() {
-                                    let _ = player.pause();
-                                }
-                                *player_state.write() = PlayerState::Paused;
-                            },
-                            on_stop: move |_| {
-                                if let Some(ref player) = *player_ref.read() {
-                                    player.set_stopped_by_user(true);
-                                    let _ = player.stop();
-                                }
-                                *player_state.write() = PlayerState::Stopped;
-                            },
-                            on_seek: move |time| {
-                                if let Some(ref player) = *player_ref.read() {
-                                    let _ = player.seek(time);
-                                }
-                                *current_time.write() = time;
-                            },
-                            on_volume_change: move |vol| {
-                                if let Some(ref player) = *player_ref.read() {
-                                    let _ = player.set_volume(vol);
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl max-h-96 flex flex-col",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "Play History" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                div { class: "flex-1 overflow-y-auto space-y-2 min-h-0",
+                    if entries.is_empty() {
+                        div { class: "text-center py-4 text-gray-400", "Nothing played yet" }
+                    }
+                    for (idx , entry) in entries.iter().enumerate() {
+                        div {
+                            key: "{idx}-{entry.track_id}",
+                            class: "p-2 bg-gray-700 rounded",
+                            div { class: "flex justify-between gap-2",
+                                div { class: "truncate text-sm font-semibold", "{entry.title}" }
+                                div { class: "text-xs text-gray-500 flex-shrink-0",
+                                    "{format_time_ago(entry.played_at)}"
                                 }
-                                *volume.write() = vol;
-                            },
-                            on_previous: move |_| {
-                                if playlists().len() > current_playlist() {
-                                    let playlist = &playlists()[current_playlist()];
-                                    if let Some(current) = current_track() {
-                                        // Find current track index
-                                        if let Some(pos) = playlist
-                                            .tracks
-                                            .iter()
-                                            .position(|t| t.id == current.id)
-                                        {
-                                            if pos > 0 {
-                                                let prev_track = playlist.tracks[pos - 1].clone();
-                                                if let Some(ref player) = *player_ref.read() {
-                                                    player.stop();
-                                                    player.set_stopped_by_user(false);
-                                                    player
-                                                        .play(
-                                                            std::path::Path::new(&prev_track.path),
-                                                            Some(prev_track.id.clone()),
-                                                        );
-                                                    let _ = player.set_volume(volume());
-                                                }
-                                                *current_track.write() = Some(prev_track);
-                                                *player_state.write() = PlayerState::Playing;
-                                            }
-                                        }
+                            }
+                            div { class: "text-xs text-gray-400 truncate", "{entry.artist}" }
+                            div { class: "text-xs text-blue-400", "Why: {entry.reason.describe()}" }
+                        }
+                    }
+                }
+
+                label { class: "flex items-center gap-2 text-sm text-gray-300 mt-4",
+                    "Shuffle skips tracks from the last"
+                    input {
+                        r#type: "number",
+                        min: "0",
+                        max: "{PLAY_HISTORY_LIMIT}",
+                        class: "w-20 px-2 py-1 bg-gray-700 rounded",
+                        value: "{shuffle_history_window}",
+                        onchange: move |e| {
+                            if let Ok(window) = e.value().parse::<usize>() {
+                                on_shuffle_history_window_change.call(window.min(PLAY_HISTORY_LIMIT));
+                            }
+                        },
+                    }
+                    "plays"
+                }
+
+                label { class: "flex items-center gap-2 text-sm text-gray-300 mt-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: smart_shuffle,
+                        onchange: move |e| on_smart_shuffle_change.call((e.checked(), smart_shuffle_exponent)),
+                    }
+                    "Smart shuffle: favour well-rated tracks you haven't played much lately"
+                }
+
+                label { class: "flex items-center gap-2 text-sm text-gray-300 mt-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: write_stats_to_tags,
+                        onchange: move |e| on_write_stats_to_tags_change.call(e.checked()),
+                    }
+                    "Save ratings and play counts into MP3 and FLAC tags (POPM / FMPS) for other players"
+                }
+
+                details { class: "text-sm text-gray-400 mt-2",
+                    summary { class: "cursor-pointer", "Advanced" }
+                    label { class: "flex items-center gap-2 mt-2",
+                        "Smart shuffle weighting"
+                        input {
+                            r#type: "number",
+                            min: "0",
+                            max: "{MAX_SMART_SHUFFLE_EXPONENT}",
+                            step: "0.1",
+                            class: "w-20 px-2 py-1 bg-gray-700 rounded",
+                            value: "{smart_shuffle_exponent}",
+                            onchange: move |e| {
+                                if let Ok(exponent) = e.value().parse::<f64>() {
+                                    if exponent.is_finite() {
+                                        on_smart_shuffle_change.call((smart_shuffle, exponent.clamp(0.0, MAX_SMART_SHUFFLE_EXPONENT)));
                                     }
                                 }
                             },
-                            on_next: move |_| {
-                                if playlists().len() > current_playlist() {
-                                    let playlist = &playlists()[current_playlist()];
-                                    if let Some(current) = current_track() {
-                                        // Find current track index
-                                        if let Some(pos) = playlist
-                                            .tracks
-                                            .iter()
-                                            .position(|t| t.id == current.id)
-                                        {
-                                            if pos < playlist.tracks.len() - 1 {
-                                                let next_track = playlist.tracks[pos + 1].clone();
-                                                if let Some(ref player) = *player_ref.read() {
-                                                    player.stop();
-                                                    player.set_stopped_by_user(false);
-                                                    player
-                                                        .play(
-                                                            std::path::Path::new(&next_track.path),
-                                                            Some(next_track.id.clone()),
-                                                        );
-                                                    let _ = player.set_volume(volume());
-                                                }
-                                                *current_track.write() = Some(next_track);
-                                                *player_state.write() = PlayerState::Playing;
-                                            }
+                        }
+                    }
+                    p { class: "text-xs text-gray-500 mt-1",
+                        "0 ignores ratings and play counts; higher values lean on them harder."
+                    }
+                }
+
+                div { class: "flex gap-4 justify-end mt-4",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded disabled:opacity-50",
+                        disabled: entries.is_empty(),
+                        onclick: move |_| on_clear.call(()),
+                        "Clear"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn PlaylistMergeModal(merge: PendingMerge, on_apply: EventHandler<()>, on_close: EventHandler<()>) -> Element {
+    use playlist::merge::{ChangeKind, Side};
+
+    let columns = [(Side::Ours, "This computer".to_string()), (Side::Theirs, merge.source.clone())];
+    let result = &merge.result;
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "Merge \"{merge.playlist_name}\"" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                if !merge.has_base {
+                    p { class: "text-xs text-gray-400 mb-2",
+                        "These copies haven't been merged before, so tracks missing from one side are kept rather than treated as removed."
+                    }
+                }
+
+                div { class: "grid grid-cols-2 gap-4",
+                    for (side , label) in columns {
+                        div { key: "{label}",
+                            h3 { class: "font-semibold mb-2 truncate", "{label}" }
+                            div { class: "max-h-80 overflow-y-auto space-y-1",
+                                if !result.changes.iter().any(|c| c.side == side) {
+                                    div { class: "text-sm text-gray-400", "No changes" }
+                                }
+                                for (idx , change) in result.changes.iter().filter(|c| c.side == side).enumerate() {
+                                    div {
+                                        key: "{idx}",
+                                        class: match change.kind {
+                                            ChangeKind::Added => "text-sm truncate text-green-400",
+                                            ChangeKind::Removed => "text-sm truncate text-red-400",
+                                            ChangeKind::Moved => "text-sm truncate text-blue-400",
+                                        },
+                                        match change.kind {
+                                            ChangeKind::Added => "+ ",
+                                            ChangeKind::Removed => "− ",
+                                            ChangeKind::Moved => "↕ ",
                                         }
+                                        "{change.track.title}"
                                     }
                                 }
-                            },
+                            }
                         }
+                    }
+                }
 
-                        NowPlayingCard {
-                            current_track: current_track(),
-                            player_ref: player_ref.clone(),
+                if !result.conflicts.is_empty() {
+                    div { class: "text-sm text-yellow-400 mt-4",
+                        "Moved on both sides, kept where this computer has them: "
+                        {result.conflicts.iter().map(|t| t.title.as_str()).collect::<Vec<_>>().join(", ")}
+                    }
+                }
+
+                div { class: "flex gap-4 justify-between items-center mt-4",
+                    span { class: "text-sm text-gray-400",
+                        "Merged playlist: {format_count(result.tracks.len(), \"track\", \"tracks\")}"
+                    }
+                    div { class: "flex gap-4",
+                        button {
+                            class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                            onclick: move |_| on_close.call(()),
+                            "Cancel"
+                        }
+                        button {
+                            class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded",
+                            onclick: move |_| on_apply.call(()),
+                            "Apply merge"
                         }
+                    }
+                }
+            }
+        }
+    }
+}
 
-                        if let Some(lyric) = current_lyric() {
-                            LyricsDisplay { current_time, lyric: Some(lyric) }
+#[component]
+fn PlaylistImportModal(import: PendingImport, on_apply: EventHandler<()>, on_close: EventHandler<()>) -> Element {
+    let found = import.matched.tracks.len();
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-lg shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "Import \"{import.name}\"" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                p { class: "text-sm text-gray-300 mb-2",
+                    "{found} of {format_count(import.total, \"song\", \"songs\")} in {import.source} found in your library."
+                }
+
+                if !import.matched.misses.is_empty() {
+                    h3 { class: "font-semibold mb-2", "Not found" }
+                    div { class: "max-h-80 overflow-y-auto space-y-1",
+                        for (idx , entry) in import.matched.misses.iter().enumerate() {
+                            div { key: "{idx}", class: "text-sm truncate text-red-400", "{entry.label()}" }
                         }
+                    }
+                }
 
-                        // Error message display
-                        if let Some(err) = error_msg() {
-                            div { class: "mb-4 p-4 bg-red-100 border border-red-400 text-red-700 rounded",
-                                "❌ {err}"
-                                button {
-                                    class: "ml-2 text-red-500 hover:text-red-700",
-                                    onclick: move |_| *error_msg.write() = None,
-                                    "✕"
-                                }
+                div { class: "flex gap-4 justify-end mt-4",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: found == 0,
+                        onclick: move |_| on_apply.call(()),
+                        "Create playlist"
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Tracklist or CUE text for the current track's chapters, previewed as it's parsed
+#[component]
+fn ChaptersModal(
+    track_title: String,
+    text: String,
+    on_save: EventHandler<Vec<chapters::Chapter>>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut text = use_signal(|| text);
+    let mut load_error = use_signal(|| None::<String>);
+    let parsed = chapters::parse(&text());
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold truncate", "Chapters for \"{track_title}\"" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                p { class: "text-xs text-gray-400 mb-2",
+                    "Paste a tracklist with one timestamp per line (\"12:34 Artist - Title\") or load a .cue sheet."
+                }
+                div { class: "grid grid-cols-2 gap-4",
+                    textarea {
+                        class: "w-full h-48",
+                        value: "{text}",
+                        placeholder: "0:00 Intro\n4:12 Artist - Title\n1:02:30 Outro",
+                        oninput: move |e| text.set(e.value()),
+                    }
+                    div { class: "h-48 overflow-y-auto space-y-1",
+                        if parsed.is_empty() {
+                            div { class: "text-sm text-gray-400", "No timestamps found" }
+                        }
+                        for (idx , chapter) in parsed.iter().enumerate() {
+                            div { key: "{idx}", class: "text-sm truncate",
+                                span { class: "text-yellow-400", "{format_duration(chapter.start)} " }
+                                "{chapter.title}"
                             }
                         }
                     }
+                }
+                if let Some(error) = load_error() {
+                    p { class: "text-sm text-red-400 mt-2", "{error}" }
+                }
 
-                    // Right: Playlist tracks
-                    aside { class: "col-span-1 h-[calc(100vh-12rem)] overflow-y-auto",
-                        if playlists().len() > current_playlist() {
-                            PlaylistTracks {
-                                playlist: playlists()[current_playlist()].clone(),
-                                current_track: current_track(),
-                                on_track_select: move |track_stub: TrackStub| {
-                                    if let Some(ref player) = *player_ref.read() {
-                                        player.set_stopped_by_user(false);
-                                        player
-                                            .play(
-                                                std::path::Path::new(&track_stub.path),
-                                                Some(track_stub.id.clone()),
-                                            );
-                                        let _ = player.set_volume(volume());
-                                    }
-                                    *current_track.write() = Some(track_stub);
-                                    *player_state.write() = PlayerState::Playing;
-                                },
-                                on_clear: move |_| {
-                                    let mut playlists_guard = playlists.write();
-                                    if playlists_guard.len() > current_playlist() {
-                                        playlists_guard[current_playlist()].tracks.clear();
+                div { class: "flex gap-4 justify-between items-center mt-4",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| {
+                            spawn(async move {
+                                let Some(file) = rfd::AsyncFileDialog::new()
+                                    .set_title("Load a CUE sheet or tracklist")
+                                    .add_filter("CUE sheet / tracklist", &["cue", "txt"])
+                                    .pick_file()
+                                    .await
+                                else {
+                                    return;
+                                };
+                                // CUE sheets are often saved in a legacy encoding; keep what decodes
+                                match std::fs::read(file.path()) {
+                                    Ok(bytes) => {
+                                        text.set(String::from_utf8_lossy(&bytes).into_owned());
+                                        load_error.set(None);
                                     }
-                                },
-                            }
+                                    Err(e) => load_error.set(Some(format!("Couldn't read {}: {}", file.file_name(), e))),
+                                }
+                            });
+                        },
+                        "📂 Load .cue…"
+                    }
+                    div { class: "flex gap-4",
+                        button {
+                            class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                            onclick: move |_| on_close.call(()),
+                            "Cancel"
+                        }
+                        button {
+                            class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded",
+                            onclick: move |_| on_save.call(chapters::parse(&text.peek())),
+                            if parsed.is_empty() { "Remove chapters" } else { "Save {format_count(parsed.len(), \"chapter\", \"chapters\")}" }
                         }
                     }
                 }
             }
+        }
+    }
+}
 
-            if show_playlist_manager() {
-                PlaylistManagerModal {
-                    on_close: move |_| {
-                        *show_playlist_manager.write() = false;
-                    },
-                    on_add_playlist: move |name| {
-                        let new_playlist = Playlist::new(name);
-                        playlists.write().push(new_playlist);
-                        *show_playlist_manager.write() = false;
-                    },
-                    on_load_files: move |_| {},
+#[component]
+fn SettingsProfilesModal(
+    profiles: Vec<SettingsProfile>,
+    active_profile: Option<String>,
+    on_close: EventHandler<()>,
+    on_save: EventHandler<String>,
+    on_apply: EventHandler<usize>,
+    on_delete: EventHandler<usize>,
+) -> Element {
+    let mut profile_name = use_signal(String::new);
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "Settings Profiles" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
                 }
-            }
 
-            if show_directory_browser() {
-                DirectoryBrowserModal {
-                    current_directory: current_directory(),
-                    on_close: move |_| {
-                        *show_directory_browser.write() = false;
-                    },
-                    on_load_directory: move |dir: String| {
-                        *current_directory.write() = dir.clone();
-                        if let Ok(tracks) = scan_music_directory(&dir) {
-                            if playlists().len() > current_playlist() {
-                                let mut plist = playlists()[current_playlist()].clone();
-                                for track in tracks {
-                                    plist.add_track(track);
+                div { class: "space-y-2 mb-4",
+                    if profiles.is_empty() {
+                        div { class: "text-center py-4 text-gray-400", "No saved profiles" }
+                    }
+                    for (idx , profile) in profiles.iter().enumerate() {
+                        div {
+                            key: "{profile.id}",
+                            class: if active_profile.as_ref() == Some(&profile.id) { "flex items-center gap-2 p-2 rounded bg-blue-600" } else { "flex items-center gap-2 p-2 rounded bg-gray-700" },
+                            div { class: "flex-1 min-w-0",
+                                div { class: "truncate text-sm font-semibold", "{profile.name}" }
+                                div { class: "text-xs text-gray-300",
+                                    "Volume {(profile.settings.volume * 100.0) as i32}% • Balance {format_balance(profile.settings.balance)}"
+                                    if profile.settings.mono_downmix {
+                                        " • Mono"
+                                    }
+                                    if profile.settings.crossfade_secs > 0 {
+                                        " • Crossfade {profile.settings.crossfade_secs}s"
+                                    }
+                                    if profile.settings.status_palette == StatusPalette::ColorBlind {
+                                        " • Color-blind colors"
+                                    }
+                                }
+                                div { class: "truncate text-xs text-gray-400",
+                                    "{profile.settings.output_device.as_deref().unwrap_or(\"System default output\")}"
                                 }
-                                let mut lists = playlists.write();
-                                lists[current_playlist()] = plist;
+                            }
+                            button {
+                                class: "px-2 py-1 bg-gray-600 hover:bg-gray-500 rounded text-xs",
+                                onclick: move |_| on_apply.call(idx),
+                                "Apply"
+                            }
+                            button {
+                                class: "px-2 py-1 bg-red-600 hover:bg-red-700 rounded text-xs",
+                                onclick: move |_| on_delete.call(idx),
+                                "Delete"
                             }
                         }
-                        *show_directory_browser.write() = false;
-                    },
+                    }
                 }
-            }
 
-            if show_webdav_config_list() {
-                WebDAVConfigListModal {
-                    configs: webdav_configs(),
-                    current_config: current_webdav_config(),
-                    on_close: move |_| {
-                        *show_webdav_config_list.write() = false;
-                    },
-                    on_add_config: move |_| {
-                        *editing_webdav_config.write() = None;
-                        *show_webdav_config.write() = true;
-                    },
-                    on_edit_config: move |idx| {
-                        *editing_webdav_config.write() = Some(idx);
-                        *show_webdav_config.write() = true;
-                    },
-                    on_delete_config: move |idx| {
-                        let mut configs = webdav_configs.write();
-                        if idx < configs.len() {
-                            configs.remove(idx);
-                        }
-                        if let Some(current) = current_webdav_config() {
-                            if current >= configs.len() && !configs.is_empty() {
-                                *current_webdav_config.write() = Some(configs.len() - 1);
-                            }
-                        }
+                input {
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    placeholder: "Save current settings as...",
+                    value: profile_name(),
+                    oninput: move |e| *profile_name.write() = e.value(),
+                }
 
-                        // 保存到磁盘
-                        let configs_to_save = configs.clone();
-                        drop(configs);
-                        if let Err(e) = save_webdav_configs(&configs_to_save) {
-                            eprintln!("保存WebDAV配置失败: {}", e);
-                        }
-                    },
-                    on_select_config: move |idx| {
-                        *current_webdav_config.write() = Some(idx);
-                    },
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: profile_name().trim().is_empty(),
+                        onclick: move |_| {
+                            on_save.call(profile_name().trim().to_string());
+                            profile_name.write().clear();
+                        },
+                        "Save"
+                    }
                 }
             }
+        }
+    }
+}
 
-            if show_webdav_config() {
-                WebDAVConfigModal {
-                    config: {
-                        let editing_idx = editing_webdav_config();
-                        if let Some(idx) = editing_idx {
-                            if idx < webdav_configs().len() {
-                                webdav_configs()[idx].clone()
-                            } else {
-                                WebDAVConfig {
-                                    id: uuid::Uuid::new_v4().to_string(),
-                                    name: String::new(),
-                                    url: String::new(),
-                                    username: String::new(),
-                                    encrypted_password: String::new(),
-                                    enabled: false,
-                                    password: None,
+// Choosing a PIN the first time lock mode is turned on, or entering it to unlock
+// Playlist entries streaming an item's tracks, with its artwork as the cover.
+// Blocks while the artwork downloads.
+fn bandcamp_tracks(item: &bandcamp::CollectionItem) -> Vec<TrackStub> {
+    let cover = item
+        .art_url
+        .as_deref()
+        .and_then(|url| reqwest::blocking::get(url).and_then(|r| r.error_for_status()?.bytes()).ok())
+        .and_then(|data| cover_cache::store(&data));
+    // Tracklists come in album order
+    let numbered = item.tracks.len() > 1;
+    item.tracks
+        .iter()
+        .enumerate()
+        .map(|(idx, track)| TrackStub {
+            id: Uuid::new_v4().to_string(),
+            path: track.stream_url.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: item.title.clone(),
+            album_artist: Some(item.artist.clone()),
+            track_number: numbered.then_some(idx as u32 + 1),
+            year: None,
+            genre: None,
+            duration: track.duration,
+            cover: cover.clone(),
+            source: TrackSource::Bandcamp,
+            gain_db: 0.0,
+            added_at: None,
+            stamp: None,
+        })
+        .collect()
+}
+
+#[component]
+fn BandcampModal(
+    on_add_tracks: EventHandler<Vec<TrackStub>>,
+    // A folder FLAC downloads were unpacked into
+    on_downloaded: EventHandler<String>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut account = use_signal(|| load_bandcamp_account().unwrap_or_default());
+    let mut identity_input = use_signal(String::new);
+    let mut collection = use_signal(|| None::<Vec<bandcamp::CollectionItem>>);
+    let mut loading = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
+    // What each item is doing ("Downloading 40%", "Saved"), by item id
+    let mut status = use_signal(std::collections::HashMap::<String, String>::new);
+    let logged_in = !account.read().encrypted_identity.is_empty();
+
+    let save = move || {
+        if let Err(e) = save_bandcamp_account(&account.peek()) {
+            eprintln!("[Config] 保存Bandcamp账户失败: {}", e);
+        }
+    };
+    let load_collection = use_callback(move |()| {
+        let identity = match account.peek().identity() {
+            Ok(identity) => identity,
+            Err(e) => {
+                error.set(Some(format!("Couldn't read the saved login: {}", e)));
+                return;
+            }
+        };
+        loading.set(true);
+        error.set(None);
+        spawn(async move {
+            let listed = tokio::task::spawn_blocking(move || bandcamp::BandcampClient::new(&identity)?.collection())
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            loading.set(false);
+            match listed {
+                Ok(items) => collection.set(Some(items)),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    });
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl max-h-96 flex flex-col",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "🎧 Bandcamp collection" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                if !logged_in {
+                    p { class: "text-sm text-gray-400 mb-2",
+                        "Log in to bandcamp.com in a browser and paste the value of its \"identity\" cookie. It's stored encrypted, like WebDAV passwords."
+                    }
+                    div { class: "flex gap-2",
+                        input {
+                            class: "flex-1 px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                            r#type: "password",
+                            placeholder: "identity cookie",
+                            value: identity_input(),
+                            oninput: move |e| identity_input.set(e.value()),
+                        }
+                        button {
+                            class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                            disabled: identity_input().trim().is_empty(),
+                            onclick: move |_| {
+                                if let Err(e) = account.write().set_identity(&identity_input.peek()) {
+                                    error.set(Some(format!("Couldn't store the login: {}", e)));
+                                    return;
                                 }
-                            }
-                        } else {
-                            WebDAVConfig {
-                                id: uuid::Uuid::new_v4().to_string(),
-                                name: String::new(),
-                                url: String::new(),
-                                username: String::new(),
-                                encrypted_password: String::new(),
-                                enabled: false,
-                                password: None,
-                            }
+                                identity_input.set(String::new());
+                                save();
+                                load_collection.call(());
+                            },
+                            "Log in"
                         }
-                    },
-                    on_close: move |_| {
-                        *show_webdav_config.write() = false;
-                        *editing_webdav_config.write() = None;
-                    },
-                    on_save_config: move |new_config: WebDAVConfig| {
-                        let editing_idx = editing_webdav_config();
-                        let mut configs = webdav_configs.write();
-                        if let Some(idx) = editing_idx {
-                            if idx < configs.len() {
-                                configs[idx] = new_config.clone();
-                            }
-                        } else {
-                            configs.push(new_config);
+                    }
+                } else {
+                    div { class: "flex items-center gap-2 mb-2 text-sm",
+                        button {
+                            class: "px-3 py-1 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                            disabled: loading(),
+                            onclick: move |_| load_collection.call(()),
+                            if loading() { "Loading…" } else if collection.read().is_some() { "↻ Refresh" } else { "Load collection" }
                         }
-                        let configs_to_save = configs.clone();
-                        drop(configs);
-                        if let Err(e) = save_webdav_configs(&configs_to_save) {
-                            eprintln!("保存WebDAV配置失败: {}", e);
+                        button {
+                            class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                            title: "Where FLAC downloads are unpacked, one folder per artist and album",
+                            onclick: move |_| {
+                                spawn(async move {
+                                    if let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await {
+                                        account.write().download_dir = Some(dir.path().to_string_lossy().to_string());
+                                        save();
+                                    }
+                                });
+                            },
+                            "📁 {account.read().download_dir.clone().unwrap_or_else(|| \"Choose download folder\".to_string())}"
                         }
-                        *show_webdav_config.write() = false;
-                        *editing_webdav_config.write() = None;
-                        *show_webdav_config_list.write() = true;
-                    },
+                        button {
+                            class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                            onclick: move |_| {
+                                account.write().encrypted_identity.clear();
+                                collection.set(None);
+                                save();
+                            },
+                            "Log out"
+                        }
+                    }
                 }
-            }
 
-            if show_webdav_browser() {
-                if let Some(config_idx) = current_webdav_config() {
-                    if config_idx < webdav_configs().len() {
-                        {
-                            rsx! {
-                                WebDAVBrowserModal {
-                                    config: webdav_configs()[config_idx].clone(),
-                                    on_close: move |_| {
-                                        *show_webdav_browser.write() = false;
+                if let Some(error) = error() {
+                    p { class: "text-sm text-red-400 mb-2", "{error}" }
+                }
+
+                if let Some(items) = collection() {
+                    div { class: "flex-1 overflow-y-auto space-y-2 min-h-0 mt-2",
+                        if items.is_empty() {
+                            div { class: "text-center py-4 text-gray-400", "No purchases yet" }
+                        }
+                        for item in items {
+                            div {
+                                key: "{item.id}",
+                                class: "flex items-center gap-2 p-2 bg-gray-700 rounded",
+                                div { class: "flex-1 min-w-0",
+                                    div { class: "truncate text-sm font-semibold", "{item.title}" }
+                                    div { class: "text-xs text-gray-400 truncate",
+                                        "{item.artist} · {format_count(item.tracks.len(), \"track\", \"tracks\")}"
+                                    }
+                                    if let Some(state) = status.read().get(&item.id) {
+                                        div { class: "text-xs text-blue-400", "{state}" }
+                                    }
+                                }
+                                button {
+                                    class: "px-2 py-1 bg-green-600 hover:bg-green-700 rounded text-sm disabled:opacity-50",
+                                    title: "Add its tracks to the current playlist, streamed from Bandcamp",
+                                    disabled: item.tracks.is_empty(),
+                                    onclick: {
+                                        let item = item.clone();
+                                        move |_| {
+                                            let item = item.clone();
+                                            spawn(async move {
+                                                if let Ok(tracks) = tokio::task::spawn_blocking(move || bandcamp_tracks(&item)).await {
+                                                    on_add_tracks.call(tracks);
+                                                }
+                                            });
+                                        }
                                     },
-                                    on_import_folder: move |tracks: Vec<Track>| {
-                                        if playlists().len() > current_playlist() {
-                                            let mut plist = playlists()[current_playlist()].clone();
-                                            for track in tracks {
-                                                plist.add_track(track.into());
-                                            }
-                                            let mut lists = playlists.write();
-                                            lists[current_playlist()] = plist;
+                                    "▶ Add"
+                                }
+                                button {
+                                    class: "px-2 py-1 bg-blue-600 hover:bg-blue-700 rounded text-sm disabled:opacity-50",
+                                    title: "Download as FLAC into the download folder and add it to the current playlist",
+                                    disabled: item.download_page.is_none() || account.read().download_dir.is_none() || status.read().get(&item.id).is_some_and(|s| s.starts_with("Downloading")),
+                                    onclick: {
+                                        let item = item.clone();
+                                        move |_| {
+                                            let Some(library) = account.peek().download_dir.clone() else { return };
+                                            let identity = match account.peek().identity() {
+                                                Ok(identity) => identity,
+                                                Err(e) => {
+                                                    error.set(Some(format!("Couldn't read the saved login: {}", e)));
+                                                    return;
+                                                }
+                                            };
+                                            let item = item.clone();
+                                            let progress = Arc::new((std::sync::atomic::AtomicU64::new(0), std::sync::atomic::AtomicU64::new(0)));
+                                            status.write().insert(item.id.clone(), "Downloading…".to_string());
+                                            spawn(async move {
+                                                let id = item.id.clone();
+                                                let dest = bandcamp::album_dir(Path::new(&library), &item);
+                                                let mut download = tokio::task::spawn_blocking({
+                                                    let progress = progress.clone();
+                                                    move || {
+                                                        bandcamp::BandcampClient::new(&identity)?.download_flac(&item, Path::new(&library), &mut |done, total| {
+                                                            progress.0.store(done, std::sync::atomic::Ordering::Relaxed);
+                                                            progress.1.store(total, std::sync::atomic::Ordering::Relaxed);
+                                                        })
+                                                    }
+                                                });
+                                                let result = loop {
+                                                    tokio::select! {
+                                                        result = &mut download => break result.unwrap_or_else(|e| Err(e.to_string())),
+                                                        _ = tokio::time::sleep(Duration::from_millis(500)) => {
+                                                            let done = progress.0.load(std::sync::atomic::Ordering::Relaxed);
+                                                            let total = progress.1.load(std::sync::atomic::Ordering::Relaxed);
+                                                            let state = match (done * 100).checked_div(total) {
+                                                                Some(percent) => format!("Downloading {}%", percent),
+                                                                None => format!("Downloading {:.1} MB", done as f64 / 1_048_576.0),
+                                                            };
+                                                            status.write().insert(id.clone(), state);
+                                                        }
+                                                    }
+                                                };
+                                                match result {
+                                                    Ok(_) => {
+                                                        status.write().insert(id, format!("Saved to {}", dest.display()));
+                                                        on_downloaded.call(dest.to_string_lossy().to_string());
+                                                    }
+                                                    Err(e) => {
+                                                        status.write().insert(id, format!("Download failed: {}", e));
+                                                    }
+                                                }
+                                            });
                                         }
-                                        *show_webdav_browser.write() = false;
                                     },
+                                    "⬇ FLAC"
                                 }
                             }
                         }
@@ -1524,417 +7822,809 @@ fn App() -> Element {
 }
 
 #[component]
-fn NowPlayingCard(
-    current_track: Option<TrackStub>,
-    player_ref: Signal<Option<player::MusicPlayer>>,
+fn OnlineTrackModal(
+    binary: Option<String>,
+    on_binary_change: EventHandler<Option<String>>,
+    on_add: EventHandler<TrackStub>,
+    on_close: EventHandler<()>,
 ) -> Element {
-    let full_track: Option<Track> = current_track.as_ref().map(|stub| {
-        Track {
-            id: stub.id.clone(),
-            path: stub.path.clone(),
-            title: stub.title.clone(),
-            artist: stub.artist.clone(),
-            album: stub.album.clone(),
-            duration: stub.duration,
-            cover: stub.cover.clone(),
+    let mut url = use_signal(String::new);
+    let mut binary_input = use_signal(|| binary.clone().unwrap_or_default());
+    let mut resolving = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
+
+    let add = move |_| {
+        let page = url.peek().trim().to_string();
+        let entered = binary_input.peek().trim().to_string();
+        let entered = (!entered.is_empty()).then_some(entered);
+        if entered != binary {
+            on_binary_change.call(entered);
         }
-    });
-
-    let mut player_metadata: Signal<Option<player::TrackMetadata>> = use_signal(|| None);
+        resolving.set(true);
+        error.set(None);
+        spawn(async move {
+            let resolved = tokio::task::spawn_blocking(move || resolve_online_track(&page))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            resolving.set(false);
+            match resolved {
+                Ok(track) => on_add.call(track),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
 
-    // Track last fetched lyrics to avoid duplicates
-    let mut last_lyric_track_info = use_signal(|| String::new());
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
 
-    // Effect to fetch lyrics when metadata changes
-    let player_ref_for_lyrics = player_ref.clone();
-    use_effect(move || {
-        let metadata = player_metadata();
-        let player_option = player_ref_for_lyrics.read().clone();
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-lg shadow-xl",
+                onclick: move |e| e.stop_propagation(),
 
-        if let Some(ref p) = player_option {
-            if let Some(m) = metadata.as_ref() {
-                if let Some(title) = m.title.clone() {
-                    if !title.is_empty() {
-                        let artist = m.artist.clone().unwrap_or_default();
-                        let track_info = format!("{}|{}", artist, title);
-                        if *last_lyric_track_info.read() != track_info {
-                            eprintln!("[Lyrics] 检测到新曲目: {} - {}", artist, title);
+                h2 { class: "text-2xl font-bold mb-2", "▶️ Add from URL" }
+                p { class: "text-sm text-gray-400 mb-4",
+                    "A YouTube or SoundCloud page. Its audio is fetched with yt-dlp, which needs to be installed separately."
+                }
 
-                            let player_for_task = p.clone();
-                            let artist_for_search = artist.clone();
-                            spawn(async move {
-                                eprintln!("[Lyrics] 开始搜索歌词...");
-                                player_for_task.fetch_lyrics_for_current_track(&title, &artist_for_search).await;
-                                eprintln!("[Lyrics] 歌词搜索完成");
-                            });
+                input {
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    placeholder: "https://www.youtube.com/watch?v=…",
+                    value: url(),
+                    oninput: move |e| {
+                        url.set(e.value());
+                        error.set(None);
+                    },
+                }
+                label { class: "block text-sm text-gray-400 mb-1", "yt-dlp program" }
+                input {
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    placeholder: "yt-dlp (from the PATH)",
+                    value: binary_input(),
+                    oninput: move |e| binary_input.set(e.value()),
+                }
+                if let Some(error) = error() {
+                    p { class: "text-sm text-red-400 mb-4", "{error}" }
+                }
 
-                            *last_lyric_track_info.write() = track_info;
-                        }
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: resolving() || url().trim().is_empty(),
+                        onclick: add,
+                        if resolving() { "Looking up…" } else { "Add" }
                     }
                 }
             }
         }
-    });
-
-    let _metadata_future = use_future(move || {
-        let player_ref = player_ref.clone();
-        let mut last_title = String::new();
-        async move {
-            loop {
-                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
 
-                if let Some(ref player) = *player_ref.read() {
-                    if let Some(metadata) = player.get_current_metadata() {
-                        let title = metadata.title.clone().unwrap_or_default();
-                        let artist = metadata.artist.clone().unwrap_or_default();
-                        if title != last_title && !title.is_empty() {
-                            eprintln!("[Metadata] 更新: {} - {}", artist, title);
-                            last_title = title.clone();
-                        }
-                        *player_metadata.write() = Some(metadata);
-                    }
-                }
+#[component]
+fn LockModal(
+    pin_hash: Option<String>,
+    on_lock: EventHandler<String>,
+    on_unlock: EventHandler<bool>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let mut pin = use_signal(String::new);
+    let mut repeat = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let choosing = pin_hash.is_none();
+
+    let submit = use_callback(move |forget: bool| {
+        let entered = pin.peek().clone();
+        match &pin_hash {
+            Some(hash) if hash_lock_pin(&entered) == *hash => on_unlock.call(forget),
+            Some(_) => {
+                pin.set(String::new());
+                error.set(Some("Wrong PIN".to_string()));
+            }
+            None if entered.len() < LOCK_PIN_MIN_DIGITS || !entered.chars().all(|c| c.is_ascii_digit()) => {
+                error.set(Some(format!("Use at least {} digits", LOCK_PIN_MIN_DIGITS)));
             }
+            None if entered != *repeat.peek() => error.set(Some("The PINs don't match".to_string())),
+            None => on_lock.call(hash_lock_pin(&entered)),
         }
     });
 
-    let cover_img = player_metadata().as_ref()
-        .and_then(|m| m.cover.as_ref())
-        .or_else(|| full_track.as_ref().and_then(|t| t.cover.as_ref()))
-        .map(|cover_data| {
-            let base64_cover = base64_encode(cover_data);
-            format!("data:image/jpeg;base64,{}", base64_cover)
-        });
-
-    let display_title = player_metadata().as_ref()
-        .and_then(|m| m.title.clone())
-        .or_else(|| full_track.as_ref().map(|t| t.title.clone()))
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let display_artist = player_metadata().as_ref()
-        .and_then(|m| m.artist.clone())
-        .or_else(|| full_track.as_ref().map(|t| t.artist.clone()))
-        .unwrap_or_else(|| "Unknown Artist".to_string());
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
 
-    let display_album = player_metadata().as_ref()
-        .and_then(|m| m.album.clone())
-        .or_else(|| full_track.as_ref().map(|t| t.album.clone()))
-        .unwrap_or_else(|| "Unknown Album".to_string());
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl",
+                onclick: move |e| e.stop_propagation(),
 
-    rsx! {
-        div { class: "bg-gray-800 rounded-lg p-6 mb-6 flex items-center gap-6",
+                h2 { class: "text-2xl font-bold mb-2", if choosing { "🔒 Lock mode" } else { "🔓 Unlock" } }
+                p { class: "text-sm text-gray-400 mb-4",
+                    if choosing {
+                        "Playback stays available; playlists, settings and WebDAV servers can't be changed until this PIN is entered."
+                    } else {
+                        "Enter the PIN to edit playlists and settings again."
+                    }
+                }
 
-            if let Some(img_src) = cover_img {
-                div { class: "w-40 h-40 flex-shrink-0 rounded-lg shadow-lg overflow-hidden",
-                    img {
-                        src: img_src,
-                        alt: "Album cover",
-                        class: "w-full h-full object-cover",
+                input {
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    r#type: "password",
+                    inputmode: "numeric",
+                    placeholder: "PIN",
+                    value: pin(),
+                    oninput: move |e| {
+                        pin.set(e.value());
+                        error.set(None);
+                    },
+                }
+                if choosing {
+                    input {
+                        class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                        r#type: "password",
+                        inputmode: "numeric",
+                        placeholder: "Repeat PIN",
+                        value: repeat(),
+                        oninput: move |e| {
+                            repeat.set(e.value());
+                            error.set(None);
+                        },
                     }
                 }
-            } else {
-                div { class: "w-40 h-40 flex-shrink-0 rounded-lg shadow-lg bg-gray-700 flex items-center justify-center text-4xl",
-                    "🎵"
+                if let Some(error) = error() {
+                    p { class: "text-sm text-red-400 mb-4", "{error}" }
                 }
-            }
 
-            div { class: "flex-1 text-left",
-                h2 { class: "text-2xl font-bold mb-2", "{display_title}" }
-                p { class: "text-gray-400 mb-1", "{display_artist}" }
-                p { class: "text-gray-500 text-sm", "{display_album}" }
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    if !choosing {
+                        button {
+                            class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded disabled:opacity-50",
+                            title: "Unlock, and ask for a new PIN the next time lock mode is turned on",
+                            disabled: pin().is_empty(),
+                            onclick: move |_| submit.call(true),
+                            "Unlock & reset PIN"
+                        }
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: pin().is_empty(),
+                        onclick: move |_| submit.call(false),
+                        if choosing { "Lock" } else { "Unlock" }
+                    }
+                }
             }
         }
     }
 }
 
+// Pick, add or remove user profiles. Also shown at startup when asked to.
 #[component]
-fn LyricsDisplay(
-    current_time: Signal<Duration>,
-    lyric: Option<player::Lyric>,
+fn UserProfilesModal(
+    current: Option<String>,
+    on_switch: EventHandler<profiles::UserProfile>,
+    on_close: EventHandler<()>,
 ) -> Element {
-    let (visible_lines, current_line_idx) = if let Some(ref lyric) = lyric {
-        let current_idx = lyric.get_current_line(*current_time.read()).unwrap_or(0);
-        let start = current_idx.saturating_sub(2);
-        let end = (current_idx + 4).min(lyric.lines.len());
-        let lines = lyric.lines[start..end].to_vec();
-        let relative_current_idx = current_idx.saturating_sub(start);
-        (lines, Some(relative_current_idx))
-    } else {
-        (vec![], None)
+    let mut registry = use_signal(|| base_config_dir().map(|dir| profiles::ProfileRegistry::load(&dir)).unwrap_or_default());
+    let mut new_name = use_signal(String::new);
+    let mut update = move |change: &dyn Fn(&mut profiles::ProfileRegistry)| {
+        change(&mut registry.write());
+        if let Err(e) = base_config_dir().and_then(|dir| registry.peek().save(&dir)) {
+            eprintln!("[Config] 保存用户列表失败: {}", e);
+        }
     };
+    let list = registry.read().profiles.clone();
 
     rsx! {
-        if !visible_lines.is_empty() {
-            div { class: "bg-gray-800 rounded-lg p-6 mb-6 text-center",
-                div { class: "space-y-3 max-h-48 overflow-y-auto",
-                    for (idx , line) in visible_lines.iter().enumerate() {
-                        if Some(idx) == current_line_idx {
-                            div { class: "text-xl font-bold text-white transition-colors scale-105",
-                                "{line.text}"
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-md shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "👤 Who's listening?" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                div { class: "space-y-2 mb-4",
+                    for profile in list {
+                        div { key: "{profile.id}", class: "flex items-center gap-2",
+                            button {
+                                class: if current.as_ref() == Some(&profile.id) { "flex-1 text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700" } else { "flex-1 text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600" },
+                                onclick: {
+                                    let profile = profile.clone();
+                                    move |_| on_switch.call(profile.clone())
+                                },
+                                "{profile.name}"
                             }
-                        } else {
-                            div { class: "text-sm text-gray-400 transition-colors",
-                                "{line.text}"
+                            if profile.id != profiles::DEFAULT_PROFILE_ID && current.as_ref() != Some(&profile.id) {
+                                button {
+                                    class: "px-3 py-2 bg-gray-700 hover:bg-red-600 rounded text-sm",
+                                    title: "Remove from this list. The profile's playlists and settings stay on disk.",
+                                    onclick: {
+                                        let id = profile.id.clone();
+                                        move |_| update(&|registry| registry.remove(&id))
+                                    },
+                                    "✕"
+                                }
                             }
                         }
                     }
                 }
+
+                div { class: "flex gap-2 mb-4",
+                    input {
+                        class: "flex-1 px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                        placeholder: "New profile name...",
+                        value: new_name(),
+                        oninput: move |e| new_name.set(e.value()),
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: new_name().trim().is_empty(),
+                        onclick: move |_| {
+                            let name = new_name.peek().clone();
+                            update(&|registry| {
+                                registry.add(&name);
+                            });
+                            new_name.set(String::new());
+                        },
+                        "Add"
+                    }
+                }
+
+                label { class: "flex items-center gap-2 text-sm text-gray-300",
+                    input {
+                        r#type: "checkbox",
+                        checked: registry.read().ask_at_startup,
+                        onchange: move |e| {
+                            let ask = e.checked();
+                            update(&|registry| registry.ask_at_startup = ask);
+                        },
+                    }
+                    "Ask which profile to use at startup"
+                }
             }
         }
     }
 }
 
 #[component]
-fn PlayerControls(
-    state: PlayerState,
-    duration: Option<Duration>,
-    volume: f32,
-    current_time: Signal<Duration>,
-    on_play: EventHandler<()>,
-    on_pause: EventHandler<()>,
-    on_stop: EventHandler<()>,
-    on_seek: EventHandler<Duration>,
-    on_volume_change: EventHandler<f32>,
-    on_previous: EventHandler<()>,
-    on_next: EventHandler<()>,
+fn PlaylistManagerModal(
+    on_close: EventHandler<()>,
+    on_add_playlist: EventHandler<String>,
+    on_load_files: EventHandler<()>,
 ) -> Element {
-    let progress_percent = if let Some(d) = duration {
-        if d.as_secs() > 0 {
-            let ct = current_time();
-            (ct.as_secs_f64() / d.as_secs_f64() * 100.0).clamp(0.0, 100.0) as i32
-        } else {
-            0
-        }
-    } else {
-        0
-    };
-
-    let formatted_time = format_duration(current_time());
-    let formatted_duration = duration.map(format_duration).unwrap_or_else(|| "0:00".to_string());
+    let mut playlist_name = use_signal(|| String::new());
 
     rsx! {
-        div { class: "bg-gray-800 rounded-lg p-6 mb-6",
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                h2 { class: "text-2xl font-bold mb-4", "Create New Playlist" }
 
-            div { class: "mb-4 relative",
                 input {
-                    r#type: "range",
-                    min: "0",
-                    max: "100",
-                    value: "{progress_percent}",
-                    class: "w-full h-2 appearance-none cursor-pointer bg-gray-700 rounded-full",
-                    style: "accent-color: #3b82f6;",
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    placeholder: "Playlist name...",
+                    value: playlist_name(),
                     oninput: move |e| {
-                        if let Some(d) = duration {
-                            let percent = e.value().parse::<f64>().unwrap_or(0.0) / 100.0;
-                            let seek_time = Duration::from_secs_f64(d.as_secs_f64() * percent);
-                            on_seek.call(seek_time);
-                        }
+                        *playlist_name.write() = e.value();
                     },
                 }
-                div { class: "flex justify-between mt-2 text-xs text-gray-400",
-                    span { "{formatted_time}" }
-                    span { "{formatted_duration}" }
+
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: playlist_name().is_empty(),
+                        onclick: move |_| {
+                            on_add_playlist.call(playlist_name());
+                        },
+                        "Create"
+                    }
                 }
             }
+        }
+    }
+}
 
-            div { class: "flex justify-center items-center gap-4 mb-6",
+// Name and rules of a smart playlist; `existing` is the one being edited, if any
+#[component]
+fn SmartPlaylistModal(
+    existing: Option<(String, playlist::smart::SmartRules)>,
+    on_save: EventHandler<(String, playlist::smart::SmartRules)>,
+    on_close: EventHandler<()>,
+) -> Element {
+    use playlist::smart::{RuleKind, SmartRules};
+
+    let is_new = existing.is_none();
+    let (initial_name, initial_rules) = existing.unwrap_or_default();
+    let mut name = use_signal(|| initial_name);
+    let mut match_all = use_signal(|| initial_rules.match_all || initial_rules.rules.is_empty());
+    // Each rule as it's being typed: its kind, and the value text
+    let mut rows = use_signal(|| {
+        let rows: Vec<(RuleKind, String)> = initial_rules.rules.iter().map(|rule| (rule.kind(), rule.value())).collect();
+        if rows.is_empty() {
+            vec![(RuleKind::ArtistContains, String::new())]
+        } else {
+            rows
+        }
+    });
+    let rules: Option<Vec<_>> = rows.read().iter().map(|(kind, value)| kind.with_value(value)).collect();
+    let can_save = !name().trim().is_empty() && rules.as_ref().is_some_and(|rules| !rules.is_empty());
+    // Why the last rule file couldn't be read or written
+    let mut file_error = use_signal(|| None::<String>);
 
-                button {
-                    class: "px-6 py-2 bg-blue-500 hover:bg-blue-600 rounded-lg font-semibold",
-                    onclick: move |_| on_previous.call(()),
-                    "⏮ Previous"
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-lg shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                h2 { class: "text-2xl font-bold mb-4",
+                    if is_new {
+                        "New Smart Playlist"
+                    } else {
+                        "Smart Playlist Rules"
+                    }
                 }
 
-                button {
-                    class: "px-6 py-2 bg-red-500 hover:bg-red-600 rounded-lg font-semibold",
-                    onclick: move |_| on_stop.call(()),
-                    "⏹ Stop"
+                input {
+                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
+                    placeholder: "Playlist name...",
+                    value: name(),
+                    oninput: move |e| name.set(e.value()),
                 }
 
-                if state == PlayerState::Playing {
-                    button {
-                        class: "px-6 py-2 bg-yellow-500 hover:bg-yellow-600 rounded-lg font-semibold text-black",
-                        onclick: move |_| on_pause.call(()),
-                        "⏸ Pause"
+                div { class: "flex items-center gap-2 mb-2 text-sm",
+                    span { "Tracks matching" }
+                    select {
+                        class: "px-2 py-1 bg-gray-700 rounded",
+                        value: if match_all() { "all" } else { "any" },
+                        onchange: move |e: FormEvent| match_all.set(e.value() == "all"),
+                        option { value: "all", "all" }
+                        option { value: "any", "any" }
+                    }
+                    span { "of these rules" }
+                }
+
+                div { class: "space-y-2 mb-4",
+                    for (i , (kind , value)) in rows().into_iter().enumerate() {
+                        div { key: "{i}", class: "flex items-center gap-2 text-sm",
+                            select {
+                                class: "px-2 py-1 bg-gray-700 rounded",
+                                value: "{RuleKind::ALL.iter().position(|k| *k == kind).unwrap_or(0)}",
+                                onchange: move |e: FormEvent| {
+                                    if let Some(kind) = e.value().parse::<usize>().ok().and_then(|k| RuleKind::ALL.get(k)) {
+                                        rows.write()[i].0 = *kind;
+                                    }
+                                },
+                                for (k , kind) in RuleKind::ALL.iter().enumerate() {
+                                    option { key: "{k}", value: "{k}", "{kind.label()}" }
+                                }
+                            }
+                            if kind.takes_value() {
+                                input {
+                                    class: if kind.with_value(&value).is_some() { "flex-1 px-2 py-1 rounded bg-gray-700 border border-gray-600 text-white" } else { "flex-1 px-2 py-1 rounded bg-gray-700 border border-red-500 text-white" },
+                                    value: "{value}",
+                                    oninput: move |e| rows.write()[i].1 = e.value(),
+                                }
+                            } else {
+                                span { class: "flex-1" }
+                            }
+                            button {
+                                class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                                title: "Remove this rule",
+                                disabled: rows.read().len() == 1,
+                                onclick: move |_| {
+                                    rows.write().remove(i);
+                                },
+                                "✕"
+                            }
+                        }
                     }
-                } else {
                     button {
-                        class: "px-6 py-2 bg-green-500 hover:bg-green-600 rounded-lg font-semibold text-black",
-                        onclick: move |_| on_play.call(()),
-                        "▶ Play"
+                        class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                        onclick: move |_| rows.write().push((RuleKind::ArtistContains, String::new())),
+                        "+ Rule"
                     }
                 }
 
-                button {
-                    class: "px-6 py-2 bg-blue-500 hover:bg-blue-600 rounded-lg font-semibold",
-                    onclick: move |_| on_next.call(()),
-                    "⏭ Next"
+                if let Some(error) = file_error() {
+                    div { class: "text-red-300 text-sm mb-2", "{error}" }
                 }
-            }
 
-            div { class: "flex items-center gap-4",
-                span { class: "text-sm", "🔊" }
-                input {
-                    r#type: "range",
-                    min: "0",
-                    max: "100",
-                    value: (volume * 100.0) as i32,
-                    class: "flex-1",
-                    oninput: move |e| {
-                        let val = e.value().parse::<f32>().unwrap_or(1.0) / 100.0;
-                        on_volume_change.call(val);
-                    },
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded mr-auto",
+                        title: "Replace these rules with ones exported from another machine or by someone else",
+                        onclick: move |_| {
+                            spawn(async move {
+                                let Some(file) = rfd::AsyncFileDialog::new()
+                                    .set_title("Smart playlist rules")
+                                    .add_filter("Smart playlist rules", &["json"])
+                                    .pick_file()
+                                    .await
+                                else {
+                                    return;
+                                };
+                                let imported = std::fs::read_to_string(file.path())
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|json| playlist::smart::import(&json));
+                                match imported {
+                                    Ok((imported_name, imported_rules)) => {
+                                        if name.peek().trim().is_empty() {
+                                            name.set(imported_name);
+                                        }
+                                        match_all.set(imported_rules.match_all);
+                                        rows.set(imported_rules.rules.iter().map(|rule| (rule.kind(), rule.value())).collect());
+                                        file_error.set(None);
+                                    }
+                                    Err(e) => file_error.set(Some(format!("Couldn't import {}: {}", file.file_name(), e))),
+                                }
+                            });
+                        },
+                        "📥 Import…"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded disabled:opacity-50",
+                        title: "Save these rules as a JSON file to share; the tracks aren't included",
+                        disabled: !can_save,
+                        onclick: {
+                            let rules = rules.clone();
+                            move |_| {
+                                let Some(rules) = rules.clone() else { return };
+                                let name = name().trim().to_string();
+                                let json = playlist::smart::export(&name, &SmartRules { match_all: match_all(), rules });
+                                spawn(async move {
+                                    if let Some(file) = rfd::AsyncFileDialog::new()
+                                        .set_file_name(format!("{}.json", name))
+                                        .add_filter("Smart playlist rules", &["json"])
+                                        .save_file()
+                                        .await
+                                    {
+                                        let written = std::fs::write(file.path(), json);
+                                        file_error.set(written.err().map(|e| format!("Couldn't export the rules: {}", e)));
+                                    }
+                                });
+                            }
+                        },
+                        "📤 Export…"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: !can_save,
+                        onclick: move |_| {
+                            if let Some(rules) = rules.clone() {
+                                on_save.call((name().trim().to_string(), SmartRules { match_all: match_all(), rules }));
+                            }
+                        },
+                        if is_new {
+                            "Create"
+                        } else {
+                            "Save"
+                        }
+                    }
                 }
-                span { class: "text-sm w-8", "{(volume * 100.0) as i32}%" }
             }
         }
     }
 }
 
+// Edits the tags saved in a local file
 #[component]
-fn PlaylistSidebar(
-    playlists: Vec<Playlist>,
-    current_playlist: usize,
-    webdav_configs: Vec<WebDAVConfig>,
-    expanded_webdav_index: Option<usize>,
-    webdav_items: Vec<webdav::WebDAVItem>,
-    webdav_current_path: String,
-    webdav_loading: bool,
-    on_select: EventHandler<usize>,
-    on_add_playlist: EventHandler<()>,
-    on_toggle_webdav: EventHandler<usize>,
-    on_webdav_navigate: EventHandler<String>,
-    on_webdav_play: EventHandler<webdav::WebDAVItem>,
-) -> Element {
+fn TagEditorModal(track: TrackStub, on_save: EventHandler<metadata::TagEdit>, on_close: EventHandler<()>) -> Element {
+    let path = track.path.clone();
+    let mut edit = use_signal(move || metadata::read_tag_edit(Path::new(&path)));
+    // The number fields as typed, checked before saving
+    let mut track_text = use_signal(|| edit.peek().track_number.map(|n| n.to_string()).unwrap_or_default());
+    let mut year_text = use_signal(|| edit.peek().year.map(|y| y.to_string()).unwrap_or_default());
+    let track_number = Some(track_text().trim().to_string())
+        .filter(|text| !text.is_empty())
+        .map(|text| text.parse::<u32>().ok().filter(|n| (1..=9999).contains(n)));
+    let year = Some(year_text().trim().to_string())
+        .filter(|text| !text.is_empty())
+        .map(|text| text.parse::<i32>().ok().filter(|y| (1000..=9999).contains(y)));
+    let can_save = track_number.is_none_or(|n| n.is_some()) && year.is_none_or(|y| y.is_some());
+    // MusicBrainz suggestions, once looked up
+    let mut lookup = use_signal(|| None::<Result<Vec<musicbrainz::Candidate>, String>>);
+    let mut looking_up = use_signal(|| false);
+    let look_up = use_callback({
+        let path = track.path.clone();
+        let duration = track.duration;
+        move |_: ()| {
+            let tags = metadata::TagEdit { track_number: track_text.peek().trim().parse().ok(), ..edit.peek().clone() };
+            let Some(query) = musicbrainz::search_query(Path::new(&path), &tags, duration) else {
+                lookup.set(Some(Err("Nothing to search by".to_string())));
+                return;
+            };
+            looking_up.set(true);
+            spawn(async move {
+                let found = tokio::task::spawn_blocking(move || musicbrainz::search(&query))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|result| result);
+                looking_up.set(false);
+                lookup.set(Some(found));
+            });
+        }
+    });
+    // Placeholder tags are looked up as soon as the editor opens
+    use_hook(move || {
+        if musicbrainz::looks_untagged(&edit.peek()) {
+            look_up.call(());
+        }
+    });
+    let mut pick = move |candidate: musicbrainz::Candidate| {
+        let mut tags = edit.write();
+        tags.title = candidate.title;
+        tags.artist = candidate.artist;
+        if !candidate.album.is_empty() {
+            tags.album = candidate.album;
+        }
+        if let Some(genre) = candidate.genre {
+            tags.genre = genre;
+        }
+        if let Some(number) = candidate.track_number {
+            track_text.set(number.to_string());
+        }
+        if let Some(year) = candidate.year {
+            year_text.set(year.to_string());
+        }
+    };
+    let field_class = |valid: bool| {
+        if valid {
+            "flex-1 px-2 py-1 rounded bg-gray-700 border border-gray-600 text-white"
+        } else {
+            "flex-1 px-2 py-1 rounded bg-gray-700 border border-red-500 text-white"
+        }
+    };
+
     rsx! {
-        div { class: "bg-gray-800 rounded-lg p-4 h-full flex flex-col",
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
 
-            div { class: "flex-1 overflow-y-auto mb-4",
-                div { class: "flex justify-between items-center mb-4",
-                    h3 { class: "text-lg font-bold", "📋 Playlists" }
-                    button {
-                        class: "px-3 py-1 bg-blue-500 hover:bg-blue-600 rounded text-sm",
-                        onclick: move |_| on_add_playlist.call(()),
-                        "+ New"
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-lg shadow-xl",
+                onclick: move |e| e.stop_propagation(),
+
+                h2 { class: "text-2xl font-bold mb-2", "🏷 Edit Tags" }
+                p { class: "text-xs text-gray-400 mb-4 truncate", title: "{track.path}", "{track.path}" }
+
+                label { class: "flex items-center gap-2 mb-3 text-sm",
+                    span { class: "w-20 text-gray-300", "Title" }
+                    input {
+                        class: field_class(true),
+                        value: "{edit.read().title}",
+                        oninput: move |e| edit.write().title = e.value(),
                     }
                 }
-
-                div { class: "space-y-2",
-                    for (idx , playlist) in playlists.iter().enumerate() {
-                        button {
-                            class: if idx == current_playlist { "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm" } else { "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm" },
-                            onclick: move |_| on_select.call(idx),
-                            div { class: "font-semibold", "{playlist.name}" }
-                            p { class: "text-xs text-gray-300", "{playlist.tracks.len()} track(s)" }
-                        }
+                label { class: "flex items-center gap-2 mb-3 text-sm",
+                    span { class: "w-20 text-gray-300", "Artist" }
+                    input {
+                        class: field_class(true),
+                        value: "{edit.read().artist}",
+                        oninput: move |e| edit.write().artist = e.value(),
+                    }
+                }
+                label { class: "flex items-center gap-2 mb-3 text-sm",
+                    span { class: "w-20 text-gray-300", "Album" }
+                    input {
+                        class: field_class(true),
+                        value: "{edit.read().album}",
+                        oninput: move |e| edit.write().album = e.value(),
+                    }
+                }
+                label { class: "flex items-center gap-2 mb-3 text-sm",
+                    span { class: "w-20 text-gray-300", "Track #" }
+                    input {
+                        class: field_class(track_number.is_none_or(|n| n.is_some())),
+                        value: "{track_text}",
+                        oninput: move |e| track_text.set(e.value()),
+                    }
+                }
+                label { class: "flex items-center gap-2 mb-3 text-sm",
+                    span { class: "w-20 text-gray-300", "Year" }
+                    input {
+                        class: field_class(year.is_none_or(|y| y.is_some())),
+                        value: "{year_text}",
+                        oninput: move |e| year_text.set(e.value()),
+                    }
+                }
+                label { class: "flex items-center gap-2 mb-4 text-sm",
+                    span { class: "w-20 text-gray-300", "Genre" }
+                    input {
+                        class: field_class(true),
+                        value: "{edit.read().genre}",
+                        oninput: move |e| edit.write().genre = e.value(),
                     }
                 }
-            }
 
-            // WebDAV Servers Section
-            if !webdav_configs.is_empty() {
-                div { class: "border-t border-gray-700 pt-4",
-                    h3 { class: "text-lg font-bold mb-2", "☁️ Cloud Sources" }
-                    div { class: "max-h-96 overflow-y-auto space-y-2 webdav-file-list",
-                        for (idx , config) in webdav_configs.iter().enumerate() {
-                            if config.enabled {
-                                div { class: "mb-2",
+                div { class: "mb-4",
+                    button {
+                        class: "px-3 py-1 bg-gray-700 hover:bg-gray-600 rounded text-sm disabled:opacity-50",
+                        title: "Search MusicBrainz by what the tags, the length and the file and folder names say",
+                        disabled: looking_up(),
+                        onclick: move |_| look_up.call(()),
+                        if looking_up() { "🔎 Looking up…" } else { "🔎 Look Up on MusicBrainz" }
+                    }
+                    match lookup() {
+                        None => rsx! {},
+                        Some(Err(error)) => rsx! {
+                            div { class: "text-xs text-red-400 mt-2", "{error}" }
+                        },
+                        Some(Ok(candidates)) if candidates.is_empty() => rsx! {
+                            div { class: "text-xs text-gray-400 mt-2", "No matches found" }
+                        },
+                        Some(Ok(candidates)) => rsx! {
+                            div { class: "mt-2 max-h-40 overflow-y-auto space-y-1",
+                                for (idx , candidate) in candidates.into_iter().enumerate() {
                                     button {
-                                        class: "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-teal-700 text-sm flex items-center gap-2 mb-1",
-                                        onclick: move |_| on_toggle_webdav.call(idx),
-                                        span { "☁️" }
-                                        div {
-                                            div { class: "font-semibold truncate", "{config.name}" }
-                                            div { class: "text-xs text-gray-400 truncate",
-                                                "{config.url}"
-                                            }
-                                        }
+                                        key: "{idx}",
+                                        class: "w-full text-left p-2 bg-gray-700 hover:bg-gray-600 rounded text-sm",
+                                        title: "Fill in the fields with this match; Save writes them to the file",
+                                        onclick: {
+                                            let candidate = candidate.clone();
+                                            move |_| pick(candidate.clone())
+                                        },
+                                        div { class: "truncate font-semibold", "{candidate.title} — {candidate.artist}" }
+                                        div { class: "truncate text-xs text-gray-400", "{candidate_summary(&candidate)}" }
                                     }
+                                }
+                            }
+                        },
+                    }
+                }
 
-                                    if expanded_webdav_index == Some(idx) {
-                                        div { class: "ml-4 border-l-2 border-gray-600 pl-2 space-y-1",
-                                            if webdav_loading {
-                                                div { class: "text-xs text-gray-400 p-2",
-                                                    "🔄 Loading..."
-                                                }
-                                            } else {
-                                                // Breadcrumb / Up Navigation
-                                                {
-                                                    if webdav_current_path != "/" {
-                                                        let nav_path = webdav_current_path.clone();
-                                                        Some(rsx! {
-                                                            button {
-                                                                class: "w-full text-left px-2 py-1 text-xs bg-gray-600 hover:bg-gray-500 rounded mb-1",
-                                                                onclick: move |_| {
-                                                                    let mut path = nav_path.clone();
-                                                                    if path.ends_with('/') {
-                                                                        path.pop();
-                                                                    }
-                                                                    if let Some(pos) = path.rfind('/') {
-                                                                        path.truncate(pos + 1);
-                                                                    } else {
-                                                                        path = "/".to_string();
-                                                                    }
-                                                                    on_webdav_navigate.call(path);
-                                                                },
-                                                                "⬆ .."
-                                                            }
-                                                        })
-                                                    } else {
-                                                        None
-                                                    }
-                                                }
+                div { class: "flex gap-4 justify-end",
+                    button {
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
+                        onclick: move |_| on_close.call(()),
+                        "Cancel"
+                    }
+                    button {
+                        class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
+                        disabled: !can_save,
+                        onclick: move |_| {
+                            on_save.call(metadata::TagEdit {
+                                track_number: track_number.flatten(),
+                                year: year.flatten(),
+                                ..edit()
+                            });
+                        },
+                        "Save"
+                    }
+                }
+            }
+        }
+    }
+}
 
-                                                if webdav_items.is_empty() {
-                                                    div { class: "text-xs text-gray-400 p-2",
-                                                        "Empty folder"
-                                                    }
-                                                } else {
-                                                    {
+// "1997 · Alternative Rock", or whichever of the two the tags have
+fn year_and_genre(year: Option<i32>, genre: Option<&str>) -> String {
+    let year = year.map(|year| year.to_string());
+    [year.as_deref(), genre].into_iter().flatten().collect::<Vec<_>>().join(" · ")
+}
 
-                                                        webdav_items
-                                                            .iter()
-                                                            .map(|item| {
-                                                                let item_clone = item.clone();
-                                                                let is_dir = item.is_dir;
-                                                                let item_name = item.name.clone();
-                                                                let current_p = webdav_current_path.clone();
-                                                                let nav_click = on_webdav_navigate.clone();
-                                                                let play_click = on_webdav_play.clone();
-                                                                rsx! {
-                                                                    div {
-                                                                        class: "flex items-center p-1 rounded hover:bg-gray-600 cursor-pointer text-sm",
-                                                                        onclick: move |_| {
-                                                                            if is_dir {
-                                                                                let mut path = current_p.clone();
-                                                                                if !path.ends_with('/') {
-                                                                                    path.push('/');
-                                                                                }
-                                                                                path.push_str(&item_name);
-                                                                                nav_click.call(path);
-                                                                            } else {
-                                                                                play_click.call(item_clone.clone());
-                                                                            }
-                                                                        },
-                                                                        span { class: "mr-2 text-xs",
-                                                                            if is_dir {
-                                                                                "📁"
-                                                                            } else {
-                                                                                "🎵"
-                                                                            }
-                                                                        }
-                                                                        span { class: "truncate flex-1", "{item.name}" }
-                                                                    }
-                                                                }
-                                                            })
+// "OK Computer · #2 · 1997 · 6:23 · 97% match"
+fn candidate_summary(candidate: &musicbrainz::Candidate) -> String {
+    let mut parts = Vec::new();
+    if !candidate.album.is_empty() {
+        parts.push(candidate.album.clone());
+    }
+    if let Some(number) = candidate.track_number {
+        parts.push(format!("#{}", number));
+    }
+    if let Some(year) = candidate.year {
+        parts.push(year.to_string());
+    }
+    if let Some(length) = candidate.length {
+        parts.push(format_duration(length));
+    }
+    parts.push(format!("{}% match", candidate.score));
+    parts.join(" · ")
+}
+
+// Sets of tracks that look like the same song, each offering to keep one copy
+#[component]
+fn DuplicatesModal(
+    groups: Option<Vec<Vec<TrackStub>>>,
+    on_keep: EventHandler<(TrackStub, Vec<TrackStub>)>,
+    on_close: EventHandler<()>,
+) -> Element {
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
+
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl max-h-96 flex flex-col",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "🧬 Duplicates" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
+
+                div { class: "flex-1 overflow-y-auto space-y-4 min-h-0",
+                    match groups {
+                        None => rsx! {
+                            div { class: "text-center py-4 text-gray-400", "Comparing tracks…" }
+                        },
+                        Some(groups) if groups.is_empty() => rsx! {
+                            div { class: "text-center py-4 text-gray-400", "No duplicates found" }
+                        },
+                        Some(groups) => rsx! {
+                            for group in groups {
+                                div {
+                                    key: "{group[0].path}",
+                                    class: "p-2 bg-gray-700 rounded space-y-2",
+                                    for track in group.iter().cloned() {
+                                        div {
+                                            key: "{track.path}",
+                                            class: "flex items-center justify-between gap-2",
+                                            div { class: "min-w-0",
+                                                div { class: "truncate text-sm font-semibold", "{track.title} — {track.artist}" }
+                                                div { class: "truncate text-xs text-gray-400", title: "{track.path}",
+                                                    if track.duration.is_zero() {
+                                                        "{track.path}"
+                                                    } else {
+                                                        "{format_duration(track.duration)} · {track.path}"
                                                     }
                                                 }
                                             }
+                                            button {
+                                                class: "flex-shrink-0 px-3 py-1 bg-blue-500 hover:bg-blue-600 rounded text-sm",
+                                                title: "Keep this copy and drop the others from every playlist",
+                                                onclick: {
+                                                    let group = group.clone();
+                                                    move |_| on_keep.call((track.clone(), group.clone()))
+                                                },
+                                                "Keep"
+                                            }
                                         }
                                     }
                                 }
                             }
-                        }
+                        },
                     }
                 }
             }
@@ -1942,67 +8632,90 @@ fn PlaylistSidebar(
     }
 }
 
+// Totals, the most played artists and albums, and disk use per format
 #[component]
-fn PlaylistTracks(
-    playlist: Playlist,
-    current_track: Option<TrackStub>,
-    on_track_select: EventHandler<TrackStub>,
-    on_clear: EventHandler<()>,
-) -> Element {
-    let has_tracks = !playlist.tracks.is_empty();
-
+fn LibraryStatsModal(stats: Option<playlist::stats::LibraryStats>, on_close: EventHandler<()>) -> Element {
     rsx! {
-        div { class: "bg-gray-800 rounded-lg p-4",
+        div {
+            class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            onclick: move |_| on_close.call(()),
 
-            div { class: "flex items-center justify-between mb-4",
-                h3 { class: "text-lg font-bold", "🎶 Tracks" }
-                if has_tracks {
+            div {
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl max-h-96 flex flex-col",
+                onclick: move |e| e.stop_propagation(),
+
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "📊 Library Stats" }
                     button {
-                        class: "px-3 py-1 bg-red-600 hover:bg-red-700 rounded text-sm text-white transition-colors",
-                        onclick: move |_| on_clear.call(()),
-                        "🗑️ Clear"
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
                     }
                 }
-            }
-
-            if playlist.tracks.is_empty() {
-                div { class: "text-center py-8 text-gray-500", "No tracks in playlist" }
-            } else {
-                div { class: "space-y-2 max-h-96 overflow-y-auto",
-                    {
 
-                        playlist
-                            .tracks
-                            .iter()
-                            .enumerate()
-                            .map(|(idx, track)| {
-                                let track_clone = track.clone();
-                                let is_current = current_track
-                                    .as_ref()
-                                    .map(|t| t.id == track.id)
-                                    .unwrap_or(false);
-                                let class_str = if is_current {
-                                    "w-full text-left px-3 py-2 rounded bg-blue-600 hover:bg-blue-700 text-sm"
-                                } else {
-                                    "w-full text-left px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 text-sm"
-                                };
-                                rsx! {
-                                    button {
-                                        key: "{idx}",
-                                        class: class_str,
-                                        onclick: move |_| on_track_select.call(track_clone.clone()),
+                div { class: "flex-1 overflow-y-auto space-y-4 min-h-0",
+                    match stats {
+                        None => rsx! {
+                            div { class: "text-center py-4 text-gray-400", "Adding up the library…" }
+                        },
+                        Some(stats) => rsx! {
+                            div { class: "grid grid-cols-4 gap-2 text-center",
+                                for (value , label) in [
+                                    (format::NumberFormat::current().integer(stats.tracks as u64), "Tracks"),
+                                    (format::NumberFormat::current().integer(stats.albums as u64), "Albums"),
+                                    (format::NumberFormat::current().integer(stats.artists as u64), "Artists"),
+                                    (format_duration(stats.total_duration), "Playtime"),
+                                ] {
+                                    div { key: "{label}", class: "p-2 bg-gray-700 rounded",
+                                        div { class: "text-sm font-semibold", "{value}" }
+                                        div { class: "text-xs text-gray-400", "{label}" }
+                                    }
+                                }
+                            }
 
-                
-                                        div { class: "font-semibold truncate", "{track.title}" }
-                                        if track.artist != "Cloud Stream" {
-                                            p { class: "text-xs text-gray-300 truncate", "{track.artist}" }
+                            div { class: "grid grid-cols-2 gap-4",
+                                div {
+                                    h3 { class: "text-sm font-semibold text-gray-300 mb-1", "Top Artists" }
+                                    if stats.top_artists.is_empty() {
+                                        div { class: "text-xs text-gray-500", "Nothing played yet" }
+                                    }
+                                    for (artist , plays) in stats.top_artists.iter().map(|(artist, plays)| (artist, format_count(*plays as usize, "play", "plays"))) {
+                                        div { key: "{artist}", class: "flex justify-between gap-2 text-sm",
+                                            span { class: "truncate", "{artist}" }
+                                            span { class: "flex-shrink-0 text-gray-400", "{plays}" }
                                         }
-                                        if track.duration.as_secs() > 0 {
-                                            p { class: "text-xs text-gray-400", "{format_duration(track.duration)}" }
+                                    }
+                                }
+                                div {
+                                    h3 { class: "text-sm font-semibold text-gray-300 mb-1", "Top Albums" }
+                                    if stats.top_albums.is_empty() {
+                                        div { class: "text-xs text-gray-500", "Nothing played yet" }
+                                    }
+                                    for (album , artist , plays) in stats.top_albums.iter().map(|(album, artist, plays)| (album, artist, format_count(*plays as usize, "play", "plays"))) {
+                                        div { key: "{album}-{artist}", class: "flex justify-between gap-2 text-sm",
+                                            span { class: "truncate", title: "{artist}", "{album}" }
+                                            span { class: "flex-shrink-0 text-gray-400", "{plays}" }
                                         }
                                     }
                                 }
-                            })
+                            }
+
+                            div {
+                                h3 { class: "text-sm font-semibold text-gray-300 mb-1", "Formats" }
+                                for usage in stats.formats.iter() {
+                                    div { key: "{usage.format}", class: "flex justify-between gap-2 text-sm",
+                                        span { "{usage.format}" }
+                                        span { class: "text-gray-400",
+                                            {format_count(usage.tracks, "track", "tracks")}
+                                            // Remote-only formats have no size to show
+                                            if usage.bytes > 0 {
+                                                " · {format_bytes(usage.bytes)}"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        },
                     }
                 }
             }
@@ -2010,13 +8723,40 @@ fn PlaylistTracks(
     }
 }
 
+// Moves listed in the organizer's preview; the rest are only counted
+const ORGANIZE_PREVIEW_LIMIT: usize = 50;
+
+// Where the organizer would move each library file, previewed before anything moves
 #[component]
-fn PlaylistManagerModal(
+fn OrganizeModal(
+    tracks: Vec<TrackStub>,
+    root: Option<String>,
+    pattern: String,
+    busy: bool,
+    on_apply: EventHandler<(String, String)>,
     on_close: EventHandler<()>,
-    on_add_playlist: EventHandler<String>,
-    on_load_files: EventHandler<()>,
 ) -> Element {
-    let mut playlist_name = use_signal(|| String::new());
+    let mut root = use_signal(|| root);
+    let mut pattern = use_signal(|| pattern);
+    let moves = match root() {
+        Some(dir) => organize::plan(std::path::Path::new(&dir), &pattern(), &tracks),
+        None => Vec::new(),
+    };
+    let preview: Vec<(String, String)> = moves
+        .iter()
+        .take(ORGANIZE_PREVIEW_LIMIT)
+        .map(|m| {
+            let base = root.peek().clone().unwrap_or_default();
+            let to = m.to.strip_prefix(&base).unwrap_or(&m.to);
+            (m.from.display().to_string(), to.display().to_string())
+        })
+        .collect();
+    let count = moves.len();
+    let root_label = root().unwrap_or_else(|| "Not chosen yet".to_string());
+    let example = tracks
+        .first()
+        .map(|track| organize::relative_path(&pattern(), track).display().to_string())
+        .unwrap_or_default();
 
     rsx! {
         div {
@@ -2024,21 +8764,70 @@ fn PlaylistManagerModal(
             onclick: move |_| on_close.call(()),
 
             div {
-                class: "bg-gray-800 rounded-lg p-6 w-96 shadow-xl",
+                class: "bg-gray-800 rounded-lg p-6 w-full max-w-2xl shadow-xl",
                 onclick: move |e| e.stop_propagation(),
 
-                h2 { class: "text-2xl font-bold mb-4", "Create New Playlist" }
+                div { class: "flex justify-between items-center mb-4",
+                    h2 { class: "text-2xl font-bold", "🗂 Organize Files" }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
+                }
 
-                input {
-                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 mb-4 text-white",
-                    placeholder: "Playlist name...",
-                    value: playlist_name(),
-                    oninput: move |e| {
-                        *playlist_name.write() = e.value();
-                    },
+                div { class: "space-y-4 mb-4",
+                    div {
+                        label { class: "block text-sm font-semibold mb-2", "Library folder" }
+                        div { class: "flex gap-2 items-center",
+                            span { class: "flex-1 text-sm truncate text-gray-300", "{root_label}" }
+                            button {
+                                class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded text-sm",
+                                onclick: move |_| {
+                                    spawn(async move {
+                                        if let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await {
+                                            root.set(Some(dir.path().to_string_lossy().into_owned()));
+                                        }
+                                    });
+                                },
+                                "📂 Choose…"
+                            }
+                        }
+                    }
+                    div {
+                        label { class: "block text-sm font-semibold mb-2", "Pattern" }
+                        input {
+                            class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                            value: "{pattern}",
+                            placeholder: organize::DEFAULT_PATTERN,
+                            oninput: move |e| pattern.set(e.value()),
+                        }
+                        p { class: "text-xs text-gray-400 mt-1",
+                            "Fields: {{artist}} (album artist), {{track_artist}}, {{album}}, {{track}}, {{title}}, {{year}}, {{genre}}. "
+                            if !example.is_empty() { "e.g. {example}" }
+                        }
+                    }
                 }
 
-                div { class: "flex gap-4 justify-end",
+                if root().is_some() {
+                    h3 { class: "font-semibold mb-2",
+                        if count == 0 { "Everything is already in place" } else { "{format_count(count, \"file\", \"files\")} to move" }
+                    }
+                    div { class: "max-h-64 overflow-y-auto space-y-1",
+                        for (from , to) in preview {
+                            div { key: "{from}", class: "text-xs truncate",
+                                span { class: "text-gray-400", "{from}" }
+                                " → "
+                                span { class: "text-green-400", "{to}" }
+                            }
+                        }
+                        if count > ORGANIZE_PREVIEW_LIMIT {
+                            div { class: "text-xs text-gray-500", "…and {count - ORGANIZE_PREVIEW_LIMIT} more" }
+                        }
+                    }
+                }
+
+                div { class: "flex gap-4 justify-end mt-4",
                     button {
                         class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
                         onclick: move |_| on_close.call(()),
@@ -2046,11 +8835,13 @@ fn PlaylistManagerModal(
                     }
                     button {
                         class: "px-4 py-2 bg-blue-500 hover:bg-blue-600 rounded disabled:opacity-50",
-                        disabled: playlist_name().is_empty(),
+                        disabled: busy || count == 0,
                         onclick: move |_| {
-                            on_add_playlist.call(playlist_name());
+                            if let Some(dir) = root() {
+                                on_apply.call((dir, pattern()));
+                            }
                         },
-                        "Create"
+                        if busy { "Moving…" } else { "Move {format_count(count, \"file\", \"files\")}" }
                     }
                 }
             }
@@ -2058,11 +8849,35 @@ fn PlaylistManagerModal(
     }
 }
 
-fn format_duration(duration: Duration) -> String {
-    let secs = duration.as_secs();
-    let mins = secs / 60;
-    let secs = secs % 60;
-    format!("{}:{:02}", mins, secs)
+// "57 tracks · 3:42:10", or just the count while no length is known
+fn playlist_summary(playlist: &Playlist) -> String {
+    let count = format_count(playlist.tracks.len(), "track", "tracks");
+    let total = playlist.total_duration();
+    if total.is_zero() {
+        count
+    } else {
+        format!("{} · {}", count, format_duration(total))
+    }
+}
+
+// "L 30%", "Center" or "R 30%"
+fn format_balance(balance: f32) -> String {
+    let percent = (balance.abs() * 100.0).round() as i32;
+    if percent == 0 {
+        "Center".to_string()
+    } else if balance < 0.0 {
+        format!("L {}%", percent)
+    } else {
+        format!("R {}%", percent)
+    }
+}
+
+fn format_gain(gain_db: f32) -> String {
+    if gain_db == 0.0 {
+        "0 dB".to_string()
+    } else {
+        format!("{:+} dB", gain_db)
+    }
 }
 
 // Encode binary data to base64 for image display
@@ -2099,18 +8914,16 @@ fn base64_encode(data: &[u8]) -> String {
     result
 }
 
-// Find cover image in directory (case-insensitive)
-fn find_cover_image_in_dir(dir: &Path) -> Option<Vec<u8>> {
-    const COVER_FILENAMES: [&str; 6] = ["cover.jpg", "cover.jpeg", "cover.png", "folder.jpg", "folder.jpeg", "folder.png"];
-
-    for filename in COVER_FILENAMES.iter() {
+// Find cover image in directory (case-insensitive); returns its cover cache id
+fn find_cover_image_in_dir(dir: &Path) -> Option<String> {
+    for filename in file_watcher::COVER_FILENAMES.iter() {
         let cover_path = dir.join(filename);
         if cover_path.exists() {
             if let Ok(data) = std::fs::read(&cover_path) {
                 // Verify it's a valid image
                 if is_valid_image(&data) {
                     eprintln!("[Cover] Found cover image: {}", cover_path.display());
-                    return Some(data);
+                    return cover_cache::store(&data);
                 }
             }
         }
@@ -2131,60 +8944,122 @@ fn is_valid_image(data: &[u8]) -> bool {
     false
 }
 
-// Scan directory for music files
-pub fn scan_music_directory(path: &str) -> Result<Vec<TrackStub>, Box<dyn std::error::Error>> {
-    let mut tracks = Vec::new();
-    let mut cover_cache = std::collections::HashMap::new();
-
-    for entry in WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file())
-    {
-        let path = entry.path();
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            let ext_lower = ext.to_lowercase();
-            if AUDIO_FORMATS.contains(&ext_lower.as_str()) {
-                let track_stub = match crate::metadata::TrackMetadata::from_file(path) {
-                    Ok(mut track) => {
-                        // If no cover from metadata, try to find in directory
-                        if track.cover.is_none() {
-                            if let Some(parent) = path.parent() {
-                                let cached = cover_cache.entry(parent.to_path_buf())
-                                    .or_insert_with(|| find_cover_image_in_dir(parent));
-                                track.cover = cached.clone();
-                            }
-                        }
-                        TrackStub::from(track)
-                    },
-                    Err(_) => {
-                        let cover = if let Some(parent) = path.parent() {
-                            cover_cache.entry(parent.to_path_buf())
-                                .or_insert_with(|| find_cover_image_in_dir(parent))
-                                .clone()
-                        } else {
-                            None
-                        };
-                        
-                        TrackStub {
-                            id: Uuid::new_v4().to_string(),
-                            path: path.to_string_lossy().to_string(),
-                            title: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string()),
-                            artist: "Unknown Artist".to_string(),
-                            album: "Unknown Album".to_string(),
-                            duration: Duration::from_secs(0),
-                            cover,
-                        }
-                    },
-                };
-                tracks.push(track_stub);
+// Read one local file's tags, falling back to its folder's cover image and,
+// if the tags can't be read at all, to its file name. Folder covers are looked
+// up once per folder through `covers`.
+fn read_local_track(path: &Path, covers: &mut std::collections::HashMap<std::path::PathBuf, Option<String>>) -> TrackStub {
+    let mut folder_cover = || {
+        path.parent()
+            .and_then(|parent| covers.entry(parent.to_path_buf()).or_insert_with(|| find_cover_image_in_dir(parent)).clone())
+    };
+    let stamp = FileStamp::of(path);
+    match crate::metadata::TrackMetadata::from_file(path) {
+        Ok(mut track) => {
+            if track.cover.is_none() {
+                track.cover = folder_cover();
             }
+            TrackStub { stamp, ..TrackStub::from(track) }
         }
+        Err(_) => TrackStub {
+            id: Uuid::new_v4().to_string(),
+            path: path.to_string_lossy().to_string(),
+            title: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string()),
+            artist: "Unknown Artist".to_string(),
+            album: "Unknown Album".to_string(),
+            album_artist: None,
+            track_number: None,
+            year: None,
+            genre: None,
+            duration: crate::duration::from_path(path).unwrap_or_default(),
+            cover: folder_cover(),
+            source: TrackSource::Local,
+            gain_db: 0.0,
+            added_at: None,
+            stamp,
+        },
     }
+}
 
+// Scan directory for music files, several at a time. Files `known` already
+// has, unchanged in size and modification time, are taken from there instead
+// of being read again.
+pub fn scan_music_directory(
+    path: &str,
+    known: &std::collections::HashMap<String, TrackStub>,
+) -> Result<Vec<TrackStub>, Box<dyn std::error::Error>> {
+    let not_cancelled = std::sync::atomic::AtomicBool::new(false);
+    let files = scanner::audio_files(path, &not_cancelled);
+    let mut tracks = Vec::with_capacity(files.len());
+    let reread = scanner::read_tracks(&files, known, &not_cancelled, |ready, _| tracks.extend(ready));
+
+    eprintln!("[Library] 扫描 {}: {} 首曲目，读取了 {} 个新的或已更改的文件", path, tracks.len(), reread);
     Ok(tracks)
 }
 
+// The tracks of one playlist by path, for a scan to take unchanged files from
+fn known_tracks(playlists: &[Playlist], idx: usize) -> std::collections::HashMap<String, TrackStub> {
+    playlists
+        .get(idx)
+        .map(|playlist| playlist.tracks.iter().map(|track| (track.path.clone(), track.clone())).collect())
+        .unwrap_or_default()
+}
+
+// Fresh reads of the local files behind `tracks` whose size or modification
+// time no longer match what was stamped when their tags were read, by path.
+// Files that are gone, and tracks inside archives, are left alone.
+pub fn reread_changed_tracks(tracks: &[TrackStub]) -> std::collections::HashMap<String, TrackStub> {
+    let mut covers = std::collections::HashMap::new();
+    let changed = tracks
+        .iter()
+        .filter(|track| track.source == TrackSource::Local && archive::split_path(&track.path).is_none())
+        .filter_map(|track| {
+            let path = Path::new(&track.path);
+            let stamp = FileStamp::of(path)?;
+            (track.stamp != Some(stamp)).then(|| (track.path.clone(), read_local_track(path, &mut covers)))
+        })
+        .collect();
+    metadata_cache::flush();
+    changed
+}
+
+// Tracks for the audio files inside a zip archive, played straight from it.
+// Tags aren't read until a track plays, so titles come from the file names.
+pub fn scan_archive(path: &Path) -> Result<Vec<TrackStub>, Box<dyn std::error::Error>> {
+    use std::io::Read;
+    let entries = archive::list(path)?;
+    let album = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown Album".to_string());
+    let cover = entries
+        .iter()
+        .find(|entry| file_watcher::COVER_FILENAMES.contains(&entry.file_name().to_lowercase().as_str()))
+        .and_then(|entry| {
+            let mut data = Vec::new();
+            archive::open_entry(path, &entry.name).ok()?.1.read_to_end(&mut data).ok()?;
+            is_valid_image(&data).then(|| cover_cache::store(&data)).flatten()
+        });
+
+    Ok(entries
+        .iter()
+        .filter(|entry| !entry.is_dir() && is_audio_file(entry.file_name()))
+        .map(|entry| TrackStub {
+            id: Uuid::new_v4().to_string(),
+            path: archive::entry_path(path, &entry.name),
+            title: Path::new(entry.file_name()).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| entry.file_name().to_string()),
+            artist: "Unknown Artist".to_string(),
+            album: album.clone(),
+            album_artist: None,
+            track_number: None,
+            year: None,
+            genre: None,
+            duration: Duration::from_secs(0),
+            cover: cover.clone(),
+            source: TrackSource::Local,
+            gain_db: 0.0,
+            added_at: None,
+            stamp: None,
+        })
+        .collect())
+}
+
 // Save all playlists to a directory
 pub fn save_all_playlists(
     playlists: &[Playlist],
@@ -2200,9 +9075,45 @@ pub fn save_all_playlists(
     Ok(())
 }
 
+// Tray menu ids of the settings profile entries, followed by the profile id
+const TRAY_PROFILE_PREFIX: &str = "profile:";
+
+// The tray icon, with the app's icon; None where the desktop has no tray
+fn build_tray_icon() -> Option<dioxus_desktop::trayicon::TrayIcon> {
+    use dioxus_desktop::trayicon::{Icon, TrayIconBuilder};
+    let image = image::load_from_memory_with_format(include_bytes!("../assets/rmusic.ico"), image::ImageFormat::Ico).ok()?;
+    let (width, height) = (image.width(), image.height());
+    let icon = Icon::from_rgba(image.to_rgba8().into_raw(), width, height).ok()?;
+    TrayIconBuilder::new()
+        .with_icon(icon)
+        .with_tooltip("Dioxus Music Player")
+        .build()
+        .map_err(|e| eprintln!("[Tray] 无法创建托盘图标: {}", e))
+        .ok()
+}
+
+// One entry per settings profile, the active one checked; disabled while locked
+fn profiles_tray_menu(profiles: &[SettingsProfile], active: Option<&str>, locked: bool) -> dioxus_desktop::trayicon::menu::Menu {
+    use dioxus_desktop::trayicon::menu::{CheckMenuItem, Menu, MenuItem};
+    let menu = Menu::new();
+    let appended = if profiles.is_empty() {
+        menu.append(&MenuItem::new("No settings profiles", false, None))
+    } else {
+        profiles.iter().try_for_each(|profile| {
+            let id = format!("{}{}", TRAY_PROFILE_PREFIX, profile.id);
+            let active = active == Some(profile.id.as_str());
+            menu.append(&CheckMenuItem::with_id(id, &profile.name, !locked, active, None))
+        })
+    };
+    if let Err(e) = appended {
+        eprintln!("[Tray] 无法生成托盘菜单: {}", e);
+    }
+    menu
+}
+
 // Load all playlists from a directory
-pub fn load_all_playlists(dir: &str) -> Result<Vec<Playlist>, Box<dyn std::error::Error>> {
-    Playlist::load_multiple_from_dir(dir)
+pub fn load_all_playlists(dir: &str, servers: &[WebDAVConfig]) -> Result<Vec<Playlist>, Box<dyn std::error::Error>> {
+    Playlist::load_multiple_from_dir(dir, servers)
 }
 
 #[component]
@@ -2210,9 +9121,14 @@ fn DirectoryBrowserModal(
     current_directory: String,
     on_close: EventHandler<()>,
     on_load_directory: EventHandler<String>,
+    on_watch_directory: EventHandler<String>,
+    on_add_tracks: EventHandler<Vec<TrackStub>>,
 ) -> Element {
     let mut selected_path = use_signal(|| current_directory.clone());
     let mut is_loading = use_signal(|| false);
+    // A zip album being looked into, and what's in it
+    let mut archive_listing = use_signal(|| None::<(std::path::PathBuf, Vec<archive::ArchiveEntry>)>);
+    let mut archive_error = use_signal(|| None::<String>);
 
     rsx! {
         div {
@@ -2233,8 +9149,24 @@ fn DirectoryBrowserModal(
                     }
                 }
 
+                if let Some((_, entries)) = archive_listing() {
+                    div { class: "max-h-64 overflow-y-auto bg-gray-900 rounded p-3 mb-4 space-y-1",
+                        for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+                            div {
+                                key: "{entry.name}",
+                                class: if is_audio_file(entry.file_name()) { "flex justify-between text-sm" } else { "flex justify-between text-sm text-gray-500" },
+                                span { class: "truncate", if is_audio_file(entry.file_name()) { "🎵 " } else { "📄 " } "{entry.name}" }
+                                span { class: "text-xs text-gray-400", "{format_bytes(entry.size)}" }
+                            }
+                        }
+                    }
+                }
+                if let Some(error) = archive_error() {
+                    p { class: "text-sm text-red-400 mb-4", "{error}" }
+                }
+
                 div { class: "text-xs text-gray-400 p-3 bg-gray-900 rounded mb-4",
-                    "Supported formats: MP3, WAV, FLAC, OGG, M4A"
+                    "Supported formats: MP3, WAV, FLAC, OGG, M4A. Zip albums play straight from the archive."
                 }
 
                 div { class: "flex gap-4 justify-end",
@@ -2245,32 +9177,125 @@ fn DirectoryBrowserModal(
                         "Cancel"
                     }
                     button {
-                        class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded disabled:opacity-50",
+                        class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded disabled:opacity-50",
+                        title: "Look inside a .zip album without unpacking it",
                         disabled: is_loading(),
                         onclick: move |_| {
-                            *is_loading.write() = true;
-                            let handler = on_load_directory.clone();
                             spawn(async move {
-                                if let Some(path) = rfd::AsyncFileDialog::new().pick_folder().await {
-                                    if let Some(path_str) = path.path().to_str() {
-                                        *selected_path.write() = path_str.to_string();
-                                        handler.call(path_str.to_string());
+                                let Some(file) = rfd::AsyncFileDialog::new().add_filter("Zip archive", &["zip"]).pick_file().await else {
+                                    return;
+                                };
+                                let path = file.path().to_path_buf();
+                                *selected_path.write() = path.to_string_lossy().to_string();
+                                let listed = tokio::task::spawn_blocking({
+                                    let path = path.clone();
+                                    move || archive::list(&path)
+                                })
+                                .await;
+                                match listed {
+                                    Ok(Ok(entries)) => {
+                                        archive_listing.set(Some((path, entries)));
+                                        archive_error.set(None);
+                                    }
+                                    Ok(Err(e)) => {
+                                        archive_listing.set(None);
+                                        archive_error.set(Some(format!("Couldn't read {}: {}", file.file_name(), e)));
                                     }
+                                    Err(_) => archive_listing.set(None),
                                 }
-                                *is_loading.write() = false;
                             });
                         },
-                        if is_loading() {
-                            "Loading..."
-                        } else {
-                            "📂 Browse Folder"
-                        }
+                        "🗜 Open Zip"
                     }
-                    button {
-                        class: "px-4 py-2 bg-green-600 hover:bg-green-700 rounded disabled:opacity-50",
-                        disabled: selected_path().is_empty() || is_loading(),
-                        onclick: move |_| on_load_directory.call(selected_path()),
-                        "✓ Load Music"
+                    if let Some((archive_path, _)) = archive_listing() {
+                        button {
+                            class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded disabled:opacity-50",
+                            title: "Unpack the audio, covers and lyrics into a folder next to the zip and add that folder instead",
+                            disabled: is_loading(),
+                            onclick: {
+                                let archive_path = archive_path.clone();
+                                move |_| {
+                                    let archive_path = archive_path.clone();
+                                    is_loading.set(true);
+                                    spawn(async move {
+                                        let dest = archive_path.with_extension("");
+                                        let unpacked = tokio::task::spawn_blocking({
+                                            let dest = dest.clone();
+                                            move || {
+                                                archive::extract_all(&archive_path, &dest, &|name| {
+                                                    let lower = name.to_lowercase();
+                                                    is_audio_file(name)
+                                                        || file_watcher::COVER_FILENAMES.contains(&lower.as_str())
+                                                        || [".lrc", ".txt", ".cue"].iter().any(|ext| lower.ends_with(ext))
+                                                })
+                                            }
+                                        })
+                                        .await;
+                                        is_loading.set(false);
+                                        match unpacked {
+                                            Ok(Ok(extracted)) => {
+                                                eprintln!("[Archive] 已解压 {} 个文件到 {}", extracted.files.len(), dest.display());
+                                                for name in &extracted.skipped {
+                                                    eprintln!("[Archive] 跳过不安全的路径: {}", name);
+                                                }
+                                                on_load_directory.call(dest.to_string_lossy().to_string());
+                                            }
+                                            Ok(Err(e)) => archive_error.set(Some(format!("Couldn't extract: {}", e))),
+                                            Err(_) => archive_error.set(Some("Extraction stopped unexpectedly".to_string())),
+                                        }
+                                    });
+                                }
+                            },
+                            "📦 Extract to library"
+                        }
+                        button {
+                            class: "px-4 py-2 bg-green-600 hover:bg-green-700 rounded disabled:opacity-50",
+                            disabled: is_loading(),
+                            onclick: {
+                                let archive_path = archive_path.clone();
+                                move |_| match scan_archive(&archive_path) {
+                                    Ok(tracks) => on_add_tracks.call(tracks),
+                                    Err(e) => archive_error.set(Some(format!("Couldn't read the archive: {}", e))),
+                                }
+                            },
+                            "✓ Add from Zip"
+                        }
+                    } else {
+                        button {
+                            class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded disabled:opacity-50",
+                            disabled: is_loading(),
+                            onclick: move |_| {
+                                *is_loading.write() = true;
+                                let handler = on_load_directory.clone();
+                                spawn(async move {
+                                    if let Some(path) = rfd::AsyncFileDialog::new().pick_folder().await {
+                                        if let Some(path_str) = path.path().to_str() {
+                                            *selected_path.write() = path_str.to_string();
+                                            handler.call(path_str.to_string());
+                                        }
+                                    }
+                                    *is_loading.write() = false;
+                                });
+                            },
+                            if is_loading() {
+                                "Loading..."
+                            } else {
+                                "📂 Browse Folder"
+                            }
+                        }
+                        button {
+                            class: "px-4 py-2 bg-green-600 hover:bg-green-700 rounded disabled:opacity-50",
+                            disabled: selected_path().is_empty() || is_loading(),
+                            onclick: move |_| on_load_directory.call(selected_path()),
+                            "✓ Load Music"
+                        }
+                        button {
+                            class: "px-4 py-2 bg-gray-700 hover:bg-gray-600 rounded disabled:opacity-50",
+                            title: "Load the folder and keep the playlist up to date as files are added, renamed or deleted in it",
+                            disabled: selected_path().is_empty() || is_loading(),
+                            onclick: move |_| on_watch_directory.call(selected_path()),
+                            "👁 Watch Folder"
+                        }
                     }
                 }
             }
@@ -2282,6 +9307,12 @@ fn DirectoryBrowserModal(
 fn WebDAVConfigListModal(
     configs: Vec<WebDAVConfig>,
     current_config: Option<usize>,
+    // Cap on the cache of played remote tracks, and how much of it is in use
+    track_cache_mb: u32,
+    track_cache_used: u64,
+    on_track_cache_change: EventHandler<u32>,
+    download_connections: usize,
+    on_download_connections_change: EventHandler<usize>,
     on_close: EventHandler<()>,
     on_add_config: EventHandler<()>,
     on_edit_config: EventHandler<usize>,
@@ -2323,9 +9354,9 @@ fn WebDAVConfigListModal(
                                     p { class: "text-xs text-gray-300 truncate", "{config.url}" }
                                     div { class: "text-xs mt-1",
                                         if config.enabled {
-                                            span { class: "text-green-400", "✓ Enabled" }
+                                            StatusBadge { status: Status::Ok, label: "Enabled" }
                                         } else {
-                                            span { class: "text-gray-400", "○ Disabled" }
+                                            StatusBadge { status: Status::Off, label: "Disabled" }
                                         }
                                     }
                                 }
@@ -2347,6 +9378,45 @@ fn WebDAVConfigListModal(
                     }
                 }
 
+                label {
+                    class: "flex items-center gap-2 text-sm text-gray-300 mb-4",
+                    title: "Tracks played from a server are kept up to this size, so they replay without downloading and play offline; the least recently played go first",
+                    "Keep up to"
+                    input {
+                        r#type: "number",
+                        min: "0",
+                        step: "256",
+                        class: "w-24 px-2 py-1 bg-gray-700 rounded",
+                        value: "{track_cache_mb}",
+                        onchange: move |e| {
+                            if let Ok(mb) = e.value().parse::<u32>() {
+                                on_track_cache_change.call(mb);
+                            }
+                        },
+                    }
+                    "MB of played tracks for offline use"
+                    span { class: "text-xs text-gray-400", "({format_bytes(track_cache_used)} in use)" }
+                }
+
+                label {
+                    class: "flex items-center gap-2 text-sm text-gray-300 mb-4",
+                    title: "Large files are downloaded a range at a time over this many connections, which helps most with distant or slow-to-answer servers; 1 downloads over a single connection",
+                    "Download large files over"
+                    input {
+                        r#type: "number",
+                        min: "1",
+                        max: "16",
+                        class: "w-16 px-2 py-1 bg-gray-700 rounded",
+                        value: "{download_connections}",
+                        onchange: move |e| {
+                            if let Ok(connections) = e.value().parse::<usize>() {
+                                on_download_connections_change.call(connections.clamp(1, 16));
+                            }
+                        },
+                    }
+                    "connections at once"
+                }
+
                 div { class: "flex gap-4 justify-between",
                     button {
                         class: "px-4 py-2 bg-gray-600 hover:bg-gray-700 rounded",
@@ -2375,9 +9445,56 @@ fn WebDAVConfigModal(
     let mut username = use_signal(|| config.username.clone());
     let mut password = use_signal(|| config.get_password().unwrap_or_default());
     let mut enabled = use_signal(|| config.enabled);
+    let mut download_limit_kbps = use_signal(|| config.download_limit_kbps);
+    let mut auth_kind = use_signal(|| {
+        match config.auth {
+            WebDAVAuth::Basic => "basic",
+            WebDAVAuth::Bearer => "bearer",
+            WebDAVAuth::OAuth2 { .. } => "oauth2",
+        }
+        .to_string()
+    });
+    let (initial_token_url, initial_client_id) = match &config.auth {
+        WebDAVAuth::OAuth2 { token_url, client_id, .. } => (token_url.clone(), client_id.clone()),
+        _ => (String::new(), String::new()),
+    };
+    let mut token_url = use_signal(|| initial_token_url);
+    let mut client_id = use_signal(|| initial_client_id);
+    let mut client_secret = use_signal(|| config.auth.client_secret().unwrap_or_default());
     let mut test_status = use_signal(|| Option::<Result<bool, String>>::None);
     let mut is_testing = use_signal(|| false);
 
+    // The server as the form has it, for testing and saving
+    let form_config = use_callback(move |_: ()| {
+        let auth = match auth_kind().as_str() {
+            "bearer" => WebDAVAuth::Bearer,
+            "oauth2" => WebDAVAuth::oauth2(&token_url(), &client_id(), &client_secret()).unwrap_or_else(|e| {
+                eprintln!("加密客户端密钥失败: {}", e);
+                WebDAVAuth::OAuth2 { token_url: token_url(), client_id: client_id(), encrypted_client_secret: String::new() }
+            }),
+            _ => WebDAVAuth::Basic,
+        };
+        let mut new_config = WebDAVConfig {
+            id: config.id.clone(),
+            name: name(),
+            url: url(),
+            // Only Basic auth sends one
+            username: if matches!(auth, WebDAVAuth::Basic) { username() } else { String::new() },
+            encrypted_password: String::new(),
+            enabled: enabled(),
+            password: None,
+            synced_folders: config.synced_folders.clone(),
+            download_limit_kbps: download_limit_kbps(),
+            auth,
+        };
+        // Tokens pasted with a stray space or newline; passwords are taken as typed
+        let secret = if matches!(new_config.auth, WebDAVAuth::Basic) { password() } else { password().trim().to_string() };
+        if let Err(e) = new_config.set_password(&secret) {
+            eprintln!("加密密码失败: {}", e);
+        }
+        new_config
+    });
+
     rsx! {
         div {
             class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
@@ -2412,23 +9529,90 @@ fn WebDAVConfigModal(
                     }
 
                     div {
-                        label { class: "block text-sm font-semibold mb-2", "Username" }
-                        input {
+                        label { class: "block text-sm font-semibold mb-2", "Sign In With" }
+                        select {
                             class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
-                            placeholder: "Your username",
-                            value: username(),
-                            oninput: move |e| *username.write() = e.value(),
+                            value: "{auth_kind}",
+                            onchange: move |e: FormEvent| auth_kind.set(e.value()),
+                            option { value: "basic", "Username and password" }
+                            option { value: "bearer", "Access token (Bearer)" }
+                            option { value: "oauth2", "OAuth2 refresh token" }
                         }
                     }
 
-                    div {
-                        label { class: "block text-sm font-semibold mb-2", "Password" }
-                        input {
-                            r#type: "password",
-                            class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
-                            placeholder: "Your password",
-                            value: password(),
-                            oninput: move |e| *password.write() = e.value(),
+                    if auth_kind() == "basic" {
+                        div {
+                            label { class: "block text-sm font-semibold mb-2", "Username" }
+                            input {
+                                class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                placeholder: "Your username",
+                                value: username(),
+                                oninput: move |e| *username.write() = e.value(),
+                            }
+                        }
+
+                        div {
+                            label { class: "block text-sm font-semibold mb-2", "Password" }
+                            input {
+                                r#type: "password",
+                                class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                placeholder: "Your password",
+                                value: password(),
+                                oninput: move |e| *password.write() = e.value(),
+                            }
+                        }
+                    } else if auth_kind() == "bearer" {
+                        div {
+                            label { class: "block text-sm font-semibold mb-2", "Access Token" }
+                            input {
+                                r#type: "password",
+                                class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                placeholder: "Sent as Authorization: Bearer …",
+                                value: password(),
+                                oninput: move |e| *password.write() = e.value(),
+                            }
+                        }
+                    } else {
+                        div {
+                            label { class: "block text-sm font-semibold mb-2", "Token Endpoint" }
+                            input {
+                                class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                placeholder: "https://auth.example.com/oauth2/token",
+                                value: token_url(),
+                                oninput: move |e| *token_url.write() = e.value(),
+                            }
+                        }
+
+                        div { class: "grid grid-cols-2 gap-4",
+                            div {
+                                label { class: "block text-sm font-semibold mb-2", "Client ID" }
+                                input {
+                                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                    value: client_id(),
+                                    oninput: move |e| *client_id.write() = e.value(),
+                                }
+                            }
+                            div {
+                                label { class: "block text-sm font-semibold mb-2", "Client Secret" }
+                                input {
+                                    r#type: "password",
+                                    class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                    placeholder: "Leave empty for public clients",
+                                    value: client_secret(),
+                                    oninput: move |e| *client_secret.write() = e.value(),
+                                }
+                            }
+                        }
+
+                        div {
+                            label { class: "block text-sm font-semibold mb-2", "Refresh Token" }
+                            input {
+                                r#type: "password",
+                                class: "w-full px-4 py-2 rounded bg-gray-700 border border-gray-600 text-white",
+                                placeholder: "Traded for access tokens as they run out",
+                                value: password(),
+                                oninput: move |e| *password.write() = e.value(),
+                            }
                         }
                     }
 
@@ -2446,6 +9630,25 @@ fn WebDAVConfigModal(
                         }
                     }
 
+                    label {
+                        class: "flex items-center gap-2 text-sm text-gray-300",
+                        title: "Tracks downloading to play, the next track being fetched ahead and folder sync all share this limit, so listening doesn't take the whole connection; 0 for no limit",
+                        "Download at most"
+                        input {
+                            r#type: "number",
+                            min: "0",
+                            step: "128",
+                            class: "w-24 px-2 py-1 bg-gray-700 rounded",
+                            value: "{download_limit_kbps}",
+                            onchange: move |e| {
+                                if let Ok(kbps) = e.value().parse::<u32>() {
+                                    *download_limit_kbps.write() = kbps;
+                                }
+                            },
+                        }
+                        "KB/s (0 for no limit)"
+                    }
+
                     div { class: "flex items-center gap-3 pt-2",
                         button {
                             class: "px-4 py-2 bg-blue-600 hover:bg-blue-700 rounded disabled:opacity-50",
@@ -2454,17 +9657,10 @@ fn WebDAVConfigModal(
                                 *is_testing.write() = true;
                                 *test_status.write() = None;
 
-                                let test_url = url().clone();
-                                let test_username = username().clone();
-                                let test_password = password().clone();
+                                let test_config = form_config.call(());
 
                                 spawn(async move {
-                                    let result = test_webdav_connection(
-                                            &test_url,
-                                            &test_username,
-                                            &test_password,
-                                        )
-                                        .await;
+                                    let result = test_webdav_connection(&test_config).await;
                                     *test_status.write() = Some(result);
                                     *is_testing.write() = false;
                                 });
@@ -2477,9 +9673,13 @@ fn WebDAVConfigModal(
                         }
 
                         if let Some(Ok(_)) = test_status() {
-                            span { class: "text-green-400 font-semibold text-lg", "OK Available" }
+                            span { class: "font-semibold text-lg",
+                                StatusBadge { status: Status::Ok, label: "Available" }
+                            }
                         } else if let Some(Err(error_msg)) = test_status() {
-                            span { class: "text-red-400 font-semibold text-lg", "FAIL Unavailable" }
+                            span { class: "font-semibold text-lg",
+                                StatusBadge { status: Status::Error, label: "Unavailable" }
+                            }
                             div { class: "text-red-300 text-sm mt-1", "{error_msg}" }
                         }
                     }
@@ -2498,23 +9698,7 @@ fn WebDAVConfigModal(
                     button {
                         class: "px-4 py-2 bg-green-600 hover:bg-green-700 rounded disabled:opacity-50",
                         disabled: name().is_empty() || url().is_empty(),
-                        onclick: move |_| {
-                            let pwd = password();
-
-                            let mut new_config = WebDAVConfig {
-                                id: config.id.clone(),
-                                name: name(),
-                                url: url(),
-                                username: username(),
-                                encrypted_password: String::new(),
-                                enabled: enabled(),
-                                password: None,
-                            };
-                            if let Err(e) = new_config.set_password(&pwd) {
-                                eprintln!("加密密码失败: {}", e);
-                            }
-                            on_save_config.call(new_config);
-                        },
+                        onclick: move |_| on_save_config.call(form_config.call(())),
                         "✓ Add Server"
                     }
                 }
@@ -2524,9 +9708,9 @@ fn WebDAVConfigModal(
 }
 
 // Test WebDAV connection availability
-async fn test_webdav_connection(url: &str, username: &str, password: &str) -> Result<bool, String> {
-    use base64::{engine::general_purpose, Engine as _};
-    
+async fn test_webdav_connection(config: &WebDAVConfig) -> Result<bool, String> {
+    let url = config.url.as_str();
+
     // Validate URL format
     let parsed_url = match reqwest::Url::parse(url) {
         Ok(u) => u,
@@ -2538,10 +9722,12 @@ async fn test_webdav_connection(url: &str, username: &str, password: &str) -> Re
         return Err("URL必须以 http:// 或 https:// 开头".to_string());
     }
     
-    // Prepare authorization header
-    let auth_str = format!("{}:{}", username, password);
-    let encoded = general_purpose::STANDARD.encode(auth_str.as_bytes());
-    let auth_header = format!("Basic {}", encoded);
+    let credentials = config.credentials().map_err(|e| format!("认证信息无效: {}", e))?;
+    // A refresh token the provider turns down would otherwise only show as a 401
+    if let Some(webdav::Credentials::OAuth2(session)) = &credentials {
+        let session = session.clone();
+        tokio::task::spawn_blocking(move || session.access_token()).await.map_err(|e| e.to_string())??;
+    }
 
     // Try to make a PROPFIND request to test connection
     let client = reqwest::Client::builder()
@@ -2558,14 +9744,15 @@ async fn test_webdav_connection(url: &str, username: &str, password: &str) -> Re
   </D:prop>
 </D:propfind>"#;
     
-    let result = client
+    let mut request = client
         .request(reqwest::Method::from_bytes(b"PROPFIND").unwrap(), url)
-        .header("Authorization", &auth_header)
         .header("Depth", "0")
         .header("Content-Type", "application/xml; charset=\"utf-8\"")
-        .body(propfind_body.to_string())
-        .send()
-        .await;
+        .body(propfind_body.to_string());
+    if let Some(credentials) = &credentials {
+        request = credentials.authorize(request).await;
+    }
+    let result = request.send().await;
 
     match result {
         Ok(response) => {
@@ -2647,6 +9834,9 @@ fn load_webdav_configs() -> Result<Vec<WebDAVConfig>, Box<dyn std::error::Error>
                     encrypted_password: String::new(),
                     enabled: old.enabled,
                     password: None,
+                    synced_folders: Vec::new(),
+                    download_limit_kbps: 0,
+                    auth: WebDAVAuth::Basic,
                 };
                 let _ = config.set_password(&password_str);
                 new_configs.push(config);
@@ -2693,8 +9883,330 @@ fn save_webdav_configs(configs: &[WebDAVConfig]) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+fn load_bandcamp_account() -> Result<bandcamp::BandcampAccount, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("bandcamp.json");
+    if !config_file.exists() {
+        return Ok(bandcamp::BandcampAccount::default());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_bandcamp_account(account: &bandcamp::BandcampAccount) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("bandcamp.json");
+    eprintln!("[Config] 保存Bandcamp账户到: {}", config_file.display());
+    std::fs::write(config_file, serde_json::to_string_pretty(account)?)?;
+    Ok(())
+}
+
 // Get config directory
+fn load_settings_profiles() -> Result<Vec<SettingsProfile>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("settings_profiles.json");
+    if !config_file.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_settings_profiles(profiles: &[SettingsProfile]) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("settings_profiles.json");
+    eprintln!("[Config] 保存设置配置到: {}", config_file.display());
+
+    let json = serde_json::to_string_pretty(profiles)?;
+    std::fs::write(config_file, json)?;
+
+    Ok(())
+}
+
+fn load_ui_settings() -> Result<UiSettings, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("ui_settings.json");
+    if !config_file.exists() {
+        return Ok(UiSettings::default());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_ui_settings(settings: &UiSettings) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("ui_settings.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(settings)?)?;
+    Ok(())
+}
+
+// Carry what's stored by track path over to the files' new paths after a move
+fn rekey_moved_paths<V>(map: &mut std::collections::HashMap<String, V>, moved: &std::collections::HashMap<String, String>) {
+    for (from, to) in moved {
+        if let Some(value) = map.remove(from) {
+            map.insert(to.clone(), value);
+        }
+    }
+}
+
+fn load_play_history() -> Result<Vec<PlayHistoryEntry>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("play_history.json");
+    if !config_file.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_play_history(entries: &[PlayHistoryEntry]) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("play_history.json");
+    std::fs::write(config_file, serde_json::to_string(entries)?)?;
+    Ok(())
+}
+
+fn load_recently_played() -> Result<Vec<RecentPlay>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("recently_played.json");
+    if !config_file.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_recently_played(entries: &[RecentPlay]) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("recently_played.json");
+    std::fs::write(config_file, serde_json::to_string(entries)?)?;
+    Ok(())
+}
+
+// Star ratings (1-5) by file_key, shared by every playlist the track is in
+fn load_track_ratings() -> Result<std::collections::HashMap<String, u8>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_ratings.json");
+    if !config_file.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_track_ratings(ratings: &std::collections::HashMap<String, u8>) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_ratings.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(ratings)?)?;
+    Ok(())
+}
+
+// Favorite track paths, shared by every playlist the track is in
+fn load_favorite_tracks() -> Result<std::collections::HashSet<String>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("favorite_tracks.json");
+    if !config_file.exists() {
+        return Ok(std::collections::HashSet::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_favorite_tracks(favorites: &std::collections::HashSet<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("favorite_tracks.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(favorites)?)?;
+    Ok(())
+}
+
+// Integrated loudness (LUFS) by track path, shared by every playlist the track is in
+fn load_track_loudness() -> Result<std::collections::HashMap<String, f64>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_loudness.json");
+    if !config_file.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_track_loudness(loudness: &std::collections::HashMap<String, f64>) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_loudness.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(loudness)?)?;
+    Ok(())
+}
+
+// Chapters by track path, for long mixes split with a tracklist or CUE sheet
+fn load_track_chapters() -> Result<std::collections::HashMap<String, Vec<chapters::Chapter>>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_chapters.json");
+    if !config_file.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_track_chapters(chapters: &std::collections::HashMap<String, Vec<chapters::Chapter>>) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("track_chapters.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(chapters)?)?;
+    Ok(())
+}
+
+fn load_playback_session() -> Result<PlaybackSession, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("playback_session.json");
+    if !config_file.exists() {
+        return Ok(PlaybackSession::default());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_playback_session(session: &PlaybackSession) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("playback_session.json");
+    std::fs::write(config_file, serde_json::to_string_pretty(session)?)?;
+    Ok(())
+}
+
+// Playlists live only in memory while the app runs; the session keeps a copy so a
+// restored track still has its playlist around it
+// Everything read from disk at startup that the first frame can do without
+struct LoadedLibrary {
+    playlists: Vec<Playlist>,
+    webdav_configs: Vec<WebDAVConfig>,
+    play_history: Vec<PlayHistoryEntry>,
+    recently_played: Vec<RecentPlay>,
+    track_ratings: std::collections::HashMap<String, u8>,
+    favorite_tracks: std::collections::HashSet<String>,
+    track_chapters: std::collections::HashMap<String, Vec<chapters::Chapter>>,
+    track_loudness: std::collections::HashMap<String, f64>,
+}
+
+fn load_library() -> LoadedLibrary {
+    if let Ok(dir) = get_config_dir() {
+        resume::load(&dir);
+    }
+    // Before the playlists, whose older saves are matched against the servers
+    let webdav_configs = load_webdav_configs().unwrap_or_default();
+    let playlists = load_session_playlists(&webdav_configs);
+    let mut track_ratings = load_track_ratings().unwrap_or_default();
+    let mut favorite_tracks = load_favorite_tracks().unwrap_or_default();
+    adopt_webdav_keys(&playlists, &mut track_ratings, &mut favorite_tracks);
+    LoadedLibrary {
+        playlists,
+        webdav_configs,
+        play_history: load_play_history().unwrap_or_default(),
+        recently_played: load_recently_played().unwrap_or_default(),
+        track_ratings,
+        favorite_tracks,
+        track_chapters: load_track_chapters().unwrap_or_default(),
+        track_loudness: load_track_loudness().unwrap_or_default(),
+    }
+}
+
+// Ratings and favorites of WebDAV tracks used to be kept by the bare path;
+// they move to the file_key of the first track with that path
+fn adopt_webdav_keys(
+    playlists: &[Playlist],
+    ratings: &mut std::collections::HashMap<String, u8>,
+    favorites: &mut std::collections::HashSet<String>,
+) {
+    let webdav = playlists.iter().flat_map(|p| p.tracks.iter()).filter(|t| matches!(t.source, TrackSource::WebDAV { .. }));
+    for track in webdav {
+        if let Some(stars) = ratings.remove(&track.path) {
+            ratings.entry(track.file_key()).or_insert(stars);
+        }
+        if favorites.remove(&track.path) {
+            favorites.insert(track.file_key());
+        }
+    }
+}
+
+fn load_session_playlists(servers: &[WebDAVConfig]) -> Vec<Playlist> {
+    // An unreadable file is moved aside, as the placeholder below gets saved over it
+    let saved = get_config_dir()
+        .ok()
+        .and_then(|dir| match playlist::schema::load_file(&dir.join("session_playlists.json"), servers) {
+            Ok(loaded) => loaded.map(|loaded| loaded.playlists),
+            Err(e) => {
+                eprintln!("[Config] 无法读取播放列表: {}", e);
+                None
+            }
+        })
+        .unwrap_or_default();
+
+    if saved.is_empty() {
+        return vec![Playlist::new("My Playlist".to_string())];
+    }
+    saved
+}
+
+fn save_session_playlists(playlists: &[Playlist]) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("session_playlists.json");
+    eprintln!("[Config] 保存播放列表到: {}", config_file.display());
+    std::fs::write(config_file, playlist::schema::to_json(playlists, false)?)?;
+    Ok(())
+}
+
+// The tracks each playlist (by id) had after its last merge: what both copies
+// agreed on, so a later merge can tell a removal from the other side's addition
+fn load_merge_bases() -> Result<std::collections::HashMap<String, Vec<TrackStub>>, Box<dyn std::error::Error>> {
+    let config_file = get_config_dir()?.join("playlist_merge_bases.json");
+    if !config_file.exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_merge_base(playlist_id: &str, tracks: &[TrackStub]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bases = load_merge_bases().unwrap_or_default();
+    bases.insert(playlist_id.to_string(), tracks.to_vec());
+    let config_file = get_config_dir()?.join("playlist_merge_bases.json");
+    std::fs::write(config_file, serde_json::to_string(&bases)?)?;
+    Ok(())
+}
+
+fn forget_merge_base(playlist_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bases = load_merge_bases()?;
+    if bases.remove(playlist_id).is_some() {
+        let config_file = get_config_dir()?.join("playlist_merge_bases.json");
+        std::fs::write(config_file, serde_json::to_string(&bases)?)?;
+    }
+    Ok(())
+}
+
+// The copy of `playlist` in a file from another machine: a single exported
+// playlist or a whole session_playlists.json, matched by id and then by name
+fn read_playlist_copy(path: &Path, playlist: &Playlist, servers: &[WebDAVConfig]) -> Result<Playlist, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let candidates = playlist::schema::from_json(&content, servers)
+        .map_err(|e| format!("not a playlist file ({})", e))?
+        .playlists;
+    let copy = candidates
+        .iter()
+        .find(|p| p.id == playlist.id)
+        .or_else(|| candidates.iter().find(|p| p.name == playlist.name))
+        .cloned()
+        .ok_or_else(|| format!("no copy of \"{}\" in it", playlist.name))?;
+    Ok(copy)
+}
+
+// An exported playlist matched against the library, waiting to be created
+#[derive(Clone, PartialEq)]
+struct PendingImport {
+    // For the new playlist: the file name without its extension
+    name: String,
+    source: String,
+    // Songs listed in the file
+    total: usize,
+    matched: playlist::import::Matched,
+}
+
+#[derive(Clone, PartialEq)]
+struct PendingMerge {
+    playlist_id: String,
+    playlist_name: String,
+    // File the other copy came from
+    source: String,
+    // False when the playlists were never merged before, so nothing counts as removed
+    has_base: bool,
+    result: playlist::merge::MergeResult,
+}
+
+// The active user profile's folder, where its library and settings live
 fn get_config_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    match profiles::active_dir() {
+        Some(dir) => Ok(dir),
+        None => base_config_dir(),
+    }
+}
+
+fn base_config_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
     // Cross-platform config directory
     if let Some(appdata) = std::env::var_os("APPDATA") {
         // Windows: %APPDATA%
@@ -2723,13 +10235,17 @@ fn get_config_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
 fn WebDAVBrowserModal(
     config: WebDAVConfig,
     on_close: EventHandler<()>,
-    on_import_folder: EventHandler<Vec<Track>>,
+    on_import_folder: EventHandler<(Vec<Track>, Vec<ImportFailure>)>,
 ) -> Element {
     let config = use_signal(|| config.clone());
     let mut current_path = use_signal(|| "/".to_string());
     let mut items = use_signal(|| Vec::new());
-    let mut selected_items = use_signal(|| Vec::new());
+    let mut selected_items = use_signal(Vec::<webdav::WebDAVItem>::new);
+    // Checked folders (browser paths) and the audio files found beneath them
+    let mut selected_folders = use_signal(Vec::<String>::new);
+    let mut folder_files = use_signal(std::collections::HashMap::<String, Vec<webdav::WebDAVItem>>::new);
     let mut is_loading = use_signal(|| false);
+    let mut is_importing = use_signal(|| false);
     let mut error_msg = use_signal(|| Option::<String>::None);
 
     // Load root directory on mount
@@ -2752,6 +10268,66 @@ fn WebDAVBrowserModal(
         });
     });
 
+    let mut toggle_folder = move |folder_path: String, checked: bool| {
+        if !checked {
+            selected_folders.write().retain(|p| p != &folder_path);
+            return;
+        }
+        if selected_folders.read().contains(&folder_path) {
+            return;
+        }
+        selected_folders.write().push(folder_path.clone());
+        if folder_files.read().contains_key(&folder_path) {
+            return;
+        }
+        let cfg = config();
+        spawn(async move {
+            match list_webdav_audio_recursive(&cfg, &folder_path).await {
+                Ok(files) => {
+                    folder_files.write().insert(folder_path, files);
+                }
+                Err(e) => {
+                    *error_msg.write() = Some(format!("扫描文件夹失败: {}", e));
+                    selected_folders.write().retain(|p| p != &folder_path);
+                }
+            }
+        });
+    };
+
+    // Everything that will be imported: checked files plus the contents of checked folders
+    let import_files: Vec<webdav::WebDAVItem> = {
+        let mut files = selected_items();
+        let scanned = folder_files.read();
+        for folder in selected_folders.read().iter() {
+            if let Some(contents) = scanned.get(folder) {
+                files.extend(contents.iter().cloned());
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        files.retain(|f| seen.insert(f.path.clone()));
+        files
+    };
+    let scanning_count = selected_folders
+        .read()
+        .iter()
+        .filter(|p| !folder_files.read().contains_key(*p))
+        .count();
+    let import_count = import_files.len();
+    let import_bytes: u64 = import_files.iter().map(|f| f.size).sum();
+    let all_selected = {
+        let path = current_path();
+        let folders = selected_folders.read();
+        let files = selected_items.read();
+        !items.read().is_empty()
+            && items.read().iter().all(|item| {
+                if item.is_dir {
+                    folders.contains(&child_webdav_path(&path, &item.name))
+                } else {
+                    files.iter().any(|f| f.path == item.path)
+                }
+            })
+    };
+
     rsx! {
         div {
             class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
@@ -2783,76 +10359,129 @@ fn WebDAVBrowserModal(
                 } else if items().is_empty() {
                     div { class: "text-center py-8 text-gray-400", "No items found" }
                 } else {
+                    div { class: "flex items-center gap-2 mb-2 text-sm",
+                        input {
+                            r#type: "checkbox",
+                            id: "webdav-select-all",
+                            checked: all_selected,
+                            onchange: move |e| {
+                                let checked = e.checked();
+                                let path = current_path();
+                                for item in items() {
+                                    if item.is_dir {
+                                        toggle_folder(child_webdav_path(&path, &item.name), checked);
+                                    } else {
+                                        let mut sel = selected_items.write();
+                                        sel.retain(|f| f.path != item.path);
+                                        if checked {
+                                            sel.push(item);
+                                        }
+                                    }
+                                }
+                            },
+                        }
+                        label { r#for: "webdav-select-all", class: "text-sm", "Select all / none" }
+                    }
                     div { class: "space-y-1 mb-4 max-h-48 overflow-y-auto",
-                        for (idx , item) in items().into_iter().enumerate() {
-                            div {
-                                key: "{idx}",
-                                class: "flex items-center justify-between p-2 rounded hover:bg-gray-600 cursor-pointer",
-
-                                div {
-                                    class: "flex-1",
-                                    onclick: move |_| {
-                                        if item.is_dir {
-                                            let mut path = current_path();
-                                            if !path.ends_with('/') {
-                                                path.push('/');
-                                            }
-                                            path.push_str(&item.name);
-
-                                            let cfg = config();
-                                            *current_path.write() = path.clone();
-                                            *is_loading.write() = true;
+                        {
+                            items()
+                                .into_iter()
+                                .enumerate()
+                                .map(|(idx, item)| {
+                                    let folder_path = child_webdav_path(&current_path(), &item.name);
+                                    let is_checked = if item.is_dir {
+                                        selected_folders.read().contains(&folder_path)
+                                    } else {
+                                        selected_items.read().iter().any(|f| f.path == item.path)
+                                    };
+                                    let folder_summary = if item.is_dir && is_checked {
+                                        Some(match folder_files.read().get(&folder_path) {
+                                            Some(files) => format_count(files.len(), "file", "files"),
+                                            None => "scanning...".to_string(),
+                                        })
+                                    } else {
+                                        None
+                                    };
+                                    let nav_item = item.clone();
+                                    let nav_path = folder_path.clone();
+                                    let check_item = item.clone();
+                                    rsx! {
+                                        div {
+                                            key: "{idx}",
+                                            class: "flex items-center justify-between p-2 rounded hover:bg-gray-600 cursor-pointer",
+
+                                            div {
+                                                class: "flex-1",
+                                                onclick: move |_| {
+                                                    if nav_item.is_dir {
+                                                        let path = nav_path.clone();
+                                                        let cfg = config();
+                                                        *current_path.write() = path.clone();
+                                                        *is_loading.write() = true;
+
+                                                        spawn(async move {
+                                                            match load_webdav_folder(&cfg, &path).await {
+                                                                Ok(folder_items) => {
+                                                                    *items.write() = folder_items;
+                                                                    *error_msg.write() = None;
+                                                                }
+                                                                Err(e) => {
+                                                                    *error_msg.write() = Some(format!("加载失败: {}", e));
+                                                                }
+                                                            }
+                                                            *is_loading.write() = false;
+                                                        });
+                                                    }
+                                                },
 
-                                            spawn(async move {
-                                                match load_webdav_folder(&cfg, &path).await {
-                                                    Ok(folder_items) => {
-                                                        *items.write() = folder_items;
-                                                        *error_msg.write() = None;
+                                                span { class: "text-lg mr-2",
+                                                    if item.is_dir {
+                                                        "📁"
+                                                    } else {
+                                                        "🎵"
                                                     }
-                                                    Err(e) => {
-                                                        *error_msg.write() = Some(format!("加载失败: {}", e));
+                                                }
+                                                span { "{item.name}" }
+                                                if !item.is_dir {
+                                                    span { class: "text-xs text-gray-400 ml-2",
+                                                        "({format_bytes(item.size)})"
                                                     }
                                                 }
-                                                *is_loading.write() = false;
-                                            });
-                                        }
-                                    },
-
-                                    span { class: "text-lg mr-2",
-                                        if item.is_dir {
-                                            "📁"
-                                        } else {
-                                            "🎵"
-                                        }
-                                    }
-                                    span { "{item.name}" }
-                                    if !item.is_dir {
-                                        span { class: "text-xs text-gray-400 ml-2",
-                                            "({item.size} bytes)"
-                                        }
-                                    }
-                                }
+                                                if let Some(summary) = folder_summary {
+                                                    span { class: "text-xs text-gray-400 ml-2", "({summary})" }
+                                                }
+                                            }
 
-                                if !item.is_dir {
-                                    input {
-                                        r#type: "checkbox",
-                                        checked: selected_items().contains(&item.path),
-                                        onchange: move |e| {
-                                            let mut sel = selected_items();
-                                            if e.checked() {
-                                                sel.push(item.path.clone());
-                                            } else {
-                                                sel.retain(|p| p != &item.path);
+                                            input {
+                                                r#type: "checkbox",
+                                                checked: is_checked,
+                                                onchange: move |e| {
+                                                    if check_item.is_dir {
+                                                        toggle_folder(folder_path.clone(), e.checked());
+                                                    } else {
+                                                        let mut sel = selected_items.write();
+                                                        sel.retain(|f| f.path != check_item.path);
+                                                        if e.checked() {
+                                                            sel.push(check_item.clone());
+                                                        }
+                                                    }
+                                                },
                                             }
-                                            *selected_items.write() = sel;
-                                        },
+                                        }
                                     }
-                                }
-                            }
+                                })
                         }
                     }
                 }
 
+                div { class: "text-xs text-gray-400 mb-2",
+                    if scanning_count > 0 {
+                        "Scanning {format_count(scanning_count, \"folder\", \"folders\")}... {format_count(import_count, \"file\", \"files\")}, {format_bytes(import_bytes)} so far"
+                    } else {
+                        "{format_count(import_count, \"file\", \"files\")}, {format_bytes(import_bytes)} will be imported"
+                    }
+                }
+
                 div { class: "flex gap-4 justify-between",
                     div { class: "flex gap-2",
                         if current_path() != "/" {
@@ -2899,25 +10528,31 @@ fn WebDAVBrowserModal(
                         }
                         button {
                             class: "px-4 py-2 bg-green-600 hover:bg-green-700 rounded disabled:opacity-50",
-                            disabled: selected_items().is_empty() || is_loading(),
+                            disabled: import_count == 0 || scanning_count > 0 || is_loading() || is_importing(),
                             onclick: move |_| {
-                                let selected = selected_items();
-                                if !selected.is_empty() {
+                                let paths: Vec<String> = import_files.iter().map(|f| f.path.clone()).collect();
+                                if !paths.is_empty() {
                                     let cfg = config();
-                                    let selected_clone = selected.clone();
+                                    *is_importing.write() = true;
 
                                     spawn(async move {
-                                        match download_and_import_webdav_files(&cfg, &selected_clone).await {
-                                            Ok(tracks) => {
-                                                on_import_folder.call(tracks);
+                                        match download_and_import_webdav_files(&cfg, &paths).await {
+                                            Ok(imported) => {
+                                                on_import_folder.call(imported);
+                                            }
+                                            Err(e) => {
+                                                *error_msg.write() = Some(format!("导入失败: {}", e));
                                             }
-                                            // Error handling
-                                            Err(_e) => {}
                                         }
+                                        *is_importing.write() = false;
                                     });
                                 }
                             },
-                            "✓ Import ({selected_items().len()})"
+                            if is_importing() {
+                                "🔄 Importing..."
+                            } else {
+                                "✓ Import ({import_count})"
+                            }
                         }
                     }
                 }
@@ -2928,29 +10563,9 @@ fn WebDAVBrowserModal(
 
 // Load WebDAV folder items
 async fn load_webdav_folder(config: &WebDAVConfig, path: &str) -> Result<Vec<webdav::WebDAVItem>, Box<dyn std::error::Error>> {
-    use webdav::WebDAVClient;
-
-    let password = if config.password.is_none() && !config.encrypted_password.is_empty() {
-        match config.get_password() {
-            Ok(p) => {
-                eprintln!("[WebDAV] 从加密密码解密: username={}, password_len={}", config.username, p.len());
-                p
-            }
-            Err(e) => {
-                eprintln!("[WebDAV] 解密失败: {}", e);
-                String::new()
-            }
-        }
-    } else {
-        config.get_password().unwrap_or_default()
-    };
-
     eprintln!("[WebDAV] 准备请求: url={}{}, user={}", config.url, path, config.username);
 
-    let client = WebDAVClient::new(config.url.clone())
-        .with_auth(config.username.clone(), password);
-    
-    let items = client.list_items(path).await?;
+    let items = browser_client(config).list_items(path).await?;
     
     // Filter to show only folders and audio files
     let filtered: Vec<webdav::WebDAVItem> = items
@@ -2961,12 +10576,78 @@ async fn load_webdav_folder(config: &WebDAVConfig, path: &str) -> Result<Vec<web
     Ok(filtered)
 }
 
+// Client for browsing a configured server
+fn browser_client(config: &WebDAVConfig) -> webdav::WebDAVClient {
+    let credentials = match config.credentials() {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            eprintln!("[WebDAV] 解密失败: {}", e);
+            None
+        }
+    };
+
+    webdav::WebDAVClient::new(config.url.clone()).with_credentials(credentials)
+}
+
+// Collect every audio file under a browser path, descending into subfolders
+async fn list_webdav_audio_recursive(
+    config: &WebDAVConfig,
+    path: &str,
+) -> Result<Vec<webdav::WebDAVItem>, Box<dyn std::error::Error>> {
+    let files = browser_client(config).list_tree(path, |_| true).await?;
+    Ok(files.into_iter().filter(|item| is_audio_file(&item.name)).collect())
+}
+
+// Browser path of an entry inside a WebDAV folder
+fn child_webdav_path(parent: &str, name: &str) -> String {
+    let mut path = parent.to_string();
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(name);
+    path
+}
+
 // Check if file is an audio file
 fn is_audio_file(filename: &str) -> bool {
     let lower = filename.to_lowercase();
     AUDIO_FORMATS.iter().any(|fmt| lower.ends_with(&format!(".{}", fmt)))
 }
 
+// One row of the sidebar's folder tree
+#[derive(Clone, Debug, PartialEq)]
+struct FolderEntry {
+    name: String,
+    path: String,
+    is_dir: bool,
+}
+
+// Subfolders, then audio files, each by name; hidden entries are left out
+fn list_folder(dir: &Path) -> std::io::Result<Vec<FolderEntry>> {
+    let mut entries: Vec<FolderEntry> = std::fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            let is_dir = path.is_dir();
+            (!name.starts_with('.') && (is_dir || is_audio_file(&name))).then(|| FolderEntry {
+                name,
+                path: path.to_string_lossy().to_string(),
+                is_dir,
+            })
+        })
+        .collect();
+    entries.sort_by_cached_key(|entry| (!entry.is_dir, entry.name.to_lowercase()));
+    Ok(entries)
+}
+
+// Cover thumbnail and audio file count shown for a folder in grid view
+#[derive(Clone, Default, PartialEq)]
+struct FolderPreview {
+    cover: Option<String>,
+    track_count: Option<usize>,
+}
+
 #[component]
 fn WebDAVSidebar(
     config: WebDAVConfig,
@@ -2974,19 +10655,79 @@ fn WebDAVSidebar(
     items: Vec<webdav::WebDAVItem>,
     is_loading: bool,
     error_msg: Option<String>,
+    // A whole-server scan is running
+    scanning: bool,
+    on_scan: EventHandler<()>,
+    // Where each of this server's synced folders is up to
+    sync_status: std::collections::HashMap<String, webdav_sync::FolderStatus>,
+    // A folder's item path and whether to keep it synced
+    on_sync_folder: EventHandler<(String, bool)>,
     on_navigate: EventHandler<String>,
     on_play_track: EventHandler<webdav::WebDAVItem>,
     on_close: EventHandler<()>,
 ) -> Element {
     let up_path = current_path.clone();
+    let mut grid_view = use_signal(|| false);
+    let click_mode = use_context::<Memo<ClickMode>>()();
+    let mut selected_item = use_signal(|| None::<String>);
+    let scroll = use_remembered_scroll(format!("webdav:{}:{}", config.id, current_path), !is_loading);
+    let DragState(mut drag_state) = use_context();
+    // Keyed by browser path; an entry exists once loading has been started
+    let mut folder_previews = use_signal(std::collections::HashMap::<String, FolderPreview>::new);
+
+    // Load previews for folders in view, one at a time so large libraries don't flood the server
+    let (watch_config, watch_path, watch_items) = (config.clone(), current_path.clone(), items.clone());
+    use_effect(use_reactive!(|(watch_config, watch_path, watch_items)| {
+        if !grid_view() {
+            return;
+        }
+        let pending: Vec<(String, String)> = watch_items
+            .iter()
+            .filter(|item| item.is_dir)
+            .map(|item| (child_webdav_path(&watch_path, &item.name), item.path.clone()))
+            .filter(|(key, _)| !folder_previews.peek().contains_key(key))
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+        for (key, _) in &pending {
+            folder_previews.write().insert(key.clone(), FolderPreview::default());
+        }
+        let config = watch_config.clone();
+        spawn(async move {
+            for (key, item_path) in pending {
+                let cover = match resolve_webdav_url(&config, &item_path) {
+                    Ok(dir_url) => find_cover_image_in_webdav(&config, &dir_url)
+                        .await
+                        .and_then(|id| cover_cache::small_data_url(&id)),
+                    Err(_) => None,
+                };
+                let track_count = load_webdav_folder(&config, &key)
+                    .await
+                    .ok()
+                    .map(|children| children.iter().filter(|c| !c.is_dir).count());
+                folder_previews.write().insert(key, FolderPreview { cover, track_count });
+            }
+        });
+    }));
+
     rsx! {
         div { class: "bg-gray-800 rounded-lg p-4 h-full flex flex-col overflow-hidden",
             div { class: "flex justify-between items-center mb-4 flex-shrink-0",
                 h3 { class: "text-lg font-bold truncate", "☁️ {config.name}" }
-                button {
-                    class: "text-gray-400 hover:text-white",
-                    onclick: move |_| on_close.call(()),
-                    "✕"
+                div { class: "flex items-center gap-2 flex-shrink-0",
+                    button {
+                        class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded text-xs disabled:opacity-50",
+                        title: "Add every audio file on this server to the current playlist, with tags read over the network",
+                        disabled: scanning,
+                        onclick: move |_| on_scan.call(()),
+                        if scanning { "Scanning…" } else { "🔍 Scan this server" }
+                    }
+                    button {
+                        class: "text-gray-400 hover:text-white",
+                        onclick: move |_| on_close.call(()),
+                        "✕"
+                    }
                 }
             }
 
@@ -3013,6 +10754,16 @@ fn WebDAVSidebar(
                 div { class: "px-2 py-1 bg-gray-700 rounded flex-1 truncate font-mono text-xs",
                     "{current_path}"
                 }
+                button {
+                    class: "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded",
+                    title: if grid_view() { "List view" } else { "Grid view" },
+                    onclick: move |_| grid_view.toggle(),
+                    if grid_view() {
+                        "☰"
+                    } else {
+                        "▦"
+                    }
+                }
             }
 
             if let Some(err) = error_msg {
@@ -3021,11 +10772,98 @@ fn WebDAVSidebar(
                 }
             }
 
-            div { class: "webdav-file-list flex-1 overflow-y-auto space-y-1 min-h-0",
+            div {
+                class: "webdav-file-list flex-1 overflow-y-auto space-y-1 min-h-0",
+                onmounted: move |e| scroll.onmounted(e),
+                onscroll: move |e| scroll.onscroll(e),
                 if is_loading {
                     div { class: "text-center py-4 text-gray-400", "🔄 Loading..." }
                 } else if items.is_empty() {
                     div { class: "text-center py-4 text-gray-400", "Empty folder" }
+                } else if grid_view() {
+                    div { class: "grid grid-cols-2 gap-2",
+                        for (idx , item) in items.iter().enumerate() {
+                            {
+                                let item_click = item.clone();
+                                let path_click = current_path.clone();
+                                let preview = folder_previews
+                                    .read()
+                                    .get(&child_webdav_path(&current_path, &item.name))
+                                    .cloned();
+                                let double_click_item = item.clone();
+                                let selected_class = if selected_item.read().as_deref() == Some(item.path.as_str()) { "row-selected" } else { "" };
+                                let drag_payload = DragPayload::CloudFile { config_id: config.id.clone(), item: item.clone() };
+                                let folder = item.path.trim_matches('/').to_string();
+                                let synced = item.is_dir && config.synced_folders.contains(&folder);
+                                let sync_label = synced.then(|| sync_status_label(sync_status.get(&folder)));
+                                rsx! {
+                                    div {
+                                        key: "{idx}",
+                                        class: "p-2 rounded hover:bg-gray-700 cursor-pointer {click_mode.row_class()} {selected_class}",
+                                        title: "{item.name}",
+                                        draggable: if item.is_dir { "false" } else { "true" },
+                                        ondragstart: move |_| drag_state.set(Some(drag_payload.clone())),
+                                        ondragend: move |_| drag_state.set(None),
+                                        onclick: move |_| {
+                                            if item_click.is_dir {
+                                                on_navigate.call(child_webdav_path(&path_click, &item_click.name));
+                                            } else if click_mode.plays_on_click() {
+                                                on_play_track.call(item_click.clone());
+                                            } else {
+                                                selected_item.set(Some(item_click.path.clone()));
+                                            }
+                                        },
+                                        ondoubleclick: move |_| {
+                                            if !double_click_item.is_dir && !click_mode.plays_on_click() {
+                                                on_play_track.call(double_click_item.clone());
+                                            }
+                                        },
+                                        div { class: "folder-cover w-full bg-gray-700 rounded overflow-hidden mb-2",
+                                            if let Some(cover) = preview.as_ref().and_then(|p| p.cover.clone()) {
+                                                img {
+                                                    class: "w-full h-full object-cover",
+                                                    src: "{cover}",
+                                                }
+                                            } else {
+                                                span { class: "text-4xl",
+                                                    if item.is_dir {
+                                                        "📁"
+                                                    } else {
+                                                        "🎵"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        div { class: "truncate text-sm", "{item.name}" }
+                                        if item.is_dir {
+                                            div { class: "flex items-center justify-between gap-1 text-xs text-gray-500",
+                                                span { class: "truncate",
+                                                    match preview.and_then(|p| p.track_count) {
+                                                        Some(count) => format!("{} tracks", count),
+                                                        None => "…".to_string(),
+                                                    }
+                                                }
+                                                button {
+                                                    class: if synced { "flex-shrink-0 text-green-400 hover:text-white" } else { "flex-shrink-0 text-gray-500 hover:text-white" },
+                                                    title: if synced { "Stop keeping this folder on this computer" } else { "Keep this folder on this computer, in step with the server" },
+                                                    onclick: move |e| {
+                                                        e.stop_propagation();
+                                                        on_sync_folder.call((folder.clone(), !synced));
+                                                    },
+                                                    "⇅"
+                                                }
+                                            }
+                                            if let Some(label) = sync_label {
+                                                div { class: "text-xs text-gray-400 truncate", "{label}" }
+                                            }
+                                        } else {
+                                            div { class: "text-xs text-gray-500", "{format_bytes(item.size)}" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
                 } else {
                     {
 
@@ -3038,10 +10876,19 @@ fn WebDAVSidebar(
                                 let path_click = current_path.clone();
                                 let nav_click = on_navigate.clone();
                                 let play_click = on_play_track.clone();
+                                let double_click_item = item.clone();
+                                let selected_class = if selected_item.read().as_deref() == Some(item.path.as_str()) { "row-selected" } else { "" };
+                                let drag_payload = DragPayload::CloudFile { config_id: config.id.clone(), item: item.clone() };
+                                let folder = item.path.trim_matches('/').to_string();
+                                let synced = item.is_dir && config.synced_folders.contains(&folder);
+                                let sync_label = synced.then(|| sync_status_label(sync_status.get(&folder)));
                                 rsx! {
                                     div {
                                         key: "{idx}",
-                                        class: "flex items-center p-2 rounded hover:bg-gray-700 cursor-pointer group",
+                                        class: "flex items-center p-2 rounded hover:bg-gray-700 cursor-pointer group {click_mode.row_class()} {selected_class}",
+                                        draggable: if item.is_dir { "false" } else { "true" },
+                                        ondragstart: move |_| drag_state.set(Some(drag_payload.clone())),
+                                        ondragend: move |_| drag_state.set(None),
                                         onclick: move |_| {
                                             if item_click.is_dir {
                                                 let mut path = path_click.clone();
@@ -3050,8 +10897,15 @@ fn WebDAVSidebar(
                                                 }
                                                 path.push_str(&item_click.name);
                                                 nav_click.call(path);
-                                            } else {
+                                            } else if click_mode.plays_on_click() {
                                                 play_click.call(item_click.clone());
+                                            } else {
+                                                selected_item.set(Some(item_click.path.clone()));
+                                            }
+                                        },
+                                        ondoubleclick: move |_| {
+                                            if !double_click_item.is_dir && !click_mode.plays_on_click() {
+                                                play_click.call(double_click_item.clone());
                                             }
                                         },
 
@@ -3068,7 +10922,21 @@ fn WebDAVSidebar(
                                         div { class: "flex-1 min-w-0",
                                             div { class: "truncate text-sm", "{item.name}" }
                                             if !item.is_dir {
-                                                div { class: "text-xs text-gray-500 truncate", "{item.size / 1024} KB • {item.modified}" }
+                                                div { class: "text-xs text-gray-500 truncate", "{format_bytes(item.size)} • {format_modified(&item.modified)}" }
+                                            }
+                                            if let Some(label) = sync_label {
+                                                div { class: "text-xs text-gray-400 truncate", "{label}" }
+                                            }
+                                        }
+                                        if item.is_dir {
+                                            button {
+                                                class: if synced { "ml-2 px-2 py-1 rounded text-xs bg-green-800 hover:bg-green-700" } else { "ml-2 px-2 py-1 rounded text-xs bg-gray-700 hover:bg-gray-600 opacity-0 group-hover:opacity-100" },
+                                                title: if synced { "Stop keeping this folder on this computer" } else { "Keep this folder on this computer, in step with the server" },
+                                                onclick: move |e| {
+                                                    e.stop_propagation();
+                                                    on_sync_folder.call((folder.clone(), !synced));
+                                                },
+                                                if synced { "⇅ Synced" } else { "⇅ Sync" }
                                             }
                                         }
                                     }
@@ -3081,17 +10949,26 @@ fn WebDAVSidebar(
     }
 }
 
-// Find cover image in WebDAV directory (with caching)
-async fn find_cover_image_in_webdav(config: &WebDAVConfig, dir_path: &str) -> Option<Vec<u8>> {
-    const COVER_FILENAMES: [&str; 6] = ["cover.jpg", "cover.jpeg", "cover.png", "folder.jpg", "folder.jpeg", "folder.png"];
+// What a synced folder's row says about it
+fn sync_status_label(status: Option<&webdav_sync::FolderStatus>) -> String {
+    match status {
+        None | Some(webdav_sync::FolderStatus::Waiting) => "Waiting to sync".to_string(),
+        Some(webdav_sync::FolderStatus::Syncing { total: 0, .. }) => "Syncing: listing files…".to_string(),
+        Some(webdav_sync::FolderStatus::Syncing { done, total }) => format!("Syncing {} of {} files", done + 1, total),
+        Some(webdav_sync::FolderStatus::Synced { files }) => format!("✓ Synced, {} files", files),
+        Some(webdav_sync::FolderStatus::Failed(e)) => format!("⚠ Sync failed: {}", e),
+    }
+}
 
+// Find cover image in WebDAV directory (with caching); returns its cover cache id
+async fn find_cover_image_in_webdav(config: &WebDAVConfig, dir_path: &str) -> Option<String> {
     // Check cache first
     let cache_key = dir_path.to_string();
     if let Some(cached) = WEBDAV_COVER_CACHE.lock().unwrap().get(&cache_key) {
         return Some(cached.clone());
     }
 
-    for filename in COVER_FILENAMES.iter() {
+    for filename in file_watcher::COVER_FILENAMES.iter() {
         let cover_path = if dir_path.ends_with('/') {
             format!("{}{}", dir_path, filename)
         } else {
@@ -3102,8 +10979,9 @@ async fn find_cover_image_in_webdav(config: &WebDAVConfig, dir_path: &str) -> Op
             Ok(data) if is_valid_image(&data) => {
                 eprintln!("[Cover] Found and cached WebDAV cover: {}", cover_path);
                 // Cache the cover
-                WEBDAV_COVER_CACHE.lock().unwrap().insert(cache_key, data.clone());
-                return Some(data);
+                let id = cover_cache::store(&data)?;
+                WEBDAV_COVER_CACHE.lock().unwrap().insert(cache_key, id.clone());
+                return Some(id);
             }
             _ => continue,
         }
@@ -3115,15 +10993,13 @@ async fn find_cover_image_in_webdav(config: &WebDAVConfig, dir_path: &str) -> Op
 async fn download_webdav_file(config: &WebDAVConfig, file_path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
 
-    let mut url = reqwest::Url::parse(file_path)?;
-    if !config.username.is_empty() {
-        url.set_username(&config.username).ok();
-        if let Some(pwd) = config.get_password().ok().filter(|p| !p.is_empty()) {
-            url.set_password(Some(&pwd)).ok();
-        }
+    let url = reqwest::Url::parse(file_path)?;
+    let mut request = client.get(url);
+    if let Some(credentials) = webdav_auth(config) {
+        request = credentials.authorize(request).await;
     }
 
-    let response = client.get(url).send().await?;
+    let response = request.send().await?;
     if response.status().is_success() {
         Ok(response.bytes().await?.to_vec())
     } else {
@@ -3131,6 +11007,205 @@ async fn download_webdav_file(config: &WebDAVConfig, file_path: &str) -> Result<
     }
 }
 
+// Build the full stream URL for a path relative to a WebDAV server.
+// Credentials are never embedded; callers send them as a Basic auth header.
+fn resolve_webdav_url(config: &WebDAVConfig, relative_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut url = reqwest::Url::parse(&config.url)?;
+    // Without a trailing / the join below would replace the server's last folder
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    // 清理 path：移除开头的多余 / 和 , 符号
+    let clean_path = relative_path.trim_start_matches('/').trim_end_matches(',');
+
+    // 将路径片段添加到 URL
+    for segment in clean_path.split('/') {
+        if !segment.is_empty() {
+            url = url.join(&format!("{}/", segment)).map_err(|_| "Invalid path segment")?;
+        }
+    }
+
+    // 移除末尾的 /
+    let mut url_str = url.to_string();
+    if url_str.ends_with('/') && !clean_path.is_empty() {
+        url_str.pop();
+    }
+
+    Ok(url_str)
+}
+
+// Log a track start and remember why it played, newest first
+fn record_play(
+    mut history: Signal<Vec<PlayHistoryEntry>>,
+    mut recent: Signal<Vec<RecentPlay>>,
+    track: &TrackStub,
+    reason: PlayReason,
+) {
+    eprintln!("[History] {} - {}: {}", track.artist, track.title, reason.describe());
+    let mut entries = history.write();
+    entries.insert(0, PlayHistoryEntry {
+        track_id: track.id.clone(),
+        path: track.path.clone(),
+        source: Some(track.source.clone()),
+        title: track.title.clone(),
+        artist: track.artist.clone(),
+        reason,
+        played_at: std::time::SystemTime::now(),
+    });
+    entries.truncate(PLAY_HISTORY_LIMIT);
+    if let Err(e) = save_play_history(&entries) {
+        eprintln!("[History] 保存播放历史失败: {}", e);
+    }
+
+    let mut recent = recent.write();
+    recent.retain(|play| play.track.path != track.path);
+    recent.insert(0, RecentPlay { track: track.clone(), played_at: std::time::SystemTime::now() });
+    recent.truncate(RECENTLY_PLAYED_LIMIT);
+    if let Err(e) = save_recently_played(&recent) {
+        eprintln!("[History] 保存最近播放失败: {}", e);
+    }
+}
+
+// Plays per file (by file_key) over the saved history
+fn play_counts(history: &[PlayHistoryEntry]) -> std::collections::HashMap<String, u32> {
+    let mut counts = std::collections::HashMap::new();
+    for entry in history {
+        *counts.entry(entry.file_key()).or_default() += 1;
+    }
+    counts
+}
+
+// What party guests can search: each file once, however many playlists it's in
+fn party_library(playlists: &[Playlist]) -> Vec<party::PartyTrack> {
+    let mut seen = std::collections::HashSet::new();
+    playlists
+        .iter()
+        .flat_map(|playlist| playlist.tracks.iter())
+        .filter(|track| seen.insert((track.source.clone(), track.path.clone())))
+        .map(|track| party::PartyTrack {
+            id: track.id.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+        })
+        .collect()
+}
+
+// What to sign a WebDAV request with, if the server needs anything
+fn webdav_auth(config: &WebDAVConfig) -> Option<webdav::Credentials> {
+    config.credentials().unwrap_or_else(|e| {
+        eprintln!("[WebDAV] 无法读取 {} 的认证信息: {}", config.name, e);
+        None
+    })
+}
+
+// Resolve what the player should open for a playlist entry, and whether that's over
+// the network. WebDAV tracks store a path relative to their server, so edits to the
+// server config apply on next play; files in a synced folder play from disk.
+fn resolve_track_path(track: &TrackStub, configs: &[WebDAVConfig]) -> (String, bool) {
+    if let TrackSource::WebDAV { config_id } = &track.source {
+        if let Some(local) = webdav_sync::local_copy(config_id, &track.path) {
+            return (local.to_string_lossy().into_owned(), false);
+        }
+    }
+    let path = match &track.source {
+        TrackSource::WebDAV { config_id } if !track.path.starts_with("http") => {
+            match configs.iter().find(|c| &c.id == config_id) {
+                Some(config) => match resolve_webdav_url(config, &track.path) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        eprintln!("[WebDAV] 无法解析曲目地址 {}: {}", track.path, e);
+                        track.path.clone()
+                    }
+                },
+                None => {
+                    eprintln!("[WebDAV] 找不到曲目对应的服务器配置: {}", config_id);
+                    track.path.clone()
+                }
+            }
+        }
+        _ => track.path.clone(),
+    };
+    // A URL is opened as one whatever the source says
+    let remote = track.source.is_remote() || path.starts_with("http://") || path.starts_with("https://");
+    (path, remote)
+}
+
+fn track_auth(track: &TrackStub, configs: &[WebDAVConfig]) -> Option<webdav::Credentials> {
+    match &track.source {
+        TrackSource::WebDAV { config_id } => configs
+            .iter()
+            .find(|c| &c.id == config_id)
+            .and_then(webdav_auth),
+        _ => None,
+    }
+}
+
+// A playlist entry for a YouTube / SoundCloud page, with its thumbnail as the
+// cover. Blocks while yt-dlp runs.
+#[cfg(feature = "ytdlp")]
+fn resolve_online_track(url: &str) -> Result<TrackStub, String> {
+    if !ytdlp::is_supported_url(url) {
+        return Err("Only YouTube and SoundCloud links are supported".to_string());
+    }
+    let info = ytdlp::fetch_info(url)?;
+    let cover = info
+        .thumbnail
+        .as_deref()
+        .and_then(|thumbnail| reqwest::blocking::get(thumbnail).and_then(|r| r.error_for_status()?.bytes()).ok())
+        .and_then(|data| cover_cache::store(&data));
+    eprintln!("[yt-dlp] 已添加: {} - {}", info.artist, info.title);
+    Ok(TrackStub {
+        id: Uuid::new_v4().to_string(),
+        path: url.to_string(),
+        title: info.title,
+        artist: info.artist,
+        album: info.album,
+        album_artist: None,
+        track_number: None,
+        year: None,
+        genre: None,
+        duration: info.duration,
+        cover,
+        source: TrackSource::Online,
+        gain_db: 0.0,
+        added_at: None,
+        stamp: None,
+    })
+}
+
+#[cfg(not(feature = "ytdlp"))]
+fn resolve_online_track(_url: &str) -> Result<TrackStub, String> {
+    Err("This build doesn't include yt-dlp support".to_string())
+}
+
+// Start playback of a playlist entry on the shared player
+fn play_track(player: &MusicPlayer, track: &TrackStub, configs: &[WebDAVConfig]) {
+    let (path, is_remote) = resolve_track_path(track, configs);
+    // Spoken-word tracks carry on where they were left
+    if let Some(position) = resume::position(&track.path) {
+        player.set_start_position(position);
+    }
+    player.set_stream_auth(track_auth(track, configs));
+    player.play(Path::new(&path), Some(track.id.clone()), is_remote);
+}
+
+// Get the entry expected to play next ready ahead of time; play_track picks it up
+// if it's the one that actually plays
+fn prefetch_track(player: &MusicPlayer, track: &TrackStub, configs: &[WebDAVConfig]) {
+    let (path, is_remote) = resolve_track_path(track, configs);
+    player.prefetch(Path::new(&path), is_remote, track_auth(track, configs));
+}
+
+// Line a prefetched entry up to take over from the current track in the player
+// itself, gapless or crossfading; false until its prefetch is ready
+fn queue_track(player: &MusicPlayer, track: &TrackStub, configs: &[WebDAVConfig]) -> bool {
+    let (path, is_remote) = resolve_track_path(track, configs);
+    player.queue_next(Path::new(&path), Some(track.id.clone()), is_remote)
+}
+
 // Create placeholder Track for WebDAV files without downloading (for adding to playlist)
 async fn create_webdav_placeholder_tracks(
     config: &WebDAVConfig,
@@ -3138,24 +11213,13 @@ async fn create_webdav_placeholder_tracks(
 ) -> Result<Vec<Track>, Box<dyn std::error::Error>> {
     let mut tracks = Vec::new();
 
-    let password = config.get_password()?;
-
-    let mut base_url = reqwest::Url::parse(&config.url)?;
-
-    if !config.username.is_empty() {
-        base_url.set_username(&config.username).map_err(|_| "Invalid username")?;
-        if !password.is_empty() {
-            base_url.set_password(Some(&password)).map_err(|_| "Invalid password")?;
-        }
-    }
-
     // Get directory path for cover search (only once for all files)
     let dir_path = if file_paths.is_empty() {
         config.url.clone()
     } else {
         let first_path = file_paths[0].trim_start_matches('/').trim_end_matches(',');
         if let Some(pos) = first_path.rfind('/') {
-            let base = base_url.to_string();
+            let base = resolve_webdav_url(config, "")?;
             let proto_end = base.find("://").map(|p| p + 3).unwrap_or(0);
             let base_without_path = &base[..proto_end + base[proto_end..].find('/').map(|p| proto_end + p).unwrap_or(base.len())];
             format!("{}{}", base_without_path, &first_path[..pos])
@@ -3168,31 +11232,6 @@ async fn create_webdav_placeholder_tracks(
     let dir_cover = find_cover_image_in_webdav(config, &dir_path).await;
 
     for path_str in file_paths {
-        let full_url = if path_str.starts_with("http") {
-            path_str.to_string()
-        } else {
-            // 使用 reqwest::Url 正确构建 URL
-            let mut url = base_url.clone();
-
-            // 清理 path_str：移除开头的多余 / 和 , 符号
-            let clean_path = path_str.trim_start_matches('/').trim_end_matches(',');
-
-            // 将路径片段添加到 URL
-            for segment in clean_path.split('/') {
-                if !segment.is_empty() {
-                    url = url.join(&format!("{}/", segment)).map_err(|_| "Invalid path segment")?;
-                }
-            }
-
-            // 移除末尾的 /
-            let mut url_str = url.to_string();
-            if url_str.ends_with('/') && !clean_path.is_empty() {
-                url_str.pop();
-            }
-
-            url_str
-        };
-
         let filename = path_str.split('/').last().unwrap_or("Unknown");
         let decoded_filename = match urlencoding::decode(filename) {
             Ok(cow) => cow.into_owned(),
@@ -3206,12 +11245,18 @@ async fn create_webdav_placeholder_tracks(
 
         let track = Track {
             id: uuid::Uuid::new_v4().to_string(),
-            path: full_url,
+            path: path_str.clone(),
             title: title,
             artist: "Cloud Stream".to_string(),
             album: "WebDAV".to_string(),
+            album_artist: None,
+            track_number: None,
+            year: None,
+            genre: None,
             duration: std::time::Duration::from_secs(0),
             cover: dir_cover.clone(),
+            source: TrackSource::WebDAV { config_id: config.id.clone() },
+            gain_db: 0.0,
         };
         tracks.push(track);
     }
@@ -3219,37 +11264,38 @@ async fn create_webdav_placeholder_tracks(
     Ok(tracks)
 }
 
-// Import WebDAV files as streams (downloads to get metadata)
+// What a cloud import added, skipped and couldn't fetch
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ImportReport {
+    config_id: String,
+    imported: usize,
+    // Titles of files already in the playlist
+    duplicates: Vec<String>,
+    failed: Vec<ImportFailure>,
+}
+
+// A selected cloud file that couldn't be imported, and why
+#[derive(Clone, Debug, PartialEq)]
+struct ImportFailure {
+    path: String,
+    name: String,
+    reason: String,
+}
+
+// Import WebDAV files as streams (downloads to get metadata). Files that fail to
+// download are reported back rather than added.
 async fn download_and_import_webdav_files(
     config: &WebDAVConfig,
     file_paths: &[String],
-) -> Result<Vec<Track>, Box<dyn std::error::Error>> {
+) -> Result<(Vec<Track>, Vec<ImportFailure>), Box<dyn std::error::Error>> {
     let mut tracks = Vec::new();
-    
-    let password = config.get_password()?;
-    
+    let mut failures = Vec::new();
+
+    let credentials = config.credentials()?;
+
     let client = reqwest::Client::new();
-    let mut base_url = reqwest::Url::parse(&config.url)?;
-    
-    if !config.username.is_empty() {
-        base_url.set_username(&config.username).map_err(|_| "Invalid username")?;
-        if !password.is_empty() {
-            base_url.set_password(Some(&password)).map_err(|_| "Invalid password")?;
-        }
-    }
-    
+
     for path_str in file_paths {
-        let full_url = if path_str.starts_with("http") {
-            let mut u = reqwest::Url::parse(path_str)?;
-            if !base_url.username().is_empty() {
-                u.set_username(base_url.username()).ok();
-                u.set_password(base_url.password()).ok();
-            }
-            u.to_string()
-        } else {
-            base_url.join(path_str)?.to_string()
-        };
-        
         let filename = path_str.split('/').last().unwrap_or("Unknown");
         let decoded_filename = match urlencoding::decode(filename) {
             Ok(cow) => cow.into_owned(),
@@ -3260,49 +11306,84 @@ async fn download_and_import_webdav_files(
            .and_then(|s| s.to_str())
            .unwrap_or(&decoded_filename)
            .to_string();
-        
-        let mut duration = std::time::Duration::from_secs(0);
-        
-        let temp_dir = std::env::temp_dir();
-        let temp_filename = format!("dioxusmusic_{}", uuid::Uuid::new_v4());
-        let temp_path = temp_dir.join(&temp_filename);
-        
-        match client.get(&full_url)
-            .basic_auth(&config.username, Some(&password))
-            .send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.bytes().await {
-                        Ok(bytes) => {
-                            if let Ok(_) = std::fs::write(&temp_path, &bytes) {
-                                // Try to read metadata from downloaded file
-                                if let Ok(d) = mp3_duration::from_path(&temp_path) {
-                                    duration = d;
-                                }
-                                // Clean up temp file
-                                let _ = std::fs::remove_file(&temp_path);
-                            }
-                        }
-                        Err(_) => {}
-                    }
+        let mut fail = |reason: String| {
+            eprintln!("[WebDAV] 导入失败 {}: {}", path_str, reason);
+            failures.push(ImportFailure { path: path_str.clone(), name: decoded_filename.clone(), reason });
+        };
+
+        let full_url = if path_str.starts_with("http") {
+            path_str.to_string()
+        } else {
+            match resolve_webdav_url(config, path_str) {
+                Ok(url) => url,
+                Err(e) => {
+                    fail(format!("Bad path: {}", e));
+                    continue;
                 }
             }
-            Err(_) => {}
-        }
-        
+        };
+
+        let duration = match remote_duration(&client, &full_url, &credentials).await {
+            Ok(duration) => duration,
+            Err(reason) => {
+                fail(reason);
+                continue;
+            }
+        };
+
         let track = Track {
             id: uuid::Uuid::new_v4().to_string(),
-            path: full_url,
+            path: path_str.clone(),
             title: title,
-            artist: "Cloud Stream".to_string(), 
+            artist: "Cloud Stream".to_string(),
             album: "WebDAV".to_string(),
+            album_artist: None,
+            track_number: None,
+            year: None,
+            genre: None,
             duration: duration,
             cover: None,
+            source: TrackSource::WebDAV { config_id: config.id.clone() },
+            gain_db: 0.0,
         };
         tracks.push(track);
     }
-    
-    Ok(tracks)
+
+    Ok((tracks, failures))
+}
+
+// A remote file's duration from its headers, read over Range requests; a server
+// that doesn't take those sends the whole file
+async fn remote_duration(
+    client: &reqwest::Client,
+    url: &str,
+    credentials: &Option<webdav::Credentials>,
+) -> Result<std::time::Duration, String> {
+    let (stream_url, auth) = (url.to_string(), credentials.clone());
+    let streamed = tokio::task::spawn_blocking(move || {
+        player::WebDAVStreamSource::open(&stream_url, auth).map(|mut source| crate::duration::read(&mut source).unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match streamed {
+        Ok(duration) => return Ok(duration),
+        Err(e) if e.kind() != std::io::ErrorKind::Unsupported => return Err(format!("Couldn't read the file: {}", e)),
+        Err(_) => {}
+    }
+
+    let mut request = client.get(url);
+    if let Some(credentials) = credentials {
+        request = credentials.authorize(request).await;
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Couldn't reach the server: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server answered {}", response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| format!("Download interrupted: {}", e))?;
+    Ok(crate::duration::read(&mut std::io::Cursor::new(&bytes[..])).unwrap_or_default())
 }
 
 // Fetch metadata for a single WebDAV file on-demand (when playing)
@@ -3313,46 +11394,27 @@ async fn fetch_webdav_track_metadata(
 ) -> Result<std::time::Duration, Box<dyn std::error::Error>> {
     let mut duration = std::time::Duration::from_secs(0);
     
-    let password = config.get_password()?;
+    let credentials = config.credentials()?;
     
     let client = reqwest::Client::new();
-    let mut base_url = reqwest::Url::parse(&config.url)?;
-    
-    if !config.username.is_empty() {
-        base_url.set_username(&config.username).ok();
-        if !password.is_empty() {
-            base_url.set_password(Some(&password)).ok();
-        }
-    }
     
     let full_url = if path.starts_with("http") {
-        let mut u = reqwest::Url::parse(path)?;
-        if !base_url.username().is_empty() {
-            u.set_username(base_url.username()).ok();
-            u.set_password(base_url.password()).ok();
-        }
-        u.to_string()
+        path.to_string()
     } else {
-        base_url.join(path)?.to_string()
+        resolve_webdav_url(config, path)?
     };
     
-    let temp_dir = std::env::temp_dir();
-    let temp_filename = format!("dioxusmusic_{}", uuid::Uuid::new_v4());
-    let temp_path = temp_dir.join(&temp_filename);
-    
-    match client.get(&full_url)
-        .basic_auth(&config.username, Some(&password))
-        .send().await {
+    let mut request = client.get(&full_url);
+    if let Some(credentials) = &credentials {
+        request = credentials.authorize(request).await;
+    }
+
+    match request.send().await {
         Ok(response) => {
             if response.status().is_success() {
                 match response.bytes().await {
                     Ok(bytes) => {
-                        if let Ok(_) = std::fs::write(&temp_path, &bytes) {
-                            if let Ok(d) = mp3_duration::from_path(&temp_path) {
-                                duration = d;
-                            }
-                            let _ = std::fs::remove_file(&temp_path);
-                        }
+                        duration = crate::duration::read(&mut std::io::Cursor::new(&bytes[..])).unwrap_or_default();
                     }
                     Err(_) => {}
                 }
@@ -1,102 +1,730 @@
-use crate::Track;
+use crate::player::StreamInfo;
+use crate::{Track, TrackSource};
+use id3::frame::{ExtendedText, Popularimeter, TimestampFormat};
 use id3::{Tag, TagLike};
 use metaflac::Tag as FlacTag;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 use std::time::Duration;
+use symphonia::core::codecs::{CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Value};
+use symphonia::core::probe::Hint;
 use uuid::Uuid;
-use rodio::Source;
 
+/// A file's tags, from symphonia or the in-house container parsers (`ogg_tags`,
+/// `mp4_tags`, ...) for the formats symphonia doesn't read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmbeddedTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    // Flagged as a compilation, for files with no album artist to group them by
+    pub compilation: bool,
+    pub lyrics: Option<String>,
+    // Every embedded picture, front covers first
+    pub pictures: Vec<Picture>,
+    pub duration: Option<Duration>,
+}
+
+/// ID3 / FLAC picture type of the front cover.
+pub const FRONT_COVER: u8 = 3;
+
+/// An embedded picture with its ID3 / FLAC picture type, which MP4 and
+/// pre-standard Vorbis covers don't have (0, "other").
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Picture {
+    pub picture_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    /// What the picture shows, in a word or two.
+    pub fn label(&self) -> &'static str {
+        match self.picture_type {
+            1 | 2 => "Icon",
+            FRONT_COVER => "Front cover",
+            4 => "Back cover",
+            5 => "Booklet",
+            6 => "Disc",
+            7..=12 => "Artist",
+            13..=16 => "Recording",
+            18 => "Illustration",
+            19 | 20 => "Logo",
+            _ => "Picture",
+        }
+    }
+}
+
+impl EmbeddedTags {
+    /// Add an embedded picture; front covers go ahead of the others.
+    pub fn add_picture(&mut self, picture_type: u8, data: Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+        let at = match picture_type {
+            FRONT_COVER => self.pictures.iter().take_while(|p| p.picture_type == FRONT_COVER).count(),
+            _ => self.pictures.len(),
+        };
+        self.pictures.insert(at, Picture { picture_type, data });
+    }
+
+    /// Use the album artist as the artist when there's no artist, as
+    /// compilations often only set the album artist.
+    pub fn fill_artist_from_album_artist(&mut self) {
+        if self.artist.is_none() {
+            self.artist = self.album_artist.clone();
+        }
+    }
+
+    /// The picture that stands for the file: its front cover, else its first picture.
+    pub fn cover(&self) -> Option<&[u8]> {
+        self.pictures.first().map(|picture| picture.data.as_slice())
+    }
+
+    // Fill in what these tags lack from `other`
+    fn or(self, other: EmbeddedTags) -> EmbeddedTags {
+        EmbeddedTags {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            album: self.album.or(other.album),
+            album_artist: self.album_artist.or(other.album_artist),
+            track_number: self.track_number.or(other.track_number),
+            year: self.year.or(other.year),
+            genre: self.genre.or(other.genre),
+            compilation: self.compilation || other.compilation,
+            lyrics: self.lyrics.or(other.lyrics),
+            pictures: if self.pictures.is_empty() { other.pictures } else { self.pictures },
+            duration: self.duration.or(other.duration),
+        }
+    }
+}
+
+/// What compilations without an album artist are filed under.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// "7", "07" or "7/12" -> 7. Track 0 means unset.
+pub fn parse_track_number(text: &str) -> Option<u32> {
+    let number = text.split('/').next()?.trim().parse().ok()?;
+    (number > 0).then_some(number)
+}
+
+/// A compilation flag as tags store it: "1" (iTunes, ID3 TCMP) or "true".
+pub fn parse_flag(text: &str) -> bool {
+    matches!(text.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes")
+}
+
+// A compilation's tracks each credit their own artist, so without an album
+// artist they'd make an album per artist; filed under Various Artists they stay one
+fn filed_album_artist(album_artist: Option<String>, compilation: bool) -> Option<String> {
+    album_artist.filter(|artist| !artist.trim().is_empty()).or_else(|| compilation.then(|| VARIOUS_ARTISTS.to_string()))
+}
+
+/// The year at the start of a date tag: "1997", "1997-05-21" or "1997-05-21T00:00:00Z".
+pub fn parse_year(text: &str) -> Option<i32> {
+    let digits: String = text.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+    (digits.len() == 4).then(|| digits.parse().ok()).flatten()
+}
+
+/// What the tag editor shows and writes back. Blank text and None clear a field.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagEdit {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub track_number: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditableFormat {
+    Id3,
+    Flac,
+    Mp4,
+}
+
+fn editable_format(path: &Path) -> Option<EditableFormat> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "mp3" => Some(EditableFormat::Id3),
+        "flac" => Some(EditableFormat::Flac),
+        "m4a" | "m4b" | "mp4" => Some(EditableFormat::Mp4),
+        _ => None,
+    }
+}
+
+/// Whether the tag editor can write to this file: MP3 (ID3v2), FLAC (Vorbis
+/// comments) and MP4 audio (iTunes atoms).
+pub fn can_edit_tags(path: &Path) -> bool {
+    editable_format(path).is_some()
+}
+
+/// The file's current tags, for filling in the editor. Fields the file doesn't
+/// have are left blank.
+pub fn read_tag_edit(path: &Path) -> TagEdit {
+    let text = |value: Option<&str>| value.unwrap_or_default().to_string();
+    match editable_format(path) {
+        // Saving writes an ID3v2 tag, so one only tagged the old way starts from that
+        Some(EditableFormat::Id3) => Tag::read_from_path(path)
+            .map(|tag| TagEdit {
+                title: text(tag.title()),
+                artist: text(tag.artist()),
+                album: text(tag.album()),
+                track_number: tag.track(),
+                year: tag.year().or_else(|| tag.date_recorded().map(|date| date.year)),
+                genre: tag.genre_parsed().map(|genre| genre.into_owned()).unwrap_or_default(),
+            })
+            .ok()
+            .or_else(|| fallback_mp3_tags(path).map(tag_edit_from))
+            .unwrap_or_default(),
+        Some(EditableFormat::Flac) => FlacTag::read_from_path(path)
+            .ok()
+            .and_then(|tag| {
+                let vorbis = tag.vorbis_comments()?;
+                let first = |values: Option<&Vec<String>>| text(values.and_then(|v| v.first()).map(String::as_str));
+                Some(TagEdit {
+                    title: first(vorbis.title()),
+                    artist: first(vorbis.artist()),
+                    album: first(vorbis.album()),
+                    track_number: vorbis.track(),
+                    year: vorbis.get("DATE").and_then(|v| v.first()).and_then(|date| parse_year(date)),
+                    genre: first(vorbis.genre()),
+                })
+            })
+            .unwrap_or_default(),
+        Some(EditableFormat::Mp4) => {
+            crate::mp4_tags::read_from_path(path).ok().flatten().map(tag_edit_from).unwrap_or_default()
+        }
+        None => TagEdit::default(),
+    }
+}
+
+fn tag_edit_from(tags: EmbeddedTags) -> TagEdit {
+    TagEdit {
+        title: tags.title.unwrap_or_default(),
+        artist: tags.artist.unwrap_or_default(),
+        album: tags.album.unwrap_or_default(),
+        track_number: tags.track_number,
+        year: tags.year,
+        genre: tags.genre.unwrap_or_default(),
+    }
+}
+
+/// Write the edited tags into the file, leaving its other tags (cover art,
+/// lyrics and so on) as they were.
+pub fn write_tags(path: &Path, edit: &TagEdit) -> Result<(), Box<dyn std::error::Error>> {
+    let text = |value: &str| Some(value.trim()).filter(|v| !v.is_empty()).map(str::to_string);
+    match editable_format(path) {
+        Some(EditableFormat::Id3) => {
+            let mut tag = Tag::read_from_path(path).unwrap_or_default();
+            match text(&edit.title) {
+                Some(title) => tag.set_title(title),
+                None => tag.remove_title(),
+            }
+            match text(&edit.artist) {
+                Some(artist) => tag.set_artist(artist),
+                None => tag.remove_artist(),
+            }
+            match text(&edit.album) {
+                Some(album) => tag.set_album(album),
+                None => tag.remove_album(),
+            }
+            match edit.track_number.filter(|n| *n > 0) {
+                Some(track) => tag.set_track(track),
+                None => tag.remove_track(),
+            }
+            // ID3v2.4 keeps the year in the recording date; TYER is the 2.3 frame
+            tag.remove_year();
+            match edit.year {
+                Some(year) => tag.set_date_recorded(id3::Timestamp { year, month: None, day: None, hour: None, minute: None, second: None }),
+                None => tag.remove_date_recorded(),
+            }
+            match text(&edit.genre) {
+                Some(genre) => tag.set_genre(genre),
+                None => tag.remove_genre(),
+            }
+            tag.write_to_path(path, id3::Version::Id3v24)?;
+        }
+        Some(EditableFormat::Flac) => {
+            let mut tag = FlacTag::read_from_path(path)?;
+            let vorbis = tag.vorbis_comments_mut();
+            match text(&edit.title) {
+                Some(title) => vorbis.set_title(vec![title]),
+                None => vorbis.remove_title(),
+            }
+            match text(&edit.artist) {
+                Some(artist) => vorbis.set_artist(vec![artist]),
+                None => vorbis.remove_artist(),
+            }
+            match text(&edit.album) {
+                Some(album) => vorbis.set_album(vec![album]),
+                None => vorbis.remove_album(),
+            }
+            match edit.track_number.filter(|n| *n > 0) {
+                Some(track) => vorbis.set_track(track),
+                None => vorbis.remove_track(),
+            }
+            match edit.year {
+                Some(year) => vorbis.set("DATE", vec![year.to_string()]),
+                None => vorbis.remove("DATE"),
+            }
+            match text(&edit.genre) {
+                Some(genre) => vorbis.set_genre(vec![genre]),
+                None => vorbis.remove_genre(),
+            }
+            tag.save()?;
+        }
+        Some(EditableFormat::Mp4) => crate::mp4_tags::write_to_path(path, edit)?,
+        None => {
+            let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            return Err(format!("editing tags isn't supported for .{} files", ext).into());
+        }
+    }
+    eprintln!("[Tags] 已写入标签: {}", path.display());
+    Ok(())
+}
+
+// Star ratings as POPM bytes, mapped the way Windows Media Player and most taggers do
+const POPM_STARS: [u8; 5] = [1, 64, 128, 196, 255];
+// Who a new POPM frame is credited to; MediaMonkey and Mp3tag use the same
+const POPM_USER: &str = "no@email";
+
+/// What to save into a file's tags for other players: a new star rating
+/// (`Some(None)` clears it) and plays to add to its play count.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatsUpdate {
+    pub rating: Option<Option<u8>>,
+    pub plays: u64,
+}
+
+impl StatsUpdate {
+    /// Fold a later update into this one.
+    pub fn merge(&mut self, later: StatsUpdate) {
+        self.rating = later.rating.or(self.rating);
+        self.plays += later.plays;
+    }
+}
+
+/// Whether ratings and play counts can be saved into this file's tags: MP3
+/// (ID3 POPM plus FMPS frames) and FLAC (FMPS Vorbis comments).
+pub fn can_write_stats(path: &Path) -> bool {
+    matches!(editable_format(path), Some(EditableFormat::Id3 | EditableFormat::Flac))
+}
+
+// FMPS stores ratings as 0.0-1.0 and counts as decimal numbers
+fn fmps_rating(stars: u8) -> String {
+    format!("{}", f32::from(stars.clamp(1, 5)) / 5.0)
+}
+
+fn fmps_count(text: &str) -> Option<u64> {
+    text.trim().parse::<f64>().ok().filter(|n| *n >= 0.0).map(|n| n as u64)
+}
+
+/// Save a rating and plays into the file's tags. A file that already has a
+/// POPM frame keeps its owner; otherwise one is added.
+pub fn write_stats(path: &Path, update: StatsUpdate) -> Result<(), Box<dyn std::error::Error>> {
+    match editable_format(path) {
+        Some(EditableFormat::Id3) => {
+            let mut tag = Tag::read_from_path(path).unwrap_or_default();
+            let mut popm = tag
+                .frames()
+                .find_map(|frame| frame.content().popularimeter())
+                .cloned()
+                .unwrap_or(Popularimeter { user: POPM_USER.to_string(), rating: 0, counter: 0 });
+            if let Some(rating) = update.rating {
+                popm.rating = rating.map_or(0, |stars| POPM_STARS[usize::from(stars.clamp(1, 5)) - 1]);
+                match rating {
+                    Some(stars) => {
+                        tag.add_frame(ExtendedText { description: "FMPS_Rating".to_string(), value: fmps_rating(stars) });
+                    }
+                    None => tag.remove_extended_text(Some("FMPS_Rating"), None),
+                }
+            }
+            if update.plays > 0 {
+                let fmps = tag
+                    .extended_texts()
+                    .find(|text| text.description == "FMPS_Playcount")
+                    .and_then(|text| fmps_count(&text.value));
+                popm.counter = popm.counter.max(fmps.unwrap_or_default()) + update.plays;
+                tag.add_frame(ExtendedText { description: "FMPS_Playcount".to_string(), value: popm.counter.to_string() });
+            }
+            tag.add_frame(popm);
+            tag.write_to_path(path, id3::Version::Id3v24)?;
+        }
+        Some(EditableFormat::Flac) => {
+            let mut tag = FlacTag::read_from_path(path)?;
+            let vorbis = tag.vorbis_comments_mut();
+            match update.rating {
+                Some(Some(stars)) => vorbis.set("FMPS_RATING", vec![fmps_rating(stars)]),
+                Some(None) => vorbis.remove("FMPS_RATING"),
+                None => {}
+            }
+            if update.plays > 0 {
+                let plays = vorbis.get("FMPS_PLAYCOUNT").and_then(|values| values.first()).and_then(|text| fmps_count(text));
+                vorbis.set("FMPS_PLAYCOUNT", vec![(plays.unwrap_or_default() + update.plays).to_string()]);
+            }
+            tag.save()?;
+        }
+        _ => return Err(format!("{} can't hold ratings or play counts", path.display()).into()),
+    }
+    Ok(())
+}
+
+/// Ogg Vorbis / Opus comments, MP4 (iTunes) atoms, the APEv2 tag of a WavPack /
+/// Monkey's Audio file, AIFF chunks or WMA (ASF) attributes, whichever the file holds.
+pub fn read_embedded_tags(path: &Path) -> Option<EmbeddedTags> {
+    crate::ogg_tags::read_from_path(path)
+        .ok()
+        .flatten()
+        .or_else(|| crate::mp4_tags::read_from_path(path).ok().flatten())
+        .or_else(|| crate::ape_tags::read_from_path(path).ok().flatten())
+        .or_else(|| crate::aiff_tags::read_from_path(path).ok().flatten())
+        .or_else(|| crate::asf_tags::read_from_path(path).ok().flatten())
+}
+
+/// A local file's tags as a track, taken from the metadata cache while the file
+/// is unchanged since it was last read.
 pub fn extract_metadata(path: &Path) -> Result<Track, Box<dyn std::error::Error>> {
+    let stamp = crate::FileStamp::of(path);
+    if let Some(track) = stamp.and_then(|stamp| crate::metadata_cache::get(path, stamp)) {
+        return Ok(track);
+    }
+    let tags = probe(path).ok_or_else(|| format!("{} isn't audio that can be read", path.display()))?.tags;
+    let track = track_from_tags(path, tags);
+    if let Some(stamp) = stamp {
+        crate::metadata_cache::insert(path, stamp, &track);
+    }
+    Ok(track)
+}
+
+fn track_from_tags(path: &Path, tags: EmbeddedTags) -> Track {
     let path_str = path.to_string_lossy().to_string();
     let file_name = path.file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("Unknown")
         .to_string();
 
-    // Try to get duration
-    let duration = get_duration(path)?;
-
-    // Try ID3 tags first (MP3)
-    if let Ok(tag) = Tag::read_from_path(path) {
-        let title = tag.title()
-            .map(|t| t.to_string())
-            .unwrap_or_else(|| file_name.clone());
-        
-        let artist = tag.artist()
-            .map(|a| a.to_string())
-            .unwrap_or_else(|| "Unknown Artist".to_string());
-        
-        let album = tag.album()
-            .map(|a| a.to_string())
-            .unwrap_or_else(|| "Unknown Album".to_string());
-
-        // Try to extract cover art
-        let cover = tag.pictures()
-            .next()
-            .map(|pic| pic.data.clone());
-
-        return Ok(Track {
-            id: Uuid::new_v4().to_string(),
-            path: path_str,
-            title,
-            artist,
-            album,
-            duration,
-            cover,
-        });
-    }
-
-    // Try FLAC tags
-    if let Ok(tag) = FlacTag::read_from_path(path) {
-        if let Some(vorbis) = tag.vorbis_comments() {
-            let title = vorbis.title()
-                .and_then(|v| v.first().cloned())
-                .unwrap_or_else(|| file_name.clone());
-            
-            let artist = vorbis.artist()
-                .and_then(|v| v.first().cloned())
-                .unwrap_or_else(|| "Unknown Artist".to_string());
-            
-            let album = vorbis.album()
-                .and_then(|v| v.first().cloned())
-                .unwrap_or_else(|| "Unknown Album".to_string());
-
-            // FLAC pictures
-            let cover = tag.pictures()
-                .next()
-                .map(|pic| pic.data.clone());
-
-            return Ok(Track {
-                id: Uuid::new_v4().to_string(),
-                path: path_str,
-                title,
-                artist,
-                album,
-                duration,
-                cover,
-            });
-        }
-    }
-
-    // Fallback to filename
-    Ok(Track {
+    let album_artist = filed_album_artist(tags.album_artist, tags.compilation);
+    let cover = cache_cover(album_artist.as_deref().or(tags.artist.as_deref()), tags.album.as_deref(), || {
+        tags.pictures.into_iter().next().map(|picture| picture.data)
+    });
+    Track {
         id: Uuid::new_v4().to_string(),
         path: path_str,
-        title: file_name,
-        artist: "Unknown Artist".to_string(),
-        album: "Unknown Album".to_string(),
-        duration,
-        cover: None,
-    })
+        title: tags.title.unwrap_or(file_name),
+        artist: tags.artist.unwrap_or_else(|| "Unknown Artist".to_string()),
+        album: tags.album.unwrap_or_else(|| "Unknown Album".to_string()),
+        album_artist,
+        track_number: tags.track_number,
+        year: tags.year,
+        genre: tags.genre,
+        duration: tags.duration.unwrap_or_default(),
+        cover,
+        source: TrackSource::Local,
+        gain_db: 0.0,
+    }
+}
+
+/// Everything reading a local file once finds: its tags, cover and duration,
+/// and the properties of its audio stream.
+#[derive(Clone, Debug, Default)]
+pub struct Probed {
+    pub tags: EmbeddedTags,
+    pub stream: StreamInfo,
 }
 
-fn get_duration(path: &Path) -> Result<Duration, Box<dyn std::error::Error>> {
-    use rodio::Decoder;
-    use std::fs::File;
+/// Read `path` with a single symphonia probe (MP3, FLAC, MP4, Ogg Vorbis, WAV,
+/// AIFF), filling in whatever that misses from the in-house parsers, which also
+/// cover the formats symphonia can't open (Opus, APE, WavPack, WMA). None if
+/// neither recognises the file.
+pub fn probe(path: &Path) -> Option<Probed> {
+    let probed = probe_with_symphonia(path);
+    if probed.as_ref().is_some_and(|probed| probed.tags.title.is_some() && probed.tags.duration.is_some()) {
+        return probed;
+    }
+    match (probed, read_embedded_tags(path)) {
+        (Some(probed), Some(embedded)) => {
+            let tags = probed.tags.or(embedded);
+            let mut stream = probed.stream;
+            if stream.bitrate_kbps.is_none() {
+                let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+                stream.set_file_size(size, tags.duration.unwrap_or_default());
+            }
+            Some(Probed { tags, stream })
+        }
+        (None, Some(tags)) => {
+            let mut stream = StreamInfo::default();
+            let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            stream.set_file_size(size, tags.duration.unwrap_or_default());
+            Some(Probed { tags, stream })
+        }
+        (probed, None) => probed,
+    }
+}
 
-    let file = File::open(path)?;
-    let source = Decoder::try_from(file)?;
-    Ok(source.total_duration().unwrap_or(Duration::from_secs(0)))
+fn probe_with_symphonia(path: &Path) -> Option<Probed> {
+    let file = File::open(path).ok()?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    probe_source(Box::new(file), size, path.extension().and_then(|ext| ext.to_str()), Some(path))
+}
+
+/// `probe` for a file that isn't on disk, such as a remote one read over Range
+/// requests: symphonia's pass alone, as the in-house parsers read from a path.
+pub fn probe_reader<R: Read + Seek + Send + Sync + 'static>(reader: R, size: u64, extension: &str) -> Option<Probed> {
+    let extension = Some(extension).filter(|extension| !extension.is_empty());
+    probe_source(Box::new(SizedReader { reader, size }), size, extension, None)
+}
+
+// A reader of known length, as the media source symphonia wants
+struct SizedReader<R> {
+    reader: R,
+    size: u64,
+}
+
+impl<R: Read> Read for SizedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for SizedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+impl<R: Read + Seek + Send + Sync> MediaSource for SizedReader<R> {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.size)
+    }
+}
+
+// The fixes for what symphonia misses need the file itself, so only happen with a `path`
+fn probe_source(source: Box<dyn MediaSource>, size: u64, extension: Option<&str>, path: Option<&Path>) -> Option<Probed> {
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+    let stream = MediaSourceStream::new(source, Default::default());
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    let mut tags = EmbeddedTags::default();
+    // An ID3v2 tag ahead of the stream (MP3) comes out of the probe; the
+    // container's own tags (FLAC, MP4, Ogg, WAV INFO) out of the reader
+    let has_id3 = match probed.metadata.get() {
+        Some(metadata) => {
+            metadata.current().into_iter().for_each(|revision| read_revision(revision, &mut tags));
+            true
+        }
+        None => false,
+    };
+    if let Some(revision) = probed.format.metadata().current() {
+        read_revision(revision, &mut tags);
+    }
+    let is_mpeg = probed
+        .format
+        .default_track()
+        .is_some_and(|track| [CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3].contains(&track.codec_params.codec));
+    if let Some(path) = path.filter(|_| is_mpeg && (tags.title.is_none() || tags.artist.is_none())) {
+        if let Some(fallback) = fallback_mp3_tags(path) {
+            tags = tags.or(fallback);
+        }
+    }
+    // symphonia skips ID3's TCMP frame (an iTunes addition), so an MP3 without
+    // an album artist is checked for it separately
+    if let Some(path) = path.filter(|_| has_id3 && tags.album_artist.is_none() && !tags.compilation) {
+        tags.compilation = Tag::read_from_path(path)
+            .ok()
+            .and_then(|tag| tag.get("TCMP").and_then(|frame| frame.content().text()).map(parse_flag))
+            .unwrap_or(false);
+    }
+
+    let mut stream = StreamInfo::default();
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        tags.duration = match (params.n_frames, params.time_base, params.sample_rate) {
+            (Some(frames), Some(time_base), _) => {
+                let time = time_base.calc_time(frames);
+                Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+            }
+            (Some(frames), None, Some(rate)) if rate > 0 => Some(Duration::from_secs_f64(frames as f64 / rate as f64)),
+            _ => None,
+        }
+        .filter(|duration| !duration.is_zero())
+        // Streams symphonia can't size still have headers to go by
+        .or_else(|| path.and_then(crate::duration::from_path));
+        stream = StreamInfo::from_codec_params(params, size, tags.duration.unwrap_or_default());
+    }
+    Some(Probed { tags, stream })
+}
+
+/// The tags of an MP3 without (a usable) ID3v2 tag, which symphonia doesn't
+/// look past: an APEv2 tag at the end, filled in from an ID3v1 tag after it.
+fn fallback_mp3_tags(path: &Path) -> Option<EmbeddedTags> {
+    let ape = crate::ape_tags::read_apev2_from_path(path).ok().flatten();
+    let v1 = File::open(path).ok().and_then(|file| id3::v1::Tag::read_from(file).ok()).map(id3v1_tags);
+    match (ape, v1) {
+        (Some(ape), Some(v1)) => Some(ape.or(v1)),
+        (ape, v1) => ape.or(v1),
+    }
+}
+
+// ID3v1 fields are fixed-width and space- or NUL-padded; track 0 means none
+fn id3v1_tags(tag: id3::v1::Tag) -> EmbeddedTags {
+    let text = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+    EmbeddedTags {
+        title: text(&tag.title),
+        artist: text(&tag.artist),
+        album: text(&tag.album),
+        track_number: tag.track.map(u32::from).filter(|track| *track > 0),
+        year: parse_year(&tag.year),
+        genre: tag.genre().map(str::to_string),
+        ..Default::default()
+    }
+}
+
+// The first value of each tag wins, so an ID3 tag read before the container's takes precedence
+fn read_revision(revision: &MetadataRevision, tags: &mut EmbeddedTags) {
+    for tag in revision.tags() {
+        let value = tag.value.to_string();
+        let text = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        if text.is_empty() {
+            continue;
+        }
+        let set = |field: &mut Option<String>| {
+            field.get_or_insert_with(|| text.to_string());
+        };
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => set(&mut tags.title),
+            Some(StandardTagKey::Artist) => set(&mut tags.artist),
+            Some(StandardTagKey::Album) => set(&mut tags.album),
+            Some(StandardTagKey::AlbumArtist) => set(&mut tags.album_artist),
+            Some(StandardTagKey::Lyrics) => tags.lyrics = prefer_synced(tags.lyrics.take(), text),
+            Some(StandardTagKey::Genre) => tags.genre = tags.genre.take().or_else(|| parse_genre(text)),
+            Some(StandardTagKey::TrackNumber) => tags.track_number = tags.track_number.or_else(|| parse_track_number(text)),
+            Some(StandardTagKey::Date | StandardTagKey::ReleaseDate | StandardTagKey::OriginalDate) => {
+                tags.year = tags.year.or_else(|| parse_year(text))
+            }
+            Some(StandardTagKey::Compilation) => tags.compilation |= matches!(tag.value, Value::Flag) || parse_flag(text),
+            // symphonia only maps the misspelt "COMPILEATION" Vorbis comment
+            None if tag.key.eq_ignore_ascii_case("COMPILATION") => tags.compilation |= parse_flag(text),
+            _ => {}
+        }
+    }
+    if tags.pictures.is_empty() {
+        for visual in revision.visuals() {
+            tags.add_picture(picture_type(visual.usage), visual.data.to_vec());
+        }
+    }
+}
+
+// symphonia's names for the ID3 / FLAC picture types, which are in the same order
+fn picture_type(usage: Option<StandardVisualKey>) -> u8 {
+    use StandardVisualKey::*;
+    match usage {
+        None => 0,
+        Some(FileIcon) => 1,
+        Some(OtherIcon) => 2,
+        Some(FrontCover) => FRONT_COVER,
+        Some(BackCover) => 4,
+        Some(Leaflet) => 5,
+        Some(Media) => 6,
+        Some(LeadArtistPerformerSoloist) => 7,
+        Some(ArtistPerformer) => 8,
+        Some(Conductor) => 9,
+        Some(BandOrchestra) => 10,
+        Some(Composer) => 11,
+        Some(Lyricist) => 12,
+        Some(RecordingLocation) => 13,
+        Some(RecordingSession) => 14,
+        Some(Performance) => 15,
+        Some(ScreenCapture) => 16,
+        // 17 is ID3's "a bright coloured fish", which symphonia has no name for
+        Some(Illustration) => 18,
+        Some(BandArtistLogo) => 19,
+        Some(PublisherStudioLogo) => 20,
+    }
+}
+
+/// Whether lyrics carry LRC timestamps, so they can follow the song.
+pub fn is_synced(lyrics: &str) -> bool {
+    !crate::player::parse_lrc(lyrics).is_empty()
+}
+
+/// Keep the lyrics already found unless they're plain text and `new` are timed,
+/// for files carrying both (`LYRICS` and `UNSYNCEDLYRICS`, say).
+pub fn prefer_synced(existing: Option<String>, new: &str) -> Option<String> {
+    match existing {
+        Some(existing) if is_synced(&existing) || !is_synced(new) => Some(existing),
+        _ => Some(new.to_string()),
+    }
+}
+
+/// An ID3 tag's synchronised (SYLT) lyrics as LRC text. symphonia doesn't read
+/// SYLT frames, so this takes a tag from the id3 crate. Timestamps counted in
+/// MPEG frames rather than milliseconds aren't used.
+pub fn id3_synced_lyrics(tag: &Tag) -> Option<String> {
+    let lyrics = tag
+        .synchronised_lyrics()
+        .find(|lyrics| lyrics.timestamp_format == TimestampFormat::Ms && !lyrics.content.is_empty())?;
+    Some(sylt_to_lrc(&lyrics.content))
+}
+
+// Taggers write SYLT either a line per entry, or a syllable per entry with a
+// line break starting the first syllable of each new line
+fn sylt_to_lrc(content: &[(u32, String)]) -> String {
+    let starts_line = |text: &str| text.starts_with(['\n', '\r']);
+    let by_syllable = content.iter().any(|(_, text)| starts_line(text));
+    let mut lines: Vec<(u32, String)> = Vec::new();
+    for (ms, text) in content {
+        match lines.last_mut() {
+            Some((_, line)) if by_syllable && !starts_line(text) => line.push_str(text),
+            _ => lines.push((*ms, text.trim_start_matches(['\n', '\r']).to_string())),
+        }
+    }
+    lines
+        .iter()
+        .map(|(ms, text)| format!("[{:02}:{:02}.{:03}]{}", ms / 60_000, ms / 1000 % 60, ms % 1000, text.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A genre as tags store it: plain text, or an ID3v1 genre number ("17", "(17)",
+/// "(17)Rock") that maps to a name.
+pub fn parse_genre(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (number, rest) = match text.strip_prefix('(').and_then(|inner| inner.split_once(')')) {
+        Some((number, rest)) => (number, rest.trim()),
+        None => (text, ""),
+    };
+    if !rest.is_empty() {
+        return Some(rest.to_string());
+    }
+    match number.parse::<u8>() {
+        Ok(genre_id) => id3::v1::Tag { genre_id, ..Default::default() }.genre().map(str::to_string),
+        Err(_) => Some(text.to_string()).filter(|text| !text.is_empty()),
+    }
+}
+
+// Embedded art is cached once per album; files without an album tag get a copy per image
+fn cache_cover(artist: Option<&str>, album: Option<&str>, picture: impl FnOnce() -> Option<Vec<u8>>) -> Option<String> {
+    match album.filter(|album| !album.trim().is_empty()) {
+        Some(album) => crate::cover_cache::store_album(artist.unwrap_or_default(), album, picture),
+        None => picture().and_then(|data| crate::cover_cache::store(&data)),
+    }
 }
 
 pub struct TrackMetadata;
@@ -106,3 +734,122 @@ impl TrackMetadata {
         extract_metadata(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::wav_bytes;
+
+    #[test]
+    fn test_probe_reads_tags_and_stream_in_one_pass() {
+        // A LIST/INFO chunk between the fmt and data chunks
+        let mut info = b"INFO".to_vec();
+        for (id, value) in [(b"INAM", "Tone"), (b"IART", "Me"), (b"ICRD", "1999"), (b"IGNR", "(17)")] {
+            info.extend_from_slice(id);
+            info.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            info.extend_from_slice(value.as_bytes());
+        }
+        let mut wav = wav_bytes(500, 8000);
+        let list = [b"LIST".as_slice(), &(info.len() as u32).to_le_bytes(), &info].concat();
+        wav.splice(36..36, list);
+        let riff_size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let path = std::env::temp_dir().join(format!("dioxus_music_probe_{}.wav", Uuid::new_v4()));
+        std::fs::write(&path, wav).unwrap();
+        let probed = probe(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let tags = probed.tags;
+        assert_eq!((tags.title.as_deref(), tags.artist.as_deref()), (Some("Tone"), Some("Me")));
+        assert_eq!((tags.year, tags.genre.as_deref()), (Some(1999), Some("Rock")));
+        assert_eq!(tags.duration, Some(Duration::from_millis(500)));
+        assert_eq!(probed.stream.summary(), "PCM · 130 kbps · 8 kHz · 16-bit · Mono");
+
+        assert_eq!(parse_genre("(17)Classic Rock").as_deref(), Some("Classic Rock"));
+        assert_eq!(parse_genre("Synthwave").as_deref(), Some("Synthwave"));
+    }
+
+    #[test]
+    fn test_synced_lyrics() {
+        let by_line = [(1_500, "First line".to_string()), (62_250, "Second line".to_string())];
+        assert_eq!(sylt_to_lrc(&by_line), "[00:01.500]First line\n[01:02.250]Second line");
+        let by_syllable = [
+            (1_000, "\nHel".to_string()),
+            (1_400, "lo ".to_string()),
+            (1_800, "there".to_string()),
+            (3_000, "\nBye".to_string()),
+        ];
+        assert_eq!(sylt_to_lrc(&by_syllable), "[00:01.000]Hello there\n[00:03.000]Bye");
+
+        let synced = "[00:01.00]Timed";
+        assert!(is_synced(synced) && !is_synced("Just words\non lines"));
+        assert_eq!(prefer_synced(Some("Just words".to_string()), synced).as_deref(), Some(synced));
+        assert_eq!(prefer_synced(Some(synced.to_string()), "Just words").as_deref(), Some(synced));
+        assert_eq!(prefer_synced(None, "Just words").as_deref(), Some("Just words"));
+    }
+
+    #[test]
+    fn test_write_stats() {
+        let path = std::env::temp_dir().join(format!("dioxus_music_stats_{}.mp3", Uuid::new_v4()));
+        std::fs::write(&path, [0xFF, 0xFB, 0x90, 0x00]).unwrap();
+        let mut tag = Tag::new();
+        tag.add_frame(Popularimeter { user: "Windows Media Player 9 Series".to_string(), rating: 1, counter: 7 });
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        write_stats(&path, StatsUpdate { rating: Some(Some(4)), plays: 2 }).unwrap();
+        let tag = Tag::read_from_path(&path).unwrap();
+        let popm: Vec<_> = tag.frames().filter_map(|frame| frame.content().popularimeter()).collect();
+        assert_eq!(popm, [&Popularimeter { user: "Windows Media Player 9 Series".to_string(), rating: 196, counter: 9 }]);
+        let fmps = |name: &str| tag.extended_texts().find(|text| text.description == name).map(|text| text.value.clone());
+        assert_eq!((fmps("FMPS_Rating").as_deref(), fmps("FMPS_Playcount").as_deref()), (Some("0.8"), Some("9")));
+
+        let mut update = StatsUpdate { rating: Some(Some(5)), plays: 1 };
+        update.merge(StatsUpdate { rating: Some(None), plays: 0 });
+        write_stats(&path, update).unwrap();
+        let tag = Tag::read_from_path(&path).unwrap();
+        let popm = tag.frames().find_map(|frame| frame.content().popularimeter()).unwrap();
+        assert_eq!((popm.rating, popm.counter), (0, 10));
+        assert!(tag.extended_texts().all(|text| text.description != "FMPS_Rating"));
+        std::fs::remove_file(&path).ok();
+
+        assert!(!can_write_stats(Path::new("song.ogg")));
+        assert!(write_stats(Path::new("song.ogg"), update).is_err());
+    }
+
+    #[test]
+    fn test_mp3_fallback_tags() {
+        let mut v1 = b"TAG".to_vec();
+        for (value, width) in [("Old Title ", 30), ("Old Artist", 30), ("Old Album", 30), ("1987", 4), ("", 28)] {
+            let mut field = value.as_bytes().to_vec();
+            field.resize(width, 0);
+            v1.extend(field);
+        }
+        v1.extend([0, 3, 17]);
+        let mut ape = 9u32.to_le_bytes().to_vec();
+        ape.extend(0u32.to_le_bytes());
+        ape.extend(b"Title\0New Title");
+        let items = ape.len() as u32;
+        ape.extend(b"APETAGEX");
+        ape.extend(2000u32.to_le_bytes());
+        ape.extend((items + 32).to_le_bytes());
+        ape.extend(1u32.to_le_bytes());
+        ape.extend([0; 12]);
+
+        let path = std::env::temp_dir().join(format!("dioxus_music_fallback_{}.mp3", Uuid::new_v4()));
+        std::fs::write(&path, [&[0xFF, 0xFB, 0x90, 0x00][..], &v1[..]].concat()).unwrap();
+        let tags = fallback_mp3_tags(&path).unwrap();
+        assert_eq!(
+            (tags.title.as_deref(), tags.artist.as_deref(), tags.album.as_deref()),
+            (Some("Old Title"), Some("Old Artist"), Some("Old Album"))
+        );
+        assert_eq!((tags.track_number, tags.year, tags.genre.as_deref()), (Some(3), Some(1987), Some("Rock")));
+
+        // An APEv2 tag ahead of the ID3v1 one wins where it has a value
+        std::fs::write(&path, [&[0xFF, 0xFB, 0x90, 0x00][..], &ape[..], &v1[..]].concat()).unwrap();
+        let tags = fallback_mp3_tags(&path).unwrap();
+        assert_eq!((tags.title.as_deref(), tags.artist.as_deref()), (Some("New Title"), Some("Old Artist")));
+        assert_eq!(read_tag_edit(&path).title, "New Title");
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,155 @@
+// Tags already read from local files, kept in the config dir by path, so
+// reopening the app or adding the same folder to another playlist doesn't parse
+// every file again. An entry only counts while the file's size and modification
+// time match what they were when it was read. Every user profile shares it.
+
+use crate::{FileStamp, Track};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// New entries written out at a time while a big folder is read
+const SAVE_EVERY: usize = 500;
+
+static CACHE: Lazy<Mutex<MetadataCache>> = Lazy::new(|| {
+    // Tests read throwaway files, which have no business in the real cache
+    if cfg!(test) {
+        return Mutex::default();
+    }
+    let file = crate::base_config_dir().ok().map(|dir| dir.join("metadata_cache.json"));
+    Mutex::new(file.map(MetadataCache::load).unwrap_or_default())
+});
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct Entry {
+    stamp: FileStamp,
+    track: Track,
+}
+
+#[derive(Default)]
+struct MetadataCache {
+    file: Option<PathBuf>,
+    entries: HashMap<String, Entry>,
+    // Entries added since the last save
+    unsaved: usize,
+}
+
+/// The cached track for `path`, under a fresh id, if the file is unchanged since.
+pub fn get(path: &Path, stamp: FileStamp) -> Option<Track> {
+    CACHE.lock().unwrap().get(path, stamp)
+}
+
+/// Remember what was read from `path`; saved every few hundred entries and on `flush`.
+pub fn insert(path: &Path, stamp: FileStamp, track: &Track) {
+    let mut cache = CACHE.lock().unwrap();
+    cache.insert(path, stamp, track);
+    if cache.unsaved >= SAVE_EVERY {
+        cache.save();
+    }
+}
+
+/// Save whatever was added since the last save, after a scan.
+pub fn flush() {
+    let mut cache = CACHE.lock().unwrap();
+    if cache.unsaved > 0 {
+        cache.save();
+    }
+}
+
+impl MetadataCache {
+    fn load(file: PathBuf) -> Self {
+        let mut entries: HashMap<String, Entry> = match std::fs::read_to_string(&file) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("[MetadataCache] 无法解析元数据缓存，将重新读取: {}", e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        // Files deleted since; a folder that's missing altogether (an unplugged
+        // drive) keeps its entries for when it's back
+        let before = entries.len();
+        entries.retain(|path, _| {
+            let path = Path::new(path);
+            path.exists() || !path.parent().is_some_and(Path::is_dir)
+        });
+        let unsaved = before - entries.len();
+        MetadataCache { file: Some(file), entries, unsaved }
+    }
+
+    fn get(&self, path: &Path, stamp: FileStamp) -> Option<Track> {
+        let entry = self.entries.get(path.to_string_lossy().as_ref()).filter(|entry| entry.stamp == stamp)?;
+        Some(Track { id: Uuid::new_v4().to_string(), ..entry.track.clone() })
+    }
+
+    fn insert(&mut self, path: &Path, stamp: FileStamp, track: &Track) {
+        let entry = Entry { stamp, track: track.clone() };
+        self.entries.insert(path.to_string_lossy().into_owned(), entry);
+        self.unsaved += 1;
+    }
+
+    fn save(&mut self) {
+        let Some(file) = self.file.as_ref() else { return };
+        // Written aside and renamed, so a crash mid-write doesn't lose the cache
+        let partial = file.with_extension("json.part");
+        let result = serde_json::to_string(&self.entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&partial, json).map_err(|e| e.to_string()))
+            .and_then(|()| std::fs::rename(&partial, file).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.unsaved = 0,
+            Err(e) => eprintln!("[MetadataCache] 保存元数据缓存失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrackSource;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_entries_last_while_the_file_is_unchanged() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_metadata_cache_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let song = dir.join("song.flac");
+        let gone = dir.join("gone.flac");
+        std::fs::write(&song, "audio").unwrap();
+        let track = Track {
+            id: "first".to_string(),
+            path: song.to_string_lossy().into_owned(),
+            title: "Song".to_string(),
+            artist: "Band".to_string(),
+            album: "LP".to_string(),
+            album_artist: None,
+            track_number: Some(2),
+            year: None,
+            genre: None,
+            duration: Duration::from_secs(180),
+            cover: None,
+            source: TrackSource::Local,
+            gain_db: 0.0,
+        };
+        let stamp = FileStamp { size: 5, modified: SystemTime::UNIX_EPOCH };
+
+        let file = dir.join("metadata_cache.json");
+        let mut cache = MetadataCache::load(file.clone());
+        cache.insert(&song, stamp, &track);
+        cache.insert(&gone, stamp, &track);
+        let cached = cache.get(&song, stamp).unwrap();
+        assert_ne!(cached.id, track.id);
+        assert_eq!(Track { id: track.id.clone(), ..cached }, track);
+        assert_eq!(cache.get(&song, FileStamp { size: 6, ..stamp }), None);
+        cache.save();
+        assert_eq!(cache.unsaved, 0);
+
+        // Read back, less the file that no longer exists
+        let reloaded = MetadataCache::load(file);
+        assert!(reloaded.get(&song, stamp).is_some());
+        assert_eq!(reloaded.get(&gone, stamp), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,547 @@
+// Tags, cover art and duration for MP4 audio (.m4a / .mp4 / .aac in MP4). id3 and
+// metaflac don't read iTunes-style atoms, so this walks the box tree directly:
+// moov/mvhd for the duration and moov/udta/meta/ilst for the tags. The tag
+// editor writes back through the same tree.
+
+use crate::metadata::{EmbeddedTags, TagEdit};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+// moov holds sample tables as well as tags; anything larger is not worth reading
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+
+// ilst data atom type codes
+const DATA_UTF8: u32 = 1;
+const DATA_UTF16: u32 = 2;
+
+/// `Ok(None)` if the file isn't an MP4 container.
+pub fn read_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    Ok(read_moov(&mut File::open(path)?)?.map(|moov| parse_moov(&moov)))
+}
+
+/// Just the movie header's duration, from any seekable source.
+pub fn read_duration<R: Read + Seek>(reader: &mut R) -> Option<Duration> {
+    let moov = read_moov(reader).ok()??;
+    parse_mvhd(find_box(&moov, b"mvhd")?)
+}
+
+/// Sample entry type of the first audio track, e.g. "mp4a" (AAC) or "alac".
+pub fn audio_codec(path: &Path) -> Option<String> {
+    let moov = read_moov(&mut File::open(path).ok()?).ok()??;
+    let codec = boxes(&moov).filter(|(kind, _)| kind == b"trak").find_map(|(_, trak)| {
+        let mdia = find_box(trak, b"mdia")?;
+        // hdlr: version/flags, pre_defined, then the handler type
+        if find_box(mdia, b"hdlr")?.get(8..12)? != b"soun" {
+            return None;
+        }
+        let stbl = find_box(find_box(mdia, b"minf")?, b"stbl")?;
+        // stsd: version/flags and entry count before the sample entries
+        let (kind, _) = boxes(find_box(stbl, b"stsd")?.get(8..)?).next()?;
+        Some(String::from_utf8_lossy(&kind).into_owned())
+    });
+    codec
+}
+
+fn read_moov<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    // Every MP4 file starts with ftyp; checking it keeps MP3s with a stray
+    // "moov" somewhere in the data from being misread
+    let mut header = [0u8; 8];
+    if reader.read_exact(&mut header).is_err() || &header[4..8] != b"ftyp" {
+        return Ok(None);
+    }
+
+    // moov may come before or after mdat, so skip over top-level boxes until it turns up
+    let mut pos = 0u64;
+    // pos saturates on sizes that run past the end, which ends the walk here
+    while pos.checked_add(8).is_some_and(|end| end <= len) {
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.read_exact(&mut header)?;
+        let size32 = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        let (size, header_len) = match size32 {
+            0 => (len - pos, 8),
+            1 => {
+                let mut large = [0u8; 8];
+                reader.read_exact(&mut large)?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size, 8),
+        };
+        if size < header_len {
+            break;
+        }
+
+        if &header[4..8] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_BYTES {
+                return Ok(None);
+            }
+            let mut body = Vec::new();
+            reader.take(body_len).read_to_end(&mut body)?;
+            return Ok(Some(body));
+        }
+        pos = pos.saturating_add(size);
+    }
+
+    Ok(None)
+}
+
+fn parse_moov(moov: &[u8]) -> EmbeddedTags {
+    let mut tags = EmbeddedTags::default();
+    for (kind, body) in boxes(moov) {
+        match &kind {
+            b"mvhd" => tags.duration = parse_mvhd(body),
+            b"udta" => {
+                if let Some(ilst) = find_box(body, b"meta").and_then(|meta| find_box(meta_children(meta), b"ilst")) {
+                    parse_ilst(ilst, &mut tags);
+                }
+            }
+            _ => {}
+        }
+    }
+    tags
+}
+
+// Movie header: version/flags, then timescale and duration (64-bit in version 1)
+fn parse_mvhd(body: &[u8]) -> Option<Duration> {
+    let (timescale, duration) = match *body.first()? {
+        0 => (be_u32(body.get(12..16)?), be_u32(body.get(16..20)?) as u64),
+        1 => (be_u32(body.get(20..24)?), u64::from_be_bytes(body.get(24..32)?.try_into().ok()?)),
+        _ => return None,
+    };
+    // All ones means the duration is unknown
+    if timescale == 0 || duration == u64::MAX || duration == u32::MAX as u64 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+// iTunes writes meta as a full box (4 bytes of version/flags before its children);
+// QuickTime writes it as a plain box. A plain box starts straight with a child header.
+fn meta_children(meta: &[u8]) -> &[u8] {
+    match meta.get(4..8) {
+        Some(kind) if kind == b"hdlr" || kind == b"ilst" => meta,
+        _ => meta.get(4..).unwrap_or_default(),
+    }
+}
+
+fn parse_ilst(ilst: &[u8], tags: &mut EmbeddedTags) {
+    for (kind, item) in boxes(ilst) {
+        // Cover art holds one data box per image; MP4 doesn't say which is which
+        if &kind == b"covr" {
+            let images = boxes(item).filter(|(k, _)| k == b"data").filter_map(|(_, body)| parse_data(body));
+            for (_, image) in images {
+                tags.add_picture(0, image.to_vec());
+            }
+            continue;
+        }
+        let Some((data_type, value)) = find_box(item, b"data").and_then(parse_data) else {
+            continue;
+        };
+        match &kind {
+            b"\xa9nam" => tags.title = tags.title.take().or_else(|| text(data_type, value)),
+            b"\xa9ART" => tags.artist = tags.artist.take().or_else(|| text(data_type, value)),
+            b"\xa9alb" => tags.album = tags.album.take().or_else(|| text(data_type, value)),
+            b"aART" => tags.album_artist = tags.album_artist.take().or_else(|| text(data_type, value)),
+            // Two bytes of padding, then track and total as 16-bit integers
+            b"trkn" => {
+                let track = value.get(2..4).map(|n| u16::from_be_bytes([n[0], n[1]]) as u32);
+                tags.track_number = tags.track_number.or(track.filter(|n| *n > 0));
+            }
+            b"\xa9day" => tags.year = tags.year.or_else(|| text(data_type, value).as_deref().and_then(crate::metadata::parse_year)),
+            b"\xa9gen" => tags.genre = tags.genre.take().or_else(|| text(data_type, value)),
+            // A one byte boolean
+            b"cpil" => tags.compilation |= value.first().is_some_and(|flag| *flag != 0),
+            b"\xa9lyr" => tags.lyrics = tags.lyrics.take().or_else(|| text(data_type, value)),
+            _ => {}
+        }
+    }
+    tags.fill_artist_from_album_artist();
+}
+
+// data atom: 1 byte version, 3 bytes type code, 4 bytes locale, then the value
+fn parse_data(data: &[u8]) -> Option<(u32, &[u8])> {
+    let data_type = be_u32(data.get(..4)?) & 0x00ff_ffff;
+    Some((data_type, data.get(8..)?))
+}
+
+fn text(data_type: u32, value: &[u8]) -> Option<String> {
+    let text = match data_type {
+        DATA_UTF8 => String::from_utf8_lossy(value).into_owned(),
+        DATA_UTF16 => {
+            let units: Vec<u16> = value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+// Child boxes of an in-memory box body. Stops at the first malformed header.
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    box_spans(data).map(move |(kind, span, header_len)| (kind, &data[span.start + header_len..span.end]))
+}
+
+// Where each child box sits in `data`, header included, and its header length
+fn box_spans(data: &[u8]) -> impl Iterator<Item = ([u8; 4], Range<usize>, usize)> + '_ {
+    let mut pos = 0usize;
+    std::iter::from_fn(move || {
+        let header = data.get(pos..pos + 8)?;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        let (size, header_len) = match be_u32(&header[..4]) as usize {
+            0 => (data.len() - pos, 8),
+            1 => (usize::try_from(u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().ok()?)).ok()?, 16),
+            size => (size, 8),
+        };
+        if size < header_len || data.len() < pos.checked_add(size)? {
+            return None;
+        }
+        let span = pos..pos + size;
+        pos += size;
+        Some((kind, span, header_len))
+    })
+}
+
+fn make_box(kind: &[u8; 4], body: &[u8]) -> io::Result<Vec<u8>> {
+    let size = u32::try_from(body.len() + 8).map_err(|_| invalid("box too large"))?;
+    let mut out = size.to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    Ok(out)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// The ilst items the tag editor owns; everything else is carried over
+const EDITED_ITEMS: [&[u8; 4]; 7] = [b"\xa9nam", b"\xa9ART", b"\xa9alb", b"trkn", b"\xa9day", b"\xa9gen", b"gnre"];
+
+/// Write the editor's fields into the file's ilst, creating udta/meta/ilst if
+/// the file has none. Other items (cover, lyrics...) are kept. Chunk offsets
+/// pointing past moov are moved by however much it grew or shrank, so files
+/// with moov ahead of mdat keep playing. The file is replaced through a
+/// temporary copy next to it.
+pub fn write_to_path(path: &Path, edit: &TagEdit) -> io::Result<()> {
+    let data = std::fs::read(path)?;
+    if data.get(4..8) != Some(b"ftyp") {
+        return Err(invalid("not an MP4 file"));
+    }
+    let (_, moov, header_len) =
+        box_spans(&data).find(|(kind, _, _)| kind == b"moov").ok_or_else(|| invalid("no moov box"))?;
+    let old_body = &data[moov.start + header_len..moov.end];
+    let mut new_body = rewrite_moov(old_body, edit)?;
+
+    let grown = (new_body.len() + 8) as i64 - moov.len() as i64;
+    shift_chunk_offsets(&mut new_body, moov.end as u64, grown)?;
+
+    let mut out = Vec::with_capacity(data.len() + new_body.len());
+    out.extend_from_slice(&data[..moov.start]);
+    out.extend(make_box(b"moov", &new_body)?);
+    out.extend_from_slice(&data[moov.end..]);
+
+    let temp = path.with_extension("tagedit.tmp");
+    std::fs::write(&temp, &out)?;
+    std::fs::set_permissions(&temp, std::fs::metadata(path)?.permissions())?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+fn rewrite_moov(moov: &[u8], edit: &TagEdit) -> io::Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(moov.len());
+    let mut has_udta = false;
+    for (kind, span, header_len) in box_spans(moov) {
+        if &kind == b"udta" {
+            has_udta = true;
+            body.extend(make_box(b"udta", &rewrite_udta(&moov[span.start + header_len..span.end], edit)?)?);
+        } else {
+            body.extend_from_slice(&moov[span]);
+        }
+    }
+    if !has_udta {
+        body.extend(make_box(b"udta", &rewrite_udta(&[], edit)?)?);
+    }
+    Ok(body)
+}
+
+fn rewrite_udta(udta: &[u8], edit: &TagEdit) -> io::Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(udta.len());
+    let mut has_meta = false;
+    for (kind, span, header_len) in box_spans(udta) {
+        if &kind == b"meta" && !has_meta {
+            has_meta = true;
+            let meta = &udta[span.start + header_len..span.end];
+            // Keep the full-box version/flags, if it has them
+            let children = meta_children(meta);
+            let mut meta_body = meta[..meta.len() - children.len()].to_vec();
+            let mut has_ilst = false;
+            for (kind, span, header_len) in box_spans(children) {
+                if &kind == b"ilst" {
+                    has_ilst = true;
+                    meta_body.extend(make_box(b"ilst", &rewrite_ilst(&children[span.start + header_len..span.end], edit)?)?);
+                } else {
+                    meta_body.extend_from_slice(&children[span]);
+                }
+            }
+            if !has_ilst {
+                meta_body.extend(make_box(b"ilst", &rewrite_ilst(&[], edit)?)?);
+            }
+            body.extend(make_box(b"meta", &meta_body)?);
+        } else {
+            body.extend_from_slice(&udta[span]);
+        }
+    }
+    if !has_meta {
+        // iTunes-style: a full box with a metadata handler ahead of ilst
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+        let mut meta_body = vec![0u8; 4];
+        meta_body.extend(make_box(b"hdlr", &hdlr)?);
+        meta_body.extend(make_box(b"ilst", &rewrite_ilst(&[], edit)?)?);
+        body.extend(make_box(b"meta", &meta_body)?);
+    }
+    Ok(body)
+}
+
+fn rewrite_ilst(ilst: &[u8], edit: &TagEdit) -> io::Result<Vec<u8>> {
+    // trkn also holds the album's track count, which the editor leaves alone
+    let track_total = find_box(ilst, b"trkn")
+        .and_then(|item| find_box(item, b"data"))
+        .and_then(parse_data)
+        .and_then(|(_, value)| value.get(4..6).map(|n| [n[0], n[1]]))
+        .unwrap_or([0, 0]);
+
+    let mut body = Vec::with_capacity(ilst.len());
+    for (kind, span, _) in box_spans(ilst) {
+        if !EDITED_ITEMS.contains(&&kind) {
+            body.extend_from_slice(&ilst[span]);
+        }
+    }
+    let item = |kind: &[u8; 4], data_type: u32, value: &[u8]| -> io::Result<Vec<u8>> {
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(value);
+        make_box(kind, &make_box(b"data", &data)?)
+    };
+    for (kind, value) in [(b"\xa9nam", &edit.title), (b"\xa9ART", &edit.artist), (b"\xa9alb", &edit.album), (b"\xa9gen", &edit.genre)] {
+        if !value.trim().is_empty() {
+            body.extend(item(kind, DATA_UTF8, value.trim().as_bytes())?);
+        }
+    }
+    if let Some(year) = edit.year {
+        body.extend(item(b"\xa9day", DATA_UTF8, year.to_string().as_bytes())?);
+    }
+    if let Some(track) = edit.track_number.and_then(|n| u16::try_from(n).ok()).filter(|n| *n > 0) {
+        let [hi, lo] = track.to_be_bytes();
+        body.extend(item(b"trkn", 0, &[0, 0, hi, lo, track_total[0], track_total[1], 0, 0])?);
+    }
+    Ok(body)
+}
+
+// Add `by` to every stco / co64 chunk offset at or past `from`, anywhere in moov's traks
+fn shift_chunk_offsets(moov: &mut [u8], from: u64, by: i64) -> io::Result<()> {
+    if by == 0 {
+        return Ok(());
+    }
+    let mut tables = Vec::new();
+    collect_offset_tables(moov, 0, &mut tables);
+    for (kind, range) in tables {
+        let table = &mut moov[range];
+        let count = table.get(4..8).map_or(0, be_u32) as usize;
+        let width = if &kind == b"co64" { 8 } else { 4 };
+        for i in 0..count {
+            let Some(entry) = table.get_mut(8 + i * width..8 + (i + 1) * width) else { break };
+            let offset = if width == 8 { u64::from_be_bytes(entry.try_into().unwrap()) } else { be_u32(entry) as u64 };
+            if offset < from {
+                continue;
+            }
+            let moved = offset.checked_add_signed(by).ok_or_else(|| invalid("chunk offset out of range"))?;
+            if width == 8 {
+                entry.copy_from_slice(&moved.to_be_bytes());
+            } else {
+                let moved = u32::try_from(moved).map_err(|_| invalid("chunk offset out of range"))?;
+                entry.copy_from_slice(&moved.to_be_bytes());
+            }
+        }
+    }
+    Ok(())
+}
+
+// The bodies of every stco / co64 under trak/mdia/minf/stbl, as ranges of `data`
+// (itself at `base` in the buffer being patched)
+fn collect_offset_tables(data: &[u8], base: usize, tables: &mut Vec<([u8; 4], Range<usize>)>) {
+    for (kind, span, header_len) in box_spans(data) {
+        let body = base + span.start + header_len..base + span.end;
+        match &kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                collect_offset_tables(&data[span.start + header_len..span.end], body.start, tables)
+            }
+            b"stco" | b"co64" => tables.push((kind, body)),
+            _ => {}
+        }
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn item(kind: &[u8; 4], data_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(value);
+        atom(kind, &atom(b"data", &data))
+    }
+
+    fn write_temp(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_itunes_tags_after_mdat() {
+        let mut mvhd = vec![0u8; 100];
+        mvhd[12..16].copy_from_slice(&44_100u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&(44_100u32 * 3).to_be_bytes());
+
+        let ilst = [
+            item(b"\xa9nam", DATA_UTF8, "Títle".as_bytes()),
+            item(b"aART", DATA_UTF8, b"Album Artist"),
+            item(b"\xa9alb", DATA_UTF8, b"Album\0"),
+            item(b"\xa9lyr", DATA_UTF8, b"la la"),
+            item(b"covr", 13, b"\xff\xd8jpeg"),
+            item(b"trkn", 0, &[0, 0, 0, 1, 0, 9, 0, 0]),
+        ]
+        .concat();
+        let mut meta = vec![0u8; 4];
+        meta.extend(atom(b"hdlr", &[0; 25]));
+        meta.extend(atom(b"ilst", &ilst));
+
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"soun");
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(atom(b"alac", &[0; 28]));
+        let stbl = atom(b"stbl", &atom(b"stsd", &stsd));
+        let mdia = atom(b"mdia", &[atom(b"hdlr", &hdlr), atom(b"minf", &stbl)].concat());
+
+        let moov = atom(
+            b"moov",
+            &[atom(b"mvhd", &mvhd), atom(b"trak", &mdia), atom(b"udta", &atom(b"meta", &meta))].concat(),
+        );
+        let file = [atom(b"ftyp", b"M4A \0\0\0\0"), atom(b"mdat", &[0x55; 300]), moov].concat();
+        let path = write_temp("tags.m4a", &file);
+
+        let tags = read_from_path(&path).unwrap().unwrap();
+        let codec = audio_codec(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(codec.as_deref(), Some("alac"));
+        assert_eq!(tags.title.as_deref(), Some("Títle"));
+        assert_eq!(tags.artist.as_deref(), Some("Album Artist"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Album Artist"));
+        assert_eq!(tags.album.as_deref(), Some("Album"));
+        assert_eq!(tags.track_number, Some(1));
+        assert_eq!(tags.lyrics.as_deref(), Some("la la"));
+        assert_eq!(tags.cover(), Some(&b"\xff\xd8jpeg"[..]));
+        assert_eq!(tags.duration, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_writes_tags_and_moves_chunk_offsets() {
+        let ilst = [item(b"\xa9nam", DATA_UTF8, b"Old"), item(b"covr", 13, b"\xff\xd8jpeg"), item(b"trkn", 0, &[0, 0, 0, 1, 0, 9, 0, 0])]
+            .concat();
+        let udta = atom(b"udta", &atom(b"meta", &[vec![0u8; 4], atom(b"hdlr", &[0; 25]), atom(b"ilst", &ilst)].concat()));
+        let build = |chunk_offset: u32| {
+            let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stco.extend_from_slice(&chunk_offset.to_be_bytes());
+            let trak = atom(b"trak", &atom(b"mdia", &atom(b"minf", &atom(b"stbl", &atom(b"stco", &stco)))));
+            atom(b"moov", &[atom(b"mvhd", &[0; 100]), trak, udta.clone()].concat())
+        };
+        // moov ahead of mdat, with the one chunk pointing at mdat's payload
+        let ftyp = atom(b"ftyp", b"M4A \0\0\0\0");
+        let chunk_offset = (ftyp.len() + build(0).len() + 8) as u32;
+        let file = [ftyp, build(chunk_offset), atom(b"mdat", b"AUDIO")].concat();
+        let path = write_temp("edit.m4a", &file);
+
+        let edit = TagEdit {
+            title: "New Title".to_string(),
+            artist: "Artist".to_string(),
+            track_number: Some(3),
+            year: Some(1997),
+            genre: "Electronic".to_string(),
+            ..Default::default()
+        };
+        write_to_path(&path, &edit).unwrap();
+        let tags = read_from_path(&path).unwrap().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(tags.title.as_deref(), Some("New Title"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.album, None);
+        assert_eq!((tags.track_number, tags.year, tags.genre.as_deref()), (Some(3), Some(1997), Some("Electronic")));
+        assert_eq!(tags.cover(), Some(&b"\xff\xd8jpeg"[..]));
+        let ilst = boxes(&data).find(|(k, _)| k == b"moov").and_then(|(_, moov)| {
+            find_box(find_box(find_box(moov, b"udta")?, b"meta")?.get(4..)?, b"ilst").map(<[u8]>::to_vec)
+        });
+        let trkn = find_box(&ilst.unwrap(), b"trkn").and_then(|item| find_box(item, b"data")).map(|data| data[8..].to_vec());
+        assert_eq!(trkn.as_deref(), Some(&[0, 0, 0, 3, 0, 9, 0, 0][..]));
+
+        let moov = boxes(&data).find(|(k, _)| k == b"moov").unwrap().1;
+        let stco = [b"trak", b"mdia", b"minf", b"stbl", b"stco"].iter().try_fold(moov, |body, kind| find_box(body, kind)).unwrap();
+        let moved = be_u32(&stco[8..12]) as usize;
+        assert_eq!(&data[moved..moved + 5], b"AUDIO");
+    }
+
+    #[test]
+    fn test_rejects_non_mp4_and_truncated_files() {
+        let path = write_temp("not.m4a", b"ID3\x04\0\0\0\0\0\0moov");
+        assert!(read_from_path(&path).unwrap().is_none());
+
+        // QuickTime-style meta, version 1 mvhd, cut off in the middle of ilst
+        let mut mvhd = vec![1u8; 4];
+        mvhd.extend_from_slice(&[0; 16]);
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&2500u64.to_be_bytes());
+        let ilst = atom(b"ilst", &item(b"\xa9ART", DATA_UTF8, b"Artist"));
+        let udta = atom(b"udta", &atom(b"meta", &ilst[..ilst.len() - 3]));
+        let file = [atom(b"ftyp", b"M4A "), atom(b"moov", &[atom(b"mvhd", &mvhd), udta].concat())].concat();
+        std::fs::write(&path, &file).unwrap();
+
+        let tags = read_from_path(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(tags.artist, None);
+        assert_eq!(tags.duration, Some(Duration::from_millis(2500)));
+
+        // A 64-bit box size that runs past the end of everything
+        let mut huge = vec![0, 0, 0, 1];
+        huge.extend_from_slice(b"free");
+        huge.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        let path = write_temp("huge.m4a", &[atom(b"ftyp", b"M4A "), huge].concat());
+        assert!(read_from_path(&path).unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
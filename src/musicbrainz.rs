@@ -0,0 +1,349 @@
+// Suggested tags from MusicBrainz's recording search, for files whose own tags
+// are missing or placeholders like "Track 01". Whatever the tags still say is
+// asked for as is; the length, the track number and the folder names fill in
+// for the rest. Picking a candidate only fills the tag editor; nothing is
+// written until it's saved. Albums with no art of their own get their front
+// cover from the Cover Art Archive, which files art by MusicBrainz id.
+
+use crate::metadata::{parse_year, TagEdit};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+const SEARCH_URL: &str = "https://musicbrainz.org/ws/2/recording";
+const RELEASE_GROUP_URL: &str = "https://musicbrainz.org/ws/2/release-group";
+const COVER_ART_URL: &str = "https://coverartarchive.org/release-group";
+// MusicBrainz turns away requests that don't say who is asking
+const USER_AGENT: &str = concat!("dioxusmusic/", env!("CARGO_PKG_VERSION"), " ( https://github.com/bboysingle/dioxusmusic )");
+const RESULT_LIMIT: usize = 10;
+// A recording is on many releases (singles, compilations...); offer only the first few
+const RELEASES_PER_RECORDING: usize = 3;
+// How far the recording's length may be from the file's
+const LENGTH_SLACK_MS: u64 = 3000;
+// Albums found with a lower score are likely someone else's
+const MIN_ALBUM_SCORE: u32 = 90;
+
+/// One suggestion: a recording as it appears on one release.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub track_number: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub length: Option<Duration>,
+    // MusicBrainz's own 0-100 match score
+    pub score: u32,
+}
+
+/// Whether a title says nothing about the song: "Track 01", "AudioTrack 5",
+/// "07" or nothing at all.
+pub fn is_placeholder_title(title: &str) -> bool {
+    let title = title.trim().to_lowercase();
+    let rest = title.strip_prefix("audio").unwrap_or(&title).trim_start();
+    let rest = rest.strip_prefix("track").unwrap_or(rest);
+    rest.chars().all(|c| c.is_ascii_digit() || c.is_whitespace() || matches!(c, '-' | '_' | '.'))
+}
+
+fn is_blank_artist(artist: &str) -> bool {
+    let artist = artist.trim();
+    artist.is_empty() || artist == "Unknown Artist"
+}
+
+/// Tags worth looking up: a placeholder title or no artist.
+pub fn looks_untagged(edit: &TagEdit) -> bool {
+    is_placeholder_title(&edit.title) || is_blank_artist(&edit.artist)
+}
+
+// A quoted Lucene phrase for `field`
+fn phrase(field: &str, value: &str) -> String {
+    let escaped = value.trim().replace('\\', "\\\\").replace('"', "\\\"");
+    format!("{}:\"{}\"", field, escaped)
+}
+
+// "01 - Song", "01. Song" or "Song" -> the number and the name
+fn split_leading_number(stem: &str) -> (Option<u32>, &str) {
+    let digits = stem.len() - stem.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = stem[..digits].parse().ok().filter(|n| *n > 0);
+    let name = stem[digits..].trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '_' | '.'));
+    (number, name)
+}
+
+/// The search for a file with these tags and this length. What the tags leave
+/// out is guessed from the file name and the Album / Artist folders it sits in,
+/// and those guesses only rank results rather than rule them out. None when
+/// there's nothing to search by.
+pub fn search_query(path: &Path, edit: &TagEdit, duration: Duration) -> Option<String> {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let folder = |levels: usize| {
+        path.ancestors()
+            .nth(levels)
+            .and_then(|dir| dir.file_name())
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.trim().is_empty())
+    };
+    let (file_number, file_title) = split_leading_number(stem);
+
+    let mut required = Vec::new();
+    let mut optional = Vec::new();
+    if !is_placeholder_title(&edit.title) {
+        required.push(phrase("recording", &edit.title));
+    } else if !is_placeholder_title(file_title) {
+        optional.push(phrase("recording", file_title));
+    }
+    if !is_blank_artist(&edit.artist) {
+        required.push(phrase("artist", &edit.artist));
+    } else if let Some(artist) = folder(2) {
+        optional.push(phrase("artist", artist));
+    }
+    if !edit.album.trim().is_empty() && edit.album.trim() != "Unknown Album" {
+        required.push(phrase("release", &edit.album));
+    } else if let Some(album) = folder(1) {
+        optional.push(phrase("release", album));
+    }
+    // "Track 05.mp3"
+    let placeholder_number = is_placeholder_title(stem)
+        .then(|| stem.trim_start_matches(|c: char| !c.is_ascii_digit()).parse().ok())
+        .flatten();
+    if let Some(number) = edit.track_number.or(file_number).or(placeholder_number) {
+        required.push(format!("tnum:{}", number));
+    }
+    if !duration.is_zero() {
+        let ms = duration.as_millis() as u64;
+        required.push(format!("dur:[{} TO {}]", ms.saturating_sub(LENGTH_SLACK_MS), ms + LENGTH_SLACK_MS));
+    }
+
+    if required.is_empty() && optional.is_empty() {
+        return None;
+    }
+    let terms: Vec<String> = required.into_iter().map(|term| format!("+{}", term)).chain(optional).collect();
+    Some(terms.join(" "))
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    recordings: Vec<RawRecording>,
+}
+
+#[derive(Deserialize)]
+struct RawRecording {
+    title: String,
+    #[serde(default)]
+    score: u32,
+    length: Option<u64>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<RawCredit>,
+    #[serde(rename = "first-release-date")]
+    first_release_date: Option<String>,
+    #[serde(default)]
+    releases: Vec<RawRelease>,
+    #[serde(default)]
+    tags: Vec<RawTag>,
+}
+
+#[derive(Deserialize)]
+struct RawCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct RawRelease {
+    title: String,
+    date: Option<String>,
+    #[serde(default)]
+    media: Vec<RawMedium>,
+}
+
+#[derive(Deserialize)]
+struct RawMedium {
+    #[serde(default)]
+    track: Vec<RawTrack>,
+}
+
+#[derive(Deserialize)]
+struct RawTrack {
+    number: String,
+}
+
+#[derive(Deserialize)]
+struct RawTag {
+    name: String,
+    #[serde(default)]
+    count: i32,
+}
+
+/// The candidates in a recording search response, best match first.
+pub fn parse_candidates(json: &str) -> Result<Vec<Candidate>, String> {
+    let response: SearchResponse =
+        serde_json::from_str(json).map_err(|e| format!("Unexpected answer from MusicBrainz: {}", e))?;
+    let mut candidates = Vec::new();
+    for recording in response.recordings {
+        let artist: String = recording.artist_credit.iter().map(|c| format!("{}{}", c.name, c.joinphrase)).collect();
+        let genre = recording.tags.iter().max_by_key(|tag| tag.count).map(|tag| tag.name.clone());
+        let length = recording.length.map(Duration::from_millis);
+        let candidate = |album: String, track_number: Option<u32>, date: Option<&str>| Candidate {
+            title: recording.title.clone(),
+            artist: artist.clone(),
+            album,
+            track_number,
+            year: date.or(recording.first_release_date.as_deref()).and_then(parse_year),
+            genre: genre.clone(),
+            length,
+            score: recording.score,
+        };
+        if recording.releases.is_empty() {
+            candidates.push(candidate(String::new(), None, None));
+        }
+        for release in recording.releases.iter().take(RELEASES_PER_RECORDING) {
+            let track_number = release.media.iter().flat_map(|medium| medium.track.first()).find_map(|track| track.number.parse().ok());
+            candidates.push(candidate(release.title.clone(), track_number, release.date.as_deref()));
+        }
+    }
+    candidates.dedup();
+    candidates.truncate(RESULT_LIMIT);
+    Ok(candidates)
+}
+
+#[derive(Deserialize)]
+struct ReleaseGroupResponse {
+    #[serde(default, rename = "release-groups")]
+    release_groups: Vec<RawReleaseGroup>,
+}
+
+#[derive(Deserialize)]
+struct RawReleaseGroup {
+    id: String,
+    #[serde(default)]
+    score: u32,
+}
+
+// The id of the best matching album in a release group search, if it's a close match
+fn parse_release_group(json: &str) -> Result<Option<String>, String> {
+    let response: ReleaseGroupResponse =
+        serde_json::from_str(json).map_err(|e| format!("Unexpected answer from MusicBrainz: {}", e))?;
+    Ok(response.release_groups.into_iter().find(|group| group.score >= MIN_ALBUM_SCORE).map(|group| group.id))
+}
+
+fn client() -> Result<Client, String> {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(20))
+        .build()
+        .map_err(|e| format!("创建HTTP客户端失败: {}", e))
+}
+
+fn get_json(client: &Client, url: &str, query: &str, limit: usize) -> Result<String, String> {
+    client
+        .get(url)
+        .query(&[("query", query), ("fmt", "json"), ("limit", limit.to_string().as_str())])
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .map_err(|e| format!("Couldn't reach MusicBrainz: {}", e))
+}
+
+/// Ask MusicBrainz for recordings matching `query` (from `search_query`).
+pub fn search(query: &str) -> Result<Vec<Candidate>, String> {
+    let candidates = parse_candidates(&get_json(&client()?, SEARCH_URL, query, RESULT_LIMIT)?)?;
+    eprintln!("[MusicBrainz] {} -> {} 个候选", query, candidates.len());
+    Ok(candidates)
+}
+
+/// The album's front cover from the Cover Art Archive, at most 500px across.
+/// None when MusicBrainz doesn't know the album or nobody has uploaded its art.
+pub fn front_cover(artist: &str, album: &str) -> Result<Option<Vec<u8>>, String> {
+    let client = client()?;
+    let query = format!("+{} +{}", phrase("releasegroup", album), phrase("artist", artist));
+    let Some(id) = parse_release_group(&get_json(&client, RELEASE_GROUP_URL, &query, 1)?)? else {
+        return Ok(None);
+    };
+    let response = client
+        .get(format!("{}/{}/front-500", COVER_ART_URL, id))
+        .send()
+        .map_err(|e| format!("Couldn't reach the Cover Art Archive: {}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let image = response
+        .error_for_status()
+        .and_then(|r| r.bytes())
+        .map_err(|e| format!("Couldn't download the cover: {}", e))?;
+    eprintln!("[MusicBrainz] 找到 {} - {} 的封面 ({} 字节)", artist, album, image.len());
+    Ok(Some(image.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholder_titles() {
+        for title in ["Track 01", "track01", "AudioTrack 5", "07", " ", "Track 1 - 02"] {
+            assert!(is_placeholder_title(title), "{:?}", title);
+        }
+        for title in ["Airbag", "Track of the Year"] {
+            assert!(!is_placeholder_title(title), "{:?}", title);
+        }
+        assert!(looks_untagged(&TagEdit { title: "Song".to_string(), artist: "Unknown Artist".to_string(), ..Default::default() }));
+        assert!(!looks_untagged(&TagEdit { title: "Song".to_string(), artist: "Band".to_string(), ..Default::default() }));
+    }
+
+    #[test]
+    fn test_search_query_falls_back_to_file_and_folders() {
+        let edit = TagEdit { title: "Track 02".to_string(), ..Default::default() };
+        let query = search_query(Path::new("/music/Radiohead/OK Computer/02 Paranoid Android.mp3"), &edit, Duration::from_secs(383));
+        assert_eq!(
+            query.as_deref(),
+            Some("+tnum:2 +dur:[380000 TO 386000] recording:\"Paranoid Android\" artist:\"Radiohead\" release:\"OK Computer\"")
+        );
+
+        let edit = TagEdit { title: "Say \"Hi\"".to_string(), artist: "Band".to_string(), album: "LP".to_string(), ..Default::default() };
+        let query = search_query(Path::new("Track 05.mp3"), &edit, Duration::ZERO);
+        assert_eq!(query.as_deref(), Some("+recording:\"Say \\\"Hi\\\"\" +artist:\"Band\" +release:\"LP\" +tnum:5"));
+    }
+
+    #[test]
+    fn test_parses_candidates() {
+        let json = r#"{"recordings": [{
+            "id": "r1", "score": 97, "title": "Paranoid Android", "length": 383493,
+            "artist-credit": [{"name": "Radiohead", "joinphrase": " & "}, {"name": "Guest"}],
+            "first-release-date": "1997-05-26",
+            "tags": [{"count": 1, "name": "rock"}, {"count": 4, "name": "alternative rock"}],
+            "releases": [
+                {"id": "a", "title": "OK Computer", "date": "1997-05-21", "media": [{"position": 1, "track": [{"id": "t", "number": "2"}]}]},
+                {"id": "b", "title": "Paranoid Android", "media": [{"track": [{"number": "A"}]}]}
+            ]
+        }, {"id": "r2", "score": 40, "title": "Other"}]}"#;
+        let candidates = parse_candidates(json).unwrap();
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(
+            candidates[0],
+            Candidate {
+                title: "Paranoid Android".to_string(),
+                artist: "Radiohead & Guest".to_string(),
+                album: "OK Computer".to_string(),
+                track_number: Some(2),
+                year: Some(1997),
+                genre: Some("alternative rock".to_string()),
+                length: Some(Duration::from_millis(383493)),
+                score: 97,
+            }
+        );
+        assert_eq!((candidates[1].track_number, candidates[1].year), (None, Some(1997)));
+        assert_eq!((candidates[2].album.as_str(), candidates[2].score), ("", 40));
+        assert!(parse_candidates("<html>").is_err());
+    }
+
+    #[test]
+    fn test_picks_a_close_album_match() {
+        let json = r#"{"release-groups": [{"id": "g1", "score": 100, "title": "OK Computer"}, {"id": "g2", "score": 95}]}"#;
+        assert_eq!(parse_release_group(json), Ok(Some("g1".to_string())));
+        assert_eq!(parse_release_group(r#"{"release-groups": [{"id": "g3", "score": 62}]}"#), Ok(None));
+        assert_eq!(parse_release_group(r#"{"count": 0}"#), Ok(None));
+    }
+}
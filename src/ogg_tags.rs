@@ -0,0 +1,318 @@
+// Tags, cover art and duration for Ogg Vorbis and Opus files. id3 and metaflac
+// don't look inside Ogg containers, so this reads the comment header directly.
+
+use crate::metadata::{parse_flag, parse_track_number, parse_year, prefer_synced, EmbeddedTags};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+// Comment headers carry embedded covers, but anything past this is not a tag block
+const MAX_HEADER_BYTES: usize = 16 * 1024 * 1024;
+// The last page is never larger than this, so the final granule position is in here
+const TAIL_BYTES: u64 = 65_307;
+const OPUS_GRANULE_RATE: u64 = 48_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Codec {
+    Vorbis { sample_rate: u32 },
+    Opus { pre_skip: u16 },
+}
+
+/// `Ok(None)` if the file isn't an Ogg Vorbis or Opus stream.
+pub fn read_from_path(path: &Path) -> io::Result<Option<EmbeddedTags>> {
+    let mut file = File::open(path)?;
+    let Some((codec, serial, comment)) = read_headers(&mut file)? else {
+        return Ok(None);
+    };
+
+    let mut tags = parse_comments(&comment).unwrap_or_default();
+
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    tags.duration = last_granule(&tail, serial).and_then(|granule| granule_to_duration(codec, granule));
+
+    Ok(Some(tags))
+}
+
+// The identification and comment packets of the first logical stream
+fn read_headers<R: Read>(reader: &mut R) -> io::Result<Option<(Codec, u32, Vec<u8>)>> {
+    let mut serial = None;
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut current = Vec::new();
+    let mut total = 0usize;
+
+    while packets.len() < 2 {
+        let mut header = [0u8; 27];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        if &header[..4] != b"OggS" {
+            return Ok(None);
+        }
+        let page_serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+        let mut segments = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut segments)?;
+        let mut body = vec![0u8; segments.iter().map(|s| *s as usize).sum()];
+        reader.read_exact(&mut body)?;
+
+        // Only follow the first stream; others (e.g. a skeleton track) are skipped
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+        total += body.len();
+        if total > MAX_HEADER_BYTES {
+            return Ok(None);
+        }
+
+        let mut offset = 0;
+        for segment in segments {
+            let segment = segment as usize;
+            current.extend_from_slice(&body[offset..offset + segment]);
+            offset += segment;
+            if segment < 255 {
+                packets.push(std::mem::take(&mut current));
+                if packets.len() == 2 {
+                    break;
+                }
+            }
+        }
+    }
+
+    let ident = &packets[0];
+    let codec = if ident.len() >= 16 && ident.starts_with(b"\x01vorbis") {
+        Codec::Vorbis {
+            sample_rate: u32::from_le_bytes(ident[12..16].try_into().unwrap()),
+        }
+    } else if ident.len() >= 12 && ident.starts_with(b"OpusHead") {
+        Codec::Opus {
+            pre_skip: u16::from_le_bytes(ident[10..12].try_into().unwrap()),
+        }
+    } else {
+        return Ok(None);
+    };
+
+    let comment = &packets[1];
+    let body = match codec {
+        Codec::Vorbis { .. } => comment.strip_prefix(b"\x03vorbis"),
+        Codec::Opus { .. } => comment.strip_prefix(b"OpusTags"),
+    };
+    Ok(body.map(|body| (codec, serial.unwrap_or_default(), body.to_vec())))
+}
+
+// Vorbis comment block: vendor string, then "KEY=value" entries
+fn parse_comments(data: &[u8]) -> Option<EmbeddedTags> {
+    let mut reader = ByteReader { data, pos: 0 };
+    let vendor_len = reader.u32_le()? as usize;
+    reader.take(vendor_len)?;
+
+    let mut tags = EmbeddedTags::default();
+
+    for _ in 0..reader.u32_le()? {
+        let len = reader.u32_le()? as usize;
+        let Some(entry) = reader.take(len) else { break };
+        let entry = String::from_utf8_lossy(entry);
+        let Some((key, value)) = entry.split_once('=') else { continue };
+        let key = key.to_ascii_uppercase();
+
+        match key.as_str() {
+            "TITLE" => tags.title = tags.title.take().or(Some(value.to_string())),
+            "ARTIST" => tags.artist = tags.artist.take().or(Some(value.to_string())),
+            "ALBUM" => tags.album = tags.album.take().or(Some(value.to_string())),
+            "ALBUMARTIST" | "ALBUM ARTIST" => tags.album_artist = tags.album_artist.take().or(Some(value.to_string())),
+            "TRACKNUMBER" => tags.track_number = tags.track_number.or_else(|| parse_track_number(value)),
+            "DATE" | "YEAR" => tags.year = tags.year.or_else(|| parse_year(value)),
+            "GENRE" => tags.genre = tags.genre.take().or(Some(value.to_string())),
+            "COMPILATION" => tags.compilation |= parse_flag(value),
+            "LYRICS" | "UNSYNCEDLYRICS" => tags.lyrics = prefer_synced(tags.lyrics.take(), value),
+            "METADATA_BLOCK_PICTURE" => {
+                if let Some((picture_type, data)) = STANDARD.decode(value.trim()).ok().and_then(|raw| parse_picture(&raw)) {
+                    tags.add_picture(u8::try_from(picture_type).unwrap_or(0), data);
+                }
+            }
+            // Pre-standard covers were the raw image, base64 encoded
+            "COVERART" => {
+                if let Ok(data) = STANDARD.decode(value.trim()) {
+                    tags.add_picture(0, data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(tags)
+}
+
+// FLAC picture block (big-endian): type, mime, description, dimensions, data
+fn parse_picture(raw: &[u8]) -> Option<(u32, Vec<u8>)> {
+    let mut reader = ByteReader { data: raw, pos: 0 };
+    let picture_type = reader.u32_be()?;
+    let mime_len = reader.u32_be()? as usize;
+    reader.take(mime_len)?;
+    let description_len = reader.u32_be()? as usize;
+    reader.take(description_len)?;
+    reader.take(16)?; // width, height, depth, colors
+    let data_len = reader.u32_be()? as usize;
+    Some((picture_type, reader.take(data_len)?.to_vec()))
+}
+
+// Granule position of the last page of `serial` in `data`
+fn last_granule(data: &[u8], serial: u32) -> Option<u64> {
+    let mut found = None;
+    let mut pos = 0;
+    while let Some(offset) = data.get(pos..)?.windows(4).position(|w| w == b"OggS") {
+        let page = pos + offset;
+        if let Some(header) = data.get(page..page + 27) {
+            let granule = i64::from_le_bytes(header[6..14].try_into().unwrap());
+            let page_serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+            // -1 marks a page on which no packet ends
+            if page_serial == serial && granule >= 0 {
+                found = Some(granule as u64);
+            }
+        }
+        pos = page + 4;
+    }
+    found
+}
+
+fn granule_to_duration(codec: Codec, granule: u64) -> Option<Duration> {
+    match codec {
+        Codec::Vorbis { sample_rate } if sample_rate > 0 => {
+            Some(Duration::from_secs_f64(granule as f64 / sample_rate as f64))
+        }
+        Codec::Opus { pre_skip } => {
+            let samples = granule.saturating_sub(pre_skip as u64);
+            Some(Duration::from_secs_f64(samples as f64 / OPUS_GRANULE_RATE as f64))
+        }
+        _ => None,
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let slice = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u32_be(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One page per packet; the CRC isn't checked by the reader so it stays zero
+    fn page(serial: u32, granule: i64, packet: &[u8]) -> Vec<u8> {
+        let mut segments = vec![255u8; packet.len() / 255];
+        segments.push((packet.len() % 255) as u8);
+        let mut page = b"OggS\x00\x00".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0; 8]);
+        page.push(segments.len() as u8);
+        page.extend_from_slice(&segments);
+        page.extend_from_slice(packet);
+        page
+    }
+
+    fn comment_block(entries: &[&str]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend_from_slice(&4u32.to_le_bytes());
+        block.extend_from_slice(b"test");
+        block.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            block.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            block.extend_from_slice(entry.as_bytes());
+        }
+        block
+    }
+
+    #[test]
+    fn test_read_opus_tags() {
+        let mut picture = Vec::new();
+        picture.extend_from_slice(&3u32.to_be_bytes());
+        picture.extend_from_slice(&10u32.to_be_bytes());
+        picture.extend_from_slice(b"image/jpeg");
+        picture.extend_from_slice(&0u32.to_be_bytes());
+        picture.extend_from_slice(&[0; 16]);
+        picture.extend_from_slice(&4u32.to_be_bytes());
+        picture.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xE0]);
+        let picture_entry = format!("METADATA_BLOCK_PICTURE={}", STANDARD.encode(&picture));
+
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&comment_block(&[
+            "title=Café",
+            "ARTIST=Someone",
+            "Album=Live",
+            "ALBUMARTIST=Various Artists",
+            "TRACKNUMBER=03/12",
+            "DATE=2001-04-02",
+            "GENRE=Jazz",
+            "COMPILATION=1",
+            &picture_entry,
+        ]));
+
+        let mut file = page(7, 0, &head);
+        file.extend(page(7, 0, &tags));
+        file.extend(page(7, 48_000 * 3 + 312, &[0; 300]));
+
+        let path = std::env::temp_dir().join(format!("dioxus_music_test_{}.opus", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &file).unwrap();
+        let result = read_from_path(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let tags = result.unwrap().expect("opus stream");
+        assert_eq!(tags.title.as_deref(), Some("Café"));
+        assert_eq!(tags.artist.as_deref(), Some("Someone"));
+        assert_eq!(tags.album.as_deref(), Some("Live"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Various Artists"));
+        assert_eq!(tags.track_number, Some(3));
+        assert_eq!((tags.year, tags.genre.as_deref()), (Some(2001), Some("Jazz")));
+        assert!(tags.compilation);
+        assert_eq!(tags.cover(), Some(&[0xFF, 0xD8, 0xFF, 0xE0][..]));
+        assert_eq!(tags.duration, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_read_vorbis_headers() {
+        let mut ident = b"\x01vorbis".to_vec();
+        ident.extend_from_slice(&0u32.to_le_bytes());
+        ident.push(2);
+        ident.extend_from_slice(&44_100u32.to_le_bytes());
+        ident.extend_from_slice(&[0; 14]);
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend_from_slice(&comment_block(&["TITLE=Song", "broken entry"]));
+
+        let mut data = page(1, 0, &ident);
+        data.extend(page(1, 0, &comment));
+        let (codec, serial, body) = read_headers(&mut data.as_slice()).unwrap().unwrap();
+        assert_eq!(codec, Codec::Vorbis { sample_rate: 44_100 });
+        assert_eq!(serial, 1);
+        assert_eq!(parse_comments(&body).unwrap().title.as_deref(), Some("Song"));
+
+        assert!(read_headers(&mut &b"RIFF....WAVEfmt "[..]).unwrap().is_none());
+        assert!(!matches!(read_headers(&mut &data[..40]), Ok(Some(_))));
+    }
+}
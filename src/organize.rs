@@ -0,0 +1,287 @@
+// Moving local files into a folder layout built from their tags, like
+// "Artist/Album/01 - Title.flac". A batch is all or nothing: if a move fails
+// partway, the files already moved are put back where they were.
+
+use crate::{archive, TrackSource, TrackStub};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PATTERN: &str = "{artist}/{album}/{track} - {title}";
+
+// Files next to a track with the same name that should move with it
+const SIDECAR_EXTENSIONS: [&str; 2] = ["lrc", "txt"];
+// Keeps each folder / file name well inside what filesystems allow
+const MAX_COMPONENT_CHARS: usize = 120;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+// Make one tag value safe to use as a folder or file name
+fn sanitize(component: &str) -> String {
+    let replaced: String = component
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .take(MAX_COMPONENT_CHARS)
+        .collect();
+    // Windows drops trailing dots and spaces, and a leading dot hides the file
+    let trimmed = replaced.trim_matches(|c: char| c == ' ' || c == '.');
+    if trimmed.is_empty() {
+        "Unknown".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn field(name: &str, track: &TrackStub) -> Option<String> {
+    let text = |value: &str| Some(value.trim()).filter(|v| !v.is_empty()).map(str::to_string);
+    match name {
+        "artist" => text(track.album_artist_or_artist()).or(Some("Unknown Artist".to_string())),
+        "track_artist" => text(&track.artist).or(Some("Unknown Artist".to_string())),
+        "album" => text(&track.album).or(Some("Unknown Album".to_string())),
+        "title" => text(&track.title),
+        "genre" => track.genre.as_deref().and_then(text),
+        "track" => track.track_number.map(|n| format!("{:02}", n)),
+        "year" => track.year.map(|year| year.to_string()),
+        _ => None,
+    }
+}
+
+// Fill in the `{field}`s of one path component. Unknown fields are left as
+// written; ones the track has no tag for become empty, and whatever separator
+// that leaves at the start ("- Title" without a number) is trimmed.
+fn fill(part: &str, track: &TrackStub) -> String {
+    let mut out = String::new();
+    let mut rest = part;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            rest = &rest[open..];
+            break;
+        };
+        let name = &rest[open + 1..open + close];
+        match field(name, track) {
+            Some(value) => out.push_str(&sanitize(&value)),
+            None if matches!(name, "title" | "genre" | "track" | "year") => {}
+            None => out.push_str(&rest[open..=open + close]),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    out.trim_start_matches(|c: char| " -._".contains(c)).to_string()
+}
+
+/// Where `track` goes under the organized root, without the extension.
+pub fn relative_path(pattern: &str, track: &TrackStub) -> PathBuf {
+    let path: PathBuf = pattern
+        .split(['/', '\\'])
+        .map(|part| fill(part, track))
+        .filter(|part| !part.is_empty())
+        .map(|part| sanitize(&part))
+        .collect();
+    if path.as_os_str().is_empty() {
+        PathBuf::from(sanitize(&track.title))
+    } else {
+        path
+    }
+}
+
+/// The moves that put `tracks` into `pattern` under `root`. Only local files
+/// outside archives are moved; ones already in place are left out, and two
+/// tracks that would land on the same name get " (2)", " (3)"... added.
+pub fn plan<'a>(root: &Path, pattern: &str, tracks: impl IntoIterator<Item = &'a TrackStub>) -> Vec<Move> {
+    let mut seen = HashSet::new();
+    let mut taken = HashSet::new();
+    let mut moves = Vec::new();
+    for track in tracks {
+        if track.source != TrackSource::Local || archive::split_path(&track.path).is_some() {
+            continue;
+        }
+        if !seen.insert(track.path.as_str()) {
+            continue;
+        }
+        let from = PathBuf::from(&track.path);
+        let extension = from.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let base = root.join(relative_path(pattern, track));
+        let with_extension = |stem: PathBuf| append_extension(stem, &extension);
+        let mut to = with_extension(base.clone());
+        let mut n = 1;
+        while to != from && (taken.contains(&to) || to.exists()) {
+            n += 1;
+            let mut numbered = base.clone().into_os_string();
+            numbered.push(format!(" ({})", n));
+            to = with_extension(PathBuf::from(numbered));
+        }
+        taken.insert(to.clone());
+        if to != from {
+            moves.push(Move { from, to });
+        }
+    }
+    moves
+}
+
+// `with_extension` would eat anything after a dot in the title ("Vol. 2")
+fn append_extension(stem: PathBuf, extension: &str) -> PathBuf {
+    if extension.is_empty() {
+        return stem;
+    }
+    let mut path = stem.into_os_string();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+// The track itself, then any lyrics / notes files named after it
+fn with_sidecars(m: &Move) -> Vec<Move> {
+    let mut moves = vec![m.clone()];
+    for extension in SIDECAR_EXTENSIONS {
+        let from = m.from.with_extension(extension);
+        if from.is_file() {
+            moves.push(Move { from, to: m.to.with_extension(extension) });
+        }
+    }
+    moves
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Renaming fails across drives, so fall back to copying
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        if let Err(e) = fs::remove_file(from) {
+            let _ = fs::remove_file(to);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// Remove `dir` and its parents while they're empty, stopping at `root`
+fn prune_empty(dir: &Path, root: &Path) {
+    let mut dir = Some(dir);
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Carry out `moves` along with their sidecar files. On failure every file is
+/// put back and the error for the move that failed is returned.
+pub fn apply(root: &Path, moves: &[Move]) -> io::Result<()> {
+    let mut done: Vec<Move> = Vec::new();
+    for m in moves.iter().flat_map(with_sidecars) {
+        if let Err(e) = move_file(&m.from, &m.to) {
+            for undo in done.iter().rev() {
+                if let Err(e) = move_file(&undo.to, &undo.from) {
+                    eprintln!("[Organize] 无法移回 {}: {}", undo.to.display(), e);
+                }
+                if let Some(parent) = undo.to.parent() {
+                    prune_empty(parent, root);
+                }
+            }
+            return Err(io::Error::new(e.kind(), format!("{}: {}", m.from.display(), e)));
+        }
+        done.push(m);
+    }
+    for m in &done {
+        let Some(parent) = m.from.parent() else {
+            continue;
+        };
+        if parent.starts_with(root) {
+            prune_empty(parent, root);
+        } else {
+            // Outside the root only the folder the file came from is tidied
+            let _ = fs::remove_dir(parent);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+    use std::time::Duration;
+
+    fn track(path: &str, title: &str, artist: &str, album: &str, number: Option<u32>) -> TrackStub {
+        TrackStub {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            track_number: number,
+            year: Some(1999),
+            duration: Duration::from_secs(200),
+            ..track_stub(title, path, title)
+        }
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let mut t = track("/a.mp3", "What? / Why", "AC/DC", "", Some(3));
+        assert_eq!(relative_path(DEFAULT_PATTERN, &t), PathBuf::from("AC_DC/Unknown Album/03 - What_ _ Why"));
+        t.track_number = None;
+        t.album_artist = Some("Various Artists".to_string());
+        assert_eq!(
+            relative_path("{artist}/{year} {album}/{track} - {title}", &t),
+            PathBuf::from("Various Artists/1999 Unknown Album/What_ _ Why")
+        );
+        assert_eq!(relative_path("{genre}/{title}", &t), PathBuf::from("What_ _ Why"));
+        assert_eq!(sanitize(" .hidden. "), "hidden");
+        assert_eq!(sanitize("..."), "Unknown");
+    }
+
+    #[test]
+    fn test_plan_and_apply() {
+        let root = std::env::temp_dir().join(format!("dioxus_music_organize_{}", uuid::Uuid::new_v4()));
+        let inbox = root.join("inbox");
+        fs::create_dir_all(&inbox).unwrap();
+        for name in ["one.MP3", "one.lrc", "two.flac", "three.flac"] {
+            fs::write(inbox.join(name), name).unwrap();
+        }
+        let tracks = [
+            track(&inbox.join("one.MP3").to_string_lossy(), "Song", "Band", "LP", Some(1)),
+            track(&inbox.join("two.flac").to_string_lossy(), "Song", "Band", "LP", Some(1)),
+            track(&inbox.join("three.flac").to_string_lossy(), "Song", "Band", "LP", Some(1)),
+        ];
+        let library = root.join("library");
+        let moves = plan(&library, DEFAULT_PATTERN, &tracks);
+        let album = library.join("Band").join("LP");
+        let targets: Vec<_> = moves.iter().map(|m| m.to.clone()).collect();
+        assert_eq!(
+            targets,
+            [album.join("01 - Song.mp3"), album.join("01 - Song.flac"), album.join("01 - Song (2).flac")]
+        );
+
+        // A file sitting on the last target makes the whole batch go back
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("01 - Song (2).flac"), "in the way").unwrap();
+        assert!(apply(&library, &moves).is_err());
+        assert!(inbox.join("one.MP3").exists() && inbox.join("one.lrc").exists() && inbox.join("two.flac").exists());
+        assert!(!album.join("01 - Song.mp3").exists());
+
+        fs::remove_file(album.join("01 - Song (2).flac")).unwrap();
+        apply(&library, &moves).unwrap();
+        assert_eq!(fs::read_to_string(album.join("01 - Song.lrc")).unwrap(), "one.lrc");
+        assert_eq!(fs::read_to_string(album.join("01 - Song (2).flac")).unwrap(), "three.flac");
+        assert!(!inbox.exists());
+        // Already in place, so nothing more to do
+        let organized: Vec<_> = tracks
+            .iter()
+            .zip(&targets)
+            .map(|(t, to)| TrackStub { path: to.to_string_lossy().into_owned(), ..t.clone() })
+            .collect();
+        assert!(plan(&library, DEFAULT_PATTERN, &organized).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
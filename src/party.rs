@@ -0,0 +1,392 @@
+// Party mode: a small HTTP server on the LAN. Guests open its page, search the
+// library and request songs; requests wait in a queue until the host approves or
+// rejects them in the app.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+pub const DEFAULT_PORT: u16 = 8787;
+// Each guest (by IP address) gets this many requests per window
+const GUEST_REQUEST_LIMIT: usize = 3;
+const GUEST_REQUEST_WINDOW: Duration = Duration::from_secs(10 * 60);
+const SEARCH_RESULT_LIMIT: usize = 50;
+const MAX_BODY_BYTES: usize = 4096;
+// The request line and headers together, and how many headers
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 32;
+// Connections handled at once; more are turned away until one finishes
+const MAX_CONNECTIONS: usize = 16;
+const MAX_GUEST_NAME_CHARS: usize = 32;
+const GUEST_PAGE: &str = include_str!("party/guest.html");
+
+/// A library entry guests can find and request. Tracks are matched by id, so
+/// guests never see where the files are.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PartyTrack {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+/// A guest's request waiting for the host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SongRequest {
+    pub id: u64,
+    pub track_id: String,
+    pub title: String,
+    pub artist: String,
+    // The name the guest typed, or their address
+    pub guest: String,
+    pub requested_at: SystemTime,
+}
+
+#[derive(Deserialize)]
+struct RequestBody {
+    id: String,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Rejection {
+    NotFound,
+    AlreadyQueued,
+    // How long until the guest's oldest request leaves the window
+    RateLimited(Duration),
+}
+
+#[derive(Default)]
+struct PartyState {
+    library: Vec<PartyTrack>,
+    queue: Vec<SongRequest>,
+    next_id: u64,
+    // When each guest made their requests within the window, oldest first
+    guest_requests: HashMap<IpAddr, Vec<Instant>>,
+}
+
+impl PartyState {
+    // Every word has to appear in the title, artist or album
+    fn search(&self, query: &str) -> Vec<PartyTrack> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        self.library
+            .iter()
+            .filter(|track| {
+                let text = format!("{} {} {}", track.title, track.artist, track.album).to_lowercase();
+                words.iter().all(|word| text.contains(word))
+            })
+            .take(SEARCH_RESULT_LIMIT)
+            .cloned()
+            .collect()
+    }
+
+    fn submit(&mut self, guest: IpAddr, name: &str, track_id: &str, now: Instant) -> Result<SongRequest, Rejection> {
+        let track = self.library.iter().find(|track| track.id == track_id).ok_or(Rejection::NotFound)?;
+        if self.queue.iter().any(|request| request.track_id == track_id) {
+            return Err(Rejection::AlreadyQueued);
+        }
+
+        let recent = self.guest_requests.entry(guest).or_default();
+        recent.retain(|at| now.duration_since(*at) < GUEST_REQUEST_WINDOW);
+        if recent.len() >= GUEST_REQUEST_LIMIT {
+            return Err(Rejection::RateLimited(GUEST_REQUEST_WINDOW - now.duration_since(recent[0])));
+        }
+        recent.push(now);
+
+        let name: String = name.trim().chars().take(MAX_GUEST_NAME_CHARS).collect();
+        self.next_id += 1;
+        let request = SongRequest {
+            id: self.next_id,
+            track_id: track.id.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            guest: if name.is_empty() { guest.to_string() } else { name },
+            requested_at: SystemTime::now(),
+        };
+        self.queue.push(request.clone());
+        Ok(request)
+    }
+}
+
+/// The running party server. Dropping it stops accepting guests.
+pub struct PartyServer {
+    guest_url: String,
+    state: Arc<Mutex<PartyState>>,
+    stopped: Arc<AtomicBool>,
+    event_receiver: Mutex<Option<UnboundedReceiver<SongRequest>>>,
+}
+
+impl PartyServer {
+    /// Listen on every interface so phones on the same network can connect.
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        // Polled so the thread notices when the server is dropped
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(PartyState::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (events, event_receiver) = unbounded_channel();
+
+        let shared = state.clone();
+        let stop = stopped.clone();
+        let open_connections = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if open_connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                            open_connections.fetch_sub(1, Ordering::SeqCst);
+                            drop(stream);
+                            continue;
+                        }
+                        let state = shared.clone();
+                        let events = events.clone();
+                        let open_connections = open_connections.clone();
+                        std::thread::spawn(move || {
+                            if let Err(e) = handle_connection(stream, &state, &events) {
+                                eprintln!("[Party] 处理请求失败: {}", e);
+                            }
+                            open_connections.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(100)),
+                    Err(e) => {
+                        eprintln!("[Party] 接受连接失败: {}", e);
+                        std::thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+            eprintln!("[Party] 派对模式已关闭");
+        });
+
+        let guest_url = format!("http://{}:{}/", lan_ip().unwrap_or(addr.ip()), addr.port());
+        eprintln!("[Party] 派对模式已启动: {}", guest_url);
+        Ok(PartyServer { guest_url, state, stopped, event_receiver: Mutex::new(Some(event_receiver)) })
+    }
+
+    /// The address to give guests: this machine's LAN address when it can be
+    /// worked out.
+    pub fn guest_url(&self) -> &str {
+        &self.guest_url
+    }
+
+    /// Replace what guests can search and request.
+    pub fn set_library(&self, library: Vec<PartyTrack>) {
+        self.state.lock().unwrap().library = library;
+    }
+
+    /// Requests waiting for the host, oldest first.
+    pub fn queue(&self) -> Vec<SongRequest> {
+        self.state.lock().unwrap().queue.clone()
+    }
+
+    /// Remove a request from the queue, whether it's being approved or rejected.
+    pub fn take_request(&self, id: u64) -> Option<SongRequest> {
+        let mut state = self.state.lock().unwrap();
+        let pos = state.queue.iter().position(|request| request.id == id)?;
+        Some(state.queue.remove(pos))
+    }
+
+    // Every newly queued request; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<SongRequest>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+impl Drop for PartyServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+// Connecting a UDP socket sends nothing, but picks the interface used to reach the outside
+fn lan_ip() -> Option<IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<PartyState>, events: &UnboundedSender<SongRequest>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let guest = stream.peer_addr()?.ip();
+    let mut reader = BufReader::new(stream.try_clone()?).take(MAX_HEAD_BYTES);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0usize;
+    let mut headers = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        headers += 1;
+        // Out of room before the blank line that ends the headers
+        if headers > MAX_HEADERS || reader.limit() == 0 {
+            return respond_error(&mut stream, "431 Request Header Fields Too Large", "Request too large");
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return respond_error(&mut stream, "413 Payload Too Large", "Request too large");
+    }
+    reader.set_limit(content_length as u64);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    match (method.as_str(), path) {
+        ("GET", "/") => respond(&mut stream, "200 OK", "text/html; charset=utf-8", GUEST_PAGE.as_bytes()),
+        ("GET", "/api/search") => {
+            let results = state.lock().unwrap().search(&query_param(query, "q").unwrap_or_default());
+            respond_json(&mut stream, "200 OK", &results)
+        }
+        ("POST", "/api/request") => {
+            let Ok(body) = serde_json::from_slice::<RequestBody>(&body) else {
+                return respond_error(&mut stream, "400 Bad Request", "Malformed request");
+            };
+            let result = state.lock().unwrap().submit(guest, &body.name, &body.id, Instant::now());
+            match result {
+                Ok(request) => {
+                    eprintln!("[Party] {} 点歌: {} - {}", request.guest, request.artist, request.title);
+                    let _ = events.send(request.clone());
+                    respond_json(&mut stream, "201 Created", &serde_json::json!({ "title": request.title }))
+                }
+                Err(Rejection::NotFound) => respond_error(&mut stream, "404 Not Found", "That song isn't in the library"),
+                Err(Rejection::AlreadyQueued) => {
+                    respond_error(&mut stream, "409 Conflict", "Someone already requested that song")
+                }
+                Err(Rejection::RateLimited(wait)) => {
+                    let minutes = wait.as_secs().div_ceil(60).max(1);
+                    let message = format!("You've used up your requests for now; try again in {} min", minutes);
+                    respond_error(&mut stream, "429 Too Many Requests", &message)
+                }
+            }
+        }
+        _ => respond_error(&mut stream, "404 Not Found", "Not found"),
+    }
+}
+
+// Form-style query parameters: `+` is a space
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| {
+            let value = value.replace('+', " ");
+            urlencoding::decode(&value).map(|v| v.into_owned()).unwrap_or(value)
+        })
+    })
+}
+
+fn respond_json<T: Serialize + ?Sized>(stream: &mut TcpStream, status: &str, value: &T) -> io::Result<()> {
+    let body = serde_json::to_vec(value).map_err(io::Error::other)?;
+    respond(stream, status, "application/json", &body)
+}
+
+fn respond_error(stream: &mut TcpStream, status: &str, message: &str) -> io::Result<()> {
+    respond_json(stream, status, &serde_json::json!({ "error": message }))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: &str, title: &str, artist: &str) -> PartyTrack {
+        PartyTrack { id: id.to_string(), title: title.to_string(), artist: artist.to_string(), album: String::new() }
+    }
+
+    #[test]
+    fn test_requests_are_rate_limited_per_guest() {
+        let mut state = PartyState {
+            library: (0..6).map(|i| track(&format!("t{}", i), &format!("Song {}", i), "Band")).collect(),
+            ..Default::default()
+        };
+        let alice: IpAddr = "192.168.1.20".parse().unwrap();
+        let bob: IpAddr = "192.168.1.21".parse().unwrap();
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert!(state.submit(alice, " Alice ", &format!("t{}", i), start).is_ok());
+        }
+        assert_eq!(state.submit(alice, "Alice", "t3", start), Err(Rejection::RateLimited(GUEST_REQUEST_WINDOW)));
+        assert_eq!(state.submit(bob, "", "t0", start), Err(Rejection::AlreadyQueued));
+        assert_eq!(state.submit(bob, "", "missing", start), Err(Rejection::NotFound));
+        let request = state.submit(bob, "", "t3", start).unwrap();
+        assert_eq!(request.guest, "192.168.1.21");
+
+        // Once the first requests age out of the window Alice can ask again
+        assert!(state.submit(alice, "Alice", "t4", start + GUEST_REQUEST_WINDOW).is_ok());
+        assert_eq!(state.queue.len(), 5);
+        assert_eq!(state.queue[0].guest, "Alice");
+    }
+
+    #[test]
+    fn test_search_matches_every_word() {
+        let state = PartyState {
+            library: vec![track("a", "Blue Monday", "New Order"), track("b", "Blue Train", "John Coltrane")],
+            ..Default::default()
+        };
+        let titles = |query: &str| state.search(query).into_iter().map(|t| t.title).collect::<Vec<_>>();
+
+        assert_eq!(titles("blue"), vec!["Blue Monday", "Blue Train"]);
+        assert_eq!(titles("BLUE order"), vec!["Blue Monday"]);
+        assert!(titles("red").is_empty());
+        assert_eq!(query_param("x=1&q=new+order%21", "q").as_deref(), Some("new order!"));
+    }
+
+    #[test]
+    fn test_guest_page_round_trip() {
+        let server = PartyServer::start(0).unwrap();
+        let mut events = server.take_events().unwrap();
+        server.set_library(vec![track("t1", "Blue Monday", "New Order")]);
+        let port = server.guest_url().trim_end_matches('/').rsplit(':').next().unwrap();
+        let base = format!("http://127.0.0.1:{}", port);
+        let client = reqwest::blocking::Client::new();
+
+        let page = client.get(format!("{}/", base)).send().unwrap().text().unwrap();
+        assert!(page.contains("<html"));
+
+        let found = client.get(format!("{}/api/search?q=monday", base)).send().unwrap().text().unwrap();
+        let found: serde_json::Value = serde_json::from_str(&found).unwrap();
+        assert_eq!(found[0]["id"], "t1");
+        assert!(found[0].get("path").is_none());
+
+        let body = serde_json::json!({ "id": "t1", "name": "Sam" }).to_string();
+        let response = client.post(format!("{}/api/request", base)).body(body.clone()).send().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let again = client.post(format!("{}/api/request", base)).body(body).send().unwrap();
+        assert_eq!(again.status(), reqwest::StatusCode::CONFLICT);
+
+        let queued = events.try_recv().unwrap();
+        assert_eq!((queued.guest.as_str(), queued.title.as_str()), ("Sam", "Blue Monday"));
+        assert_eq!(server.take_request(queued.id), Some(queued));
+        assert!(server.queue().is_empty());
+    }
+}
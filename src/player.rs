@@ -1,12 +1,43 @@
+use crate::webdav::Credentials;
 use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink, Source};
 use std::fs::File;
 use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+mod channel_mix;
+mod download;
+mod events;
+mod growing_file;
+mod handoff;
+mod level_meter;
+mod lrc;
 mod lyrics;
+mod prefetch;
+mod read_ahead;
+mod spectrum;
+mod stream_info;
+mod webdav_stream;
+use channel_mix::{ChannelMix, ChannelMixSettings};
+use download::DownloadError;
+pub use download::{
+    cache_size, set_cache_limit, set_download_connections, DEFAULT_CACHE_LIMIT_MB, DEFAULT_DOWNLOAD_CONNECTIONS,
+};
+use events::{EventTap, PlaybackClock};
+use growing_file::{DownloadProgress, GrowingFile};
+use handoff::{Handoff, NextTrack, QueuedTrack};
+pub use events::{PlaybackError, PlayerEvent};
+use level_meter::{LevelMeter, LevelTap};
+pub use lrc::parse_lrc;
 pub use lyrics::Lyric;
+use prefetch::{PreparedTrack, Prefetcher};
+use read_ahead::ReadAheadReader;
+pub use stream_info::StreamInfo;
+use spectrum::{SpectrumBuffer, SpectrumTap, SPECTRUM_BANDS};
+pub use webdav_stream::WebDAVStreamSource;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PlayerState {
@@ -15,8 +46,14 @@ pub enum PlayerState {
     Stopped,
 }
 
-const MAX_FILE_SIZE: u64 = 200 * 1024 * 1024; // 200MB limit for streaming
 const STREAMING_MIN_BYTES: u64 = 512 * 1024; // 512KB minimum for streaming playback (increased from 128KB)
+const STREAMING_START_BYTES: u64 = 1024 * 1024; // play() starts decoding a remote track once this much is downloaded
+pub const DEFAULT_READ_AHEAD_MB: u32 = 4; // Read-ahead for local files, smooths over slow network mounts
+const DEFAULT_READ_AHEAD_BYTES: usize = DEFAULT_READ_AHEAD_MB as usize * 1024 * 1024;
+const STREAM_READ_AHEAD_BYTES: usize = 4 * 1024 * 1024; // Buffered ahead of the decoder for remote tracks read over Range requests
+const STREAM_PROBE_BYTES: usize = 256 * 1024; // Read-ahead for the second pass over a remote track that finds its tags
+const DEVICE_RETRY_INTERVAL: Duration = Duration::from_secs(1); // Wait between attempts to reopen a lost output device
+pub const MAX_TRACK_GAIN_DB: f32 = 12.0; // Furthest a per-track gain goes either way
 
 #[derive(Clone)]
 #[allow(dead_code)]
@@ -33,92 +70,83 @@ pub struct TrackMetadata {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
-    pub cover: Option<Vec<u8>>,
+    // Every embedded picture, front covers first
+    pub pictures: Vec<crate::metadata::Picture>,
     pub duration: Duration,
     pub lyrics: Option<String>,
+    pub track_number: Option<u32>,
+    pub stream: StreamInfo,
 }
 
 impl TrackMetadata {
     pub fn from_path(path: &Path) -> Self {
-        use id3::{Tag, TagLike};
-        use metaflac::Tag as FlacTag;
-
         let file_name = path.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("Unknown")
             .to_string();
 
-        let mut metadata = TrackMetadata::default();
-
-        // Try ID3 tags first (MP3, M4A)
-        if let Ok(tag) = Tag::read_from_path(path).or_else(|_| {
-            let file = File::open(path)?;
-            Tag::read_from2(file)
-        }) {
-            metadata.title = tag.title().map(|t| t.to_string()).or(Some(file_name.clone()));
-            metadata.artist = tag.artist().map(|a| a.to_string());
-            metadata.album = tag.album().map(|a| a.to_string());
-            metadata.cover = tag.pictures().next().map(|pic| pic.data.clone());
-
-            // Read lyrics from USLT frame
-            if let Some(lyrics) = tag.lyrics().next() {
-                metadata.lyrics = Some(lyrics.text.to_string());
-            }
-        }
-
-        // Try FLAC tags
-        if metadata.title.is_none() || metadata.artist.is_none() {
-            if let Ok(tag) = FlacTag::read_from_path(path) {
-                if let Some(vorbis) = tag.vorbis_comments() {
-                    if metadata.title.is_none() {
-                        metadata.title = vorbis.title()
-                            .and_then(|v| v.first().cloned())
-                            .or(Some(file_name.clone()));
-                    }
-                    if metadata.artist.is_none() {
-                        metadata.artist = vorbis.artist().and_then(|v| v.first().cloned());
-                    }
-                    if metadata.album.is_none() {
-                        metadata.album = vorbis.album().and_then(|v| v.first().cloned());
-                    }
-                }
-                if metadata.cover.is_none() {
-                    metadata.cover = tag.pictures().next().map(|pic| pic.data.clone());
-                }
-            }
-        }
-
-        // Get duration
-        if let Ok(file) = File::open(path) {
-            if let Ok(source) = Decoder::try_from(file) {
-                metadata.duration = source.total_duration().unwrap_or(Duration::from_secs(0));
-            }
+        // Tags, cover, duration and stream properties from one read of the file
+        let probed = crate::metadata::probe(path).unwrap_or_default();
+        let tags = probed.tags;
+        // ID3's timed SYLT lyrics aren't among what the probe reads, so they're
+        // looked for whenever the other tags don't have timed lyrics
+        let lyrics = match tags.lyrics {
+            Some(lyrics) if crate::metadata::is_synced(&lyrics) => Some(lyrics),
+            lyrics => id3::Tag::read_from_path(path)
+                .ok()
+                .and_then(|tag| crate::metadata::id3_synced_lyrics(&tag))
+                .or(lyrics),
+        };
+        TrackMetadata {
+            title: tags.title.or(Some(file_name)),
+            artist: tags.artist,
+            album: tags.album,
+            pictures: tags.pictures,
+            duration: tags.duration.unwrap_or_default(),
+            lyrics,
+            track_number: tags.track_number,
+            stream: probed.stream,
         }
+    }
 
-        if metadata.title.is_none() {
-            metadata.title = Some(file_name);
+    /// What `from_path` finds, for a remote file read over Range requests: what
+    /// symphonia reads, without SYLT lyrics or the in-house tag parsers.
+    pub fn from_stream(source: WebDAVStreamSource) -> Self {
+        let extension = source.extension();
+        let byte_len = source.byte_len();
+        let probed = ReadAheadReader::new(source, STREAM_PROBE_BYTES)
+            .ok()
+            .and_then(|reader| crate::metadata::probe_reader(reader, byte_len, &extension))
+            .unwrap_or_default();
+        let tags = probed.tags;
+        TrackMetadata {
+            title: tags.title,
+            artist: tags.artist,
+            album: tags.album,
+            pictures: tags.pictures,
+            duration: tags.duration.unwrap_or_default(),
+            lyrics: tags.lyrics,
+            track_number: tags.track_number,
+            stream: probed.stream,
         }
-
-        metadata
     }
 }
 
 pub struct MusicPlayer {
     sink: Arc<Mutex<Option<Sink>>>,
-    _stream: Arc<Mutex<OutputStream>>,
+    // None for the headless player used in tests
+    _stream: Arc<Mutex<Option<OutputStream>>>,
     current_duration: Arc<Mutex<Duration>>,
     current_time: Arc<Mutex<Duration>>,
     current_path: Arc<Mutex<Option<PathBuf>>>,
-    on_track_end: Arc<Mutex<Option<Box<dyn FnMut() + Send + 'static>>>>,
     temp_file: Arc<Mutex<Option<PathBuf>>>,
     playlist: Arc<Mutex<Vec<Track>>>,
     current_index: Arc<Mutex<usize>>,
     auto_play: Arc<Mutex<bool>>,
     last_track_path: Arc<Mutex<Option<String>>>,
     last_track_id: Arc<Mutex<Option<String>>>,
-    pub track_ended: Arc<Mutex<bool>>,
-    playback_start: Arc<Mutex<Option<std::time::Instant>>>,
-    pub stopped_by_user: Arc<Mutex<bool>>,
+    // Position in the current track, kept by the samples actually played
+    clock: Arc<PlaybackClock>,
     is_playing: Arc<Mutex<bool>>,
     current_metadata: Arc<Mutex<Option<TrackMetadata>>>,
     downloaded_bytes: Arc<Mutex<u64>>,
@@ -126,7 +154,32 @@ pub struct MusicPlayer {
     is_remote: Arc<Mutex<bool>>,
     current_lyric: Arc<Mutex<Option<Lyric>>>,
     pub download_cancelled: Arc<Mutex<bool>>,
-    playback_started: Arc<Mutex<bool>>,
+    // Bumped by every play() so the previous track's download stops
+    download_generation: Arc<AtomicU64>,
+    // Download feeding the current remote track while it's still streaming
+    stream_progress: Arc<Mutex<Option<Arc<DownloadProgress>>>>,
+    // URL and credentials of the remote track playing straight off the server
+    range_stream: Arc<Mutex<Option<(String, Option<Credentials>)>>>,
+    stream_auth: Arc<Mutex<Option<Credentials>>>,
+    device_lost_at: Arc<Mutex<Option<std::time::Instant>>>,
+    last_recovery_attempt: Arc<Mutex<Option<std::time::Instant>>>,
+    // Name of the output device picked by the user; None for the system default
+    output_device: Arc<Mutex<Option<String>>>,
+    read_ahead_bytes: Arc<Mutex<usize>>,
+    // Where the next play() should start, paused; used to restore a saved session
+    start_position: Arc<Mutex<Option<Duration>>>,
+    channel_mix: Arc<ChannelMixSettings>,
+    spectrum: Arc<SpectrumBuffer>,
+    levels: Arc<LevelMeter>,
+    prefetch: Arc<Prefetcher>,
+    // Lined up by queue_next to follow the current track
+    next_track: Arc<NextTrack<Tapped>>,
+    crossfade: Arc<Mutex<Duration>>,
+    // The sink plays at the master volume scaled by the current track's gain
+    volume: Arc<Mutex<f32>>,
+    track_gain_db: Arc<Mutex<f32>>,
+    events: UnboundedSender<PlayerEvent>,
+    event_receiver: Arc<Mutex<Option<UnboundedReceiver<PlayerEvent>>>>,
 }
 
 impl Clone for MusicPlayer {
@@ -137,16 +190,13 @@ impl Clone for MusicPlayer {
             current_duration: Arc::clone(&self.current_duration),
             current_time: Arc::clone(&self.current_time),
             current_path: Arc::clone(&self.current_path),
-            on_track_end: Arc::clone(&self.on_track_end),
             temp_file: Arc::clone(&self.temp_file),
             playlist: Arc::clone(&self.playlist),
             current_index: Arc::clone(&self.current_index),
             auto_play: Arc::clone(&self.auto_play),
             last_track_path: Arc::clone(&self.last_track_path),
             last_track_id: Arc::clone(&self.last_track_id),
-            track_ended: Arc::clone(&self.track_ended),
-            playback_start: Arc::clone(&self.playback_start),
-            stopped_by_user: Arc::clone(&self.stopped_by_user),
+            clock: Arc::clone(&self.clock),
             is_playing: Arc::clone(&self.is_playing),
             current_metadata: Arc::clone(&self.current_metadata),
             downloaded_bytes: Arc::clone(&self.downloaded_bytes),
@@ -154,7 +204,25 @@ impl Clone for MusicPlayer {
             is_remote: Arc::clone(&self.is_remote),
             current_lyric: Arc::clone(&self.current_lyric),
             download_cancelled: Arc::clone(&self.download_cancelled),
-            playback_started: Arc::clone(&self.playback_started),
+            download_generation: Arc::clone(&self.download_generation),
+            stream_progress: Arc::clone(&self.stream_progress),
+            range_stream: Arc::clone(&self.range_stream),
+            stream_auth: Arc::clone(&self.stream_auth),
+            device_lost_at: Arc::clone(&self.device_lost_at),
+            last_recovery_attempt: Arc::clone(&self.last_recovery_attempt),
+            output_device: Arc::clone(&self.output_device),
+            read_ahead_bytes: Arc::clone(&self.read_ahead_bytes),
+            start_position: Arc::clone(&self.start_position),
+            channel_mix: Arc::clone(&self.channel_mix),
+            spectrum: Arc::clone(&self.spectrum),
+            levels: Arc::clone(&self.levels),
+            prefetch: Arc::clone(&self.prefetch),
+            next_track: Arc::clone(&self.next_track),
+            crossfade: Arc::clone(&self.crossfade),
+            volume: Arc::clone(&self.volume),
+            track_gain_db: Arc::clone(&self.track_gain_db),
+            events: self.events.clone(),
+            event_receiver: Arc::clone(&self.event_receiver),
         }
     }
 }
@@ -162,25 +230,36 @@ impl Clone for MusicPlayer {
 #[allow(dead_code)]
 impl MusicPlayer {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let stream = OutputStreamBuilder::open_default_stream()?;
+        let device_lost_at = Arc::new(Mutex::new(None));
+        let stream = open_output_stream(None, device_lost_at.clone())?;
         let sink = Sink::connect_new(&stream.mixer());
+        Ok(Self::with_sink(sink, Some(stream), device_lost_at))
+    }
+
+    /// A player with no audio device. Samples go to the returned queue output,
+    /// which the test drains to drive playback.
+    #[cfg(test)]
+    pub fn new_headless() -> (Self, rodio::queue::SourcesQueueOutput) {
+        let (sink, output) = Sink::new();
+        (Self::with_sink(sink, None, Arc::new(Mutex::new(None))), output)
+    }
 
-        Ok(MusicPlayer {
+    fn with_sink(sink: Sink, stream: Option<OutputStream>, device_lost_at: Arc<Mutex<Option<std::time::Instant>>>) -> Self {
+        let (events, event_receiver) = unbounded_channel();
+
+        MusicPlayer {
             sink: Arc::new(Mutex::new(Some(sink))),
             _stream: Arc::new(Mutex::new(stream)),
             current_duration: Arc::new(Mutex::new(Duration::from_secs(0))),
             current_time: Arc::new(Mutex::new(Duration::from_secs(0))),
             current_path: Arc::new(Mutex::new(None)),
-            on_track_end: Arc::new(Mutex::new(None)),
             temp_file: Arc::new(Mutex::new(None)),
             playlist: Arc::new(Mutex::new(Vec::new())),
             current_index: Arc::new(Mutex::new(0)),
             auto_play: Arc::new(Mutex::new(true)),
             last_track_path: Arc::new(Mutex::new(None)),
             last_track_id: Arc::new(Mutex::new(None)),
-            track_ended: Arc::new(Mutex::new(false)),
-            playback_start: Arc::new(Mutex::new(None)),
-            stopped_by_user: Arc::new(Mutex::new(false)),
+            clock: Arc::new(PlaybackClock::default()),
             is_playing: Arc::new(Mutex::new(false)),
             current_metadata: Arc::new(Mutex::new(None)),
             downloaded_bytes: Arc::new(Mutex::new(0)),
@@ -188,286 +267,479 @@ impl MusicPlayer {
             is_remote: Arc::new(Mutex::new(false)),
             current_lyric: Arc::new(Mutex::new(None)),
             download_cancelled: Arc::new(Mutex::new(false)),
-            playback_started: Arc::new(Mutex::new(false)),
-        })
+            download_generation: Arc::new(AtomicU64::new(0)),
+            stream_progress: Arc::new(Mutex::new(None)),
+            range_stream: Arc::new(Mutex::new(None)),
+            stream_auth: Arc::new(Mutex::new(None)),
+            device_lost_at,
+            last_recovery_attempt: Arc::new(Mutex::new(None)),
+            output_device: Arc::new(Mutex::new(None)),
+            read_ahead_bytes: Arc::new(Mutex::new(DEFAULT_READ_AHEAD_BYTES)),
+            start_position: Arc::new(Mutex::new(None)),
+            channel_mix: Arc::new(ChannelMixSettings::new()),
+            spectrum: Arc::new(SpectrumBuffer::new()),
+            levels: Arc::new(LevelMeter::new()),
+            prefetch: Arc::new(Prefetcher::new()),
+            next_track: Arc::new(NextTrack::new()),
+            crossfade: Arc::new(Mutex::new(Duration::ZERO)),
+            volume: Arc::new(Mutex::new(1.0)),
+            track_gain_db: Arc::new(Mutex::new(0.0)),
+            events,
+            event_receiver: Arc::new(Mutex::new(Some(event_receiver))),
+        }
     }
 
-    pub fn play(&self, path: &Path, track_id: Option<String>) {
+    pub fn play(&self, path: &Path, track_id: Option<String>, is_remote: bool) {
         *self.is_playing.lock().unwrap() = true;
-        *self.stopped_by_user.lock().unwrap() = false;
         *self.download_cancelled.lock().unwrap() = false;
-        *self.playback_started.lock().unwrap() = false;
+        let generation = self.download_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.release_stream();
+        *self.range_stream.lock().unwrap() = None;
+        self.next_track.clear();
 
-        if let Some(id) = track_id {
+        if let Some(id) = track_id.clone() {
             if let Ok(mut guard) = self.last_track_id.lock() {
                 *guard = Some(id);
             }
         }
 
+        self.set_remote(is_remote);
+
         let path = path.to_path_buf();
         let path_str = path.to_string_lossy().into_owned();
-        let is_remote = path_str.starts_with("http://") || path_str.starts_with("https://");
+
+        if let Some(prepared) = self.prefetch.take(&path_str) {
+            eprintln!("[Player] 使用预加载的曲目: {}", path_str);
+            self.play_prepared(prepared, track_id);
+            return;
+        }
 
         let sink = self.sink.clone();
         let current_duration = self.current_duration.clone();
         let current_path = self.current_path.clone();
-        let on_track_end = self.on_track_end.clone();
-        let track_ended = self.track_ended.clone();
         let is_playing = self.is_playing.clone();
-        let playback_start = self.playback_start.clone();
+        let clock = self.clock.clone();
         let current_metadata = self.current_metadata.clone();
-        let download_cancelled = self.download_cancelled.clone();
-        let playback_started = self.playback_started.clone();
-
-        if is_remote {
-            let temp_dir = std::env::temp_dir();
-            let temp_filename = format!("dioxus_music_{}", uuid::Uuid::new_v4());
-            let temp_path = temp_dir.join(&temp_filename);
-            let url = path_str.clone();
+        let read_ahead_bytes = *self.read_ahead_bytes.lock().unwrap();
+        let channel_mix = self.channel_mix.clone();
+        let spectrum = self.spectrum.clone();
+        let levels = self.levels.clone();
+        let next_track = self.next_track.clone();
+        let events = self.events.clone();
+        let start_position = self.start_position.lock().unwrap().take();
+        let current_time = self.current_time.clone();
+
+        // Tracks inside zip archives are inflated into the download cache and
+        // streamed from there just like remote ones
+        let in_archive = crate::archive::split_path(&path_str).is_some();
+        if is_remote && !in_archive && streams_over_ranges(&path_str) {
+            self.play_stream(path_str, generation, track_id, start_position);
+        } else if is_remote || in_archive {
+            if is_remote && fetched_by_url(&path_str) {
+                // Plays from the cache; checked against the server meanwhile, so a
+                // file changed there is fetched afresh next time
+                let (url, auth) = (path_str.clone(), self.stream_auth.lock().unwrap().clone());
+                std::thread::spawn(move || download::revalidate(&url, auth));
+            }
+            self.play_download(path_str, in_archive, generation, track_id, start_position);
+        } else {
+            let extension = path.extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("")
+                .to_lowercase();
 
             std::thread::spawn(move || {
-                let client = match reqwest::blocking::Client::builder()
-                    .timeout(std::time::Duration::from_secs(120))
-                    .build()
-                {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("[Player] 创建HTTP客户端失败: {}", e);
-                        *is_playing.lock().unwrap() = false;
-                        return;
-                    }
-                };
+                let source_result = play_local_file_async(&path, &extension, read_ahead_bytes);
+
+                match source_result {
+                    Ok(mut source) => {
+                        let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
+                        let start = seek_to_start(&mut source, start_position);
+
+                        let metadata = TrackMetadata::from_path(&path);
+                        eprintln!("[Player] 本地提取元数据: title={:?}, artist={:?}, duration={:?}",
+                            metadata.title, metadata.artist, duration);
+                        *current_metadata.lock().unwrap() = Some(metadata.clone());
 
-                let response = match client.get(&url).send() {
-                    Ok(r) => r,
+                        if let Ok(sink_guard) = sink.lock() {
+                            if let Some(audio_sink) = sink_guard.as_ref() {
+                                audio_sink.stop();
+                                audio_sink.append(output_source(source, &channel_mix, &spectrum, &levels, &next_track, &events, &clock, track_id, start));
+                                if start_position.is_some() {
+                                    audio_sink.pause();
+                                    *is_playing.lock().unwrap() = false;
+                                } else {
+                                    audio_sink.play();
+                                }
+                                clock.set(start);
+
+                                *current_duration.lock().unwrap() = duration;
+                                *current_time.lock().unwrap() = start;
+                                *current_path.lock().unwrap() = Some(path);
+                            }
+                        }
+                        let _ = events.send(PlayerEvent::MetadataLoaded { metadata, duration });
+                    }
                     Err(e) => {
-                        eprintln!("[Player] 无法下载音频文件: {}", e);
+                        eprintln!("[Player] 播放失败: {}", e);
                         *is_playing.lock().unwrap() = false;
-                        return;
+                        let error = if path.exists() { PlaybackError::Decode(e) } else { PlaybackError::NotFound(path) };
+                        let _ = events.send(PlayerEvent::Error { track_id, error });
                     }
-                };
+                }
+            });
+        }
+    }
 
-                if !response.status().is_success() {
-                    eprintln!("[Player] 下载失败 (HTTP {})", response.status());
-                    *is_playing.lock().unwrap() = false;
+    // Play a remote file straight off the server over Range requests, with the
+    // decoder reading ahead; a server that only sends whole files gets
+    // `play_download` instead
+    fn play_stream(&self, url: String, generation: u64, track_id: Option<String>, start_position: Option<Duration>) {
+        let player = self.clone();
+        let auth = self.stream_auth.lock().unwrap().clone();
+        std::thread::spawn(move || {
+            // Stop, or another track starting, makes this one moot
+            let is_current = || {
+                !*player.download_cancelled.lock().unwrap() && player.download_generation.load(Ordering::SeqCst) == generation
+            };
+            let source = match player.open_stream(&url, auth.clone()) {
+                Ok(source) => source,
+                Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                    eprintln!("[Player] 服务器不支持范围请求，改为下载: {}", url);
+                    if is_current() {
+                        player.play_download(url, false, generation, track_id, start_position);
+                    }
                     return;
                 }
-
-                let content_length = response.content_length().unwrap_or(0);
-                if content_length > MAX_FILE_SIZE {
-                    eprintln!("[Player] 文件过大");
-                    *is_playing.lock().unwrap() = false;
+                Err(e) => {
+                    if is_current() {
+                        eprintln!("[Player] 无法打开远程音频: {}", e);
+                        *player.is_playing.lock().unwrap() = false;
+                        let _ = player.events.send(PlayerEvent::Error { track_id, error: PlaybackError::Download(e.to_string()) });
+                    }
                     return;
                 }
+            };
+            let extension = source.extension();
+            let mut source = match decode_stream(source, &extension) {
+                Ok(source) => source,
+                Err(e) => {
+                    if is_current() {
+                        eprintln!("[Player] 音频解码失败: {}", e);
+                        *player.is_playing.lock().unwrap() = false;
+                        let _ = player.events.send(PlayerEvent::Error { track_id, error: PlaybackError::Decode(e) });
+                    }
+                    return;
+                }
+            };
+            if !is_current() {
+                return;
+            }
 
-                let mut file = match std::fs::File::create(&temp_path) {
-                    Ok(f) => f,
-                    Err(e) => {
-                        eprintln!("[Player] 无法创建临时文件: {}", e);
-                        *is_playing.lock().unwrap() = false;
-                        return;
+            let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
+            let start = seek_to_start(&mut source, start_position);
+            *player.range_stream.lock().unwrap() = Some((url.clone(), auth.clone()));
+            if let Ok(sink_guard) = player.sink.lock() {
+                if let Some(audio_sink) = sink_guard.as_ref() {
+                    audio_sink.stop();
+                    audio_sink.append(output_source(source, &player.channel_mix, &player.spectrum, &player.levels, &player.next_track, &player.events, &player.clock, track_id, start));
+                    if start_position.is_some() {
+                        audio_sink.pause();
+                        *player.is_playing.lock().unwrap() = false;
+                    } else {
+                        audio_sink.play();
                     }
-                };
 
-                let mut downloaded = 0;
-                let mut response = response;
-                let mut started_playing = false;
+                    *player.current_duration.lock().unwrap() = duration;
+                    *player.current_time.lock().unwrap() = start;
+                    *player.current_path.lock().unwrap() = None;
+                    player.clock.set(start);
+                }
+            }
 
-                let on_track_end_clone = on_track_end.clone();
-                let track_ended_clone = track_ended.clone();
-                let current_metadata_clone = current_metadata.clone();
+            // Tags and cover come from a second pass over the file's head, once it's playing
+            let mut metadata = WebDAVStreamSource::open(&url, auth).map(TrackMetadata::from_stream).unwrap_or_default();
+            if metadata.title.is_none() {
+                metadata.title = Some(title_from_url(&url));
+            }
+            if metadata.duration.is_zero() {
+                metadata.duration = duration;
+            }
+            eprintln!("[Player] 远程流元数据: title={:?}, artist={:?}, duration={:?}",
+                metadata.title, metadata.artist, duration);
+            if !is_current() {
+                return;
+            }
+            *player.current_metadata.lock().unwrap() = Some(metadata.clone());
+            let _ = player.events.send(PlayerEvent::MetadataLoaded { metadata, duration });
+        });
+    }
 
-                loop {
-                    if *download_cancelled.lock().unwrap() {
-                        eprintln!("[Player] 下载已取消");
-                        let _ = std::fs::remove_file(&temp_path);
-                        return;
-                    }
+    // A Range stream of `url` that fills the cache as it goes and keeps the
+    // download progress up to date with how far it has read
+    fn open_stream(&self, url: &str, auth: Option<Credentials>) -> std::io::Result<WebDAVStreamSource> {
+        let downloaded_bytes = self.downloaded_bytes.clone();
+        let total_bytes = self.total_bytes.clone();
+        let source = WebDAVStreamSource::open(url, auth)?.cached();
+        *total_bytes.lock().unwrap() = source.byte_len();
+        Ok(source.on_read(move |read, len| {
+            *downloaded_bytes.lock().unwrap() = read;
+            *total_bytes.lock().unwrap() = len;
+        }))
+    }
 
-                    let mut chunk = vec![0u8; 16384];
-                    match response.read(&mut chunk) {
-                        Ok(0) => break,
-                        Ok(n) => {
-                            chunk.truncate(n);
-                            if let Err(e) = file.write_all(&chunk) {
-                                eprintln!("[Player] 写入文件失败: {}", e);
-                                let _ = std::fs::remove_file(&temp_path);
+    // Download a remote track (or inflate one from a zip archive) into the cache
+    // and play it as it arrives
+    fn play_download(&self, path_str: String, in_archive: bool, generation: u64, track_id: Option<String>, start_position: Option<Duration>) {
+        let sink = self.sink.clone();
+        let current_duration = self.current_duration.clone();
+        let current_path = self.current_path.clone();
+        let is_playing = self.is_playing.clone();
+        let clock = self.clock.clone();
+        let current_metadata = self.current_metadata.clone();
+        let download_cancelled = self.download_cancelled.clone();
+        let stream_auth = self.stream_auth.lock().unwrap().clone();
+        let read_ahead_bytes = *self.read_ahead_bytes.lock().unwrap();
+        let channel_mix = self.channel_mix.clone();
+        let spectrum = self.spectrum.clone();
+        let levels = self.levels.clone();
+        let next_track = self.next_track.clone();
+        let events = self.events.clone();
+        let current_time = self.current_time.clone();
+        let url = path_str.clone();
+        let url_for_title = path_str.clone();
+        let file_path = download::cache_path(&url);
+        let download_generation = self.download_generation.clone();
+        let downloaded_bytes = self.downloaded_bytes.clone();
+        let total_bytes = self.total_bytes.clone();
+        let progress = Arc::new(DownloadProgress::default());
+        *self.stream_progress.lock().unwrap() = Some(progress.clone());
+        // Stop, or another track starting, ends this download
+        let is_cancelled = move || *download_cancelled.lock().unwrap() || download_generation.load(Ordering::SeqCst) != generation;
+
+        let download_progress = progress.clone();
+        let download_cancelled = is_cancelled.clone();
+        std::thread::spawn(move || {
+            let mut on_progress = |_: &Path, downloaded: u64, total: u64| {
+                *downloaded_bytes.lock().unwrap() = downloaded;
+                *total_bytes.lock().unwrap() = total;
+                download_progress.update(downloaded, (total > 0).then_some(total));
+            };
+            let result = if in_archive {
+                download::unpack(&url, &download_cancelled, &mut on_progress)
+            } else {
+                download::fetch(&url, stream_auth, &download_cancelled, &mut on_progress)
+            };
+            match result {
+                Ok(path) => {
+                    let len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    download_progress.update(len, Some(len));
+                    download_progress.finish(Ok(()));
+                }
+                Err(DownloadError::Cancelled) => {
+                    eprintln!("[Player] 下载已取消，已保留部分数据");
+                    download_progress.finish(Err("下载已取消".to_string()));
+                }
+                Err(DownloadError::Failed(message)) => {
+                    eprintln!("[Player] 下载失败: {}", message);
+                    download_progress.finish(Err(message));
+                }
+            }
+        });
+
+        // Playback starts on its own thread once 1MB is in (which skips past large
+        // embedded covers), decoding the cache file while the download is still
+        // writing it, so there's no limit on how big a remote file can be
+        std::thread::spawn(move || {
+            let state = progress.wait_for(STREAMING_START_BYTES);
+            if is_cancelled() {
+                return;
+            }
+            if let Some(message) = state.error {
+                *is_playing.lock().unwrap() = false;
+                let _ = events.send(PlayerEvent::Error { track_id, error: PlaybackError::Download(message) });
+                return;
+            }
+
+            let extension = file_path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+            let decoded = if state.done {
+                play_local_file_async(&file_path, &extension, read_ahead_bytes)
+            } else {
+                match decode_growing_file(&file_path, &progress, &extension) {
+                    Ok(source) => Ok(source),
+                    Err(e) => {
+                        // Some files won't decode from a prefix; try again once complete
+                        eprintln!("[Player] 音频解码失败: {} (已下载: {} bytes)，等待下载完整文件...", e, state.written);
+                        let state = progress.wait_for(u64::MAX);
+                        if is_cancelled() {
+                            return;
+                        }
+                        match state.error {
+                            Some(message) => {
                                 *is_playing.lock().unwrap() = false;
+                                let _ = events.send(PlayerEvent::Error { track_id, error: PlaybackError::Download(message) });
                                 return;
                             }
-                            downloaded += n;
-                        }
-                        Err(e) => {
-                            eprintln!("[Player] 下载出错: {}", e);
-                            let _ = std::fs::remove_file(&temp_path);
-                            *is_playing.lock().unwrap() = false;
-                            return;
+                            None => play_local_file_async(&file_path, &extension, read_ahead_bytes),
                         }
                     }
+                }
+            };
+            let mut source = match decoded {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("[Player] 音频解码失败: {}", e);
+                    *is_playing.lock().unwrap() = false;
+                    let _ = events.send(PlayerEvent::Error { track_id, error: PlaybackError::Decode(e) });
+                    return;
+                }
+            };
+
+            let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
+            let start = seek_to_start(&mut source, start_position);
 
-                    if started_playing {
-                        continue;
+            let mut metadata = TrackMetadata::from_path(&file_path);
+            // Probed while the file is still arriving, so its size isn't the final one
+            if let Some(total) = progress.snapshot().total {
+                metadata.stream.set_file_size(total, duration);
+            }
+            // 从原始 URL 提取文件名作为标题（如果元数据无效）
+            if metadata.title.as_ref().is_none_or(|t| t.starts_with("dioxus_music_")) {
+                metadata.title = Some(title_from_url(&url_for_title));
+            }
+            eprintln!("[Player] 流式提取元数据: title={:?}, artist={:?}, duration={:?}",
+                metadata.title, metadata.artist, duration);
+            *current_metadata.lock().unwrap() = Some(metadata.clone());
+
+            if let Ok(sink_guard) = sink.lock() {
+                if let Some(audio_sink) = sink_guard.as_ref() {
+                    audio_sink.stop();
+                    audio_sink.append(output_source(source, &channel_mix, &spectrum, &levels, &next_track, &events, &clock, track_id, start));
+                    if start_position.is_some() {
+                        audio_sink.pause();
+                        *is_playing.lock().unwrap() = false;
+                    } else {
+                        audio_sink.play();
                     }
 
-                    // 统一使用 1MB 阈值，跳过可能的大封面等元数据
-                    if downloaded >= 1024 * 1024 {
-                        let file_for_play = match File::open(&temp_path) {
-                            Ok(f) => f,
-                            Err(e) => {
-                                eprintln!("[Player] 无法打开临时文件: {}", e);
-                                *is_playing.lock().unwrap() = false;
-                                return;
-                            }
-                        };
-                        let buf_reader = BufReader::new(file_for_play);
-
-                        match Decoder::new(buf_reader) {
-                            Ok(source) => {
-                                let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
-
-                                let mut metadata = TrackMetadata::from_path(&temp_path);
-
-                                // 从原始 URL 提取文件名作为标题（如果元数据无效）
-                                if metadata.title.is_none() || metadata.title.as_ref().map_or(false, |t| t.starts_with("dioxus_music_")) {
-                                    let filename = url.split('/').last().unwrap_or("Unknown");
-                                    let decoded_filename = match urlencoding::decode(filename) {
-                                        Ok(cow) => cow.into_owned(),
-                                        Err(_) => filename.to_string(),
-                                    };
-                                    let title = std::path::Path::new(&decoded_filename)
-                                        .file_stem()
-                                        .and_then(|s| s.to_str())
-                                        .unwrap_or(&decoded_filename)
-                                        .to_string();
-                                    metadata.title = Some(title);
-                                }
+                    *current_duration.lock().unwrap() = duration;
+                    *current_time.lock().unwrap() = start;
+                    *current_path.lock().unwrap() = Some(file_path);
+                    clock.set(start);
+                }
+            }
+            let _ = events.send(PlayerEvent::MetadataLoaded { metadata, duration });
+        });
+    }
 
-                                eprintln!("[Player] 流式提取元数据: title={:?}, artist={:?}, duration={:?}",
-                                    metadata.title, metadata.artist, duration);
-                                *current_metadata_clone.lock().unwrap() = Some(metadata);
-
-                                if let Ok(sink_guard) = sink.lock() {
-                                    if let Some(audio_sink) = sink_guard.as_ref() {
-                                        audio_sink.stop();
-                                        audio_sink.append(source);
-                                        audio_sink.play();
-                                        started_playing = true;
-                                        *playback_started.lock().unwrap() = true;
-
-                                        *current_duration.lock().unwrap() = duration;
-                                        *current_path.lock().unwrap() = Some(temp_path.clone());
-                                        *playback_start.lock().unwrap() = Some(std::time::Instant::now());
-
-                                        let sink_for_check = sink.clone();
-                                        let on_track_end_for_check = on_track_end_clone.clone();
-                                        let track_ended_for_check = track_ended_clone.clone();
-                                        let playback_started_for_check = playback_started.clone();
-                                        std::thread::spawn(move || {
-                                            loop {
-                                                std::thread::sleep(std::time::Duration::from_millis(100));
-                                                if let Ok(guard) = sink_for_check.lock() {
-                                                    if let Some(sink) = guard.as_ref() {
-                                                        if sink.empty() {
-                                                            if *playback_started_for_check.lock().unwrap() {
-                                                                if let Ok(mut callback_guard) = on_track_end_for_check.lock() {
-                                                                    if let Some(callback) = callback_guard.as_mut() {
-                                                                        callback();
-                                                                    }
-                                                                }
-                                                                *track_ended_for_check.lock().unwrap() = true;
-                                                            }
-                                                            break;
-                                                        }
-                                                    }
-                                                } else {
-                                                    break;
-                                                }
-                                            }
-                                        });
-                                    }
-                                }
-                            }
-                            Err(rodio_error) => {
-                                eprintln!("[Player] 音频解码失败: {} (已下载: {} bytes)", rodio_error, downloaded);
-                                
-                                if downloaded >= 1024 * 1024 {
-                                    eprintln!("[Player] 1MB数据已下载但解码失败，等待下载完整文件...");
-                                    started_playing = true;
-                                    continue;
-                                } else {
-                                    eprintln!("[Player] 数据不足，继续下载...");
-                                    std::thread::sleep(std::time::Duration::from_millis(500));
-                                }
-                            }
-                        }
-                    }
+    // Start a track the prefetcher already decoded. Remote tracks play from their
+    // cached download, which also makes seeking within them local.
+    fn play_prepared(&self, prepared: PreparedTrack, track_id: Option<String>) {
+        let PreparedTrack { mut source, metadata, duration, file } = prepared;
+        let start_position = self.start_position.lock().unwrap().take();
+        let start = seek_to_start(&mut source, start_position);
+        *self.current_metadata.lock().unwrap() = Some(metadata.clone());
+
+        if let Ok(sink_guard) = self.sink.lock() {
+            if let Some(audio_sink) = sink_guard.as_ref() {
+                audio_sink.stop();
+                audio_sink.append(output_source(source, &self.channel_mix, &self.spectrum, &self.levels, &self.next_track, &self.events, &self.clock, track_id, start));
+                if start_position.is_some() {
+                    audio_sink.pause();
+                    *self.is_playing.lock().unwrap() = false;
+                } else {
+                    audio_sink.play();
                 }
-            });
-        } else {
-            let extension = path.extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("")
-                .to_lowercase();
+                self.clock.set(start);
 
-            std::thread::spawn(move || {
-                let source_result = play_local_file_async(&path, &extension);
+                *self.current_duration.lock().unwrap() = duration;
+                *self.current_time.lock().unwrap() = start;
+                *self.current_path.lock().unwrap() = Some(file);
+            }
+        }
+        let _ = self.events.send(PlayerEvent::MetadataLoaded { metadata, duration });
+    }
 
-                match source_result {
-                    Ok(source) => {
-                        let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
+    /// Download (if remote) and decode a track in the background so a later
+    /// `play` of the same path starts immediately.
+    pub fn prefetch(&self, path: &Path, is_remote: bool, auth: Option<Credentials>) {
+        let read_ahead_bytes = *self.read_ahead_bytes.lock().unwrap();
+        self.prefetch.start(path.to_string_lossy().into_owned(), is_remote, auth, read_ahead_bytes);
+    }
 
-                        let metadata = TrackMetadata::from_path(&path);
-                        eprintln!("[Player] 本地提取元数据: title={:?}, artist={:?}, duration={:?}",
-                            metadata.title, metadata.artist, duration);
-                        *current_metadata.lock().unwrap() = Some(metadata);
+    pub fn clear_prefetch(&self) {
+        self.prefetch.clear();
+    }
 
-                        if let Ok(sink_guard) = sink.lock() {
-                            if let Some(audio_sink) = sink_guard.as_ref() {
-                                audio_sink.stop();
-                                audio_sink.append(source);
-                                audio_sink.play();
-                                *playback_started.lock().unwrap() = true;
-                                *playback_start.lock().unwrap() = Some(std::time::Instant::now());
+    /// Line up a prefetched track to take over when the current one ends:
+    /// gapless if it's the next track of the same album, otherwise crossfading
+    /// for the crossfade length. Returns false until the prefetch of `path` is
+    /// ready. A later `play`, `stop` or `clear_next` drops it.
+    pub fn queue_next(&self, path: &Path, track_id: Option<String>, is_remote: bool) -> bool {
+        let Some(prepared) = self.prefetch.take_ready(&path.to_string_lossy()) else {
+            return false;
+        };
+        let PreparedTrack { source, metadata, duration, file } = prepared;
+        let fade = match self.current_metadata.lock().unwrap().as_ref() {
+            Some(current) if continues_album(current, &metadata) => Duration::ZERO,
+            _ => *self.crossfade.lock().unwrap(),
+        };
+        eprintln!("[Player] 已排队下一首: {} (淡入淡出 {:?})", path.display(), fade);
+
+        let channel_mix = self.channel_mix.clone();
+        let spectrum = self.spectrum.clone();
+        let levels = self.levels.clone();
+        let events = self.events.clone();
+        let clock = self.clock.clone();
+        let chain_track_id = track_id.clone();
+        let build = Box::new(move |channels, sample_rate| {
+            let converted = Box::new(rodio::source::UniformSourceIterator::new(source, channels, sample_rate));
+            track_chain(converted, &channel_mix, &spectrum, &levels, &events, &clock, chain_track_id, Duration::ZERO)
+        });
 
-                                *current_duration.lock().unwrap() = duration;
-                                *current_path.lock().unwrap() = Some(path);
+        // Runs on the audio thread the moment the track takes over
+        let last_track_id = self.last_track_id.clone();
+        let remote = self.is_remote.clone();
+        let downloaded_bytes = self.downloaded_bytes.clone();
+        let total_bytes = self.total_bytes.clone();
+        let current_metadata = self.current_metadata.clone();
+        let current_duration = self.current_duration.clone();
+        let current_time = self.current_time.clone();
+        let current_path = self.current_path.clone();
+        let range_stream = self.range_stream.clone();
+        let clock = self.clock.clone();
+        let events = self.events.clone();
+        let on_start = Box::new(move || {
+            if let Some(id) = track_id.clone() {
+                *last_track_id.lock().unwrap() = Some(id);
+            }
+            *remote.lock().unwrap() = is_remote;
+            if !is_remote {
+                *downloaded_bytes.lock().unwrap() = 0;
+                *total_bytes.lock().unwrap() = 0;
+            }
+            *current_metadata.lock().unwrap() = Some(metadata.clone());
+            *current_duration.lock().unwrap() = duration;
+            *current_time.lock().unwrap() = Duration::ZERO;
+            *current_path.lock().unwrap() = Some(file);
+            *range_stream.lock().unwrap() = None;
+            clock.set(Duration::ZERO);
+            let _ = events.send(PlayerEvent::QueuedTrackStarted { track_id });
+            let _ = events.send(PlayerEvent::MetadataLoaded { metadata, duration });
+        });
 
-                                let sink_for_check = sink.clone();
-                                let on_track_end_for_check = on_track_end.clone();
-                                let track_ended_for_check = track_ended.clone();
-                                let playback_started_for_check = playback_started.clone();
-                                std::thread::spawn(move || {
-                                    loop {
-                                        std::thread::sleep(std::time::Duration::from_millis(100));
-                                        if let Ok(guard) = sink_for_check.lock() {
-                                            if let Some(sink) = guard.as_ref() {
-                                                if sink.empty() {
-                                                    if *playback_started_for_check.lock().unwrap() {
-                                                        if let Ok(mut callback_guard) = on_track_end_for_check.lock() {
-                                                            if let Some(callback) = callback_guard.as_mut() {
-                                                                callback();
-                                                            }
-                                                        }
-                                                        *track_ended_for_check.lock().unwrap() = true;
-                                                    }
-                                                    break;
-                                                }
-                                            }
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                });
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("[Player] 播放失败: {}", e);
-                        *is_playing.lock().unwrap() = false;
-                    }
-                }
-            });
-        }
+        self.next_track.set(QueuedTrack { build, fade, on_start });
+        true
+    }
+
+    pub fn clear_next(&self) {
+        self.next_track.clear();
+    }
+
+    /// How long unrelated tracks overlap when one hands over to the next; zero
+    /// plays them back to back.
+    pub fn set_crossfade(&self, crossfade: Duration) {
+        *self.crossfade.lock().unwrap() = crossfade;
     }
 
     fn play_local_file(&self, path: &Path, extension: &str) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, Box<dyn std::error::Error>> {
@@ -487,18 +759,17 @@ impl MusicPlayer {
 
         let file_size = file.metadata()?.len();
 
-        if file_size > MAX_FILE_SIZE {
-            return Err(format!("文件过大 ({}MB)，当前不支持播放超过 {}MB 的音频文件",
-                              file_size / (1024 * 1024), MAX_FILE_SIZE / (1024 * 1024)).into());
-        }
-
+        let read_ahead_bytes = *self.read_ahead_bytes.lock().unwrap();
         match std::panic::catch_unwind(|| {
-            Decoder::try_from(file)
+            decode_local_file(file, extension, read_ahead_bytes)
         }) {
-            Ok(Ok(source)) => Ok(Box::new(source) as Box<dyn rodio::Source<Item = f32> + Send>),
+            Ok(Ok(source)) => Ok(source),
             Ok(Err(rodio_error)) => {
-                Err(format!("音频解码失败 '{}': {}. 文件大小: {} bytes, 扩展名: {}",
-                          path.display(), rodio_error, file_size, extension).into())
+                let message = unsupported_codec_message(path, extension).unwrap_or_else(|| {
+                    format!("音频解码失败 '{}': {}. 文件大小: {} bytes, 扩展名: {}",
+                            path.display(), rodio_error, file_size, extension)
+                });
+                Err(message.into())
             }
             Err(_) => {
                 Err(format!("音频解码器在处理文件 '{}' 时发生内部错误。文件大小: {} bytes, 扩展名: {}",
@@ -557,12 +828,6 @@ impl MusicPlayer {
                 *total_guard = content_length;
             }
 
-            if content_length > MAX_FILE_SIZE {
-                let _ = tx.send(Err(format!("文件过大 ({}MB)，当前不支持播放超过 {}MB 的音频文件",
-                    content_length / (1024 * 1024), MAX_FILE_SIZE / (1024 * 1024))));
-                return;
-            }
-
             let mut file = match std::fs::File::create(&temp_path) {
                 Ok(f) => f,
                 Err(e) => {
@@ -681,8 +946,10 @@ impl MusicPlayer {
 
     pub fn stop(&self) {
         *self.is_playing.lock().unwrap() = false;
-        *self.stopped_by_user.lock().unwrap() = true;
         *self.download_cancelled.lock().unwrap() = true;
+        self.release_stream();
+        *self.range_stream.lock().unwrap() = None;
+        self.next_track.clear();
         if let Ok(sink_guard) = self.sink.lock() {
             if let Some(sink) = sink_guard.as_ref() {
                 sink.stop();
@@ -694,12 +961,35 @@ impl MusicPlayer {
         if let Ok(mut time_guard) = self.current_time.lock() {
             *time_guard = Duration::from_secs(0);
         }
+        self.clock.set(Duration::ZERO);
+        self.spectrum.clear();
+    }
+
+    // A decoder waiting on the current track's download could otherwise hold up
+    // the audio thread until the download notices it was cancelled
+    fn release_stream(&self) {
+        if let Some(progress) = self.stream_progress.lock().unwrap().take() {
+            progress.finish(Err("播放已停止".to_string()));
+        }
     }
 
     pub fn set_volume(&self, volume: f32) {
+        *self.volume.lock().unwrap() = volume.clamp(0.0, 1.0);
+        self.apply_volume();
+    }
+
+    /// Gain in dB for the track that's playing, on top of the master volume.
+    pub fn set_track_gain(&self, gain_db: f32) {
+        let gain_db = if gain_db.is_finite() { gain_db.clamp(-MAX_TRACK_GAIN_DB, MAX_TRACK_GAIN_DB) } else { 0.0 };
+        *self.track_gain_db.lock().unwrap() = gain_db;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&self) {
+        let volume = *self.volume.lock().unwrap() * 10f32.powf(*self.track_gain_db.lock().unwrap() / 20.0);
         if let Ok(sink_guard) = self.sink.lock() {
             if let Some(sink) = sink_guard.as_ref() {
-                sink.set_volume(volume.clamp(0.0, 1.0));
+                sink.set_volume(volume);
             }
         }
     }
@@ -743,9 +1033,6 @@ impl MusicPlayer {
         self.last_track_id.lock().unwrap().clone()
     }
     
-    pub fn reset_track_ended(&self) {
-        *self.track_ended.lock().unwrap() = false;
-    }
     
     pub fn get_current_time(&self) -> Duration {
         *self.current_time.lock().unwrap()
@@ -755,22 +1042,12 @@ impl MusicPlayer {
         *self.current_duration.lock().unwrap()
     }
 
+    // Position in the current track by the samples played so far, so it holds
+    // still through pauses and buffering stalls
     pub fn get_elapsed(&self) -> Duration {
-        let is_playing = *self.is_playing.lock().unwrap();
-        if !is_playing {
-            return *self.current_time.lock().unwrap();
-        }
-
-        if let Some(start_time) = *self.playback_start.lock().unwrap() {
-            let now = std::time::Instant::now();
-            let elapsed = now.duration_since(start_time);
-            let total = self.get_duration();
-            let elapsed = elapsed.min(total);
-            *self.current_time.lock().unwrap() = elapsed;
-            elapsed
-        } else {
-            *self.current_time.lock().unwrap()
-        }
+        let elapsed = self.clock.get();
+        let total = self.get_duration();
+        if total.is_zero() { elapsed } else { elapsed.min(total) }
     }
 
     pub fn get_current_metadata(&self) -> Option<TrackMetadata> {
@@ -811,11 +1088,67 @@ impl MusicPlayer {
 
     pub fn set_duration(&self, duration: Duration) {
         *self.current_duration.lock().unwrap() = duration;
-        *self.playback_start.lock().unwrap() = Some(std::time::Instant::now());
     }
     
-    pub fn set_stopped_by_user(&self, stopped: bool) {
-        *self.stopped_by_user.lock().unwrap() = stopped;
+    /// Credentials the next remote `play` signs its requests with
+    pub fn set_stream_auth(&self, auth: Option<Credentials>) {
+        *self.stream_auth.lock().unwrap() = auth;
+    }
+
+    // Stereo balance from -1.0 (left only) to 1.0 (right only)
+    pub fn set_balance(&self, balance: f32) {
+        self.channel_mix.set_balance(balance);
+    }
+
+    pub fn get_balance(&self) -> f32 {
+        self.channel_mix.balance()
+    }
+
+    pub fn set_mono_downmix(&self, mono: bool) {
+        self.channel_mix.set_mono(mono);
+    }
+
+    pub fn is_mono_downmix(&self) -> bool {
+        self.channel_mix.is_mono()
+    }
+
+    // Band levels (0.0..=1.0, low to high frequency) of what is currently playing;
+    // all zeros while paused or stopped
+    pub fn get_spectrum(&self) -> Vec<f32> {
+        if !*self.is_playing.lock().unwrap() || self.is_paused() {
+            return vec![0.0; SPECTRUM_BANDS];
+        }
+        self.spectrum.spectrum()
+    }
+
+    // The last few dozen milliseconds as `points` values in -1.0..=1.0, for an
+    // oscilloscope; flat while paused or stopped
+    pub fn get_waveform(&self, points: usize) -> Vec<f32> {
+        if !*self.is_playing.lock().unwrap() || self.is_paused() {
+            return vec![0.0; points];
+        }
+        self.spectrum.waveform(points)
+    }
+
+    // Left/right peak levels (0.0..=1.0) since the previous call
+    pub fn take_peak_levels(&self) -> (f32, f32) {
+        self.levels.take_peaks()
+    }
+
+    /// Make the next `play` load its track paused at `position` instead of
+    /// starting from the top. Used to reopen where the last session stopped.
+    pub fn set_start_position(&self, position: Duration) {
+        *self.start_position.lock().unwrap() = Some(position);
+    }
+
+    // Size of the background read-ahead buffer for local files; 0 reads directly
+    pub fn set_read_ahead_size(&self, bytes: usize) {
+        *self.read_ahead_bytes.lock().unwrap() = bytes;
+    }
+
+    // The receiving end of the player's event channel; only the first caller gets it
+    pub fn take_events(&self) -> Option<UnboundedReceiver<PlayerEvent>> {
+        self.event_receiver.lock().unwrap().take()
     }
     
     pub fn seek(&self, time: Duration) -> Result<(), Box<dyn std::error::Error>> {
@@ -823,6 +1156,19 @@ impl MusicPlayer {
             if let Some(sink) = sink_guard.as_ref() {
                 sink.stop();
 
+                // A track playing off the server is opened again at the new position,
+                // which waits on the network, so off this thread
+                let range_stream = self.range_stream.lock().unwrap().clone();
+                if let Some((url, auth)) = range_stream {
+                    eprintln!("[Player] 远程流跳转到 {} 秒", time.as_secs());
+                    let player = self.clone();
+                    let generation = self.download_generation.load(Ordering::SeqCst);
+                    std::thread::spawn(move || player.seek_stream(&url, auth, time, generation));
+                    self.clock.set(time);
+                    *self.current_time.lock().unwrap() = time;
+                    return Ok(());
+                }
+
                 // Check for temporary file first (WebDAV downloads)
                 let temp_path = {
                     let temp_guard = self.temp_file.lock().unwrap();
@@ -842,10 +1188,20 @@ impl MusicPlayer {
                     }
                 };
 
-                let path_str = play_path.to_string_lossy();
-                let is_remote = path_str.contains("dioxus_music_");
+                // Decoding a download in progress can wait on the network, so that
+                // happens off this thread
+                let streaming = self.stream_progress.lock().unwrap().clone().filter(|progress| !progress.snapshot().done);
+                if let Some(progress) = streaming {
+                    eprintln!("[Player] 下载中跳转到 {} 秒", time.as_secs());
+                    let player = self.clone();
+                    let generation = self.download_generation.load(Ordering::SeqCst);
+                    std::thread::spawn(move || player.seek_streaming(&play_path, &progress, time, generation));
+                    self.clock.set(time);
+                    *self.current_time.lock().unwrap() = time;
+                    return Ok(());
+                }
 
-                if is_remote {
+                if self.is_remote_track() {
                     eprintln!("[Player] Seeking remote track to {} seconds", time.as_secs());
                 } else {
                     eprintln!("[Player] Seeking to {} seconds", time.as_secs());
@@ -859,10 +1215,20 @@ impl MusicPlayer {
 
                 let source = self.play_local_file_with_seek(&path_clone, &extension, time)?;
 
-                sink.append(source);
+                sink.append(output_source(
+                    source,
+                    &self.channel_mix,
+                    &self.spectrum,
+                    &self.levels,
+                    &self.next_track,
+                    &self.events,
+                    &self.clock,
+                    self.get_last_track_id(),
+                    time,
+                ));
                 sink.play();
 
-                *self.playback_start.lock().unwrap() = Some(std::time::Instant::now() - time);
+                self.clock.set(time);
                 *self.current_time.lock().unwrap() = time;
 
                 return Ok(());
@@ -871,6 +1237,55 @@ impl MusicPlayer {
         Err("Failed to seek".into())
     }
     
+    fn seek_streaming(&self, path: &Path, progress: &Arc<DownloadProgress>, time: Duration, generation: u64) {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+        let mut source = match decode_growing_file(path, progress, &extension) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("[Player] 跳转失败: {}", e);
+                return;
+            }
+        };
+        let start = seek_to_start(&mut source, Some(time));
+        if self.download_generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        if let Ok(sink_guard) = self.sink.lock() {
+            if let Some(sink) = sink_guard.as_ref() {
+                sink.append(output_source(source, &self.channel_mix, &self.spectrum, &self.levels, &self.next_track, &self.events, &self.clock, self.get_last_track_id(), start));
+                sink.play();
+            }
+        }
+        self.clock.set(start);
+        *self.current_time.lock().unwrap() = start;
+    }
+
+    fn seek_stream(&self, url: &str, auth: Option<Credentials>, time: Duration, generation: u64) {
+        let decoded = self.open_stream(url, auth).map_err(|e| e.to_string()).and_then(|source| {
+            let extension = source.extension();
+            decode_stream(source, &extension)
+        });
+        let mut source = match decoded {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("[Player] 跳转失败: {}", e);
+                return;
+            }
+        };
+        let start = seek_to_start(&mut source, Some(time));
+        if self.download_generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        if let Ok(sink_guard) = self.sink.lock() {
+            if let Some(sink) = sink_guard.as_ref() {
+                sink.append(output_source(source, &self.channel_mix, &self.spectrum, &self.levels, &self.next_track, &self.events, &self.clock, self.get_last_track_id(), start));
+                sink.play();
+            }
+        }
+        self.clock.set(start);
+        *self.current_time.lock().unwrap() = start;
+    }
+
     fn play_local_file_with_seek(&self, path: &Path, extension: &str, seek_time: Duration) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, Box<dyn std::error::Error>> {
         match extension {
             "mp3" => {
@@ -882,7 +1297,12 @@ impl MusicPlayer {
                 let bytes_per_second = 16000;
                 let seek_byte = (seek_time.as_secs() * bytes_per_second).min(file_size.saturating_sub(100));
                 
-                let mut file = BufReader::new(file);
+                let read_ahead_bytes = *self.read_ahead_bytes.lock().unwrap();
+                let mut file: Box<dyn SeekRead> = if read_ahead_bytes > 0 {
+                    Box::new(ReadAheadReader::new(file, read_ahead_bytes)?)
+                } else {
+                    Box::new(BufReader::new(file))
+                };
                 
                 if seek_byte > 0 {
                     let _ = file.seek(SeekFrom::Start(seek_byte));
@@ -915,15 +1335,91 @@ impl MusicPlayer {
                     Err(e) => Err(format!("Failed to decode WAV: {}", e).into()),
                 }
             }
-            "flac" => {
-                // FLAC seeking is complex, just restart from beginning for now
-                eprintln!("[Player] FLAC seek not fully implemented, restarting from beginning");
-                self.play_local_file(path, extension)
-            }
+            // FLAC, MP4/AAC, Ogg: the decoder is opened seekable, so symphonia can seek
+            // through the container's own index
             _ => {
-                self.play_local_file(path, extension)
+                let mut source = self.play_local_file(path, extension)?;
+                if let Err(e) = source.try_seek(seek_time) {
+                    eprintln!("[Player] {} 无法跳转 ({}), 从头开始", extension, e);
+                }
+                Ok(source)
+            }
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost_at.lock().unwrap().is_some()
+    }
+
+    /// Reopen the output device after the previous one disappeared (unplugged
+    /// DAC, disconnected Bluetooth headphones) and continue the current track
+    /// from where the old device stopped.
+    pub fn recover_output_device(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.device_lost_at.lock().unwrap().is_none() {
+            return Ok(());
+        }
+
+        {
+            let mut last_attempt = self.last_recovery_attempt.lock().unwrap();
+            if last_attempt.is_some_and(|t| t.elapsed() < DEVICE_RETRY_INTERVAL) {
+                return Ok(());
             }
+            *last_attempt = Some(std::time::Instant::now());
         }
+
+        eprintln!("[Player] 音频设备丢失，尝试重新打开输出设备");
+        self.reopen_output()?;
+        eprintln!("[Player] 音频设备已恢复");
+        Ok(())
+    }
+
+    /// Play through the output device named `name` (one of `output_devices()`),
+    /// or the system default for None, carrying on from the same spot. A device
+    /// that isn't there falls back to the default until it comes back.
+    pub fn set_output_device(&self, name: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        if *self.output_device.lock().unwrap() == name {
+            return Ok(());
+        }
+        let previous = std::mem::replace(&mut *self.output_device.lock().unwrap(), name);
+        // The headless test player has no device to move
+        if self._stream.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        // Still on the old device, so that's the one to report and recover
+        self.reopen_output().inspect_err(|_| *self.output_device.lock().unwrap() = previous)
+    }
+
+    // Move playback onto a newly opened stream on the chosen device
+    fn reopen_output(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let device = self.output_device.lock().unwrap().clone();
+        let stream = open_output_stream(device.as_deref(), self.device_lost_at.clone())?;
+        let new_sink = Sink::connect_new(stream.mixer());
+
+        let was_playing = *self.is_playing.lock().unwrap();
+        // Nothing is played once the device is gone, so the clock stopped right there
+        let position = self.clock.get();
+        let duration = self.get_duration();
+        let position = if duration.is_zero() { position } else { position.min(duration) };
+
+        if let Ok(mut sink_guard) = self.sink.lock() {
+            if let Some(old_sink) = sink_guard.as_ref() {
+                new_sink.set_volume(old_sink.volume());
+            }
+            *sink_guard = Some(new_sink);
+        }
+        *self._stream.lock().unwrap() = Some(stream);
+        *self.device_lost_at.lock().unwrap() = None;
+        *self.last_recovery_attempt.lock().unwrap() = None;
+
+        if self.get_current_path().is_some() {
+            self.seek(position)?;
+            if !was_playing {
+                self.pause();
+            }
+        }
+
+        eprintln!("[Player] 已切换到输出设备 {}，从 {} 秒继续", device.as_deref().unwrap_or("(默认)"), position.as_secs());
+        Ok(())
     }
 
     pub fn get_lyric(&self) -> Option<Lyric> {
@@ -951,6 +1447,15 @@ impl MusicPlayer {
         }
     }
 
+    /// Reload the current track's lyrics if `lyric_path` is the local file it
+    /// would load, e.g. one just downloaded next to it.
+    pub fn lyric_file_changed(&self, lyric_path: &std::path::Path) {
+        let Some(music_path) = self.current_path.lock().unwrap().clone() else { return };
+        if lyrics::find_local_lyric(&music_path).as_deref() == Some(lyric_path) {
+            self.load_local_lyric(&music_path);
+        }
+    }
+
     pub async fn fetch_lyrics_for_current_track(&self, title: &str, artist: &str) {
         if title.is_empty() {
             return;
@@ -984,7 +1489,233 @@ impl MusicPlayer {
     }
 }
 
-fn play_local_file_async(path: &Path, extension: &str) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, String> {
+/// Names of the output devices playback can be sent to.
+pub fn output_devices() -> Vec<String> {
+    use rodio::cpal::traits::{DeviceTrait, HostTrait};
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            eprintln!("[Player] 无法列出输出设备: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+// Open the output device named `device`, or the default one, recording when the
+// device goes away so the player can recover onto whatever device is there next.
+fn open_output_stream(
+    device: Option<&str>,
+    device_lost_at: Arc<Mutex<Option<std::time::Instant>>>,
+) -> Result<OutputStream, rodio::StreamError> {
+    let on_error = move |err: rodio::cpal::StreamError| {
+        eprintln!("[Player] 音频输出错误: {}", err);
+        if matches!(err, rodio::cpal::StreamError::DeviceNotAvailable) {
+            let mut guard = device_lost_at.lock().unwrap();
+            if guard.is_none() {
+                *guard = Some(std::time::Instant::now());
+            }
+        }
+    };
+
+    let chosen = device.and_then(|name| {
+        use rodio::cpal::traits::{DeviceTrait, HostTrait};
+        let found = rodio::cpal::default_host()
+            .output_devices()
+            .ok()?
+            .find(|device| device.name().is_ok_and(|n| n == name));
+        if found.is_none() {
+            eprintln!("[Player] 找不到输出设备 {}，使用默认设备", name);
+        }
+        found
+    });
+    let builder = match chosen {
+        Some(device) => OutputStreamBuilder::from_device(device),
+        None => OutputStreamBuilder::from_default_device(),
+    };
+    builder
+        .and_then(|builder| builder.with_error_callback(on_error).open_stream())
+        .or_else(|_| OutputStreamBuilder::open_default_stream())
+}
+
+type Tapped = LevelTap<SpectrumTap<ChannelMix<Box<dyn Source<Item = f32> + Send>>>>;
+
+// Every source goes through the same chain before reaching the sink
+#[allow(clippy::too_many_arguments)]
+fn output_source(
+    source: Box<dyn Source<Item = f32> + Send>,
+    channel_mix: &Arc<ChannelMixSettings>,
+    spectrum: &Arc<SpectrumBuffer>,
+    levels: &Arc<LevelMeter>,
+    next_track: &Arc<NextTrack<Tapped>>,
+    events: &UnboundedSender<PlayerEvent>,
+    clock: &Arc<PlaybackClock>,
+    track_id: Option<String>,
+    start: Duration,
+) -> Handoff<Tapped> {
+    Handoff::new(track_chain(source, channel_mix, spectrum, levels, events, clock, track_id, start), start, next_track.clone())
+}
+
+// The part of the chain that belongs to one track, which a queued track gets
+// its own copy of
+#[allow(clippy::too_many_arguments)]
+fn track_chain(
+    source: Box<dyn Source<Item = f32> + Send>,
+    channel_mix: &Arc<ChannelMixSettings>,
+    spectrum: &Arc<SpectrumBuffer>,
+    levels: &Arc<LevelMeter>,
+    events: &UnboundedSender<PlayerEvent>,
+    clock: &Arc<PlaybackClock>,
+    track_id: Option<String>,
+    start: Duration,
+) -> EventTap<Tapped> {
+    let mixed = ChannelMix::new(source, channel_mix.clone());
+    let tapped = LevelTap::new(SpectrumTap::new(mixed, spectrum.clone()), levels.clone());
+    EventTap::new(tapped, events.clone(), clock.clone(), track_id, start)
+}
+
+// Continuous albums (live recordings, DJ mixes, classical works) run one track
+// into the next, so the album's next track follows without a gap or crossfade.
+// Without track numbers on both, any two tracks of the same album count.
+fn continues_album(current: &TrackMetadata, next: &TrackMetadata) -> bool {
+    let album = |metadata: &TrackMetadata| {
+        metadata.album.as_deref().map(|album| album.trim().to_lowercase()).filter(|album| !album.is_empty())
+    };
+    match (album(current), album(next)) {
+        (Some(current_album), Some(next_album)) if current_album == next_album => {
+            match (current.track_number, next.track_number) {
+                (Some(current_number), Some(next_number)) => next_number == current_number + 1,
+                _ => true,
+            }
+        }
+        _ => false,
+    }
+}
+
+// File name from the end of a URL, without its extension, for tracks whose tags
+// carry no title
+fn title_from_url(url: &str) -> String {
+    let filename = url.split('/').next_back().unwrap_or("Unknown");
+    let decoded_filename = match urlencoding::decode(filename) {
+        Ok(cow) => cow.into_owned(),
+        Err(_) => filename.to_string(),
+    };
+    Path::new(&decoded_filename)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(&decoded_filename)
+        .to_string()
+}
+
+trait SeekRead: Read + Seek + Send + Sync {}
+impl<T: Read + Seek + Send + Sync> SeekRead for T {}
+
+// Decode a local file, reading ahead on a background thread unless disabled. The
+// extension is passed to symphonia as a hint; without it MP4/AAC probing often
+// fails on files whose moov atom sits after the audio data.
+fn decode_local_file(file: File, extension: &str, read_ahead_bytes: usize) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, Box<dyn std::error::Error>> {
+    let byte_len = file.metadata()?.len();
+    let data: Box<dyn SeekRead> = if read_ahead_bytes == 0 {
+        Box::new(BufReader::new(file))
+    } else {
+        Box::new(ReadAheadReader::new(file, read_ahead_bytes)?)
+    };
+
+    let mut builder = Decoder::builder()
+        .with_data(data)
+        .with_byte_len(byte_len)
+        .with_seekable(true);
+    if !extension.is_empty() {
+        builder = builder.with_hint(extension);
+    }
+    Ok(Box::new(builder.build()?))
+}
+
+// Decode a remote track's cache file while it's still downloading. Reads that get
+// ahead of the download wait for it.
+fn decode_growing_file(path: &Path, progress: &Arc<DownloadProgress>, extension: &str) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, String> {
+    let file = GrowingFile::open(path, progress.clone()).map_err(|e| format!("无法打开缓存文件 '{}': {}", path.display(), e))?;
+    let mut builder = Decoder::builder()
+        .with_data(BufReader::new(file))
+        .with_seekable(true);
+    if let Some(total) = progress.snapshot().total {
+        builder = builder.with_byte_len(total);
+    }
+    if !extension.is_empty() {
+        builder = builder.with_hint(extension);
+    }
+    match std::panic::catch_unwind(|| builder.build()) {
+        Ok(Ok(decoder)) => Ok(Box::new(decoder)),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("音频解码器发生内部错误".to_string()),
+    }
+}
+
+// Decode a remote file as it's read off the server, read ahead on a background
+// thread so network hiccups don't reach the audio
+fn decode_stream(source: WebDAVStreamSource, extension: &str) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, String> {
+    let byte_len = source.byte_len();
+    let data = ReadAheadReader::new(source, STREAM_READ_AHEAD_BYTES).map_err(|e| e.to_string())?;
+    let mut builder = Decoder::builder()
+        .with_data(data)
+        .with_byte_len(byte_len)
+        .with_seekable(true);
+    if !extension.is_empty() {
+        builder = builder.with_hint(extension);
+    }
+    match std::panic::catch_unwind(|| builder.build()) {
+        Ok(Ok(decoder)) => Ok(Box::new(decoder)),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("音频解码器发生内部错误".to_string()),
+    }
+}
+
+// Remote files play straight off the server unless the cache has them in full
+// already
+fn streams_over_ranges(url: &str) -> bool {
+    fetched_by_url(url) && !download::is_cached(url)
+}
+
+// Files fetched from the URL they're played by; pages yt-dlp resolves aren't
+// files to ask for ranges of
+fn fetched_by_url(url: &str) -> bool {
+    #[cfg(feature = "ytdlp")]
+    if crate::ytdlp::is_supported_url(url) {
+        return false;
+    }
+    url.starts_with("http")
+}
+
+// Seek a freshly decoded source to a requested start position. Falls back to the
+// beginning if the format can't seek.
+fn seek_to_start<S: Source>(source: &mut S, position: Option<Duration>) -> Duration {
+    match position {
+        Some(position) if source.try_seek(position).is_ok() => position,
+        Some(position) => {
+            eprintln!("[Player] 无法跳转到 {:?}，从头开始", position);
+            Duration::ZERO
+        }
+        None => Duration::ZERO,
+    }
+}
+
+// A clearer message than symphonia's "unsupported codec" for formats that can be
+// tagged and listed but not decoded with the codecs this build has
+fn unsupported_codec_message(path: &Path, extension: &str) -> Option<String> {
+    match extension {
+        "opus" => Some(format!("'{}' 是 Opus 音频，当前的解码器 (symphonia 0.5) 还不支持 Opus 播放", path.display())),
+        "wv" => Some(format!("'{}' 是 WavPack 音频，当前的解码器还不支持 WavPack 播放", path.display())),
+        "wma" => Some(format!("'{}' 是 WMA 音频，当前的解码器还不支持 WMA 播放", path.display())),
+        "ape" => Some(format!("'{}' 是 Monkey's Audio (APE) 音频，当前的解码器还不支持 APE 播放", path.display())),
+        "m4a" | "mp4" | "m4b" => match crate::mp4_tags::audio_codec(path).as_deref() {
+            Some("alac") => Some(format!("'{}' 是 ALAC 无损音频，当前的解码器还不支持 ALAC 播放", path.display())),
+            Some("mp4a") | None => None,
+            Some(other) => Some(format!("'{}' 使用了不支持的 MP4 音频编码 '{}'", path.display(), other)),
+        },
+        _ => None,
+    }
+}
+
+fn play_local_file_async(path: &Path, extension: &str, read_ahead_bytes: usize) -> Result<Box<dyn rodio::Source<Item = f32> + Send>, String> {
     let metadata = std::fs::metadata(path)
         .map_err(|e| format!("无法访问文件 '{}': {}", path.display(), e))?;
 
@@ -1001,16 +1732,12 @@ fn play_local_file_async(path: &Path, extension: &str) -> Result<Box<dyn rodio::
 
     let file_size = file.metadata().map_err(|e| e.to_string())?.len();
 
-    if file_size > MAX_FILE_SIZE {
-        return Err(format!("文件过大 ({}MB)，当前不支持播放超过 {}MB 的音频文件",
-                          file_size / (1024 * 1024), MAX_FILE_SIZE / (1024 * 1024)));
-    }
-
-    let buf_reader = BufReader::new(file);
-
-    match Decoder::new(buf_reader) {
-        Ok(source) => Ok(Box::new(source) as Box<dyn rodio::Source<Item = f32> + Send>),
+    match decode_local_file(file, extension, read_ahead_bytes) {
+        Ok(source) => Ok(source),
         Err(rodio_error) => {
+            if let Some(message) = unsupported_codec_message(path, extension) {
+                return Err(message);
+            }
             Err(format!("音频解码失败 '{}': {}. 文件大小: {} bytes, 扩展名: {}",
                       path.display(), rodio_error, file_size, extension))
         }
@@ -1035,13 +1762,6 @@ fn play_remote_url_async(url: &str) -> Result<Box<dyn rodio::Source<Item = f32>
         return Err(format!("下载失败 (HTTP {})", response.status()));
     }
 
-    let content_length = response.content_length().unwrap_or(0);
-
-    if content_length > MAX_FILE_SIZE {
-        return Err(format!("文件过大 ({}MB)，当前不支持播放超过 {}MB 的音频文件",
-            content_length / (1024 * 1024), MAX_FILE_SIZE / (1024 * 1024)));
-    }
-
     let mut file = std::fs::File::create(&temp_path)
         .map_err(|e| format!("无法创建临时文件: {}", e))?;
 
@@ -1086,4 +1806,272 @@ impl Default for MusicPlayer {
     fn default() -> Self {
         Self::new().expect("Failed to initialize music player")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{drain_until_ended, wav_bytes, MockWebDAV};
+
+    #[test]
+    fn test_local_track_plays_to_end() {
+        let path = std::env::temp_dir().join(format!("dioxus_music_test_{}.wav", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wav_bytes(1500, 8000)).unwrap();
+
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.play(&path, Some("local".to_string()), false);
+
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(10)).expect("track never ended");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(seen.iter().any(|e| matches!(e, PlayerEvent::MetadataLoaded { duration, .. } if duration.as_millis() == 1500)));
+        assert!(seen.iter().any(|e| matches!(e, PlayerEvent::Progress { position } if *position >= Duration::from_secs(1))));
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "local"));
+    }
+
+    #[test]
+    fn test_missing_local_file_reports_not_found() {
+        let path = std::env::temp_dir().join(format!("dioxus_music_missing_{}.wav", uuid::Uuid::new_v4()));
+
+        let (player, _output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.play(&path, Some("missing".to_string()), false);
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        let (track_id, error) = loop {
+            match events.try_recv() {
+                Ok(PlayerEvent::Error { track_id, error }) => break (track_id, error),
+                Ok(_) => {}
+                Err(_) => {
+                    assert!(std::time::Instant::now() < deadline, "no error reported");
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+            }
+        };
+        assert_eq!(track_id.as_deref(), Some("missing"));
+        assert!(matches!(&error, PlaybackError::NotFound(missing) if *missing == path));
+        assert!(error.to_string().starts_with("file not found"));
+        assert!(!*player.is_playing.lock().unwrap());
+    }
+
+    #[test]
+    fn test_remote_track_streams_with_auth() {
+        let server = MockWebDAV::start(&[("music/long.wav", wav_bytes(70_000, 8000))], Some(("alice", "secret")));
+        let url = format!("{}/long.wav", server.url("music"));
+        download::remove_cached(&url);
+
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.set_stream_auth(Some(Credentials::basic("alice", "secret")));
+        player.play(Path::new(&url), Some("remote".to_string()), true);
+
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(30)).expect("track never ended");
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "remote"));
+        assert!(seen.iter().any(|e| matches!(e, PlayerEvent::MetadataLoaded { metadata, .. } if metadata.title.as_deref() == Some("long"))));
+        // Played straight off the server over Range requests, and cached as it went
+        let requests = server.requests();
+        assert_eq!(requests.first().map(String::as_str), Some("GET /dav/music/long.wav bytes=0-"));
+        assert!(requests.iter().all(|request| request.starts_with("GET /dav/music/long.wav bytes=")), "{:?}", requests);
+        assert!(download::is_cached(&url));
+
+        // Played again from the cache; the server is only asked whether the file has changed
+        let played = requests.len();
+        player.play(Path::new(&url), Some("again".to_string()), true);
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(30)).expect("replay never ended");
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "again"));
+        assert!(server.requests()[played..].iter().all(|request| request.ends_with("bytes=0-0")), "{:?}", server.requests());
+        assert!(download::is_cached(&url));
+        download::remove_cached(&url);
+    }
+
+    #[test]
+    fn test_changed_file_drops_out_of_the_cache() {
+        let server = MockWebDAV::start(&[("music/edit.wav", wav_bytes(300, 8000))], None);
+        let url = format!("{}/edit.wav", server.url("music"));
+        download::remove_cached(&url);
+        assert!(download::fetch(&url, None, &|| false, &mut |_, _, _| {}).is_ok());
+
+        download::revalidate(&url, None);
+        assert!(download::is_cached(&url));
+        server.put("music/edit.wav", wav_bytes(400, 8000));
+        download::revalidate(&url, None);
+        assert!(!download::is_cached(&url));
+        assert!(!download::cache_path(&url).exists());
+    }
+
+    #[test]
+    fn test_stream_carries_on_after_a_dropped_connection() {
+        // The connection drops partway through; the track has to carry on from
+        // there with a new request rather than end
+        let server = MockWebDAV::start(&[("music/mix.wav", wav_bytes(200_000, 8000))], None);
+        let url = format!("{}/mix.wav", server.url("music"));
+        download::remove_cached(&url);
+        server.cut_off_after(1_200_000, 1);
+
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.play(Path::new(&url), Some("mix".to_string()), true);
+
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(30)).expect("track never ended");
+        download::remove_cached(&url);
+        let last_position = seen.iter().rev().find_map(|e| match e {
+            PlayerEvent::Progress { position } => Some(*position),
+            _ => None,
+        });
+        assert!(last_position.is_some_and(|position| position >= Duration::from_secs(199)), "ended early at {:?}", last_position);
+        assert!(server.requests().contains(&"GET /dav/music/mix.wav bytes=1200000-".to_string()), "{:?}", server.requests());
+    }
+
+    #[test]
+    fn test_interrupted_download_resumes_and_replays_from_cache() {
+        let server = MockWebDAV::start(&[("music/flaky.wav", wav_bytes(70_000, 8000))], None);
+        let url = format!("{}/flaky.wav", server.url("music"));
+        download::remove_cached(&url);
+        server.cut_off_after(300_000, 1);
+
+        // Prefetching downloads into the cache, picking up where the connection dropped
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.prefetch(Path::new(&url), true, None);
+        let deadline = std::time::Instant::now() + Duration::from_secs(30);
+        while !player.prefetch.is_ready(&url) {
+            assert!(std::time::Instant::now() < deadline, "prefetch never finished");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        player.play(Path::new(&url), Some("flaky".to_string()), true);
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(30)).expect("track never ended");
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "flaky"));
+
+        // The finished download is played again rather than the server streamed
+        player.play(Path::new(&url), Some("again".to_string()), true);
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(30)).expect("replay never ended");
+        download::remove_cached(&url);
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "again"));
+        assert_eq!(
+            server.requests(),
+            vec!["GET /dav/music/flaky.wav".to_string(), "GET /dav/music/flaky.wav bytes=300000-".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_large_download_comes_over_several_connections() {
+        let data = wav_bytes(400_000, 8000);
+        let server = MockWebDAV::start(&[("music/set.wav", data.clone())], None);
+        let url = format!("{}/set.wav", server.url("music"));
+        download::remove_cached(&url);
+        // The first connection drops; the next attempt carries on from there, ranges and all
+        server.cut_off_after(300_000, 1);
+
+        let Ok(path) = download::fetch(&url, None, &|| false, &mut |_, _, _| {}) else { panic!("download failed") };
+        assert!(std::fs::read(&path).unwrap() == data, "reassembled out of order");
+        assert!(download::is_cached(&url));
+        download::remove_cached(&url);
+        let requests = server.requests();
+        for expected in [
+            "GET /dav/music/set.wav",
+            "GET /dav/music/set.wav bytes=1048576-2097151",
+            "GET /dav/music/set.wav bytes=300000-",
+            "GET /dav/music/set.wav bytes=1348576-2397151",
+        ] {
+            assert!(requests.contains(&expected.to_string()), "no {} in {:?}", expected, requests);
+        }
+    }
+
+    #[test]
+    fn test_download_keeps_to_the_server_speed_limit() {
+        // 320KB at 200KB/s
+        let server = MockWebDAV::start(&[("music/slow.wav", wav_bytes(20_000, 8000))], None);
+        let url = format!("{}/slow.wav", server.url("music"));
+        download::remove_cached(&url);
+        crate::throttle::set_limit(&server.url("music"), 200);
+
+        let started = std::time::Instant::now();
+        assert!(download::fetch(&url, None, &|| false, &mut |_, _, _| {}).is_ok());
+        download::remove_cached(&url);
+        assert!(started.elapsed() >= Duration::from_millis(1400), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn test_queued_track_follows_without_track_ended() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("dioxus_music_test_{}.wav", uuid::Uuid::new_v4()));
+        let second = dir.join(format!("dioxus_music_test_{}.wav", uuid::Uuid::new_v4()));
+        std::fs::write(&first, wav_bytes(500, 8000)).unwrap();
+        // A different rate, so the handover has to convert it
+        std::fs::write(&second, wav_bytes(500, 11025)).unwrap();
+
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.play(&first, Some("first".to_string()), false);
+        player.prefetch(&second, false, None);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !player.queue_next(&second, Some("second".to_string()), false) {
+            assert!(std::time::Instant::now() < deadline, "prefetch never finished");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(10)).expect("track never ended");
+        let _ = std::fs::remove_file(&first);
+        let _ = std::fs::remove_file(&second);
+
+        assert!(seen.iter().any(|e| matches!(e, PlayerEvent::QueuedTrackStarted { track_id: Some(id) } if id == "second")));
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "second"));
+        assert_eq!(player.get_last_track_id().as_deref(), Some("second"));
+        assert_eq!(player.get_current_path(), Some(second));
+    }
+
+    #[test]
+    fn test_continues_album() {
+        let track = |album: &str, number: Option<u32>| TrackMetadata {
+            album: Some(album.to_string()),
+            track_number: number,
+            ..Default::default()
+        };
+        assert!(continues_album(&track("Live at Leeds", Some(3)), &track("live at leeds ", Some(4))));
+        assert!(!continues_album(&track("Live at Leeds", Some(3)), &track("Live at Leeds", Some(7))));
+        assert!(continues_album(&track("Mix Session", None), &track("Mix Session", Some(2))));
+        assert!(!continues_album(&track("A", Some(1)), &track("B", Some(2))));
+        assert!(!continues_album(&track("", Some(1)), &track("", Some(2))));
+    }
+
+    #[test]
+    fn test_track_gain_scales_master_volume() {
+        let (player, _output) = MusicPlayer::new_headless();
+        let sink_volume = || player.sink.lock().unwrap().as_ref().unwrap().volume();
+        player.set_volume(0.5);
+        player.set_track_gain(6.0);
+        assert!((sink_volume() - 0.5 * 1.995).abs() < 0.01);
+        // The gain stays with the track as the master volume moves
+        player.set_volume(0.25);
+        assert!((sink_volume() - 0.25 * 1.995).abs() < 0.01);
+        player.set_track_gain(f32::NAN);
+        assert_eq!(sink_volume(), 0.25);
+    }
+
+    #[test]
+    fn test_prefetched_remote_track_plays_from_download() {
+        let server = MockWebDAV::start(&[("music/next.wav", wav_bytes(1500, 8000))], None);
+        let url = format!("{}/next.wav", server.url("music"));
+        download::remove_cached(&url);
+
+        let (player, mut output) = MusicPlayer::new_headless();
+        let mut events = player.take_events().unwrap();
+        player.prefetch(Path::new(&url), true, None);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !player.prefetch.is_ready(&url) {
+            assert!(std::time::Instant::now() < deadline, "prefetch never finished");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        player.play(Path::new(&url), Some("next".to_string()), true);
+
+        let seen = drain_until_ended(&mut output, &mut events, Duration::from_secs(10)).expect("track never ended");
+        assert!(seen.iter().any(|e| matches!(e, PlayerEvent::MetadataLoaded { metadata, .. } if metadata.title.as_deref() == Some("next"))));
+        assert!(matches!(seen.last(), Some(PlayerEvent::TrackEnded { track_id: Some(id) }) if id == "next"));
+        assert_eq!(server.requests(), vec!["GET /dav/music/next.wav".to_string()]);
+
+        // Seeking reads the cached download
+        assert_eq!(player.current_path.lock().unwrap().clone(), Some(download::cache_path(&url)));
+        download::remove_cached(&url);
+    }
+}
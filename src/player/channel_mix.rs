@@ -0,0 +1,128 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Balance and mono settings shared with the audio thread; changes apply to the
+/// next frame without restarting playback.
+pub struct ChannelMixSettings {
+    balance: AtomicU32,
+    mono: AtomicBool,
+}
+
+impl ChannelMixSettings {
+    pub fn new() -> Self {
+        ChannelMixSettings {
+            balance: AtomicU32::new(0f32.to_bits()),
+            mono: AtomicBool::new(false),
+        }
+    }
+
+    // -1.0 is full left, 1.0 full right
+    pub fn set_balance(&self, balance: f32) {
+        let balance = if balance.is_finite() { balance.clamp(-1.0, 1.0) } else { 0.0 };
+        self.balance.store(balance.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn balance(&self) -> f32 {
+        f32::from_bits(self.balance.load(Ordering::Relaxed))
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+}
+
+/// Per-channel gain stage: optional mono downmix followed by left/right balance.
+pub struct ChannelMix<S> {
+    input: S,
+    settings: Arc<ChannelMixSettings>,
+    frame: Vec<f32>,
+    pos: usize,
+}
+
+impl<S: Source> ChannelMix<S> {
+    pub fn new(input: S, settings: Arc<ChannelMixSettings>) -> Self {
+        ChannelMix {
+            input,
+            settings,
+            frame: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn fill_frame(&mut self) -> bool {
+        let channels = self.input.channels() as usize;
+        self.frame.clear();
+        self.pos = 0;
+        for _ in 0..channels.max(1) {
+            match self.input.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return false;
+        }
+
+        if channels >= 2 {
+            if self.settings.is_mono() {
+                let right = self.frame.get(1).copied().unwrap_or(self.frame[0]);
+                let mixed = (self.frame[0] + right) / 2.0;
+                self.frame[0] = mixed;
+                if let Some(right) = self.frame.get_mut(1) {
+                    *right = mixed;
+                }
+            }
+
+            let balance = self.settings.balance();
+            self.frame[0] *= (1.0 - balance).min(1.0);
+            if let Some(right) = self.frame.get_mut(1) {
+                *right *= (1.0 + balance).min(1.0);
+            }
+        }
+        true
+    }
+}
+
+impl<S: Source> Iterator for ChannelMix<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.frame.len() && !self.fill_frame() {
+            return None;
+        }
+        let sample = self.frame[self.pos];
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for ChannelMix<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.frame.clear();
+        self.pos = 0;
+        self.input.try_seek(pos)
+    }
+}
@@ -0,0 +1,753 @@
+use crate::webdav::Credentials;
+use once_cell::sync::Lazy;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+// Remote tracks are kept in a cache under the config dir, one file per URL with
+// the server's ETag (or Last-Modified) beside it, so playing a track again doesn't
+// fetch it again and a cached track plays offline. Interrupted downloads (network
+// errors, or the track being skipped) keep their partial data and continue with a
+// Range request, guarded by If-Range so a file changed on the server starts over.
+
+const CACHE_PREFIX: &str = "dioxus_music_cache_";
+pub const DEFAULT_CACHE_LIMIT_MB: u32 = 1024;
+// Least recently played tracks are deleted once the cache grows past this
+static CACHE_LIMIT_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_CACHE_LIMIT_MB as u64 * 1024 * 1024);
+// Checking a cached track against the server shouldn't hold anything up for long
+const REVALIDATE_TIMEOUT: Duration = Duration::from_secs(15);
+
+static CACHE_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    // Tests cache throwaway files, which have no business in the real cache
+    let dir = if cfg!(test) { None } else { crate::base_config_dir().ok().map(|dir| dir.join("track_cache")) };
+    match dir {
+        Some(dir) => match std::fs::create_dir_all(&dir) {
+            Ok(()) => dir,
+            Err(e) => {
+                eprintln!("[Download] 无法创建缓存目录 {}，改用临时目录: {}", dir.display(), e);
+                std::env::temp_dir()
+            }
+        },
+        None => std::env::temp_dir(),
+    }
+});
+
+// Consecutive failed attempts (no new data) before giving up
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const CHUNK_SIZE: usize = 16384;
+// Files with at least this much left to fetch come down over several connections
+// at once, a range each, which high-latency servers need to get anywhere near the
+// line speed
+const PARALLEL_MIN_BYTES: u64 = 4 * 1024 * 1024;
+const RANGE_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_DOWNLOAD_CONNECTIONS: usize = 4;
+static DOWNLOAD_CONNECTIONS: AtomicUsize = AtomicUsize::new(DEFAULT_DOWNLOAD_CONNECTIONS);
+
+pub enum DownloadError {
+    Cancelled,
+    Failed(String),
+}
+
+enum AttemptError {
+    Cancelled,
+    Fatal(String),
+    Retry { message: String, progressed: bool },
+}
+
+// Sidecar next to each cached file
+#[derive(Default, Serialize, Deserialize)]
+struct CacheInfo {
+    // Strong ETag or Last-Modified, sent as If-Range when resuming
+    validator: Option<String>,
+    total: Option<u64>,
+    complete: bool,
+}
+
+// Cache files being written right now; a second download of the same URL waits
+static ACTIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static ACTIVE_CHANGED: Condvar = Condvar::new();
+
+struct ActiveGuard(PathBuf);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.lock().unwrap().retain(|path| *path != self.0);
+        ACTIVE_CHANGED.notify_all();
+    }
+}
+
+fn claim(path: &Path, is_cancelled: &dyn Fn() -> bool) -> Result<ActiveGuard, DownloadError> {
+    let mut active = ACTIVE.lock().unwrap();
+    while active.iter().any(|p| p == path) {
+        if is_cancelled() {
+            return Err(DownloadError::Cancelled);
+        }
+        active = ACTIVE_CHANGED.wait_timeout(active, Duration::from_millis(100)).unwrap().0;
+    }
+    active.push(path.to_path_buf());
+    Ok(ActiveGuard(path.to_path_buf()))
+}
+
+/// Where `url` is cached. Keeps the URL's extension so decoders get a hint.
+pub fn cache_path(url: &str) -> PathBuf {
+    let digest = Sha256::digest(url.as_bytes());
+    let hash: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    let extension = Path::new(url.split(['?', '#']).next().unwrap_or(url))
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| format!(".{}", ext.to_lowercase()))
+        .unwrap_or_default();
+    CACHE_DIR.join(format!("{}{}{}", CACHE_PREFIX, hash, extension))
+}
+
+fn info_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+fn read_info(path: &Path) -> CacheInfo {
+    std::fs::read_to_string(info_path(path))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_info(path: &Path, info: &CacheInfo) {
+    if let Ok(content) = serde_json::to_string(info) {
+        if let Err(e) = std::fs::write(info_path(path), content) {
+            eprintln!("[Download] 无法保存缓存信息: {}", e);
+        }
+    }
+}
+
+/// Whether the cache has all of `url`, so playing it needs no network.
+pub fn is_cached(url: &str) -> bool {
+    let path = cache_path(url);
+    let info = read_info(&path);
+    info.complete && info.total.is_some() && info.total == std::fs::metadata(&path).map(|m| m.len()).ok()
+}
+
+/// Drop `url` from the cache, complete or partial.
+#[cfg(test)]
+pub fn remove_cached(url: &str) {
+    remove_entry(&cache_path(url));
+}
+
+fn remove_entry(path: &Path) -> bool {
+    let _ = std::fs::remove_file(info_path(path));
+    std::fs::remove_file(path).is_ok()
+}
+
+/// Cap the cache at `bytes`, deleting the least recently played tracks past it
+/// in the background.
+pub fn set_cache_limit(bytes: u64) {
+    CACHE_LIMIT_BYTES.store(bytes, Ordering::Relaxed);
+    std::thread::spawn(|| prune_cache(None));
+}
+
+/// How much the cached tracks take up, partial ones included.
+pub fn cache_size() -> u64 {
+    cached_files().iter().map(|(_, len, _)| len).sum()
+}
+
+/// Ask the server whether a cached `url` is still the file it was, and drop it
+/// if not, so the next play fetches it again. Offline, or with nothing to
+/// compare, the cached copy stays.
+pub fn revalidate(url: &str, auth: Option<Credentials>) {
+    let path = cache_path(url);
+    let info = read_info(&path);
+    let Some(cached) = info.validator.filter(|_| info.complete) else { return };
+    let Ok(client) = Client::builder().timeout(REVALIDATE_TIMEOUT).build() else { return };
+    let mut request = client.get(url).header(RANGE, "bytes=0-0");
+    if let Some(credentials) = &auth {
+        request = credentials.authorize_blocking(request);
+    }
+    let Ok(response) = request.send() else { return };
+    if !response.status().is_success() {
+        return;
+    }
+    match validator(&response) {
+        Some(current) if current != cached => {
+            if ACTIVE.lock().unwrap().contains(&path) {
+                return;
+            }
+            eprintln!("[Download] 服务器上的文件已更改，丢弃缓存: {}", url);
+            remove_entry(&path);
+        }
+        _ => {}
+    }
+}
+
+/// Fills the cache with a track as it's streamed, so once it has been read
+/// through it plays again without the network. Only a run of bytes from the
+/// start is kept: reads past a gap (after a seek ahead) are left out, and a
+/// partial file is resumed by `fetch` like any interrupted download.
+pub struct CacheWriter {
+    path: PathBuf,
+    // None once the file is complete, or can't be written
+    file: Option<File>,
+    info: CacheInfo,
+    written: u64,
+    _active: ActiveGuard,
+}
+
+impl CacheWriter {
+    /// Start caching `url`, `total` bytes long with the server's `validator`.
+    /// None if it's cached already or being written by something else.
+    pub fn start(url: &str, validator: Option<String>, total: u64) -> Option<Self> {
+        let path = cache_path(url);
+        let active = {
+            let mut active = ACTIVE.lock().unwrap();
+            if active.contains(&path) {
+                return None;
+            }
+            active.push(path.clone());
+            ActiveGuard(path.clone())
+        };
+        let info = read_info(&path);
+        let len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if info.complete && info.total == Some(len) && info.validator == validator {
+            return None;
+        }
+        // What's there already carries on if it's the same file
+        let resumed = validator.is_some() && info.validator == validator && info.total == Some(total) && len < total;
+        let file = OpenOptions::new().create(true).append(true).open(&path).and_then(|file| {
+            if !resumed {
+                file.set_len(0)?;
+            }
+            Ok(file)
+        });
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("[Download] 无法创建缓存文件: {}", e);
+                return None;
+            }
+        };
+        let info = CacheInfo { validator, total: Some(total), complete: false };
+        write_info(&path, &info);
+        let written = if resumed { len } else { 0 };
+        Some(CacheWriter { path, file: Some(file), info, written, _active: active })
+    }
+
+    /// `data` was read from `offset` in the file.
+    pub fn wrote(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        let Some(file) = self.file.as_mut() else { return };
+        if offset > self.written || end <= self.written {
+            return;
+        }
+        if let Err(e) = file.write_all(&data[(self.written - offset) as usize..]) {
+            // Whatever made it is still a valid start of the file
+            eprintln!("[Download] 写入缓存失败: {}", e);
+            self.file = None;
+            return;
+        }
+        self.written = end;
+        if Some(self.written) == self.info.total {
+            self.file = None;
+            self.info.complete = true;
+            write_info(&self.path, &self.info);
+            eprintln!("[Download] 已缓存 {} ({} bytes)", self.path.display(), self.written);
+            prune_cache(Some(&self.path));
+        }
+    }
+}
+
+/// How many connections a large download may use at once; 1 fetches every
+/// file front to back over one.
+pub fn set_download_connections(connections: usize) {
+    DOWNLOAD_CONNECTIONS.store(connections.max(1), Ordering::Relaxed);
+}
+
+/// Download `url` into the cache and return the cached file. Returns at once if
+/// it's already complete; otherwise resumes from whatever is there.
+/// `on_progress` gets the file, bytes written so far and the total size (0 if
+/// unknown) after every chunk.
+pub fn fetch(
+    url: &str,
+    auth: Option<Credentials>,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<PathBuf, DownloadError> {
+    // Video pages are cached under the page's URL; yt-dlp only runs if the
+    // audio isn't cached already
+    #[cfg(feature = "ytdlp")]
+    if crate::ytdlp::is_supported_url(url) {
+        return fetch_from(url, &|| crate::ytdlp::stream_url(url), None, is_cancelled, on_progress);
+    }
+    fetch_from(url, &|| Ok(url.to_string()), auth, is_cancelled, on_progress)
+}
+
+// `fetch`, with the cache keyed by `key` and the URL to download from looked up
+// only once there's something to download
+fn fetch_from(
+    key: &str,
+    resolve: &dyn Fn() -> Result<String, String>,
+    auth: Option<Credentials>,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<PathBuf, DownloadError> {
+    let path = cache_path(key);
+    let _active = claim(&path, is_cancelled)?;
+
+    let mut info = read_info(&path);
+    let mut offset = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if info.complete && info.total == Some(offset) {
+        eprintln!("[Download] 使用缓存: {}", path.display());
+        // Refresh the mtime; pruning removes the least recently used files first
+        if let Ok(file) = OpenOptions::new().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        return Ok(path);
+    }
+    if info.validator.is_none() {
+        // Can't tell whether old partial data still matches the server's file
+        offset = 0;
+    }
+
+    let url = resolve().map_err(DownloadError::Failed)?;
+    let url = url.as_str();
+    let client = Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()
+        .map_err(|e| DownloadError::Failed(format!("创建HTTP客户端失败: {}", e)))?;
+
+    let mut failures = 0;
+    loop {
+        match fetch_once(&client, url, &auth, &path, &mut info, &mut offset, is_cancelled, on_progress) {
+            Ok(()) => {
+                info.complete = true;
+                info.total = Some(offset);
+                write_info(&path, &info);
+                eprintln!("[Download] 下载完成，共 {} bytes", offset);
+                prune_cache(Some(&path));
+                return Ok(path);
+            }
+            Err(AttemptError::Cancelled) => return Err(DownloadError::Cancelled),
+            Err(AttemptError::Fatal(message)) => return Err(DownloadError::Failed(message)),
+            Err(AttemptError::Retry { message, progressed }) => {
+                failures = if progressed { 1 } else { failures + 1 };
+                if failures >= MAX_ATTEMPTS {
+                    return Err(DownloadError::Failed(format!("{} (已重试 {} 次)", message, failures - 1)));
+                }
+                let delay = RETRY_BASE_DELAY * 2u32.pow(failures - 1);
+                eprintln!("[Download] {}，{} 秒后从 {} bytes 处继续", message, delay.as_secs(), offset);
+                let deadline = std::time::Instant::now() + delay;
+                while std::time::Instant::now() < deadline {
+                    if is_cancelled() {
+                        return Err(DownloadError::Cancelled);
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+}
+
+/// Inflate an entry of a zip archive (a path from `archive::entry_path`) into
+/// the cache, so it streams, seeks and prefetches the way a download does. The
+/// archive's size and mtime stand in for a validator.
+pub fn unpack(
+    key: &str,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<PathBuf, DownloadError> {
+    let (archive, entry) = crate::archive::split_path(key)
+        .ok_or_else(|| DownloadError::Failed(format!("'{}' 不是压缩包中的文件", key)))?;
+    let stamp = std::fs::metadata(archive)
+        .map_err(|e| DownloadError::Failed(format!("无法访问压缩包 '{}': {}", archive.display(), e)))?;
+    let validator = format!("{}:{:?}", stamp.len(), stamp.modified().ok());
+
+    let path = cache_path(key);
+    let _active = claim(&path, is_cancelled)?;
+    let info = read_info(&path);
+    let len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if info.complete && info.total == Some(len) && info.validator.as_ref() == Some(&validator) {
+        eprintln!("[Download] 使用缓存: {}", path.display());
+        if let Ok(file) = OpenOptions::new().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        return Ok(path);
+    }
+
+    let mut written = 0;
+    let result = crate::archive::extract_entry(archive, entry, &path, is_cancelled, &mut |so_far, total| {
+        written = so_far;
+        on_progress(&path, so_far, total);
+    });
+    match result {
+        Ok(()) => {
+            write_info(&path, &CacheInfo { validator: Some(validator), total: Some(written), complete: true });
+            prune_cache(Some(&path));
+            Ok(path)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => Err(DownloadError::Cancelled),
+        Err(e) => Err(DownloadError::Failed(format!("无法解压 '{}': {}", entry, e))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fetch_once(
+    client: &Client,
+    url: &str,
+    auth: &Option<Credentials>,
+    path: &Path,
+    info: &mut CacheInfo,
+    offset: &mut u64,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<(), AttemptError> {
+    let mut request = client.get(url);
+    if let Some(credentials) = auth {
+        request = credentials.authorize_blocking(request);
+    }
+    if *offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+        if let Some(validator) = &info.validator {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+
+    let response = request.send().map_err(|e| AttemptError::Retry {
+        message: format!("无法下载音频文件: {}", e),
+        progressed: false,
+    })?;
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The file on the server shrank; start over
+        *offset = 0;
+        return Err(AttemptError::Retry { message: "续传范围无效".to_string(), progressed: false });
+    }
+    // A 401 for an access token the server stopped taking early passes too: the
+    // retry gets a fresh one
+    if status.is_server_error() || (status == StatusCode::UNAUTHORIZED && auth.as_ref().is_some_and(Credentials::renew)) {
+        return Err(AttemptError::Retry { message: format!("下载失败 (HTTP {})", status), progressed: false });
+    }
+    if !status.is_success() {
+        return Err(AttemptError::Fatal(format!("下载失败 (HTTP {})", status)));
+    }
+
+    // A 200 means the server sent the whole file: no Range support, or If-Range
+    // found the file changed
+    let range = content_range(&response);
+    let resumed = *offset > 0 && status == StatusCode::PARTIAL_CONTENT && range.is_some_and(|(start, _)| start == *offset);
+    if status == StatusCode::PARTIAL_CONTENT && !resumed {
+        *offset = 0;
+        return Err(AttemptError::Retry { message: "服务器返回了意外的范围".to_string(), progressed: false });
+    }
+    if !resumed {
+        *offset = 0;
+    }
+    let total = if resumed {
+        range.and_then(|(_, total)| total)
+    } else {
+        response.content_length()
+    };
+    if !resumed {
+        *info = CacheInfo { validator: validator(&response), total, complete: false };
+        write_info(path, info);
+    }
+
+    let open = || -> std::io::Result<File> {
+        let mut file = OpenOptions::new().create(true).write(true).truncate(!resumed).open(path)?;
+        file.set_len(*offset)?;
+        file.seek(SeekFrom::Start(*offset))?;
+        Ok(file)
+    };
+    let mut file = open().map_err(|e| AttemptError::Fatal(format!("无法创建临时文件: {}", e)))?;
+
+    // Ranges need the server's word on the size, and a validator so they can't
+    // come from different versions of the file
+    let connections = DOWNLOAD_CONNECTIONS.load(Ordering::Relaxed);
+    let takes_ranges = status == StatusCode::PARTIAL_CONTENT
+        || response.headers().get(ACCEPT_RANGES).is_some_and(|value| value.as_bytes() == b"bytes");
+    if let (Some(total), Some(validator)) = (total, info.validator.clone()) {
+        if connections > 1 && takes_ranges && total >= *offset + PARALLEL_MIN_BYTES {
+            let ranges = Ranges { client, url, auth, validator: &validator, total, connections };
+            return fetch_ranges(&ranges, limited(url, response), &mut file, path, offset, is_cancelled, on_progress);
+        }
+    }
+    copy_body(&mut limited(url, response), &mut file, path, offset, total, total, is_cancelled, on_progress)
+}
+
+// A response body read no faster than the speed limit of the server it's from, if it has one
+pub(super) fn limited(url: &str, response: Response) -> Box<dyn Read + Send> {
+    match crate::throttle::for_url(url) {
+        Some(throttle) => Box::new(crate::throttle::Throttled::new(response, throttle)),
+        None => Box::new(response),
+    }
+}
+
+// Write `body` to the end of the file until `end` (or until it runs out, if
+// unknown), reporting progress against `total`
+#[allow(clippy::too_many_arguments)]
+fn copy_body(
+    body: &mut dyn Read,
+    file: &mut File,
+    path: &Path,
+    offset: &mut u64,
+    end: Option<u64>,
+    total: Option<u64>,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<(), AttemptError> {
+    let mut progressed = false;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        if is_cancelled() {
+            return Err(AttemptError::Cancelled);
+        }
+        let wanted = match end {
+            Some(end) if *offset >= end => return Ok(()),
+            Some(end) => chunk.len().min((end - *offset) as usize),
+            None => chunk.len(),
+        };
+        match body.read(&mut chunk[..wanted]) {
+            Ok(0) => {
+                return match end {
+                    Some(end) if *offset < end => Err(AttemptError::Retry {
+                        message: format!("连接提前关闭 ({}/{} bytes)", offset, end),
+                        progressed,
+                    }),
+                    _ => Ok(()),
+                };
+            }
+            Ok(n) => {
+                file.write_all(&chunk[..n]).map_err(|e| AttemptError::Fatal(format!("写入文件失败: {}", e)))?;
+                *offset += n as u64;
+                progressed = true;
+                on_progress(path, *offset, total.unwrap_or(0));
+            }
+            Err(e) => return Err(AttemptError::Retry { message: format!("下载出错: {}", e), progressed }),
+        }
+    }
+}
+
+// What the connections fetching one file's ranges share
+struct Ranges<'a> {
+    client: &'a Client,
+    url: &'a str,
+    auth: &'a Option<Credentials>,
+    validator: &'a str,
+    total: u64,
+    connections: usize,
+}
+
+// How far the extra connections have got: the next range to start, how many
+// have been written to the file, and whether to stop
+#[derive(Default)]
+struct RangeWindow {
+    next: usize,
+    written: usize,
+    stopped: bool,
+}
+
+// The rest of a file from `offset`, over several connections. The response
+// already under way carries on for the first range, straight into the file for
+// the decoder to follow; the others fetch the ranges after it into memory, at
+// most one per connection ahead of what's been written, and each is written
+// once the ones before it are in. The file on disk is always a run of bytes
+// from the start, so an interrupted download resumes as usual.
+fn fetch_ranges(
+    ranges: &Ranges,
+    mut first: Box<dyn Read + Send>,
+    file: &mut File,
+    path: &Path,
+    offset: &mut u64,
+    is_cancelled: &dyn Fn() -> bool,
+    on_progress: &mut dyn FnMut(&Path, u64, u64),
+) -> Result<(), AttemptError> {
+    let started = *offset;
+    let spans: Vec<(u64, u64)> = (started + RANGE_BYTES..ranges.total)
+        .step_by(RANGE_BYTES as usize)
+        .map(|start| (start, (start + RANGE_BYTES).min(ranges.total)))
+        .collect();
+    let window = (Mutex::new(RangeWindow::default()), Condvar::new());
+    let stopped = || window.0.lock().unwrap().stopped;
+    let (sender, receiver) = channel();
+    eprintln!("[Download] 分 {} 段并行下载，{} 个连接", spans.len() + 1, ranges.connections);
+
+    std::thread::scope(|scope| {
+        for _ in 0..(ranges.connections - 1).min(spans.len()) {
+            let (sender, window, spans, stopped) = (sender.clone(), &window, &spans, &stopped);
+            scope.spawn(move || loop {
+                let index = {
+                    let mut state = window.0.lock().unwrap();
+                    while !state.stopped && state.next < spans.len() && state.next >= state.written + ranges.connections {
+                        state = window.1.wait(state).unwrap();
+                    }
+                    if state.stopped || state.next >= spans.len() {
+                        return;
+                    }
+                    state.next += 1;
+                    state.next - 1
+                };
+                let (start, end) = spans[index];
+                if sender.send((index, fetch_range(ranges, start, end, stopped))).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(sender);
+
+        let result = (|| {
+            copy_body(&mut first, file, path, offset, Some(started + RANGE_BYTES), Some(ranges.total), is_cancelled, on_progress)?;
+            drop(first);
+            let mut ready = BTreeMap::new();
+            for index in 0..spans.len() {
+                let data = loop {
+                    if let Some(data) = ready.remove(&index) {
+                        break data;
+                    }
+                    if is_cancelled() {
+                        return Err(AttemptError::Cancelled);
+                    }
+                    match receiver.recv_timeout(Duration::from_millis(100)) {
+                        Ok((done, Ok(data))) => {
+                            ready.insert(done, data);
+                        }
+                        Ok((_, Err(AttemptError::Retry { message, .. }))) => {
+                            return Err(AttemptError::Retry { message, progressed: *offset > started });
+                        }
+                        Ok((_, Err(e))) => return Err(e),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(AttemptError::Retry {
+                                message: "下载连接意外中断".to_string(),
+                                progressed: *offset > started,
+                            });
+                        }
+                    }
+                };
+                file.write_all(&data).map_err(|e| AttemptError::Fatal(format!("写入文件失败: {}", e)))?;
+                *offset += data.len() as u64;
+                on_progress(path, *offset, ranges.total);
+                window.0.lock().unwrap().written = index + 1;
+                window.1.notify_all();
+            }
+            Ok(())
+        })();
+        window.0.lock().unwrap().stopped = true;
+        window.1.notify_all();
+        result
+    })
+}
+
+// Bytes `start..end` of the file, if it's still the version being downloaded
+fn fetch_range(ranges: &Ranges, start: u64, end: u64, stopped: &dyn Fn() -> bool) -> Result<Vec<u8>, AttemptError> {
+    let mut request = ranges
+        .client
+        .get(ranges.url)
+        .header(RANGE, format!("bytes={}-{}", start, end - 1))
+        .header(IF_RANGE, ranges.validator);
+    if let Some(credentials) = ranges.auth {
+        request = credentials.authorize_blocking(request);
+    }
+    let response = request.send().map_err(|e| AttemptError::Retry {
+        message: format!("无法下载音频文件: {}", e),
+        progressed: false,
+    })?;
+    let status = response.status();
+    // A 401 for an access token the server stopped taking early passes too: the
+    // retry gets a fresh one
+    if status.is_server_error() || (status == StatusCode::UNAUTHORIZED && ranges.auth.as_ref().is_some_and(Credentials::renew)) {
+        return Err(AttemptError::Retry { message: format!("下载失败 (HTTP {})", status), progressed: false });
+    }
+    if !status.is_success() {
+        return Err(AttemptError::Fatal(format!("下载失败 (HTTP {})", status)));
+    }
+    // A 200 here means If-Range found the file changed; the retry starts it over
+    if status != StatusCode::PARTIAL_CONTENT || content_range(&response).map(|(from, _)| from) != Some(start) {
+        return Err(AttemptError::Retry { message: "服务器返回了意外的范围".to_string(), progressed: false });
+    }
+
+    let len = end - start;
+    let mut data = Vec::with_capacity(len as usize);
+    let mut body = limited(ranges.url, response).take(len);
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        if stopped() {
+            return Err(AttemptError::Cancelled);
+        }
+        match body.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => data.extend_from_slice(&chunk[..n]),
+            Err(e) => return Err(AttemptError::Retry { message: format!("下载出错: {}", e), progressed: false }),
+        }
+    }
+    if (data.len() as u64) < len {
+        return Err(AttemptError::Retry {
+            message: format!("连接提前关闭 ({}/{} bytes)", start + data.len() as u64, end),
+            progressed: false,
+        });
+    }
+    Ok(data)
+}
+
+// "bytes 100-199/2000" -> (100, Some(2000)); the total may be "*"
+pub fn content_range(response: &Response) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+// If-Range only accepts strong ETags
+pub fn validator(response: &Response) -> Option<String> {
+    let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    header(ETAG).filter(|etag| !etag.starts_with("W/")).or_else(|| header(LAST_MODIFIED))
+}
+
+// Every cached file (less the sidecars) with its size and when it was last played
+fn cached_files() -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = std::fs::read_dir(&*CACHE_DIR) else { return Vec::new() };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(CACHE_PREFIX) && !name.ends_with(".json")
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.len(), metadata.modified().ok()?))
+        })
+        .collect()
+}
+
+// Delete the least recently used cached tracks until the cache fits its limit
+fn prune_cache(keep: Option<&Path>) {
+    let limit = CACHE_LIMIT_BYTES.load(Ordering::Relaxed);
+    let mut files = cached_files();
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if size <= limit {
+        return;
+    }
+    files.sort_by_key(|(_, _, modified)| *modified);
+    let active = ACTIVE.lock().unwrap().clone();
+    for (path, len, _) in files {
+        if size <= limit {
+            break;
+        }
+        if Some(path.as_path()) == keep || active.contains(&path) {
+            continue;
+        }
+        eprintln!("[Download] 缓存超出上限，删除 {}", path.display());
+        if remove_entry(&path) {
+            size -= len;
+        }
+    }
+}
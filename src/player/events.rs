@@ -0,0 +1,171 @@
+use super::TrackMetadata;
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+// How often position updates are sent while samples are flowing
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Notifications from the playback threads to the UI.
+#[derive(Clone)]
+pub enum PlayerEvent {
+    /// The decoder ran out of samples. Stopping, seeking or replacing the
+    /// track never produces this.
+    TrackEnded { track_id: Option<String> },
+    /// A track lined up with `queue_next` took over from the one before it,
+    /// which sends no `TrackEnded`.
+    QueuedTrackStarted { track_id: Option<String> },
+    Progress { position: Duration },
+    /// The track never started; playback has stopped.
+    Error { track_id: Option<String>, error: PlaybackError },
+    MetadataLoaded { metadata: TrackMetadata, duration: Duration },
+}
+
+/// Why a track couldn't be played. The `Display` text is what the UI shows.
+#[derive(Clone, Debug)]
+pub enum PlaybackError {
+    NotFound(PathBuf),
+    // HTTP errors, or the network giving out after every retry
+    Download(String),
+    Decode(String),
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaybackError::NotFound(path) => write!(f, "file not found: {}", path.display()),
+            PlaybackError::Download(message) => write!(f, "download failed: {}", message),
+            PlaybackError::Decode(message) => write!(f, "can't decode audio: {}", message),
+        }
+    }
+}
+
+/// Where the playing track is, going by the samples its `EventTap` has passed
+/// on, so buffering stalls and pauses can't put it ahead the way a wall clock does.
+#[derive(Default)]
+pub struct PlaybackClock {
+    micros: AtomicU64,
+}
+
+impl PlaybackClock {
+    pub fn set(&self, position: Duration) {
+        self.micros.store(position.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_micros(self.micros.load(Ordering::Relaxed))
+    }
+}
+
+/// Reports position from the samples actually consumed and signals the natural
+/// end of a track. The sink drops its sources on stop, so this only sees `None`
+/// when the decoder is exhausted.
+pub struct EventTap<S> {
+    input: S,
+    events: UnboundedSender<PlayerEvent>,
+    clock: Arc<PlaybackClock>,
+    track_id: Option<String>,
+    start: Duration,
+    // Seconds of audio consumed since `start`
+    played: f64,
+    last_report: Duration,
+    ended: bool,
+    // Off while another track is queued to follow straight on
+    report_end: bool,
+    // Fading out under the next track: it's no longer what's playing
+    detached: bool,
+}
+
+impl<S: Source> EventTap<S> {
+    pub fn new(
+        input: S,
+        events: UnboundedSender<PlayerEvent>,
+        clock: Arc<PlaybackClock>,
+        track_id: Option<String>,
+        start: Duration,
+    ) -> Self {
+        EventTap {
+            input,
+            events,
+            clock,
+            track_id,
+            start,
+            played: 0.0,
+            last_report: start,
+            ended: false,
+            report_end: true,
+            detached: false,
+        }
+    }
+
+    pub fn set_report_end(&mut self, report: bool) {
+        self.report_end = report;
+    }
+
+    /// Stop sending events; the samples keep coming.
+    pub fn detach(&mut self) {
+        self.detached = true;
+    }
+}
+
+impl<S: Source> Iterator for EventTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        match self.input.next() {
+            Some(sample) => {
+                let per_second = self.input.sample_rate() as f64 * self.input.channels().max(1) as f64;
+                if per_second > 0.0 {
+                    self.played += 1.0 / per_second;
+                }
+                let position = self.start + Duration::from_secs_f64(self.played);
+                if !self.detached {
+                    self.clock.set(position);
+                    if position.saturating_sub(self.last_report) >= PROGRESS_INTERVAL {
+                        self.last_report = position;
+                        let _ = self.events.send(PlayerEvent::Progress { position });
+                    }
+                }
+                Some(sample)
+            }
+            None => {
+                if !self.ended && self.report_end && !self.detached {
+                    self.ended = true;
+                    let _ = self.events.send(PlayerEvent::TrackEnded { track_id: self.track_id.clone() });
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<S: Source> Source for EventTap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.start = pos;
+        self.played = 0.0;
+        self.last_report = pos;
+        Ok(())
+    }
+}
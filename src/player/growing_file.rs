@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+
+#[derive(Clone, Default)]
+pub struct ProgressState {
+    // Bytes of the file that are valid so far
+    pub written: u64,
+    pub total: Option<u64>,
+    // No more data is coming: the download finished, or gave up with `error`
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+/// How far a download into a file has got, shared between the downloading
+/// thread and whoever is reading the file.
+#[derive(Default)]
+pub struct DownloadProgress {
+    state: Mutex<ProgressState>,
+    changed: Condvar,
+}
+
+impl DownloadProgress {
+    pub fn update(&self, written: u64, total: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        state.written = written;
+        state.total = total.or(state.total);
+        self.changed.notify_all();
+    }
+
+    pub fn finish(&self, result: Result<(), String>) {
+        let mut state = self.state.lock().unwrap();
+        state.done = true;
+        state.error = result.err();
+        self.changed.notify_all();
+    }
+
+    pub fn snapshot(&self) -> ProgressState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Block until `bytes` are written or the download is over.
+    pub fn wait_for(&self, bytes: u64) -> ProgressState {
+        let mut state = self.state.lock().unwrap();
+        while state.written < bytes && !state.done {
+            state = self.changed.wait(state).unwrap();
+        }
+        state.clone()
+    }
+}
+
+/// Reads a file while a download is still writing it. Reads past what has
+/// arrived wait for more data instead of hitting end of file, so a decoder can
+/// start on the first megabyte of a file of any size.
+pub struct GrowingFile {
+    file: File,
+    pos: u64,
+    progress: Arc<DownloadProgress>,
+}
+
+impl GrowingFile {
+    pub fn open(path: &Path, progress: Arc<DownloadProgress>) -> io::Result<Self> {
+        Ok(GrowingFile { file: File::open(path)?, pos: 0, progress })
+    }
+}
+
+impl Read for GrowingFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = {
+            let mut state = self.progress.state.lock().unwrap();
+            while self.pos >= state.written && !state.done {
+                state = self.progress.changed.wait(state).unwrap();
+            }
+            if state.done {
+                buf.len()
+            } else {
+                buf.len().min((state.written - self.pos) as usize)
+            }
+        };
+
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        let read = self.file.read(&mut buf[..available])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for GrowingFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                let state = self.progress.snapshot();
+                let len = match state.total {
+                    Some(total) => total,
+                    None if state.done => self.file.metadata()?.len(),
+                    None => return Err(io::Error::new(io::ErrorKind::Unsupported, "length not known yet")),
+                };
+                len.checked_add_signed(delta)
+            }
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reads_wait_for_the_download() {
+        let path = std::env::temp_dir().join(format!("dioxus_music_growing_{}", uuid::Uuid::new_v4()));
+        let mut writer = File::create(&path).unwrap();
+        writer.write_all(b"0123").unwrap();
+        let progress = Arc::new(DownloadProgress::default());
+        progress.update(4, Some(10));
+
+        let mut reader = GrowingFile::open(&path, progress.clone()).unwrap();
+        let downloader = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            writer.write_all(b"456789").unwrap();
+            progress.update(10, None);
+            progress.finish(Ok(()));
+        });
+
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        // Blocks until the rest arrives rather than reporting end of file
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"456789");
+        downloader.join().unwrap();
+
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 7);
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"789");
+        std::fs::remove_file(&path).ok();
+    }
+}
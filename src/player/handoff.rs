@@ -0,0 +1,239 @@
+use super::events::EventTap;
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::f32::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// `fade_ms` when nothing is queued
+const NOTHING_QUEUED: u64 = u64::MAX;
+
+/// The track lined up to follow the current one. It's built on the audio
+/// thread when it starts, in whatever format the output is in at that point.
+pub struct QueuedTrack<S> {
+    pub build: Box<dyn FnOnce(ChannelCount, SampleRate) -> EventTap<S> + Send>,
+    // Zero starts it when the current track runs out
+    pub fade: Duration,
+    pub on_start: Box<dyn FnOnce() + Send>,
+}
+
+/// Shared between the player and whichever `Handoff` the sink is playing.
+pub struct NextTrack<S> {
+    queued: Mutex<Option<QueuedTrack<S>>>,
+    // The queued track's fade, readable every sample without locking
+    fade_ms: AtomicU64,
+}
+
+impl<S> NextTrack<S> {
+    pub fn new() -> Self {
+        NextTrack { queued: Mutex::new(None), fade_ms: AtomicU64::new(NOTHING_QUEUED) }
+    }
+
+    pub fn set(&self, track: QueuedTrack<S>) {
+        let mut queued = self.queued.lock().unwrap();
+        self.fade_ms.store(track.fade.as_millis() as u64, Ordering::SeqCst);
+        *queued = Some(track);
+    }
+
+    pub fn clear(&self) {
+        drop(self.take());
+    }
+
+    fn take(&self) -> Option<QueuedTrack<S>> {
+        let mut queued = self.queued.lock().unwrap();
+        self.fade_ms.store(NOTHING_QUEUED, Ordering::SeqCst);
+        queued.take()
+    }
+
+    fn fade(&self) -> Option<Duration> {
+        match self.fade_ms.load(Ordering::Relaxed) {
+            NOTHING_QUEUED => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+}
+
+struct FadeOut<S> {
+    track: EventTap<S>,
+    // Samples the fade lasts, and how many have gone
+    len: u64,
+    done: u64,
+}
+
+/// Plays the current track, then hands over to the queued one: right after its
+/// last sample, or overlapping its last `fade` with an equal-power crossfade.
+/// The next track is converted to the current output format, so the sink sees
+/// one continuous source and there's no gap to fill.
+pub struct Handoff<S> {
+    current: EventTap<S>,
+    outgoing: Option<FadeOut<S>>,
+    next: Arc<NextTrack<S>>,
+    // Whether `current` was last told a track is queued after it
+    queued_seen: bool,
+    // Where the current track started, and samples played since
+    start: Duration,
+    played: u64,
+}
+
+impl<S: Source> Handoff<S> {
+    pub fn new(current: EventTap<S>, start: Duration, next: Arc<NextTrack<S>>) -> Self {
+        Handoff { current, outgoing: None, next, queued_seen: false, start, played: 0 }
+    }
+
+    // Whether the crossfade into the queued track should begin, and how long it
+    // runs: never more than half the track, so a short one isn't skipped outright
+    fn fade_due(&self, fade: Duration) -> Option<Duration> {
+        let total = self.current.total_duration()?;
+        let per_second = self.current.sample_rate() as f64 * self.current.channels().max(1) as f64;
+        let position = self.start + Duration::from_secs_f64(self.played as f64 / per_second);
+        let fade = fade.min(total / 2);
+        (total.saturating_sub(position) <= fade).then_some(fade)
+    }
+
+    fn start_next(&mut self, fade: Duration) -> bool {
+        let Some(queued) = self.next.take() else { return false };
+        let (channels, sample_rate) = (self.current.channels(), self.current.sample_rate());
+        let mut previous = std::mem::replace(&mut self.current, (queued.build)(channels, sample_rate));
+        if !fade.is_zero() {
+            previous.detach();
+            let len = (fade.as_secs_f64() * sample_rate as f64 * channels as f64) as u64;
+            self.outgoing = Some(FadeOut { track: previous, len: len.max(1), done: 0 });
+        }
+        self.queued_seen = false;
+        self.start = Duration::ZERO;
+        self.played = 0;
+        (queued.on_start)();
+        true
+    }
+}
+
+impl<S: Source> Iterator for Handoff<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let fade = self.next.fade();
+        if fade.is_some() != self.queued_seen {
+            self.queued_seen = fade.is_some();
+            self.current.set_report_end(!self.queued_seen);
+        }
+        // Only on a frame boundary, so the two tracks' channels line up
+        let at_frame = self.played.is_multiple_of(self.current.channels().max(1) as u64);
+        if let Some(fade) = fade.filter(|fade| !fade.is_zero() && at_frame && self.outgoing.is_none()) {
+            if let Some(fade) = self.fade_due(fade) {
+                self.start_next(fade);
+            }
+        }
+
+        let sample = match self.current.next() {
+            Some(sample) => sample,
+            None => {
+                self.outgoing = None;
+                if !self.start_next(Duration::ZERO) {
+                    return None;
+                }
+                self.current.next()?
+            }
+        };
+        self.played += 1;
+
+        let Some(fading) = self.outgoing.as_mut() else { return Some(sample) };
+        let progress = fading.done as f32 / fading.len as f32 * FRAC_PI_2;
+        let old = fading.track.next();
+        fading.done += 1;
+        if old.is_none() || fading.done >= fading.len {
+            self.outgoing = None;
+        }
+        Some(sample * progress.sin() + old.unwrap_or(0.0) * progress.cos())
+    }
+}
+
+impl<S: Source> Source for Handoff<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.current.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.current.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.current.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.current.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.current.try_seek(pos)?;
+        self.outgoing = None;
+        self.start = pos;
+        self.played = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::events::PlaybackClock;
+    use crate::player::PlayerEvent;
+    use rodio::buffer::SamplesBuffer;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[test]
+    fn test_hands_over_gapless_and_crossfading() {
+        let (events, mut received) = unbounded_channel();
+        let clock = Arc::new(PlaybackClock::default());
+        let tap = |value: f32, id: &str| {
+            // One second, stereo at 100Hz
+            let source = SamplesBuffer::new(2, 100, vec![value; 200]);
+            EventTap::new(source, events.clone(), clock.clone(), Some(id.to_string()), Duration::ZERO)
+        };
+        let queue = |value: f32, id: &'static str, fade: Duration| {
+            let events = events.clone();
+            let track = tap(value, id);
+            QueuedTrack {
+                build: Box::new(move |channels, sample_rate| {
+                    assert_eq!((channels, sample_rate), (2, 100));
+                    track
+                }),
+                fade,
+                on_start: Box::new(move || {
+                    let _ = events.send(PlayerEvent::QueuedTrackStarted { track_id: Some(id.to_string()) });
+                }),
+            }
+        };
+
+        let next = Arc::new(NextTrack::new());
+        let mut handoff = Handoff::new(tap(1.0, "a"), Duration::ZERO, next.clone());
+        next.set(queue(0.5, "b", Duration::ZERO));
+        assert!(handoff.by_ref().take(200).all(|s| s == 1.0));
+        assert_eq!(clock.get(), Duration::from_secs(1));
+        // b follows straight on, and the clock with it
+        assert_eq!(handoff.next(), Some(0.5));
+        assert_eq!(clock.get(), Duration::from_millis(5));
+
+        // c crossfades over b's last half second
+        next.set(queue(0.0, "c", Duration::from_secs(5)));
+        let rest: Vec<f32> = handoff.by_ref().collect();
+        assert_eq!(rest.len(), 99 + 200);
+        assert!(rest[..100].iter().all(|s| *s == 0.5));
+        assert!(rest[100..199].windows(2).all(|w| w[1] < w[0]));
+        assert!(rest[199..].iter().all(|s| *s == 0.0));
+        // b fading out underneath doesn't move it
+        assert_eq!(clock.get(), Duration::from_secs(1));
+
+        let mut seen = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            match event {
+                PlayerEvent::TrackEnded { track_id } => seen.push(format!("ended {}", track_id.unwrap())),
+                PlayerEvent::QueuedTrackStarted { track_id } => seen.push(format!("started {}", track_id.unwrap())),
+                _ => {}
+            }
+        }
+        // a went straight into b and b was faded out, so only c reports an end
+        assert_eq!(seen, ["started b", "started c", "ended c"]);
+    }
+}
@@ -0,0 +1,114 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Samples between publishes, so the audio thread touches the atomics rarely
+const PUBLISH_INTERVAL: usize = 512;
+
+/// Left/right peak levels since the last read. Levels are non-negative, so their
+/// f32 bit patterns order the same as their values and `fetch_max` works directly.
+pub struct LevelMeter {
+    left: AtomicU32,
+    right: AtomicU32,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        LevelMeter {
+            left: AtomicU32::new(0),
+            right: AtomicU32::new(0),
+        }
+    }
+
+    fn publish(&self, left: f32, right: f32) {
+        self.left.fetch_max(left.to_bits(), Ordering::Relaxed);
+        self.right.fetch_max(right.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Peak (left, right) in 0.0..=1.0 since the previous call.
+    pub fn take_peaks(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.left.swap(0, Ordering::Relaxed)).min(1.0),
+            f32::from_bits(self.right.swap(0, Ordering::Relaxed)).min(1.0),
+        )
+    }
+}
+
+/// Passes samples through while tracking per-channel peaks into a `LevelMeter`.
+/// Mono sources report the same level on both sides.
+pub struct LevelTap<S> {
+    input: S,
+    meter: Arc<LevelMeter>,
+    left: f32,
+    right: f32,
+    channel: usize,
+    counted: usize,
+}
+
+impl<S: Source> LevelTap<S> {
+    pub fn new(input: S, meter: Arc<LevelMeter>) -> Self {
+        LevelTap {
+            input,
+            meter,
+            left: 0.0,
+            right: 0.0,
+            channel: 0,
+            counted: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for LevelTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1) as usize;
+        let level = if sample.is_finite() { sample.abs() } else { 0.0 };
+
+        match (channels, self.channel) {
+            (1, _) => {
+                self.left = self.left.max(level);
+                self.right = self.right.max(level);
+            }
+            (_, 0) => self.left = self.left.max(level),
+            (_, 1) => self.right = self.right.max(level),
+            _ => {}
+        }
+        self.channel = (self.channel + 1) % channels;
+
+        self.counted += 1;
+        if self.counted >= PUBLISH_INTERVAL {
+            self.meter.publish(self.left, self.right);
+            self.left = 0.0;
+            self.right = 0.0;
+            self.counted = 0;
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for LevelTap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.channel = 0;
+        self.input.try_seek(pos)
+    }
+}
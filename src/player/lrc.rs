@@ -0,0 +1,189 @@
+// LRC lyric parsing. Kept free of other crate modules so the fuzz targets can
+// include this file directly.
+
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    pub time: Duration,
+    pub text: String,
+}
+
+/// Parse LRC text into lines sorted by time. Never panics; anything that isn't a
+/// timed lyric line is skipped.
+///
+/// Accepts a leading BOM, `\n` / `\r\n` / `\r` line endings, several timestamps on
+/// one line (`[00:12.00][01:40.00]chorus`), `[mm:ss]`, `[mm:ss.x]` to `[mm:ss.xxx]`,
+/// `[mm:ss:xx]` and `[mm:ss,xx]`, the `[offset:±ms]` tag, and strips enhanced-LRC
+/// word timings (`<00:12.34>`) from the text.
+pub fn parse_lrc(content: &str) -> Vec<LyricLine> {
+    let content = content.trim_start_matches('\u{feff}');
+    let mut lines = Vec::new();
+    // Positive offsets make lyrics appear earlier
+    let mut offset_ms: i64 = 0;
+
+    for raw in content.split(['\n', '\r']) {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(end) = tag.find(']') else { break };
+            let inner = &tag[..end];
+            rest = tag[end + 1..].trim_start();
+
+            if let Some(time) = parse_timestamp(inner) {
+                times.push(time);
+            } else if let Some(value) = metadata_value(inner, "offset") {
+                offset_ms = value.trim().trim_start_matches('+').parse().unwrap_or(offset_ms);
+            }
+        }
+
+        if times.is_empty() {
+            continue;
+        }
+        let text = strip_word_timings(rest);
+        for time in times {
+            lines.push(LyricLine { time, text: text.clone() });
+        }
+    }
+
+    if offset_ms != 0 {
+        for line in lines.iter_mut() {
+            let ms = line.time.as_millis() as i64 - offset_ms;
+            line.time = Duration::from_millis(ms.max(0) as u64);
+        }
+    }
+
+    lines.sort_by_key(|l| l.time);
+    lines
+}
+
+/// `mm:ss`, `mm:ss.fff`, `mm:ss:xx` or `mm:ss,xx` to a duration.
+pub fn parse_timestamp(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let (minutes, rest) = text.split_once(':')?;
+    let (seconds, fraction) = match rest.find(['.', ':', ',']) {
+        Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+        None => (rest, None),
+    };
+
+    let minutes = parse_digits(minutes)?;
+    let seconds = parse_digits(seconds)?;
+    let millis = match fraction {
+        // "1" is tenths, "12" hundredths, "123" milliseconds; extra digits are dropped
+        Some(fraction) if !fraction.is_empty() => {
+            if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let digits = &fraction[..fraction.len().min(3)];
+            parse_digits(digits)? * 10u64.pow(3 - digits.len() as u32)
+        }
+        _ => 0,
+    };
+
+    let total = minutes.checked_mul(60_000)?.checked_add(seconds.checked_mul(1000)?)?.checked_add(millis)?;
+    Some(Duration::from_millis(total))
+}
+
+// ASCII digits only: rejects signs, spaces and empty strings that `parse` would
+// otherwise treat differently
+fn parse_digits(text: &str) -> Option<u64> {
+    if text.is_empty() || text.len() > 9 || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+fn metadata_value<'a>(tag: &'a str, key: &str) -> Option<&'a str> {
+    let (name, value) = tag.split_once(':')?;
+    name.trim().eq_ignore_ascii_case(key).then_some(value)
+}
+
+fn strip_word_timings(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('>') {
+            Some(end) if parse_timestamp(&after[..end]).is_some() => rest = &after[end + 1..],
+            _ => {
+                result.push('<');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("01:02"), Some(ms(62_000)));
+        assert_eq!(parse_timestamp("01:02.5"), Some(ms(62_500)));
+        assert_eq!(parse_timestamp("01:02.34"), Some(ms(62_340)));
+        assert_eq!(parse_timestamp("01:02.345"), Some(ms(62_345)));
+        assert_eq!(parse_timestamp("01:02.3456"), Some(ms(62_345)));
+        assert_eq!(parse_timestamp("01:02:34"), Some(ms(62_340)));
+        assert_eq!(parse_timestamp("01:02,34"), Some(ms(62_340)));
+        assert_eq!(parse_timestamp(" 1:02.34 "), Some(ms(62_340)));
+        assert_eq!(parse_timestamp("ar:Someone"), None);
+        assert_eq!(parse_timestamp("-1:02"), None);
+        assert_eq!(parse_timestamp("01:02."), Some(ms(62_000)));
+        assert_eq!(parse_timestamp("01:"), None);
+        assert_eq!(parse_timestamp("01:02.ab"), None);
+        assert_eq!(parse_timestamp("01:02.３"), None);
+        assert_eq!(parse_timestamp("01:02.1３"), None);
+    }
+
+    #[test]
+    fn test_parse_messy_lrc() {
+        let content = "\u{feff}[ti:Song]\r\n[ar:Artist]\r\n[offset:+500]\r\n\
+                       [00:12.00][01:40.00]Chorus\r\
+                       [00:05:50] <00:05.50>Hello <00:06.00>world\n\
+                       [00:20.00]\n\
+                       not a lyric\n\
+                       [xx:yy]broken\n\
+                       [00:30.00] a <b> c";
+        let lines = parse_lrc(content);
+
+        let expected = [
+            (5_000, "Hello world"),
+            (11_500, "Chorus"),
+            (19_500, ""),
+            (29_500, "a <b> c"),
+            (99_500, "Chorus"),
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for (line, (time, text)) in lines.iter().zip(expected) {
+            assert_eq!(line.time, ms(time));
+            assert_eq!(line.text, text);
+        }
+    }
+
+    #[test]
+    fn test_parse_lrc_survives_garbage() {
+        let alphabet: Vec<char> = "[]<>:.,-+0123456789 \r\n\u{feff}é中ab".chars().collect();
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        for _ in 0..2000 {
+            let len = next() % 64;
+            let input: String = (0..len).map(|_| alphabet[next() % alphabet.len()]).collect();
+            let lines = parse_lrc(&input);
+            assert!(lines.windows(2).all(|w| w[0].time <= w[1].time), "unsorted for {:?}", input);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::lrc::{parse_lrc, LyricLine};
 use base64::{Engine, prelude::BASE64_STANDARD};
 use reqwest::Client;
 use std::fs;
@@ -30,12 +31,6 @@ fn decode_html_entities(text: &str) -> String {
     result
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct LyricLine {
-    pub time: Duration,
-    pub text: String,
-}
-
 #[derive(Clone, Debug, PartialEq)]
 pub struct Lyric {
     pub title: String,
@@ -70,6 +65,11 @@ impl Lyric {
         }
         Some(self.lines.len().saturating_sub(1))
     }
+
+    /// The line being sung at `time`; None before the first one starts.
+    pub fn line_at(&self, time: Duration) -> Option<&LyricLine> {
+        self.lines.iter().rev().find(|line| line.time <= time)
+    }
 }
 
 #[allow(dead_code)]
@@ -246,58 +246,6 @@ pub async fn download_lyrics(
     })
 }
 
-fn parse_lrc(content: &str) -> Vec<LyricLine> {
-    let mut lines = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        if let Some((time_str, text)) = line.split_once(']') {
-            if let Some(time_str) = time_str.strip_prefix('[') {
-                if let Some(duration) = parse_time(time_str) {
-                    lines.push(LyricLine {
-                        time: duration,
-                        text: text.trim().to_string(),
-                    });
-                }
-            }
-        }
-    }
-
-    lines.sort_by_key(|l| l.time);
-    lines
-}
-
-fn parse_time(time_str: &str) -> Option<Duration> {
-    let parts: Vec<&str> = time_str.split(':').collect();
-    if parts.len() != 2 {
-        return None;
-    }
-
-    let minutes: u64 = match parts[0].parse() {
-        Ok(m) => m,
-        Err(_) => return None,
-    };
-
-    let seconds_parts: Vec<&str> = parts[1].split('.').collect();
-    let seconds: u64 = match seconds_parts[0].parse() {
-        Ok(s) => s,
-        Err(_) => return None,
-    };
-
-    let millis: u64 = if seconds_parts.len() > 1 {
-        let ms_str = &seconds_parts[1][0..std::cmp::min(seconds_parts[1].len(), 2)];
-        ms_str.parse().unwrap_or(0) * 10
-    } else {
-        0
-    };
-
-    Some(Duration::from_secs(minutes * 60 + seconds) + Duration::from_millis(millis))
-}
-
 pub async fn search_kugou_lyrics(
     title: &str,
     artist: &str,
@@ -0,0 +1,171 @@
+use super::download::{self, DownloadError};
+use super::{play_local_file_async, title_from_url, TrackMetadata};
+use crate::webdav::Credentials;
+use rodio::Source;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A track decoded ahead of time, ready to go straight into the sink.
+pub struct PreparedTrack {
+    pub source: Box<dyn Source<Item = f32> + Send>,
+    pub metadata: TrackMetadata,
+    pub duration: Duration,
+    // What's actually read: the file itself, or a remote track's cached download
+    pub file: PathBuf,
+}
+
+enum PrefetchState {
+    Loading,
+    Ready(Box<PreparedTrack>),
+    Failed,
+}
+
+struct Prefetch {
+    // The path or URL `play` will be called with
+    key: String,
+    cancelled: Arc<AtomicBool>,
+    state: Arc<Mutex<PrefetchState>>,
+}
+
+impl Prefetch {
+    // A cancelled download keeps its partial data in the cache for later
+    fn cancel(self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        *self.state.lock().unwrap() = PrefetchState::Failed;
+    }
+}
+
+/// Prepares the track the UI expects to play next on a background thread:
+/// remote tracks are downloaded into the cache, and both kinds are opened and
+/// probed so `play` only has to append the source. Only one track is held;
+/// asking for a different one drops the previous one.
+pub struct Prefetcher {
+    current: Mutex<Option<Prefetch>>,
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        Prefetcher { current: Mutex::new(None) }
+    }
+
+    /// Start preparing `key` unless it's already being prepared.
+    pub fn start(&self, key: String, is_remote: bool, auth: Option<Credentials>, read_ahead_bytes: usize) {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|prefetch| prefetch.key == key) {
+            return;
+        }
+        if let Some(previous) = current.take() {
+            previous.cancel();
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(Mutex::new(PrefetchState::Loading));
+        *current = Some(Prefetch { key: key.clone(), cancelled: cancelled.clone(), state: state.clone() });
+
+        std::thread::spawn(move || {
+            let result = if is_remote || crate::archive::split_path(&key).is_some() {
+                prepare_remote(&key, auth, &cancelled)
+            } else {
+                prepare_local(Path::new(&key), read_ahead_bytes)
+            };
+
+            let mut state = state.lock().unwrap();
+            match result {
+                Ok(_) if cancelled.load(Ordering::SeqCst) => {}
+                Ok(prepared) => {
+                    eprintln!("[Prefetch] 下一首已就绪: {}", key);
+                    *state = PrefetchState::Ready(Box::new(prepared));
+                }
+                Err(e) => {
+                    if !cancelled.load(Ordering::SeqCst) {
+                        eprintln!("[Prefetch] 预加载失败 {}: {}", key, e);
+                    }
+                    *state = PrefetchState::Failed;
+                }
+            }
+        });
+    }
+
+    /// The prepared track for `key` if it finished in time. Whatever else was
+    /// prefetched, or `key` itself if it's still loading, is dropped so the
+    /// caller falls back to a normal start.
+    pub fn take(&self, key: &str) -> Option<PreparedTrack> {
+        let prefetch = self.current.lock().unwrap().take()?;
+        if prefetch.key != key {
+            prefetch.cancel();
+            return None;
+        }
+
+        let ready = match std::mem::replace(&mut *prefetch.state.lock().unwrap(), PrefetchState::Failed) {
+            PrefetchState::Ready(prepared) => Some(*prepared),
+            PrefetchState::Loading | PrefetchState::Failed => None,
+        };
+        prefetch.cancel();
+        ready
+    }
+
+    /// The prepared track for `key` if it's ready. Unlike `take`, a prefetch
+    /// that's still loading is left to finish.
+    pub fn take_ready(&self, key: &str) -> Option<PreparedTrack> {
+        let mut current = self.current.lock().unwrap();
+        let prefetch = current.as_ref().filter(|prefetch| prefetch.key == key)?;
+        let mut state = prefetch.state.lock().unwrap();
+        let prepared = match std::mem::replace(&mut *state, PrefetchState::Failed) {
+            PrefetchState::Ready(prepared) => *prepared,
+            loading => {
+                *state = loading;
+                return None;
+            }
+        };
+        drop(state);
+        current.take();
+        Some(prepared)
+    }
+
+    #[cfg(test)]
+    pub fn is_ready(&self, key: &str) -> bool {
+        self.current.lock().unwrap().as_ref().is_some_and(|prefetch| {
+            prefetch.key == key && matches!(*prefetch.state.lock().unwrap(), PrefetchState::Ready(_))
+        })
+    }
+
+    pub fn clear(&self) {
+        if let Some(prefetch) = self.current.lock().unwrap().take() {
+            prefetch.cancel();
+        }
+    }
+}
+
+fn prepare_local(path: &Path, read_ahead_bytes: usize) -> Result<PreparedTrack, String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+    let source = play_local_file_async(path, &extension, read_ahead_bytes)?;
+    Ok(PreparedTrack {
+        duration: source.total_duration().unwrap_or_default(),
+        source,
+        metadata: TrackMetadata::from_path(path),
+        file: path.to_path_buf(),
+    })
+}
+
+// Also covers tracks inside zip archives, which are unpacked into the same cache
+fn prepare_remote(url: &str, auth: Option<Credentials>, cancelled: &AtomicBool) -> Result<PreparedTrack, String> {
+    let is_cancelled = || cancelled.load(Ordering::SeqCst);
+    let fetched = if crate::archive::split_path(url).is_some() {
+        download::unpack(url, &is_cancelled, &mut |_, _, _| {})
+    } else {
+        download::fetch(url, auth, &is_cancelled, &mut |_, _, _| {})
+    };
+    let path = match fetched {
+        Ok(path) => path,
+        Err(DownloadError::Cancelled) => return Err("下载已取消".to_string()),
+        Err(DownloadError::Failed(message)) => return Err(message),
+    };
+    // The cached file is complete and local, so reading ahead would only add a thread
+    let mut prepared = prepare_local(&path, 0)?;
+    if prepared.metadata.title.as_ref().is_none_or(|t| t.starts_with("dioxus_music_")) {
+        prepared.metadata.title = Some(title_from_url(url));
+    }
+    Ok(prepared)
+}
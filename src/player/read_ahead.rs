@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct Shared {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    // Stream offset of the first byte in `buffer`
+    pos: u64,
+    eof: bool,
+    error: Option<io::Error>,
+    seek_to: Option<u64>,
+    // Bumped on every buffer reset so the worker drops chunks read before a seek
+    generation: u64,
+    closed: bool,
+}
+
+/// Reads ahead of the decoder on a background thread into a bounded ring buffer,
+/// so slow reads from network mounts don't stall audio output.
+pub struct ReadAheadReader {
+    state: Arc<(Mutex<Shared>, Condvar)>,
+    len: u64,
+}
+
+impl ReadAheadReader {
+    pub fn new<R: Read + Seek + Send + 'static>(mut inner: R, capacity: usize) -> io::Result<Self> {
+        let len = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        let state = Arc::new((
+            Mutex::new(Shared {
+                buffer: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                pos: 0,
+                eof: false,
+                error: None,
+                seek_to: None,
+                generation: 0,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let worker_state = state.clone();
+        std::thread::spawn(move || fill_buffer(inner, worker_state));
+
+        Ok(ReadAheadReader { state, len })
+    }
+}
+
+fn fill_buffer<R: Read + Seek>(mut inner: R, state: Arc<(Mutex<Shared>, Condvar)>) {
+    let (lock, cvar) = &*state;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let (generation, seek_to, want) = {
+            let mut shared = lock.lock().unwrap();
+            while !shared.closed
+                && shared.seek_to.is_none()
+                && (shared.eof || shared.error.is_some() || shared.buffer.len() >= shared.capacity)
+            {
+                shared = cvar.wait(shared).unwrap();
+            }
+            if shared.closed {
+                return;
+            }
+            let want = (shared.capacity - shared.buffer.len()).min(CHUNK_SIZE);
+            (shared.generation, shared.seek_to.take(), want)
+        };
+
+        if let Some(offset) = seek_to {
+            if let Err(e) = inner.seek(SeekFrom::Start(offset)) {
+                let mut shared = lock.lock().unwrap();
+                if shared.generation == generation {
+                    shared.error = Some(e);
+                    cvar.notify_all();
+                }
+                continue;
+            }
+        }
+
+        let result = inner.read(&mut chunk[..want.max(1)]);
+
+        let mut shared = lock.lock().unwrap();
+        if shared.generation != generation {
+            continue;
+        }
+        match result {
+            Ok(0) => shared.eof = true,
+            Ok(n) => shared.buffer.extend(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => shared.error = Some(e),
+        }
+        cvar.notify_all();
+    }
+}
+
+impl Read for ReadAheadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (lock, cvar) = &*self.state;
+        let mut shared = lock.lock().unwrap();
+        loop {
+            if !shared.buffer.is_empty() {
+                let n = buf.len().min(shared.buffer.len());
+                for (dst, src) in buf.iter_mut().zip(shared.buffer.drain(..n)) {
+                    *dst = src;
+                }
+                shared.pos += n as u64;
+                cvar.notify_all();
+                return Ok(n);
+            }
+            if let Some(e) = shared.error.take() {
+                return Err(e);
+            }
+            if shared.eof {
+                return Ok(0);
+            }
+            shared = cvar.wait(shared).unwrap();
+        }
+    }
+}
+
+impl Seek for ReadAheadReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (lock, cvar) = &*self.state;
+        let mut shared = lock.lock().unwrap();
+
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => shared.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start of stream"))?;
+
+        // Short forward skips are served from what is already buffered
+        if target >= shared.pos && target - shared.pos <= shared.buffer.len() as u64 {
+            let skip = (target - shared.pos) as usize;
+            shared.buffer.drain(..skip);
+            shared.pos = target;
+            cvar.notify_all();
+            return Ok(target);
+        }
+
+        shared.buffer.clear();
+        shared.pos = target;
+        shared.eof = false;
+        shared.error = None;
+        shared.seek_to = Some(target);
+        shared.generation += 1;
+        cvar.notify_all();
+        Ok(target)
+    }
+}
+
+impl Drop for ReadAheadReader {
+    fn drop(&mut self) {
+        // The worker may be blocked on a slow read, so it is not joined here
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().closed = true;
+        cvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_ahead_read_and_seek() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = ReadAheadReader::new(Cursor::new(data.clone()), 4096).unwrap();
+
+        let mut head = vec![0u8; 1000];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(head, data[..1000]);
+
+        assert_eq!(reader.seek(SeekFrom::Start(150_000)).unwrap(), 150_000);
+        let mut middle = vec![0u8; 5000];
+        reader.read_exact(&mut middle).unwrap();
+        assert_eq!(middle, data[150_000..155_000]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), data.len() as u64 - 10);
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 10..]);
+    }
+}
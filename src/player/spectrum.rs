@@ -0,0 +1,248 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const FFT_SIZE: usize = 2048;
+pub const SPECTRUM_BANDS: usize = 32;
+// Frames collected on the audio thread before handing them over in one lock
+const TAP_BATCH: usize = 256;
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 20_000.0;
+const FLOOR_DB: f32 = -80.0;
+
+/// The most recent mono samples heard, shared between the audio thread and the UI.
+pub struct SpectrumBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    sample_rate: AtomicU32,
+}
+
+impl SpectrumBuffer {
+    pub fn new() -> Self {
+        SpectrumBuffer {
+            samples: Mutex::new(VecDeque::with_capacity(FFT_SIZE)),
+            sample_rate: AtomicU32::new(44_100),
+        }
+    }
+
+    // Never blocks the audio thread; a batch is dropped if the UI holds the lock
+    fn push(&self, batch: &[f32], sample_rate: SampleRate) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        if let Ok(mut samples) = self.samples.try_lock() {
+            samples.extend(batch);
+            let excess = samples.len().saturating_sub(FFT_SIZE);
+            samples.drain(..excess);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+
+    /// Band magnitudes in 0.0..=1.0, log-spaced from 20Hz to 20kHz.
+    pub fn spectrum(&self) -> Vec<f32> {
+        let samples: Vec<f32> = self.samples.lock().unwrap().iter().copied().collect();
+        compute_spectrum(&samples, self.sample_rate.load(Ordering::Relaxed), SPECTRUM_BANDS)
+    }
+
+    /// The newest samples squeezed into `points` values in -1.0..=1.0, each the
+    /// largest swing in its stretch so short transients still show.
+    pub fn waveform(&self, points: usize) -> Vec<f32> {
+        let samples: Vec<f32> = self.samples.lock().unwrap().iter().copied().collect();
+        compute_waveform(&samples, points)
+    }
+}
+
+/// Passes samples through untouched while copying a mono mix into a `SpectrumBuffer`.
+pub struct SpectrumTap<S> {
+    input: S,
+    buffer: Arc<SpectrumBuffer>,
+    pending: Vec<f32>,
+    frame_sum: f32,
+    frame_pos: usize,
+}
+
+impl<S: Source> SpectrumTap<S> {
+    pub fn new(input: S, buffer: Arc<SpectrumBuffer>) -> Self {
+        SpectrumTap {
+            input,
+            buffer,
+            pending: Vec::with_capacity(TAP_BATCH),
+            frame_sum: 0.0,
+            frame_pos: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for SpectrumTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1) as usize;
+
+        self.frame_sum += sample;
+        self.frame_pos += 1;
+        if self.frame_pos >= channels {
+            self.pending.push(self.frame_sum / channels as f32);
+            self.frame_sum = 0.0;
+            self.frame_pos = 0;
+            if self.pending.len() >= TAP_BATCH {
+                self.buffer.push(&self.pending, self.input.sample_rate());
+                self.pending.clear();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for SpectrumTap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.pending.clear();
+        self.frame_sum = 0.0;
+        self.frame_pos = 0;
+        self.input.try_seek(pos)
+    }
+}
+
+pub fn compute_spectrum(samples: &[f32], sample_rate: u32, bands: usize) -> Vec<f32> {
+    if samples.len() < FFT_SIZE || sample_rate == 0 {
+        return vec![0.0; bands];
+    }
+
+    // Hann window over the newest FFT_SIZE samples
+    let start = samples.len() - FFT_SIZE;
+    let mut re: Vec<f32> = samples[start..]
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+
+    let bins = FFT_SIZE / 2;
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let max_freq = MAX_FREQ.min(sample_rate as f32 / 2.0);
+    let ratio = max_freq / MIN_FREQ;
+
+    (0..bands)
+        .map(|band| {
+            let lo = MIN_FREQ * ratio.powf(band as f32 / bands as f32);
+            let hi = MIN_FREQ * ratio.powf((band + 1) as f32 / bands as f32);
+            let lo_bin = ((lo / bin_width) as usize).clamp(1, bins - 1);
+            let hi_bin = ((hi / bin_width).ceil() as usize).clamp(lo_bin + 1, bins);
+
+            let peak = (lo_bin..hi_bin)
+                .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt())
+                .fold(0.0f32, f32::max);
+            // A full-scale sine under a Hann window peaks at FFT_SIZE / 4
+            let db = 20.0 * (peak / (FFT_SIZE as f32 / 4.0)).max(1e-9).log10();
+            ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+pub fn compute_waveform(samples: &[f32], points: usize) -> Vec<f32> {
+    if points == 0 || samples.len() < points {
+        return vec![0.0; points];
+    }
+    let per_point = samples.len() / points;
+    samples[samples.len() - per_point * points..]
+        .chunks(per_point)
+        .map(|chunk| chunk.iter().copied().fold(0.0f32, |peak, s| if s.abs() > peak.abs() { s } else { peak }).clamp(-1.0, 1.0))
+        .collect()
+}
+
+// In-place iterative radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spectrum_peaks_at_sine_frequency() {
+        let sample_rate = 44_100;
+        let samples: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (2.0 * PI * 1000.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+
+        let spectrum = compute_spectrum(&samples, sample_rate, SPECTRUM_BANDS);
+        let loudest = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+
+        // 1kHz falls in band floor(32 * log(1000/20) / log(1000))
+        assert_eq!(loudest, 18);
+        assert!(spectrum[loudest] > 0.9);
+        assert!(compute_spectrum(&[], sample_rate, SPECTRUM_BANDS).iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn test_waveform_keeps_peaks() {
+        let mut samples = vec![0.1; 1000];
+        samples[0] = -0.8;
+        samples[999] = 1.5;
+        // The oldest sample doesn't fill a whole stretch and is left out
+        assert_eq!(compute_waveform(&samples, 3), vec![0.1, 0.1, 1.0]);
+        assert_eq!(compute_waveform(&samples[..999], 3), vec![-0.8, 0.1, 0.1]);
+        assert_eq!(compute_waveform(&samples[..2], 3), vec![0.0; 3]);
+    }
+}
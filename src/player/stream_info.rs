@@ -0,0 +1,103 @@
+use symphonia::core::codecs::{self, CodecParameters, CodecType};
+use std::time::Duration;
+
+/// Technical details of a track's audio stream, from the container and codec
+/// headers the decoder starts from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamInfo {
+    pub codec: Option<String>,
+    // Averaged over the whole file, so it's only approximate for VBR
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    // Only lossless formats have one
+    pub bit_depth: Option<u32>,
+}
+
+impl StreamInfo {
+    /// What symphonia's codec parameters say about a stream, with the average
+    /// bitrate over a file of `file_size` bytes.
+    pub fn from_codec_params(params: &CodecParameters, file_size: u64, duration: Duration) -> Self {
+        let mut info = StreamInfo {
+            codec: codec_name(params.codec),
+            bitrate_kbps: None,
+            sample_rate: params.sample_rate,
+            channels: params.channels.map(|channels| channels.count() as u16),
+            bit_depth: params.bits_per_sample.filter(|_| is_lossless(params.codec)),
+        };
+        info.set_file_size(file_size, duration);
+        info
+    }
+
+    /// Work the bitrate out again once the file's full size is known, for a
+    /// remote track probed before its download finished.
+    pub fn set_file_size(&mut self, bytes: u64, duration: Duration) {
+        self.bitrate_kbps = (bytes > 0 && !duration.is_zero())
+            .then(|| (bytes as f64 * 8.0 / duration.as_secs_f64() / 1000.0).round() as u32);
+    }
+
+    // "FLAC · 905 kbps · 44.1 kHz · 16-bit · Stereo", leaving out what's unknown
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        parts.extend(self.codec.clone());
+        parts.extend(self.bitrate_kbps.map(|kbps| format!("{} kbps", kbps)));
+        parts.extend(self.sample_rate.map(|rate| format!("{} kHz", rate as f64 / 1000.0)));
+        parts.extend(self.bit_depth.map(|bits| format!("{}-bit", bits)));
+        parts.extend(self.channels.map(|channels| match channels {
+            1 => "Mono".to_string(),
+            2 => "Stereo".to_string(),
+            n => format!("{} ch", n),
+        }));
+        parts.join(" · ")
+    }
+}
+
+fn codec_name(codec: CodecType) -> Option<String> {
+    let name = match codec {
+        codecs::CODEC_TYPE_MP1 => "MP1",
+        codecs::CODEC_TYPE_MP2 => "MP2",
+        codecs::CODEC_TYPE_MP3 => "MP3",
+        codecs::CODEC_TYPE_AAC => "AAC",
+        codecs::CODEC_TYPE_VORBIS => "Vorbis",
+        codecs::CODEC_TYPE_OPUS => "Opus",
+        codecs::CODEC_TYPE_WMA => "WMA",
+        codecs::CODEC_TYPE_FLAC => "FLAC",
+        codecs::CODEC_TYPE_ALAC => "ALAC",
+        codecs::CODEC_TYPE_WAVPACK => "WavPack",
+        codecs::CODEC_TYPE_MONKEYS_AUDIO => "APE",
+        codec if is_pcm(codec) => "PCM",
+        codecs::CODEC_TYPE_NULL => return None,
+        codec => return symphonia::default::get_codecs().get_codec(codec).map(|d| d.short_name.to_uppercase()),
+    };
+    Some(name.to_string())
+}
+
+// The many PCM sample formats (pcm_s16le, pcm_f32be, ...) all show as "PCM"
+fn is_pcm(codec: CodecType) -> bool {
+    symphonia::default::get_codecs().get_codec(codec).is_some_and(|d| d.short_name.starts_with("pcm"))
+}
+
+fn is_lossless(codec: CodecType) -> bool {
+    is_pcm(codec)
+        || matches!(
+            codec,
+            codecs::CODEC_TYPE_FLAC | codecs::CODEC_TYPE_ALAC | codecs::CODEC_TYPE_WAVPACK | codecs::CODEC_TYPE_MONKEYS_AUDIO
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::wav_bytes;
+
+    #[test]
+    fn test_probes_wav_stream() {
+        let path = std::env::temp_dir().join(format!("dioxus_music_stream_{}.wav", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wav_bytes(1000, 8000)).unwrap();
+        let probed = crate::metadata::probe(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(probed.tags.duration, Some(Duration::from_secs(1)));
+        assert_eq!(probed.stream.summary(), "PCM · 128 kbps · 8 kHz · 16-bit · Mono");
+    }
+}
@@ -0,0 +1,286 @@
+use super::download::{content_range, limited, validator, CacheWriter};
+use crate::webdav::Credentials;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+// Remote files read in place over HTTP Range requests, so a track starts from its
+// first bytes and a seek costs one request instead of waiting for a download to
+// get there. What's read from the start on can be kept in the download cache.
+
+// Forward seeks this short read through the open response rather than starting another
+const SKIP_LIMIT: u64 = 256 * 1024;
+// Responses are cut off after this (a paused track's included); the next read
+// picks up where it ended with a new request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A remote file read with ranged GETs. Reads carry on through one open
+/// response; a seek elsewhere starts a new one at the new offset, and a
+/// connection that drops is requested again from where it broke off.
+pub struct WebDAVStreamSource {
+    client: Client,
+    url: String,
+    auth: Option<Credentials>,
+    len: u64,
+    pos: u64,
+    // ETag or Last-Modified when the file was opened
+    validator: Option<String>,
+    cache: Option<CacheWriter>,
+    // The response being read (held to the server's speed limit), and how far
+    // into the file it has got
+    response: Option<Box<dyn Read + Send>>,
+    response_pos: u64,
+    on_read: Option<Box<dyn FnMut(u64, u64) + Send>>,
+}
+
+impl WebDAVStreamSource {
+    /// Start reading `url` from the beginning. Fails with `Unsupported` if the
+    /// server answers with the whole file instead of a range, which leaves
+    /// downloading it.
+    pub fn open(url: &str, auth: Option<Credentials>) -> io::Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(15))
+            .timeout(RESPONSE_TIMEOUT)
+            .build()
+            .map_err(io::Error::other)?;
+        let mut source = WebDAVStreamSource {
+            client,
+            url: url.to_string(),
+            auth,
+            len: 0,
+            pos: 0,
+            validator: None,
+            cache: None,
+            response: None,
+            response_pos: 0,
+            on_read: None,
+        };
+        let (response, len) = source.request(0)?;
+        source.validator = validator(&response);
+        source.response = Some(limited(url, response));
+        source.len = len;
+        Ok(source)
+    }
+
+    /// Called after every read with how far into the file reading has got, and its length.
+    pub fn on_read(mut self, on_read: impl FnMut(u64, u64) + Send + 'static) -> Self {
+        self.on_read = Some(Box::new(on_read));
+        self
+    }
+
+    /// Keep what's read in the download cache as well, so a track played
+    /// through needn't be fetched again.
+    pub fn cached(mut self) -> Self {
+        self.cache = CacheWriter::start(&self.url, self.validator.clone(), self.len);
+        self
+    }
+
+    pub fn byte_len(&self) -> u64 {
+        self.len
+    }
+
+    /// The extension at the end of the URL's path, lowercased, as a decoder hint.
+    pub fn extension(&self) -> String {
+        Path::new(self.url.split(['?', '#']).next().unwrap_or(&self.url))
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase()
+    }
+
+    // GET from `offset` to the end; the response and the file's length
+    fn request(&self, offset: u64) -> io::Result<(Response, u64)> {
+        let send = || {
+            let mut request = self.client.get(&self.url).header(RANGE, format!("bytes={}-", offset));
+            if let Some(credentials) = &self.auth {
+                request = credentials.authorize_blocking(request);
+            }
+            request.send().map_err(io::Error::other)
+        };
+        let mut response = send()?;
+        // An access token the server stopped taking early gets one fresh try
+        if response.status() == StatusCode::UNAUTHORIZED && self.auth.as_ref().is_some_and(Credentials::renew) {
+            response = send()?;
+        }
+        let status = response.status();
+        match content_range(&response) {
+            Some((start, Some(len))) if status == StatusCode::PARTIAL_CONTENT && start == offset => Ok((response, len)),
+            _ if status == StatusCode::PARTIAL_CONTENT => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "服务器返回了意外的范围"))
+            }
+            _ if status.is_success() => Err(io::Error::new(io::ErrorKind::Unsupported, "服务器不支持范围请求")),
+            _ if status == StatusCode::NOT_FOUND => Err(io::Error::new(io::ErrorKind::NotFound, format!("HTTP {}", status))),
+            _ => Err(io::Error::other(format!("HTTP {}", status))),
+        }
+    }
+
+    // One read at `pos` from the open response, once it's there: a response a
+    // little behind is read through, one further off (or none) is replaced
+    fn read_at_pos(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let reusable = self.response.is_some() && (self.response_pos..=self.response_pos + SKIP_LIMIT).contains(&self.pos);
+        if !reusable {
+            self.response = None;
+            self.response = Some(limited(&self.url, self.request(self.pos)?.0));
+            self.response_pos = self.pos;
+        }
+        let response = self.response.as_mut().expect("opened above");
+        let skip = self.pos - self.response_pos;
+        if skip > 0 {
+            let skipped = io::copy(&mut response.by_ref().take(skip), &mut io::sink())?;
+            self.response_pos += skipped;
+            if skipped < skip {
+                return Ok(0);
+            }
+        }
+        let read = response.read(buf)?;
+        self.response_pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Read for WebDAVStreamSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Ok(0);
+        }
+        let mut reopened = false;
+        loop {
+            match self.read_at_pos(buf) {
+                Ok(read) if read > 0 => {
+                    if let Some(cache) = self.cache.as_mut() {
+                        cache.wrote(self.pos, &buf[..read]);
+                    }
+                    self.pos += read as u64;
+                    if let Some(on_read) = self.on_read.as_mut() {
+                        on_read(self.pos, self.len);
+                    }
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // The response ended short of the file: dropped, or timed out
+                result if !reopened => {
+                    if let Err(e) = result {
+                        eprintln!("[Stream] 读取中断: {}", e);
+                    }
+                    eprintln!("[Stream] 从 {} bytes 处重新请求", self.pos);
+                    self.response = None;
+                    reopened = true;
+                }
+                Ok(_) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "连接提前关闭")),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Seek for WebDAVStreamSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::download;
+    use crate::test_support::MockWebDAV;
+
+    #[test]
+    fn test_reads_and_seeks_with_ranges() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let server = MockWebDAV::start(&[("music/big.flac", data.clone())], Some(("alice", "secret")));
+        let url = format!("{}/big.flac", server.url("music"));
+
+        let auth = Some(Credentials::basic("alice", "secret"));
+        let mut source = WebDAVStreamSource::open(&url, auth).unwrap();
+        assert_eq!((source.byte_len(), source.extension().as_str()), (1_000_000, "flac"));
+        let mut head = vec![0u8; 1000];
+        source.read_exact(&mut head).unwrap();
+        assert_eq!(head, data[..1000]);
+
+        // A short skip reads on; a long one, or going back, asks for a new range
+        source.seek(SeekFrom::Current(1000)).unwrap();
+        source.read_exact(&mut head).unwrap();
+        assert_eq!(head, data[2000..3000]);
+        source.seek(SeekFrom::End(-100)).unwrap();
+        let mut tail = Vec::new();
+        source.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 100..]);
+        source.seek(SeekFrom::Start(10)).unwrap();
+        source.read_exact(&mut head).unwrap();
+        assert_eq!(head, data[10..1010]);
+
+        let path = "GET /dav/music/big.flac";
+        let expected: Vec<String> =
+            ["bytes=0-", "bytes=999900-", "bytes=10-"].iter().map(|range| format!("{} {}", path, range)).collect();
+        assert_eq!(server.requests(), expected);
+    }
+
+    #[test]
+    fn test_picks_up_a_dropped_connection() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 199) as u8).collect();
+        let server = MockWebDAV::start(&[("music/flaky.mp3", data.clone())], None);
+        let url = format!("{}/flaky.mp3", server.url("music"));
+        server.cut_off_after(50_000, 1);
+
+        let mut source = WebDAVStreamSource::open(&url, None).unwrap();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        assert_eq!(server.requests().last().map(String::as_str), Some("GET /dav/music/flaky.mp3 bytes=50000-"));
+    }
+
+    #[test]
+    fn test_reads_keep_to_the_server_limit() {
+        let data: Vec<u8> = (0..60 * 1024u32).map(|i| (i % 239) as u8).collect();
+        let server = MockWebDAV::start(&[("music/slow.mp3", data.clone())], None);
+        let url = format!("{}/slow.mp3", server.url("music"));
+        crate::throttle::set_limit(&server.url("music"), 100);
+
+        // 60 KB at 100 KB/s
+        let started = std::time::Instant::now();
+        let mut source = WebDAVStreamSource::open(&url, None).unwrap();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        assert!(started.elapsed() >= Duration::from_millis(450), "{:?}", started.elapsed());
+        crate::throttle::set_limit(&server.url("music"), 0);
+    }
+
+    #[test]
+    fn test_caches_what_is_read_from_the_start() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 241) as u8).collect();
+        let server = MockWebDAV::start(&[("music/cached.ogg", data.clone())], None);
+        let url = format!("{}/cached.ogg", server.url("music"));
+        download::remove_cached(&url);
+
+        // Skipping ahead leaves a gap, so only the first read is kept
+        let mut source = WebDAVStreamSource::open(&url, None).unwrap().cached();
+        let mut head = vec![0u8; 1000];
+        source.read_exact(&mut head).unwrap();
+        source.seek(SeekFrom::Start(100_000)).unwrap();
+        source.read_exact(&mut head).unwrap();
+        drop(source);
+        let path = download::cache_path(&url);
+        assert_eq!(std::fs::read(&path).unwrap(), data[..1000]);
+        assert!(!download::is_cached(&url));
+
+        // Reading on from the start carries on from there, up to the whole file
+        let mut source = WebDAVStreamSource::open(&url, None).unwrap().cached();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        drop(source);
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert!(download::is_cached(&url));
+        download::remove_cached(&url);
+    }
+}
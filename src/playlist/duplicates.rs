@@ -0,0 +1,268 @@
+// Finding the same recording more than once across the library: the same title
+// and artist at nearly the same length, or local files whose audio is byte for
+// byte the same once their tags are left out, e.g. a copy renamed and retagged.
+// Unlike `Playlist::dedup`, which tidies one playlist, this looks at every
+// regular playlist and folds the copies into the one the user keeps.
+
+use super::{Playlist, DUPLICATE_DURATION_SLACK};
+use crate::TrackStub;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+fn read_at<const N: usize>(file: &mut File, offset: u64) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Where the audio sits in the file, past the tags of the formats that carry
+// them apart from it; anything else, or a file that doesn't parse, is taken whole
+fn audio_range(file: &mut File, extension: &str) -> std::io::Result<Range<u64>> {
+    let len = file.metadata()?.len();
+    let range = match extension {
+        "mp3" => {
+            // An ID3v2 tag in front, and maybe an ID3v1 one in the last 128 bytes
+            let header: [u8; 10] = read_at(file, 0)?;
+            let start = if &header[..3] == b"ID3" {
+                let size = header[6..].iter().fold(0u64, |size, byte| (size << 7) | (*byte & 0x7f) as u64);
+                let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+                10 + size + footer
+            } else {
+                0
+            };
+            let end = if len >= start + 128 && &read_at::<3>(file, len - 128)? == b"TAG" { len - 128 } else { len };
+            start..end
+        }
+        "flac" => {
+            // The metadata blocks after "fLaC", up to the one flagged last
+            if &read_at::<4>(file, 0)? != b"fLaC" {
+                return Ok(0..len);
+            }
+            let mut offset = 4;
+            loop {
+                let header: [u8; 4] = read_at(file, offset)?;
+                offset += 4 + u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+                if header[0] & 0x80 != 0 {
+                    break offset..len;
+                }
+            }
+        }
+        "m4a" | "m4b" | "mp4" => {
+            // The samples are in the top-level mdat box; the tags are in moov
+            let mut offset = 0;
+            loop {
+                if offset + 8 > len {
+                    break 0..len;
+                }
+                let header: [u8; 8] = read_at(file, offset)?;
+                let (size, header_len) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                    0 => (len - offset, 8),
+                    1 => (u64::from_be_bytes(read_at(file, offset + 8)?), 16),
+                    size => (size as u64, 8),
+                };
+                if &header[4..] == b"mdat" {
+                    break offset + header_len..(offset + size).min(len);
+                }
+                if size < header_len {
+                    break 0..len;
+                }
+                offset += size;
+            }
+        }
+        _ => 0..len,
+    };
+    Ok(if range.start <= range.end && range.end <= len { range } else { 0..len })
+}
+
+/// A hash of the audio in a local file, leaving out its tags, so a copy still
+/// matches after it was retagged or renamed.
+pub fn fingerprint(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let range = audio_range(&mut file, &extension)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file.take(range.end - range.start), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Fingerprints of the local files among `tracks`, by path. Only files whose
+/// audio is as long, in bytes, as another's are read in full; the rest can't
+/// match anything.
+pub fn fingerprints<'a>(tracks: impl IntoIterator<Item = &'a TrackStub>) -> HashMap<String, String> {
+    let mut by_length: HashMap<u64, Vec<&str>> = HashMap::new();
+    for track in tracks {
+        let path = Path::new(&track.path);
+        if !path.is_file() {
+            continue;
+        }
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        match File::open(path).and_then(|mut file| audio_range(&mut file, &extension)) {
+            Ok(range) => by_length.entry(range.end - range.start).or_default().push(&track.path),
+            Err(e) => eprintln!("[Library] 无法读取 {}: {}", track.path, e),
+        }
+    }
+    by_length
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .flatten()
+        .filter_map(|path| match fingerprint(Path::new(path)) {
+            Ok(hash) => Some((path.to_string(), hash)),
+            Err(e) => {
+                eprintln!("[Library] 无法读取 {}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// The sets of tracks across the regular playlists that look like the same
+/// recording, each path once, in playlist order. `fingerprints` comes from
+/// `fingerprints`; tracks whose length isn't known only match by fingerprint.
+pub fn find(playlists: &[Playlist], fingerprints: &HashMap<String, String>) -> Vec<Vec<TrackStub>> {
+    let mut seen = HashSet::new();
+    let library: Vec<&TrackStub> = playlists
+        .iter()
+        .filter(|playlist| playlist.smart.is_none())
+        .flat_map(|playlist| playlist.tracks.iter())
+        .filter(|track| seen.insert((&track.source, track.path.as_str())))
+        .collect();
+
+    let mut parents: Vec<usize> = (0..library.len()).collect();
+    let mut recordings: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut hashes: HashMap<&str, usize> = HashMap::new();
+    for (i, track) in library.iter().enumerate() {
+        if !track.duration.is_zero() {
+            let earlier = recordings.entry((track.title.trim().to_lowercase(), track.artist.trim().to_lowercase())).or_default();
+            for &j in earlier.iter() {
+                if library[j].duration.abs_diff(track.duration) <= DUPLICATE_DURATION_SLACK {
+                    let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                    parents[a] = b;
+                }
+            }
+            earlier.push(i);
+        }
+        if let Some(hash) = fingerprints.get(&track.path) {
+            let j = *hashes.entry(hash).or_insert(i);
+            let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+            parents[a] = b;
+        }
+    }
+
+    let mut groups: Vec<Vec<TrackStub>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for (i, track) in library.iter().enumerate() {
+        let group = *group_of_root.entry(root(&mut parents, i)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push((*track).clone());
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Keep `keep` and drop the other tracks of its group from every regular
+/// playlist. Where a playlist had only copies, the first becomes `keep` in
+/// place, keeping its position and entry id; any other copies go. Returns how
+/// many entries were removed or replaced.
+pub fn keep_one(playlists: &mut [Playlist], keep: &TrackStub, group: &[TrackStub]) -> usize {
+    let dropped: HashSet<&str> = group.iter().map(|t| t.path.as_str()).filter(|path| *path != keep.path).collect();
+    let mut changed = 0;
+    for playlist in playlists.iter_mut().filter(|playlist| playlist.smart.is_none()) {
+        let mut has_keep = playlist.tracks.iter().any(|t| t.path == keep.path);
+        playlist.tracks.retain_mut(|track| {
+            if !dropped.contains(track.path.as_str()) {
+                return true;
+            }
+            changed += 1;
+            if has_keep {
+                return false;
+            }
+            has_keep = true;
+            *track = TrackStub { id: track.id.clone(), added_at: track.added_at, ..keep.clone() };
+            true
+        });
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+    use std::time::Duration;
+
+    fn track(path: &str, title: &str, secs: u64) -> TrackStub {
+        TrackStub {
+            artist: "Artist".to_string(),
+            duration: Duration::from_secs(secs),
+            ..track_stub(&uuid::Uuid::new_v4().to_string(), path, title)
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_tags() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_dupes_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio = [0xffu8, 0xfb, 0x90, 0x64, 1, 2, 3, 4, 5, 6, 7, 8];
+        let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x05hello".to_vec();
+        tagged.extend_from_slice(&audio);
+        tagged.extend_from_slice(b"TAG");
+        tagged.extend_from_slice(&[b' '; 125]);
+        std::fs::write(dir.join("a.mp3"), &tagged).unwrap();
+        std::fs::write(dir.join("b.mp3"), audio).unwrap();
+        std::fs::write(dir.join("c.mp3"), [audio.as_slice(), b"!"].concat()).unwrap();
+
+        let a = track(&dir.join("a.mp3").to_string_lossy(), "One", 0);
+        let b = track(&dir.join("b.mp3").to_string_lossy(), "Two", 0);
+        let c = track(&dir.join("c.mp3").to_string_lossy(), "Three", 0);
+        let hashes = fingerprints([&a, &b, &c]);
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[&a.path], hashes[&b.path]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_finds_and_folds_duplicates() {
+        let mut mixes = Playlist::new("Mixes".to_string());
+        mixes.add_track(track("/a.mp3", "Song", 200));
+        mixes.add_track(track("/other.mp3", "Other", 200));
+        mixes.add_track(track("https://dav.example.com/a.mp3", " song ", 201));
+        let mut road = Playlist::new("Road".to_string());
+        road.add_track(track("/copy.mp3", "Song (copy)", 0));
+        road.add_track(track("/song-live.mp3", "Song", 260));
+        let mut playlists = vec![mixes, road];
+
+        let fingerprints = HashMap::from([
+            ("/a.mp3".to_string(), "f1".to_string()),
+            ("/copy.mp3".to_string(), "f1".to_string()),
+            ("/song-live.mp3".to_string(), "f2".to_string()),
+        ]);
+        let groups = find(&playlists, &fingerprints);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&str> = groups[0].iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(paths, ["/a.mp3", "https://dav.example.com/a.mp3", "/copy.mp3"]);
+
+        let road_entry = playlists[1].tracks[0].id.clone();
+        assert_eq!(keep_one(&mut playlists, &groups[0][0], &groups[0]), 2);
+        let paths = |playlist: &Playlist| playlist.tracks.iter().map(|t| t.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&playlists[0]), ["/a.mp3", "/other.mp3"]);
+        assert_eq!(paths(&playlists[1]), ["/a.mp3", "/song-live.mp3"]);
+        assert_eq!(playlists[1].tracks[0].id, road_entry);
+        assert!(find(&playlists, &fingerprints).is_empty());
+    }
+}
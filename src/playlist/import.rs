@@ -0,0 +1,360 @@
+// Playlists exported from other services, e.g. a Spotify playlist saved as CSV
+// by Exportify or as JSON from the Spotify API or an account data export. They
+// only name the songs, so each one is looked up in the library by title and
+// artist, allowing for the usual differences in spelling: case, punctuation,
+// "(Remastered 2011)", "- Live", "feat." and so on.
+
+use super::Playlist;
+use crate::TrackStub;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+// How alike (0 to 1) the cleaned up titles and artists have to be
+const TITLE_THRESHOLD: f64 = 0.85;
+const ARTIST_THRESHOLD: f64 = 0.7;
+// Lengths further apart than this are different recordings, when both are known
+const DURATION_SLACK: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    // Every credited artist; exports list features alongside the main one
+    pub artists: Vec<String>,
+    pub album: String,
+    pub duration: Option<Duration>,
+}
+
+impl Entry {
+    /// "Artist – Title", for the list of songs that weren't found.
+    pub fn label(&self) -> String {
+        match self.artists.first() {
+            Some(artist) => format!("{} – {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Matched {
+    // Library tracks in the exported order, as new entries
+    pub tracks: Vec<TrackStub>,
+    pub misses: Vec<Entry>,
+}
+
+/// The songs listed in an exported playlist, CSV or JSON.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let entries = if text.starts_with('{') || text.starts_with('[') {
+        let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let mut entries = Vec::new();
+        collect_json(&value, &mut entries);
+        entries
+    } else {
+        parse_csv(text)?
+    };
+    if entries.is_empty() {
+        return Err("no tracks found in it".to_string());
+    }
+    Ok(entries)
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Entry>, String> {
+    let mut rows = csv_rows(text).into_iter();
+    let header: Vec<String> = rows.next().unwrap_or_default().iter().map(|name| name.trim().to_lowercase()).collect();
+    let column = |names: &[&str]| header.iter().position(|name| names.contains(&name.as_str()));
+    let title = column(&["track name", "title", "name", "track"]).ok_or("no track name column")?;
+    let artist = column(&["artist name(s)", "artist name", "artist", "artists"]);
+    let album = column(&["album name", "album"]);
+    let duration_ms = column(&["track duration (ms)", "duration (ms)", "duration_ms"]);
+
+    let field = |row: &[String], idx: Option<usize>| idx.and_then(|idx| row.get(idx)).map(|value| value.trim().to_string());
+    Ok(rows
+        .filter_map(|row| {
+            let title = field(&row, Some(title)).filter(|title| !title.is_empty())?;
+            Some(Entry {
+                title,
+                // Exportify separates the artists with commas, some versions with semicolons
+                artists: split_artists(&field(&row, artist).unwrap_or_default()),
+                album: field(&row, album).unwrap_or_default(),
+                duration: field(&row, duration_ms).and_then(|ms| ms.parse().ok()).map(Duration::from_millis),
+            })
+        })
+        .collect())
+}
+
+fn split_artists(artists: &str) -> Vec<String> {
+    artists.split([',', ';']).map(str::trim).filter(|artist| !artist.is_empty()).map(str::to_string).collect()
+}
+
+// RFC 4180: fields in double quotes may hold commas, line breaks and "" for a quote
+fn csv_rows(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    rows
+}
+
+// Anything track-like anywhere in the document: Spotify API playlists keep them
+// under items[].track, account exports under playlists[].items[].track
+fn collect_json(value: &Value, entries: &mut Vec<Entry>) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|value| collect_json(value, entries)),
+        Value::Object(object) => {
+            if let Some(track @ Value::Object(_)) = object.get("track") {
+                collect_json(track, entries);
+            } else if let Some(entry) = json_entry(object) {
+                entries.push(entry);
+            } else {
+                object.values().for_each(|value| collect_json(value, entries));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_entry(object: &Map<String, Value>) -> Option<Entry> {
+    let text = |keys: &[&str]| keys.iter().find_map(|key| object.get(*key)?.as_str()).map(|text| text.trim().to_string());
+    let title = text(&["name", "title", "trackName", "track_name"]).filter(|title| !title.is_empty())?;
+    // Without an artist it's a playlist or an album, not a track
+    let artists = match object.get("artists") {
+        Some(Value::Array(artists)) => artists
+            .iter()
+            .filter_map(|artist| artist.as_str().or_else(|| artist.get("name")?.as_str()))
+            .map(|artist| artist.trim().to_string())
+            .collect(),
+        _ => split_artists(&text(&["artists", "artist", "artistName", "artist_name"])?),
+    };
+    let album = match object.get("album") {
+        Some(Value::Object(album)) => album.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+        _ => text(&["album", "albumName", "album_name"]).unwrap_or_default(),
+    };
+    let duration = ["duration_ms", "durationMs"].iter().find_map(|key| object.get(*key)?.as_u64()).map(Duration::from_millis);
+    Some(Entry { title, artists, album, duration })
+}
+
+// Lower case letters and digits only, without the bracketed or dashed extras
+// services add to titles and the features added to artists
+fn clean(text: &str) -> String {
+    let mut cleaned = String::new();
+    let mut depth = 0usize;
+    for c in text.to_lowercase().chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            '\'' | '’' => {}
+            '&' => cleaned.push_str(" and "),
+            c if c.is_alphanumeric() => cleaned.push(c),
+            _ => cleaned.push(' '),
+        }
+    }
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    let end = words.iter().position(|word| matches!(*word, "feat" | "ft" | "featuring")).unwrap_or(words.len());
+    words[..end].join(" ")
+}
+
+// "Song - Remastered 2011" and "Song - Live at Wembley" are still "Song"
+fn clean_title(title: &str) -> String {
+    let title = title.split(" - ").next().unwrap_or(title);
+    clean(title)
+}
+
+// "The Beatles" and "Beatles" are the same band
+fn clean_artist(artist: &str) -> String {
+    let artist = clean(artist);
+    artist.strip_prefix("the ").map(str::to_string).unwrap_or(artist)
+}
+
+// 1 for the same text, down to 0 for nothing in common
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+struct Candidate<'a> {
+    track: &'a TrackStub,
+    title: String,
+    artist: String,
+    album: String,
+}
+
+/// Find each entry in the regular playlists, the closest title and artist
+/// winning and a matching album breaking ties. Songs with no close enough
+/// track in the library end up in `misses`.
+pub fn match_library(entries: &[Entry], playlists: &[Playlist]) -> Matched {
+    let mut seen = HashSet::new();
+    let library: Vec<Candidate> = playlists
+        .iter()
+        .filter(|playlist| playlist.smart.is_none())
+        .flat_map(|playlist| playlist.tracks.iter())
+        .filter(|track| seen.insert((&track.source, track.path.as_str())))
+        .map(|track| Candidate {
+            track,
+            title: clean_title(&track.title),
+            artist: clean_artist(&track.artist),
+            album: clean(&track.album),
+        })
+        .collect();
+    // Only tracks sharing a word of the title are worth comparing
+    let mut by_word: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, candidate) in library.iter().enumerate() {
+        for word in candidate.title.split_whitespace() {
+            by_word.entry(word).or_default().push(idx);
+        }
+    }
+
+    let mut matched = Matched::default();
+    for entry in entries {
+        let title = clean_title(&entry.title);
+        let artists: Vec<String> = entry.artists.iter().map(|artist| clean_artist(artist)).collect();
+        let album = clean(&entry.album);
+        let candidates: HashSet<usize> =
+            title.split_whitespace().filter_map(|word| by_word.get(word)).flatten().copied().collect();
+
+        let best = candidates
+            .into_iter()
+            .filter_map(|idx| {
+                let candidate = &library[idx];
+                let track = candidate.track;
+                if let Some(duration) = entry.duration.filter(|_| !track.duration.is_zero()) {
+                    if duration.abs_diff(track.duration) > DURATION_SLACK {
+                        return None;
+                    }
+                }
+                let title_score = similarity(&title, &candidate.title);
+                // Exports may credit the album artist where the file names the track's own
+                let album_artist = track.album_artist.as_deref().map(clean_artist);
+                let artist_score = artists
+                    .iter()
+                    .flat_map(|artist| {
+                        let by_album_artist = album_artist.as_deref().map(|album_artist| similarity(artist, album_artist));
+                        [Some(similarity(artist, &candidate.artist)), by_album_artist]
+                    })
+                    .flatten()
+                    .fold(if artists.is_empty() { 1.0 } else { 0.0 }, f64::max);
+                if title_score < TITLE_THRESHOLD || artist_score < ARTIST_THRESHOLD {
+                    return None;
+                }
+                let album_bonus = if !album.is_empty() && album == candidate.album { 0.05 } else { 0.0 };
+                Some((title_score + artist_score + album_bonus, idx))
+            })
+            // The earlier track in the library wins a tie
+            .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        match best {
+            Some((_, idx)) => matched.tracks.push(TrackStub {
+                id: Uuid::new_v4().to_string(),
+                added_at: None,
+                ..library[idx].track.clone()
+            }),
+            None => matched.misses.push(entry.clone()),
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+
+    #[test]
+    fn test_parse_exportify_csv_and_spotify_json() {
+        let csv = "\u{feff}\"Track URI\",\"Track Name\",\"Artist Name(s)\",\"Album Name\",\"Track Duration (ms)\"\r\n\
+                   \"spotify:track:1\",\"Hello, \"\"World\"\"\",\"Band, Guest\",\"LP\",\"200500\"\r\n\
+                   \"spotify:track:2\",\"\",\"Nobody\",\"\",\"\"\r\n";
+        assert_eq!(
+            parse(csv).unwrap(),
+            [Entry {
+                title: "Hello, \"World\"".to_string(),
+                artists: vec!["Band".to_string(), "Guest".to_string()],
+                album: "LP".to_string(),
+                duration: Some(Duration::from_millis(200_500)),
+            }]
+        );
+
+        let api = r#"{"name": "Mix", "tracks": {"items": [
+            {"added_at": "2024-01-01", "track": {"name": "Song", "artists": [{"name": "Band"}], "album": {"name": "LP"}, "duration_ms": 1000}},
+            {"added_at": "2024-01-02", "track": null}
+        ]}}"#;
+        let export = r#"{"playlists": [{"name": "Mix", "items": [
+            {"track": {"trackName": "Song", "artistName": "Band", "albumName": "LP"}, "episode": null}
+        ]}]}"#;
+        let entries = [parse(api).unwrap(), parse(export).unwrap()];
+        assert!(entries.iter().all(|entries| entries.len() == 1 && entries[0].label() == "Band – Song"));
+        assert_eq!(entries[0][0].duration, Some(Duration::from_secs(1)));
+        assert!(parse(r#"{"name": "Empty", "items": []}"#).is_err());
+        assert!(parse("Artist,Album\nBand,LP\n").is_err());
+    }
+
+    #[test]
+    fn test_match_library() {
+        let track = |title: &str, artist: &str, album: &str, secs: u64| TrackStub {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            duration: Duration::from_secs(secs),
+            ..track_stub(title, &format!("/music/{}.flac", title), title)
+        };
+        let mut playlist = Playlist::new("Library".to_string());
+        playlist.tracks = vec![
+            track("Here Comes the Sun", "The Beatles", "Abbey Road", 185),
+            track("Dont Stop Me Now", "Queen", "Jazz", 209),
+            track("Intro", "Someone Else", "Other", 60),
+        ];
+        let entry = |title: &str, artist: &str, secs: Option<u64>| Entry {
+            title: title.to_string(),
+            artists: split_artists(artist),
+            duration: secs.map(Duration::from_secs),
+            ..Default::default()
+        };
+        let entries = [
+            entry("Don't Stop Me Now - Remastered 2011", "Queen", Some(211)),
+            entry("Here Comes The Sun (2019 Mix)", "Beatles, Someone", None),
+            entry("Intro", "Queen", None),
+            entry("Here Comes the Sun", "The Beatles", Some(300)),
+        ];
+        let matched = match_library(&entries, &[playlist]);
+        let titles: Vec<&str> = matched.tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Dont Stop Me Now", "Here Comes the Sun"]);
+        assert_ne!(matched.tracks[0].id, "Dont Stop Me Now");
+        assert_eq!(matched.misses, [entries[2].clone(), entries[3].clone()]);
+    }
+}
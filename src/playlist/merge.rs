@@ -0,0 +1,231 @@
+use crate::TrackStub;
+use std::collections::{HashMap, HashSet};
+
+// Three-way merge of two copies of a playlist that changed independently, say
+// on two machines, against the version they last agreed on. Entries are matched
+// by path (the nth occurrence of a path matches the nth occurrence on the other
+// side), so the same song added on both machines isn't doubled even though each
+// side gave it a different entry id.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Moved,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub side: Side,
+    pub kind: ChangeKind,
+    pub track: TrackStub,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeResult {
+    pub tracks: Vec<TrackStub>,
+    pub changes: Vec<Change>,
+    // Moved to different places on both sides; our position was kept
+    pub conflicts: Vec<TrackStub>,
+}
+
+type Key = (String, usize);
+
+fn keys(tracks: &[TrackStub]) -> Vec<Key> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    tracks
+        .iter()
+        .map(|track| {
+            let n = seen.entry(track.path.as_str()).or_default();
+            *n += 1;
+            (track.path.clone(), *n)
+        })
+        .collect()
+}
+
+struct SideChanges {
+    added: HashSet<Key>,
+    removed: HashSet<Key>,
+    moved: HashSet<Key>,
+}
+
+fn side_changes(base: &[Key], side: &[Key]) -> SideChanges {
+    let base_set: HashSet<&Key> = base.iter().collect();
+    let side_set: HashSet<&Key> = side.iter().collect();
+    let added = side.iter().filter(|key| !base_set.contains(key)).cloned().collect();
+    let removed = base.iter().filter(|key| !side_set.contains(key)).cloned().collect();
+
+    // Entries on both sides that keep their base order are the longest increasing
+    // run of base positions; everything else on both sides was moved
+    let base_pos: HashMap<&Key, usize> = base.iter().enumerate().map(|(i, key)| (key, i)).collect();
+    let common: Vec<&Key> = side.iter().filter(|key| base_set.contains(key)).collect();
+    let positions: Vec<usize> = common.iter().map(|key| base_pos[key]).collect();
+    let kept = longest_increasing(&positions);
+    let moved = common
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !kept.contains(i))
+        .map(|(_, key)| (*key).clone())
+        .collect();
+
+    SideChanges { added, removed, moved }
+}
+
+// Indices into `values` of one longest strictly increasing subsequence
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // tails[k]: index of the smallest last value of an increasing run of length k+1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < value);
+        previous[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut run = HashSet::new();
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        run.insert(i);
+        next = previous[i];
+    }
+    run
+}
+
+/// Merge `ours` and `theirs`, both edited from `base`. Removals from either
+/// side win, additions from both are kept, and their additions and moves are
+/// placed after the entry they follow on their side. A track both sides moved
+/// stays where we put it and is reported in `conflicts`.
+pub fn merge(base: &[TrackStub], ours: &[TrackStub], theirs: &[TrackStub]) -> MergeResult {
+    let base_keys = keys(base);
+    let our_keys = keys(ours);
+    let their_keys = keys(theirs);
+    let our_changes = side_changes(&base_keys, &our_keys);
+    let their_changes = side_changes(&base_keys, &their_keys);
+
+    let mut changes = Vec::new();
+    let mut report = |side, kind, keys: &[Key], tracks: &[TrackStub], picked: &HashSet<Key>| {
+        for (key, track) in keys.iter().zip(tracks) {
+            if picked.contains(key) {
+                changes.push(Change { side, kind, track: track.clone() });
+            }
+        }
+    };
+    report(Side::Ours, ChangeKind::Added, &our_keys, ours, &our_changes.added);
+    report(Side::Ours, ChangeKind::Removed, &base_keys, base, &our_changes.removed);
+    report(Side::Ours, ChangeKind::Moved, &our_keys, ours, &our_changes.moved);
+    report(Side::Theirs, ChangeKind::Added, &their_keys, theirs, &their_changes.added);
+    report(Side::Theirs, ChangeKind::Removed, &base_keys, base, &their_changes.removed);
+    report(Side::Theirs, ChangeKind::Moved, &their_keys, theirs, &their_changes.moved);
+
+    // Start from our order without what they removed or are about to place
+    let our_set: HashSet<&Key> = our_keys.iter().collect();
+    let their_placed: HashSet<&Key> = their_keys
+        .iter()
+        .filter(|key| {
+            (their_changes.added.contains(key) && !our_set.contains(key))
+                || (their_changes.moved.contains(key) && !our_changes.moved.contains(key))
+        })
+        .collect();
+    let mut merged: Vec<(Key, TrackStub)> = our_keys
+        .iter()
+        .cloned()
+        .zip(ours.iter().cloned())
+        .filter(|(key, _)| !their_changes.removed.contains(key) && !their_placed.contains(key))
+        .collect();
+    let mut in_merged: HashSet<Key> = merged.iter().map(|(key, _)| key.clone()).collect();
+
+    let our_tracks: HashMap<&Key, &TrackStub> = our_keys.iter().zip(ours).collect();
+    let mut conflicts = Vec::new();
+    for (i, (key, track)) in their_keys.iter().zip(theirs).enumerate() {
+        if their_changes.moved.contains(key) && our_changes.moved.contains(key) {
+            let predecessor = |keys: &[Key]| {
+                let pos = keys.iter().position(|k| k == key)?;
+                pos.checked_sub(1).map(|p| keys[p].clone())
+            };
+            if predecessor(&our_keys) != predecessor(&their_keys) {
+                conflicts.push(our_tracks.get(key).map_or(track, |t| *t).clone());
+            }
+        }
+        if !their_placed.contains(key) || our_changes.removed.contains(key) {
+            continue;
+        }
+        // After the nearest entry before it on their side that's in the result
+        let at = their_keys[..i]
+            .iter()
+            .rev()
+            .find(|before| in_merged.contains(*before))
+            .and_then(|before| merged.iter().position(|(k, _)| k == before))
+            .map_or(0, |pos| pos + 1);
+        let track = our_tracks.get(key).map_or(track, |t| *t).clone();
+        in_merged.insert(key.clone());
+        merged.insert(at, (key.clone(), track));
+    }
+
+    MergeResult {
+        tracks: merged.into_iter().map(|(_, track)| track).collect(),
+        changes,
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+
+    fn list(paths: &str) -> Vec<TrackStub> {
+        paths
+            .split_whitespace()
+            .map(|path| track_stub(&uuid::Uuid::new_v4().to_string(), path, &path.to_uppercase()))
+            .collect()
+    }
+
+    fn paths(tracks: &[TrackStub]) -> String {
+        tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn summary(result: &MergeResult) -> Vec<(Side, ChangeKind, String)> {
+        result.changes.iter().map(|c| (c.side, c.kind, c.track.path.clone())).collect()
+    }
+
+    #[test]
+    fn test_merge_keeps_both_sides_edits() {
+        let base = list("a b c d e");
+        // We dropped b and added x after c; they moved e to the front and added y at the end
+        let result = merge(&base, &list("a c x d e"), &list("e a b c d y"));
+        assert_eq!(paths(&result.tracks), "e a c x d y");
+        assert_eq!(
+            summary(&result),
+            vec![
+                (Side::Ours, ChangeKind::Added, "x".to_string()),
+                (Side::Ours, ChangeKind::Removed, "b".to_string()),
+                (Side::Theirs, ChangeKind::Added, "y".to_string()),
+                (Side::Theirs, ChangeKind::Moved, "e".to_string()),
+            ]
+        );
+        assert!(result.conflicts.is_empty());
+
+        // The same song added on both sides only appears once
+        let result = merge(&base, &list("a b c d e z"), &list("a b c d e z"));
+        assert_eq!(paths(&result.tracks), "a b c d e z");
+
+        // Both moved d to different places: ours wins and it's flagged
+        let result = merge(&base, &list("d a b c e"), &list("a d b c e"));
+        assert_eq!(paths(&result.tracks), "d a b c e");
+        assert_eq!(paths(&result.conflicts), "d");
+
+        // Their move of a track we removed doesn't bring it back
+        let result = merge(&base, &list("a b d e"), &list("c a b d e"));
+        assert_eq!(paths(&result.tracks), "a b d e");
+    }
+}
@@ -0,0 +1,309 @@
+// Smart playlists: instead of a fixed track list, a set of rules checked against
+// every track in the regular playlists. The tracks are worked out again whenever
+// the library, play history, ratings or favorites change.
+
+use super::Playlist;
+use crate::{TrackSource, TrackStub};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "field", content = "value", rename_all = "snake_case")]
+pub enum Rule {
+    // Case-insensitive substring matches
+    ArtistContains(String),
+    TitleContains(String),
+    AlbumContains(String),
+    AddedWithinDays(u32),
+    PlayedMoreThan(u32),
+    ShorterThanMinutes(u32),
+    LongerThanMinutes(u32),
+    RatedAtLeast(u8),
+    Favorite,
+}
+
+/// Each kind of rule, for picking one in the editor without a value yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleKind {
+    ArtistContains,
+    TitleContains,
+    AlbumContains,
+    AddedWithinDays,
+    PlayedMoreThan,
+    ShorterThanMinutes,
+    LongerThanMinutes,
+    RatedAtLeast,
+    Favorite,
+}
+
+impl RuleKind {
+    pub const ALL: [RuleKind; 9] = [
+        RuleKind::ArtistContains,
+        RuleKind::TitleContains,
+        RuleKind::AlbumContains,
+        RuleKind::AddedWithinDays,
+        RuleKind::PlayedMoreThan,
+        RuleKind::ShorterThanMinutes,
+        RuleKind::LongerThanMinutes,
+        RuleKind::RatedAtLeast,
+        RuleKind::Favorite,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RuleKind::ArtistContains => "Artist contains",
+            RuleKind::TitleContains => "Title contains",
+            RuleKind::AlbumContains => "Album contains",
+            RuleKind::AddedWithinDays => "Added in the last (days)",
+            RuleKind::PlayedMoreThan => "Play count above",
+            RuleKind::ShorterThanMinutes => "Shorter than (min)",
+            RuleKind::LongerThanMinutes => "Longer than (min)",
+            RuleKind::RatedAtLeast => "Rated at least (stars)",
+            RuleKind::Favorite => "Is a favorite",
+        }
+    }
+
+    /// Whether the rule needs a value typed in.
+    pub fn takes_value(self) -> bool {
+        self != RuleKind::Favorite
+    }
+
+    /// The rule this kind makes with `value` as typed, or None if it doesn't fit.
+    pub fn with_value(self, value: &str) -> Option<Rule> {
+        let value = value.trim();
+        let text = || (!value.is_empty()).then(|| value.to_string());
+        Some(match self {
+            RuleKind::ArtistContains => Rule::ArtistContains(text()?),
+            RuleKind::TitleContains => Rule::TitleContains(text()?),
+            RuleKind::AlbumContains => Rule::AlbumContains(text()?),
+            RuleKind::AddedWithinDays => Rule::AddedWithinDays(value.parse().ok()?),
+            RuleKind::PlayedMoreThan => Rule::PlayedMoreThan(value.parse().ok()?),
+            RuleKind::ShorterThanMinutes => Rule::ShorterThanMinutes(value.parse().ok()?),
+            RuleKind::LongerThanMinutes => Rule::LongerThanMinutes(value.parse().ok()?),
+            RuleKind::RatedAtLeast => Rule::RatedAtLeast(value.parse().ok().filter(|stars| (1..=5).contains(stars))?),
+            RuleKind::Favorite => Rule::Favorite,
+        })
+    }
+}
+
+impl Rule {
+    pub fn kind(&self) -> RuleKind {
+        match self {
+            Rule::ArtistContains(_) => RuleKind::ArtistContains,
+            Rule::TitleContains(_) => RuleKind::TitleContains,
+            Rule::AlbumContains(_) => RuleKind::AlbumContains,
+            Rule::AddedWithinDays(_) => RuleKind::AddedWithinDays,
+            Rule::PlayedMoreThan(_) => RuleKind::PlayedMoreThan,
+            Rule::ShorterThanMinutes(_) => RuleKind::ShorterThanMinutes,
+            Rule::LongerThanMinutes(_) => RuleKind::LongerThanMinutes,
+            Rule::RatedAtLeast(_) => RuleKind::RatedAtLeast,
+            Rule::Favorite => RuleKind::Favorite,
+        }
+    }
+
+    /// The value as the editor shows it.
+    pub fn value(&self) -> String {
+        match self {
+            Rule::ArtistContains(text) | Rule::TitleContains(text) | Rule::AlbumContains(text) => text.clone(),
+            Rule::AddedWithinDays(n) | Rule::PlayedMoreThan(n) | Rule::ShorterThanMinutes(n) | Rule::LongerThanMinutes(n) => {
+                n.to_string()
+            }
+            Rule::RatedAtLeast(stars) => stars.to_string(),
+            Rule::Favorite => String::new(),
+        }
+    }
+
+    fn matches(&self, track: &TrackStub, stats: &TrackStats) -> bool {
+        let contains = |field: &str, text: &str| field.to_lowercase().contains(&text.to_lowercase());
+        match self {
+            Rule::ArtistContains(text) => contains(&track.artist, text),
+            Rule::TitleContains(text) => contains(&track.title, text),
+            Rule::AlbumContains(text) => contains(&track.album, text),
+            Rule::AddedWithinDays(days) => track
+                .added_at
+                .and_then(|added| stats.now.duration_since(added).ok())
+                .is_some_and(|age| age <= DAY * *days),
+            Rule::PlayedMoreThan(plays) => stats.play_counts.get(&track.file_key()).copied().unwrap_or(0) > *plays,
+            // Tracks whose length isn't known yet match neither
+            Rule::ShorterThanMinutes(minutes) => {
+                !track.duration.is_zero() && track.duration < Duration::from_secs(60 * *minutes as u64)
+            }
+            Rule::LongerThanMinutes(minutes) => track.duration > Duration::from_secs(60 * *minutes as u64),
+            Rule::RatedAtLeast(stars) => stats.ratings.get(&track.file_key()).is_some_and(|rating| rating >= stars),
+            Rule::Favorite => stats.favorites.contains(&track.file_key()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SmartRules {
+    // Every rule has to hold, rather than any one of them
+    pub match_all: bool,
+    pub rules: Vec<Rule>,
+}
+
+impl SmartRules {
+    pub fn matches(&self, track: &TrackStub, stats: &TrackStats) -> bool {
+        if self.match_all {
+            self.rules.iter().all(|rule| rule.matches(track, stats))
+        } else {
+            self.rules.iter().any(|rule| rule.matches(track, stats))
+        }
+    }
+}
+
+// Version of the rule set files `export` writes
+const RULE_SET_VERSION: u32 = 1;
+
+/// A smart playlist's name and rules as saved to a file to share, without its tracks.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct RuleSetFile {
+    version: u32,
+    name: String,
+    #[serde(flatten)]
+    rules: SmartRules,
+}
+
+/// JSON for a smart playlist's rules, for `import` to read on another machine.
+pub fn export(name: &str, rules: &SmartRules) -> String {
+    let file = RuleSetFile { version: RULE_SET_VERSION, name: name.to_string(), rules: rules.clone() };
+    serde_json::to_string_pretty(&file).expect("rules serialize")
+}
+
+/// The name and rules in a file `export` wrote.
+pub fn import(json: &str) -> Result<(String, SmartRules), String> {
+    let file: RuleSetFile = serde_json::from_str(json).map_err(|e| format!("Not a smart playlist rule file: {}", e))?;
+    if file.version > RULE_SET_VERSION {
+        return Err(format!("Made by a newer version of the app (rule file version {})", file.version));
+    }
+    if file.rules.rules.is_empty() {
+        return Err("The file has no rules".to_string());
+    }
+    Ok((file.name, file.rules))
+}
+
+/// What rules can ask about a track beyond its own fields, keyed by file_key.
+pub struct TrackStats<'a> {
+    pub play_counts: &'a HashMap<String, u32>,
+    pub ratings: &'a HashMap<String, u8>,
+    pub favorites: &'a HashSet<String>,
+    pub now: SystemTime,
+}
+
+/// The smart playlists whose tracks no longer match their rules, by index, with
+/// the tracks they should have now: each matching file in the regular playlists
+/// once, in playlist order. Tracks that stay keep their entry ids, so playback
+/// and selection carry on.
+pub fn refreshed(playlists: &[Playlist], stats: &TrackStats) -> Vec<(usize, Vec<TrackStub>)> {
+    let mut seen = HashSet::new();
+    let library: Vec<&TrackStub> = playlists
+        .iter()
+        .filter(|playlist| playlist.smart.is_none())
+        .flat_map(|playlist| playlist.tracks.iter())
+        .filter(|track| seen.insert((&track.source, track.path.as_str())))
+        .collect();
+
+    playlists
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, playlist)| {
+            let rules = playlist.smart.as_ref()?;
+            let ids: HashMap<(&TrackSource, &str), &str> =
+                playlist.tracks.iter().map(|t| ((&t.source, t.path.as_str()), t.id.as_str())).collect();
+            let tracks: Vec<TrackStub> = library
+                .iter()
+                .filter(|track| rules.matches(track, stats))
+                .map(|track| TrackStub {
+                    id: ids.get(&(&track.source, track.path.as_str())).map_or_else(|| uuid::Uuid::new_v4().to_string(), |id| id.to_string()),
+                    ..(*track).clone()
+                })
+                .collect();
+            (tracks != playlist.tracks).then_some((idx, tracks))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+
+    fn track(path: &str, artist: &str, minutes: u64, added_days_ago: Option<u64>, now: SystemTime) -> TrackStub {
+        TrackStub {
+            artist: artist.to_string(),
+            duration: Duration::from_secs(60 * minutes),
+            added_at: added_days_ago.map(|days| now - DAY * days as u32),
+            ..track_stub(&uuid::Uuid::new_v4().to_string(), path, path)
+        }
+    }
+
+    #[test]
+    fn test_smart_playlist_follows_the_library() {
+        let now = SystemTime::now();
+        let mut mixes = Playlist::new("Mixes".to_string());
+        mixes.add_track(track("/a.mp3", "Boards of Canada", 4, Some(3), now));
+        mixes.add_track(track("/b.mp3", "Aphex Twin", 12, Some(90), now));
+        let mut other = Playlist::new("Other".to_string());
+        other.add_track(track("/c.mp3", "boards of canada", 7, None, now));
+        other.add_track(track("/a.mp3", "Boards of Canada", 4, Some(3), now));
+        let mut smart = Playlist::new("BoC".to_string());
+        smart.smart = Some(SmartRules {
+            match_all: true,
+            rules: vec![Rule::ArtistContains("Boards".to_string()), Rule::ShorterThanMinutes(5)],
+        });
+        let mut playlists = vec![mixes, other, smart];
+
+        let play_counts = HashMap::from([("/b.mp3".to_string(), 4)]);
+        let ratings = HashMap::new();
+        let favorites = HashSet::from(["/c.mp3".to_string()]);
+        let stats = TrackStats { play_counts: &play_counts, ratings: &ratings, favorites: &favorites, now };
+        let updates = refreshed(&playlists, &stats);
+        assert_eq!(updates.len(), 1);
+        let (idx, tracks) = updates.into_iter().next().unwrap();
+        assert_eq!(idx, 2);
+        assert_eq!(tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>(), ["/a.mp3"]);
+        let kept_id = tracks[0].id.clone();
+        playlists[2].tracks = tracks;
+        assert!(refreshed(&playlists, &stats).is_empty());
+
+        playlists[2].smart = Some(SmartRules {
+            match_all: false,
+            rules: vec![Rule::AddedWithinDays(30), Rule::PlayedMoreThan(3), Rule::ArtistContains("boards".to_string())],
+        });
+        let (_, tracks) = refreshed(&playlists, &stats).remove(0);
+        assert_eq!(tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>(), ["/a.mp3", "/b.mp3", "/c.mp3"]);
+        assert_eq!(tracks[0].id, kept_id);
+
+        playlists[2].smart = Some(SmartRules { match_all: true, rules: vec![Rule::Favorite] });
+        let (_, tracks) = refreshed(&playlists, &stats).remove(0);
+        assert_eq!(tracks.iter().map(|t| t.path.as_str()).collect::<Vec<_>>(), ["/c.mp3"]);
+
+        assert_eq!(RuleKind::RatedAtLeast.with_value("6"), None);
+        assert_eq!(RuleKind::ArtistContains.with_value("  "), None);
+        let rule = RuleKind::ShorterThanMinutes.with_value(" 5 ").unwrap();
+        assert_eq!((rule.kind(), rule.value()), (RuleKind::ShorterThanMinutes, "5".to_string()));
+    }
+
+    #[test]
+    fn test_rule_sets_round_trip_through_json() {
+        let rules = SmartRules {
+            match_all: false,
+            rules: vec![
+                Rule::ArtistContains("Boards".to_string()),
+                Rule::AddedWithinDays(30),
+                Rule::RatedAtLeast(4),
+                Rule::Favorite,
+            ],
+        };
+        let json = export("Recent BoC", &rules);
+        assert_eq!(import(&json), Ok(("Recent BoC".to_string(), rules)));
+        assert!(json.contains(r#""field": "artist_contains""#));
+
+        assert!(import(r#"{"version": 2, "name": "x", "match_all": true, "rules": [{"field": "favorite"}]}"#).is_err());
+        assert!(import(r#"{"version": 1, "name": "x", "match_all": true, "rules": []}"#).is_err());
+        assert!(import("[]").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+// Figures about the whole library, every track of the regular playlists counted
+// once per file: how much there is, what gets played most, and which formats take
+// up the disk. Albums go by their album artist, so a compilation is one album
+// rather than one per artist on it.
+
+use super::Playlist;
+use crate::TrackSource;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+// Rows in each of the "most played" lists
+const TOP_LIMIT: usize = 5;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LibraryStats {
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    pub total_duration: Duration,
+    // (artist, plays), most played first
+    pub top_artists: Vec<(String, u32)>,
+    // (album, artist, plays), most played first
+    pub top_albums: Vec<(String, String, u32)>,
+    // Largest share of the disk first
+    pub formats: Vec<FormatUsage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatUsage {
+    // Upper-case extension, e.g. "FLAC"
+    pub format: String,
+    pub tracks: usize,
+    // Bytes of the local files; remote tracks only add to the count
+    pub bytes: u64,
+}
+
+fn is_known(name: &str, unknown: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && name != unknown
+}
+
+// The extension of a file path or URL, ignoring any query string
+fn format_of(path: &str) -> Option<String> {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let extension = Path::new(path).extension()?.to_str()?;
+    Some(extension.to_uppercase())
+}
+
+// Entries keyed case-insensitively, each shown as first spelled
+fn top<K: Eq + std::hash::Hash, N: Ord>(plays: HashMap<K, (N, u32)>) -> Vec<(N, u32)> {
+    let mut ranked: Vec<(N, u32)> = plays.into_values().filter(|(_, plays)| *plays > 0).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(TOP_LIMIT);
+    ranked
+}
+
+/// Statistics over the tracks of the regular playlists. `play_counts` is by
+/// file_key; local files without a recorded size are looked up on disk.
+pub fn compute(playlists: &[Playlist], play_counts: &HashMap<String, u32>) -> LibraryStats {
+    let mut seen = HashSet::new();
+    let mut stats = LibraryStats::default();
+    let mut artists: HashMap<String, (String, u32)> = HashMap::new();
+    let mut albums: HashMap<(String, String), ((String, String), u32)> = HashMap::new();
+    let mut formats: HashMap<String, FormatUsage> = HashMap::new();
+
+    let library = playlists
+        .iter()
+        .filter(|playlist| playlist.smart.is_none())
+        .flat_map(|playlist| playlist.tracks.iter())
+        .filter(|track| seen.insert((&track.source, track.path.as_str())));
+    for track in library {
+        stats.tracks += 1;
+        stats.total_duration += track.duration;
+        let plays = play_counts.get(&track.file_key()).copied().unwrap_or(0);
+
+        // Names differ only in case or stray spaces between files of one album
+        let artist = is_known(&track.artist, "Unknown Artist").then(|| track.artist.trim());
+        let album = is_known(&track.album, "Unknown Album").then(|| track.album.trim());
+        if let Some(artist) = artist {
+            let entry = artists.entry(artist.to_lowercase()).or_insert_with(|| (artist.to_string(), 0));
+            entry.1 += plays;
+        }
+        if let Some(album) = album {
+            let album_artist = track.album_artist_or_artist();
+            let artist = is_known(album_artist, "Unknown Artist").then(|| album_artist.trim()).unwrap_or_default();
+            let entry = albums
+                .entry((album.to_lowercase(), artist.to_lowercase()))
+                .or_insert_with(|| ((album.to_string(), artist.to_string()), 0));
+            entry.1 += plays;
+        }
+
+        if let Some(format) = format_of(&track.path) {
+            let bytes = match track.source {
+                TrackSource::Local => track
+                    .stamp
+                    .map(|stamp| stamp.size)
+                    .or_else(|| std::fs::metadata(&track.path).ok().map(|metadata| metadata.len()))
+                    .unwrap_or(0),
+                _ => 0,
+            };
+            let usage = formats.entry(format.clone()).or_insert_with(|| FormatUsage { format, tracks: 0, bytes: 0 });
+            usage.tracks += 1;
+            usage.bytes += bytes;
+        }
+    }
+
+    stats.albums = albums.len();
+    stats.artists = artists.len();
+    stats.top_artists = top(artists);
+    stats.top_albums = top(albums).into_iter().map(|((album, artist), plays)| (album, artist, plays)).collect();
+    stats.formats = formats.into_values().collect();
+    stats.formats.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.tracks.cmp(&a.tracks)).then_with(|| a.format.cmp(&b.format)));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_stub;
+    use crate::{FileStamp, TrackStub};
+
+    fn track(path: &str, artist: &str, album: &str, secs: u64, size: u64) -> TrackStub {
+        TrackStub {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            duration: Duration::from_secs(secs),
+            stamp: Some(FileStamp { size, modified: std::time::SystemTime::UNIX_EPOCH }),
+            ..track_stub(&uuid::Uuid::new_v4().to_string(), path, "Song")
+        }
+    }
+
+    #[test]
+    fn test_counts_each_track_once() {
+        let mut mixes = Playlist::new("Mixes".to_string());
+        mixes.add_track(track("/a.flac", "Band", "First", 200, 30_000_000));
+        mixes.add_track(track("/b.flac", "band ", "First", 100, 20_000_000));
+        mixes.add_track(track("/c.mp3", "Singer", "Unknown Album", 60, 5_000_000));
+        let mut road = Playlist::new("Road".to_string());
+        road.add_track(track("/a.flac", "Band", "First", 200, 30_000_000));
+        road.add_track(track("/d.mp3", "Unknown Artist", "Second", 30, 3_000_000));
+        let mut remote = track("https://dav.example.com/e.mp3?x=1", "Singer", "Second", 10, 0);
+        remote.source = TrackSource::WebDAV { config_id: "dav".to_string() };
+        road.add_track(remote);
+        for (path, artist) in [("/f.mp3", "DJ"), ("/g.mp3", "MC")] {
+            road.add_track(TrackStub { album_artist: Some("Various Artists".to_string()), ..track(path, artist, "Hits", 0, 0) });
+        }
+
+        let play_counts = HashMap::from([
+            ("/a.flac".to_string(), 3),
+            ("/b.flac".to_string(), 1),
+            ("/c.mp3".to_string(), 5),
+            ("/gone.mp3".to_string(), 9),
+        ]);
+        let stats = compute(&[mixes, road], &play_counts);
+        assert_eq!(stats.tracks, 7);
+        assert_eq!(stats.total_duration, Duration::from_secs(400));
+        assert_eq!(stats.artists, 4);
+        assert_eq!(stats.albums, 4);
+        assert_eq!(stats.top_artists, [("Singer".to_string(), 5), ("Band".to_string(), 4)]);
+        assert_eq!(stats.top_albums, [("First".to_string(), "Band".to_string(), 4)]);
+        assert_eq!(
+            stats.formats,
+            [
+                FormatUsage { format: "FLAC".to_string(), tracks: 2, bytes: 50_000_000 },
+                FormatUsage { format: "MP3".to_string(), tracks: 5, bytes: 8_000_000 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// Named user profiles, each keeping its own playlists, WebDAV servers, history,
+// ratings and settings in a folder of its own. The list of profiles, the cover
+// cache, the encryption key and crash reports stay shared in the config dir.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+pub const DEFAULT_PROFILE_ID: &str = "default";
+const REGISTRY_FILE: &str = "user_profiles.json";
+const PROFILES_DIR: &str = "profiles";
+
+// The folder get_config_dir hands out, once a profile has been picked
+static ACTIVE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub id: String,
+    pub name: String,
+}
+
+impl UserProfile {
+    fn default_profile() -> Self {
+        UserProfile { id: DEFAULT_PROFILE_ID.to_string(), name: "Default".to_string() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRegistry {
+    #[serde(default)]
+    pub profiles: Vec<UserProfile>,
+    #[serde(default)]
+    pub last_used: Option<String>,
+    // Offer the list at startup instead of opening the last profile used
+    #[serde(default)]
+    pub ask_at_startup: bool,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        ProfileRegistry { profiles: vec![UserProfile::default_profile()], last_used: None, ask_at_startup: false }
+    }
+}
+
+impl ProfileRegistry {
+    pub fn load(config_dir: &Path) -> Self {
+        let mut registry = std::fs::read_to_string(config_dir.join(REGISTRY_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str::<ProfileRegistry>(&content).ok())
+            .unwrap_or_default();
+        // Whatever was there before profiles existed belongs to this one
+        if !registry.profiles.iter().any(|p| p.id == DEFAULT_PROFILE_ID) {
+            registry.profiles.insert(0, UserProfile::default_profile());
+        }
+        registry
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(config_dir.join(REGISTRY_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn last_used(&self) -> UserProfile {
+        self.last_used
+            .as_ref()
+            .and_then(|id| self.profiles.iter().find(|p| &p.id == id))
+            .or_else(|| self.profiles.first())
+            .cloned()
+            .unwrap_or_else(UserProfile::default_profile)
+    }
+
+    pub fn add(&mut self, name: &str) -> UserProfile {
+        let profile = UserProfile { id: uuid::Uuid::new_v4().to_string(), name: name.trim().to_string() };
+        self.profiles.push(profile.clone());
+        profile
+    }
+
+    /// Drop a profile from the list. Its folder is left on disk.
+    pub fn remove(&mut self, id: &str) {
+        if id != DEFAULT_PROFILE_ID {
+            self.profiles.retain(|p| p.id != id);
+        }
+    }
+}
+
+/// Where a profile keeps its files. The default profile uses the config dir
+/// itself, so a library from before profiles existed stays where it is.
+pub fn profile_dir(config_dir: &Path, profile: &UserProfile) -> PathBuf {
+    if profile.id == DEFAULT_PROFILE_ID {
+        config_dir.to_path_buf()
+    } else {
+        config_dir.join(PROFILES_DIR).join(&profile.id)
+    }
+}
+
+/// Make `profile`'s folder the one everything is loaded from and saved to.
+pub fn activate(config_dir: &Path, profile: &UserProfile) -> std::io::Result<()> {
+    let dir = profile_dir(config_dir, profile);
+    std::fs::create_dir_all(&dir)?;
+    eprintln!("[Config] 切换到用户 {}: {}", profile.name, dir.display());
+    *ACTIVE_DIR.write().unwrap() = Some(dir);
+    Ok(())
+}
+
+pub fn active_dir() -> Option<PathBuf> {
+    ACTIVE_DIR.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_get_their_own_folders() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_profiles_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut registry = ProfileRegistry::load(&dir);
+        assert_eq!(registry.profiles.len(), 1);
+        assert_eq!(profile_dir(&dir, &registry.last_used()), dir);
+
+        let kids = registry.add(" Kids ");
+        assert_eq!(kids.name, "Kids");
+        registry.last_used = Some(kids.id.clone());
+        registry.remove(DEFAULT_PROFILE_ID);
+        registry.save(&dir).unwrap();
+
+        let registry = ProfileRegistry::load(&dir);
+        assert_eq!(registry.profiles.len(), 2);
+        assert_eq!(registry.last_used(), kids);
+        assert_eq!(profile_dir(&dir, &kids), dir.join("profiles").join(&kids.id));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,123 @@
+// Where spoken-word tracks (podcasts, audiobooks) were left off, by path, so
+// playing one again carries on from there. Only playlists whose content type
+// resumes record anything; a track that plays to the end is forgotten.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const POSITIONS_FILE: &str = "resume_positions.json";
+// Closer to the start than this isn't worth coming back to
+const MIN_POSITION: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Positions {
+    by_path: HashMap<String, Duration>,
+    // Changed since the last save
+    dirty: bool,
+}
+
+static POSITIONS: Mutex<Option<Positions>> = Mutex::new(None);
+
+fn with_positions<T>(f: impl FnOnce(&mut Positions) -> T) -> T {
+    f(POSITIONS.lock().unwrap().get_or_insert_with(Positions::default))
+}
+
+/// Replace what's remembered with what `config_dir` has saved.
+pub fn load(config_dir: &Path) {
+    let by_path = std::fs::read_to_string(config_dir.join(POSITIONS_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    *POSITIONS.lock().unwrap() = Some(Positions { by_path, dirty: false });
+}
+
+/// Write the positions out if any changed since they were loaded or last saved.
+pub fn save(config_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    with_positions(|positions| {
+        if positions.dirty {
+            std::fs::write(config_dir.join(POSITIONS_FILE), serde_json::to_string(&positions.by_path)?)?;
+            positions.dirty = false;
+        }
+        Ok(())
+    })
+}
+
+pub fn remember(path: &str, position: Duration) {
+    if position < MIN_POSITION {
+        return forget(path);
+    }
+    let position = Duration::from_secs(position.as_secs());
+    with_positions(|positions| {
+        if positions.by_path.insert(path.to_string(), position) != Some(position) {
+            positions.dirty = true;
+        }
+    });
+}
+
+pub fn forget(path: &str) {
+    with_positions(|positions| {
+        if positions.by_path.remove(path).is_some() {
+            positions.dirty = true;
+        }
+    });
+}
+
+pub fn position(path: &str) -> Option<Duration> {
+    with_positions(|positions| positions.by_path.get(path).copied())
+}
+
+/// Follow files that moved, by old path to new, to where they are now.
+pub fn rekey(moved: &HashMap<String, String>) {
+    with_positions(|positions| {
+        // All taken out before any go back in, as one file's new path can be
+        // another's old one
+        let taken: Vec<(&String, Duration)> = moved
+            .iter()
+            .filter_map(|(from, to)| positions.by_path.remove(from).map(|position| (to, position)))
+            .collect();
+        if taken.is_empty() {
+            return;
+        }
+        positions.by_path.extend(taken.into_iter().map(|(to, position)| (to.clone(), position)));
+        positions.dirty = true;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_survive_a_reload() {
+        let dir = std::env::temp_dir().join(format!("dioxus_music_resume_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        load(&dir);
+
+        remember("/podcasts/ep1.mp3", Duration::from_millis(754_300));
+        remember("/podcasts/ep2.mp3", Duration::from_secs(2));
+        remember("/podcasts/ep3.mp3", Duration::from_secs(60));
+        forget("/podcasts/ep3.mp3");
+        save(&dir).unwrap();
+
+        load(&dir);
+        assert_eq!(position("/podcasts/ep1.mp3"), Some(Duration::from_secs(754)));
+        assert_eq!(position("/podcasts/ep2.mp3"), None);
+        assert_eq!(position("/podcasts/ep3.mp3"), None);
+
+        // Files moved by the organizer keep their place
+        remember("/podcasts/ep2.mp3", Duration::from_secs(90));
+        let moved = HashMap::from([
+            ("/podcasts/ep1.mp3".to_string(), "/podcasts/ep2.mp3".to_string()),
+            ("/podcasts/ep2.mp3".to_string(), "/library/ep2.mp3".to_string()),
+        ]);
+        rekey(&moved);
+        save(&dir).unwrap();
+        load(&dir);
+        assert_eq!(position("/podcasts/ep1.mp3"), None);
+        assert_eq!(position("/podcasts/ep2.mp3"), Some(Duration::from_secs(754)));
+        assert_eq!(position("/library/ep2.mp3"), Some(Duration::from_secs(90)));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
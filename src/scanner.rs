@@ -0,0 +1,198 @@
+// Reading a folder's tags on a pool of worker threads. Tracks are handed back
+// in folder order as soon as the ones before them are read, so a big folder
+// fills the playlist in while it's scanned instead of all at the end.
+
+use crate::{read_local_track, FileStamp, TrackStub, AUDIO_FORMATS};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use walkdir::WalkDir;
+
+// Past this, threads mostly wait on the same disk
+const MAX_WORKERS: usize = 8;
+
+pub enum ScanEvent {
+    // How many audio files the folder has, once it's been walked
+    Found(usize),
+    // The next tracks in folder order (possibly none), and how many files have been read
+    Tracks { tracks: Vec<TrackStub>, done: usize },
+    Finished,
+}
+
+/// The audio files under `dir`, in the order they're walked. Stops early once
+/// `cancelled` is set.
+pub fn audio_files(dir: &str, cancelled: &AtomicBool) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .take_while(|_| !cancelled.load(Ordering::Relaxed))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| e.into_path())
+        .filter(|path| {
+            path.extension().and_then(|e| e.to_str()).is_some_and(|ext| AUDIO_FORMATS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect()
+}
+
+// A file `known` has with the same size and modification time needn't be read again
+fn unchanged<'a>(path: &Path, known: &'a HashMap<String, TrackStub>) -> Option<&'a TrackStub> {
+    known
+        .get(path.to_string_lossy().as_ref())
+        .filter(|track| track.stamp.is_some() && track.stamp == FileStamp::of(path))
+}
+
+/// Read `files` on up to one thread per core, taking unchanged ones from
+/// `known`. `ready` is called as each file is read with whichever tracks are
+/// now complete in order, and the count read so far. Returns how many files
+/// had to be read rather than taken from `known`.
+pub fn read_tracks(
+    files: &[PathBuf],
+    known: &HashMap<String, TrackStub>,
+    cancelled: &AtomicBool,
+    mut ready: impl FnMut(Vec<TrackStub>, usize),
+) -> usize {
+    let next = AtomicUsize::new(0);
+    let reread = AtomicUsize::new(0);
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get()).clamp(1, MAX_WORKERS).min(files.len());
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let (sender, next, reread) = (sender.clone(), &next, &reread);
+            scope.spawn(move || {
+                // Folder covers are looked up once per folder by each worker
+                let mut covers = HashMap::new();
+                while !cancelled.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = files.get(index) else { break };
+                    let track = match unchanged(path, known) {
+                        Some(track) => track.clone(),
+                        None => {
+                            reread.fetch_add(1, Ordering::Relaxed);
+                            read_local_track(path, &mut covers)
+                        }
+                    };
+                    if sender.send((index, track)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Tracks read ahead of one still being read wait here for it
+        let mut waiting = BTreeMap::new();
+        let (mut handed_out, mut done) = (0, 0);
+        for (index, track) in receiver {
+            waiting.insert(index, track);
+            done += 1;
+            let mut tracks = Vec::new();
+            while let Some(track) = waiting.remove(&handed_out) {
+                tracks.push(track);
+                handed_out += 1;
+            }
+            ready(tracks, done);
+        }
+    });
+    crate::metadata_cache::flush();
+    reread.into_inner()
+}
+
+/// A folder scan running in the background; dropping it cancels the scan.
+pub struct LibraryScanner {
+    dir: String,
+    cancelled: Arc<AtomicBool>,
+    event_receiver: Mutex<Option<UnboundedReceiver<ScanEvent>>>,
+}
+
+impl LibraryScanner {
+    pub fn start(dir: String, known: HashMap<String, TrackStub>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (events, event_receiver) = unbounded_channel();
+
+        let stop = cancelled.clone();
+        let folder = dir.clone();
+        std::thread::spawn(move || {
+            let files = audio_files(&folder, &stop);
+            if events.send(ScanEvent::Found(files.len())).is_err() {
+                return;
+            }
+            let reread = read_tracks(&files, &known, &stop, |tracks, done| {
+                let _ = events.send(ScanEvent::Tracks { tracks, done });
+            });
+            if stop.load(Ordering::Relaxed) {
+                eprintln!("[Library] 已取消扫描 {}", folder);
+            } else {
+                eprintln!("[Library] 扫描 {}: {} 首曲目，读取了 {} 个新的或已更改的文件", folder, files.len(), reread);
+            }
+            let _ = events.send(ScanEvent::Finished);
+        });
+
+        LibraryScanner { dir, cancelled, event_receiver: Mutex::new(Some(event_receiver)) }
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    // Tracks already handed out stay; the rest of the folder isn't read
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    // Found, the tracks as they're read, then Finished; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<ScanEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+impl Drop for LibraryScanner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_reads_in_folder_order() {
+        let root = std::env::temp_dir().join(format!("dioxus_music_scan_{}", uuid::Uuid::new_v4()));
+        for disc in ["cd1", "cd2"] {
+            fs::create_dir_all(root.join(disc)).unwrap();
+            for n in 0..6 {
+                fs::write(root.join(disc).join(format!("{:02}.wav", n)), crate::test_support::wav_bytes(50, 8000)).unwrap();
+            }
+        }
+        fs::write(root.join("cd1").join("notes.txt"), "not audio").unwrap();
+
+        let dir = root.to_string_lossy().into_owned();
+        let files = audio_files(&dir, &AtomicBool::new(false));
+        assert_eq!(files.len(), 12);
+
+        let mut tracks = Vec::new();
+        let mut progress = Vec::new();
+        let reread = read_tracks(&files, &HashMap::new(), &AtomicBool::new(false), |ready, done| {
+            tracks.extend(ready);
+            progress.push(done);
+        });
+        assert_eq!(reread, 12);
+        assert_eq!(progress, (1..=12).collect::<Vec<_>>());
+        let paths: Vec<PathBuf> = tracks.iter().map(|t| PathBuf::from(&t.path)).collect();
+        assert_eq!(paths, files);
+
+        // Unchanged files come from what's known; a cancelled scan reads nothing
+        let known: HashMap<String, TrackStub> = tracks.into_iter().map(|t| (t.path.clone(), t)).collect();
+        assert_eq!(read_tracks(&files, &known, &AtomicBool::new(false), |_, _| {}), 0);
+        let mut handed_out = 0;
+        read_tracks(&files, &HashMap::new(), &AtomicBool::new(true), |ready, _| handed_out += ready.len());
+        assert_eq!(handed_out, 0);
+        assert!(audio_files(&dir, &AtomicBool::new(true)).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
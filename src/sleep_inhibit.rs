@@ -0,0 +1,128 @@
+// Keeps the computer from suspending on idle while music plays, using whatever
+// the platform offers. The screen may still blank; only system sleep is held off.
+
+use std::io;
+
+/// Holds off idle sleep for as long as it lives.
+pub struct SleepInhibitor {
+    // Only ever dropped; that's what releases it
+    _held: imp::Inhibit,
+}
+
+impl SleepInhibitor {
+    pub fn acquire() -> io::Result<Self> {
+        imp::acquire().map(|held| SleepInhibitor { _held: held })
+    }
+}
+
+// logind's idle inhibitor, held by a systemd-inhibit child for as long as it
+// runs. The command it wraps reads our stdin, so it also ends if we die.
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::io;
+    use std::process::{Child, Command, Stdio};
+
+    pub struct Inhibit(Child);
+
+    pub fn acquire() -> io::Result<Inhibit> {
+        Command::new("systemd-inhibit")
+            .args(["--what=idle", "--who=Dioxus Music", "--why=Playing music", "--mode=block", "cat"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(Inhibit)
+    }
+
+    impl Drop for Inhibit {
+        fn drop(&mut self) {
+            drop(self.0.stdin.take());
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+// caffeinate takes the IOKit "prevent idle sleep" assertion, and gives it up by
+// itself should this process exit without releasing it
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::io;
+    use std::process::{Child, Command, Stdio};
+
+    pub struct Inhibit(Child);
+
+    pub fn acquire() -> io::Result<Inhibit> {
+        Command::new("caffeinate")
+            .args(["-i", "-w", &std::process::id().to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(Inhibit)
+    }
+
+    impl Drop for Inhibit {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+// The execution state belongs to the thread that sets it, so a thread of our
+// own sets it and waits to be told to clear it
+#[cfg(windows)]
+mod imp {
+    use std::io;
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread::JoinHandle;
+
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    pub struct Inhibit {
+        release: Option<Sender<()>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    pub fn acquire() -> io::Result<Inhibit> {
+        let (release, released) = channel::<()>();
+        let (ready, is_ready) = channel();
+        let thread = std::thread::spawn(move || {
+            let held = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } != 0;
+            let _ = ready.send(held.then_some(()).ok_or_else(io::Error::last_os_error));
+            if held {
+                // Returns once the sender is dropped
+                let _ = released.recv();
+                unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+            }
+        });
+        is_ready.recv().unwrap_or_else(|_| Err(io::Error::other("inhibitor thread exited")))?;
+        Ok(Inhibit { release: Some(release), thread: Some(thread) })
+    }
+
+    impl Drop for Inhibit {
+        fn drop(&mut self) {
+            drop(self.release.take());
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod imp {
+    use std::io;
+
+    pub struct Inhibit;
+
+    pub fn acquire() -> io::Result<Inhibit> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "no sleep inhibitor on this platform"))
+    }
+}
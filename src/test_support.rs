@@ -0,0 +1,436 @@
+// Fixtures for tests that need a WebDAV server, a player without an audio device,
+// or tracks to fill playlists with.
+
+use crate::player::PlayerEvent;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rodio::queue::SourcesQueueOutput;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// A local track with just an id, path and title; tests set whatever else they
+/// look at with `TrackStub { .., ..track_stub(..) }`.
+pub fn track_stub(id: &str, path: &str, title: &str) -> crate::TrackStub {
+    crate::TrackStub {
+        id: id.to_string(),
+        path: path.to_string(),
+        title: title.to_string(),
+        artist: String::new(),
+        album: String::new(),
+        album_artist: None,
+        track_number: None,
+        year: None,
+        genre: None,
+        duration: Duration::ZERO,
+        cover: None,
+        source: crate::TrackSource::Local,
+        gain_db: 0.0,
+        added_at: None,
+        stamp: None,
+    }
+}
+
+/// A tiny in-process WebDAV server. Files live under `/dav/`; folders are implied
+/// by the file paths. Supports PROPFIND (Depth 1 or infinity), GET with `Range` /
+/// `If-Range`, and optional Basic or Bearer auth, with an OAuth2 token endpoint
+/// handing out the Bearer tokens, and can be told to fail for a while.
+pub struct MockWebDAV {
+    addr: std::net::SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    state: Arc<MockState>,
+}
+
+struct MockState {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+    credentials: Option<(String, String)>,
+    // (bytes, times): the next `times` GETs close the connection after `bytes` of body
+    cut_off: Mutex<(usize, usize)>,
+    // Answer Depth: infinity with 403, as most servers are set up to
+    finite_depth: AtomicBool,
+    // Requests left to answer with 503
+    failures: Mutex<usize>,
+    // Taken as `Authorization: Bearer` in place of the Basic credentials
+    bearer: Mutex<Option<String>>,
+    // The refresh token the token endpoint takes, how long its access tokens
+    // last, and how many it has handed out
+    oauth: Mutex<Option<(String, u64, usize)>>,
+}
+
+impl MockWebDAV {
+    pub fn start(files: &[(&str, Vec<u8>)], credentials: Option<(&str, &str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock WebDAV server");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(MockState {
+            files: Mutex::new(
+                files.iter().map(|(path, data)| (path.trim_start_matches('/').to_string(), data.clone())).collect(),
+            ),
+            credentials: credentials.map(|(u, p)| (u.to_string(), p.to_string())),
+            cut_off: Mutex::new((0, 0)),
+            finite_depth: AtomicBool::new(false),
+            failures: Mutex::new(0),
+            bearer: Mutex::new(None),
+            oauth: Mutex::new(None),
+        });
+
+        let log = requests.clone();
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = state.clone();
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    let _ = handle_connection(stream, &state, &log);
+                });
+            }
+        });
+
+        MockWebDAV { addr, requests, state: shared }
+    }
+
+    /// Server root as a user would enter it in the WebDAV config, e.g. `http://127.0.0.1:1234/dav/music`.
+    pub fn url(&self, root: &str) -> String {
+        format!("http://{}/dav/{}", self.addr, root.trim_matches('/'))
+    }
+
+    /// "METHOD path" for every request received so far, plus the `Range` header if sent.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Change (or add) a file on the server, as someone else editing it would.
+    pub fn put(&self, path: &str, data: Vec<u8>) {
+        self.state.files.lock().unwrap().insert(path.trim_start_matches('/').to_string(), data);
+    }
+
+    /// Delete a file from the server.
+    pub fn remove(&self, path: &str) {
+        self.state.files.lock().unwrap().remove(path.trim_start_matches('/'));
+    }
+
+    /// Drop the connection after `bytes` of the body for the next `times` GETs,
+    /// while still announcing the full length, like a flaky network would.
+    pub fn cut_off_after(&self, bytes: usize, times: usize) {
+        *self.state.cut_off.lock().unwrap() = (bytes, times);
+    }
+
+    /// Answer the next `times` requests with 503 Service Unavailable, like an overloaded server.
+    pub fn fail_next(&self, times: usize) {
+        *self.state.failures.lock().unwrap() = times;
+    }
+
+    /// Take `Authorization: Bearer <token>` from now on, instead of Basic auth or an earlier token.
+    pub fn accept_token(&self, token: &str) {
+        *self.state.bearer.lock().unwrap() = Some(token.to_string());
+    }
+
+    /// Answer refresh-token grants at `token_url()`: `refresh_token` gets access
+    /// token `access-1` (then taken by the server) lasting `expires_in` seconds,
+    /// and is swapped for `refresh-1`, which gets `access-2`, and so on.
+    pub fn issue_tokens(&self, refresh_token: &str, expires_in: u64) {
+        *self.state.oauth.lock().unwrap() = Some((refresh_token.to_string(), expires_in, 0));
+    }
+
+    pub fn token_url(&self) -> String {
+        format!("http://{}/oauth/token", self.addr)
+    }
+
+    /// Turn down PROPFINDs for a whole tree, so clients have to go a folder at a time.
+    pub fn refuse_infinite_depth(&self) {
+        self.state.finite_depth.store(true, Ordering::SeqCst);
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, state: &MockState, log: &Mutex<Vec<String>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let raw_path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let body_len: usize = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; body_len];
+    reader.read_exact(&mut body)?;
+
+    match headers.get("range") {
+        Some(range) => log.lock().unwrap().push(format!("{} {} {}", method, raw_path, range)),
+        None => log.lock().unwrap().push(format!("{} {}", method, raw_path)),
+    }
+
+    {
+        let mut failures = state.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            return respond(&mut stream, "503 Service Unavailable", &[], b"");
+        }
+    }
+
+    if method == "POST" && raw_path == "/oauth/token" {
+        return issue_token(&mut stream, state, &String::from_utf8_lossy(&body));
+    }
+
+    let expected = match (state.bearer.lock().unwrap().as_ref(), &state.credentials) {
+        (Some(token), _) => Some(format!("Bearer {}", token)),
+        (None, Some((user, pass))) => Some(format!("Basic {}", STANDARD.encode(format!("{}:{}", user, pass)))),
+        (None, None) => None,
+    };
+    if let Some(expected) = expected {
+        if headers.get("authorization") != Some(&expected) {
+            return respond(&mut stream, "401 Unauthorized", &[("WWW-Authenticate", "Basic realm=\"mock\"")], b"");
+        }
+    }
+
+    let path = urlencoding::decode(&raw_path).map(|p| p.into_owned()).unwrap_or(raw_path);
+    let Some(relative) = path.strip_prefix("/dav/").or_else(|| path.strip_prefix("/dav")) else {
+        return respond(&mut stream, "404 Not Found", &[], b"");
+    };
+    let relative = relative.trim_matches('/');
+
+    match method.as_str() {
+        "PROPFIND" if headers.get("depth").is_some_and(|d| d == "infinity") && state.finite_depth.load(Ordering::SeqCst) => {
+            respond(&mut stream, "403 Forbidden", &[], b"")
+        }
+        "PROPFIND" => match propfind(state, relative, headers.get("depth").is_some_and(|d| d == "infinity")) {
+            Some(xml) => respond(&mut stream, "207 Multi-Status", &[("Content-Type", "application/xml")], xml.as_bytes()),
+            None => respond(&mut stream, "404 Not Found", &[], b""),
+        },
+        "GET" => {
+            // Copied out, so a file can be changed while it is being sent
+            let file = state.files.lock().unwrap().get(relative).cloned();
+            match file {
+                Some(data) => {
+                    let etag = etag(&data);
+                    // A Range guarded by a stale If-Range gets the whole file instead
+                    let range = match headers.get("if-range") {
+                        Some(validator) if *validator != etag => None,
+                        _ => headers.get("range").and_then(|r| parse_range(r, data.len())),
+                    };
+                    let (status, content_range, body) = match range {
+                        Some((start, end)) => {
+                            ("206 Partial Content", Some(format!("bytes {}-{}/{}", start, end, data.len())), &data[start..=end])
+                        }
+                        None => ("200 OK", None, &data[..]),
+                    };
+                    let mut response_headers = vec![("Accept-Ranges", "bytes"), ("ETag", etag.as_str())];
+                    if let Some(content_range) = &content_range {
+                        response_headers.push(("Content-Range", content_range));
+                    }
+
+                    let sent = {
+                        let mut cut_off = state.cut_off.lock().unwrap();
+                        if cut_off.1 > 0 {
+                            cut_off.1 -= 1;
+                            cut_off.0.min(body.len())
+                        } else {
+                            body.len()
+                        }
+                    };
+                    write_response(&mut stream, status, &response_headers, body.len(), &body[..sent])
+                }
+                None => respond(&mut stream, "404 Not Found", &[], b""),
+            }
+        }
+        _ => respond(&mut stream, "405 Method Not Allowed", &[], b""),
+    }
+}
+
+// A refresh-token grant at the token endpoint
+fn issue_token(stream: &mut TcpStream, state: &MockState, form: &str) -> std::io::Result<()> {
+    let field = |name: &str| {
+        form.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| urlencoding::decode(value).map(|v| v.into_owned()).unwrap_or_default())
+    };
+    let mut oauth = state.oauth.lock().unwrap();
+    let Some((refresh_token, expires_in, issued)) = oauth.as_mut() else {
+        return respond(stream, "404 Not Found", &[], b"");
+    };
+    if field("grant_type").as_deref() != Some("refresh_token") || field("refresh_token").as_deref() != Some(refresh_token.as_str()) {
+        return respond(stream, "400 Bad Request", &[("Content-Type", "application/json")], br#"{"error":"invalid_grant"}"#);
+    }
+    *issued += 1;
+    let access_token = format!("access-{}", issued);
+    *refresh_token = format!("refresh-{}", issued);
+    *state.bearer.lock().unwrap() = Some(access_token.clone());
+    let json = format!(
+        r#"{{"access_token":"{}","token_type":"Bearer","expires_in":{},"refresh_token":"{}"}}"#,
+        access_token, expires_in, refresh_token
+    );
+    respond(stream, "200 OK", &[("Content-Type", "application/json")], json.as_bytes())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, &str)], body: &[u8]) -> std::io::Result<()> {
+    write_response(stream, status, headers, body.len(), body)
+}
+
+// `content_length` can be more than `body` to simulate a connection dropped mid-transfer
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    content_length: usize,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, content_length);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+// Changes whenever the file's content does, which is all If-Range needs
+fn etag(data: &[u8]) -> String {
+    let hash = data.iter().fold(0u32, |hash, byte| hash.wrapping_mul(31).wrapping_add(*byte as u32));
+    format!("\"{:x}-{:08x}\"", data.len(), hash)
+}
+
+// "bytes=a-b" / "bytes=a-" to an inclusive range within the file
+fn parse_range(header: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let end = if end.is_empty() { len.checked_sub(1)? } else { end.parse::<usize>().ok()?.min(len.checked_sub(1)?) };
+    (start <= end).then_some((start, end))
+}
+
+fn propfind(state: &MockState, dir: &str, infinite: bool) -> Option<String> {
+    let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+    let files = state.files.lock().unwrap();
+    let is_dir = dir.is_empty() || files.keys().any(|path| path.starts_with(&prefix));
+    if !is_dir {
+        return None;
+    }
+
+    let mut entries = vec![response_xml(dir, true, 0)];
+    let mut seen_dirs = Vec::new();
+    for (path, data) in files.range(prefix.clone()..) {
+        let Some(rest) = path.strip_prefix(&prefix) else { break };
+        // Folders on the way down to the file (all of them for the whole tree)
+        let folders: Vec<&str> = rest.match_indices('/').map(|(end, _)| &rest[..end]).collect();
+        let shown = if infinite { &folders[..] } else { &folders[..folders.len().min(1)] };
+        for folder in shown {
+            if !seen_dirs.contains(folder) {
+                seen_dirs.push(*folder);
+                entries.push(response_xml(&format!("{}{}", prefix, folder), true, 0));
+            }
+        }
+        if infinite || folders.is_empty() {
+            entries.push(response_xml(path, false, data.len()));
+        }
+    }
+
+    Some(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n<D:multistatus xmlns:D=\"DAV:\">\n{}</D:multistatus>\n",
+        entries.concat()
+    ))
+}
+
+fn response_xml(path: &str, is_dir: bool, size: usize) -> String {
+    let encoded: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| urlencoding::encode(s).into_owned())
+        .collect();
+    let mut href = format!("/dav/{}", encoded.join("/"));
+    if is_dir && !href.ends_with('/') {
+        href.push('/');
+    }
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let (resource_type, length) = if is_dir {
+        ("<D:collection/>".to_string(), String::new())
+    } else {
+        (String::new(), format!("<D:getcontentlength>{}</D:getcontentlength>", size))
+    };
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname><D:resourcetype>{}</D:resourcetype>{}<D:getlastmodified>Mon, 01 Jan 2024 00:00:00 GMT</D:getlastmodified></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+        href, name, resource_type, length
+    )
+}
+
+/// A mono 16-bit PCM WAV holding a 440Hz tone.
+pub fn wav_bytes(duration_ms: u32, sample_rate: u32) -> Vec<u8> {
+    let samples = sample_rate * duration_ms / 1000;
+    let data_len = samples * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for i in 0..samples {
+        let t = i as f32 / sample_rate as f32;
+        let sample = ((2.0 * std::f32::consts::PI * 440.0 * t).sin() * 8000.0) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// Pull samples out of a headless player as fast as possible until the current
+/// track ends. Returns every event seen, or None if the track didn't end in time.
+pub fn drain_until_ended(
+    output: &mut SourcesQueueOutput,
+    events: &mut UnboundedReceiver<PlayerEvent>,
+    timeout: Duration,
+) -> Option<Vec<PlayerEvent>> {
+    let deadline = Instant::now() + timeout;
+    let mut seen = Vec::new();
+    while Instant::now() < deadline {
+        // The queue keeps yielding silence when empty, so this never runs dry
+        output.by_ref().take(4096).for_each(drop);
+        while let Ok(event) = events.try_recv() {
+            let ended = matches!(event, PlayerEvent::TrackEnded { .. });
+            seen.push(event);
+            if ended {
+                return Some(seen);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_webdav_serves_ranges() {
+        let server = MockWebDAV::start(&[("music/a.wav", b"0123456789".to_vec())], None);
+        let url = format!("{}/a.wav", server.url("music"));
+        let client = reqwest::blocking::Client::new();
+
+        let partial = client.get(&url).header("Range", "bytes=2-5").send().unwrap();
+        assert_eq!(partial.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.bytes().unwrap().as_ref(), b"2345");
+
+        let tail = client.get(&url).header("Range", "bytes=7-").send().unwrap();
+        assert_eq!(tail.bytes().unwrap().as_ref(), b"789");
+
+        assert_eq!(client.get(&url).send().unwrap().bytes().unwrap().as_ref(), b"0123456789");
+        assert_eq!(server.requests().len(), 3);
+    }
+}
@@ -0,0 +1,122 @@
+// Download speed limits, one per WebDAV server, keyed by the server's URL so
+// anything fetching a file from it can find its limit. Everything fetched from a
+// server draws on the same allowance, so downloads running side by side (folder
+// sync, prefetching, a track downloading to play) stay under its limit together.
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Allowance left unused this long ago doesn't add up into a burst
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+static THROTTLES: Lazy<Mutex<HashMap<String, Arc<Throttle>>>> = Lazy::new(Default::default);
+
+pub struct Throttle {
+    // 0 for no limit
+    bytes_per_sec: AtomicU64,
+    // When everything read so far will have fitted in the limit
+    caught_up: Mutex<Instant>,
+}
+
+impl Throttle {
+    fn new() -> Self {
+        Throttle { bytes_per_sec: AtomicU64::new(0), caught_up: Mutex::new(Instant::now()) }
+    }
+
+    // Wait for as long as `bytes` more take at the limit
+    fn take(&self, bytes: usize) {
+        let rate = self.bytes_per_sec.load(Ordering::Relaxed);
+        if rate == 0 || bytes == 0 {
+            return;
+        }
+        let wait = {
+            let mut caught_up = self.caught_up.lock().unwrap();
+            let now = Instant::now();
+            let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+            *caught_up = (*caught_up).max(earliest) + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            caught_up.saturating_duration_since(now)
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// The throttle shared by everything downloaded from the server at `server` (its URL).
+pub fn for_server(server: &str) -> Arc<Throttle> {
+    // As URLs made from it spell it
+    let key = reqwest::Url::parse(server).map(String::from).unwrap_or_else(|_| server.to_string());
+    let key = key.trim_end_matches('/').to_string();
+    THROTTLES.lock().unwrap().entry(key).or_insert_with(|| Arc::new(Throttle::new())).clone()
+}
+
+/// The throttle of the server `url` is on, if it's one with a limit.
+pub fn for_url(url: &str) -> Option<Arc<Throttle>> {
+    let throttles = THROTTLES.lock().unwrap();
+    throttles
+        .iter()
+        .filter(|(server, throttle)| {
+            throttle.bytes_per_sec.load(Ordering::Relaxed) > 0
+                && url.strip_prefix(server.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        // A server inside another one's folders has the last word on its files
+        .max_by_key(|(server, _)| server.len())
+        .map(|(_, throttle)| throttle.clone())
+}
+
+/// Hold downloads from the server at `server` to `kbps` KB/s; 0 lifts the limit.
+pub fn set_limit(server: &str, kbps: u32) {
+    for_server(server).bytes_per_sec.store(u64::from(kbps) * 1024, Ordering::Relaxed);
+}
+
+/// A reader that keeps to a throttle's limit.
+pub struct Throttled<R> {
+    inner: R,
+    throttle: Arc<Throttle>,
+}
+
+impl<R> Throttled<R> {
+    pub fn new(inner: R, throttle: Arc<Throttle>) -> Self {
+        Throttled { inner, throttle }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.throttle.take(read);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_keep_to_the_limit() {
+        let server = format!("http://{}.test/dav/", uuid::Uuid::new_v4());
+        set_limit(&server, 100);
+        assert!(for_url(&format!("{}music/a.flac", server)).is_some());
+        assert!(for_url(&format!("{}-other/a.flac", server.trim_end_matches('/'))).is_none());
+        let data = vec![7u8; 60 * 1024];
+        let mut reader = Throttled::new(&data[..], for_server(&server));
+
+        // 60 KB at 100 KB/s
+        let started = Instant::now();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        assert!(started.elapsed() >= Duration::from_millis(450), "{:?}", started.elapsed());
+
+        set_limit(&server, 0);
+        assert!(for_url(&format!("{}music/a.flac", server)).is_none());
+        let started = Instant::now();
+        Throttled::new(&data[..], for_server(&server)).read_to_end(&mut Vec::new()).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -1,13 +1,63 @@
-use reqwest::Client;
+use rand::Rng;
+use reqwest::{Client, StatusCode};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 
+mod auth;
+mod propfind;
+
+pub use auth::{session as oauth2_session, Credentials, OAuth2Settings};
+
 #[derive(Clone, Debug)]
 pub struct WebDAVClient {
     client: Arc<Client>,
     base_url: String,
-    username: Option<String>,
-    password: Option<String>,
+    credentials: Option<Credentials>,
+    retry: RetryPolicy,
+}
+
+/// How PROPFIND and GET requests that fail for reasons that tend to pass
+/// (timeouts, refused or dropped connections, 5xx responses) are tried again.
+/// The wait doubles after each attempt, up to `max_delay`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    // Attempts in all, the first included; 1 never retries
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Share of each wait added or taken off at random, so clients that failed
+    // together don't all come back at once
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            jitter: 0.25,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // The wait after failed attempt number `attempt` (from 1)
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(self.max_delay);
+        let jitter = if self.jitter.is_finite() { self.jitter.clamp(0.0, 1.0) } else { 0.0 };
+        if jitter <= 0.0 {
+            return backoff;
+        }
+        backoff.mul_f64(1.0 + rand::thread_rng().gen_range(-jitter..=jitter))
+    }
+}
+
+// Failures worth another try: the server or the network, not the request
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -30,41 +80,154 @@ impl WebDAVClient {
                 .build()
                 .unwrap_or_else(|_| Client::new())),
             base_url: clean_url,
-            username: None,
-            password: None,
+            credentials: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // Run `attempt` (a request and reading its body) under the retry policy.
+    // A 5xx still standing after the last attempt is returned for the caller to
+    // report like any other status.
+    async fn retrying<T, F, Fut>(&self, what: &str, attempt: F) -> Result<(StatusCode, T), reqwest::Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(StatusCode, T), reqwest::Error>>,
+    {
+        let mut tries = 1;
+        let mut renewed = false;
+        loop {
+            let result = attempt().await;
+            let reason = match &result {
+                // An access token the server stopped taking before it was due
+                // to run out gets one fresh try, outside the retry count
+                Ok((StatusCode::UNAUTHORIZED, _)) if !renewed && self.credentials.as_ref().is_some_and(Credentials::renew) => {
+                    eprintln!("[WebDAV] {} 被拒绝 (HTTP 401)，刷新访问令牌后重试", what);
+                    renewed = true;
+                    continue;
+                }
+                Ok((status, _)) if status.is_server_error() => format!("HTTP {}", status),
+                Err(e) if is_transient(e) => e.to_string(),
+                _ => return result,
+            };
+            if tries >= self.retry.attempts {
+                return result;
+            }
+            let delay = self.retry.delay(tries);
+            eprintln!("[WebDAV] {} 失败 ({})，{} ms 后重试 ({}/{})", what, reason, delay.as_millis(), tries, self.retry.attempts - 1);
+            tokio::time::sleep(delay).await;
+            tries += 1;
         }
     }
 
     pub fn with_auth(mut self, username: String, password: String) -> Self {
-        self.username = Some(username);
-        self.password = Some(password);
+        self.credentials = Some(Credentials::basic(username, password));
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.credentials = credentials;
         self
     }
 
+    async fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.credentials {
+            Some(credentials) => credentials.authorize(request).await,
+            None => request,
+        }
+    }
+
     pub async fn list_files(&self, path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
         let url = format!("{}{}", self.base_url, path);
         
         // Use a generic request for PROPFIND since reqwest doesn't have propfind method
-        let mut req = self.client.request(reqwest::Method::from_bytes(b"PROPFIND").unwrap(), &url);
-        
-        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
-            eprintln!("[WebDAV-Client] 使用认证: user={}, pass_len={}", user, pass.len());
-            req = req.basic_auth(user.clone(), Some(pass.clone()));
+        if let Some(credentials) = &self.credentials {
+            eprintln!("[WebDAV-Client] 使用认证: {:?}", credentials);
         } else {
             eprintln!("[WebDAV-Client] 没有认证信息");
         }
 
         eprintln!("[WebDAV-Client] 发送PROPFIND请求到: {}", url);
-        let response = req.send().await?;
+        let url = url.as_str();
+        let (_, text) = self
+            .retrying("PROPFIND", || async move {
+                let req = self.client.request(reqwest::Method::from_bytes(b"PROPFIND").unwrap(), url);
+                let response = self.authorize(req).await.send().await?;
+                Ok::<_, reqwest::Error>((response.status(), response.text().await?))
+            })
+            .await?;
         
         // Parse WebDAV response (simplified - would need proper XML parsing)
-        let text: String = response.text().await?;
         let files = parse_webdav_response(&text);
         
         Ok(files)
     }
 
     pub async fn list_items(&self, path: &str) -> Result<Vec<WebDAVItem>, Box<dyn std::error::Error>> {
+        let (status, text) = self.propfind(path, "1").await?;
+        if !status.is_success() {
+            return Err(format!("WebDAV 请求失败 (HTTP {}): {}", status, text).into());
+        }
+        
+        if text.is_empty() {
+            return Err("WebDAV 服务器返回空响应".into());
+        }
+        
+        let items = parse_webdav_items(&text, &self.base_url);
+        
+        #[cfg(debug_assertions)]
+        {
+            eprintln!("[WebDAV] Parsed {} items", items.len());
+        }
+        
+        Ok(items)
+    }
+
+    /// Every file under `path`, however deep. Asks for the whole tree in one
+    /// PROPFIND (`Depth: infinity`) and, where the server refuses that as most
+    /// do by default, lists it a folder at a time. `keep_going` is told how many
+    /// files have been found after each listing; returning false stops the walk
+    /// with what's been found so far.
+    pub async fn list_tree(
+        &self,
+        path: &str,
+        mut keep_going: impl FnMut(usize) -> bool,
+    ) -> Result<Vec<WebDAVItem>, Box<dyn std::error::Error>> {
+        let (status, text) = self.propfind(path, "infinity").await?;
+        if status.is_success() && !text.is_empty() {
+            let files: Vec<WebDAVItem> = parse_webdav_items(&text, &self.base_url).into_iter().filter(|item| !item.is_dir).collect();
+            keep_going(files.len());
+            return Ok(files);
+        }
+        eprintln!("[WebDAV] 服务器不支持 Depth: infinity (HTTP {})，逐个文件夹列出", status);
+
+        let mut files = Vec::new();
+        let mut pending = vec![path.trim_matches('/').to_string()];
+        while let Some(dir) = pending.pop() {
+            for item in self.list_items(&dir).await? {
+                // The folder lists itself too
+                if item.path.trim_matches('/') == dir {
+                    continue;
+                }
+                if item.is_dir {
+                    pending.push(item.path.trim_matches('/').to_string());
+                } else {
+                    files.push(item);
+                }
+            }
+            if !keep_going(files.len()) {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    // PROPFIND `path` to `depth` ("1" or "infinity"): the status and the body
+    async fn propfind(&self, path: &str, depth: &str) -> Result<(reqwest::StatusCode, String), Box<dyn std::error::Error>> {
         let normalized_path = if !path.starts_with('/') {
             format!("/{}", path)
         } else {
@@ -83,19 +246,18 @@ impl WebDAVClient {
   </D:prop>
 </D:propfind>"#;
         
-        let mut req = self.client.request(reqwest::Method::from_bytes(b"PROPFIND").unwrap(), &url);
-        req = req.header("Depth", "1");
-        req = req.header("Content-Type", "application/xml; charset=\"utf-8\"");
-        req = req.body(propfind_body.to_string());
-        
-        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
-            req = req.basic_auth(user.clone(), Some(pass.clone()));
-        }
+        let url = url.as_str();
+        let (status, text) = self
+            .retrying("PROPFIND", || async move {
+                let mut req = self.client.request(reqwest::Method::from_bytes(b"PROPFIND").unwrap(), url);
+                req = req.header("Depth", depth);
+                req = req.header("Content-Type", "application/xml; charset=\"utf-8\"");
+                req = req.body(propfind_body.to_string());
 
-        let response = req.send().await?;
-        
-        let status = response.status();
-        let text: String = response.text().await?;
+                let response = self.authorize(req).await.send().await?;
+                Ok::<_, reqwest::Error>((response.status(), response.text().await?))
+            })
+            .await?;
         
         // 调试：打印响应状态和内容（如果是开发环境）
         #[cfg(debug_assertions)]
@@ -108,22 +270,7 @@ impl WebDAVClient {
             }
         }
         
-        if !status.is_success() {
-            return Err(format!("WebDAV 请求失败 (HTTP {}): {}", status, text).into());
-        }
-        
-        if text.is_empty() {
-            return Err("WebDAV 服务器返回空响应".into());
-        }
-        
-        let items = parse_webdav_items(&text, &self.base_url);
-        
-        #[cfg(debug_assertions)]
-        {
-            eprintln!("[WebDAV] Parsed {} items", items.len());
-        }
-        
-        Ok(items)
+        Ok((status, text))
     }
 
     pub async fn download_file(
@@ -133,15 +280,17 @@ impl WebDAVClient {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let url = format!("{}{}", self.base_url, path);
         
-        let mut req = self.client.get(&url);
-        
-        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
-            req = req.basic_auth(user.clone(), Some(pass.clone()));
+        let url = url.as_str();
+        let (status, bytes) = self
+            .retrying("GET", || async move {
+                let response = self.authorize(self.client.get(url)).await.send().await?;
+                Ok::<_, reqwest::Error>((response.status(), response.bytes().await?))
+            })
+            .await?;
+        if !status.is_success() {
+            return Err(format!("下载失败 (HTTP {})", status).into());
         }
 
-        let response = req.send().await?;
-        let bytes = response.bytes().await?;
-        
         tokio::fs::write(dest, bytes).await?;
         Ok(())
     }
@@ -154,118 +303,33 @@ impl WebDAVClient {
         let url = format!("{}{}", self.base_url, dest);
         let bytes = tokio::fs::read(src).await?;
         
-        let mut req = self.client.put(&url)
+        let req = self.client.put(&url)
             .body(bytes);
-        
-        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
-            req = req.basic_auth(user.clone(), Some(pass.clone()));
-        }
 
-        req.send().await?;
+        self.authorize(req).await.send().await?;
         Ok(())
     }
 }
 
 #[allow(dead_code)]
 fn parse_webdav_response(response: &str) -> Vec<String> {
-    // Simple parsing - in production use proper XML parser
-    let mut files = Vec::new();
-    
-    for line in response.lines() {
-        if line.contains("<D:href>") {
-            let start = line.find("<D:href>").map(|i| i + 8).unwrap_or(0);
-            let end = line.find("</D:href>").unwrap_or(line.len());
-            let href = &line[start..end];
-            
-            if !href.is_empty() && !href.ends_with('/') {
-                files.push(href.to_string());
-            }
-        }
-    }
-    
-    files
+    propfind::parse_multistatus(response)
+        .into_iter()
+        .filter(|entry| !entry.is_collection && !entry.href.ends_with('/'))
+        .map(|entry| entry.href)
+        .collect()
 }
 
 fn parse_webdav_items(response: &str, base_url: &str) -> Vec<WebDAVItem> {
     let mut items = Vec::new();
-    
-    let responses = response
-        .split("<D:response")
-        .skip(1)
-        .collect::<Vec<&str>>();
-    
-    for response_part in responses {
-        if !response_part.contains("</D:response>") {
-            continue;
-        }
-        
-        let response_end = match response_part.find("</D:response>") {
-            Some(pos) => pos,
-            None => continue,
-        };
-        
-        let clean_part = &response_part[..response_end];
-        
-        let mut href = String::new();
-        let mut displayname = String::new();
-        let mut is_collection = false;
-        let mut size = 0u64;
-        let mut modified = String::new();
-        
-        // 解析href - 查找第一个<D:href>...</D:href>
-        if let Some(href_start) = clean_part.find("<D:href>") {
-            let content_start = href_start + 8;
-            if let Some(href_end) = clean_part[content_start..].find("</D:href>") {
-                href = clean_part[content_start..content_start + href_end].to_string();
-            }
-        }
-        
-        // 解析displayname
-        if let Some(name_start) = clean_part.find("<D:displayname>") {
-            let content_start = name_start + 15;
-            if let Some(name_end) = clean_part[content_start..].find("</D:displayname>") {
-                displayname = clean_part[content_start..content_start + name_end].to_string();
-            }
-        }
-        
-        // 解析resourcetype - 检查是否是collection
-        // 格式可能是 <D:collection/> 或 <D:collection xmlns:D="DAV:"/>
-        if clean_part.contains("<D:collection") {
-            is_collection = true;
-        }
-        
-        // 解析getcontentlength - 需要在<D:propstat>中找到正确的值
-        // 跳过404的部分（404表示该属性不存在）
-        if let Some(prop_start) = clean_part.find("<D:propstat>") {
-            let prop_section = &clean_part[prop_start..];
-            if let Some(first_prop) = prop_section.find("<D:prop>") {
-                let after_first_prop = &prop_section[first_prop + 8..];
-                if let Some(first_prop_end) = after_first_prop.find("</D:prop>") {
-                    let first_prop_content = &after_first_prop[..first_prop_end];
-                    if !first_prop_content.contains("<D:getcontentlength>") 
-                        || first_prop_content.contains("<D:collection") {
-                        if let Some(size_start) = first_prop_content.find("<D:getcontentlength>") {
-                            let size_content_start = size_start + 20;
-                            if let Some(size_end) = first_prop_content[size_content_start..].find("</D:getcontentlength>") {
-                                let size_str = &first_prop_content[size_content_start..size_content_start + size_end];
-                                if !size_str.is_empty() {
-                                    size = size_str.parse().unwrap_or(0);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        // 解析getlastmodified
-        if let Some(mod_start) = clean_part.find("<D:getlastmodified>") {
-            let content_start = mod_start + 19;
-            if let Some(mod_end) = clean_part[content_start..].find("</D:getlastmodified>") {
-                modified = clean_part[content_start..content_start + mod_end].to_string();
-            }
-        }
-        
+
+    for entry in propfind::parse_multistatus(response) {
+        let href = entry.href;
+        let displayname = entry.display_name.unwrap_or_default();
+        let is_collection = entry.is_collection;
+        let size = entry.content_length.unwrap_or(0);
+        let modified = entry.last_modified.unwrap_or_default();
+
         // 提取名称
         let name = if !displayname.is_empty() {
             displayname
@@ -333,34 +397,6 @@ fn parse_webdav_items(response: &str, base_url: &str) -> Vec<WebDAVItem> {
     items
 }
 
-#[allow(dead_code)]
-fn extract_xml_content(xml: &str, tag: &str) -> Option<String> {
-    let tag_upper = tag.to_uppercase();
-    let tag_lower = tag.to_lowercase();
-    
-    let search_patterns = vec![
-        format!("<D:{}>", tag_upper),
-        format!("<d:{}>", tag_lower),
-        format!("<D:{}>", tag_lower),
-        format!("<{}>", tag),
-    ];
-    
-    for pattern in search_patterns {
-        let start_tag = &pattern;
-        let end_tag = format!("</{}>", tag_upper);
-        
-        if let Some(start_idx) = xml.find(start_tag) {
-            let content_start = start_idx + start_tag.len();
-            if let Some(end_idx) = xml[content_start..].find(&end_tag) {
-                let full_end = content_start + end_idx;
-                return Some(xml[content_start..full_end].to_string());
-            }
-        }
-    }
-    
-    None
-}
-
 fn extract_name_from_path(path: &str) -> String {
     if path.is_empty() || path == "/" {
         return String::new();
@@ -399,4 +435,142 @@ mod tests {
         let files = parse_webdav_response(response);
         assert!(files.contains(&"/music/song1.mp3".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_list_items_from_mock_server() {
+        use crate::test_support::MockWebDAV;
+
+        let server = MockWebDAV::start(
+            &[
+                ("music/Album/01 Intro.flac", vec![0; 10]),
+                ("music/Café.mp3", vec![0; 42]),
+            ],
+            Some(("alice", "secret")),
+        );
+
+        let client = WebDAVClient::new(server.url("music")).with_auth("alice".to_string(), "secret".to_string());
+        let items = client.list_items("").await.unwrap();
+        let song = items.iter().find(|i| i.name == "Café.mp3").expect("file listed");
+        assert!(!song.is_dir);
+        assert_eq!(song.size, 42);
+        assert!(items.iter().any(|i| i.name == "Album" && i.is_dir));
+
+        let nested = client.list_items("Album").await.unwrap();
+        assert!(nested.iter().any(|i| i.name == "01 Intro.flac"));
+
+        let wrong = WebDAVClient::new(server.url("music")).with_auth("alice".to_string(), "nope".to_string());
+        assert!(wrong.list_items("").await.is_err());
+        assert!(WebDAVClient::new(server.url("music")).list_items("").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lists_a_folder_with_subfolders() {
+        use crate::test_support::MockWebDAV;
+
+        let server = MockWebDAV::start(
+            &[
+                ("music/Album/01 Intro.flac", vec![0; 10]),
+                ("music/Album/Disc 2/01 Outro.flac", vec![0; 10]),
+                ("music/Single.mp3", vec![0; 10]),
+            ],
+            None,
+        );
+        let client = WebDAVClient::new(server.url("music"));
+        let names = |files: Vec<WebDAVItem>| {
+            let mut names: Vec<String> = files.into_iter().map(|f| f.name).collect();
+            names.sort();
+            names
+        };
+        let expected = vec!["01 Intro.flac".to_string(), "01 Outro.flac".to_string()];
+        assert_eq!(names(client.list_tree("Album", |_| true).await.unwrap()), expected);
+
+        // A folder at a time, skipping each folder's own entry
+        server.refuse_infinite_depth();
+        assert_eq!(names(client.list_tree("Album", |_| true).await.unwrap()), expected);
+        assert_eq!(names(client.list_tree("/Album/", |_| true).await.unwrap()), expected);
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        use crate::test_support::MockWebDAV;
+
+        let server = MockWebDAV::start(&[("music/a.mp3", vec![7; 42])], None);
+        let policy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(10), max_delay: Duration::from_millis(20), jitter: 0.5 };
+        let client = WebDAVClient::new(server.url("music")).with_retry(policy.clone());
+        let propfinds = || server.requests().iter().filter(|r| r.starts_with("PROPFIND")).count();
+
+        // Two 503s, then it answers
+        server.fail_next(2);
+        assert!(client.list_items("").await.unwrap().iter().any(|i| i.name == "a.mp3"));
+        assert_eq!(propfinds(), 3);
+
+        // Out of attempts, the last error stands
+        server.fail_next(3);
+        let error = client.list_items("").await.unwrap_err().to_string();
+        assert!(error.contains("503"), "{}", error);
+        assert_eq!(propfinds(), 6);
+
+        let dest = std::env::temp_dir().join(format!("dioxus_music_retry_{}.mp3", uuid::Uuid::new_v4()));
+        server.fail_next(1);
+        client.download_file("/a.mp3", dest.to_str().unwrap()).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), vec![7; 42]);
+        let _ = std::fs::remove_file(&dest);
+
+        // Nothing to retry in a 404, or with retries turned off
+        assert!(client.download_file("/missing.mp3", dest.to_str().unwrap()).await.is_err());
+        server.fail_next(1);
+        assert!(client.clone().with_retry(RetryPolicy { attempts: 1, ..policy }).list_items("").await.is_err());
+        let gets = server.requests().iter().filter(|r| r.starts_with("GET")).count();
+        assert_eq!((gets, propfinds()), (3, 7));
+    }
+
+    #[tokio::test]
+    async fn test_signs_requests_with_tokens() {
+        use crate::test_support::MockWebDAV;
+
+        let server = MockWebDAV::start(&[("music/a.mp3", vec![7; 42])], None);
+        server.accept_token("fixed");
+        let bearer = |token: &str| WebDAVClient::new(server.url("music")).with_credentials(Some(Credentials::Bearer(token.to_string())));
+        assert!(bearer("fixed").list_items("").await.is_ok());
+        assert!(bearer("wrong").list_items("").await.is_err());
+
+        // The refresh token is traded for an access token once, which is then reused
+        server.issue_tokens("refresh-0", 3600);
+        let settings = OAuth2Settings {
+            token_url: server.token_url(),
+            client_id: "player".to_string(),
+            client_secret: String::new(),
+            refresh_token: "refresh-0".to_string(),
+        };
+        let session = oauth2_session(&uuid::Uuid::new_v4().to_string(), settings.clone());
+        let client = WebDAVClient::new(server.url("music")).with_credentials(Some(Credentials::OAuth2(session)));
+        assert!(client.list_items("").await.is_ok());
+        assert!(client.list_items("").await.is_ok());
+        let refreshes = || server.requests().iter().filter(|r| r.starts_with("POST /oauth/token")).count();
+        assert_eq!(refreshes(), 1);
+
+        // Revoked early: the 401 gets a fresh token, from the rotated refresh token
+        server.accept_token("revoked");
+        let dest = std::env::temp_dir().join(format!("dioxus_music_oauth_{}.mp3", uuid::Uuid::new_v4()));
+        client.download_file("/a.mp3", dest.to_str().unwrap()).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), vec![7; 42]);
+        let _ = std::fs::remove_file(&dest);
+        assert_eq!(refreshes(), 2);
+
+        // A spent refresh token is turned down, and so is everything signed without a token
+        let stale = oauth2_session(&uuid::Uuid::new_v4().to_string(), settings);
+        let client = WebDAVClient::new(server.url("music")).with_credentials(Some(Credentials::OAuth2(stale.clone())));
+        assert!(client.list_items("").await.is_err());
+        let refused = tokio::task::spawn_blocking(move || stale.access_token()).await.unwrap();
+        assert!(refused.unwrap_err().contains("invalid_grant"));
+    }
+
+    #[test]
+    fn test_retry_delays_back_off() {
+        let policy = RetryPolicy { jitter: 0.0, ..Default::default() };
+        let delays: Vec<u64> = (1..=6).map(|attempt| policy.delay(attempt).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 8000]);
+        let jittered = RetryPolicy::default().delay(2);
+        assert!(jittered >= Duration::from_millis(750) && jittered <= Duration::from_millis(1250));
+    }
 }
@@ -0,0 +1,267 @@
+// How requests to a WebDAV server say who's asking: a username and password
+// (Basic), a fixed token (Bearer), or OAuth2, where a refresh token is traded at
+// the provider's token endpoint for access tokens that run out after a while.
+//
+// An OAuth2 server's session is shared by everything talking to it (browsing,
+// playback downloads, prefetching, sync), so an access token is refreshed once
+// for all of them. Providers that hand out a new refresh token with each access
+// token get it saved, encrypted, so the next start doesn't use a spent one.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Access tokens are refreshed this long before they run out, so one doesn't
+// expire on the way to the server
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+// For token endpoints that don't say how long their tokens last
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(20);
+
+static SESSIONS: Lazy<Mutex<HashMap<String, Arc<OAuth2Session>>>> = Lazy::new(Default::default);
+
+static TOKEN_STORE: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    // Tests don't keep tokens around
+    if cfg!(test) {
+        return None;
+    }
+    crate::base_config_dir().ok().map(|dir| dir.join("oauth_tokens.json"))
+});
+
+#[derive(Clone)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+    OAuth2(Arc<OAuth2Session>),
+}
+
+// Without the secrets, as these end up in logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Basic { username, .. } => write!(f, "Basic({})", username),
+            Credentials::Bearer(_) => write!(f, "Bearer"),
+            Credentials::OAuth2(session) => write!(f, "OAuth2({})", session.settings.token_url),
+        }
+    }
+}
+
+impl Credentials {
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials::Basic { username: username.into(), password: password.into() }
+    }
+
+    /// Sign `request`, refreshing an OAuth2 access token first if it has run out.
+    pub async fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            Credentials::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Credentials::Bearer(token) => request.bearer_auth(token),
+            Credentials::OAuth2(session) => {
+                let token = match session.current() {
+                    Some(token) => Ok(token),
+                    None => {
+                        let session = session.clone();
+                        tokio::task::spawn_blocking(move || session.access_token())
+                            .await
+                            .unwrap_or_else(|e| Err(e.to_string()))
+                    }
+                };
+                match token {
+                    Ok(token) => request.bearer_auth(token),
+                    // Sent unsigned, the server's 401 says the rest
+                    Err(e) => {
+                        eprintln!("[OAuth2] 获取访问令牌失败: {}", e);
+                        request
+                    }
+                }
+            }
+        }
+    }
+
+    /// `authorize` for blocking requests; blocks while a token is refreshed.
+    pub fn authorize_blocking(&self, request: reqwest::blocking::RequestBuilder) -> reqwest::blocking::RequestBuilder {
+        match self {
+            Credentials::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Credentials::Bearer(token) => request.bearer_auth(token),
+            Credentials::OAuth2(session) => match session.access_token() {
+                Ok(token) => request.bearer_auth(token),
+                Err(e) => {
+                    eprintln!("[OAuth2] 获取访问令牌失败: {}", e);
+                    request
+                }
+            },
+        }
+    }
+
+    /// After a 401: drop an OAuth2 access token the server no longer takes, so
+    /// the next request gets a fresh one. False when there's nothing new to try.
+    pub fn renew(&self) -> bool {
+        match self {
+            Credentials::OAuth2(session) => session.tokens.lock().unwrap().access.take().is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// Where and how to refresh a server's access tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OAuth2Settings {
+    pub token_url: String,
+    pub client_id: String,
+    // Empty for public clients
+    pub client_secret: String,
+    // As entered; may have been replaced by a newer one since
+    pub refresh_token: String,
+}
+
+pub struct OAuth2Session {
+    id: String,
+    settings: OAuth2Settings,
+    // Tells a refresh token saved for the one entered from one saved for an
+    // earlier one, which is stale
+    seed: String,
+    tokens: Mutex<Tokens>,
+    // Held while refreshing, so requests that all find the token run out only
+    // refresh it once
+    refreshing: Mutex<()>,
+}
+
+struct Tokens {
+    // The token and when to stop using it
+    access: Option<(String, Instant)>,
+    refresh: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    seed: String,
+    // Encrypted
+    refresh_token: String,
+}
+
+/// The session of the server with config id `id`. Kept while its settings stay
+/// the same; entering a new refresh token (or endpoint) starts over.
+pub fn session(id: &str, settings: OAuth2Settings) -> Arc<OAuth2Session> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get(id).filter(|s| s.settings == settings) {
+        return session.clone();
+    }
+    let seed = format!("{:x}", Sha256::digest(settings.refresh_token.as_bytes()));
+    let refresh = stored_refresh_token(id, &seed).unwrap_or_else(|| settings.refresh_token.clone());
+    let session = Arc::new(OAuth2Session {
+        id: id.to_string(),
+        settings,
+        seed,
+        tokens: Mutex::new(Tokens { access: None, refresh }),
+        refreshing: Mutex::new(()),
+    });
+    sessions.insert(id.to_string(), session.clone());
+    session
+}
+
+impl OAuth2Session {
+    // The access token, if there is one that's still good
+    fn current(&self) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap();
+        tokens.access.as_ref().filter(|(_, until)| Instant::now() < *until).map(|(token, _)| token.clone())
+    }
+
+    /// A good access token, refreshed at the token endpoint if need be. Blocks while refreshing.
+    pub fn access_token(&self) -> Result<String, String> {
+        if let Some(token) = self.current() {
+            return Ok(token);
+        }
+        let _refreshing = self.refreshing.lock().unwrap();
+        // Someone else refreshed it while we waited
+        if let Some(token) = self.current() {
+            return Ok(token);
+        }
+
+        let refresh_token = self.tokens.lock().unwrap().refresh.clone();
+        let response = request_token(&self.settings, &refresh_token)?;
+        let expires_in = response.expires_in.map(Duration::from_secs).unwrap_or(DEFAULT_LIFETIME);
+        let lifetime = if expires_in > EXPIRY_MARGIN * 2 { expires_in - EXPIRY_MARGIN } else { expires_in / 2 };
+        eprintln!("[OAuth2] 已刷新访问令牌: {} ({} 秒有效)", self.settings.token_url, expires_in.as_secs());
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.access = Some((response.access_token.clone(), Instant::now() + lifetime));
+        if let Some(rotated) = response.refresh_token.filter(|token| !token.is_empty() && *token != tokens.refresh) {
+            tokens.refresh = rotated.clone();
+            drop(tokens);
+            if let Err(e) = store_refresh_token(&self.id, &self.seed, &rotated) {
+                eprintln!("[OAuth2] 保存刷新令牌失败: {}", e);
+            }
+        }
+        Ok(response.access_token)
+    }
+}
+
+fn request_token(settings: &OAuth2Settings, refresh_token: &str) -> Result<TokenResponse, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(TOKEN_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", settings.client_id.as_str()),
+    ];
+    if !settings.client_secret.is_empty() {
+        form.push(("client_secret", settings.client_secret.as_str()));
+    }
+
+    let response = client
+        .post(&settings.token_url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .form(&form)
+        .send()
+        .map_err(|e| format!("Couldn't reach the token server: {}", e))?;
+    let status = response.status();
+    let body = response.bytes().map_err(|e| format!("Couldn't reach the token server: {}", e))?;
+    if !status.is_success() {
+        // invalid_grant when the refresh token was revoked or has expired
+        let reason = serde_json::from_slice::<TokenError>(&body).map(|e| e.error).unwrap_or_else(|_| status.to_string());
+        return Err(format!("The token server turned down the refresh token ({})", reason));
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("Unexpected answer from the token server: {}", e))
+}
+
+fn load_store() -> HashMap<String, StoredToken> {
+    TOKEN_STORE
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn stored_refresh_token(id: &str, seed: &str) -> Option<String> {
+    let stored = load_store().remove(id).filter(|stored| stored.seed == seed)?;
+    let master_password = crate::crypto::get_master_password().ok()?;
+    crate::crypto::decrypt_password(&stored.refresh_token, &master_password).ok()
+}
+
+fn store_refresh_token(id: &str, seed: &str, refresh_token: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = TOKEN_STORE.as_ref() else { return Ok(()) };
+    let mut store = load_store();
+    let encrypted = crate::crypto::encrypt_password(refresh_token, &crate::crypto::get_master_password()?)?;
+    store.insert(id.to_string(), StoredToken { seed: seed.to_string(), refresh_token: encrypted });
+    std::fs::write(path, serde_json::to_string_pretty(&store)?)?;
+    Ok(())
+}
@@ -0,0 +1,319 @@
+// PROPFIND multistatus parsing. Kept free of other crate modules so the fuzz
+// targets can include this file directly.
+//
+// Servers disagree on namespace prefixes (`D:`, `d:`, `lp1:`, none), whitespace,
+// attribute placement and entity escaping, so this is a forgiving tag scanner
+// rather than a validating XML parser. Element names are matched by local name,
+// case-insensitively.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PropfindEntry {
+    pub href: String,
+    pub display_name: Option<String>,
+    pub is_collection: bool,
+    pub content_length: Option<u64>,
+    pub last_modified: Option<String>,
+}
+
+// Properties collected from one <propstat>, only kept if its status is 2xx
+#[derive(Default)]
+struct PropstatProps {
+    display_name: Option<String>,
+    is_collection: bool,
+    content_length: Option<u64>,
+    last_modified: Option<String>,
+    status_ok: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Href,
+    DisplayName,
+    ContentLength,
+    LastModified,
+    Status,
+}
+
+/// Every `<response>` in a multistatus body, in document order. Never panics;
+/// responses without an href are dropped.
+pub fn parse_multistatus(xml: &str) -> Vec<PropfindEntry> {
+    let xml = xml.trim_start_matches('\u{feff}');
+    let mut entries = Vec::new();
+
+    let mut entry: Option<PropfindEntry> = None;
+    let mut propstat: Option<PropstatProps> = None;
+    let mut field: Option<Field> = None;
+    let mut text = String::new();
+
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        if field.is_some() {
+            text.push_str(&rest[..open]);
+        }
+        rest = &rest[open..];
+
+        // Comments, processing instructions and CDATA
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").unwrap_or(after.len());
+            if field.is_some() {
+                // Marked so entity decoding leaves it alone
+                text.push_str(&after[..end].replace('&', "&amp;"));
+            }
+            rest = after.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let Some(close) = rest.find('>') else { break };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let name = local_name(tag.trim_start_matches('/').trim_end_matches('/'));
+
+        if closing {
+            if let Some(current) = field {
+                if field_for(&name) == Some(current) {
+                    let value = decode_entities(text.trim());
+                    store(current, value, entry.as_mut(), propstat.as_mut());
+                    field = None;
+                    text.clear();
+                }
+                continue;
+            }
+            match name.as_str() {
+                "propstat" => {
+                    if let (Some(props), Some(entry)) = (propstat.take(), entry.as_mut()) {
+                        if props.status_ok {
+                            entry.display_name = entry.display_name.take().or(props.display_name);
+                            entry.is_collection |= props.is_collection;
+                            entry.content_length = entry.content_length.or(props.content_length);
+                            entry.last_modified = entry.last_modified.take().or(props.last_modified);
+                        }
+                    }
+                }
+                "response" => {
+                    if let Some(entry) = entry.take() {
+                        if !entry.href.is_empty() {
+                            entries.push(entry);
+                        }
+                    }
+                    propstat = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if field.is_some() {
+            // Markup inside a text field, e.g. a stray <br/>; ignore it
+            continue;
+        }
+
+        match name.as_str() {
+            "response" => {
+                entry = Some(PropfindEntry::default());
+                propstat = None;
+            }
+            // A propstat without a <status> is taken as successful
+            "propstat" if !self_closing => {
+                propstat = Some(PropstatProps {
+                    status_ok: true,
+                    ..Default::default()
+                })
+            }
+            "collection" => {
+                if let Some(props) = propstat.as_mut() {
+                    props.is_collection = true;
+                } else if let Some(entry) = entry.as_mut() {
+                    entry.is_collection = true;
+                }
+            }
+            _ => {
+                if let Some(found) = field_for(&name) {
+                    if entry.is_some() && !self_closing {
+                        field = Some(found);
+                        text.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+fn field_for(name: &str) -> Option<Field> {
+    match name {
+        "href" => Some(Field::Href),
+        "displayname" => Some(Field::DisplayName),
+        "getcontentlength" => Some(Field::ContentLength),
+        "getlastmodified" => Some(Field::LastModified),
+        "status" => Some(Field::Status),
+        _ => None,
+    }
+}
+
+fn store(field: Field, value: String, entry: Option<&mut PropfindEntry>, propstat: Option<&mut PropstatProps>) {
+    let Some(entry) = entry else { return };
+    match (field, propstat) {
+        (Field::Href, _) => {
+            if entry.href.is_empty() {
+                entry.href = value;
+            }
+        }
+        (Field::Status, Some(props)) => props.status_ok = status_is_success(&value),
+        // A response-level status applies to the whole response
+        (Field::Status, None) => {
+            if !status_is_success(&value) {
+                entry.href.clear();
+            }
+        }
+        (Field::DisplayName, Some(props)) => props.display_name = Some(value).filter(|v| !v.is_empty()),
+        (Field::ContentLength, Some(props)) => props.content_length = value.parse().ok(),
+        (Field::LastModified, Some(props)) => props.last_modified = Some(value).filter(|v| !v.is_empty()),
+        // Properties outside a propstat are not valid DAV, but some servers send them
+        (Field::DisplayName, None) => entry.display_name = Some(value).filter(|v| !v.is_empty()),
+        (Field::ContentLength, None) => entry.content_length = value.parse().ok(),
+        (Field::LastModified, None) => entry.last_modified = Some(value).filter(|v| !v.is_empty()),
+    }
+}
+
+// "HTTP/1.1 200 OK" -> true
+fn status_is_success(status: &str) -> bool {
+    status
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .is_some_and(|code| (200..300).contains(&code))
+}
+
+// "D:getcontentlength xmlns:D=\"DAV:\"" -> "getcontentlength"
+fn local_name(tag: &str) -> String {
+    let name = tag.split(|c: char| c.is_whitespace()).next().unwrap_or_default();
+    let local = name.rsplit(':').next().unwrap_or(name);
+    local.to_ascii_lowercase()
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        let after = &rest[amp + 1..];
+        let decoded = after.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &after[..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => {
+                    let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            ch.map(|ch| (ch, end))
+        });
+        match decoded {
+            Some((ch, end)) => {
+                result.push(ch);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_namespaced_multistatus() {
+        let xml = "\u{feff}<?xml version=\"1.0\"?>\r\n\
+            <d:multistatus xmlns:d=\"DAV:\" xmlns:lp1=\"DAV:\">\r\n\
+              <!-- a comment with <d:response> inside -->\r\n\
+              <d:response>\r\n\
+                <d:href> /dav/music/Rock%20%26%20Roll/ </d:href>\r\n\
+                <d:propstat><d:prop>\r\n\
+                  <d:displayname>Rock &amp; Roll</d:displayname>\r\n\
+                  <d:resourcetype><d:collection xmlns:d=\"DAV:\"/></d:resourcetype>\r\n\
+                </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>\r\n\
+                <d:propstat><d:prop><d:getcontentlength/></d:prop>\r\n\
+                <d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>\r\n\
+              </d:response>\r\n\
+              <response xmlns=\"DAV:\">\r\n\
+                <href>/dav/music/a.mp3</href>\r\n\
+                <propstat><prop>\r\n\
+                  <lp1:getcontentlength>1234</lp1:getcontentlength>\r\n\
+                  <lp1:getlastmodified>Mon, 01 Jan 2024 00:00:00 GMT</lp1:getlastmodified>\r\n\
+                  <displayname><![CDATA[a & b]]></displayname>\r\n\
+                  <resourcetype/>\r\n\
+                </prop><status>HTTP/1.1 200 OK</status></propstat>\r\n\
+              </response>\r\n\
+              <D:RESPONSE><D:HREF>/dav/music/gone.mp3</D:HREF><D:STATUS>HTTP/1.1 404 Not Found</D:STATUS></D:RESPONSE>\r\n\
+              <D:response><D:propstat><D:prop><D:displayname>no href</D:displayname></D:prop></D:propstat></D:response>\r\n\
+            </d:multistatus>";
+
+        let entries = parse_multistatus(xml);
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].href, "/dav/music/Rock%20%26%20Roll/");
+        assert_eq!(entries[0].display_name.as_deref(), Some("Rock & Roll"));
+        assert!(entries[0].is_collection);
+        assert_eq!(entries[0].content_length, None);
+
+        assert_eq!(entries[1].href, "/dav/music/a.mp3");
+        assert_eq!(entries[1].display_name.as_deref(), Some("a & b"));
+        assert!(!entries[1].is_collection);
+        assert_eq!(entries[1].content_length, Some(1234));
+        assert_eq!(entries[1].last_modified.as_deref(), Some("Mon, 01 Jan 2024 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("a &amp; b &lt;c&gt; &#233;&#x4E2D;"), "a & b <c> é中");
+        assert_eq!(decode_entities("AT&T &bogus; &#xZZ; &"), "AT&T &bogus; &#xZZ; &");
+    }
+
+    #[test]
+    fn test_parse_multistatus_survives_garbage() {
+        let pieces = [
+            "<", ">", "/", "<D:response>", "</D:response>", "<D:href>", "</D:href>", "<D:propstat>",
+            "</D:propstat>", "<D:collection/>", "<D:status>HTTP/1.1 200 OK</D:status>", "<!--", "-->",
+            "<![CDATA[", "]]>", "&", "&#x", ";", "é", "中", "\u{feff}", "\r\n", "12", "<?", "?>",
+        ];
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        for _ in 0..2000 {
+            let len = next() % 40;
+            let input: String = (0..len).map(|_| pieces[next() % pieces.len()]).collect();
+            for entry in parse_multistatus(&input) {
+                assert!(!entry.href.is_empty(), "empty href for {:?}", input);
+            }
+        }
+    }
+}
@@ -0,0 +1,409 @@
+// Keeping chosen WebDAV folders on disk. A background thread goes over every
+// server's synced folders now and then (and whenever the choice changes),
+// downloads the audio files that are new or changed since last time, and deletes
+// the local copies of files that went away on the server or whose folder isn't
+// synced any more. Each server's folders are mirrored under
+// `<config dir>/synced/<server id>/`, next to a manifest of what was downloaded,
+// so nothing the sync didn't put there is ever deleted.
+
+use crate::webdav::{Credentials, WebDAVClient, WebDAVItem};
+use crate::{is_audio_file, resolve_webdav_url, throttle, WebDAVConfig};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// How often the synced folders are checked for changes on the server
+const SYNC_INTERVAL: Duration = Duration::from_secs(30 * 60);
+const MANIFEST_FILE: &str = ".sync_manifest.json";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+static SYNC_ROOT: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    // Tests sync into folders of their own
+    if cfg!(test) {
+        return None;
+    }
+    crate::base_config_dir().ok().map(|dir| dir.join("synced"))
+});
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FolderStatus {
+    // Queued behind the other folders in this pass
+    Waiting,
+    Syncing { done: usize, total: usize },
+    Synced { files: usize },
+    Failed(String),
+}
+
+pub struct SyncEvent {
+    pub config_id: String,
+    pub folder: String,
+    pub status: FolderStatus,
+}
+
+/// A server and the folders (item paths) to keep from it; no folders removes
+/// everything synced from it earlier.
+#[derive(Clone)]
+pub struct SyncTarget {
+    pub config: WebDAVConfig,
+    pub credentials: Option<Credentials>,
+}
+
+// What was downloaded for a remote file, to tell when it changes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct SyncedFile {
+    size: u64,
+    modified: String,
+}
+
+/// The sync thread; dropping it ends the thread once the pass in progress is done.
+pub struct WebDAVSync {
+    pending: Arc<Mutex<Option<Vec<SyncTarget>>>>,
+    wake: Sender<()>,
+    event_receiver: Mutex<Option<UnboundedReceiver<SyncEvent>>>,
+}
+
+impl WebDAVSync {
+    pub fn start() -> Self {
+        let pending: Arc<Mutex<Option<Vec<SyncTarget>>>> = Arc::new(Mutex::new(None));
+        let (wake, woken) = channel();
+        let (events, event_receiver) = unbounded_channel();
+
+        let changes = pending.clone();
+        std::thread::spawn(move || {
+            let Some(root) = SYNC_ROOT.as_ref() else { return };
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    eprintln!("[Sync] 无法启动同步: {}", e);
+                    return;
+                }
+            };
+            let mut targets = Vec::new();
+            loop {
+                match woken.recv_timeout(SYNC_INTERVAL) {
+                    Err(RecvTimeoutError::Disconnected) => return,
+                    Ok(()) | Err(RecvTimeoutError::Timeout) => while woken.try_recv().is_ok() {},
+                }
+                if let Some(changed) = changes.lock().unwrap().take() {
+                    targets = changed;
+                }
+                // A new choice of folders starts the pass over with it
+                let interrupted = || changes.lock().unwrap().is_some();
+                for target in &targets {
+                    sync_server(root, target, &runtime, &interrupted, &events);
+                    if interrupted() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        WebDAVSync { pending, wake, event_receiver: Mutex::new(Some(event_receiver)) }
+    }
+
+    /// Sync these servers from now on, starting right away.
+    pub fn set_targets(&self, targets: Vec<SyncTarget>) {
+        *self.pending.lock().unwrap() = Some(targets);
+        let _ = self.wake.send(());
+    }
+
+    // Status changes as they happen; only the first caller gets the receiver
+    pub fn take_events(&self) -> Option<UnboundedReceiver<SyncEvent>> {
+        self.event_receiver.lock().unwrap().take()
+    }
+}
+
+/// The synced copy of a file on a server, if there is one.
+pub fn local_copy(config_id: &str, path: &str) -> Option<PathBuf> {
+    let local = local_path(&SYNC_ROOT.as_ref()?.join(config_id), path)?;
+    local.is_file().then_some(local)
+}
+
+// Where `path` (percent-encoded, relative to the server root) goes under `dir`.
+// Names that would climb out of it aren't synced.
+fn local_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut local = dir.to_path_buf();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let name = urlencoding::decode(segment).ok()?;
+        let mut components = Path::new(name.as_ref()).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => local.push(name),
+            _ => return None,
+        }
+    }
+    (local != dir).then_some(local)
+}
+
+fn in_folder(path: &str, folder: &str) -> bool {
+    folder.is_empty() || path.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+// One pass over a server's synced folders
+fn sync_server(
+    root: &Path,
+    target: &SyncTarget,
+    runtime: &tokio::runtime::Runtime,
+    interrupted: &dyn Fn() -> bool,
+    events: &UnboundedSender<SyncEvent>,
+) {
+    let config = &target.config;
+    let dir = root.join(&config.id);
+    let mut manifest = load_manifest(&dir);
+    let report = |folder: &String, status: FolderStatus| {
+        let _ = events.send(SyncEvent { config_id: config.id.clone(), folder: folder.to_string(), status });
+    };
+    for folder in &config.synced_folders {
+        report(folder, FolderStatus::Waiting);
+    }
+
+    let client = WebDAVClient::new(config.url.clone()).with_credentials(target.credentials.clone());
+    let mut on_server = HashSet::new();
+    let mut unlisted = Vec::new();
+    for folder in &config.synced_folders {
+        report(folder, FolderStatus::Syncing { done: 0, total: 0 });
+        let files: Vec<WebDAVItem> = match runtime.block_on(client.list_tree(folder, |_| !interrupted())) {
+            Ok(files) => files.into_iter().filter(|f| is_audio_file(&f.name)).collect(),
+            Err(e) => {
+                eprintln!("[Sync] 无法列出 {} 的 {}: {}", config.name, folder, e);
+                report(folder, FolderStatus::Failed(e.to_string()));
+                unlisted.push(folder.as_str());
+                continue;
+            }
+        };
+        if interrupted() {
+            return;
+        }
+
+        let mut failures = Vec::new();
+        for (done, item) in files.iter().enumerate() {
+            report(folder, FolderStatus::Syncing { done, total: files.len() });
+            on_server.insert(item.path.clone());
+            let Some(local) = local_path(&dir, &item.path) else { continue };
+            let synced = SyncedFile { size: item.size, modified: item.modified.clone() };
+            if manifest.get(&item.path) == Some(&synced) && local.is_file() {
+                continue;
+            }
+            let result = resolve_webdav_url(config, &item.path)
+                .map_err(|e| e.to_string())
+                .and_then(|url| download(&url, &target.credentials, &config.url, &local, interrupted));
+            match result {
+                Ok(()) => {
+                    manifest.insert(item.path.clone(), synced);
+                    save_manifest(&dir, &manifest);
+                }
+                Err(_) if interrupted() => return,
+                Err(e) => {
+                    eprintln!("[Sync] 下载失败 {}: {}", item.path, e);
+                    failures.push(e);
+                }
+            }
+        }
+        report(
+            folder,
+            match failures.first() {
+                None => FolderStatus::Synced { files: files.len() },
+                Some(e) => FolderStatus::Failed(format!("{} of {} files failed: {}", failures.len(), files.len(), e)),
+            },
+        );
+    }
+
+    // Gone from the server or no longer in a synced folder; a folder that couldn't
+    // be listed keeps what it had
+    let stale: Vec<String> = manifest
+        .keys()
+        .filter(|path| !on_server.contains(*path) && !unlisted.iter().any(|folder| in_folder(path, folder)))
+        .cloned()
+        .collect();
+    for path in stale {
+        if let Some(local) = local_path(&dir, &path) {
+            if let Err(e) = std::fs::remove_file(&local) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("[Sync] 无法删除 {}: {}", local.display(), e);
+                    continue;
+                }
+            }
+            remove_empty_parents(&dir, &local);
+        }
+        manifest.remove(&path);
+    }
+    save_manifest(&dir, &manifest);
+}
+
+// Download `url` to `local` by way of a `.part` file, at the server's speed limit
+fn download(
+    url: &str,
+    credentials: &Option<Credentials>,
+    server: &str,
+    local: &Path,
+    interrupted: &dyn Fn() -> bool,
+) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        // However long the file takes at the speed limit
+        .timeout(None::<Duration>)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let send = || {
+        let mut request = client.get(url);
+        if let Some(credentials) = credentials {
+            request = credentials.authorize_blocking(request);
+        }
+        request.send().map_err(|e| e.to_string())
+    };
+    let mut response = send()?;
+    // An access token the server stopped taking early gets one fresh try
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED && credentials.as_ref().is_some_and(Credentials::renew) {
+        response = send()?;
+    }
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+
+    if let Some(parent) = local.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut part = local.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let result = (|| {
+        let mut file = std::fs::File::create(&part).map_err(|e| e.to_string())?;
+        let mut body = throttle::Throttled::new(response, throttle::for_server(server));
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            if interrupted() {
+                return Err("Interrupted".to_string());
+            }
+            let read = body.read(&mut buf).map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            std::io::Write::write_all(&mut file, &buf[..read]).map_err(|e| e.to_string())?;
+        }
+        std::fs::rename(&part, local).map_err(|e| e.to_string())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&part);
+    }
+    result
+}
+
+fn remove_empty_parents(dir: &Path, file: &Path) {
+    let mut parent = file.parent();
+    while let Some(folder) = parent.filter(|p| *p != dir && p.starts_with(dir)) {
+        // Fails once a folder still has something in it
+        if std::fs::remove_dir(folder).is_err() {
+            break;
+        }
+        parent = folder.parent();
+    }
+}
+
+fn load_manifest(dir: &Path) -> BTreeMap<String, SyncedFile> {
+    std::fs::read_to_string(dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_manifest(dir: &Path, manifest: &BTreeMap<String, SyncedFile>) {
+    let result = std::fs::create_dir_all(dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_string_pretty(manifest).map_err(|e| e.to_string()))
+        .and_then(|json| std::fs::write(dir.join(MANIFEST_FILE), json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("[Sync] 无法保存同步清单 {}: {}", dir.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{wav_bytes, MockWebDAV};
+    use crate::WebDAVAuth;
+
+    #[test]
+    fn test_local_paths_stay_inside_the_folder() {
+        let dir = Path::new("/synced/nas");
+        assert_eq!(local_path(dir, "A/01%20One.wav"), Some(dir.join("A").join("01 One.wav")));
+        assert_eq!(local_path(dir, "A/%2E%2E/%2E%2E/x.wav"), None);
+        assert_eq!(local_path(dir, "A/..%2Fx.wav"), None);
+        assert_eq!(local_path(dir, ""), None);
+    }
+
+    #[test]
+    fn test_syncs_chosen_folders() {
+        let server = MockWebDAV::start(
+            &[
+                ("music/A/01 One.wav", wav_bytes(100, 8000)),
+                ("music/A/B/02 Two.wav", wav_bytes(200, 8000)),
+                ("music/A/cover.jpg", vec![0xFF, 0xD8]),
+                ("music/C/03 Three.wav", wav_bytes(100, 8000)),
+            ],
+            Some(("alice", "secret")),
+        );
+        let root = std::env::temp_dir().join(format!("dioxus_music_sync_{}", uuid::Uuid::new_v4()));
+        let dir = root.join("nas");
+        let mut target = SyncTarget {
+            config: WebDAVConfig {
+                id: "nas".to_string(),
+                name: "NAS".to_string(),
+                url: server.url("music"),
+                username: "alice".to_string(),
+                encrypted_password: String::new(),
+                enabled: true,
+                password: None,
+                synced_folders: vec!["A".to_string()],
+                download_limit_kbps: 0,
+                auth: WebDAVAuth::Basic,
+            },
+            credentials: Some(Credentials::basic("alice", "secret")),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let (events, mut statuses) = unbounded_channel();
+        let sync = |target: &SyncTarget| sync_server(&root, target, &runtime, &|| false, &events);
+        let mut last_status = || {
+            let mut last = None;
+            while let Ok(event) = statuses.try_recv() {
+                last = Some(event.status);
+            }
+            last
+        };
+
+        sync(&target);
+        assert_eq!(last_status(), Some(FolderStatus::Synced { files: 2 }));
+        assert_eq!(std::fs::read(dir.join("A/B/02 Two.wav")).unwrap(), wav_bytes(200, 8000));
+        assert!(dir.join("A/01 One.wav").is_file());
+        assert!(!dir.join("A/cover.jpg").exists() && !dir.join("C").exists());
+
+        // Nothing changed, so nothing is downloaded again
+        let gets = |server: &MockWebDAV| server.requests().iter().filter(|r| r.starts_with("GET")).count();
+        let before = gets(&server);
+        sync(&target);
+        assert_eq!(gets(&server), before);
+
+        // Changes on the server come through; deletions too
+        server.put("music/A/01 One.wav", wav_bytes(300, 8000));
+        server.put("music/A/04 Four.wav", wav_bytes(100, 8000));
+        server.remove("music/A/B/02 Two.wav");
+        sync(&target);
+        assert_eq!(gets(&server), before + 2);
+        assert_eq!(std::fs::read(dir.join("A/01 One.wav")).unwrap(), wav_bytes(300, 8000));
+        assert!(dir.join("A/04 Four.wav").is_file());
+        assert!(!dir.join("A/B").exists());
+
+        // A file put next to the synced ones is left alone when the folder is unsynced
+        std::fs::write(dir.join("A/notes.txt"), "mine").unwrap();
+        target.config.synced_folders.clear();
+        sync(&target);
+        assert!(!dir.join("A/01 One.wav").exists() && !dir.join("A/04 Four.wav").exists());
+        assert!(dir.join("A/notes.txt").is_file());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,119 @@
+// YouTube and SoundCloud pages played through a yt-dlp the user installed
+// themselves. Tracks keep the page URL; the stream URL behind it expires, so
+// it's looked up again each time the track is downloaded.
+
+use serde::Deserialize;
+use std::process::Command;
+use std::sync::RwLock;
+use std::time::Duration;
+
+// Audio-only, over plain HTTP(S) so the downloader can fetch it in one piece.
+// m4a first since it decodes everywhere; whatever's best after that.
+const FORMAT: &str = "bestaudio[ext=m4a][protocol^=http]/bestaudio[protocol^=http]";
+const HOSTS: [&str; 5] = ["youtube.com", "youtu.be", "music.youtube.com", "m.youtube.com", "soundcloud.com"];
+
+// Set from the settings; None runs "yt-dlp" from the PATH
+static BINARY: RwLock<Option<String>> = RwLock::new(None);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnlineTrack {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: Duration,
+    pub thumbnail: Option<String>,
+}
+
+pub fn set_binary(path: Option<String>) {
+    *BINARY.write().unwrap() = path.filter(|path| !path.trim().is_empty());
+}
+
+fn command() -> Command {
+    Command::new(BINARY.read().unwrap().as_deref().unwrap_or("yt-dlp"))
+}
+
+/// Whether `url` is a page yt-dlp should resolve rather than a file to download.
+pub fn is_supported_url(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("").to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    HOSTS.contains(&host)
+}
+
+fn run(args: &[&str], url: &str) -> Result<String, String> {
+    let output = command()
+        .args(args)
+        .args(["--no-playlist", "--no-warnings", "-f", FORMAT, "--", url])
+        .output()
+        .map_err(|e| format!("无法运行 yt-dlp: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("yt-dlp 失败: {}", stderr.lines().last().unwrap_or("").trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Title, artist and so on for the page at `url`. Blocks while yt-dlp runs.
+pub fn fetch_info(url: &str) -> Result<OnlineTrack, String> {
+    parse_info(&run(&["--dump-json"], url)?)
+}
+
+/// A direct link to the page's audio, good for a few hours. Blocks while yt-dlp runs.
+pub fn stream_url(url: &str) -> Result<String, String> {
+    let output = run(&["--get-url"], url)?;
+    eprintln!("[yt-dlp] 已解析音频地址: {}", url);
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| "yt-dlp 没有返回音频地址".to_string())
+}
+
+fn parse_info(json: &str) -> Result<OnlineTrack, String> {
+    #[derive(Deserialize)]
+    struct Info {
+        title: Option<String>,
+        // Music uploads carry proper track tags; plain videos only the uploader
+        track: Option<String>,
+        artist: Option<String>,
+        creator: Option<String>,
+        uploader: Option<String>,
+        album: Option<String>,
+        duration: Option<f64>,
+        thumbnail: Option<String>,
+    }
+
+    let info: Info = serde_json::from_str(json).map_err(|e| format!("无法解析 yt-dlp 输出: {}", e))?;
+    Ok(OnlineTrack {
+        title: info.track.or(info.title).unwrap_or_else(|| "Unknown".to_string()),
+        artist: info.artist.or(info.creator).or(info.uploader).unwrap_or_else(|| "Unknown Artist".to_string()),
+        album: info.album.unwrap_or_default(),
+        duration: info.duration.filter(|secs| secs.is_finite() && *secs > 0.0).map(Duration::from_secs_f64).unwrap_or_default(),
+        thumbnail: info.thumbnail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recognises_pages_and_reads_info() {
+        assert!(is_supported_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(is_supported_url("https://youtu.be/dQw4w9WgXcQ"));
+        assert!(is_supported_url("https://soundcloud.com/artist/track"));
+        assert!(!is_supported_url("https://youtube.com.example.org/a.mp3"));
+        assert!(!is_supported_url("https://dav.example.com/music/a.mp3"));
+
+        let video = parse_info(r#"{"title": "Live at the Park", "uploader": "Some Channel", "duration": 241.5}"#).unwrap();
+        assert_eq!(video.title, "Live at the Park");
+        assert_eq!(video.artist, "Some Channel");
+        assert_eq!(video.duration, Duration::from_millis(241_500));
+
+        let song = parse_info(r#"{"title": "Artist - Song (Official Video)", "track": "Song", "artist": "Artist", "uploader": "ArtistVEVO"}"#).unwrap();
+        assert_eq!((song.title.as_str(), song.artist.as_str()), ("Song", "Artist"));
+    }
+}